@@ -0,0 +1,739 @@
+// advisories.rs
+//
+// agent advisories：把项目依赖 (Cargo.lock) 与 RustSec 公告对照，并在图上回答"有漏洞的函数在链上是否真的会被调用"
+// 公告来自本地的 advisory-db 克隆 (cargo audit 默认放在 ~/.cargo/advisory-db)，或 `cargo audit --json` 的输出
+// 锁定的版本落在公告的受影响范围 (不在 patched 和 unaffected 中) 时，公告中列出的受影响函数 (affected.functions) 在项目中的调用点：
+//   MIR节点 (CPG) 的终结符中带完整路径的调用，以及AST中按路径或名字 (方法调用、use 导入后的直接调用) 匹配的调用
+// 调用点所在的函数在调用图 (见 callgraph.rs) 上能否从程序入口 (指令处理函数和 entrypoint!(..)) 到达，决定公告的状态：
+//   reachable (有可达的调用点)、unreachable (有调用点但都到达不了)、not_called (没有调用点)、unknown (公告没有列出受影响函数)
+// 只检查项目自己的代码；依赖内部对受影响函数的调用看不到，not_called 不代表一定安全
+
+use crate::callgraph::{project_callgraph, side};
+use crate::chunks::IGNORED_DIRS;
+use crate::config::ArtifactsArgs;
+use crate::dead_code::entrypoint_function;
+use crate::graph::{EdgeKind, Layer, NodeKind};
+use crate::manifest::now_rfc3339;
+use crate::merge::graph_key;
+use crate::symbols::{definition_name, line_of, AstNode};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+/// 输出文件名，默认位于产物目录下
+const ADVISORIES_FILE_NAME: &str = "advisories.json";
+
+/// advisory-db 相对于 CARGO_HOME 的位置，与 cargo audit 相同
+const ADVISORY_DB_DIR: &str = "advisory-db";
+
+/// `agent advisories` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct AdvisoriesArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// RustSec advisory-db 的本地克隆，默认为 $CARGO_HOME/advisory-db
+    #[arg(long, value_name = "DIR")]
+    db: Option<PathBuf>,
+
+    /// 改为读取 `cargo audit --json` 的输出，其中已经给出了有漏洞的依赖及其版本
+    #[arg(long, value_name = "FILE", conflicts_with = "db")]
+    audit: Option<PathBuf>,
+
+    /// 输出文件，默认为产物目录下的 advisories.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 公告 (advisory-db 中 TOML 前言的 [advisory] 部分，或 cargo audit 输出中的 advisory)
+#[derive(Deserialize, Debug, Clone)]
+struct AdvisoryMeta {
+    id: String,
+    package: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    /// unmaintained、unsound 等不是漏洞的公告
+    #[serde(default)]
+    informational: Option<String>,
+    #[serde(default)]
+    withdrawn: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+struct Versions {
+    #[serde(default)]
+    patched: Vec<String>,
+    #[serde(default)]
+    unaffected: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+struct Affected {
+    /// 受影响的函数 (完整路径) -> 受影响的版本范围
+    #[serde(default)]
+    functions: BTreeMap<String, Vec<String>>,
+}
+
+/// 一条公告
+#[derive(Deserialize, Debug, Clone)]
+struct Advisory {
+    advisory: AdvisoryMeta,
+    #[serde(default)]
+    versions: Versions,
+    /// cargo audit 的输出中可能为 null
+    #[serde(default)]
+    affected: Option<Affected>,
+}
+
+/// cargo audit --json 输出中的一项漏洞
+#[derive(Deserialize)]
+struct AuditVulnerability {
+    #[serde(flatten)]
+    advisory: Advisory,
+    package: LockedPackage,
+}
+
+/// Cargo.lock 中的一个包
+#[derive(Deserialize, Debug, Clone)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    /// 项目自己的包没有 source
+    #[serde(default)]
+    source: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+/// 公告的状态，见文件开头的说明
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Reachable,
+    Unreachable,
+    NotCalled,
+    Unknown,
+}
+
+/// 调用点与受影响函数的匹配方式
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Confidence {
+    /// 完整路径 (MIR中的调用总是完整路径)
+    Exact,
+    /// 路径的最后两段，例如 `SmallVec::insert_many(..)`
+    Path,
+    /// 只有名字：方法调用，或 use 导入后的直接调用
+    Name,
+}
+
+/// 受影响函数的一个调用点
+#[derive(Serialize, Debug)]
+struct CallSite {
+    /// 受影响的函数
+    api: String,
+    layer: Layer,
+    file: PathBuf,
+    line: usize,
+    /// 调用点所在的函数
+    function: String,
+    /// 调用图中的节点
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node: Option<String>,
+    confidence: Confidence,
+    reachable: bool,
+    /// 能到达该调用点的入口 (指令或入口函数)
+    entry_points: Vec<String>,
+    code: String,
+}
+
+/// 一条命中的公告
+#[derive(Serialize, Debug)]
+struct Match {
+    id: String,
+    package: String,
+    /// Cargo.lock 中锁定的版本
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    patched: Vec<String>,
+    status: Status,
+    /// 在该版本中受影响的函数
+    functions: Vec<String>,
+    call_sites: Vec<CallSite>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+}
+
+/// advisories.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct AdvisoriesReport {
+    metadata: AdvisoriesMetadata,
+    /// 公告的来源：advisory-db 目录或 cargo audit 的输出
+    database: PathBuf,
+    lockfiles: Vec<PathBuf>,
+    /// 数据库中的公告数
+    checked: usize,
+    advisories: Vec<Match>,
+}
+
+#[derive(Serialize, Debug)]
+struct AdvisoriesMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 默认的 advisory-db 位置
+fn default_db() -> PathBuf {
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")))
+        .unwrap_or_default();
+    cargo_home.join(ADVISORY_DB_DIR)
+}
+
+/// 读取 advisory-db 中的一条公告：Markdown 文件开头 ```toml 代码块中的前言 (标题为其后的第一个 # 标题)，或旧格式的 TOML 文件
+fn parse_advisory(path: &Path) -> Result<Advisory, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        return Ok(toml::from_str(&content)?);
+    }
+    let front = content
+        .split_once("```toml")
+        .and_then(|(_, rest)| rest.split_once("```"))
+        .ok_or("没有 TOML 前言")?;
+    let mut advisory: Advisory = toml::from_str(front.0)?;
+    if advisory.advisory.title.is_none() {
+        advisory.advisory.title = front
+            .1
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .map(|title| title.trim().to_string());
+    }
+    Ok(advisory)
+}
+
+/// advisory-db 中 crates/ 下的所有公告
+fn load_db(db: &Path) -> Result<Vec<Advisory>, Box<dyn Error>> {
+    let crates = db.join("crates");
+    if !crates.is_dir() {
+        return Err(format!(
+            "'{}' 不是 advisory-db 的克隆，可以用 git clone https://github.com/rustsec/advisory-db 取得，或用 --audit 读取 cargo audit --json 的输出",
+            db.display()
+        )
+        .into());
+    }
+    let mut advisories = vec![];
+    for entry in WalkDir::new(&crates)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let path = entry.path();
+        if !entry.file_type().is_file()
+            || !path
+                .extension()
+                .is_some_and(|ext| ext == "md" || ext == "toml")
+        {
+            continue;
+        }
+        match parse_advisory(path) {
+            Ok(advisory) => advisories.push(advisory),
+            Err(e) => warn!(file = %path.display(), "无法解析公告: {}", e),
+        }
+    }
+    Ok(advisories)
+}
+
+/// 项目中所有的 Cargo.lock (相对于项目根目录)
+fn find_lockfiles(project: &Path) -> Vec<PathBuf> {
+    WalkDir::new(project)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.depth() == 0
+                || !e.file_type().is_dir()
+                || !(name.starts_with('.') || IGNORED_DIRS.contains(&name.as_ref()))
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.file_name() == "Cargo.lock")
+        .map(|e| {
+            e.path()
+                .strip_prefix(project)
+                .unwrap_or(e.path())
+                .to_path_buf()
+        })
+        .collect()
+}
+
+/// 版本是否满足其中某个范围；无法解析的范围忽略
+fn matches_any(version: &Version, requirements: &[String]) -> bool {
+    requirements.iter().any(|r| match VersionReq::parse(r) {
+        Ok(req) => req.matches(version),
+        Err(e) => {
+            debug!(requirement = %r, "无法解析版本范围: {}", e);
+            false
+        }
+    })
+}
+
+/// 锁定的版本是否受公告影响
+fn is_vulnerable(version: &Version, versions: &Versions) -> bool {
+    !matches_any(version, &versions.patched) && !matches_any(version, &versions.unaffected)
+}
+
+/// 去掉泛型参数和空白，例如 `SmallVec::<[u8; 4]>::insert_many` -> `SmallVec::insert_many`
+fn strip_generics(text: &str) -> String {
+    let mut result = String::new();
+    let mut depth = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ':' if depth == 0 && result.ends_with(':') && chars.peek() == Some(&'<') => {
+                result.pop();
+                chars.next();
+                depth = 1;
+            }
+            '<' if depth > 0 => depth += 1,
+            '>' if depth > 0 => depth -= 1,
+            _ if depth > 0 || c.is_whitespace() => {}
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// 路径的最后两段
+fn tail(path: &str) -> Option<String> {
+    let mut segments = path.rsplit("::");
+    let name = segments.next()?;
+    let parent = segments.next()?;
+    Some(format!("{}::{}", parent, name))
+}
+
+/// 受影响的函数，以及用于在AST中匹配调用的各部分
+struct Api {
+    path: String,
+    crate_name: String,
+    /// 所属的类型或模块
+    parent: String,
+    name: String,
+}
+
+impl Api {
+    fn new(path: &str) -> Api {
+        let segments: Vec<&str> = path.split("::").collect();
+        let name = segments.last().copied().unwrap_or("");
+        let parent = if segments.len() > 1 {
+            segments[segments.len() - 2]
+        } else {
+            ""
+        };
+        Api {
+            path: path.to_string(),
+            crate_name: segments[0].replace('-', "_"),
+            parent: parent.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    /// AST中的调用 (被调用的表达式，去掉泛型参数) 与该函数的匹配方式；`source` 为调用所在的文件
+    fn match_call(&self, callee: &str, method: bool, source: &str) -> Option<Confidence> {
+        if callee == self.path || callee.ends_with(&format!("::{}", self.path)) {
+            return Some(Confidence::Exact);
+        }
+        if self.parent != self.crate_name
+            && tail(&self.path)
+                .is_some_and(|t| callee == t || callee.ends_with(&format!("::{}", t)))
+        {
+            return Some(Confidence::Path);
+        }
+        let mentions_crate = source.contains(&format!("{}::", self.crate_name));
+        let last = callee.rsplit(['.', ':']).next().unwrap_or(callee);
+        if last != self.name || !mentions_crate {
+            return None;
+        }
+        // 方法调用只在文件中提到了所属类型时计入；直接调用只在文件中导入了该函数时计入
+        let is_type = self.parent.starts_with(|c: char| c.is_ascii_uppercase());
+        let imported = source.lines().any(|line| {
+            let line = line.trim_start();
+            line.starts_with("use ") && line.contains(&self.crate_name) && line.contains(&self.name)
+        });
+        match (method, is_type) {
+            (true, true) if source.contains(&self.parent) => Some(Confidence::Name),
+            (false, false) if callee == self.name && imported => Some(Confidence::Name),
+            _ => None,
+        }
+    }
+}
+
+/// AST中的一个调用
+struct AstCall<'a> {
+    /// 所在的函数
+    function: &'a str,
+    /// 被调用的表达式，去掉了泛型参数
+    callee: String,
+    method: bool,
+    node: &'a AstNode,
+}
+
+fn collect_calls<'a>(node: &'a AstNode, function: Option<&'a str>, calls: &mut Vec<AstCall<'a>>) {
+    let function = if node.kind == "function_item" {
+        definition_name(node).map(|n| n.text.as_str())
+    } else {
+        function
+    };
+    if let (Some(function), "call_expression") = (function, node.kind.as_str()) {
+        if let Some(callee) = node.children.first() {
+            calls.push(AstCall {
+                function,
+                callee: strip_generics(&callee.text),
+                method: callee.kind == "field_expression",
+                node,
+            });
+        }
+    }
+    for c in &node.children {
+        collect_calls(c, function, calls);
+    }
+}
+
+/// entrypoint!(..) 指定的原生程序入口函数
+fn collect_entrypoints(node: &AstNode, file: &Path, entries: &mut Vec<(String, String)>) {
+    for item in &node.children {
+        if item.kind == "macro_invocation" {
+            if let Some(name) = entrypoint_function(&item.text) {
+                entries.push((format!("ast:{}:{}", file.display(), name), name.to_string()));
+            }
+        }
+        collect_entrypoints(item, file, entries);
+    }
+}
+
+/// 对照公告与依赖，定位受影响函数的调用点并判断可达性，写出 advisories.json
+pub fn run(args: &AdvisoriesArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("advisories")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let lockfiles = find_lockfiles(project);
+
+    // 命中的公告及锁定的版本
+    let mut hits: Vec<(Advisory, Version)> = vec![];
+    let (database, checked) = match &args.audit {
+        Some(audit) => {
+            let content = fs::read_to_string(audit)
+                .map_err(|e| format!("无法读取 '{}': {}", audit.display(), e))?;
+            let value: Value = serde_json::from_str(&content)
+                .map_err(|e| format!("无法解析 '{}': {}", audit.display(), e))?;
+            let list: Vec<AuditVulnerability> = serde_json::from_value(
+                value
+                    .pointer("/vulnerabilities/list")
+                    .cloned()
+                    .unwrap_or_default(),
+            )?;
+            let checked = value
+                .pointer("/database/advisory-count")
+                .and_then(Value::as_u64)
+                .map_or(list.len(), |n| n as usize);
+            for vulnerability in list {
+                match Version::parse(&vulnerability.package.version) {
+                    Ok(version) => hits.push((vulnerability.advisory, version)),
+                    Err(e) => warn!(package = %vulnerability.package.name, "无法解析版本: {}", e),
+                }
+            }
+            (audit.clone(), checked)
+        }
+        None => {
+            let db = args.db.clone().unwrap_or_else(default_db);
+            let advisories = load_db(&db)?;
+            if lockfiles.is_empty() {
+                warn!(
+                    "项目中没有 Cargo.lock，无法确定依赖的版本；可先运行 cargo generate-lockfile"
+                );
+            }
+            let mut packages: BTreeSet<(String, String)> = BTreeSet::new();
+            for lockfile in &lockfiles {
+                let content = fs::read_to_string(project.join(lockfile))?;
+                let lock: Lockfile = toml::from_str(&content)
+                    .map_err(|e| format!("无法解析 '{}': {}", lockfile.display(), e))?;
+                packages.extend(
+                    lock.package
+                        .into_iter()
+                        .filter(|p| p.source.is_some())
+                        .map(|p| (p.name, p.version)),
+                );
+            }
+            let mut by_name: HashMap<&str, Vec<&Advisory>> = HashMap::new();
+            for advisory in &advisories {
+                if advisory.advisory.withdrawn.is_none()
+                    && advisory.advisory.informational.is_none()
+                {
+                    by_name
+                        .entry(advisory.advisory.package.as_str())
+                        .or_default()
+                        .push(advisory);
+                }
+            }
+            for (name, version) in &packages {
+                let Ok(version) = Version::parse(version) else {
+                    continue;
+                };
+                for advisory in by_name.get(name.as_str()).into_iter().flatten() {
+                    if is_vulnerable(&version, &advisory.versions) {
+                        hits.push(((*advisory).clone(), version.clone()));
+                    }
+                }
+            }
+            (db, advisories.len())
+        }
+    };
+
+    // 调用图上从程序入口出发的可达性：节点 -> 能到达它的入口
+    let callgraph = project_callgraph(&args.artifacts)?;
+    let node_of: HashMap<&str, &str> = callgraph
+        .graph
+        .nodes
+        .iter()
+        .map(|n| (graph_key(&n.id), n.id.as_str()))
+        .collect();
+    let mut entries: Vec<(String, String)> = callgraph
+        .graph
+        .nodes
+        .iter()
+        .filter(|n| side(n) != "external")
+        .filter_map(|n| {
+            let instruction = n.properties.get("instruction")?.as_str()?;
+            Some((n.id.clone(), instruction.to_string()))
+        })
+        .collect();
+    let mut native = vec![];
+    for (file, root) in &callgraph.asts {
+        if file.extension().is_some_and(|ext| ext == "rs") {
+            collect_entrypoints(root, file, &mut native);
+        }
+    }
+    entries.extend(
+        native
+            .into_iter()
+            .filter_map(|(key, name)| node_of.get(key.as_str()).map(|id| (id.to_string(), name))),
+    );
+    let mut successors: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in callgraph
+        .graph
+        .edges
+        .iter()
+        .filter(|e| e.kind == EdgeKind::Call)
+    {
+        successors
+            .entry(edge.source.as_str())
+            .or_default()
+            .push(edge.target.as_str());
+    }
+    let mut reached_from: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for (entry, name) in &entries {
+        let mut seen = HashSet::from([entry.as_str()]);
+        let mut queue = VecDeque::from([entry.as_str()]);
+        while let Some(current) = queue.pop_front() {
+            reached_from.entry(current).or_default().insert(name);
+            for &next in successors.get(current).into_iter().flatten() {
+                if seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    let mut sources: HashMap<PathBuf, String> = HashMap::new();
+    let mut source_of = |file: &Path| -> String {
+        sources
+            .entry(file.to_path_buf())
+            .or_insert_with(|| fs::read_to_string(project.join(file)).unwrap_or_default())
+            .clone()
+    };
+    let mut ast_calls = vec![];
+    for (file, root) in &callgraph.asts {
+        if file.extension().is_some_and(|ext| ext == "rs") {
+            let mut calls = vec![];
+            collect_calls(root, None, &mut calls);
+            ast_calls.push((file, source_of(file), calls));
+        }
+    }
+    let mir_calls: Vec<_> = callgraph
+        .linked
+        .nodes
+        .iter()
+        .filter(|n| n.layer == Layer::Mir && n.kind == NodeKind::Terminator)
+        .filter_map(|n| Some((n, n.span.as_ref()?, strip_generics(&n.label))))
+        .collect();
+
+    let mut matches = vec![];
+    for (advisory, version) in hits {
+        let functions: Vec<String> = advisory
+            .affected
+            .iter()
+            .flat_map(|a| &a.functions)
+            .filter(|(_, versions)| versions.is_empty() || matches_any(&version, versions))
+            .map(|(path, _)| path.clone())
+            .collect();
+        let mut call_sites: Vec<CallSite> = vec![];
+        let mut seen = HashSet::new();
+        for api in functions.iter().map(|f| Api::new(f)) {
+            let mut push =
+                |layer, file: &Path, start_byte, function: &str, confidence, code: &str| {
+                    let source = source_of(file);
+                    let line = line_of(&source, start_byte);
+                    if !seen.insert((api.path.clone(), file.to_path_buf(), line)) {
+                        return;
+                    }
+                    let short = function.rsplit("::").next().unwrap_or(function);
+                    let key = format!("ast:{}:{}", file.display(), short);
+                    let node = node_of.get(key.as_str()).copied();
+                    let entry_points: Vec<String> = node
+                        .and_then(|id| reached_from.get(id))
+                        .into_iter()
+                        .flatten()
+                        .map(|e| e.to_string())
+                        .collect();
+                    call_sites.push(CallSite {
+                        api: api.path.clone(),
+                        layer,
+                        file: file.to_path_buf(),
+                        line,
+                        function: short.to_string(),
+                        node: node.map(str::to_string),
+                        confidence,
+                        reachable: !entry_points.is_empty(),
+                        entry_points,
+                        code: code.split_whitespace().collect::<Vec<_>>().join(" "),
+                    });
+                };
+            for (node, span, label) in &mir_calls {
+                if label.contains(&api.path) {
+                    push(
+                        Layer::Mir,
+                        &span.file,
+                        span.start_byte,
+                        &node.function,
+                        Confidence::Exact,
+                        &node.label,
+                    );
+                }
+            }
+            for (file, source, calls) in &ast_calls {
+                for call in calls {
+                    if let Some(confidence) = api.match_call(&call.callee, call.method, source) {
+                        push(
+                            Layer::Ast,
+                            file,
+                            call.node.start_byte,
+                            call.function,
+                            confidence,
+                            &call.node.text,
+                        );
+                    }
+                }
+            }
+        }
+        call_sites.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+        let status = if functions.is_empty() {
+            Status::Unknown
+        } else if call_sites.iter().any(|c| c.reachable) {
+            Status::Reachable
+        } else if call_sites.is_empty() {
+            Status::NotCalled
+        } else {
+            Status::Unreachable
+        };
+        // 报告中的位置：第一个可达的调用点，其次是第一个调用点，都没有时为 Cargo.lock
+        let location = call_sites
+            .iter()
+            .find(|c| c.reachable)
+            .or(call_sites.first())
+            .map(|c| (c.file.clone(), Some(c.line)))
+            .or_else(|| lockfiles.first().map(|l| (l.clone(), None)));
+        let (file, line) = location.map_or((None, None), |(f, l)| (Some(f), l));
+        match status {
+            Status::Reachable => {
+                warn!(id = %advisory.advisory.id, package = %advisory.advisory.package, version = %version, "受影响的函数可从程序入口到达")
+            }
+            _ => {
+                info!(id = %advisory.advisory.id, package = %advisory.advisory.package, version = %version, status = ?status, "依赖受公告影响")
+            }
+        }
+        matches.push(Match {
+            id: advisory.advisory.id,
+            package: advisory.advisory.package,
+            version: version.to_string(),
+            title: advisory.advisory.title,
+            url: advisory.advisory.url,
+            aliases: advisory.advisory.aliases,
+            patched: advisory.versions.patched,
+            status,
+            functions,
+            call_sites,
+            file,
+            line,
+        });
+    }
+    matches.sort_by(|a, b| (a.status, &a.id).cmp(&(b.status, &b.id)));
+
+    let report = AdvisoriesReport {
+        metadata: AdvisoriesMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        database,
+        lockfiles,
+        checked,
+        advisories: matches,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(ADVISORIES_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    let count = |status| {
+        report
+            .advisories
+            .iter()
+            .filter(|a| a.status == status)
+            .count()
+    };
+    info!(
+        checked = report.checked,
+        advisories = report.advisories.len(),
+        reachable = count(Status::Reachable),
+        unreachable = count(Status::Unreachable),
+        not_called = count(Status::NotCalled),
+        unknown = count(Status::Unknown),
+        entry_points = entries.len(),
+        output = %output.display(),
+        "已写出依赖公告的可达性"
+    );
+    Ok(())
+}
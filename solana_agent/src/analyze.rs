@@ -0,0 +1,543 @@
+// analyze.rs
+
+use crate::chunks::ChunkedRun;
+use crate::config::{ArtifactsArgs, Config, CONFIG_FILE_NAME};
+use crate::cu;
+use crate::manifest::{
+    content_hash, crate_sources_hash, Artifact, PreviousRunManifest, RunManifest,
+};
+use crate::scope::{self, literal_glob};
+use crate::suppressions;
+use crate::LogOptions;
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::time::Instant;
+use tracing::{debug, info};
+
+/// `agent analyze` 的命令行参数
+/// 命令行参数优先于 agent.toml 中的同名配置
+#[derive(clap::Args, Debug)]
+pub struct AnalyzeArgs {
+    /// 要分析的项目根目录
+    #[arg(default_value = ".")]
+    project: PathBuf,
+
+    /// 配置文件路径，默认为 <PROJECT>/agent.toml
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// 输出目录，覆盖配置文件中的 output.dir
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// 并行线程数，覆盖配置文件中的 resources.jobs
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// 忽略上一次的结果，重新生成所有产物 (默认只重新生成源文件有变化的部分)
+    #[arg(long)]
+    full: bool,
+
+    /// 只分析相对于该提交有变化的文件 (包括工作区中未提交的修改) 及依赖它们的文件
+    #[arg(long, value_name = "REV", conflicts_with = "diff")]
+    since: Option<String>,
+
+    /// 只分析两个提交之间有变化的文件及依赖它们的文件，例如 main..HEAD
+    #[arg(long, value_name = "REV1..REV2")]
+    diff: Option<String>,
+
+    /// 分块模式：按包 (Cargo.toml 或 package.json 所在的目录) 把项目切成块，每块由单独的生成器进程处理，
+    /// 用于一次放不进内存的大型仓库；覆盖配置文件中的 resources.chunked，不能与 --since/--diff 同时使用
+    #[arg(long, conflicts_with_all = ["since", "diff"])]
+    chunked: bool,
+
+    /// 分块模式下同时处理的块数，覆盖配置文件中的 resources.chunk_processes，默认为 1
+    #[arg(long, value_name = "N")]
+    chunk_processes: Option<usize>,
+}
+
+impl AnalyzeArgs {
+    /// 要分析的项目根目录
+    pub fn project(&self) -> &Path {
+        &self.project
+    }
+}
+
+/// 查找生成器的可执行文件
+/// 优先使用与 agent 位于同一目录下的版本，否则交给 PATH 查找
+fn tool_path(name: &str) -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(name)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// 一次生成器运行的耗时和峰值内存
+#[derive(Serialize, Debug, Clone)]
+pub struct ToolRun {
+    pub stage: &'static str,
+    pub tool: &'static str,
+    pub wall_ms: f64,
+    /// 子进程的峰值常驻内存 (KiB)，无法取得时为空
+    pub peak_rss_kib: Option<u64>,
+}
+
+/// 一次流水线运行的结果
+pub struct PipelineRun {
+    pub output_dir: PathBuf,
+    pub tool_runs: Vec<ToolRun>,
+}
+
+/// 等待子进程结束，同时取得它的峰值内存
+#[cfg(unix)]
+fn wait_with_peak_rss(child: &mut Child) -> io::Result<(ExitStatus, Option<u64>)> {
+    use std::os::unix::process::ExitStatusExt;
+    let mut status = 0;
+    // SAFETY: rusage 是纯数据结构，全零是合法的初始值；pid 是尚未被回收的子进程
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut usage) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // ru_maxrss 在 Linux 上以 KiB 为单位，在 macOS 上以字节为单位
+    let max_rss = usage.ru_maxrss as u64;
+    let peak_rss_kib = if cfg!(target_os = "macos") {
+        max_rss / 1024
+    } else {
+        max_rss
+    };
+    Ok((ExitStatus::from_raw(status), Some(peak_rss_kib)))
+}
+
+#[cfg(not(unix))]
+fn wait_with_peak_rss(child: &mut Child) -> io::Result<(ExitStatus, Option<u64>)> {
+    Ok((child.wait()?, None))
+}
+
+/// 运行一个生成器并等待其结束
+pub fn run_tool(
+    stage: &'static str,
+    name: &'static str,
+    args: &[String],
+) -> Result<ToolRun, Box<dyn Error>> {
+    let program = tool_path(name);
+    debug!(tool = %program.display(), ?args, "启动生成器");
+
+    let mut command = Command::new(&program);
+    command.args(args);
+    let started = Instant::now();
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("无法启动 {}: {}", program.display(), e))?;
+    let (status, peak_rss_kib) = wait_with_peak_rss(&mut child)?;
+    if !status.success() {
+        return Err(format!("{} 执行失败: {}", name, status).into());
+    }
+    Ok(ToolRun {
+        stage,
+        tool: name,
+        wall_ms: started.elapsed().as_secs_f64() * 1000.0,
+        peak_rss_kib,
+    })
+}
+
+/// 通过 `--version` 查询生成器的版本号，查询失败时返回 "unknown"
+pub fn tool_version(name: &str) -> String {
+    Command::new(tool_path(name))
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| {
+            // clap 的输出格式为 "<name> <version>"
+            String::from_utf8_lossy(&output.stdout)
+                .split_whitespace()
+                .nth(1)
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// 把可选参数追加为 `--flag value` 形式
+pub fn push_opt<T: ToString>(args: &mut Vec<String>, flag: &str, value: Option<T>) {
+    if let Some(value) = value {
+        args.push(flag.to_string());
+        args.push(value.to_string());
+    }
+}
+
+/// 把crate根文件路径转换为输出文件名，例如 programs/vault/src/lib.rs -> programs_vault_src_lib
+fn crate_output_name(crate_root: &Path) -> String {
+    crate_root
+        .with_extension("")
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// CPG的HTML页面：每个函数一个 `<crate>.<函数>.html`，只有一个函数时为 `<crate>.html`
+fn cpg_html_pages(cpg_dir: &Path, name: &str) -> io::Result<Vec<String>> {
+    let prefix = format!("{}.", name);
+    let mut pages = vec![];
+    for entry in fs::read_dir(cpg_dir)? {
+        let file = entry?.file_name().to_string_lossy().into_owned();
+        if file.starts_with(&prefix) && file.ends_with(".html") {
+            pages.push(file);
+        }
+    }
+    pages.sort();
+    Ok(pages)
+}
+
+/// AST生成器中来自配置的参数：exclude、语言和资源限制；输入、输出和要遍历的目录由调用者给出
+pub fn ast_options(config: &Config, jobs: Option<usize>) -> Vec<String> {
+    let mut args = vec![];
+    for pattern in &config.input.exclude {
+        push_opt(&mut args, "--exclude", Some(pattern));
+    }
+    if !config.input.languages.is_empty() {
+        push_opt(
+            &mut args,
+            "--language",
+            Some(config.input.languages.join(",")),
+        );
+    }
+    push_opt(&mut args, "--jobs", jobs);
+    push_opt(&mut args, "--memory-limit", config.resources.memory_limit);
+    push_opt(
+        &mut args,
+        "--timeout-per-file",
+        config.resources.timeout_per_file,
+    );
+    args
+}
+
+/// CFG生成器中来自配置的参数：输出格式和资源限制
+pub fn cfg_options(config: &Config, jobs: Option<usize>) -> Vec<String> {
+    let mut args = vec![];
+    if !config.output.formats.is_empty() {
+        push_opt(&mut args, "--format", Some(config.output.formats.join(",")));
+    }
+    push_opt(&mut args, "--jobs", jobs);
+    push_opt(&mut args, "--memory-limit", config.resources.memory_limit);
+    push_opt(
+        &mut args,
+        "--timeout-per-function",
+        config.resources.timeout_per_function,
+    );
+    args
+}
+
+/// 依次运行 AST、CFG 和 CPG 三个阶段
+pub fn run(args: &AnalyzeArgs, log: &LogOptions) -> Result<(), Box<dyn Error>> {
+    run_pipeline(args, log, None).map(|_| ())
+}
+
+/// 运行整条流水线，返回每次生成器运行的耗时和峰值内存
+/// `timings_dir` 不为空时让AST和CFG生成器把每个文件的耗时写到该目录下，
+/// 此时总是重新生成所有产物，以免复用的结果影响统计
+pub fn run_pipeline(
+    args: &AnalyzeArgs,
+    log: &LogOptions,
+    timings_dir: Option<&Path>,
+) -> Result<PipelineRun, Box<dyn Error>> {
+    if !args.project.is_dir() {
+        return Err(format!("项目路径 '{}' 不是一个有效的目录。", args.project.display()).into());
+    }
+
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| args.project.join(CONFIG_FILE_NAME));
+    let config = Config::load(&config_path)?;
+    debug!(config = %config_path.display(), ?config, "已加载配置");
+
+    let output_dir = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.project.join(&config.output.dir));
+    let ast_dir = output_dir.join("ast");
+    let cfg_dir = output_dir.join("cfg");
+    let cpg_dir = output_dir.join("cpg");
+    let resources = &config.resources;
+    let jobs = args.jobs.or(resources.jobs);
+
+    info!(
+        project = %args.project.display(),
+        output = %output_dir.display(),
+        "开始分析"
+    );
+
+    let full = args.full || timings_dir.is_some();
+    let mut tool_runs = vec![];
+    let previous = if full {
+        PreviousRunManifest::default()
+    } else {
+        PreviousRunManifest::load(&output_dir)
+    };
+    let mut manifest = RunManifest::new();
+
+    // 指定了提交范围时，只分析范围内变化的文件及依赖它们的文件
+    // 这种情况下不使用增量模式，以免删除范围之外的已有产物
+    let mut scope = if args.since.is_some() || args.diff.is_some() {
+        let scope = scope::resolve(
+            &args.project,
+            &config.input.roots,
+            args.since.as_deref(),
+            args.diff.as_deref(),
+        )?;
+        info!(
+            range = %scope.range,
+            changed = scope.changed.len(),
+            dependents = scope.dependents.len(),
+            "已确定分析范围"
+        );
+        Some(scope)
+    } else {
+        None
+    };
+    let incremental = !full && scope.is_none();
+    if let Some(scope) = scope.take_if(|scope| scope.files(&args.project).is_empty()) {
+        info!("范围内没有需要分析的文件");
+        fs::create_dir_all(&output_dir)?;
+        manifest.set_scope(scope);
+        manifest.write(&output_dir)?;
+        return Ok(PipelineRun {
+            output_dir,
+            tool_runs,
+        });
+    }
+
+    // 分块模式下AST和CFG按块由单独的进程生成，合并后的产物与不分块时相同 (见 chunks.rs)
+    // 有分析范围或需要统计耗时时不分块
+    let chunked = (args.chunked || resources.chunked) && scope.is_none() && timings_dir.is_none();
+    if chunked {
+        let chunked_run = ChunkedRun {
+            project: &args.project,
+            output_dir: &output_dir,
+            config: &config,
+            jobs,
+            processes: args
+                .chunk_processes
+                .or(resources.chunk_processes)
+                .unwrap_or(1),
+            log,
+        };
+        let (chunks, runs) = chunked_run.run(&previous)?;
+        tool_runs.extend(runs);
+        manifest.set_chunks(chunks);
+    } else {
+        // 阶段 1: AST
+        let mut ast_args = log.to_tool_args();
+        ast_args.extend([
+            "--input".to_string(),
+            args.project.display().to_string(),
+            "--output".to_string(),
+            ast_dir.display().to_string(),
+        ]);
+        for root in &config.input.roots {
+            push_opt(&mut ast_args, "--root", Some(root.display()));
+        }
+        // 有分析范围时，范围内的文件已按 include 过滤过，直接作为 include 传给生成器
+        match &scope {
+            Some(scope) => {
+                let include = scope::globset(&config.input.include)?;
+                for file in scope.files(&args.project) {
+                    if config.input.include.is_empty() || include.is_match(file) {
+                        push_opt(&mut ast_args, "--include", Some(literal_glob(file)));
+                    }
+                }
+            }
+            None => {
+                for pattern in &config.input.include {
+                    push_opt(&mut ast_args, "--include", Some(pattern));
+                }
+            }
+        }
+        ast_args.extend(ast_options(&config, jobs));
+        if incremental {
+            ast_args.push("--incremental".to_string());
+        }
+        if let Some(dir) = timings_dir {
+            push_opt(
+                &mut ast_args,
+                "--timings",
+                Some(dir.join("ast.json").display()),
+            );
+        }
+        tool_runs.push(run_tool("ast", "solana_ast_generator", &ast_args)?);
+
+        // 阶段 2: CFG
+        let mut cfg_args = log.to_tool_args();
+        cfg_args.extend([
+            "--input".to_string(),
+            ast_dir.display().to_string(),
+            "--output".to_string(),
+            cfg_dir.display().to_string(),
+        ]);
+        cfg_args.extend(cfg_options(&config, jobs));
+        if incremental {
+            cfg_args.push("--incremental".to_string());
+        }
+        if let Some(dir) = timings_dir {
+            push_opt(
+                &mut cfg_args,
+                "--timings",
+                Some(dir.join("cfg.json").display()),
+            );
+        }
+        tool_runs.push(run_tool("cfg", "solana_cfg_generator", &cfg_args)?);
+    }
+    manifest.add_stage("ast", &output_dir, &ast_dir, None)?;
+    manifest.add_stage("cfg", &output_dir, &cfg_dir, Some(Path::new("ast")))?;
+
+    // 阶段 3: CPG (只针对配置中列出的crate)
+    // CPG生成器不写 manifest，由 agent 记录它的产物
+    if !config.cpg.crates.is_empty() {
+        fs::create_dir_all(&cpg_dir)?;
+        manifest.add_stage_summary(
+            "cpg",
+            "solana_cpg_generator",
+            tool_version("solana_cpg_generator"),
+        );
+    }
+    // CPG生成器按输出格式一次写出 `<crate>.<扩展名>`；未指定格式时与CFG相同，输出 dot 和 json
+    let cpg_formats = if config.output.formats.is_empty() {
+        vec!["dot".to_string(), "json".to_string()]
+    } else {
+        config.output.formats.clone()
+    };
+    let cpg_html = cpg_formats.iter().any(|f| f == "html");
+    for crate_root in &config.cpg.crates {
+        // 范围内没有该crate的文件时跳过
+        let crate_dir = crate_root.parent().unwrap_or(Path::new(""));
+        if scope
+            .as_ref()
+            .is_some_and(|scope| !scope.touches(&args.project, crate_dir))
+        {
+            debug!(crate_root = %crate_root.display(), "crate 不在分析范围内，已跳过");
+            continue;
+        }
+        let name = crate_output_name(crate_root);
+        let report_name = format!("{}.skipped.json", name);
+        let sources_hash = crate_sources_hash(&args.project.join(crate_root))?;
+
+        // 本次需要的输出文件及其产物类别；HTML 页面的数量取决于函数数量，只能复用已有的页面
+        let mut outputs = vec![(report_name.clone(), "report")];
+        outputs.extend(
+            cpg_formats
+                .iter()
+                .filter(|f| *f != "html")
+                .map(|f| (format!("{}.{}", name, f), "cpg")),
+        );
+        let previous_pages = if cpg_html {
+            cpg_html_pages(&cpg_dir, &name)?
+        } else {
+            vec![]
+        };
+        let missing_pages = cpg_html && previous_pages.is_empty();
+        outputs.extend(previous_pages.iter().map(|page| (page.clone(), "cpg")));
+
+        // crate 的源文件都没有变化时直接复用上一次的CPG
+        let reusable: Option<Vec<&Artifact>> = outputs
+            .iter()
+            .map(|(file, kind)| {
+                previous
+                    .find(&output_dir, &Path::new("cpg").join(file))
+                    .filter(|a| *kind != "cpg" || a.source_hash.as_ref() == Some(&sources_hash))
+            })
+            .collect();
+        if let Some(reusable) = reusable.filter(|_| !missing_pages) {
+            debug!(crate_root = %crate_root.display(), "crate 未变化，复用已有CPG");
+            for artifact in reusable {
+                manifest.add_artifact(artifact.clone());
+            }
+            continue;
+        }
+
+        let mut cpg_args = log.to_tool_args();
+        cpg_args.push(args.project.join(crate_root).display().to_string());
+        push_opt(&mut cpg_args, "--jobs", jobs);
+        push_opt(&mut cpg_args, "--memory-limit", resources.memory_limit);
+        push_opt(
+            &mut cpg_args,
+            "--timeout-per-function",
+            resources.timeout_per_function,
+        );
+        push_opt(
+            &mut cpg_args,
+            "--skipped-report",
+            Some(cpg_dir.join(&report_name).display()),
+        );
+        push_opt(
+            &mut cpg_args,
+            "--output",
+            Some(cpg_dir.join(&name).display()),
+        );
+        push_opt(&mut cpg_args, "--format", Some(cpg_formats.join(",")));
+        // 上一次的HTML页面可能包含已删除的函数，重新生成前先清除
+        for page in &previous_pages {
+            fs::remove_file(cpg_dir.join(page))?;
+        }
+        tool_runs.push(run_tool("cpg", "solana_cpg_generator", &cpg_args)?);
+
+        outputs.retain(|(file, _)| !previous_pages.contains(file));
+        if cpg_html {
+            outputs.extend(
+                cpg_html_pages(&cpg_dir, &name)?
+                    .into_iter()
+                    .map(|page| (page, "cpg")),
+            );
+        }
+        for (file, kind) in outputs {
+            let is_cpg = kind == "cpg";
+            manifest.add_artifact(Artifact {
+                path: Path::new("cpg").join(&file),
+                kind: kind.to_string(),
+                source: is_cpg.then(|| crate_root.clone()),
+                source_hash: is_cpg.then(|| sources_hash.clone()),
+                hash: content_hash(&fs::read(cpg_dir.join(&file))?),
+            });
+        }
+    }
+
+    if let Some(scope) = scope {
+        manifest.set_scope(scope);
+    }
+    manifest.write(&output_dir)?;
+
+    // 源码中的抑制标注，由 agent report 使用
+    suppressions::write(&output_dir, &args.project)?;
+
+    // 处理函数的CU估算，作为之后 agent compare-cu 的基线，连同各函数的估算写出 cu.json；
+    // 估算依赖已写出的产物，失败时不影响本次分析
+    let artifacts = ArtifactsArgs {
+        project: args.project.clone(),
+        config: args.config.clone(),
+        artifacts: Some(output_dir.clone()),
+    };
+    match cu::estimate(&artifacts) {
+        Ok(estimates) => {
+            fs::write(
+                output_dir.join(cu::CU_FILE_NAME),
+                serde_json::to_string_pretty(&estimates)?,
+            )?;
+            manifest.set_compute_units(estimates.handlers);
+            manifest.write(&output_dir)?;
+        }
+        Err(e) => {
+            debug!(error = %e, "未能估算CU");
+            // 不留下上一次运行的估算
+            let _ = fs::remove_file(output_dir.join(cu::CU_FILE_NAME));
+        }
+    }
+    info!(output = %output_dir.display(), "分析完成");
+    Ok(PipelineRun {
+        output_dir,
+        tool_runs,
+    })
+}
@@ -0,0 +1,161 @@
+// anchor_accounts.rs
+//
+// `#[derive(Accounts)]` 结构体的字段和 `#[account(...)]` 约束的解析
+// constraints、known_vulns、lifecycle、mutability、pda、privileges、signers、sysvars 共用这里的结果，
+// 各自只解释关心的约束
+
+use crate::layout::split_top_level;
+use crate::symbols::{child, definition_name, line_of, normalize, AstNode};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// 一个 `#[derive(Accounts)]` 结构体
+pub struct AccountsStruct<'a> {
+    pub file: PathBuf,
+    pub fields: Vec<AccountsField<'a>>,
+}
+
+/// Accounts 结构体的一个字段
+pub struct AccountsField<'a> {
+    /// field_declaration 节点
+    pub node: &'a AstNode,
+    pub name: String,
+    /// 字段类型，空白合并为一个空格
+    pub ty: String,
+    /// 字段前所有 `#[account(...)]` 中的约束，按出现顺序，例如 `mut`、`has_one = owner @ ErrorCode::X`
+    pub constraints: Vec<String>,
+    /// 字段前 `/// CHECK:` 注释的内容
+    pub check_comment: Option<String>,
+    /// 字段声明所在的行
+    pub line: usize,
+}
+
+impl AccountsField<'_> {
+    /// 账户类型的最外层名字，去掉 Box<..>、Option<..> 和路径，例如 `Signer`、`Account`
+    pub fn base_type(&self) -> &str {
+        let mut ty = self.ty.trim();
+        for wrapper in ["Box<", "Option<"] {
+            if let Some(inner) = ty.strip_prefix(wrapper) {
+                ty = inner.trim();
+            }
+        }
+        let ty = ty.split('<').next().unwrap_or(ty).trim();
+        ty.rsplit("::").next().unwrap_or(ty)
+    }
+
+    /// 是否要求签名：Signer 类型或 `signer` 约束
+    pub fn is_signer(&self) -> bool {
+        self.base_type() == "Signer" || self.flag("signer")
+    }
+
+    /// 是否有以 `key` 为名的约束 (`mut` 或 `seeds = [...]` 都算)
+    pub fn flag(&self, key: &str) -> bool {
+        self.constraints.iter().any(|c| constraint_key(c) == key)
+    }
+
+    /// 第一个 `key = value` 约束的值，去掉自定义错误 `@ ErrorCode::X`
+    pub fn value(&self, key: &str) -> Option<String> {
+        self.values(key).into_iter().next()
+    }
+
+    /// 所有 `key = value` 约束的值 (has_one、constraint 可以出现多次)
+    pub fn values(&self, key: &str) -> Vec<String> {
+        self.constraints
+            .iter()
+            .filter_map(|c| {
+                let (k, v) = c.split_once('=')?;
+                let v = v.split(" @ ").next().unwrap_or(v).trim();
+                (k.trim() == key).then(|| v.to_string())
+            })
+            .collect()
+    }
+}
+
+/// 约束的名字：`=`、`@` 或 `(` 之前的部分
+pub fn constraint_key(constraint: &str) -> &str {
+    constraint
+        .split(['=', '@', '('])
+        .next()
+        .unwrap_or("")
+        .trim()
+}
+
+/// 在AST中收集 `#[derive(Accounts)]` 结构体，以结构体名为键
+pub fn collect<'a>(
+    node: &'a AstNode,
+    file: &Path,
+    source: &str,
+    structs: &mut HashMap<String, AccountsStruct<'a>>,
+) {
+    let mut derives_accounts = false;
+    for item in &node.children {
+        match item.kind.as_str() {
+            "attribute_item" => {
+                let text = normalize(&item.text);
+                derives_accounts |= text.starts_with("#[derive(") && text.contains("Accounts");
+                continue;
+            }
+            "line_comment" | "block_comment" => continue,
+            "struct_item" if derives_accounts => {
+                if let Some(name) = definition_name(item) {
+                    structs.insert(
+                        name.text.clone(),
+                        AccountsStruct {
+                            file: file.to_path_buf(),
+                            fields: fields(item, source),
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+        derives_accounts = false;
+        collect(item, file, source, structs);
+    }
+}
+
+/// 结构体 (struct_item) 的字段及其约束；没有名字的字段 (元组结构体) 跳过
+pub fn fields<'a>(item: &'a AstNode, source: &str) -> Vec<AccountsField<'a>> {
+    let mut fields = vec![];
+    let mut constraints: Vec<String> = vec![];
+    let mut check_comment = None;
+    for field in child(item, "field_declaration_list")
+        .into_iter()
+        .flat_map(|fields| &fields.children)
+    {
+        match field.kind.as_str() {
+            "attribute_item" => {
+                let text = normalize(&field.text);
+                if let Some(args) = text
+                    .strip_prefix("#[account(")
+                    .and_then(|t| t.strip_suffix(")]"))
+                {
+                    constraints.extend(split_top_level(args).into_iter().map(String::from));
+                }
+            }
+            "line_comment" | "block_comment" => {
+                let text = field.text.trim_start_matches(['/', '*', '!']).trim();
+                if let Some(check) = text.strip_prefix("CHECK:") {
+                    check_comment = Some(check.trim().to_string());
+                }
+            }
+            "field_declaration" => {
+                let constraints = std::mem::take(&mut constraints);
+                let check_comment = check_comment.take();
+                let Some(name) = child(field, "field_identifier") else {
+                    continue;
+                };
+                fields.push(AccountsField {
+                    node: field,
+                    name: name.text.clone(),
+                    ty: normalize(field.children.last().map_or("", |t| t.text.as_str())),
+                    constraints,
+                    check_comment,
+                    line: line_of(source, field.start_byte),
+                });
+            }
+            _ => {}
+        }
+    }
+    fields
+}
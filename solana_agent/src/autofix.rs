@@ -0,0 +1,780 @@
+// autofix.rs
+//
+// agent autofix：为机械性的问题生成建议的修复补丁，修改位置取自AST中语法节点的源码范围
+// 覆盖三种问题：
+//   缺少 #[account(mut)] (mutability/missing_mut，来自 mutability.json)：在字段已有的 #[account(..)] 中加入 mut，没有时在字段前加一行 #[account(mut)]
+//   丢弃了 invoke/invoke_signed 的返回值 (autofix/unchecked_invoke)：`invoke(..);` 改为 `invoke(..)?;`，`let _ = invoke(..);` 同样改写
+//   账户字段上的复合赋值 += -= *= (autofix/unchecked_arithmetic)：改为 checked_add 等，溢出时返回 ProgramError::ArithmeticOverflow
+// 后两种问题由本命令检测，写入 autofix.json 的 findings；所有修复写入 fixes，每个修复带字节范围的编辑和统一格式的 diff
+// 全部修复另外合并为一个补丁 autofix.patch，可以直接 git apply；--apply 时直接改写源文件
+// agent report 按 (规则, 文件, 行号) 把修复附到问题上，写入 SARIF 的 fixes、JSON 和 HTML
+
+use crate::config::ArtifactsArgs;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{child, definition_name, line_of, load_asts, AstNode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+pub const AUTOFIX_FILE_NAME: &str = "autofix.json";
+const PATCH_FILE_NAME: &str = "autofix.patch";
+
+/// diff 中每处修改前后保留的上下文行数
+const CONTEXT_LINES: usize = 3;
+
+/// 返回值必须检查的 CPI 函数
+const INVOKE_FUNCTIONS: &[&str] = &["invoke", "invoke_signed"];
+
+/// 可以换成 checked 运算的复合赋值运算符
+const CHECKED_OPERATORS: &[(&str, &str)] = &[
+    ("+=", "checked_add"),
+    ("-=", "checked_sub"),
+    ("*=", "checked_mul"),
+];
+
+/// `agent autofix` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct AutofixArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 autofix.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// 合并后的补丁，默认为产物目录下的 autofix.patch
+    #[arg(long, value_name = "FILE")]
+    patch: Option<PathBuf>,
+
+    /// 直接把修复写入项目中的源文件
+    #[arg(long)]
+    apply: bool,
+}
+
+/// 本命令检测的问题种类
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum FindingKind {
+    /// invoke/invoke_signed 的返回值被丢弃
+    UncheckedInvoke,
+    /// 账户字段上可能溢出的复合赋值
+    UncheckedArithmetic,
+}
+
+impl FindingKind {
+    fn name(self) -> &'static str {
+        match self {
+            FindingKind::UncheckedInvoke => "unchecked_invoke",
+            FindingKind::UncheckedArithmetic => "unchecked_arithmetic",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Finding {
+    kind: FindingKind,
+    file: PathBuf,
+    line: usize,
+    function: String,
+    code: String,
+}
+
+/// 一处编辑：把源文件中 [start_byte, end_byte) 替换为 text
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Edit {
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub text: String,
+}
+
+/// 一个问题的建议修复
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Fix {
+    /// 修复的问题的规则，与 agent report 中的规则相同，例如 `mutability/missing_mut`
+    pub rule: String,
+    pub file: PathBuf,
+    pub line: usize,
+    pub subject: String,
+    pub message: String,
+    pub edits: Vec<Edit>,
+    /// 只含这个修复的统一格式 diff
+    pub diff: String,
+}
+
+#[derive(Serialize)]
+struct AutofixMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// autofix.json 的顶层结构
+#[derive(Serialize)]
+struct AutofixReport {
+    metadata: AutofixMetadata,
+    findings: Vec<Finding>,
+    fixes: Vec<Fix>,
+}
+
+/// 读取产物目录中 autofix.json 的修复；没有该文件时为空
+pub fn load_fixes(artifacts_dir: &Path) -> Result<Vec<Fix>, Box<dyn Error>> {
+    let path = artifacts_dir.join(AUTOFIX_FILE_NAME);
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Ok(vec![]);
+    };
+    let value: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("无法解析 '{}': {}", path.display(), e))?;
+    Ok(serde_json::from_value(
+        value.get("fixes").cloned().unwrap_or_default(),
+    )?)
+}
+
+/// 调用的函数名 (路径的最后一段)
+fn callee(call: &AstNode) -> &str {
+    let function = call.children.first().map_or("", |f| f.text.as_str());
+    function.rsplit("::").next().unwrap_or(function).trim()
+}
+
+fn is_invoke(node: &AstNode) -> bool {
+    node.kind == "call_expression" && INVOKE_FUNCTIONS.contains(&callee(node))
+}
+
+/// 一个文件中检测到的问题和对应的修复
+struct Visitor<'a> {
+    file: &'a Path,
+    source: &'a str,
+    findings: Vec<Finding>,
+    fixes: Vec<Fix>,
+}
+
+impl Visitor<'_> {
+    fn visit(&mut self, node: &AstNode, function: Option<&str>) {
+        let function = if node.kind == "function_item" {
+            definition_name(node).map(|n| n.text.as_str())
+        } else {
+            function
+        };
+        if let Some(function) = function {
+            match node.kind.as_str() {
+                // invoke(..);
+                "expression_statement" => {
+                    if let Some(call) = node.children.first().filter(|c| is_invoke(c)) {
+                        self.push(
+                            FindingKind::UncheckedInvoke,
+                            node,
+                            function,
+                            format!("用 ? 传播 {} 的错误", callee(call)),
+                            Edit {
+                                start_byte: call.end_byte,
+                                end_byte: call.end_byte,
+                                text: "?".to_string(),
+                            },
+                        );
+                    }
+                }
+                // let _ = invoke(..);
+                "let_declaration" => {
+                    let discarded = node.children.get(1).is_some_and(|p| p.text == "_");
+                    let call = node.children.iter().find(|c| is_invoke(c));
+                    if let Some(call) = call.filter(|_| discarded) {
+                        self.push(
+                            FindingKind::UncheckedInvoke,
+                            node,
+                            function,
+                            format!("用 ? 传播 {} 的错误，不再丢弃返回值", callee(call)),
+                            Edit {
+                                start_byte: node.start_byte,
+                                end_byte: node.end_byte,
+                                text: format!("{}?;", call.text),
+                            },
+                        );
+                    }
+                }
+                // 账户字段上的 += -= *=；左边带方法调用 (如 borrow_mut) 的不改写，重复求值会改变语义
+                "compound_assignment_expr" => {
+                    if let [left, operator, right] = node.children.as_slice() {
+                        let method = CHECKED_OPERATORS
+                            .iter()
+                            .find(|(op, _)| *op == operator.text)
+                            .map(|(_, method)| *method)
+                            .filter(|_| {
+                                left.kind == "field_expression"
+                                    && !left.text.contains('(')
+                                    && right.kind != "float_literal"
+                            });
+                        if let Some(method) = method {
+                            self.push(
+                                FindingKind::UncheckedArithmetic,
+                                node,
+                                function,
+                                format!("用 {} 代替 {}，溢出时返回错误", method, operator.text),
+                                Edit {
+                                    start_byte: node.start_byte,
+                                    end_byte: node.end_byte,
+                                    text: format!(
+                                        "{left} = {left}.{method}({right}).ok_or(ProgramError::ArithmeticOverflow)?",
+                                        left = left.text,
+                                        method = method,
+                                        right = right.text,
+                                    ),
+                                },
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for c in &node.children {
+            self.visit(c, function);
+        }
+    }
+
+    fn push(
+        &mut self,
+        kind: FindingKind,
+        node: &AstNode,
+        function: &str,
+        message: String,
+        edit: Edit,
+    ) {
+        let line = line_of(self.source, node.start_byte);
+        let code = node.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.fixes.push(Fix {
+            rule: format!("autofix/{}", kind.name()),
+            file: self.file.to_path_buf(),
+            line,
+            subject: function.to_string(),
+            message,
+            diff: unified_diff(self.file, self.source, std::slice::from_ref(&edit)),
+            edits: vec![edit],
+        });
+        self.findings.push(Finding {
+            kind,
+            file: self.file.to_path_buf(),
+            line,
+            function: function.to_string(),
+            code,
+        });
+    }
+}
+
+/// Accounts 结构体中某个字段缺少 mut 时的编辑
+fn missing_mut_edit(
+    root: &AstNode,
+    source: &str,
+    accounts_struct: &str,
+    account: &str,
+) -> Option<Edit> {
+    let item = find_struct(root, accounts_struct)?;
+    let fields = &child(item, "field_declaration_list")?.children;
+    let index = fields.iter().position(|f| {
+        f.kind == "field_declaration"
+            && child(f, "field_identifier").is_some_and(|n| n.text == account)
+    })?;
+    // 字段前紧挨着的属性
+    let attributes = fields[..index]
+        .iter()
+        .rev()
+        .take_while(|f| f.kind == "attribute_item" || f.kind == "line_comment");
+    for attribute in attributes.filter(|a| a.kind == "attribute_item") {
+        let compact: String = attribute.text.split_whitespace().collect();
+        if !compact.starts_with("#[account(") {
+            continue;
+        }
+        let open = attribute.text.find('(')? + 1;
+        let empty = attribute.text[open..].trim_start().starts_with(')');
+        return Some(Edit {
+            start_byte: attribute.start_byte + open,
+            end_byte: attribute.start_byte + open,
+            text: if empty { "mut" } else { "mut, " }.to_string(),
+        });
+    }
+    let field = &fields[index];
+    let line_start = source[..field.start_byte].rfind('\n').map_or(0, |i| i + 1);
+    let indent = &source[line_start..field.start_byte];
+    // 新加的一行沿用字段所在行的换行符
+    let crlf = source[field.start_byte..]
+        .split('\n')
+        .next()
+        .is_some_and(|line| line.ends_with('\r'));
+    Some(Edit {
+        start_byte: field.start_byte,
+        end_byte: field.start_byte,
+        text: format!(
+            "#[account(mut)]{}{}",
+            if crlf { "\r\n" } else { "\n" },
+            indent
+        ),
+    })
+}
+
+fn find_struct<'a>(node: &'a AstNode, name: &str) -> Option<&'a AstNode> {
+    if node.kind == "struct_item" && definition_name(node).is_some_and(|n| n.text == name) {
+        return Some(node);
+    }
+    node.children.iter().find_map(|c| find_struct(c, name))
+}
+
+/// 按起始位置排序并去掉相互重叠的编辑 (保留靠前的)
+fn disjoint(edits: &[Edit]) -> Vec<Edit> {
+    let mut sorted = edits.to_vec();
+    sorted.sort_by_key(|e| (e.start_byte, e.end_byte));
+    let mut result: Vec<Edit> = vec![];
+    for edit in sorted {
+        if result.last().is_some_and(|last| {
+            edit.start_byte < last.end_byte
+                || (edit.start_byte == last.start_byte && edit.start_byte == last.end_byte)
+        }) {
+            continue;
+        }
+        result.push(edit);
+    }
+    result
+}
+
+/// 把编辑应用到 source 中 offset 开始的一段文本上
+fn apply_edits(text: &str, offset: usize, edits: &[Edit]) -> String {
+    let mut result = String::new();
+    let mut position = offset;
+    for edit in edits {
+        result.push_str(&text[position - offset..edit.start_byte - offset]);
+        result.push_str(&edit.text);
+        position = edit.end_byte;
+    }
+    result.push_str(&text[position - offset..]);
+    result
+}
+
+/// 一处连续的修改：旧文件中 [lo, hi) 行被替换为 new
+struct Change {
+    lo: usize,
+    hi: usize,
+    new: Vec<String>,
+}
+
+/// 编辑前后的统一格式 diff，文件名带 a/ 和 b/ 前缀，可以直接 git apply
+fn unified_diff(file: &Path, source: &str, edits: &[Edit]) -> String {
+    let edits = disjoint(edits);
+    if edits.is_empty() {
+        return String::new();
+    }
+    let mut starts = vec![0];
+    starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+    if starts.last() == Some(&source.len()) && starts.len() > 1 {
+        starts.pop();
+    }
+    // 每行保留行尾的 \r\n 或 \n，原样写入 diff，CRLF 文件的补丁才能 git apply
+    let lines: Vec<&str> = source.split_inclusive('\n').collect();
+    let line_of_byte = |byte: usize| starts.partition_point(|&s| s <= byte) - 1;
+    let line_end = |line: usize| starts.get(line + 1).copied().unwrap_or(source.len());
+
+    // 落在相同行上的编辑合并为一处修改
+    let mut groups: Vec<(usize, usize, Vec<Edit>)> = vec![];
+    for edit in edits {
+        let lo = line_of_byte(edit.start_byte);
+        let last = if edit.end_byte > edit.start_byte {
+            edit.end_byte - 1
+        } else {
+            edit.start_byte
+        };
+        let hi = line_of_byte(last) + 1;
+        match groups.last_mut() {
+            Some(group) if lo < group.1 => {
+                group.1 = group.1.max(hi);
+                group.2.push(edit);
+            }
+            _ => groups.push((lo, hi, vec![edit])),
+        }
+    }
+    let changes: Vec<Change> = groups
+        .into_iter()
+        .map(|(lo, hi, edits)| {
+            let offset = starts[lo];
+            let new = apply_edits(&source[offset..line_end(hi - 1)], offset, &edits);
+            Change {
+                lo,
+                hi,
+                new: new.split_inclusive('\n').map(str::to_string).collect(),
+            }
+        })
+        .collect();
+
+    let mut diff = format!(
+        "--- a/{name}\n+++ b/{name}\n",
+        name = file.to_string_lossy().replace('\\', "/")
+    );
+    // 上下文相互重叠的修改放在同一个 hunk 中
+    let mut hunks: Vec<Vec<&Change>> = vec![];
+    for change in &changes {
+        match hunks.last_mut() {
+            Some(hunk) if change.lo <= hunk.last().unwrap().hi + 2 * CONTEXT_LINES => {
+                hunk.push(change)
+            }
+            _ => hunks.push(vec![change]),
+        }
+    }
+    let mut delta: isize = 0;
+    for hunk in hunks {
+        let start = hunk[0].lo.saturating_sub(CONTEXT_LINES);
+        let end = (hunk.last().unwrap().hi + CONTEXT_LINES).min(lines.len());
+        let mut body = String::new();
+        let mut position = start;
+        let mut new_count = end - start;
+        for change in &hunk {
+            for line in &lines[position..change.lo] {
+                push_line(&mut body, ' ', line);
+            }
+            for line in &lines[change.lo..change.hi] {
+                push_line(&mut body, '-', line);
+            }
+            for line in &change.new {
+                push_line(&mut body, '+', line);
+            }
+            new_count = new_count + change.new.len() - (change.hi - change.lo);
+            position = change.hi;
+        }
+        for line in &lines[position..end] {
+            push_line(&mut body, ' ', line);
+        }
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n{}",
+            start + 1,
+            end - start,
+            (start as isize + 1 + delta),
+            new_count,
+            body
+        ));
+        delta += new_count as isize - (end - start) as isize;
+    }
+    diff
+}
+
+/// diff 中的一行；文件最后一行没有换行时按 diff 的约定加上 `\ No newline at end of file`
+fn push_line(body: &mut String, prefix: char, line: &str) {
+    body.push(prefix);
+    body.push_str(line);
+    if !line.ends_with('\n') {
+        body.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// 检测问题、生成修复，写出 autofix.json 和 autofix.patch
+pub fn run(args: &AutofixArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("autofix")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    let mut sources: HashMap<&Path, String> = HashMap::new();
+    let mut findings = vec![];
+    let mut fixes = vec![];
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        let mut visitor = Visitor {
+            file,
+            source: &source,
+            findings: vec![],
+            fixes: vec![],
+        };
+        visitor.visit(root, None);
+        findings.extend(visitor.findings);
+        fixes.extend(visitor.fixes);
+        sources.insert(file.as_path(), source);
+    }
+
+    // agent mutability 找到的缺少 mut 的账户
+    let mutability_path = artifacts_dir.join("mutability.json");
+    match fs::read_to_string(&mutability_path) {
+        Ok(content) => {
+            let mutability: Value = serde_json::from_str(&content)
+                .map_err(|e| format!("无法解析 '{}': {}", mutability_path.display(), e))?;
+            let missing = mutability
+                .get("findings")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter(|f| f.get("kind").and_then(Value::as_str) == Some("missing_mut"));
+            for finding in missing {
+                let text = |key| finding.get(key).and_then(Value::as_str).unwrap_or("");
+                let file = PathBuf::from(text("file"));
+                let (accounts_struct, account) = (text("accounts_struct"), text("account"));
+                let edit = asts
+                    .iter()
+                    .find(|(f, _)| *f == file)
+                    .zip(sources.get(file.as_path()))
+                    .and_then(|((_, root), source)| {
+                        missing_mut_edit(root, source, accounts_struct, account)
+                    });
+                let Some(edit) = edit else {
+                    warn!(accounts_struct, account, file = %file.display(), "找不到账户字段，无法生成修复");
+                    continue;
+                };
+                fixes.push(Fix {
+                    rule: "mutability/missing_mut".to_string(),
+                    line: finding.get("line").and_then(Value::as_u64).unwrap_or(0) as usize,
+                    subject: format!("{}.{}", accounts_struct, account),
+                    message: format!("为账户 {} 加上 #[account(mut)]", account),
+                    diff: unified_diff(
+                        &file,
+                        &sources[file.as_path()],
+                        std::slice::from_ref(&edit),
+                    ),
+                    edits: vec![edit],
+                    file,
+                });
+            }
+        }
+        Err(_) => info!("没有 mutability.json，不生成缺少 mut 的修复；可先运行 agent mutability"),
+    }
+
+    // 按文件合并所有修复
+    let mut by_file: BTreeMap<PathBuf, Vec<Edit>> = BTreeMap::new();
+    for fix in &fixes {
+        by_file
+            .entry(fix.file.clone())
+            .or_default()
+            .extend(fix.edits.iter().cloned());
+    }
+    let mut patch = String::new();
+    for (file, edits) in &by_file {
+        patch.push_str(&unified_diff(file, &sources[file.as_path()], edits));
+        if args.apply {
+            let edits = disjoint(edits);
+            let source = &sources[file.as_path()];
+            fs::write(project.join(file), apply_edits(source, 0, &edits))?;
+            info!(file = %file.display(), edits = edits.len(), "已应用修复");
+        }
+    }
+
+    let report = AutofixReport {
+        metadata: AutofixMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        findings,
+        fixes,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(AUTOFIX_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    let patch_path = args
+        .patch
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(PATCH_FILE_NAME));
+    fs::write(&patch_path, &patch)?;
+    let count = |kind| report.findings.iter().filter(|f| f.kind == kind).count();
+    info!(
+        unchecked_invoke = count(FindingKind::UncheckedInvoke),
+        unchecked_arithmetic = count(FindingKind::UncheckedArithmetic),
+        fixes = report.fixes.len(),
+        files = by_file.len(),
+        output = %output.display(),
+        patch = %patch_path.display(),
+        "已写出修复建议"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// source 中第一次出现的 text 对应的节点
+    fn node(source: &str, kind: &str, text: &str, children: Vec<AstNode>) -> AstNode {
+        let start_byte = source.find(text).unwrap();
+        AstNode {
+            id: String::new(),
+            kind: kind.to_string(),
+            field: None,
+            text: text.to_string(),
+            start_byte,
+            end_byte: start_byte + text.len(),
+            start_line: 0,
+            start_column: 0,
+            end_line: 0,
+            end_column: 0,
+            children,
+            source: None,
+        }
+    }
+
+    /// 按 diff 改写 source；上下文行和删除行必须与原文逐字节一致，否则 panic
+    fn apply_diff(source: &str, diff: &str) -> String {
+        let old: Vec<&str> = source.split_inclusive('\n').collect();
+        // `\ No newline at end of file` 去掉上一行的换行
+        let mut lines: Vec<String> = vec![];
+        for line in diff.split_inclusive('\n').skip(2) {
+            if line.starts_with('\\') {
+                assert_eq!(lines.last_mut().unwrap().pop(), Some('\n'));
+            } else {
+                lines.push(line.to_string());
+            }
+        }
+        let mut result = String::new();
+        let mut position = 0;
+        for line in &lines {
+            if let Some(header) = line.strip_prefix("@@ -") {
+                let start: usize = header.split([',', ' ']).next().unwrap().parse().unwrap();
+                for line in &old[position..start - 1] {
+                    result.push_str(line);
+                }
+                position = start - 1;
+                continue;
+            }
+            let (prefix, text) = line.split_at(1);
+            if prefix != "+" {
+                assert_eq!(text, old[position], "diff 与原文不一致");
+                position += 1;
+            }
+            if prefix != "-" {
+                result.push_str(text);
+            }
+        }
+        for line in &old[position..] {
+            result.push_str(line);
+        }
+        result
+    }
+
+    fn fixes(source: &str, root: &AstNode) -> Vec<Fix> {
+        let mut visitor = Visitor {
+            file: Path::new("src/lib.rs"),
+            source,
+            findings: vec![],
+            fixes: vec![],
+        };
+        visitor.visit(root, None);
+        visitor.fixes
+    }
+
+    /// `fn f() { <statement> }` 的AST
+    fn function(source: &str, statement: AstNode) -> AstNode {
+        let body = &source[source.find('{').unwrap()..=source.rfind('}').unwrap()];
+        let item = node(
+            source,
+            "function_item",
+            source.trim_end(),
+            vec![
+                node(source, "identifier", "transfer", vec![]),
+                node(source, "block", body, vec![statement]),
+            ],
+        );
+        node(source, "source_file", source, vec![item])
+    }
+
+    #[test]
+    fn missing_mut_diff_applies_to_crlf_source() {
+        let source = "#[derive(Accounts)]\r\npub struct Update<'info> {\r\n    #[account(has_one = owner)]\r\n    pub vault: Account<'info, Vault>,\r\n    pub owner: Signer<'info>,\r\n}\r\n";
+        let field = |name: &str, text: &str| {
+            let mut identifier = node(source, "field_identifier", name, vec![]);
+            identifier.start_byte = source.find(text).unwrap() + text.find(name).unwrap();
+            identifier.end_byte = identifier.start_byte + name.len();
+            node(source, "field_declaration", text, vec![identifier])
+        };
+        let list = &source[source.find('{').unwrap()..=source.rfind('}').unwrap()];
+        let item = node(
+            source,
+            "struct_item",
+            &source[source.find("pub struct").unwrap()..source.len() - 2],
+            vec![
+                node(source, "type_identifier", "Update", vec![]),
+                node(
+                    source,
+                    "field_declaration_list",
+                    list,
+                    vec![
+                        node(
+                            source,
+                            "attribute_item",
+                            "#[account(has_one = owner)]",
+                            vec![],
+                        ),
+                        field("vault", "pub vault: Account<'info, Vault>"),
+                        field("owner", "pub owner: Signer<'info>"),
+                    ],
+                ),
+            ],
+        );
+        let root = node(source, "source_file", source, vec![item]);
+
+        // 已有 #[account(..)]：在括号内加 mut
+        let edit = missing_mut_edit(&root, source, "Update", "vault").unwrap();
+        let diff = unified_diff(Path::new("src/lib.rs"), source, &[edit]);
+        assert!(diff.contains("+    #[account(mut, has_one = owner)]\r\n"));
+        assert_eq!(
+            apply_diff(source, &diff),
+            source.replace("#[account(has_one", "#[account(mut, has_one")
+        );
+
+        // 没有属性：在字段前加一行，换行符与文件一致
+        let edit = missing_mut_edit(&root, source, "Update", "owner").unwrap();
+        let diff = unified_diff(Path::new("src/lib.rs"), source, &[edit]);
+        assert_eq!(
+            apply_diff(source, &diff),
+            source.replace("    pub owner", "    #[account(mut)]\r\n    pub owner")
+        );
+    }
+
+    #[test]
+    fn unchecked_invoke_diff_applies_without_trailing_newline() {
+        let source = "fn transfer() {\n    invoke(&ix, &accounts);\n}";
+        let call = node(
+            source,
+            "call_expression",
+            "invoke(&ix, &accounts)",
+            vec![
+                node(source, "identifier", "invoke", vec![]),
+                node(source, "arguments", "(&ix, &accounts)", vec![]),
+            ],
+        );
+        let statement = node(
+            source,
+            "expression_statement",
+            "invoke(&ix, &accounts);",
+            vec![call],
+        );
+        let fixes = fixes(source, &function(source, statement));
+        assert_eq!(fixes.len(), 1);
+        assert!(fixes[0]
+            .diff
+            .ends_with(" }\n\\ No newline at end of file\n"));
+        assert_eq!(
+            apply_diff(source, &fixes[0].diff),
+            "fn transfer() {\n    invoke(&ix, &accounts)?;\n}"
+        );
+    }
+
+    #[test]
+    fn unchecked_arithmetic_diff_applies_on_last_line() {
+        let source = "fn transfer() {\r\n    vault.amount += lamports; }";
+        let assignment = node(
+            source,
+            "compound_assignment_expr",
+            "vault.amount += lamports",
+            vec![
+                node(source, "field_expression", "vault.amount", vec![]),
+                node(source, "+=", "+=", vec![]),
+                node(source, "identifier", "lamports", vec![]),
+            ],
+        );
+        let fixes = fixes(source, &function(source, assignment));
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(
+            apply_diff(source, &fixes[0].diff),
+            "fn transfer() {\r\n    vault.amount = vault.amount.checked_add(lamports).ok_or(ProgramError::ArithmeticOverflow)?; }"
+        );
+    }
+}
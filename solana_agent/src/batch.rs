@@ -0,0 +1,405 @@
+// batch.rs
+//
+// agent batch：按仓库清单 (repos.toml) 依次克隆或更新每个仓库，用各自的配置运行完整的分析流程，
+// 最后写出跨项目的汇总报告 report.json、每个仓库一行的 summary.csv，以及所有项目的图合在一起的数据集
+// 清单的格式：
+//   [defaults]                  # 可选，各仓库没有给出时使用
+//   checks = ["mutability", "signers"]
+//   config = "configs/default.toml"
+//
+//   [features]                  # 可选，合并数据集的节点特征，格式同 agent.toml 的 [features]
+//   hash_dim = 32
+//
+//   [[repo]]
+//   name = "escrow"             # 输出子目录的名字，默认取 URL 的最后一段
+//   url = "https://github.com/org/escrow.git"   # 也可以是本地仓库的路径
+//   rev = "v1.2.0"              # 分支、标签或提交，默认为远程的默认分支
+//   path = "programs"           # 分析仓库中的哪个子目录，默认为仓库根目录
+//   config = "configs/escrow.toml"
+// 清单中的相对路径 (config 和本地仓库) 相对于清单所在的目录；没有 config 时使用仓库中的 agent.toml
+// 仓库克隆到 <输出目录>/repos/<name>，产物写到 <输出目录>/out/<name>；已经克隆的仓库只 fetch 后重新检出
+// 每个步骤 (克隆、分析、各项检查) 的失败记录在汇总报告中，不影响其他仓库
+
+use crate::config::{ArtifactsArgs, FeaturesConfig};
+use crate::dataset::export_combined;
+use crate::history::{analyze_revision, FindingCounts, DEFAULT_CHECKS};
+use crate::manifest::now_rfc3339;
+use crate::report::load_findings;
+use crate::scope::{check_commit, check_git_arg, git_lines};
+use crate::LogOptions;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出目录下的文件名和子目录
+const REPORT_FILE_NAME: &str = "report.json";
+const SUMMARY_FILE_NAME: &str = "summary.csv";
+const DATASET_DIR: &str = "dataset";
+const REPOS_DIR: &str = "repos";
+const OUT_DIR: &str = "out";
+
+/// 默认的输出目录，相对于清单所在的目录
+const DEFAULT_OUTPUT_DIR: &str = "batch";
+
+/// `agent batch` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct BatchArgs {
+    /// 仓库清单，格式见 batch.rs
+    #[arg(long, value_name = "FILE")]
+    manifest: PathBuf,
+
+    /// 输出目录，默认为清单所在目录下的 batch
+    #[arg(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+
+    /// 只处理这些仓库 (名字，逗号分隔)
+    #[arg(long, value_name = "NAME", value_delimiter = ',')]
+    only: Vec<String>,
+
+    /// 已经克隆的仓库不再 fetch，直接检出本地已有的版本
+    #[arg(long)]
+    offline: bool,
+
+    /// 不导出合并的数据集
+    #[arg(long)]
+    no_dataset: bool,
+}
+
+/// 清单中各仓库共用的默认值
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Defaults {
+    checks: Option<Vec<String>>,
+    config: Option<PathBuf>,
+}
+
+/// 清单中的一个仓库
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RepoEntry {
+    #[serde(default)]
+    name: Option<String>,
+    url: String,
+    #[serde(default)]
+    rev: Option<String>,
+    #[serde(default)]
+    path: Option<PathBuf>,
+    #[serde(default)]
+    config: Option<PathBuf>,
+    #[serde(default)]
+    checks: Option<Vec<String>>,
+}
+
+impl RepoEntry {
+    /// 仓库的名字：清单中给出的，或 URL 的最后一段 (去掉 .git)
+    fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            let last = self
+                .url
+                .trim_end_matches('/')
+                .rsplit(['/', ':'])
+                .next()
+                .unwrap_or(&self.url);
+            last.trim_end_matches(".git").to_string()
+        })
+    }
+}
+
+/// repos.toml 的顶层结构
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default)]
+    defaults: Defaults,
+    #[serde(default)]
+    features: FeaturesConfig,
+    #[serde(default)]
+    repo: Vec<RepoEntry>,
+}
+
+/// 一个仓库的结果
+#[derive(Serialize, Debug)]
+struct RepoResult {
+    name: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rev: Option<String>,
+    /// 实际分析的提交
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit: Option<String>,
+    project: PathBuf,
+    artifacts: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<PathBuf>,
+    /// 失败的步骤
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failed: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    findings: Option<FindingCounts>,
+}
+
+/// 汇总报告 report.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct BatchReport {
+    metadata: BatchMetadata,
+    repos: Vec<RepoResult>,
+    /// 所有仓库的问题数之和
+    totals: FindingCounts,
+    /// 所有仓库的问题，每个问题带有所属仓库的名字 (repo)
+    findings: Vec<Value>,
+    /// 合并数据集中的图数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dataset_graphs: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+struct BatchMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+    manifest: PathBuf,
+}
+
+/// 克隆或更新仓库并检出要分析的版本，返回检出的提交
+fn checkout(
+    entry: &RepoEntry,
+    base: &Path,
+    dir: &Path,
+    offline: bool,
+) -> Result<String, Box<dyn Error>> {
+    // 本地仓库的相对路径相对于清单所在的目录
+    let local = base.join(&entry.url);
+    let url = if local.exists() {
+        local.display().to_string()
+    } else {
+        entry.url.clone()
+    };
+    if dir.join(".git").exists() {
+        if !offline {
+            git_lines(dir, &["fetch", "--quiet", "--tags", "--force", "origin"])?;
+        }
+    } else {
+        let parent = dir.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)?;
+        let dir_arg = dir.display().to_string();
+        check_git_arg("仓库地址", &url)?;
+        git_lines(parent, &["clone", "--quiet", "--", &url, &dir_arg])?;
+    }
+    // 分支优先取远程的最新提交
+    if let Some(rev) = &entry.rev {
+        check_git_arg("版本", rev)?;
+    }
+    let candidates: Vec<String> = match &entry.rev {
+        Some(rev) => vec![format!("origin/{}", rev), rev.clone()],
+        None => vec!["origin/HEAD".to_string(), "HEAD".to_string()],
+    };
+    let commit = candidates
+        .iter()
+        .find_map(|rev| {
+            git_lines(
+                dir,
+                &[
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{}^{{commit}}", rev),
+                ],
+            )
+            .ok()?
+            .pop()
+        })
+        .ok_or_else(|| {
+            format!(
+                "仓库中找不到版本 {}",
+                entry.rev.as_deref().unwrap_or("HEAD")
+            )
+        })?;
+    check_commit(&commit)?;
+    git_lines(
+        dir,
+        &["checkout", "--quiet", "--detach", "--force", &commit, "--"],
+    )?;
+    git_lines(dir, &["clean", "-fdq"])?;
+    Ok(commit)
+}
+
+/// CSV 字段，必要时加引号
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_summary(path: &Path, repos: &[RepoResult]) -> Result<(), Box<dyn Error>> {
+    let mut csv = String::from("repo,commit,findings,errors,warnings,notes,failed\n");
+    for repo in repos {
+        let findings = |level: Option<&str>| {
+            repo.findings.as_ref().map_or(String::new(), |f| {
+                match level {
+                    Some(level) => f.levels.get(level).copied().unwrap_or(0),
+                    None => f.total,
+                }
+                .to_string()
+            })
+        };
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            csv_field(&repo.name),
+            repo.commit.as_deref().unwrap_or(""),
+            findings(None),
+            findings(Some("error")),
+            findings(Some("warning")),
+            findings(Some("note")),
+            csv_field(&repo.failed.join(" "))
+        ));
+    }
+    fs::write(path, csv)?;
+    Ok(())
+}
+
+/// 依次处理清单中的仓库，写出汇总报告、summary.csv 和合并的数据集
+pub fn run(args: &BatchArgs, log: &LogOptions) -> Result<(), Box<dyn Error>> {
+    let content = fs::read_to_string(&args.manifest)
+        .map_err(|e| format!("无法读取清单 '{}': {}", args.manifest.display(), e))?;
+    let manifest: Manifest = toml::from_str(&content)
+        .map_err(|e| format!("清单 '{}' 格式错误: {}", args.manifest.display(), e))?;
+    let base = args
+        .manifest
+        .canonicalize()?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| base.join(DEFAULT_OUTPUT_DIR));
+    fs::create_dir_all(&output)?;
+    let output = output.canonicalize()?;
+
+    let entries: Vec<&RepoEntry> = manifest
+        .repo
+        .iter()
+        .filter(|r| args.only.is_empty() || args.only.contains(&r.name()))
+        .collect();
+    if entries.is_empty() {
+        return Err("清单中没有要处理的仓库".into());
+    }
+    let mut names = std::collections::HashSet::new();
+    if let Some(duplicate) = entries
+        .iter()
+        .map(|r| r.name())
+        .find(|n| !names.insert(n.clone()))
+    {
+        return Err(format!("清单中有重名的仓库 {}，请用 name 区分", duplicate).into());
+    }
+    info!(repos = entries.len(), output = %output.display(), "开始批量分析");
+
+    let mut repos = vec![];
+    let mut findings = vec![];
+    let mut totals = FindingCounts::default();
+    let mut projects = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        let name = entry.name();
+        info!(repo = %name, progress = %format!("{}/{}", i + 1, entries.len()), "处理仓库");
+        let dir = output.join(REPOS_DIR).join(&name);
+        let out = output.join(OUT_DIR).join(&name);
+        let project = match &entry.path {
+            Some(path) => dir.join(path),
+            None => dir.clone(),
+        };
+        let config = entry
+            .config
+            .as_ref()
+            .or(manifest.defaults.config.as_ref())
+            .map(|c| base.join(c));
+        let mut result = RepoResult {
+            name: name.clone(),
+            url: entry.url.clone(),
+            rev: entry.rev.clone(),
+            commit: None,
+            project: project.clone(),
+            artifacts: out.clone(),
+            config: config.clone(),
+            failed: vec![],
+            findings: None,
+        };
+        match checkout(entry, &base, &dir, args.offline) {
+            Ok(commit) => result.commit = Some(commit),
+            Err(e) => {
+                warn!(repo = %name, error = %e, "无法取得仓库，跳过");
+                result.failed.push("clone".to_string());
+                repos.push(result);
+                continue;
+            }
+        }
+
+        let checks: Vec<String> = match entry.checks.as_ref().or(manifest.defaults.checks.as_ref())
+        {
+            Some(checks) => checks.clone(),
+            None => DEFAULT_CHECKS.iter().map(|c| c.to_string()).collect(),
+        };
+        fs::create_dir_all(&out)?;
+        result.failed = analyze_revision(&project, config.as_deref(), &out, &checks, log);
+        if !result.failed.iter().any(|step| step == "analyze") {
+            let repo_findings = load_findings(&out)?;
+            let counts = FindingCounts::count(&repo_findings);
+            totals.add(&counts);
+            result.findings = Some(counts);
+            findings.extend(repo_findings.into_iter().map(|mut finding| {
+                finding["repo"] = json!(name);
+                finding
+            }));
+            projects.push((
+                name.clone(),
+                ArtifactsArgs {
+                    project: project.clone(),
+                    config: config.clone(),
+                    artifacts: Some(out.clone()),
+                },
+            ));
+        }
+        repos.push(result);
+    }
+
+    let dataset_graphs = if args.no_dataset {
+        None
+    } else {
+        Some(export_combined(
+            &projects,
+            &manifest.features,
+            &output.join(DATASET_DIR),
+        )?)
+    };
+
+    let report = BatchReport {
+        metadata: BatchMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+            manifest: args.manifest.clone(),
+        },
+        repos,
+        totals,
+        findings,
+        dataset_graphs,
+    };
+    fs::write(
+        output.join(REPORT_FILE_NAME),
+        serde_json::to_string_pretty(&report)?,
+    )?;
+    write_summary(&output.join(SUMMARY_FILE_NAME), &report.repos)?;
+    info!(
+        repos = report.repos.len(),
+        failed = report.repos.iter().filter(|r| !r.failed.is_empty()).count(),
+        findings = report.totals.total,
+        dataset_graphs = report.dataset_graphs,
+        output = %output.display(),
+        "已写出批量分析的汇总报告"
+    );
+    Ok(())
+}
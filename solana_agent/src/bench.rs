@@ -0,0 +1,246 @@
+// bench.rs
+//
+// agent bench：完整运行一次流水线，统计每个阶段的耗时和峰值内存，
+// 以及AST/CFG阶段按语言和文件大小分组的耗时，输出便于做回归跟踪的JSON
+
+use crate::analyze::{self, AnalyzeArgs, ToolRun};
+use crate::manifest::now_rfc3339;
+use crate::LogOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tracing::info;
+
+/// 存放各生成器 --timings 文件的临时目录名前缀
+const TIMINGS_DIR_PREFIX: &str = "agent-bench";
+
+/// 报告的默认文件名，位于输出目录下
+const REPORT_FILE_NAME: &str = "bench.json";
+
+/// 报告中列出的最慢文件数
+const SLOWEST_FILES: usize = 10;
+
+/// 文件大小分组的上界 (字节，不含) 及名字
+const SIZE_BUCKETS: &[(u64, &str)] = &[
+    (1024, "<1KiB"),
+    (10 * 1024, "1-10KiB"),
+    (100 * 1024, "10-100KiB"),
+    (1024 * 1024, "100KiB-1MiB"),
+    (u64::MAX, ">=1MiB"),
+];
+
+/// `agent bench` 的命令行参数；分析相关的参数与 agent analyze 相同
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    #[command(flatten)]
+    analyze: AnalyzeArgs,
+
+    /// 报告的路径，默认为输出目录下的 bench.json
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+}
+
+/// 生成器 --timings 输出中的一条记录
+#[derive(Deserialize, Debug)]
+struct FileTiming {
+    path: PathBuf,
+    bytes: u64,
+    millis: f64,
+}
+
+/// 一个阶段的汇总
+#[derive(Serialize, Debug)]
+struct StageReport {
+    stage: &'static str,
+    tool: &'static str,
+    runs: usize,
+    wall_ms: f64,
+    peak_rss_kib: Option<u64>,
+}
+
+/// 一组文件的耗时汇总
+#[derive(Serialize, Debug, Default)]
+struct GroupReport {
+    files: usize,
+    bytes: u64,
+    total_ms: f64,
+    max_ms: f64,
+}
+
+impl GroupReport {
+    fn add(&mut self, bytes: u64, millis: f64) {
+        self.files += 1;
+        self.bytes += bytes;
+        self.total_ms += millis;
+        self.max_ms = self.max_ms.max(millis);
+    }
+}
+
+/// 耗时最长的文件
+#[derive(Serialize, Debug)]
+struct SlowFile {
+    stage: &'static str,
+    path: PathBuf,
+    bytes: u64,
+    millis: f64,
+}
+
+/// bench.json 的顶层结构
+/// languages 和 size_buckets 的键为阶段名，值按语言或大小分组；
+/// 各文件的耗时是工作线程内的耗时，并行时总和会大于阶段的墙钟时间
+#[derive(Serialize, Debug)]
+struct BenchReport {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+    project: PathBuf,
+    wall_ms: f64,
+    stages: Vec<StageReport>,
+    languages: BTreeMap<&'static str, BTreeMap<&'static str, GroupReport>>,
+    size_buckets: BTreeMap<&'static str, BTreeMap<&'static str, GroupReport>>,
+    slowest_files: Vec<SlowFile>,
+}
+
+/// 根据扩展名判断语言
+fn language_of(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("rs") => "rust",
+        Some("ts") | Some("tsx") => "typescript",
+        Some("js") | Some("jsx") | Some("mjs") | Some("cjs") => "javascript",
+        Some("py") => "python",
+        Some("sol") => "solidity",
+        Some("toml") => "toml",
+        Some("move") => "move",
+        _ => "other",
+    }
+}
+
+/// 文件大小所属的分组
+fn size_bucket(bytes: u64) -> &'static str {
+    SIZE_BUCKETS
+        .iter()
+        .find(|(limit, _)| bytes < *limit)
+        .map_or(">=1MiB", |(_, name)| name)
+}
+
+/// 读取一个生成器写出的 --timings 文件；该阶段没有运行时返回空
+fn read_timings(path: &Path) -> Result<Vec<FileTiming>, Box<dyn Error>> {
+    if !path.is_file() {
+        return Ok(vec![]);
+    }
+    serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| format!("无法解析 {}: {}", path.display(), e).into())
+}
+
+/// 把同一阶段的多次运行 (例如每个crate一次的CPG) 合并为一条
+fn stage_reports(tool_runs: &[ToolRun]) -> Vec<StageReport> {
+    let mut stages: Vec<StageReport> = vec![];
+    for run in tool_runs {
+        match stages.iter_mut().find(|s| s.stage == run.stage) {
+            Some(stage) => {
+                stage.runs += 1;
+                stage.wall_ms += run.wall_ms;
+                stage.peak_rss_kib = stage.peak_rss_kib.max(run.peak_rss_kib);
+            }
+            None => stages.push(StageReport {
+                stage: run.stage,
+                tool: run.tool,
+                runs: 1,
+                wall_ms: run.wall_ms,
+                peak_rss_kib: run.peak_rss_kib,
+            }),
+        }
+    }
+    stages
+}
+
+/// 完整运行一次流水线并写出性能报告
+pub fn run(args: &BenchArgs, log: &LogOptions) -> Result<(), Box<dyn Error>> {
+    let timings_dir =
+        std::env::temp_dir().join(format!("{}-{}", TIMINGS_DIR_PREFIX, std::process::id()));
+    fs::create_dir_all(&timings_dir)?;
+    let started = Instant::now();
+    let pipeline = analyze::run_pipeline(&args.analyze, log, Some(&timings_dir))?;
+    let wall_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    // CFG的输入是 <源文件>.ast.json，按源文件的大小分组，与AST阶段保持一致
+    let ast_timings = read_timings(&timings_dir.join("ast.json"))?;
+    let mut cfg_timings = read_timings(&timings_dir.join("cfg.json"))?;
+    let source_sizes: HashMap<&Path, u64> = ast_timings
+        .iter()
+        .map(|t| (t.path.as_path(), t.bytes))
+        .collect();
+    for timing in &mut cfg_timings {
+        let source = PathBuf::from(
+            timing
+                .path
+                .to_string_lossy()
+                .trim_end_matches(".ast.json")
+                .to_string(),
+        );
+        if let Some(&bytes) = source_sizes.get(source.as_path()) {
+            timing.bytes = bytes;
+        }
+        timing.path = source;
+    }
+
+    let mut languages: BTreeMap<_, BTreeMap<_, GroupReport>> = BTreeMap::new();
+    let mut size_buckets: BTreeMap<_, BTreeMap<_, GroupReport>> = BTreeMap::new();
+    let mut slowest_files = vec![];
+    for (stage, timings) in [("ast", ast_timings), ("cfg", cfg_timings)] {
+        for timing in timings {
+            languages
+                .entry(stage)
+                .or_default()
+                .entry(language_of(&timing.path))
+                .or_default()
+                .add(timing.bytes, timing.millis);
+            size_buckets
+                .entry(stage)
+                .or_default()
+                .entry(size_bucket(timing.bytes))
+                .or_default()
+                .add(timing.bytes, timing.millis);
+            slowest_files.push(SlowFile {
+                stage,
+                path: timing.path,
+                bytes: timing.bytes,
+                millis: timing.millis,
+            });
+        }
+    }
+    slowest_files.sort_by(|a, b| b.millis.total_cmp(&a.millis));
+    slowest_files.truncate(SLOWEST_FILES);
+
+    let stages = stage_reports(&pipeline.tool_runs);
+    for stage in &stages {
+        info!(
+            stage = stage.stage,
+            wall_ms = %format!("{:.1}", stage.wall_ms),
+            peak_rss_kib = stage.peak_rss_kib,
+            "阶段耗时"
+        );
+    }
+    let report = BenchReport {
+        tool: env!("CARGO_PKG_NAME"),
+        tool_version: env!("CARGO_PKG_VERSION"),
+        generated_at: now_rfc3339(),
+        project: args.analyze.project().to_path_buf(),
+        wall_ms,
+        stages,
+        languages,
+        size_buckets,
+        slowest_files,
+    };
+    let report_path = args
+        .report
+        .clone()
+        .unwrap_or_else(|| pipeline.output_dir.join(REPORT_FILE_NAME));
+    fs::write(&report_path, serde_json::to_string_pretty(&report)?)?;
+    let _ = fs::remove_dir_all(&timings_dir);
+    info!(report = %report_path.display(), wall_ms = %format!("{:.1}", wall_ms), "已写出性能报告");
+    Ok(())
+}
@@ -0,0 +1,480 @@
+// callgraph.rs
+//
+// agent callgraph：整个项目 (程序和客户端) 的函数级调用图，格式与 merged.json 相同，另写出 callgraph.dot 用于打印
+// 在 agent link 的跨语言图上，把每个函数 (AST层) 收拢为一个节点，节点ID为函数的入口节点，可以与其他产物直接关联
+// 边都是 Call 边：程序内和客户端内的函数调用、客户端调用点到指令处理函数的跨语言调用，以及程序中的 CPI
+// CPI 从基本块的文本中识别：<程序>::cpi::<指令>(..)、token::transfer(..) 等 Anchor 辅助函数，
+// 以及 invoke/invoke_signed(&<程序>::instruction::<指令>(..), ..)；目标是项目内的程序时连到其处理函数，
+// 否则连到代表外部程序指令的节点，无法确定目标的 invoke 连到 <unresolved>::invoke
+// MIR层的函数与AST层重复，不出现在调用图中
+
+use crate::config::ArtifactsArgs;
+use crate::graph::{EdgeKind, Layer, NodeKind, Span};
+use crate::link::linked_graph;
+use crate::merge::{graph_key, node_id, MergedEdge, MergedGraph, MergedMetadata, MergedNode};
+use crate::symbols::{definition_name, AstNode, CallGraph};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use tracing::info;
+
+/// 输出文件名，默认位于产物目录下
+const CALLGRAPH_FILE_NAME: &str = "callgraph.json";
+const CALLGRAPH_DOT_FILE_NAME: &str = "callgraph.dot";
+
+/// 外部程序的指令节点所属的单元
+const EXTERNAL_UNIT: &str = "<external>";
+
+/// 无法确定目标程序的 CPI
+const UNRESOLVED_PROGRAM: &str = "<unresolved>";
+
+/// 发起 CPI 的函数
+const INVOKE_FUNCTIONS: &[&str] = &[
+    "invoke",
+    "invoke_signed",
+    "invoke_unchecked",
+    "invoke_signed_unchecked",
+];
+
+/// 模块路径 -> 程序名：Anchor 的 CPI 辅助模块 (token::transfer(..)) 和构造指令的 crate
+const KNOWN_PROGRAMS: &[(&str, &str)] = &[
+    ("token", "spl_token"),
+    ("token_interface", "spl_token"),
+    ("token_2022", "spl_token_2022"),
+    ("associated_token", "spl_associated_token_account"),
+    ("system_program", "system_program"),
+    ("system_instruction", "system_program"),
+    ("spl_token", "spl_token"),
+    ("spl_token_2022", "spl_token_2022"),
+    (
+        "spl_associated_token_account",
+        "spl_associated_token_account",
+    ),
+];
+
+/// 程序模块中不是指令的函数
+const NOT_INSTRUCTIONS: &[&str] = &["id", "check_id", "check_program_account"];
+
+/// `agent callgraph` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct CallgraphArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 callgraph.json；callgraph.dot 写在同一目录下
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 找出文本中所有形如 `a::b::c(` 的调用，返回调用的起始位置、被调用的路径和 `(` 之后的文本
+fn called_paths(text: &str) -> Vec<(usize, &str, &str)> {
+    let bytes = text.as_bytes();
+    let is_path = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b':';
+    let mut paths = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if is_path(bytes[i]) && (i == 0 || !is_path(bytes[i - 1])) {
+            let start = i;
+            while i < bytes.len() && is_path(bytes[i]) {
+                i += 1;
+            }
+            let path = text[start..i].trim_matches(':');
+            if bytes.get(i) == Some(&b'(') && !path.is_empty() && !bytes[start].is_ascii_digit() {
+                paths.push((start, path, &text[i + 1..]));
+            }
+        } else {
+            i += 1;
+        }
+    }
+    paths
+}
+
+/// 调用的第一个参数 (到同一层的 `,` 或 `)` 为止)
+fn first_argument(arguments: &str) -> &str {
+    let mut depth = 0;
+    for (i, c) in arguments.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' if depth == 0 => return &arguments[..i],
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => return &arguments[..i],
+            _ => {}
+        }
+    }
+    arguments
+}
+
+/// 模块路径对应的程序名
+fn program_of_module(module: &str) -> String {
+    KNOWN_PROGRAMS
+        .iter()
+        .find(|(m, _)| *m == module)
+        .map_or_else(|| module.to_string(), |(_, program)| program.to_string())
+}
+
+/// 构造指令的调用 `<程序>::instruction::<指令>` 或 `system_instruction::<指令>` 对应的 (程序, 指令)
+fn instruction_builder(path: &str) -> Option<(String, String)> {
+    let segments: Vec<&str> = path.split("::").collect();
+    match segments.as_slice() {
+        [.., program, "instruction", instruction] => {
+            Some((program_of_module(program), instruction.to_string()))
+        }
+        [.., module, instruction] if module.ends_with("_instruction") => {
+            Some((program_of_module(module), instruction.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// 文本中的 CPI，返回 (程序, 指令)
+pub fn cpi_targets(text: &str) -> Vec<(String, String)> {
+    let mut targets = vec![];
+    let builders = |text| {
+        called_paths(text)
+            .into_iter()
+            .filter_map(|(_, path, _)| instruction_builder(path))
+            .collect::<Vec<_>>()
+    };
+    for (start, path, arguments) in called_paths(text) {
+        let segments: Vec<&str> = path.split("::").collect();
+        match segments.as_slice() {
+            // Anchor 生成的 CPI 模块：<程序>::cpi::<指令>(ctx, ..)
+            [.., program, "cpi", instruction] => {
+                targets.push((program.to_string(), instruction.to_string()));
+            }
+            [.., module, instruction]
+                if KNOWN_PROGRAMS.iter().any(|(m, _)| m == module)
+                    && !module.ends_with("_instruction")
+                    && instruction.starts_with(char::is_lowercase)
+                    && !NOT_INSTRUCTIONS.contains(instruction) =>
+            {
+                targets.push((program_of_module(module), instruction.to_string()));
+            }
+            // 指令通常直接在第一个参数中构造，否则取前面最近一次构造的指令 (let ix = ..; invoke(&ix, ..))
+            [.., function] if INVOKE_FUNCTIONS.contains(function) => {
+                let builder = builders(first_argument(arguments))
+                    .into_iter()
+                    .next()
+                    .or_else(|| builders(&text[..start]).pop());
+                targets.push(
+                    builder
+                        .unwrap_or_else(|| (UNRESOLVED_PROGRAM.to_string(), function.to_string())),
+                );
+            }
+            _ => {}
+        }
+    }
+    targets
+}
+
+/// 函数节点的 side 属性
+pub fn side(node: &MergedNode) -> &str {
+    node.properties
+        .get("side")
+        .and_then(|s| s.as_str())
+        .unwrap_or_default()
+}
+
+/// 调用图：客户端、程序和外部程序各为一个子图，跨语言调用为虚线，CPI 为红色
+fn to_dot(graph: &MergedGraph, cpi: &HashSet<(String, String)>) -> String {
+    let mut dot = String::from("digraph callgraph {\n  rankdir=LR;\n  node [shape=box];\n");
+    for (side_name, title) in [
+        ("client", "客户端"),
+        ("program", "程序"),
+        ("external", "外部程序"),
+    ] {
+        let _ = writeln!(dot, "  subgraph {:?} {{", format!("cluster_{}", side_name));
+        let _ = writeln!(dot, "    label={:?};", title);
+        for node in graph.nodes.iter().filter(|n| side(n) == side_name) {
+            let label = match node.properties.get("instruction").and_then(|i| i.as_str()) {
+                Some(instruction) if side_name == "program" => {
+                    format!("{}\n[{}]", node.function, instruction)
+                }
+                _ => node.function.clone(),
+            };
+            let shape = if side_name == "external" {
+                ", shape=ellipse"
+            } else {
+                ""
+            };
+            let _ = writeln!(dot, "    {:?} [label={:?}{}];", node.id, label, shape);
+        }
+        dot.push_str("  }\n");
+    }
+    let sides: HashMap<&str, &str> = graph
+        .nodes
+        .iter()
+        .map(|n| (n.id.as_str(), side(n)))
+        .collect();
+    for edge in &graph.edges {
+        let style = if cpi.contains(&(edge.source.clone(), edge.target.clone())) {
+            " [color=red]"
+        } else if sides.get(edge.source.as_str()) != sides.get(edge.target.as_str()) {
+            " [style=dashed]"
+        } else {
+            ""
+        };
+        let _ = writeln!(dot, "  {:?} -> {:?}{};", edge.source, edge.target, style);
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// 函数的 side/language 属性：客户端调用图中的节点带有 language 属性，其余为程序中的 Rust 函数
+fn function_properties(language: Option<&Value>) -> BTreeMap<String, Value> {
+    let mut properties = BTreeMap::new();
+    match language {
+        Some(language) => {
+            properties.insert("side".to_string(), json!("client"));
+            properties.insert("language".to_string(), language.clone());
+        }
+        None => {
+            properties.insert("side".to_string(), json!("program"));
+            properties.insert("language".to_string(), json!("rust"));
+        }
+    }
+    properties
+}
+
+/// AST中的所有函数定义 (包括模块和 impl 中的函数)
+pub fn collect_function_items<'a>(node: &'a AstNode, items: &mut Vec<(&'a str, &'a AstNode)>) {
+    for item in &node.children {
+        if item.kind == "function_item" {
+            if let Some(name) = definition_name(item) {
+                items.push((&name.text, item));
+            }
+        }
+        collect_function_items(item, items);
+    }
+}
+
+/// 整个项目的调用图及其来源
+pub struct ProjectCallGraph {
+    pub artifacts_dir: PathBuf,
+    pub graph: MergedGraph,
+    /// 产物目录中的所有AST
+    pub asts: Vec<(PathBuf, AstNode)>,
+    /// CPI 边 (调用方, 被调用方)
+    pub cpi: HashSet<(String, String)>,
+    /// 跨语言的调用边数
+    pub cross_language: usize,
+    /// 构建调用图所用的 agent link 跨语言图
+    pub linked: MergedGraph,
+}
+
+/// 构建整个项目的函数级调用图
+pub fn project_callgraph(artifacts: &ArtifactsArgs) -> Result<ProjectCallGraph, Box<dyn Error>> {
+    let linked = linked_graph(artifacts)?;
+
+    // 每个函数一个节点，取其入口节点 (没有入口节点时取第一个节点)
+    let mut functions: BTreeMap<String, MergedNode> = BTreeMap::new();
+    for node in linked.graph.nodes.iter().filter(|n| n.layer == Layer::Ast) {
+        let key = graph_key(&node.id);
+        if node.kind == NodeKind::Entry || !functions.contains_key(key) {
+            functions.insert(
+                key.to_string(),
+                MergedNode {
+                    id: node.id.clone(),
+                    layer: Layer::Ast,
+                    function: node.function.clone(),
+                    kind: NodeKind::Entry,
+                    label: node.function.clone(),
+                    span: node.span.clone(),
+                    provenance: node.provenance.clone(),
+                    properties: function_properties(node.properties.get("language")),
+                },
+            );
+        }
+    }
+
+    // 程序中的函数定义：补上没有图的函数，按名字解析函数之间的调用，并从函数体中找出 CPI
+    let mut call_graph = CallGraph::default();
+    let mut bodies = vec![];
+    for (file, root) in &linked.asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        call_graph.add_file(file, root);
+        let mut items = vec![];
+        collect_function_items(root, &mut items);
+        for (name, item) in items {
+            let unit = file.display().to_string();
+            let key = format!("ast:{}:{}", unit, name);
+            let span = Span {
+                file: file.clone(),
+                start_byte: item.start_byte,
+                end_byte: item.end_byte,
+            };
+            let provenance = format!(
+                "{}@{}..{}",
+                span.file.display(),
+                span.start_byte,
+                span.end_byte
+            );
+            let node = functions.entry(key.clone()).or_insert_with(|| MergedNode {
+                id: node_id(Layer::Ast, &unit, name, 0),
+                layer: Layer::Ast,
+                function: name.to_string(),
+                kind: NodeKind::Entry,
+                label: name.to_string(),
+                span: None,
+                provenance: None,
+                properties: function_properties(None),
+            });
+            // 图中的入口节点没有源码范围，取函数定义的范围
+            if node.span.is_none() {
+                node.span = Some(span);
+                node.provenance = Some(provenance);
+            }
+            bodies.push((key, &item.text));
+        }
+    }
+
+    // 处理函数 -> 指令 `<program>::<ix>`
+    let mut handler_entries: HashMap<String, String> = HashMap::new();
+    for program in &linked.programs {
+        for (handler, _) in &program.handlers {
+            let key = format!("ast:{}:{}", handler.file.display(), handler.function);
+            if let Some(node) = functions.get_mut(&key) {
+                let instruction = format!("{}::{}", program.name, handler.function);
+                node.properties
+                    .insert("instruction".to_string(), json!(instruction));
+                handler_entries.insert(instruction, node.id.clone());
+            }
+        }
+    }
+
+    // 函数调用：调用点所在函数 -> 被调用函数
+    let function_of = |key: &str| functions.get(key).map(|node| node.id.clone());
+    let mut calls: Vec<(String, String)> = linked
+        .graph
+        .edges
+        .iter()
+        .filter(|e| e.kind == EdgeKind::Call)
+        .filter_map(|e| {
+            Some((
+                function_of(graph_key(&e.source))?,
+                function_of(graph_key(&e.target))?,
+            ))
+        })
+        .collect();
+    for ((caller_file, caller), (callee_file, callee)) in call_graph.calls() {
+        let key = |file: &PathBuf, name: &str| format!("ast:{}:{}", file.display(), name);
+        if let (Some(source), Some(target)) = (
+            function_of(&key(&caller_file, &caller)),
+            function_of(&key(&callee_file, &callee)),
+        ) {
+            calls.push((source, target));
+        }
+    }
+    let side_of: HashMap<&str, &str> = functions
+        .values()
+        .map(|n| (n.id.as_str(), side(n)))
+        .collect();
+    let mut seen = HashSet::new();
+    let mut edges = vec![];
+    let mut cross_language = 0;
+    for (source, target) in calls {
+        if seen.insert((source.clone(), target.clone())) {
+            if side_of.get(source.as_str()) != side_of.get(target.as_str()) {
+                cross_language += 1;
+            }
+            edges.push(MergedEdge {
+                source,
+                target,
+                kind: EdgeKind::Call,
+            });
+        }
+    }
+
+    // CPI：项目内的程序连到处理函数，其余连到外部程序的指令节点
+    let mut external: BTreeMap<String, (String, String)> = BTreeMap::new();
+    let mut cpi = HashSet::new();
+    for (key, body) in bodies {
+        let source = functions[&key].id.clone();
+        for (program, instruction) in cpi_targets(body) {
+            let name = format!("{}::{}", program, instruction);
+            let target = match handler_entries.get(&name) {
+                Some(entry) => entry.clone(),
+                None => {
+                    let id = node_id(Layer::Ast, EXTERNAL_UNIT, &name, 0);
+                    external.insert(id.clone(), (program, instruction));
+                    id
+                }
+            };
+            if seen.insert((source.clone(), target.clone())) {
+                cpi.insert((source.clone(), target.clone()));
+                edges.push(MergedEdge {
+                    source: source.clone(),
+                    target,
+                    kind: EdgeKind::Call,
+                });
+            }
+        }
+    }
+    let mut nodes: Vec<MergedNode> = functions.into_values().collect();
+    for (id, (program, instruction)) in external {
+        let name = format!("{}::{}", program, instruction);
+        let mut properties = BTreeMap::new();
+        properties.insert("side".to_string(), json!("external"));
+        properties.insert("program".to_string(), json!(program));
+        properties.insert("instruction".to_string(), json!(instruction));
+        nodes.push(MergedNode {
+            id,
+            layer: Layer::Ast,
+            function: name.clone(),
+            kind: NodeKind::Entry,
+            label: name,
+            span: None,
+            provenance: None,
+            properties,
+        });
+    }
+
+    Ok(ProjectCallGraph {
+        artifacts_dir: linked.artifacts_dir,
+        graph: MergedGraph {
+            metadata: MergedMetadata::current(),
+            nodes,
+            edges,
+        },
+        asts: linked.asts,
+        cpi,
+        cross_language,
+        linked: linked.graph,
+    })
+}
+
+/// 构建整个项目的调用图，写出 callgraph.json 和 callgraph.dot
+pub fn run(args: &CallgraphArgs) -> Result<(), Box<dyn Error>> {
+    let ProjectCallGraph {
+        artifacts_dir,
+        graph,
+        cpi,
+        cross_language,
+        ..
+    } = project_callgraph(&args.artifacts)?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(CALLGRAPH_FILE_NAME));
+    let dot_output = output.with_file_name(CALLGRAPH_DOT_FILE_NAME);
+    fs::write(&output, serde_json::to_string_pretty(&graph)?)?;
+    fs::write(&dot_output, to_dot(&graph, &cpi))?;
+    let count = |side_name: &str| graph.nodes.iter().filter(|n| side(n) == side_name).count();
+    info!(
+        program_functions = count("program"),
+        client_functions = count("client"),
+        external_instructions = count("external"),
+        call_edges = graph.edges.len(),
+        cross_language_edges = cross_language,
+        cpi_edges = cpi.len(),
+        output = %output.display(),
+        dot = %dot_output.display(),
+        "已写出调用图"
+    );
+    Ok(())
+}
@@ -0,0 +1,690 @@
+// chunks.rs
+//
+// agent analyze 的分块模式：按包 (Cargo.toml 或 package.json 所在的目录) 把项目切成互相独立的块，
+// 每块由单独的AST和CFG生成器进程处理，峰值内存取决于最大的块而不是整个项目；--chunk-processes 可以同时处理多个块
+// 嵌套的包各自成块，不计入外层的包；不属于任何包的文件 (例如工作区根目录下的脚本) 组成 _root 块
+// 每块先生成到 chunks/<块>/ 下，完成后AST和CFG移到 ast/ 和 cfg/ 中，块自己的 manifest.json 和 skipped.json 留在原处；
+// 全部完成后各块的 manifest 合并为 ast/ 和 cfg/ 的 manifest，skipped.json 逐项合并，
+// 因此之后的阶段和命令看到的产物与不分块时相同
+// 块的源文件、生成器参数和生成器版本都没有变化时直接复用上一次的产物
+
+use crate::analyze::{ast_options, cfg_options, push_opt, run_tool, tool_version, ToolRun};
+use crate::config::Config;
+use crate::manifest::{
+    content_hash, now_rfc3339, Artifact, PreviousRunManifest, StageManifest, MANIFEST_FILE_NAME,
+};
+use crate::scope::{self, literal_glob};
+use crate::symbols::language_of;
+use crate::LogOptions;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+use tracing::{debug, info};
+use walkdir::WalkDir;
+
+/// 各块的中间产物所在的目录，位于输出目录下
+pub const CHUNKS_DIR_NAME: &str = "chunks";
+
+/// 不属于任何包的文件所在的块
+const ROOT_CHUNK_NAME: &str = "_root";
+
+/// 标志一个包的文件
+const PACKAGE_MANIFESTS: &[&str] = &["Cargo.toml", "package.json"];
+
+/// 查找包时不进入的目录：其中的 Cargo.toml/package.json 属于构建产物或第三方依赖
+pub const IGNORED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// 各生成器写出的跳过记录
+const SKIPPED_FILE_NAME: &str = "skipped.json";
+
+/// 分块处理的两个阶段：阶段名 (也是输出子目录) 及其生成器
+const STAGES: [(&str, &str); 2] = [
+    ("ast", "solana_ast_generator"),
+    ("cfg", "solana_cfg_generator"),
+];
+
+/// 一个块，记录在输出根目录的 manifest.json 中
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Chunk {
+    pub name: String,
+    /// 包的目录 (相对于项目根目录)；_root 块为空
+    pub root: PathBuf,
+    /// 块中的源文件数
+    pub files: usize,
+    /// 源文件、生成器参数和生成器版本的哈希
+    pub source_hash: String,
+}
+
+/// 一个块要遍历的目录，以及其中要跳过的嵌套包 (只列出最外层的)
+struct Plan {
+    chunk: Chunk,
+    roots: Vec<PathBuf>,
+    nested: Vec<PathBuf>,
+}
+
+/// 一次分块运行的设置
+pub struct ChunkedRun<'a> {
+    pub project: &'a Path,
+    pub output_dir: &'a Path,
+    pub config: &'a Config,
+    pub jobs: Option<usize>,
+    /// 同时处理的块数
+    pub processes: usize,
+    pub log: &'a LogOptions,
+}
+
+/// 包目录的块名，例如 programs/vault -> programs_vault
+fn chunk_name(root: &Path) -> String {
+    if root.as_os_str().is_empty() {
+        return ROOT_CHUNK_NAME.to_string();
+    }
+    root.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// 最外层的包：不在其他包之内的那些；`packages` 已排序
+fn outermost<'a>(packages: impl Iterator<Item = &'a PathBuf>) -> Vec<PathBuf> {
+    let mut outer: Vec<PathBuf> = vec![];
+    for package in packages {
+        if !outer.last().is_some_and(|last| package.starts_with(last)) {
+            outer.push(package.clone());
+        }
+    }
+    outer
+}
+
+/// 读取某个阶段输出目录中的 manifest.json
+fn load_stage_manifest(stage_dir: &Path) -> Result<StageManifest, Box<dyn Error>> {
+    let path = stage_dir.join(MANIFEST_FILE_NAME);
+    let content =
+        fs::read_to_string(&path).map_err(|e| format!("无法读取 {}: {}", path.display(), e))?;
+    serde_json::from_str(&content).map_err(|e| format!("无法解析 {}: {}", path.display(), e).into())
+}
+
+impl ChunkedRun<'_> {
+    /// 路径相对于项目根目录的部分
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(self.project)
+            .unwrap_or(path)
+            .to_path_buf()
+    }
+
+    /// 块的中间产物目录
+    fn chunk_dir(&self, name: &str) -> PathBuf {
+        self.output_dir.join(CHUNKS_DIR_NAME).join(name)
+    }
+
+    /// 在输入目录 (input.roots，默认为整个项目) 中查找所有的包，不含项目根目录本身
+    fn find_packages(&self) -> Vec<PathBuf> {
+        let output = self.relative(self.output_dir);
+        let search = if self.config.input.roots.is_empty() {
+            vec![PathBuf::new()]
+        } else {
+            self.config.input.roots.clone()
+        };
+        let mut packages = BTreeSet::new();
+        for root in search {
+            let walker = WalkDir::new(self.project.join(root))
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|e| {
+                    let name = e.file_name().to_string_lossy();
+                    e.depth() == 0
+                        || !e.file_type().is_dir()
+                        || !(name.starts_with('.')
+                            || IGNORED_DIRS.contains(&name.as_ref())
+                            || self.relative(e.path()) == output)
+                });
+            for entry in walker.filter_map(|e| e.ok()) {
+                let relative = self.relative(entry.path());
+                if entry.file_type().is_dir()
+                    && !relative.as_os_str().is_empty()
+                    && PACKAGE_MANIFESTS
+                        .iter()
+                        .any(|m| entry.path().join(m).is_file())
+                {
+                    packages.insert(relative);
+                }
+            }
+        }
+        packages.into_iter().collect()
+    }
+
+    /// AST生成器中决定块的内容的参数：要遍历的目录、include 和跳过的嵌套包
+    fn scope_args(&self, plan: &Plan) -> Vec<String> {
+        let mut args = vec![];
+        for root in plan.roots.iter().filter(|r| !r.as_os_str().is_empty()) {
+            push_opt(&mut args, "--root", Some(root.display()));
+        }
+        for pattern in &self.config.input.include {
+            push_opt(&mut args, "--include", Some(pattern));
+        }
+        for nested in &plan.nested {
+            push_opt(
+                &mut args,
+                "--exclude",
+                Some(format!("{}/**", literal_glob(nested))),
+            );
+        }
+        args
+    }
+
+    /// 确定一个块的源文件并计算哈希；块中没有源文件时返回 None
+    fn plan(
+        &self,
+        root: PathBuf,
+        roots: Vec<PathBuf>,
+        nested: Vec<PathBuf>,
+        versions: &[String],
+    ) -> Result<Option<Plan>, Box<dyn Error>> {
+        let include = scope::globset(&self.config.input.include)?;
+        let exclude = scope::globset(&self.config.input.exclude)?;
+        let mut plan = Plan {
+            chunk: Chunk {
+                name: chunk_name(&root),
+                root,
+                files: 0,
+                source_hash: String::new(),
+            },
+            roots,
+            nested,
+        };
+        let mut hasher = blake3::Hasher::new();
+        // 线程数不影响生成的内容，不计入哈希
+        for part in self
+            .scope_args(&plan)
+            .iter()
+            .chain(&ast_options(self.config, None))
+            .chain(&cfg_options(self.config, None))
+            .chain(versions)
+        {
+            hasher.update(part.as_bytes());
+            hasher.update(&[0]);
+        }
+        // 与生成器一样不进入 target、node_modules，否则其中的文件会改变块的哈希
+        for root in &plan.roots {
+            let walker = WalkDir::new(self.project.join(root))
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|e| {
+                    let ignored = e.depth() > 0
+                        && e.file_type().is_dir()
+                        && IGNORED_DIRS.contains(&e.file_name().to_string_lossy().as_ref());
+                    !ignored && !plan.nested.contains(&self.relative(e.path()))
+                });
+            for entry in walker.filter_map(|e| e.ok()) {
+                let relative = self.relative(entry.path());
+                if !entry.file_type().is_file()
+                    || language_of(&relative).is_none()
+                    || (!self.config.input.include.is_empty() && !include.is_match(&relative))
+                    || exclude.is_match(&relative)
+                {
+                    continue;
+                }
+                hasher.update(relative.to_string_lossy().as_bytes());
+                hasher.update(&[0]);
+                hasher.update(&fs::read(entry.path())?);
+                plan.chunk.files += 1;
+            }
+        }
+        if plan.chunk.files == 0 {
+            debug!(chunk = %plan.chunk.name, "块中没有源文件，已跳过");
+            return Ok(None);
+        }
+        plan.chunk.source_hash = hasher.finalize().to_hex().to_string();
+        Ok(Some(plan))
+    }
+
+    /// 上一次的产物是否可以复用：块未变化，且它的全部产物仍然存在
+    fn reusable(&self, plan: &Plan, previous: &PreviousRunManifest) -> bool {
+        let unchanged = previous
+            .chunks
+            .iter()
+            .any(|c| c.name == plan.chunk.name && c.source_hash == plan.chunk.source_hash);
+        unchanged
+            && STAGES.iter().all(|(stage, _)| {
+                load_stage_manifest(&self.chunk_dir(&plan.chunk.name).join(stage)).is_ok_and(
+                    |manifest| {
+                        manifest
+                            .artifacts
+                            .iter()
+                            .filter(|a| a.kind != "report")
+                            .all(|a| self.output_dir.join(stage).join(&a.path).is_file())
+                    },
+                )
+            })
+    }
+
+    /// 处理一个块：在块的目录下生成AST和CFG，再把它们移到 ast/ 和 cfg/ 中
+    fn run_chunk(&self, plan: &Plan) -> Result<Vec<ToolRun>, Box<dyn Error>> {
+        let chunk_dir = self.chunk_dir(&plan.chunk.name);
+        if chunk_dir.exists() {
+            fs::remove_dir_all(&chunk_dir)?;
+        }
+        let ast_dir = chunk_dir.join("ast");
+        let cfg_dir = chunk_dir.join("cfg");
+
+        let mut ast_args = self.log.to_tool_args();
+        ast_args.extend([
+            "--input".to_string(),
+            self.project.display().to_string(),
+            "--output".to_string(),
+            ast_dir.display().to_string(),
+        ]);
+        ast_args.extend(self.scope_args(plan));
+        ast_args.extend(ast_options(self.config, self.jobs));
+        let mut cfg_args = self.log.to_tool_args();
+        cfg_args.extend([
+            "--input".to_string(),
+            ast_dir.display().to_string(),
+            "--output".to_string(),
+            cfg_dir.display().to_string(),
+        ]);
+        cfg_args.extend(cfg_options(self.config, self.jobs));
+        let runs = vec![
+            run_tool(STAGES[0].0, STAGES[0].1, &ast_args)?,
+            run_tool(STAGES[1].0, STAGES[1].1, &cfg_args)?,
+        ];
+
+        for (stage, _) in STAGES {
+            let manifest = load_stage_manifest(&chunk_dir.join(stage))?;
+            for artifact in manifest.artifacts.iter().filter(|a| a.kind != "report") {
+                let target = self.output_dir.join(stage).join(&artifact.path);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(chunk_dir.join(stage).join(&artifact.path), target)?;
+            }
+        }
+        Ok(runs)
+    }
+
+    /// 把各块的 manifest 和 skipped.json 合并为 ast/ 和 cfg/ 的，并删除上一次生成、这次不再有的产物
+    fn merge(&self, plans: &[Plan], versions: &[String]) -> Result<(), Box<dyn Error>> {
+        for ((stage, tool), version) in STAGES.iter().zip(versions) {
+            let stage_dir = self.output_dir.join(stage);
+            fs::create_dir_all(&stage_dir)?;
+            let mut artifacts = vec![];
+            let mut skipped: Vec<Value> = vec![];
+            // 生成器在 manifest 中另外记录的字段 (例如CFG的格式版本和输出格式) 各块相同，取第一块的
+            let mut template = None;
+            for plan in plans {
+                let chunk_stage_dir = self.chunk_dir(&plan.chunk.name).join(stage);
+                let manifest = load_stage_manifest(&chunk_stage_dir)?;
+                if template.is_none() {
+                    template = fs::read_to_string(chunk_stage_dir.join(MANIFEST_FILE_NAME))
+                        .ok()
+                        .and_then(|content| serde_json::from_str::<Value>(&content).ok());
+                }
+                artifacts.extend(
+                    manifest
+                        .artifacts
+                        .into_iter()
+                        .filter(|a| a.kind != "report"),
+                );
+                if let Some(Value::Array(items)) =
+                    fs::read_to_string(chunk_stage_dir.join(SKIPPED_FILE_NAME))
+                        .ok()
+                        .and_then(|content| serde_json::from_str(&content).ok())
+                {
+                    skipped.extend(items);
+                }
+            }
+
+            if let Ok(previous) = load_stage_manifest(&stage_dir) {
+                let current: HashSet<&PathBuf> = artifacts.iter().map(|a| &a.path).collect();
+                for stale in previous
+                    .artifacts
+                    .iter()
+                    .filter(|a| a.kind != "report" && !current.contains(&a.path))
+                {
+                    debug!(output = %stale.path.display(), "删除过期的产物");
+                    let _ = fs::remove_file(stage_dir.join(&stale.path));
+                }
+            }
+
+            let content = serde_json::to_string_pretty(&skipped)?;
+            fs::write(stage_dir.join(SKIPPED_FILE_NAME), &content)?;
+            artifacts.push(Artifact {
+                path: PathBuf::from(SKIPPED_FILE_NAME),
+                kind: "report".to_string(),
+                source: None,
+                source_hash: None,
+                hash: content_hash(content.as_bytes()),
+            });
+            artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+            let mut manifest = serde_json::to_value(StageManifest {
+                tool: tool.to_string(),
+                tool_version: version.clone(),
+                generated_at: now_rfc3339(),
+                artifacts,
+            })?;
+            if let (Some(Value::Object(mut fields)), Value::Object(merged)) = (template, &manifest)
+            {
+                fields.extend(merged.clone());
+                manifest = Value::Object(fields);
+            }
+            fs::write(
+                stage_dir.join(MANIFEST_FILE_NAME),
+                serde_json::to_string_pretty(&manifest)?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 分块生成AST和CFG，返回本次的块及每次生成器运行的耗时
+    pub fn run(
+        &self,
+        previous: &PreviousRunManifest,
+    ) -> Result<(Vec<Chunk>, Vec<ToolRun>), Box<dyn Error>> {
+        let versions: Vec<String> = STAGES.iter().map(|(_, tool)| tool_version(tool)).collect();
+        let packages = self.find_packages();
+        let root_roots = if self.config.input.roots.is_empty() {
+            vec![PathBuf::new()]
+        } else {
+            self.config.input.roots.clone()
+        };
+        let mut plans = vec![];
+        plans.extend(self.plan(
+            PathBuf::new(),
+            root_roots,
+            outermost(packages.iter()),
+            &versions,
+        )?);
+        for package in &packages {
+            let nested = outermost(
+                packages
+                    .iter()
+                    .filter(|p| *p != package && p.starts_with(package)),
+            );
+            plans.extend(self.plan(package.clone(), vec![package.clone()], nested, &versions)?);
+        }
+
+        // 已经不存在的块的中间产物
+        let names: HashSet<&str> = plans.iter().map(|p| p.chunk.name.as_str()).collect();
+        if let Ok(entries) = fs::read_dir(self.output_dir.join(CHUNKS_DIR_NAME)) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                if !names.contains(entry.file_name().to_string_lossy().as_ref()) {
+                    fs::remove_dir_all(entry.path())?;
+                }
+            }
+        }
+
+        let pending: Vec<&Plan> = plans
+            .iter()
+            .filter(|plan| !self.reusable(plan, previous))
+            .collect();
+        let processes = self.processes.clamp(1, pending.len().max(1));
+        info!(
+            chunks = plans.len(),
+            reused = plans.len() - pending.len(),
+            processes,
+            "开始分块处理"
+        );
+
+        // 每个线程依次取出下一个块，在子进程中处理；有块失败后不再开始新的块
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new(vec![]);
+        thread::scope(|s| {
+            for _ in 0..processes {
+                s.spawn(|| {
+                    while !failed.load(Ordering::Relaxed) {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(plan) = pending.get(i) else {
+                            break;
+                        };
+                        let started = Instant::now();
+                        let result = self
+                            .run_chunk(plan)
+                            .map_err(|e| format!("块 {} 处理失败: {}", plan.chunk.name, e));
+                        match &result {
+                            Ok(_) => info!(
+                                chunk = %plan.chunk.name,
+                                files = plan.chunk.files,
+                                elapsed_ms = started.elapsed().as_millis() as u64,
+                                "块已完成"
+                            ),
+                            Err(_) => failed.store(true, Ordering::Relaxed),
+                        }
+                        results.lock().unwrap().push((i, result));
+                    }
+                });
+            }
+        });
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(i, _)| *i);
+        let mut tool_runs = vec![];
+        for (_, result) in results {
+            tool_runs.extend(result?);
+        }
+
+        self.merge(&plans, &versions)?;
+        Ok((plans.into_iter().map(|p| p.chunk).collect(), tool_runs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LogFormat;
+
+    fn write(dir: &Path, file: &str, content: &str) {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// 工作区根目录下的脚本、一个程序包和其中嵌套的 SDK 包；node_modules 中的包不算
+    fn project(name: &str) -> PathBuf {
+        let project = std::env::temp_dir().join(format!(
+            "solana_agent-chunks-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&project);
+        write(&project, "Cargo.toml", "[workspace]\n");
+        write(&project, "scripts/deploy.ts", "deploy();\n");
+        write(&project, "programs/vault/Cargo.toml", "[package]\n");
+        write(&project, "programs/vault/src/lib.rs", "pub fn vault() {}\n");
+        write(&project, "programs/vault/sdk/package.json", "{}\n");
+        write(
+            &project,
+            "programs/vault/sdk/index.ts",
+            "export const a = 1;\n",
+        );
+        write(&project, "node_modules/dep/package.json", "{}\n");
+        write(
+            &project,
+            "node_modules/dep/index.js",
+            "module.exports = 1;\n",
+        );
+        project
+    }
+
+    fn plans(run: &ChunkedRun) -> Vec<Plan> {
+        let packages = run.find_packages();
+        let mut plans = vec![];
+        plans.extend(
+            run.plan(
+                PathBuf::new(),
+                vec![PathBuf::new()],
+                outermost(packages.iter()),
+                &[],
+            )
+            .unwrap(),
+        );
+        for package in &packages {
+            let nested = outermost(
+                packages
+                    .iter()
+                    .filter(|p| *p != package && p.starts_with(package)),
+            );
+            plans.extend(
+                run.plan(package.clone(), vec![package.clone()], nested, &[])
+                    .unwrap(),
+            );
+        }
+        plans
+    }
+
+    const LOG: LogOptions = LogOptions {
+        verbose: 0,
+        quiet: true,
+        format: LogFormat::Text,
+    };
+
+    #[test]
+    fn nested_packages_form_their_own_chunks() {
+        let project = project("plan");
+        let config = Config::default();
+        let output_dir = project.join("analysis");
+        let run = ChunkedRun {
+            project: &project,
+            output_dir: &output_dir,
+            config: &config,
+            jobs: None,
+            processes: 1,
+            log: &LOG,
+        };
+        assert_eq!(
+            run.find_packages(),
+            [
+                PathBuf::from("programs/vault"),
+                PathBuf::from("programs/vault/sdk")
+            ]
+        );
+        let before = plans(&run);
+        let chunks: Vec<_> = before
+            .iter()
+            .map(|p| (p.chunk.name.as_str(), p.chunk.files))
+            .collect();
+        // 每个文件只属于最内层的包；根目录的块不含任何包中的文件，也不含 node_modules 中的文件
+        assert_eq!(
+            chunks,
+            [
+                ("_root", 2),
+                ("programs_vault", 2),
+                ("programs_vault_sdk", 1)
+            ]
+        );
+
+        // 只有文件所在的块的哈希改变
+        write(
+            &project,
+            "programs/vault/sdk/index.ts",
+            "export const a = 2;\n",
+        );
+        let after = plans(&run);
+        fs::remove_dir_all(&project).unwrap();
+        let changed: Vec<_> = before
+            .iter()
+            .zip(&after)
+            .map(|(b, a)| b.chunk.source_hash != a.chunk.source_hash)
+            .collect();
+        assert_eq!(changed, [false, false, true]);
+    }
+
+    #[test]
+    fn merge_combines_chunk_manifests_and_drops_stale_artifacts() {
+        let output_dir =
+            std::env::temp_dir().join(format!("solana_agent-chunks-merge-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&output_dir);
+        let config = Config::default();
+        let run = ChunkedRun {
+            project: Path::new("."),
+            output_dir: &output_dir,
+            config: &config,
+            jobs: None,
+            processes: 1,
+            log: &LOG,
+        };
+        let artifact = |path: &str| Artifact {
+            path: PathBuf::from(path),
+            kind: "ast".to_string(),
+            source: None,
+            source_hash: None,
+            hash: content_hash(path.as_bytes()),
+        };
+        let manifest = |artifacts: Vec<Artifact>| {
+            serde_json::to_string(&StageManifest {
+                tool: "tool".to_string(),
+                tool_version: "0".to_string(),
+                generated_at: now_rfc3339(),
+                artifacts,
+            })
+            .unwrap()
+        };
+        let mut plans = vec![];
+        for (name, file) in [("a", "a.ast.json"), ("b", "b.ast.json")] {
+            for (stage, _) in STAGES {
+                let dir = run.chunk_dir(name).join(stage);
+                write(&dir, MANIFEST_FILE_NAME, &manifest(vec![artifact(file)]));
+                write(
+                    &dir,
+                    SKIPPED_FILE_NAME,
+                    &format!(r#"[{{"path": "{}"}}]"#, name),
+                );
+                write(&output_dir.join(stage), file, "{}");
+            }
+            plans.push(Plan {
+                chunk: Chunk {
+                    name: name.to_string(),
+                    root: PathBuf::from(name),
+                    files: 1,
+                    source_hash: name.to_string(),
+                },
+                roots: vec![],
+                nested: vec![],
+            });
+        }
+        // 上一次运行还有块 c 的产物
+        write(&output_dir.join("ast"), "c.ast.json", "{}");
+        write(
+            &output_dir.join("ast"),
+            MANIFEST_FILE_NAME,
+            &manifest(vec![artifact("c.ast.json")]),
+        );
+
+        let previous = PreviousRunManifest {
+            chunks: plans.iter().map(|p| p.chunk.clone()).collect(),
+            ..PreviousRunManifest::default()
+        };
+        assert!(plans.iter().all(|p| run.reusable(p, &previous)));
+        run.merge(&plans, &["1".to_string(), "2".to_string()])
+            .unwrap();
+        let merged = load_stage_manifest(&output_dir.join("ast")).unwrap();
+        let skipped = fs::read_to_string(output_dir.join("cfg").join(SKIPPED_FILE_NAME)).unwrap();
+        let stale = output_dir.join("ast").join("c.ast.json").exists();
+        fs::remove_file(output_dir.join("cfg").join("b.ast.json")).unwrap();
+        let reusable = run.reusable(&plans[1], &previous);
+        fs::remove_dir_all(&output_dir).unwrap();
+
+        let paths: Vec<_> = merged.artifacts.iter().map(|a| a.path.clone()).collect();
+        assert_eq!(
+            paths,
+            ["a.ast.json", "b.ast.json", SKIPPED_FILE_NAME].map(PathBuf::from)
+        );
+        assert_eq!(merged.tool_version, "1");
+        assert_eq!(
+            serde_json::from_str::<Value>(&skipped)
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(!stale);
+        // 块的产物缺失后不再复用
+        assert!(!reusable);
+    }
+}
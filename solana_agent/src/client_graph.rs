@@ -0,0 +1,748 @@
+// client_graph.rs
+//
+// agent client-graph：基于 TypeScript/JavaScript 的AST构建客户端代码的调用图，格式与 merged.json 相同
+// 每个函数有入口和出口节点，中间按求值顺序排列函数中的调用点：
+// 调用项目内的函数 (Call 边连到被调用函数的入口)、Anchor 客户端调用 (program.methods.<ix>(..).accounts(..).rpc() 整条链为一个节点)
+// 以及 web3.js 中构造、签名和发送交易的调用；其他调用 (console.log 等) 不出现在图中
+// 调用的结果赋给变量后又被后面的调用用到，或者直接作为外层调用的参数/接收者时，两者之间有一条 DataFlow 边
+// 不在任何具名函数中的调用归入 <module>
+
+use crate::config::ArtifactsArgs;
+use crate::graph::{EdgeKind, Layer, NodeKind, Span};
+use crate::idl::snake_case;
+use crate::merge::{node_id, MergedEdge, MergedGraph, MergedMetadata, MergedNode};
+use crate::symbols::{child, definition_name, load_asts, normalize, AstNode};
+use crate::test_coverage::{collect_declarations, program_of_declaration, NAMESPACES};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// 输出文件名，默认位于产物目录下
+const CLIENT_GRAPH_FILE_NAME: &str = "client_graph.json";
+
+/// 不在任何具名函数中的调用所属的函数名
+const MODULE_FUNCTION: &str = "<module>";
+
+/// Anchor 方法链中指定账户的方法
+const ACCOUNTS_METHODS: &[&str] = &["accounts", "accountsStrict", "accountsPartial"];
+
+/// 调用的结果中签名者所在的位置
+#[derive(Clone, Copy)]
+enum SignerArgs {
+    None,
+    /// 第 n 个参数 (数组或单个签名者)
+    At(usize),
+    /// 所有参数
+    All,
+}
+
+/// web3.js 中构造、签名和发送交易的调用：(被调用的名字, 操作, 签名者)
+/// `new X` 匹配构造调用，其余匹配被调用者的最后一段
+const WEB3_CALLS: &[(&str, &str, SignerArgs)] = &[
+    ("new Transaction", "transaction", SignerArgs::None),
+    ("new VersionedTransaction", "transaction", SignerArgs::None),
+    ("new TransactionMessage", "message", SignerArgs::None),
+    (
+        "new TransactionInstruction",
+        "instruction",
+        SignerArgs::None,
+    ),
+    ("add", "add_instruction", SignerArgs::None),
+    ("sign", "sign", SignerArgs::All),
+    ("partialSign", "sign", SignerArgs::All),
+    ("sendAndConfirmTransaction", "send", SignerArgs::At(2)),
+    ("sendTransaction", "send", SignerArgs::At(1)),
+    ("sendAndConfirm", "send", SignerArgs::At(1)),
+    ("sendRawTransaction", "send_raw", SignerArgs::None),
+    ("sendAndConfirmRawTransaction", "send_raw", SignerArgs::None),
+];
+
+/// 只有接收者是交易时才算 web3.js 调用的方法名
+const TRANSACTION_METHODS: &[&str] = &["add", "sign", "partialSign"];
+
+/// `agent client-graph` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct ClientGraphArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 client_graph.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 函数中的一个调用点
+struct Site<'a> {
+    node: &'a AstNode,
+    /// 被调用者的最后一段，用于解析到项目内的函数
+    name: String,
+    /// Anchor 或 web3.js 调用的属性；为空表示普通调用
+    properties: BTreeMap<String, Value>,
+    /// 结果赋给的变量
+    binding: Option<String>,
+}
+
+/// 客户端代码中的一个函数
+struct ClientFunction<'a> {
+    file: &'a Path,
+    language: &'static str,
+    name: String,
+    node: Option<&'a AstNode>,
+    sites: Vec<Site<'a>>,
+}
+
+/// 遍历一个文件时的状态
+struct FileWalk<'a> {
+    file: &'a Path,
+    language: &'static str,
+    declarations: HashMap<String, String>,
+    functions: Vec<ClientFunction<'a>>,
+    /// <module> 在 functions 中的下标
+    module: Option<usize>,
+    /// 调用表达式的源码范围 -> 结果赋给的变量
+    bindings: HashMap<(usize, usize), String>,
+}
+
+/// 去掉 await 和括号后的表达式
+fn unwrap_expression(node: &AstNode) -> &AstNode {
+    match node.kind.as_str() {
+        "await_expression"
+        | "parenthesized_expression"
+        | "non_null_expression"
+        | "as_expression" => node
+            .children
+            .iter()
+            .find(|c| c.kind.ends_with("expression") || c.kind == "identifier")
+            .map_or(node, unwrap_expression),
+        _ => node,
+    }
+}
+
+/// 调用的参数节点 (去掉括号和逗号)
+fn arguments(call: &AstNode) -> Vec<&AstNode> {
+    child(call, "arguments")
+        .into_iter()
+        .flat_map(|args| &args.children)
+        .filter(|c| !matches!(c.kind.as_str(), "(" | ")" | ","))
+        .collect()
+}
+
+/// 数组字面量的元素；不是数组时为它本身
+fn elements(node: &AstNode) -> Vec<String> {
+    if node.kind == "array" {
+        node.children
+            .iter()
+            .filter(|c| !matches!(c.kind.as_str(), "[" | "]" | ","))
+            .map(|c| normalize(&c.text))
+            .collect()
+    } else {
+        vec![normalize(&node.text)]
+    }
+}
+
+/// 对象字面量中的属性名和值，`{ a }` 的值为 None
+fn properties(object: &AstNode) -> Vec<(String, Option<&AstNode>)> {
+    object
+        .children
+        .iter()
+        .filter_map(|c| match c.kind.as_str() {
+            "pair" => {
+                let key = c
+                    .children
+                    .first()?
+                    .text
+                    .trim_matches(['"', '\''])
+                    .to_string();
+                Some((key, c.children.last()))
+            }
+            "shorthand_property_identifier" => Some((c.text.clone(), None)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 对象字面量中某个属性的值
+fn property<'a>(object: &'a AstNode, key: &str) -> Option<&'a AstNode> {
+    properties(object)
+        .into_iter()
+        .find(|(k, _)| k == key)
+        .and_then(|(_, value)| value)
+}
+
+/// 对象字面量中某个属性的值的文本，`{ a }` 的值为 a
+fn property_text(object: &AstNode, key: &str) -> Option<String> {
+    properties(object)
+        .into_iter()
+        .find(|(k, _)| k == key)
+        .map(|(k, value)| value.map_or(k, |v| normalize(&v.text)))
+}
+
+/// 对象字面量的属性名，按书写顺序
+fn keys(object: &AstNode) -> Vec<String> {
+    properties(object).into_iter().map(|(key, _)| key).collect()
+}
+
+/// `[{ pubkey, isSigner, isWritable }, ..]` 形式的账户列表，按书写顺序
+fn account_metas(array: &AstNode) -> Value {
+    if array.kind != "array" {
+        return normalize(&array.text).into();
+    }
+    array
+        .children
+        .iter()
+        .filter(|c| c.kind == "object")
+        .map(|meta| {
+            let flag = |key| match property_text(meta, key).as_deref() {
+                Some("true") => json!(true),
+                Some("false") | None => json!(false),
+                Some(other) => json!(other),
+            };
+            json!({
+                "pubkey": property_text(meta, "pubkey"),
+                "is_signer": flag("isSigner"),
+                "is_writable": flag("isWritable"),
+            })
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// program.<命名空间>.<指令>(..) 开头的整条方法链；`call` 为链上最外层的调用
+fn anchor_call<'a>(
+    call: &'a AstNode,
+    declarations: &HashMap<String, String>,
+) -> Option<(Vec<&'a AstNode>, BTreeMap<String, Value>)> {
+    // 从外到内依次为 (方法名, 调用节点)
+    let mut links = vec![];
+    let mut current = call;
+    while current.kind == "call_expression" {
+        let callee = current.children.first()?;
+        if callee.kind != "member_expression" {
+            return None;
+        }
+        let method = child(callee, "property_identifier")?;
+        let object = callee.children.first()?;
+        links.push((method.text.as_str(), current));
+        if object.kind == "member_expression" {
+            let namespace = child(object, "property_identifier").map(|p| p.text.as_str());
+            if let Some(namespace) = namespace.filter(|n| NAMESPACES.contains(n)) {
+                let receiver = normalize(&object.children.first()?.text);
+                let variable = receiver.rsplit('.').next().unwrap_or(&receiver);
+                let program = program_of_declaration(&receiver).or_else(|| {
+                    declarations
+                        .get(variable)
+                        .and_then(|d| program_of_declaration(d))
+                });
+                return Some(anchor_properties(links, namespace, program));
+            }
+        }
+        current = object;
+    }
+    None
+}
+
+/// Anchor 方法链的属性：指令、账户、签名者以及最后一个方法
+fn anchor_properties<'a>(
+    links: Vec<(&str, &'a AstNode)>,
+    namespace: &str,
+    program: Option<String>,
+) -> (Vec<&'a AstNode>, BTreeMap<String, Value>) {
+    let (method, instruction_call) = links[links.len() - 1];
+    let mut properties = BTreeMap::new();
+    properties.insert("category".to_string(), json!("anchor"));
+    properties.insert("namespace".to_string(), json!(namespace));
+    properties.insert("method".to_string(), json!(method));
+    properties.insert("instruction".to_string(), json!(snake_case(method)));
+    if let Some(program) = program {
+        properties.insert("program".to_string(), json!(program));
+    }
+    if links.len() > 1 {
+        properties.insert("terminal".to_string(), json!(links[0].0));
+    }
+
+    let mut accounts = None;
+    let mut signers = None;
+    let mut remaining = None;
+    if namespace == "methods" {
+        for &(name, call) in &links[..links.len() - 1] {
+            let Some(&argument) = arguments(call).first() else {
+                continue;
+            };
+            if ACCOUNTS_METHODS.contains(&name) && argument.kind == "object" {
+                accounts = Some(argument);
+            } else if name == "signers" {
+                signers = Some(argument);
+            } else if name == "remainingAccounts" {
+                remaining = Some(argument);
+            }
+        }
+    } else if let Some(context) = arguments(instruction_call)
+        .last()
+        .filter(|a| a.kind == "object")
+    {
+        // 旧的 program.rpc.<ix>(参数.., { accounts, signers, remainingAccounts })
+        accounts = property(context, "accounts").filter(|a| a.kind == "object");
+        signers = property(context, "signers");
+        remaining = property(context, "remainingAccounts");
+    }
+    if let Some(accounts) = accounts {
+        properties.insert("accounts".to_string(), json!(keys(accounts)));
+    }
+    if let Some(signers) = signers {
+        properties.insert("signers".to_string(), json!(elements(signers)));
+    }
+    if let Some(remaining) = remaining {
+        properties.insert("remaining_accounts".to_string(), account_metas(remaining));
+    }
+
+    // 链中各个调用的参数里可能还有其他调用
+    let argument_nodes = links
+        .iter()
+        .filter_map(|(_, call)| child(call, "arguments"))
+        .collect();
+    (argument_nodes, properties)
+}
+
+impl<'a> FileWalk<'a> {
+    /// 接收者是否为交易：`new Transaction()` 本身或者声明中出现 Transaction 的变量
+    fn is_transaction(&self, receiver: &str) -> bool {
+        receiver.contains("Transaction")
+            || self
+                .declarations
+                .get(receiver.rsplit('.').next().unwrap_or(receiver))
+                .is_some_and(|d| d.contains("Transaction"))
+    }
+
+    /// web3.js 调用的属性；不是 web3.js 调用时为空
+    fn web3_properties(
+        &self,
+        node: &AstNode,
+        name: &str,
+        receiver: Option<&str>,
+    ) -> BTreeMap<String, Value> {
+        let mut properties = BTreeMap::new();
+        let known = WEB3_CALLS
+            .iter()
+            .find(|(call, _, _)| *call == name)
+            .filter(|_| {
+                !TRANSACTION_METHODS.contains(&name)
+                    || receiver.is_some_and(|r| self.is_transaction(r))
+            });
+        let (operation, signers) = match known {
+            Some(&(_, operation, signers)) => (operation, signers),
+            // createTransferInstruction(..)、SystemProgram.transfer(..)、ComputeBudgetProgram.setComputeUnitLimit(..)
+            None if name.ends_with("Instruction") && !name.starts_with("new ") => {
+                ("instruction", SignerArgs::None)
+            }
+            None if receiver
+                .is_some_and(|r| r.ends_with("Program") && r.starts_with(char::is_uppercase)) =>
+            {
+                ("instruction", SignerArgs::None)
+            }
+            None => return properties,
+        };
+        properties.insert("category".to_string(), json!("web3"));
+        properties.insert("operation".to_string(), json!(operation));
+
+        let args = arguments(node);
+        let signers: Vec<String> = match signers {
+            SignerArgs::None => vec![],
+            SignerArgs::At(index) => args.get(index).map(|a| elements(a)).unwrap_or_default(),
+            SignerArgs::All => args.iter().flat_map(|a| elements(a)).collect(),
+        };
+        if !signers.is_empty() {
+            properties.insert("signers".to_string(), json!(signers));
+        }
+        if name == "new TransactionInstruction" {
+            if let Some(object) = args.first().filter(|a| a.kind == "object") {
+                if let Some(keys) = property(object, "keys") {
+                    properties.insert("keys".to_string(), account_metas(keys));
+                }
+                if let Some(program_id) = property_text(object, "programId") {
+                    properties.insert("program_id".to_string(), json!(program_id));
+                }
+            }
+        }
+        properties
+    }
+
+    /// 当前所在的函数；不在具名函数中时为 <module>
+    fn function_index(&mut self, function: Option<usize>) -> usize {
+        if let Some(index) = function.or(self.module) {
+            return index;
+        }
+        self.functions.push(ClientFunction {
+            file: self.file,
+            language: self.language,
+            name: MODULE_FUNCTION.to_string(),
+            node: None,
+            sites: vec![],
+        });
+        self.module = Some(self.functions.len() - 1);
+        self.functions.len() - 1
+    }
+
+    /// 新建一个函数，返回它的下标
+    fn add_function(&mut self, name: String, node: &'a AstNode) -> usize {
+        self.functions.push(ClientFunction {
+            file: self.file,
+            language: self.language,
+            name,
+            node: Some(node),
+            sites: vec![],
+        });
+        self.functions.len() - 1
+    }
+
+    /// 在当前函数中记下一个调用点
+    fn push_site(
+        &mut self,
+        function: Option<usize>,
+        node: &'a AstNode,
+        name: String,
+        properties: BTreeMap<String, Value>,
+    ) {
+        let binding = self
+            .bindings
+            .get(&(node.start_byte, node.end_byte))
+            .cloned();
+        let index = self.function_index(function);
+        self.functions[index].sites.push(Site {
+            node,
+            name,
+            properties,
+            binding,
+        });
+    }
+
+    /// 记下 `name = <调用>` 中调用结果赋给的变量
+    fn bind(&mut self, name: Option<&AstNode>, value: Option<&AstNode>) {
+        if let (Some(name), Some(value)) = (name.filter(|n| n.kind == "identifier"), value) {
+            let value = unwrap_expression(value);
+            if matches!(value.kind.as_str(), "call_expression" | "new_expression") {
+                self.bindings
+                    .insert((value.start_byte, value.end_byte), name.text.clone());
+            }
+        }
+    }
+
+    fn walk(&mut self, node: &'a AstNode, function: Option<usize>, class: Option<&str>) {
+        match node.kind.as_str() {
+            "function_declaration" | "generator_function_declaration" => {
+                let name = definition_name(node).map_or_else(String::new, |n| n.text.clone());
+                let index = self.add_function(name, node);
+                return self.walk_children(node, Some(index), class);
+            }
+            "method_definition" => {
+                let name = definition_name(node).map_or_else(String::new, |n| n.text.clone());
+                let name =
+                    class.map_or_else(|| name.clone(), |class| format!("{}.{}", class, name));
+                let index = self.add_function(name, node);
+                return self.walk_children(node, Some(index), class);
+            }
+            "class_declaration" | "class" => {
+                let name = child(node, "type_identifier").or_else(|| child(node, "identifier"));
+                let name = name.map(|n| n.text.clone());
+                return self.walk_children(node, function, name.as_deref().or(class));
+            }
+            "variable_declarator" | "public_field_definition" => {
+                let name = definition_name(node);
+                let value = node.children.last();
+                if let (Some(name), Some(value)) = (name, value) {
+                    if matches!(
+                        value.kind.as_str(),
+                        "arrow_function" | "function_expression" | "function"
+                    ) {
+                        let name = match (node.kind.as_str(), class) {
+                            ("public_field_definition", Some(class)) => {
+                                format!("{}.{}", class, name.text)
+                            }
+                            _ => name.text.clone(),
+                        };
+                        let index = self.add_function(name, value);
+                        return self.walk_children(value, Some(index), class);
+                    }
+                }
+                self.bind(name, value);
+            }
+            "assignment_expression" => self.bind(node.children.first(), node.children.last()),
+            "call_expression" => {
+                if let Some((arguments, properties)) = anchor_call(node, &self.declarations) {
+                    self.push_site(function, node, String::new(), properties);
+                    for arguments in arguments {
+                        self.walk(arguments, function, class);
+                    }
+                    return;
+                }
+                if let Some(callee) = node.children.first() {
+                    let (receiver, name) = match callee.kind.as_str() {
+                        "member_expression" => (
+                            callee.children.first().map(|r| normalize(&r.text)),
+                            child(callee, "property_identifier")
+                                .map_or_else(String::new, |p| p.text.clone()),
+                        ),
+                        _ => (None, callee.text.clone()),
+                    };
+                    let properties = self.web3_properties(node, &name, receiver.as_deref());
+                    self.push_site(function, node, name, properties);
+                }
+            }
+            "new_expression" => {
+                let constructor = node.children.get(1).map(|c| normalize(&c.text));
+                let name = format!("new {}", constructor.unwrap_or_default());
+                let properties = self.web3_properties(node, &name, None);
+                self.push_site(function, node, name, properties);
+            }
+            _ => {}
+        }
+        self.walk_children(node, function, class);
+    }
+
+    fn walk_children(&mut self, node: &'a AstNode, function: Option<usize>, class: Option<&str>) {
+        for item in &node.children {
+            self.walk(item, function, class);
+        }
+    }
+}
+
+/// 文本中是否以标识符的形式出现 `name` (前面不是 `.`)
+fn mentions(text: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    text.match_indices(name).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + name.len()..].chars().next();
+        !before.is_some_and(|c| is_ident(c) || c == '.') && !after.is_some_and(is_ident)
+    })
+}
+
+/// 函数调用的目标：优先解析到同一文件内的同名函数，否则只在名字全局唯一时解析
+fn resolve(
+    by_name: &HashMap<&str, Vec<usize>>,
+    functions: &[ClientFunction],
+    file: &Path,
+    name: &str,
+) -> Option<usize> {
+    let candidates = by_name.get(name)?;
+    let local: Vec<usize> = candidates
+        .iter()
+        .copied()
+        .filter(|&i| functions[i].file == file)
+        .collect();
+    match (local.as_slice(), candidates.as_slice()) {
+        ([index], _) | ([], [index]) => Some(*index),
+        _ => None,
+    }
+}
+
+/// 构建客户端代码的调用图
+fn build_graph(functions: &[ClientFunction]) -> MergedGraph {
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, function) in functions.iter().enumerate() {
+        if function.node.is_some() {
+            let short = function.name.rsplit('.').next().unwrap_or(&function.name);
+            by_name.entry(short).or_default().push(index);
+        }
+    }
+    let unit = |function: &ClientFunction| function.file.display().to_string();
+    let span = |file: &Path, node: &AstNode| Span {
+        file: file.to_path_buf(),
+        start_byte: node.start_byte,
+        end_byte: node.end_byte,
+    };
+    let provenance = |span: &Span| {
+        format!(
+            "{}@{}..{}",
+            span.file.display(),
+            span.start_byte,
+            span.end_byte
+        )
+    };
+
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    for function in functions {
+        let id = |local_id| node_id(Layer::Ast, &unit(function), &function.name, local_id);
+        let mut base = BTreeMap::new();
+        base.insert("language".to_string(), json!(function.language));
+        let entry_span = function.node.map(|node| span(function.file, node));
+        nodes.push(MergedNode {
+            id: id(0),
+            layer: Layer::Ast,
+            function: function.name.clone(),
+            kind: NodeKind::Entry,
+            label: "Entry".to_string(),
+            provenance: entry_span.as_ref().map(provenance),
+            span: entry_span,
+            properties: base.clone(),
+        });
+        nodes.push(MergedNode {
+            id: id(1),
+            layer: Layer::Ast,
+            function: function.name.clone(),
+            kind: NodeKind::Exit,
+            label: "Exit".to_string(),
+            span: None,
+            provenance: None,
+            properties: base.clone(),
+        });
+
+        // 保留的调用点，按求值顺序 (内层调用先于外层) 排列
+        let mut sites = vec![];
+        for site in &function.sites {
+            let mut properties = site.properties.clone();
+            if let Some(target) = resolve(&by_name, functions, function.file, &site.name) {
+                properties.insert("category".to_string(), json!("function"));
+                properties.insert("callee".to_string(), json!(functions[target].name));
+                sites.push((site, properties, Some(target)));
+            } else if !properties.is_empty() {
+                sites.push((site, properties, None));
+            }
+        }
+        sites.sort_by_key(|(site, _, _)| (site.node.end_byte, site.node.start_byte));
+
+        let mut previous = id(0);
+        for (position, (site, properties, target)) in sites.iter().enumerate() {
+            let local_id = position + 2;
+            let mut properties = properties.clone();
+            properties.extend(base.clone());
+            let binding = site.binding.as_deref();
+            if let Some(binding) = binding {
+                properties.insert("binding".to_string(), json!(binding));
+            }
+            let site_span = span(function.file, site.node);
+            nodes.push(MergedNode {
+                id: id(local_id),
+                layer: Layer::Ast,
+                function: function.name.clone(),
+                kind: NodeKind::Statement,
+                label: normalize(&site.node.text),
+                provenance: Some(provenance(&site_span)),
+                span: Some(site_span),
+                properties,
+            });
+            edges.push(MergedEdge {
+                source: previous,
+                target: id(local_id),
+                kind: EdgeKind::ControlFlow,
+            });
+            previous = id(local_id);
+            if let Some(target) = target {
+                edges.push(MergedEdge {
+                    source: id(local_id),
+                    target: node_id(
+                        Layer::Ast,
+                        &unit(&functions[*target]),
+                        &functions[*target].name,
+                        0,
+                    ),
+                    kind: EdgeKind::Call,
+                });
+            }
+
+            // 结果流向的后续调用：最近的外层调用，以及用到所赋变量的调用
+            let outer = sites
+                .iter()
+                .enumerate()
+                .filter(|(_, (other, _, _))| {
+                    other.node.start_byte <= site.node.start_byte
+                        && site.node.end_byte <= other.node.end_byte
+                        && !std::ptr::eq(other.node, site.node)
+                })
+                .min_by_key(|(_, (other, _, _))| other.node.end_byte - other.node.start_byte)
+                .map(|(i, _)| i);
+            let readers = sites
+                .iter()
+                .enumerate()
+                .skip(position + 1)
+                .filter(|(_, (other, _, _))| {
+                    binding.is_some_and(|b| {
+                        other.node.start_byte >= site.node.end_byte && mentions(&other.node.text, b)
+                    })
+                })
+                .map(|(i, _)| i);
+            for reader in outer.into_iter().chain(readers) {
+                edges.push(MergedEdge {
+                    source: id(local_id),
+                    target: id(reader + 2),
+                    kind: EdgeKind::DataFlow,
+                });
+            }
+        }
+        edges.push(MergedEdge {
+            source: previous,
+            target: id(1),
+            kind: EdgeKind::ControlFlow,
+        });
+    }
+
+    MergedGraph {
+        metadata: MergedMetadata::current(),
+        nodes,
+        edges,
+    }
+}
+
+/// 由所有 TypeScript/JavaScript 文件的AST构建客户端代码的调用图
+pub fn client_graph(asts: &[(PathBuf, AstNode)]) -> MergedGraph {
+    let mut functions = vec![];
+    for (file, root) in asts {
+        let language = match file.extension().and_then(|ext| ext.to_str()) {
+            Some("ts" | "tsx") => "typescript",
+            Some("js" | "jsx" | "mjs" | "cjs") => "javascript",
+            _ => continue,
+        };
+        let mut declarations = HashMap::new();
+        collect_declarations(root, &mut declarations);
+        let mut walk = FileWalk {
+            file,
+            language,
+            declarations,
+            functions: vec![],
+            module: None,
+            bindings: HashMap::new(),
+        };
+        walk.walk(root, None, None);
+        debug!(file = %file.display(), functions = walk.functions.len(), "已遍历客户端文件");
+        functions.extend(walk.functions);
+    }
+    build_graph(&functions)
+}
+
+/// 读取 TypeScript/JavaScript 的AST，写出客户端代码的调用图
+pub fn run(args: &ClientGraphArgs) -> Result<(), Box<dyn Error>> {
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let graph = client_graph(&asts);
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(CLIENT_GRAPH_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&graph)?)?;
+    let count = |category: &str| {
+        graph
+            .nodes
+            .iter()
+            .filter(|n| n.properties.get("category").is_some_and(|c| c == category))
+            .count()
+    };
+    info!(
+        functions = graph.nodes.iter().filter(|n| n.kind == NodeKind::Entry).count(),
+        anchor_calls = count("anchor"),
+        web3_calls = count("web3"),
+        call_edges = graph.edges.iter().filter(|e| e.kind == EdgeKind::Call).count(),
+        output = %output.display(),
+        "已写出客户端调用图"
+    );
+    Ok(())
+}
@@ -0,0 +1,307 @@
+// client_lint.rs
+//
+// agent client-lint：在 TypeScript/JavaScript 的AST中查找已废弃或危险的 web3.js 用法，写出 client_lint.json
+// - 旧的 Account 类 (已由 Keypair 取代)
+// - 只传签名的 connection.confirmTransaction(sig)：已废弃，且不指定 commitment 和区块哈希的有效期
+// - 其他已废弃的 Connection 方法，例如 getRecentBlockhash
+// - 源码中的私钥：64 个字节的数组字面量，或长度与 64 字节的 base58 编码相同的字符串
+// - 非测试代码中的 skipPreflight: true
+// 私钥的位置会报告，但不会把私钥本身写进报告
+
+use crate::config::ArtifactsArgs;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{child, line_of, load_asts, normalize, AstNode};
+use crate::test_coverage::is_test_file;
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const CLIENT_LINT_FILE_NAME: &str = "client_lint.json";
+
+/// web3.js 的包名
+const WEB3_PACKAGE: &str = "@solana/web3.js";
+
+/// 已废弃的 Connection 方法及替代方法
+const DEPRECATED_METHODS: &[(&str, &str)] = &[
+    ("getRecentBlockhash", "getLatestBlockhash"),
+    (
+        "getRecentBlockhashAndContext",
+        "getLatestBlockhashAndContext",
+    ),
+    ("getFeeCalculatorForBlockhash", "getFeeForMessage"),
+    ("getFees", "getFeeForMessage"),
+    ("getConfirmedBlock", "getBlock"),
+    ("getConfirmedBlockSignatures", "getBlockSignatures"),
+    ("getConfirmedTransaction", "getTransaction"),
+    ("getParsedConfirmedTransaction", "getParsedTransaction"),
+    ("getParsedConfirmedTransactions", "getParsedTransactions"),
+    (
+        "getConfirmedSignaturesForAddress",
+        "getSignaturesForAddress",
+    ),
+    (
+        "getConfirmedSignaturesForAddress2",
+        "getSignaturesForAddress",
+    ),
+    ("getStakeActivation", "getAccountInfo"),
+];
+
+/// 私钥 (ed25519 的 secret key) 的字节数
+const SECRET_KEY_BYTES: usize = 64;
+
+/// 64 字节的 base58 编码的长度范围
+const SECRET_KEY_BASE58_LEN: std::ops::RangeInclusive<usize> = 86..=88;
+
+/// base58 的字母表
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// `agent client-lint` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct ClientLintArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 client_lint.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 问题的种类
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum FindingKind {
+    /// 使用已废弃的 Account 类
+    LegacyAccount,
+    /// confirmTransaction 只传了签名
+    ConfirmWithoutCommitment,
+    /// 调用已废弃的 Connection 方法
+    DeprecatedApi,
+    /// 源码中的私钥
+    PrivateKeyLiteral,
+    /// 非测试代码中跳过预检
+    SkipPreflight,
+}
+
+#[derive(Serialize, Debug)]
+struct Finding {
+    kind: FindingKind,
+    file: PathBuf,
+    line: usize,
+    test: bool,
+    /// 出问题的代码；私钥不写出
+    code: String,
+    message: String,
+}
+
+/// client_lint.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct ClientLintReport {
+    metadata: ClientLintMetadata,
+    files: usize,
+    findings: Vec<Finding>,
+}
+
+#[derive(Serialize, Debug)]
+struct ClientLintMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 检查一个文件时的上下文
+struct Scope<'a> {
+    file: &'a Path,
+    source: &'a str,
+    test: bool,
+    /// 是否从 web3.js 导入了 Account
+    imports_account: bool,
+}
+
+/// 是否有 `import { Account } from "@solana/web3.js"`
+fn imports_account(root: &AstNode) -> bool {
+    root.children
+        .iter()
+        .filter(|c| c.kind == "import_statement" && c.text.contains(WEB3_PACKAGE))
+        .any(|c| {
+            c.text
+                .split(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                .any(|word| word == "Account")
+        })
+}
+
+/// 是否为 64 个 0..=255 的数字组成的数组字面量
+fn is_secret_key_array(node: &AstNode) -> bool {
+    let elements: Vec<&AstNode> = node
+        .children
+        .iter()
+        .filter(|c| !matches!(c.kind.as_str(), "[" | "]" | ","))
+        .collect();
+    elements.len() == SECRET_KEY_BYTES
+        && elements
+            .iter()
+            .all(|e| e.kind == "number" && e.text.parse::<u16>().is_ok_and(|n| n <= 255))
+}
+
+/// 是否为长度与 64 字节的 base58 编码相同的字符串
+fn is_secret_key_string(text: &str) -> bool {
+    SECRET_KEY_BASE58_LEN.contains(&text.len()) && text.chars().all(|c| BASE58_ALPHABET.contains(c))
+}
+
+fn visit(node: &AstNode, scope: &Scope, findings: &mut Vec<Finding>) {
+    for item in &node.children {
+        let mut push = |kind, code: String, message: String| {
+            findings.push(Finding {
+                kind,
+                file: scope.file.to_path_buf(),
+                line: line_of(scope.source, item.start_byte),
+                test: scope.test,
+                code,
+                message,
+            })
+        };
+        match item.kind.as_str() {
+            "new_expression" => {
+                let constructor = item.children.get(1).map_or("", |c| c.text.as_str());
+                let legacy = (constructor == "Account" && scope.imports_account)
+                    || constructor.ends_with("web3.Account");
+                if legacy {
+                    push(
+                        FindingKind::LegacyAccount,
+                        normalize(&item.text),
+                        "Account 已废弃，请改用 Keypair".to_string(),
+                    );
+                }
+            }
+            "call_expression" => {
+                let method = item
+                    .children
+                    .first()
+                    .filter(|c| c.kind == "member_expression")
+                    .and_then(|c| child(c, "property_identifier"))
+                    .map_or("", |p| p.text.as_str());
+                let arguments: Vec<&AstNode> = child(item, "arguments")
+                    .into_iter()
+                    .flat_map(|args| &args.children)
+                    .filter(|c| !matches!(c.kind.as_str(), "(" | ")" | ","))
+                    .collect();
+                if let Some((_, replacement)) =
+                    DEPRECATED_METHODS.iter().find(|(m, _)| *m == method)
+                {
+                    push(
+                        FindingKind::DeprecatedApi,
+                        normalize(&item.text),
+                        format!("{} 已废弃，请改用 {}", method, replacement),
+                    );
+                } else if method == "confirmTransaction"
+                    && arguments.len() == 1
+                    && arguments[0].kind != "object"
+                {
+                    push(
+                        FindingKind::ConfirmWithoutCommitment,
+                        normalize(&item.text),
+                        "confirmTransaction 只传了签名：该用法已废弃，且没有指定 commitment 和区块哈希的有效期".to_string(),
+                    );
+                }
+            }
+            "array" if is_secret_key_array(item) => {
+                push(
+                    FindingKind::PrivateKeyLiteral,
+                    format!("<{} 字节的数组>", SECRET_KEY_BYTES),
+                    "源码中有看起来是私钥的字节数组".to_string(),
+                );
+                continue;
+            }
+            "string_fragment" if is_secret_key_string(&item.text) => {
+                push(
+                    FindingKind::PrivateKeyLiteral,
+                    format!("<{} 个字符的 base58 字符串>", item.text.len()),
+                    "源码中有看起来是 base58 编码私钥的字符串".to_string(),
+                );
+            }
+            "pair" if !scope.test => {
+                let key = item.children.first().map_or("", |k| k.text.as_str());
+                let value = item.children.last().map_or("", |v| v.text.as_str());
+                if key == "skipPreflight" && value == "true" {
+                    push(
+                        FindingKind::SkipPreflight,
+                        normalize(&item.text),
+                        "非测试代码跳过了预检，失败的交易会直接上链并扣除手续费".to_string(),
+                    );
+                }
+            }
+            _ => {}
+        }
+        visit(item, scope, findings);
+    }
+}
+
+/// 检查客户端代码中的 web3.js 用法，写出 client_lint.json
+pub fn run(args: &ClientLintArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("client_lint")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut findings = vec![];
+    let mut files = 0;
+    for (file, root) in &asts {
+        if !matches!(
+            file.extension().and_then(|ext| ext.to_str()),
+            Some("ts" | "tsx" | "js" | "jsx" | "mjs" | "cjs")
+        ) {
+            continue;
+        }
+        files += 1;
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        let scope = Scope {
+            file,
+            source: &source,
+            test: is_test_file(file),
+            imports_account: imports_account(root),
+        };
+        visit(root, &scope, &mut findings);
+    }
+    for finding in &findings {
+        warn!(
+            file = %finding.file.display(),
+            line = finding.line,
+            code = %finding.code,
+            "{}",
+            finding.message
+        );
+    }
+
+    let report = ClientLintReport {
+        metadata: ClientLintMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        files,
+        findings,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(CLIENT_LINT_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        files = report.files,
+        findings = report.findings.len(),
+        output = %output.display(),
+        "已写出客户端检查结果"
+    );
+    Ok(())
+}
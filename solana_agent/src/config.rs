@@ -0,0 +1,200 @@
+// config.rs
+
+use crate::features::FeatureGroup;
+use crate::report::Level;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// 项目配置文件的默认文件名，放在被分析项目的根目录下
+pub const CONFIG_FILE_NAME: &str = "agent.toml";
+
+/// agent.toml 的完整结构
+/// 所有字段都可以省略，省略时使用各个生成器自己的默认行为
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub input: InputConfig,
+    pub output: OutputConfig,
+    pub resources: ResourceConfig,
+    pub cpg: CpgConfig,
+    pub features: FeaturesConfig,
+    pub detectors: DetectorsConfig,
+}
+
+/// [input] 表：要分析哪些源文件
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// 只分析项目下的这些子目录，例如 ["programs", "app"]
+    pub roots: Vec<PathBuf>,
+    /// 只处理匹配这些 glob 的文件 (相对于项目根目录)
+    pub include: Vec<String>,
+    /// 跳过匹配这些 glob 的文件
+    pub exclude: Vec<String>,
+    /// 要分析的语言，例如 ["rust", "typescript"]
+    pub languages: Vec<String>,
+}
+
+/// [output] 表：输出到哪里、输出什么格式
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// 输出目录 (相对于项目根目录)
+    pub dir: PathBuf,
+    /// CFG/CPG 的输出格式，可选 dot、json、graphml、html，例如 ["dot", "json", "graphml"]
+    pub formats: Vec<String>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            dir: PathBuf::from("agent-out"),
+            formats: vec![],
+        }
+    }
+}
+
+/// [resources] 表：对应各个生成器的 --jobs/--memory-limit/--timeout-* 参数，以及 agent analyze 的分块模式
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceConfig {
+    pub jobs: Option<usize>,
+    /// 内存预算 (MiB)
+    pub memory_limit: Option<u64>,
+    /// 单个文件的解析超时时间 (秒)
+    pub timeout_per_file: Option<u64>,
+    /// 单个函数的CFG/CPG构建超时时间 (秒)
+    pub timeout_per_function: Option<u64>,
+    /// 按包分块处理 (见 chunks.rs)
+    pub chunked: bool,
+    /// 分块模式下同时处理的块数
+    pub chunk_processes: Option<usize>,
+}
+
+/// [cpg] 表：需要进行MIR级分析的crate
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CpgConfig {
+    /// crate 的根文件 (相对于项目根目录)，例如 ["programs/vault/src/lib.rs"]
+    pub crates: Vec<PathBuf>,
+}
+
+/// [features] 表：agent dataset 和 agent merge --emit-features 提取的节点特征
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    /// 要提取的特征组，例如 ["kind", "tokens", "flags"]，默认提取全部
+    pub groups: Vec<FeatureGroup>,
+    /// 节点文本的特征哈希维数，默认为 64
+    pub hash_dim: Option<usize>,
+}
+
+/// [detectors] 表：按检测器或单条规则开关、调整问题的严重程度
+/// 键为检测器的名字 (结果文件名去掉 .json，例如 signers、detect) 或规则 (例如 mutability/extra_mut)，
+/// 规则自己的设置优先于它所属的检测器
+#[derive(Deserialize, Debug, Default)]
+#[serde(transparent)]
+pub struct DetectorsConfig(pub BTreeMap<String, DetectorConfig>);
+
+/// [detectors.<名字>] 表
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DetectorConfig {
+    /// 为 false 时检测器子命令不运行，agent report 也不报告它的问题
+    pub enabled: bool,
+    /// 覆盖报告中问题的严重程度，可选 note、warning、error
+    pub severity: Option<Level>,
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        DetectorConfig {
+            enabled: true,
+            severity: None,
+        }
+    }
+}
+
+impl DetectorsConfig {
+    /// 规则所属的检测器及规则本身的设置，按优先级从低到高
+    fn settings<'a>(&'a self, rule: &'a str) -> impl Iterator<Item = &'a DetectorConfig> {
+        let detector = rule.split_once('/').map_or(rule, |(detector, _)| detector);
+        let rule = (rule != detector).then_some(rule);
+        [Some(detector), rule]
+            .into_iter()
+            .flatten()
+            .filter_map(|key| self.0.get(key))
+    }
+
+    /// 检测器 (或规则) 是否启用；检测器被禁用时它的所有规则都不启用
+    pub fn enabled(&self, rule: &str) -> bool {
+        self.settings(rule).all(|config| config.enabled)
+    }
+
+    /// 配置中给出的严重程度
+    pub fn severity(&self, rule: &str) -> Option<Level> {
+        self.settings(rule)
+            .filter_map(|config| config.severity)
+            .last()
+    }
+}
+
+/// 读取 agent analyze 输出的子命令 (merge、query 等) 共用的参数
+#[derive(clap::Args, Debug)]
+pub struct ArtifactsArgs {
+    /// 项目根目录
+    #[arg(default_value = ".")]
+    pub project: PathBuf,
+
+    /// 配置文件路径，默认为 <PROJECT>/agent.toml
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// agent analyze 的输出目录，覆盖配置文件中的 output.dir
+    #[arg(long, value_name = "DIR")]
+    pub artifacts: Option<PathBuf>,
+}
+
+impl ArtifactsArgs {
+    /// 读取项目的配置文件
+    pub fn load_config(&self) -> Result<Config, Box<dyn Error>> {
+        let config_path = self
+            .config
+            .clone()
+            .unwrap_or_else(|| self.project.join(CONFIG_FILE_NAME));
+        Config::load(&config_path)
+    }
+
+    /// 配置中是否启用了该检测器；检测器子命令在开始时检查，禁用时直接返回
+    pub fn detector_enabled(&self, detector: &str) -> Result<bool, Box<dyn Error>> {
+        let enabled = self.load_config()?.detectors.enabled(detector);
+        if !enabled {
+            info!(detector, "配置中禁用了该检测器，跳过");
+        }
+        Ok(enabled)
+    }
+
+    /// 产物所在的目录
+    pub fn artifacts_dir(&self) -> Result<PathBuf, Box<dyn Error>> {
+        if let Some(dir) = &self.artifacts {
+            return Ok(dir.clone());
+        }
+        Ok(self.project.join(self.load_config()?.output.dir))
+    }
+}
+
+impl Config {
+    /// 读取配置文件；文件不存在时返回默认配置
+    pub fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .map_err(|e| format!("配置文件 '{}' 格式错误: {}", path.display(), e).into())
+    }
+}
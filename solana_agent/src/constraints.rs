@@ -0,0 +1,380 @@
+// constraints.rs
+//
+// agent constraints：列出每个 Accounts 结构体的每个账户字段由 Anchor 检查了哪些方面
+// (signer、owner/程序、seeds、has_one、address)，并把什么都不检查的字段按指令对它的使用方式排序：
+// 可写 > 传给 CPI > 读取 > 未使用
+// 账户类型隐含的检查也计入，例如 Account<'info, T> 检查 owner，Program<'info, T> 检查地址
+
+use crate::anchor_accounts;
+use crate::config::ArtifactsArgs;
+use crate::idl::collect_programs;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{definition_name, line_of, load_asts, normalize, AstNode, CallGraph};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const CONSTRAINTS_FILE_NAME: &str = "constraints.json";
+
+/// 由 Anchor 检查 owner 的账户类型
+const OWNER_CHECKED_TYPES: &[&str] = &[
+    "Account",
+    "AccountLoader",
+    "InterfaceAccount",
+    "SystemAccount",
+];
+
+/// 由 Anchor 检查地址的账户类型
+const ADDRESS_CHECKED_TYPES: &[&str] = &["Program", "Interface", "Sysvar"];
+
+/// `agent constraints` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct ConstraintsArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 constraints.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 约束的类别
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum Category {
+    Signer,
+    /// owner 约束，或账户类型隐含的 owner 检查
+    Owner,
+    Seeds,
+    /// 声明了 has_one，或者是其他字段 has_one 的目标
+    HasOne,
+    /// address 约束，或 Program/Sysvar 类型隐含的地址检查
+    Address,
+}
+
+/// 指令对账户的使用方式，按风险从低到高排列
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum Usage {
+    Unused,
+    Read,
+    /// 传给了 invoke 或 CpiContext
+    Cpi,
+    /// 标记为可写 (mut、init、close 等)
+    Written,
+}
+
+/// Accounts 结构体中的一个字段
+#[derive(Serialize, Debug)]
+struct FieldCoverage {
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+    line: usize,
+    categories: BTreeSet<Category>,
+    /// constraint = .. 自定义约束的数量
+    custom_constraints: usize,
+    /// AccountInfo/UncheckedAccount 上的 `/// CHECK:` 说明
+    #[serde(skip_serializing_if = "Option::is_none")]
+    check_comment: Option<String>,
+    /// 所有使用该结构体的指令中最强的使用方式
+    usage: Usage,
+    #[serde(skip)]
+    has_one: Vec<String>,
+    #[serde(skip)]
+    mutable: bool,
+}
+
+/// 一个 Accounts 结构体
+#[derive(Serialize, Debug)]
+struct StructCoverage {
+    name: String,
+    file: PathBuf,
+    line: usize,
+    instructions: BTreeSet<String>,
+    fields: Vec<FieldCoverage>,
+}
+
+/// 没有任何约束类别的字段
+#[derive(Serialize, Debug)]
+struct Uncovered {
+    accounts_struct: String,
+    field: String,
+    #[serde(rename = "type")]
+    ty: String,
+    usage: Usage,
+    custom_constraints: usize,
+    file: PathBuf,
+    line: usize,
+    instructions: BTreeSet<String>,
+}
+
+/// constraints.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct ConstraintsReport {
+    metadata: ConstraintsMetadata,
+    /// 按使用方式从高到低排序
+    uncovered: Vec<Uncovered>,
+    structs: Vec<StructCoverage>,
+}
+
+#[derive(Serialize, Debug)]
+struct ConstraintsMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 在AST中收集 `#[derive(Accounts)]` 结构体的字段及其约束
+fn collect_structs(node: &AstNode, file: &Path, source: &str, structs: &mut Vec<StructCoverage>) {
+    let mut derives_accounts = false;
+    for item in &node.children {
+        match item.kind.as_str() {
+            "attribute_item" => {
+                let text = normalize(&item.text);
+                derives_accounts |= text.starts_with("#[derive(") && text.contains("Accounts");
+                continue;
+            }
+            "line_comment" | "block_comment" => continue,
+            "struct_item" if derives_accounts => {
+                if let Some(name) = definition_name(item) {
+                    structs.push(StructCoverage {
+                        name: name.text.clone(),
+                        file: file.to_path_buf(),
+                        line: line_of(source, item.start_byte),
+                        instructions: BTreeSet::new(),
+                        fields: collect_fields(item, source),
+                    });
+                }
+            }
+            _ => {}
+        }
+        derives_accounts = false;
+        collect_structs(item, file, source, structs);
+    }
+}
+
+/// 结构体的字段；has_one 的目标在所有字段收集完后再标记
+fn collect_fields(item: &AstNode, source: &str) -> Vec<FieldCoverage> {
+    let mut fields: Vec<FieldCoverage> = anchor_accounts::fields(item, source)
+        .into_iter()
+        .map(|field| {
+            let base = field.base_type();
+            let mut categories = BTreeSet::new();
+            if field.is_signer() {
+                categories.insert(Category::Signer);
+            }
+            if OWNER_CHECKED_TYPES.contains(&base) || field.flag("owner") {
+                categories.insert(Category::Owner);
+            }
+            if field.flag("seeds") {
+                categories.insert(Category::Seeds);
+            }
+            let has_one = field.values("has_one");
+            if !has_one.is_empty() {
+                categories.insert(Category::HasOne);
+            }
+            if ADDRESS_CHECKED_TYPES.contains(&base) || field.flag("address") {
+                categories.insert(Category::Address);
+            }
+            FieldCoverage {
+                line: field.line,
+                categories,
+                custom_constraints: field.values("constraint").len(),
+                usage: Usage::Unused,
+                has_one,
+                mutable: ["mut", "init", "init_if_needed", "zero", "close", "realloc"]
+                    .iter()
+                    .any(|f| field.flag(f))
+                    || field.flag("payer"),
+                name: field.name,
+                check_comment: field.check_comment,
+                ty: field.ty,
+            }
+        })
+        .collect();
+    let targets: Vec<String> = fields.iter().flat_map(|f| f.has_one.clone()).collect();
+    for field in &mut fields {
+        if targets.contains(&field.name) {
+            field.categories.insert(Category::HasOne);
+        }
+    }
+    fields
+}
+
+/// 每个函数的源码文本，用于判断字段的使用方式
+fn collect_function_texts(
+    node: &AstNode,
+    file: &Path,
+    functions: &mut HashMap<(PathBuf, String), String>,
+) {
+    for item in &node.children {
+        if item.kind == "function_item" {
+            if let Some(name) = definition_name(item) {
+                functions
+                    .entry((file.to_path_buf(), name.text.clone()))
+                    .or_default()
+                    .push_str(&normalize(&item.text));
+            }
+        }
+        collect_function_texts(item, file, functions);
+    }
+}
+
+/// 某个字段在函数体中的使用方式：传给 invoke/CpiContext 的算 CPI，其余出现算读取
+fn text_usage(texts: &[&str], field: &str) -> Usage {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut usage = Usage::Unused;
+    for text in texts {
+        for pattern in [format!("accounts.{}", field), format!("self.{}", field)] {
+            for (i, m) in text.match_indices(&pattern) {
+                let before = &text[..i];
+                if text[i + m.len()..].starts_with(is_ident) || before.ends_with(is_ident) {
+                    continue;
+                }
+                // 所在的最近一个 invoke/CpiContext 调用还没有闭合
+                let in_cpi = ["invoke(", "invoke_signed(", "CpiContext::new"]
+                    .iter()
+                    .filter_map(|call| before.rfind(call))
+                    .max()
+                    .is_some_and(|start| {
+                        let call = &before[start..];
+                        call.matches('(').count() > call.matches(')').count()
+                    });
+                usage = usage.max(if in_cpi { Usage::Cpi } else { Usage::Read });
+            }
+        }
+    }
+    usage
+}
+
+/// 收集所有 Accounts 结构体的约束覆盖情况，写出 constraints.json
+pub fn run(args: &ConstraintsArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("constraints")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut structs = vec![];
+    let mut calls = CallGraph::default();
+    let mut programs = vec![];
+    let mut functions: HashMap<(PathBuf, String), String> = HashMap::new();
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        collect_structs(root, file, &source, &mut structs);
+        calls.add_file(file, root);
+        collect_programs(root, file, &source, &mut programs);
+        collect_function_texts(root, file, &mut functions);
+    }
+
+    let by_name: HashMap<String, usize> = structs
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.name.clone(), i))
+        .collect();
+    for program in &programs {
+        for (handler, _) in &program.handlers {
+            let Some(&index) = handler
+                .accounts_struct
+                .as_ref()
+                .and_then(|s| by_name.get(s))
+            else {
+                continue;
+            };
+            let texts: Vec<&str> = calls
+                .reachable(&handler.file, &handler.function)
+                .iter()
+                .filter_map(|key| functions.get(key).map(String::as_str))
+                .collect();
+            let accounts = &mut structs[index];
+            accounts
+                .instructions
+                .insert(format!("{}::{}", program.name, handler.function));
+            for field in &mut accounts.fields {
+                let usage = if field.mutable {
+                    Usage::Written
+                } else {
+                    text_usage(&texts, &field.name)
+                };
+                field.usage = field.usage.max(usage);
+            }
+        }
+    }
+
+    let mut uncovered: Vec<Uncovered> = structs
+        .iter()
+        .flat_map(|s| {
+            s.fields
+                .iter()
+                .filter(|f| f.categories.is_empty())
+                .map(|f| Uncovered {
+                    accounts_struct: s.name.clone(),
+                    field: f.name.clone(),
+                    ty: f.ty.clone(),
+                    usage: f.usage,
+                    custom_constraints: f.custom_constraints,
+                    file: s.file.clone(),
+                    line: f.line,
+                    instructions: s.instructions.clone(),
+                })
+        })
+        .collect();
+    uncovered.sort_by(|a, b| {
+        b.usage
+            .cmp(&a.usage)
+            .then(a.custom_constraints.cmp(&b.custom_constraints))
+            .then_with(|| (&a.file, a.line).cmp(&(&b.file, b.line)))
+    });
+    for field in uncovered
+        .iter()
+        .filter(|f| f.usage >= Usage::Cpi && f.custom_constraints == 0)
+    {
+        warn!(
+            accounts_struct = %field.accounts_struct,
+            field = %field.field,
+            usage = ?field.usage,
+            file = %field.file.display(),
+            line = field.line,
+            "账户没有任何约束，但被写入或传给了 CPI"
+        );
+    }
+
+    let report = ConstraintsReport {
+        metadata: ConstraintsMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        uncovered,
+        structs,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(CONSTRAINTS_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        structs = report.structs.len(),
+        fields = report.structs.iter().map(|s| s.fields.len()).sum::<usize>(),
+        uncovered = report.uncovered.len(),
+        output = %output.display(),
+        "已写出约束覆盖报告"
+    );
+    Ok(())
+}
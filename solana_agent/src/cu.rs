@@ -0,0 +1,577 @@
+// cu.rs
+//
+// 处理函数的计算单元 (CU) 估算，以及 agent compare-cu：与基线 manifest 中记录的估算比较，找出开销增长超过阈值的处理函数
+// agent analyze 在每次运行结束时把估算写入 manifest.json 的 compute_units，之后的运行就可以用它作为基线；
+// 处理函数和项目中每个函数的估算另外写在 cu.json 中，供 agent dashboard 和节点特征使用
+// 估算是最坏路径上的开销：每个节点按种类计一个基础开销，加上其中调用的系统调用 (见 SYSCALLS) 和 CPI 的开销，
+// 调用项目内的函数时加上被调用函数的最坏开销；循环只计一次 (回边不参与最长路径)，因此结果是相对量，适合比较前后两次运行
+// 处理函数有MIR层的图时使用MIR层，否则使用AST层；图中没有基本块的函数改用AST中的函数定义，所有语句都计入
+
+use crate::callgraph::{collect_function_items, cpi_targets};
+use crate::config::ArtifactsArgs;
+use crate::graph::{EdgeKind, Layer, NodeKind};
+use crate::idl::{collect_programs, handler_graphs};
+use crate::manifest::now_rfc3339;
+use crate::merge::{called_names, graph_key, load_merged, MergedGraph};
+use crate::symbols::{load_asts, AstNode, CallGraph};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const COMPARE_CU_FILE_NAME: &str = "cu_compare.json";
+
+/// agent analyze 写出的CU估算，位于产物目录下
+pub const CU_FILE_NAME: &str = "cu.json";
+
+/// AST层基本块中每条语句的开销
+const AST_STATEMENT_COST: u64 = 10;
+/// MIR语句和终结符的开销
+const MIR_STATEMENT_COST: u64 = 3;
+const MIR_TERMINATOR_COST: u64 = 5;
+
+/// 系统调用的开销，按被调用的名字匹配
+const SYSCALLS: &[(&str, u64)] = &[
+    ("find_program_address", 1500),
+    ("try_find_program_address", 1500),
+    ("create_program_address", 1500),
+    ("sol_log", 100),
+    ("sol_log_data", 100),
+    ("sol_log_compute_units", 100),
+    ("hash", 85),
+    ("hashv", 85),
+    ("keccak", 85),
+    ("secp256k1_recover", 25000),
+    ("set_return_data", 100),
+    ("get_return_data", 100),
+];
+
+/// 会产生系统调用的宏，按 `name!(` 匹配
+const MACROS: &[(&str, u64)] = &[("msg", 100), ("emit", 100), ("emit_cpi", 1100)];
+
+/// 每次 CPI 的固定开销
+const CPI_BASE_COST: u64 = 1000;
+
+/// 常见外部程序指令自身的开销
+const EXTERNAL_CPI_COSTS: &[(&str, &str, u64)] = &[
+    ("spl_token", "transfer", 4645),
+    ("spl_token", "transfer_checked", 6200),
+    ("spl_token", "mint_to", 4538),
+    ("spl_token", "mint_to_checked", 4545),
+    ("spl_token", "burn", 4753),
+    ("spl_token", "burn_checked", 4753),
+    ("spl_token", "close_account", 2916),
+    ("spl_token", "set_authority", 3000),
+    ("spl_token", "approve", 2900),
+    ("spl_token", "initialize_account3", 4240),
+    ("system_program", "transfer", 150),
+    ("system_program", "create_account", 150),
+    ("system_program", "allocate", 150),
+    ("system_program", "assign", 150),
+    ("spl_associated_token_account", "create", 25000),
+    ("spl_associated_token_account", "create_idempotent", 25000),
+];
+
+/// 未知的外部程序指令的开销
+const UNKNOWN_CPI_COST: u64 = 5000;
+
+/// 默认阈值：开销增长超过基线的百分之多少时报告
+const DEFAULT_THRESHOLD_PERCENT: f64 = 10.0;
+
+/// `agent compare-cu` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct CompareCuArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 基线：之前一次 agent analyze 写出的 manifest.json
+    #[arg(long, value_name = "MANIFEST")]
+    baseline: PathBuf,
+
+    /// 开销增长超过基线的百分比时视为退化
+    #[arg(long, value_name = "PERCENT", default_value_t = DEFAULT_THRESHOLD_PERCENT)]
+    threshold: f64,
+
+    /// 有退化时以非零状态退出，用于CI
+    #[arg(long)]
+    fail_on_regression: bool,
+
+    /// 输出文件，默认为产物目录下的 cu_compare.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 基线 manifest 中需要的部分
+#[derive(Deserialize, Debug)]
+struct BaselineManifest {
+    #[serde(default)]
+    compute_units: BTreeMap<String, u64>,
+}
+
+/// cu.json 的结构
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CuEstimates {
+    /// 每个处理函数的最坏开销，键为指令 `<program>::<ix>`
+    pub handlers: BTreeMap<String, u64>,
+    /// 每个函数的最坏开销，键为函数图的键 (见 merge::graph_key)；
+    /// 没有CFG的函数按AST中的函数定义估算，键为 `ast:<文件>:<函数名>`
+    pub functions: BTreeMap<String, u64>,
+}
+
+impl CuEstimates {
+    /// 读取产物目录中的 cu.json；没有时返回 None
+    pub fn load(artifacts_dir: &Path) -> Result<Option<CuEstimates>, Box<dyn Error>> {
+        let path = artifacts_dir.join(CU_FILE_NAME);
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Ok(None);
+        };
+        let estimates = serde_json::from_str(&content)
+            .map_err(|e| format!("无法解析 '{}': {}", path.display(), e))?;
+        Ok(Some(estimates))
+    }
+}
+
+/// 与基线比较的结果
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    /// 增长超过阈值
+    Regressed,
+    /// 下降超过阈值
+    Improved,
+    Unchanged,
+    /// 基线中没有的处理函数
+    New,
+    /// 本次运行中不再存在的处理函数
+    Removed,
+}
+
+/// 一个处理函数的比较结果
+#[derive(Serialize, Debug)]
+struct Comparison {
+    instruction: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    baseline: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta: Option<i64>,
+    /// 相对基线的变化 (百分比)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percent: Option<f64>,
+    status: Status,
+}
+
+/// cu_compare.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct CompareReport {
+    metadata: CompareMetadata,
+    baseline: PathBuf,
+    threshold_percent: f64,
+    regressions: usize,
+    handlers: Vec<Comparison>,
+}
+
+#[derive(Serialize, Debug)]
+struct CompareMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 文本中 `name!(` 形式的宏调用次数
+fn macro_calls(text: &str, name: &str) -> usize {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(&format!("{}!(", name))
+        .filter(|(start, _)| !text[..*start].chars().next_back().is_some_and(is_ident))
+        .count()
+}
+
+/// 在合并图和AST上估算处理函数的最坏开销
+struct Estimator<'a> {
+    graph: &'a MergedGraph,
+    /// 函数图的键 -> 节点下标
+    functions: HashMap<&'a str, Vec<usize>>,
+    /// 控制流边
+    successors: HashMap<usize, Vec<usize>>,
+    /// 调用点 -> 被调用函数图的键
+    callees: HashMap<usize, Vec<&'a str>>,
+    /// AST中函数定义的文本，键为AST层图的键 `ast:<文件>:<函数名>`
+    bodies: HashMap<String, &'a str>,
+    /// 按名字解析的函数之间的调用，键同上
+    body_callees: HashMap<String, Vec<String>>,
+    /// 项目内的指令 `<program>::<ix>` -> 处理函数图的键
+    handlers: HashMap<String, String>,
+    memo: HashMap<String, u64>,
+    in_progress: HashSet<String>,
+}
+
+impl<'a> Estimator<'a> {
+    fn new(graph: &'a MergedGraph, asts: &'a [(PathBuf, AstNode)]) -> Self {
+        let index: HashMap<&str, usize> = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.id.as_str(), i))
+            .collect();
+        let mut functions: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, node) in graph.nodes.iter().enumerate() {
+            functions.entry(graph_key(&node.id)).or_default().push(i);
+        }
+        let mut successors: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut callees: HashMap<usize, Vec<&str>> = HashMap::new();
+        for edge in &graph.edges {
+            let Some(&source) = index.get(edge.source.as_str()) else {
+                continue;
+            };
+            match edge.kind {
+                EdgeKind::ControlFlow => {
+                    if let Some(&target) = index.get(edge.target.as_str()) {
+                        successors.entry(source).or_default().push(target);
+                    }
+                }
+                EdgeKind::Call => {
+                    let callee = graph_key(&edge.target);
+                    let list = callees.entry(source).or_default();
+                    if !list.contains(&callee) {
+                        list.push(callee);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let key = |file: &Path, name: &str| format!("ast:{}:{}", file.display(), name);
+        let mut bodies = HashMap::new();
+        let mut call_graph = CallGraph::default();
+        for (file, root) in asts {
+            if file.extension().is_none_or(|ext| ext != "rs") {
+                continue;
+            }
+            call_graph.add_file(file, root);
+            let mut items = vec![];
+            collect_function_items(root, &mut items);
+            for (name, item) in items {
+                bodies.insert(key(file, name), item.text.as_str());
+            }
+        }
+        let mut body_callees: HashMap<String, Vec<String>> = HashMap::new();
+        for ((caller_file, caller), (callee_file, callee)) in call_graph.calls() {
+            body_callees
+                .entry(key(&caller_file, &caller))
+                .or_default()
+                .push(key(&callee_file, &callee));
+        }
+
+        Estimator {
+            graph,
+            functions,
+            successors,
+            callees,
+            bodies,
+            body_callees,
+            handlers: HashMap::new(),
+            memo: HashMap::new(),
+            in_progress: HashSet::new(),
+        }
+    }
+
+    /// 文本中的系统调用、宏和 CPI 的开销
+    fn call_cost(&mut self, text: &str) -> u64 {
+        let syscalls: u64 = called_names(text)
+            .into_iter()
+            .filter_map(|name| SYSCALLS.iter().find(|(s, _)| *s == name))
+            .map(|(_, cost)| cost)
+            .sum();
+        let macros: u64 = MACROS
+            .iter()
+            .map(|(name, cost)| macro_calls(text, name) as u64 * cost)
+            .sum();
+        let mut cost = syscalls + macros;
+        for (program, instruction) in cpi_targets(text) {
+            let name = format!("{}::{}", program, instruction);
+            cost += CPI_BASE_COST;
+            cost += match self.handlers.get(&name).cloned() {
+                Some(handler) => self.function_cost(&handler),
+                None => EXTERNAL_CPI_COSTS
+                    .iter()
+                    .find(|(p, ix, _)| *p == program && *ix == instruction)
+                    .map_or(UNKNOWN_CPI_COST, |(_, _, cost)| *cost),
+            };
+        }
+        cost
+    }
+
+    /// 节点的开销：按种类的基础开销，加上其中的调用和调用的项目内函数
+    fn node_cost(&mut self, i: usize) -> u64 {
+        let graph = self.graph;
+        let node = &graph.nodes[i];
+        let base = match node.kind {
+            NodeKind::Entry | NodeKind::Exit | NodeKind::Syntax => 0,
+            NodeKind::BasicBlock => {
+                let statements = node
+                    .properties
+                    .get("statements")
+                    .and_then(|s| s.as_array())
+                    .map_or(1, |s| s.len());
+                statements as u64 * AST_STATEMENT_COST
+            }
+            NodeKind::Statement => MIR_STATEMENT_COST,
+            NodeKind::Terminator => MIR_TERMINATOR_COST,
+        };
+        let mut cost = base + self.call_cost(&node.label);
+        for callee in self.callees.get(&i).cloned().unwrap_or_default() {
+            cost += self.function_cost(callee);
+        }
+        cost
+    }
+
+    /// 从入口出发的最坏路径开销
+    fn path_cost(&mut self, nodes: &[usize]) -> u64 {
+        // AST层的入口是 Entry 节点，MIR层的入口是第一个节点
+        let entry = nodes
+            .iter()
+            .copied()
+            .find(|&i| self.graph.nodes[i].kind == NodeKind::Entry)
+            .unwrap_or(nodes[0]);
+
+        // 深度优先搜索得到后序和回边，按后序计算到出口的最长路径
+        let mut order = vec![];
+        let mut back_edges = HashSet::new();
+        let mut finished: HashMap<usize, bool> = HashMap::from([(entry, false)]);
+        let mut stack = vec![(entry, 0)];
+        while let Some((current, next)) = stack.pop() {
+            let successors = self.successors.get(&current).map_or(&[][..], |s| s);
+            if let Some(&successor) = successors.get(next) {
+                stack.push((current, next + 1));
+                match finished.get(&successor) {
+                    Some(false) => {
+                        back_edges.insert((current, successor));
+                    }
+                    Some(true) => {}
+                    None => {
+                        finished.insert(successor, false);
+                        stack.push((successor, 0));
+                    }
+                }
+            } else {
+                finished.insert(current, true);
+                order.push(current);
+            }
+        }
+        let mut longest: HashMap<usize, u64> = HashMap::new();
+        for current in order {
+            let tail = self
+                .successors
+                .get(&current)
+                .into_iter()
+                .flatten()
+                .filter(|&&s| !back_edges.contains(&(current, s)))
+                .filter_map(|s| longest.get(s))
+                .max()
+                .copied()
+                .unwrap_or(0);
+            let cost = self.node_cost(current) + tail;
+            longest.insert(current, cost);
+        }
+        longest[&entry]
+    }
+
+    /// 函数定义的开销：没有分支信息，所有语句都计入，是最坏路径的上界
+    fn body_cost(&mut self, key: &str, body: &str) -> u64 {
+        let statements = body.matches(';').count().max(1) as u64;
+        let mut cost = statements * AST_STATEMENT_COST + self.call_cost(body);
+        for callee in self.body_callees.get(key).cloned().unwrap_or_default() {
+            cost += self.function_cost(&callee);
+        }
+        cost
+    }
+
+    /// 函数的最坏开销；图中只有入口和出口 (或没有图) 时使用AST中的函数定义，递归调用计为 0
+    fn function_cost(&mut self, key: &str) -> u64 {
+        if let Some(&cost) = self.memo.get(key) {
+            return cost;
+        }
+        if !self.in_progress.insert(key.to_string()) {
+            return 0;
+        }
+        let graph = self.graph;
+        let nodes = self.functions.get(key).cloned().unwrap_or_default();
+        let has_blocks = nodes
+            .iter()
+            .any(|&i| !matches!(graph.nodes[i].kind, NodeKind::Entry | NodeKind::Exit));
+        let cost = if has_blocks {
+            self.path_cost(&nodes)
+        } else if let Some(body) = self.bodies.get(key).copied() {
+            self.body_cost(key, body)
+        } else {
+            0
+        };
+        self.in_progress.remove(key);
+        self.memo.insert(key.to_string(), cost);
+        cost
+    }
+}
+
+/// 估算项目中每个处理函数和每个函数的最坏开销
+pub fn estimate(artifacts: &ArtifactsArgs) -> Result<CuEstimates, Box<dyn Error>> {
+    let (artifacts_dir, graph) = load_merged(artifacts)?;
+    let asts = load_asts(&artifacts_dir)?;
+    let mut programs = vec![];
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(artifacts.project.join(file)).unwrap_or_default();
+        collect_programs(root, file, &source, &mut programs);
+    }
+
+    let keys: BTreeMap<String, (Layer, String)> = graph
+        .nodes
+        .iter()
+        .map(|n| (graph_key(&n.id).to_string(), (n.layer, n.function.clone())))
+        .collect();
+    let mut estimator = Estimator::new(&graph, &asts);
+    for program in &programs {
+        for (handler, _) in &program.handlers {
+            let key = handler_graphs(&keys, &program.name, handler)
+                .into_iter()
+                .find(|k| keys[k].0 == Layer::Mir)
+                .unwrap_or_else(|| format!("ast:{}:{}", handler.file.display(), handler.function));
+            estimator
+                .handlers
+                .insert(format!("{}::{}", program.name, handler.function), key);
+        }
+    }
+
+    let mut estimates = CuEstimates::default();
+    for (instruction, key) in estimator.handlers.clone() {
+        let cost = estimator.function_cost(&key);
+        debug!(instruction = %instruction, graph = %key, cost, "已估算CU");
+        estimates.handlers.insert(instruction, cost);
+    }
+    let functions: BTreeSet<String> = estimator
+        .functions
+        .keys()
+        .map(|key| key.to_string())
+        .chain(estimator.bodies.keys().cloned())
+        .collect();
+    for key in functions {
+        let cost = estimator.function_cost(&key);
+        estimates.functions.insert(key, cost);
+    }
+    Ok(estimates)
+}
+
+/// 读取基线 manifest 中的CU估算
+fn load_baseline(path: &Path) -> Result<BTreeMap<String, u64>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("无法读取基线 '{}': {}", path.display(), e))?;
+    let manifest: BaselineManifest = serde_json::from_str(&content)
+        .map_err(|e| format!("基线 '{}' 不是 manifest.json: {}", path.display(), e))?;
+    if manifest.compute_units.is_empty() {
+        return Err(format!(
+            "基线 '{}' 中没有CU估算，请用当前版本的 agent analyze 重新生成",
+            path.display()
+        )
+        .into());
+    }
+    Ok(manifest.compute_units)
+}
+
+/// 估算当前的CU并与基线比较，写出 cu_compare.json
+pub fn run(args: &CompareCuArgs) -> Result<(), Box<dyn Error>> {
+    let baseline = load_baseline(&args.baseline)?;
+    let current = estimate(&args.artifacts)?.handlers;
+
+    let instructions: Vec<&String> = baseline
+        .keys()
+        .chain(current.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut handlers = vec![];
+    for instruction in instructions {
+        let before = baseline.get(instruction).copied();
+        let after = current.get(instruction).copied();
+        let (delta, percent, status) = match (before, after) {
+            (Some(before), Some(after)) => {
+                let delta = after as i64 - before as i64;
+                let percent = if before == 0 {
+                    if after == 0 {
+                        0.0
+                    } else {
+                        f64::INFINITY
+                    }
+                } else {
+                    delta as f64 * 100.0 / before as f64
+                };
+                let status = if percent > args.threshold {
+                    Status::Regressed
+                } else if percent < -args.threshold {
+                    Status::Improved
+                } else {
+                    Status::Unchanged
+                };
+                (Some(delta), percent.is_finite().then_some(percent), status)
+            }
+            (None, Some(_)) => (None, None, Status::New),
+            _ => (None, None, Status::Removed),
+        };
+        if status == Status::Regressed {
+            warn!(
+                instruction = %instruction,
+                baseline = before,
+                current = after,
+                percent = percent.map(|p| format!("{:+.1}%", p)),
+                "CU估算超过阈值"
+            );
+        }
+        handlers.push(Comparison {
+            instruction: instruction.clone(),
+            baseline: before,
+            current: after,
+            delta,
+            percent,
+            status,
+        });
+    }
+
+    let regressions = handlers
+        .iter()
+        .filter(|h| h.status == Status::Regressed)
+        .count();
+    let report = CompareReport {
+        metadata: CompareMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        baseline: args.baseline.clone(),
+        threshold_percent: args.threshold,
+        regressions,
+        handlers,
+    };
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => args.artifacts.artifacts_dir()?.join(COMPARE_CU_FILE_NAME),
+    };
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        handlers = report.handlers.len(),
+        regressions,
+        output = %output.display(),
+        "已写出CU比较结果"
+    );
+    if args.fail_on_regression && regressions > 0 {
+        return Err(format!(
+            "{} 个处理函数的CU估算增长超过 {}%",
+            regressions, args.threshold
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,487 @@
+// dashboard.rs
+//
+// 把所有函数的指标汇总为一个 dashboard.json 和一个 dashboard.csv，
+// 并按crate和Anchor指令 (指令处理函数及其传递调用的函数) 分别汇总
+// 复杂度、unsafe 用法和调用来自AST，调用关系按名字解析，规则与 agent merge 相同；
+// CU估算来自 agent analyze 写出的 cu.json，问题数来自 agent report 写出的报告 (按行号归到包含它的最内层函数)，
+// 没有这些文件时相应的列为空或为 0
+
+use crate::config::ArtifactsArgs;
+use crate::cu::CuEstimates;
+use crate::manifest::now_rfc3339;
+use crate::report::load_findings;
+use crate::symbols::{
+    definition_name, is_program_attribute, line_of, load_asts, AstNode, DEFINITION_KINDS,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// 输出文件名，位于输出目录下
+const DASHBOARD_JSON: &str = "dashboard.json";
+const DASHBOARD_CSV: &str = "dashboard.csv";
+
+/// 使圈复杂度加一的节点种类 (Rust 与 TypeScript/JavaScript)
+const DECISION_KINDS: &[&str] = &[
+    // Rust
+    "if_expression",
+    "match_arm",
+    "while_expression",
+    "for_expression",
+    "loop_expression",
+    "try_expression",
+    // TypeScript / JavaScript
+    "if_statement",
+    "for_statement",
+    "for_in_statement",
+    "while_statement",
+    "do_statement",
+    "switch_case",
+    "catch_clause",
+    "ternary_expression",
+];
+
+/// 使圈复杂度加一的短路运算符
+const SHORT_CIRCUIT_OPERATORS: &[&str] = &["&&", "||", "??"];
+
+/// `agent dashboard` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct DashboardArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// dashboard.json 和 dashboard.csv 的输出目录，默认为产物目录
+    #[arg(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+}
+
+/// 一组函数的指标；单个函数时 functions 为 1
+#[derive(Serialize, Debug, Default, Clone)]
+struct Metrics {
+    functions: usize,
+    complexity: usize,
+    max_complexity: usize,
+    unsafe_blocks: usize,
+    unsafe_functions: usize,
+    calls: usize,
+    /// 报告中位于函数内的问题数 (不含已修复和被抑制的)
+    findings: usize,
+    /// 最坏路径的CU估算；汇总时取其中开销最大的函数，处理函数的估算已包含它调用的函数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_compute_units: Option<u64>,
+}
+
+impl Metrics {
+    fn add(&mut self, other: &Metrics) {
+        self.functions += other.functions;
+        self.complexity += other.complexity;
+        self.max_complexity = self.max_complexity.max(other.max_complexity);
+        self.unsafe_blocks += other.unsafe_blocks;
+        self.unsafe_functions += other.unsafe_functions;
+        self.calls += other.calls;
+        self.findings += other.findings;
+        self.max_compute_units = self.max_compute_units.max(other.max_compute_units);
+    }
+}
+
+/// 单个函数的指标
+#[derive(Serialize, Debug)]
+struct FunctionRow {
+    #[serde(rename = "crate")]
+    crate_dir: PathBuf,
+    file: PathBuf,
+    function: String,
+    instruction: bool,
+    #[serde(flatten)]
+    metrics: Metrics,
+}
+
+/// 一个crate的汇总
+#[derive(Serialize, Debug)]
+struct CrateRow {
+    #[serde(rename = "crate")]
+    crate_dir: PathBuf,
+    instructions: usize,
+    #[serde(flatten)]
+    metrics: Metrics,
+}
+
+/// 一条指令的汇总：指令处理函数以及它传递调用的所有函数
+#[derive(Serialize, Debug)]
+struct InstructionRow {
+    #[serde(rename = "crate")]
+    crate_dir: PathBuf,
+    file: PathBuf,
+    instruction: String,
+    #[serde(flatten)]
+    metrics: Metrics,
+}
+
+/// dashboard.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct Dashboard {
+    metadata: DashboardMetadata,
+    totals: Metrics,
+    crates: Vec<CrateRow>,
+    instructions: Vec<InstructionRow>,
+    functions: Vec<FunctionRow>,
+}
+
+#[derive(Serialize, Debug)]
+struct DashboardMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 函数的标识：(源文件, 函数名)
+type FunctionKey<'a> = (&'a Path, &'a str);
+
+/// 在AST中收集到的一个函数
+struct FunctionInfo {
+    name: String,
+    /// 函数定义的起止行
+    lines: (usize, usize),
+    instruction: bool,
+    complexity: usize,
+    unsafe_blocks: usize,
+    unsafe_function: bool,
+    /// 函数体内调用的名字
+    callees: Vec<String>,
+}
+
+/// 函数体内的统计结果
+#[derive(Default)]
+struct BodyStats {
+    decisions: usize,
+    unsafe_blocks: usize,
+    callees: Vec<String>,
+}
+
+/// 统计函数体内的判定点、unsafe 块和调用，不进入嵌套的函数定义
+fn count_body(node: &AstNode, stats: &mut BodyStats) {
+    for child in &node.children {
+        if DEFINITION_KINDS.iter().any(|(kind, _)| *kind == child.kind) {
+            continue;
+        }
+        match child.kind.as_str() {
+            kind if DECISION_KINDS.contains(&kind) => stats.decisions += 1,
+            "binary_expression" | "logical_expression"
+                if child
+                    .children
+                    .iter()
+                    .any(|op| SHORT_CIRCUIT_OPERATORS.contains(&op.kind.as_str())) =>
+            {
+                stats.decisions += 1
+            }
+            "unsafe_block" => stats.unsafe_blocks += 1,
+            // 被调用的名字取调用表达式的最后一段，例如 `a::b` 和 `x.b` 都取 `b`
+            "call_expression" => {
+                if let Some(name) = child.children.first().and_then(|callee| {
+                    callee
+                        .text
+                        .rsplit(|c: char| !(c.is_alphanumeric() || c == '_'))
+                        .next()
+                        .filter(|name| !name.is_empty())
+                }) {
+                    stats.callees.push(name.to_string());
+                }
+            }
+            _ => {}
+        }
+        count_body(child, stats);
+    }
+}
+
+/// 收集一个文件中的所有函数，source 为该文件的源码；`in_program` 表示当前位于 `#[program]` 模块的顶层
+fn collect_functions(
+    node: &AstNode,
+    source: &str,
+    in_program: bool,
+    functions: &mut Vec<FunctionInfo>,
+) {
+    let mut program_attribute = false;
+    for child in &node.children {
+        let is_function = DEFINITION_KINDS
+            .iter()
+            .any(|(kind, symbol)| *kind == child.kind && matches!(*symbol, "function" | "method"));
+        if let Some(name) = definition_name(child).filter(|_| is_function) {
+            let mut stats = BodyStats::default();
+            count_body(child, &mut stats);
+            let is_pub = child
+                .children
+                .iter()
+                .any(|c| c.kind == "visibility_modifier");
+            functions.push(FunctionInfo {
+                name: name.text.clone(),
+                lines: (
+                    line_of(source, child.start_byte),
+                    line_of(source, child.end_byte),
+                ),
+                instruction: in_program && is_pub,
+                complexity: stats.decisions + 1,
+                unsafe_blocks: stats.unsafe_blocks,
+                unsafe_function: child
+                    .children
+                    .iter()
+                    .any(|c| c.kind == "function_modifiers" && c.text.contains("unsafe")),
+                callees: stats.callees,
+            });
+        }
+        // 指令处理函数是 `#[program]` 模块中声明的 pub fn
+        let program_module = child.kind == "mod_item" && program_attribute;
+        let descend_into_program = in_program && child.kind == "declaration_list";
+        collect_functions(
+            child,
+            source,
+            program_module || descend_into_program,
+            functions,
+        );
+        program_attribute =
+            is_program_attribute(child) || (program_attribute && child.kind == "attribute_item");
+    }
+}
+
+/// 源文件所属的crate或包：向上找到的第一个包含 Cargo.toml 或 package.json 的目录
+fn crate_of(project: &Path, file: &Path) -> PathBuf {
+    file.ancestors()
+        .skip(1)
+        .find(|dir| {
+            let dir = project.join(dir);
+            dir.join("Cargo.toml").is_file() || dir.join("package.json").is_file()
+        })
+        .unwrap_or(Path::new(""))
+        .to_path_buf()
+}
+
+/// 写出CSV，每行一个函数、crate或指令，由 level 列区分
+fn write_csv(path: &Path, dashboard: &Dashboard) -> Result<(), Box<dyn Error>> {
+    fn field(value: &str) -> String {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+    let mut csv = String::from(
+        "level,crate,file,name,functions,complexity,max_complexity,unsafe_blocks,unsafe_functions,calls,findings,max_compute_units\n",
+    );
+    let mut row = |level: &str, crate_dir: &Path, file: &str, name: &str, m: &Metrics| {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            level,
+            field(&crate_dir.to_string_lossy()),
+            field(file),
+            field(name),
+            m.functions,
+            m.complexity,
+            m.max_complexity,
+            m.unsafe_blocks,
+            m.unsafe_functions,
+            m.calls,
+            m.findings,
+            m.max_compute_units
+                .map_or(String::new(), |cu| cu.to_string())
+        ));
+    };
+    for c in &dashboard.crates {
+        row("crate", &c.crate_dir, "", "", &c.metrics);
+    }
+    for i in &dashboard.instructions {
+        let file = i.file.to_string_lossy();
+        row(
+            "instruction",
+            &i.crate_dir,
+            &file,
+            &i.instruction,
+            &i.metrics,
+        );
+    }
+    for f in &dashboard.functions {
+        let file = f.file.to_string_lossy();
+        row("function", &f.crate_dir, &file, &f.function, &f.metrics);
+    }
+    fs::write(path, csv)?;
+    Ok(())
+}
+
+/// 汇总产物目录中所有函数的指标，写出 dashboard.json 和 dashboard.csv
+pub fn run(args: &DashboardArgs) -> Result<(), Box<dyn Error>> {
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut infos = vec![];
+    for (file, root) in &asts {
+        let mut found = vec![];
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        collect_functions(root, &source, false, &mut found);
+        let crate_dir = crate_of(project, file);
+        infos.extend(
+            found
+                .into_iter()
+                .map(|info| (file, crate_dir.clone(), info)),
+        );
+    }
+
+    let compute_units = CuEstimates::load(&artifacts_dir)?.unwrap_or_default();
+    // 问题归到包含其所在行的最内层 (范围最小的) 函数
+    let mut findings: HashMap<(&Path, &str), usize> = HashMap::new();
+    let report = load_findings(&artifacts_dir)?;
+    for finding in &report {
+        let (Some(file), Some(line)) = (
+            finding.get("file").and_then(|f| f.as_str()).map(Path::new),
+            finding
+                .get("line")
+                .and_then(|l| l.as_u64())
+                .map(|l| l as usize),
+        ) else {
+            continue;
+        };
+        let innermost = infos
+            .iter()
+            .filter(|(f, _, info)| {
+                f.as_path() == file && info.lines.0 <= line && line <= info.lines.1
+            })
+            .min_by_key(|(_, _, info)| info.lines.1 - info.lines.0);
+        if let Some((f, _, info)) = innermost {
+            *findings
+                .entry((f.as_path(), info.name.as_str()))
+                .or_default() += 1;
+        }
+    }
+
+    // 函数以 (文件, 名字) 标识，同一文件中的同名函数 (例如不同 impl 中的方法) 视为同一个
+    let mut by_name: HashMap<&str, Vec<&Path>> = HashMap::new();
+    for (file, _, info) in &infos {
+        let files = by_name.entry(info.name.as_str()).or_default();
+        if !files.contains(&file.as_path()) {
+            files.push(file);
+        }
+    }
+
+    // 调用关系：优先解析到同一文件中的同名函数，否则只在名字全局唯一时解析
+    let mut calls: HashMap<FunctionKey, HashSet<FunctionKey>> = HashMap::new();
+    for (file, _, info) in &infos {
+        let callees = calls.entry((file, &info.name)).or_default();
+        for name in &info.callees {
+            let Some((name, files)) = by_name.get_key_value(name.as_str()) else {
+                continue;
+            };
+            match files.as_slice() {
+                files if files.contains(&file.as_path()) => callees.insert((file, name)),
+                [target] => callees.insert((target, name)),
+                _ => continue,
+            };
+        }
+    }
+
+    let mut functions: Vec<FunctionRow> = infos
+        .iter()
+        .map(|(file, crate_dir, info)| FunctionRow {
+            crate_dir: crate_dir.clone(),
+            file: file.to_path_buf(),
+            function: info.name.clone(),
+            instruction: info.instruction,
+            metrics: Metrics {
+                functions: 1,
+                complexity: info.complexity,
+                max_complexity: info.complexity,
+                unsafe_blocks: info.unsafe_blocks,
+                unsafe_functions: info.unsafe_function as usize,
+                calls: calls[&(file.as_path(), info.name.as_str())].len(),
+                findings: findings
+                    .get(&(file.as_path(), info.name.as_str()))
+                    .copied()
+                    .unwrap_or(0),
+                max_compute_units: compute_units
+                    .functions
+                    .get(&format!("ast:{}:{}", file.display(), info.name))
+                    .copied(),
+            },
+        })
+        .collect();
+    functions.sort_by(|a, b| (&a.file, &a.function).cmp(&(&b.file, &b.function)));
+
+    let mut by_key: HashMap<FunctionKey, Metrics> = HashMap::new();
+    let mut totals = Metrics::default();
+    let mut crates: BTreeMap<&Path, CrateRow> = BTreeMap::new();
+    for f in &functions {
+        by_key
+            .entry((&f.file, &f.function))
+            .or_default()
+            .add(&f.metrics);
+        totals.add(&f.metrics);
+        let row = crates.entry(&f.crate_dir).or_insert_with(|| CrateRow {
+            crate_dir: f.crate_dir.clone(),
+            instructions: 0,
+            metrics: Metrics::default(),
+        });
+        row.instructions += f.instruction as usize;
+        row.metrics.add(&f.metrics);
+    }
+
+    // 指令的汇总：沿调用关系找到所有传递调用的函数
+    let mut instructions = vec![];
+    for f in functions.iter().filter(|f| f.instruction) {
+        let start: FunctionKey = (&f.file, &f.function);
+        let mut seen = HashSet::from([start]);
+        let mut queue = vec![start];
+        let mut metrics = Metrics::default();
+        while let Some(key) = queue.pop() {
+            if let Some(m) = by_key.get(&key) {
+                metrics.add(m);
+            }
+            for &callee in calls.get(&key).into_iter().flatten() {
+                if seen.insert(callee) {
+                    queue.push(callee);
+                }
+            }
+        }
+        instructions.push(InstructionRow {
+            crate_dir: f.crate_dir.clone(),
+            file: f.file.clone(),
+            instruction: f.function.clone(),
+            metrics,
+        });
+    }
+
+    let dashboard = Dashboard {
+        metadata: DashboardMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        totals,
+        crates: crates.into_values().collect(),
+        instructions,
+        functions,
+    };
+
+    let output_dir = args.output.clone().unwrap_or(artifacts_dir);
+    fs::create_dir_all(&output_dir)?;
+    fs::write(
+        output_dir.join(DASHBOARD_JSON),
+        serde_json::to_string_pretty(&dashboard)?,
+    )?;
+    write_csv(&output_dir.join(DASHBOARD_CSV), &dashboard)?;
+    info!(
+        functions = dashboard.totals.functions,
+        findings = dashboard.totals.findings,
+        crates = dashboard.crates.len(),
+        instructions = dashboard.instructions.len(),
+        output = %output_dir.display(),
+        "已写出指标汇总"
+    );
+    Ok(())
+}
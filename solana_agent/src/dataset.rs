@@ -0,0 +1,519 @@
+// dataset.rs
+//
+// agent dataset：把合并图中的每个函数图导出为图学习可以直接读取的数据集
+// dataset.npz 采用与 OGB 相同的拼接方式：所有图的节点特征拼成一个矩阵 x，
+// 节点特征由 features.rs 提取；第 i 个图的节点是 x[node_ptr[i]..node_ptr[i+1]]，边是 edge_index[:, edge_ptr[i]..edge_ptr[i+1]]，
+// edge_index 中的下标相对于所在图的第一个节点，可以直接构造 PyG 的 Data 或 DGL 的 graph
+// graphs.json 记录每个图对应的函数、特征和边类型的含义
+// 标签由 labels.rs 映射：y 为每个图的标签，node_y 为每个节点的标签，没有标签记为 -1
+
+use crate::config::{ArtifactsArgs, FeaturesConfig};
+use crate::features::{FeatureArgs, Featurizer};
+use crate::graph::{EdgeKind, Layer};
+use crate::labels::{Labels, UNLABELED};
+use crate::merge::{graph_key, load_merged, MergedGraph};
+use crate::npz::{npy_f32, npy_i64, write_npz};
+use crate::sample::{self, SampleArgs};
+use clap::ValueEnum;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 数据集的文件名，位于输出目录下
+const DATASET_FILE_NAME: &str = "dataset.npz";
+const GRAPHS_FILE_NAME: &str = "graphs.json";
+
+/// edge_type 中边种类的编号
+const EDGE_KINDS: [EdgeKind; 4] = [
+    EdgeKind::ControlFlow,
+    EdgeKind::DataFlow,
+    EdgeKind::Call,
+    EdgeKind::SameSource,
+];
+
+/// `agent dataset` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct DatasetArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出目录，默认为产物目录下的 dataset
+    #[arg(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+
+    /// 只导出某一层的图，默认两层都导出
+    #[arg(long, value_enum)]
+    layer: Option<LayerFilter>,
+
+    #[command(flatten)]
+    features: FeatureArgs,
+
+    #[command(flatten)]
+    sample: SampleArgs,
+
+    /// 标签文件：按图的键或函数名给出整数标签的JSON对象，
+    /// 或者由 (commit, file, start_line, end_line, cwe) 组成的JSON数组，格式见 labels.rs
+    #[arg(long, value_name = "FILE")]
+    labels: Option<PathBuf>,
+}
+
+/// --layer 的取值
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LayerFilter {
+    Ast,
+    Mir,
+}
+
+impl LayerFilter {
+    fn matches(self, layer: Layer) -> bool {
+        matches!(
+            (self, layer),
+            (LayerFilter::Ast, Layer::Ast) | (LayerFilter::Mir, Layer::Mir)
+        )
+    }
+}
+
+/// 拼接后的数据集
+#[derive(Default)]
+struct Dataset {
+    x: Vec<f32>,
+    edge_index: [Vec<i64>; 2],
+    edge_type: Vec<i64>,
+    node_ptr: Vec<i64>,
+    edge_ptr: Vec<i64>,
+    y: Vec<i64>,
+    node_y: Vec<i64>,
+}
+
+impl Dataset {
+    /// 把另一个数据集的图接在后面；edge_index 中的下标相对于所在图，只需平移 node_ptr 和 edge_ptr
+    fn append(&mut self, other: Dataset) {
+        let nodes = *self.node_ptr.last().unwrap();
+        let edges = *self.edge_ptr.last().unwrap();
+        self.x.extend(other.x);
+        for (mine, theirs) in self.edge_index.iter_mut().zip(other.edge_index) {
+            mine.extend(theirs);
+        }
+        self.edge_type.extend(other.edge_type);
+        self.node_ptr
+            .extend(other.node_ptr.iter().skip(1).map(|p| p + nodes));
+        self.edge_ptr
+            .extend(other.edge_ptr.iter().skip(1).map(|p| p + edges));
+        self.y.extend(other.y);
+        self.node_y.extend(other.node_y);
+    }
+}
+
+/// 按函数图拆分合并图，生成数据集和每个图的描述
+fn build_dataset(
+    merged: &MergedGraph,
+    project: &Path,
+    layer: Option<LayerFilter>,
+    featurizer: &Featurizer,
+    compute_units: &BTreeMap<String, u64>,
+    labels: &Labels,
+) -> (Dataset, Vec<serde_json::Value>) {
+    let dim = featurizer.dim();
+    let features = featurizer.featurize(merged, compute_units);
+    let node_labels = labels.node_labels(merged, project);
+    // 图的键 -> 该图节点在 merged.nodes 中的下标
+    let mut graphs: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, node) in merged.nodes.iter().enumerate() {
+        if layer.is_none_or(|filter| filter.matches(node.layer)) {
+            graphs.entry(graph_key(&node.id)).or_default().push(i);
+        }
+    }
+    let mut edges_by_graph: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, edge) in merged.edges.iter().enumerate() {
+        let key = graph_key(&edge.source);
+        if key == graph_key(&edge.target) {
+            edges_by_graph.entry(key).or_default().push(i);
+        }
+    }
+
+    let mut dataset = Dataset {
+        node_ptr: vec![0],
+        edge_ptr: vec![0],
+        ..Dataset::default()
+    };
+    let mut descriptions = vec![];
+    for (key, node_indices) in &graphs {
+        let local: HashMap<&str, i64> = node_indices
+            .iter()
+            .enumerate()
+            .map(|(local, &i)| (merged.nodes[i].id.as_str(), local as i64))
+            .collect();
+        for &i in node_indices {
+            dataset
+                .x
+                .extend_from_slice(&features[i * dim..(i + 1) * dim]);
+            dataset.node_y.push(node_labels[i]);
+        }
+        let mut edges = 0;
+        for &i in edges_by_graph.get(key).into_iter().flatten() {
+            let edge = &merged.edges[i];
+            let (Some(&source), Some(&target)) = (
+                local.get(edge.source.as_str()),
+                local.get(edge.target.as_str()),
+            ) else {
+                continue;
+            };
+            let kind = EDGE_KINDS.iter().position(|k| *k == edge.kind).unwrap_or(0);
+            dataset.edge_index[0].push(source);
+            dataset.edge_index[1].push(target);
+            dataset.edge_type.push(kind as i64);
+            edges += 1;
+        }
+
+        let first = &merged.nodes[node_indices[0]];
+        let graph_node_labels = &dataset.node_y[dataset.node_y.len() - node_indices.len()..];
+        let label = labels.graph_label(key, &first.function, graph_node_labels);
+        let labeled_nodes = graph_node_labels
+            .iter()
+            .filter(|&&l| l != UNLABELED)
+            .count();
+        dataset.y.push(label);
+        dataset
+            .node_ptr
+            .push(dataset.node_ptr.last().unwrap() + node_indices.len() as i64);
+        dataset
+            .edge_ptr
+            .push(dataset.edge_ptr.last().unwrap() + edges as i64);
+        descriptions.push(json!({
+            "key": key,
+            "layer": first.layer,
+            "function": first.function,
+            "nodes": node_indices.len(),
+            "edges": edges,
+            "label": label,
+            "labeled_nodes": labeled_nodes,
+        }));
+    }
+    (dataset, descriptions)
+}
+
+/// 写出 dataset.npz 和 graphs.json
+fn write_dataset(
+    output_dir: &Path,
+    dataset: &Dataset,
+    graphs: Vec<serde_json::Value>,
+    featurizer: &Featurizer,
+    labels: &Labels,
+) -> Result<(), Box<dyn Error>> {
+    let nodes = *dataset.node_ptr.last().unwrap() as usize;
+    let edges = dataset.edge_type.len();
+    fs::create_dir_all(output_dir)?;
+    write_npz(
+        &output_dir.join(DATASET_FILE_NAME),
+        &[
+            ("x.npy", npy_f32(&[nodes, featurizer.dim()], &dataset.x)),
+            (
+                "edge_index.npy",
+                npy_i64(&[2, edges], &dataset.edge_index.concat()),
+            ),
+            ("edge_type.npy", npy_i64(&[edges], &dataset.edge_type)),
+            (
+                "node_ptr.npy",
+                npy_i64(&[dataset.node_ptr.len()], &dataset.node_ptr),
+            ),
+            (
+                "edge_ptr.npy",
+                npy_i64(&[dataset.edge_ptr.len()], &dataset.edge_ptr),
+            ),
+            ("y.npy", npy_i64(&[dataset.y.len()], &dataset.y)),
+            ("node_y.npy", npy_i64(&[nodes], &dataset.node_y)),
+        ],
+    )?;
+    let description = json!({
+        "feature_names": featurizer.names(),
+        "edge_types": EDGE_KINDS,
+        "label_classes": labels.classes,
+        "graphs": graphs,
+    });
+    fs::write(
+        output_dir.join(GRAPHS_FILE_NAME),
+        serde_json::to_string_pretty(&description)?,
+    )?;
+    Ok(())
+}
+
+/// 把多个项目的图导出为同一个数据集 (agent batch 使用)：所有项目共用同一套特征，graphs.json 中每个图带有所属项目的名字；
+/// 没有图的项目跳过，返回导出的图数
+pub fn export_combined(
+    projects: &[(String, ArtifactsArgs)],
+    features: &FeaturesConfig,
+    output_dir: &Path,
+) -> Result<usize, Box<dyn Error>> {
+    let featurizer = Featurizer::new(&FeatureArgs::default(), features);
+    let labels = Labels::default();
+    let mut dataset = Dataset {
+        node_ptr: vec![0],
+        edge_ptr: vec![0],
+        ..Dataset::default()
+    };
+    let mut graphs = vec![];
+    for (name, artifacts) in projects {
+        let (artifacts_dir, merged) = match load_merged(artifacts) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                warn!(project = %name, "{}，不计入数据集", e);
+                continue;
+            }
+        };
+        let compute_units = featurizer.load_compute_units(&artifacts_dir)?;
+        let (part, descriptions) = build_dataset(
+            &merged,
+            &artifacts.project,
+            None,
+            &featurizer,
+            &compute_units,
+            &labels,
+        );
+        dataset.append(part);
+        graphs.extend(descriptions.into_iter().map(|mut d| {
+            d["project"] = json!(name);
+            d
+        }));
+    }
+    let count = graphs.len();
+    if count > 0 {
+        write_dataset(output_dir, &dataset, graphs, &featurizer, &labels)?;
+    }
+    Ok(count)
+}
+
+/// 读取产物目录中的图，导出图学习数据集
+pub fn run(args: &DatasetArgs) -> Result<(), Box<dyn Error>> {
+    let labels = match &args.labels {
+        Some(path) => Labels::load(path, &args.artifacts.project)?,
+        None => Labels::default(),
+    };
+    let featurizer = Featurizer::new(&args.features, &args.artifacts.load_config()?.features);
+    let (artifacts_dir, mut merged) = load_merged(&args.artifacts)?;
+    sample::apply(&args.sample, &mut merged);
+    let compute_units = featurizer.load_compute_units(&artifacts_dir)?;
+    let (dataset, graphs) = build_dataset(
+        &merged,
+        &args.artifacts.project,
+        args.layer,
+        &featurizer,
+        &compute_units,
+        &labels,
+    );
+    if graphs.is_empty() {
+        return Err("没有符合条件的图".into());
+    }
+
+    let nodes = *dataset.node_ptr.last().unwrap() as usize;
+    let edges = dataset.edge_type.len();
+    let output_dir = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join("dataset"));
+    let labeled = dataset.y.iter().filter(|&&y| y != UNLABELED).count();
+    let labeled_nodes = dataset.node_y.iter().filter(|&&y| y != UNLABELED).count();
+    let count = graphs.len();
+    write_dataset(&output_dir, &dataset, graphs, &featurizer, &labels)?;
+    info!(
+        graphs = count,
+        nodes,
+        edges,
+        labeled,
+        labeled_nodes,
+        output = %output_dir.display(),
+        "已导出数据集"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::NodeKind;
+    use crate::merge::{MergedEdge, MergedMetadata, MergedNode};
+
+    fn node(id: &str, layer: Layer, function: &str, label: &str) -> MergedNode {
+        MergedNode {
+            id: id.to_string(),
+            layer,
+            function: function.to_string(),
+            kind: NodeKind::BasicBlock,
+            label: label.to_string(),
+            span: None,
+            provenance: None,
+            properties: BTreeMap::new(),
+        }
+    }
+
+    fn edge(source: &str, target: &str, kind: EdgeKind) -> MergedEdge {
+        MergedEdge {
+            source: source.to_string(),
+            target: target.to_string(),
+            kind,
+        }
+    }
+
+    /// AST层的 deposit (3个节点) 和MIR层的 withdraw (2个节点)，外加一条跨图的调用边
+    fn merged() -> MergedGraph {
+        MergedGraph {
+            metadata: MergedMetadata::current(),
+            nodes: vec![
+                node("ast:src/lib.rs:deposit#0", Layer::Ast, "deposit", "entry"),
+                node(
+                    "mir:src/lib.rs:withdraw#0",
+                    Layer::Mir,
+                    "withdraw",
+                    "_1 = move _2",
+                ),
+                node(
+                    "ast:src/lib.rs:deposit#1",
+                    Layer::Ast,
+                    "deposit",
+                    "transfer(ctx, amount)",
+                ),
+                node("ast:src/lib.rs:deposit#2", Layer::Ast, "deposit", "exit"),
+                node(
+                    "mir:src/lib.rs:withdraw#1",
+                    Layer::Mir,
+                    "withdraw",
+                    "return",
+                ),
+            ],
+            edges: vec![
+                edge(
+                    "ast:src/lib.rs:deposit#0",
+                    "ast:src/lib.rs:deposit#1",
+                    EdgeKind::ControlFlow,
+                ),
+                edge(
+                    "ast:src/lib.rs:deposit#1",
+                    "ast:src/lib.rs:deposit#2",
+                    EdgeKind::ControlFlow,
+                ),
+                edge(
+                    "ast:src/lib.rs:deposit#0",
+                    "ast:src/lib.rs:deposit#2",
+                    EdgeKind::DataFlow,
+                ),
+                edge(
+                    "mir:src/lib.rs:withdraw#0",
+                    "mir:src/lib.rs:withdraw#1",
+                    EdgeKind::ControlFlow,
+                ),
+                edge(
+                    "ast:src/lib.rs:deposit#1",
+                    "mir:src/lib.rs:withdraw#0",
+                    EdgeKind::Call,
+                ),
+            ],
+        }
+    }
+
+    fn featurizer() -> Featurizer {
+        Featurizer::new(&FeatureArgs::default(), &FeaturesConfig::default())
+    }
+
+    #[test]
+    fn graphs_are_sliced_with_local_edge_indices() {
+        let featurizer = featurizer();
+        let (dataset, graphs) = build_dataset(
+            &merged(),
+            Path::new("."),
+            None,
+            &featurizer,
+            &BTreeMap::new(),
+            &Labels::default(),
+        );
+        // 图按键排序：先 ast:...deposit，后 mir:...withdraw；跨图的调用边不计入
+        assert_eq!(graphs.len(), 2);
+        assert_eq!(graphs[0]["function"], "deposit");
+        assert_eq!(graphs[1]["function"], "withdraw");
+        assert_eq!(dataset.node_ptr, [0, 3, 5]);
+        assert_eq!(dataset.edge_ptr, [0, 3, 4]);
+        assert_eq!(dataset.edge_index, [vec![0, 1, 0, 0], vec![1, 2, 2, 1]]);
+        assert_eq!(dataset.edge_type, [0, 0, 1, 0]);
+        assert_eq!(dataset.x.len(), 5 * featurizer.dim());
+        assert_eq!(dataset.y, [UNLABELED, UNLABELED]);
+        assert_eq!(dataset.node_y, [UNLABELED; 5]);
+    }
+
+    #[test]
+    fn layer_filter_and_graph_labels() {
+        let dir = std::env::temp_dir().join(format!("solana_agent-dataset-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("labels.json");
+        fs::write(&path, r#"{"withdraw": 1}"#).unwrap();
+        let labels = Labels::load(&path, &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (dataset, graphs) = build_dataset(
+            &merged(),
+            Path::new("."),
+            Some(LayerFilter::Mir),
+            &featurizer(),
+            &BTreeMap::new(),
+            &labels,
+        );
+        assert_eq!(graphs.len(), 1);
+        assert_eq!(graphs[0]["key"], "mir:src/lib.rs:withdraw");
+        assert_eq!(dataset.node_ptr, [0, 2]);
+        assert_eq!(dataset.edge_index, [vec![0], vec![1]]);
+        assert_eq!(dataset.y, [1]);
+    }
+
+    #[test]
+    fn append_shifts_pointers_and_writes_npz() {
+        let featurizer = featurizer();
+        let build = || {
+            build_dataset(
+                &merged(),
+                Path::new("."),
+                None,
+                &featurizer,
+                &BTreeMap::new(),
+                &Labels::default(),
+            )
+        };
+        let (mut dataset, mut graphs) = build();
+        let (other, other_graphs) = build();
+        dataset.append(other);
+        graphs.extend(other_graphs);
+        assert_eq!(dataset.node_ptr, [0, 3, 5, 8, 10]);
+        assert_eq!(dataset.edge_ptr, [0, 3, 4, 7, 8]);
+        // edge_index 仍相对于所在的图
+        assert_eq!(dataset.edge_index[0][4..], [0, 1, 0, 0]);
+
+        let dir = std::env::temp_dir().join(format!("solana_agent-npz-{}", std::process::id()));
+        write_dataset(&dir, &dataset, graphs, &featurizer, &Labels::default()).unwrap();
+        let npz = fs::read(dir.join(DATASET_FILE_NAME)).unwrap();
+        let description: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join(GRAPHS_FILE_NAME)).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(npz.starts_with(b"PK\x03\x04"));
+        for name in [
+            "x.npy",
+            "edge_index.npy",
+            "node_ptr.npy",
+            "y.npy",
+            "node_y.npy",
+        ] {
+            let at = npz
+                .windows(name.len())
+                .position(|w| w == name.as_bytes())
+                .unwrap();
+            assert!(npz[at + name.len()..].starts_with(b"\x93NUMPY"), "{}", name);
+        }
+        let header = "{'descr': '<i8', 'fortran_order': False, 'shape': (5,), }";
+        assert!(npz.windows(header.len()).any(|w| w == header.as_bytes()));
+        assert_eq!(description["graphs"].as_array().unwrap().len(), 4);
+        assert_eq!(description["edge_types"][1], "data_flow");
+        assert_eq!(
+            description["feature_names"].as_array().unwrap().len(),
+            featurizer.dim()
+        );
+    }
+}
@@ -0,0 +1,584 @@
+// db.rs
+//
+// agent db：把一次运行中各阶段的产物 (AST、CFG、MIR层的 CPG、跨语言链接和调用图) 以及 agent report 的问题
+// 导入同一个 SQLite 数据库 agent.db，便于用 SQL 做临时查询或接入其他工具
+// 关系模式见 SCHEMA (可以用 --print-schema 打印)：
+//   files          源文件
+//   ast_nodes      AST的每个语法节点，parent_id 指向父节点；文本只保存在叶子节点上，其余节点按字节范围从源码中取
+//   nodes / edges  agent link 的跨语言图 (AST层 CFG、MIR层 CPG 和客户端调用图)，节点ID与 merged.json 相同
+//   functions      函数级调用图 (agent callgraph) 的函数，entry 为函数在 nodes 中的入口节点
+//   calls          函数之间的调用，cpi 标记跨程序调用
+//   provenance     跨层溯源 (见 provenance.rs)：CFG 语句和MIR节点对应的AST语法节点，MIR节点另有同源的 CFG 语句
+//   findings       agent report 写出的报告中的问题，finding_nodes 和 finding_cwes 为其相关节点和 CWE 编号
+// 视图：handlers (指令处理函数)、cpis (CPI 的调用方和目标)、finding_details (问题及其所在文件)、
+// cross_layer (MIR节点 ↔ CFG 语句 ↔ AST语法节点) 和 finding_sources (问题的相关节点在源码中对应的语法结构)
+// 每次运行都重新生成整个数据库，所有写入在一个事务中完成
+
+use crate::callgraph::{project_callgraph, side, ProjectCallGraph};
+use crate::config::ArtifactsArgs;
+use crate::manifest::now_rfc3339;
+use crate::merge::{graph_key, MergedNode};
+use crate::provenance::{ast_node_id, provenance};
+use crate::report::load_findings;
+use crate::symbols::{language_of, line_of, AstNode};
+use rusqlite::{params, Connection, Statement, Transaction};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// 输出文件名，默认位于产物目录下
+const DB_FILE_NAME: &str = "agent.db";
+
+/// 数据库模式的版本，写入 metadata 表；表或列有不兼容的变化时递增
+const SCHEMA_VERSION: &str = "2";
+
+/// 数据库的关系模式：表、索引和视图
+const SCHEMA: &str = "\
+-- 本次导入的元数据：tool、tool_version、schema_version、generated_at、project
+CREATE TABLE metadata (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+-- 源文件，path 为相对于项目根目录的路径
+CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    language TEXT
+);
+
+-- AST的语法节点；parent_id 为空的是文件的根节点，position 为在父节点中的序号
+-- key 为跨运行稳定的ID `<文件>@<起始字节>..<结束字节>:<种类>`，与 provenance.ast_node 对应
+-- text 只保存在叶子节点上，其余节点的文本为源码中 [start_byte, end_byte) 的部分
+CREATE TABLE ast_nodes (
+    id INTEGER PRIMARY KEY,
+    key TEXT NOT NULL,
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    parent_id INTEGER REFERENCES ast_nodes(id),
+    position INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    start_byte INTEGER NOT NULL,
+    end_byte INTEGER NOT NULL,
+    text TEXT
+);
+CREATE INDEX ast_nodes_key ON ast_nodes(key);
+CREATE INDEX ast_nodes_file ON ast_nodes(file_id, start_byte);
+CREATE INDEX ast_nodes_parent ON ast_nodes(parent_id);
+CREATE INDEX ast_nodes_kind ON ast_nodes(kind);
+
+-- 跨语言图的节点 (与 merged.json 和 linked.json 相同)；layer 为 ast 或 mir，
+-- graph 为节点所属函数图的键 `<层>:<单元>:<函数>`，properties 为 JSON 对象
+CREATE TABLE nodes (
+    id TEXT PRIMARY KEY,
+    layer TEXT NOT NULL,
+    graph TEXT NOT NULL,
+    function TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    label TEXT NOT NULL,
+    file_id INTEGER REFERENCES files(id),
+    start_byte INTEGER,
+    end_byte INTEGER,
+    start_line INTEGER,
+    end_line INTEGER,
+    provenance TEXT,
+    properties TEXT
+);
+CREATE INDEX nodes_graph ON nodes(graph);
+CREATE INDEX nodes_function ON nodes(function);
+CREATE INDEX nodes_kind ON nodes(kind);
+CREATE INDEX nodes_location ON nodes(file_id, start_line);
+
+-- 跨语言图的边
+CREATE TABLE edges (
+    id INTEGER PRIMARY KEY,
+    source TEXT NOT NULL REFERENCES nodes(id),
+    target TEXT NOT NULL REFERENCES nodes(id),
+    kind TEXT NOT NULL
+);
+CREATE INDEX edges_source ON edges(source, kind);
+CREATE INDEX edges_target ON edges(target, kind);
+
+-- 跨层溯源：图节点对应的AST语法节点；statement 为 CFG 基本块中语句的序号，整个节点对应同一处源码时为空
+-- MIR节点另记下同源的 CFG 基本块 cfg_node 及其中的语句 cfg_statement
+CREATE TABLE provenance (
+    node_id TEXT NOT NULL REFERENCES nodes(id),
+    statement INTEGER,
+    cfg_node TEXT REFERENCES nodes(id),
+    cfg_statement INTEGER,
+    ast_node TEXT NOT NULL,
+    ast_kind TEXT NOT NULL,
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    start_byte INTEGER NOT NULL,
+    end_byte INTEGER NOT NULL,
+    start_line INTEGER,
+    end_line INTEGER
+);
+CREATE INDEX provenance_node ON provenance(node_id, statement);
+CREATE INDEX provenance_cfg ON provenance(cfg_node, cfg_statement);
+CREATE INDEX provenance_ast ON provenance(ast_node);
+
+-- 函数级调用图的函数；entry 为函数入口节点的ID，外部程序的指令没有对应的 nodes 行
+-- side 为 program、client 或 external；instruction 对处理函数为 `<程序>::<指令>`，对外部程序的指令为指令名
+CREATE TABLE functions (
+    entry TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    side TEXT NOT NULL,
+    language TEXT,
+    program TEXT,
+    instruction TEXT,
+    file_id INTEGER REFERENCES files(id),
+    start_line INTEGER,
+    end_line INTEGER
+);
+CREATE INDEX functions_name ON functions(name);
+CREATE INDEX functions_instruction ON functions(instruction);
+
+-- 函数之间的调用，cpi 为 1 时是跨程序调用
+CREATE TABLE calls (
+    caller TEXT NOT NULL REFERENCES functions(entry),
+    callee TEXT NOT NULL REFERENCES functions(entry),
+    cpi INTEGER NOT NULL,
+    PRIMARY KEY (caller, callee)
+);
+CREATE INDEX calls_callee ON calls(callee);
+
+-- agent report 的问题 (不含已修复和被抑制的)，列与 report.json 中的字段相同
+CREATE TABLE findings (
+    id INTEGER PRIMARY KEY,
+    rule TEXT NOT NULL,
+    stage TEXT,
+    level TEXT NOT NULL,
+    message TEXT NOT NULL,
+    class TEXT,
+    file_id INTEGER REFERENCES files(id),
+    line INTEGER,
+    subject TEXT,
+    fingerprint TEXT,
+    status TEXT
+);
+CREATE INDEX findings_rule ON findings(rule);
+CREATE INDEX findings_class ON findings(class);
+CREATE INDEX findings_location ON findings(file_id, line);
+
+-- 问题在跨语言图中的相关节点
+CREATE TABLE finding_nodes (
+    finding_id INTEGER NOT NULL REFERENCES findings(id),
+    node_id TEXT NOT NULL REFERENCES nodes(id),
+    PRIMARY KEY (finding_id, node_id)
+);
+CREATE INDEX finding_nodes_node ON finding_nodes(node_id);
+
+-- 问题的 CWE 编号
+CREATE TABLE finding_cwes (
+    finding_id INTEGER NOT NULL REFERENCES findings(id),
+    cwe INTEGER NOT NULL,
+    PRIMARY KEY (finding_id, cwe)
+);
+CREATE INDEX finding_cwes_cwe ON finding_cwes(cwe);
+
+-- 指令处理函数及其所在文件、被客户端调用的次数
+CREATE VIEW handlers AS
+SELECT f.instruction, f.name AS function, files.path AS file, f.start_line, f.end_line, f.entry,
+       (SELECT COUNT(*) FROM calls c JOIN functions caller ON caller.entry = c.caller
+         WHERE c.callee = f.entry AND caller.side = 'client') AS client_calls
+FROM functions f LEFT JOIN files ON files.id = f.file_id
+WHERE f.side = 'program' AND f.instruction IS NOT NULL;
+
+-- CPI：调用方函数、目标 (项目内的处理函数或外部程序的指令) 及目标所属的程序
+CREATE VIEW cpis AS
+SELECT caller.name AS caller, files.path AS file, caller.start_line AS line,
+       callee.name AS target, callee.side = 'external' AS external,
+       COALESCE(callee.program, substr(callee.instruction, 1, instr(callee.instruction, '::') - 1)) AS program,
+       c.caller AS caller_entry, c.callee AS target_entry
+FROM calls c
+JOIN functions caller ON caller.entry = c.caller
+JOIN functions callee ON callee.entry = c.callee
+LEFT JOIN files ON files.id = caller.file_id
+WHERE c.cpi = 1;
+
+-- 问题及其所在文件、CWE 编号和相关节点数
+CREATE VIEW finding_details AS
+SELECT f.id, f.rule, f.level, f.class,
+       (SELECT group_concat('CWE-' || cwe, ',') FROM finding_cwes WHERE finding_id = f.id) AS cwe,
+       files.path AS file, f.line, f.subject, f.message, f.status, f.fingerprint,
+       (SELECT COUNT(*) FROM finding_nodes WHERE finding_id = f.id) AS nodes
+FROM findings f LEFT JOIN files ON files.id = f.file_id;
+
+-- 每个MIR节点在三层中的对应：MIR节点、同源的 CFG 语句及其来源的语法节点、MIR节点本身最小的语法节点
+CREATE VIEW cross_layer AS
+SELECT m.id AS mir_node, m.label AS mir_label, p.cfg_node, p.cfg_statement,
+       c.ast_node AS cfg_ast_node, p.ast_node, p.ast_kind,
+       files.path AS file, p.start_line, p.end_line
+FROM provenance p
+JOIN nodes m ON m.id = p.node_id AND m.layer = 'mir'
+LEFT JOIN provenance c ON c.node_id = p.cfg_node AND c.statement IS p.cfg_statement
+LEFT JOIN files ON files.id = p.file_id;
+
+-- 问题的相关节点在源码中对应的语法结构，以及语法节点的父节点作为上下文
+CREATE VIEW finding_sources AS
+SELECT fn.finding_id, f.rule, fn.node_id, n.layer, p.statement, p.ast_node, p.ast_kind,
+       files.path AS file, p.start_line, p.end_line,
+       parent.kind AS parent_kind, parent.key AS parent_ast_node
+FROM finding_nodes fn
+JOIN findings f ON f.id = fn.finding_id
+JOIN nodes n ON n.id = fn.node_id
+JOIN provenance p ON p.node_id = fn.node_id
+LEFT JOIN files ON files.id = p.file_id
+LEFT JOIN ast_nodes a ON a.key = p.ast_node
+LEFT JOIN ast_nodes parent ON parent.id = a.parent_id;
+";
+
+/// `agent db` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct DbArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 agent.db；已存在时覆盖
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// 不导入 AST 的语法节点 (通常是数据库中最大的部分)
+    #[arg(long)]
+    no_ast: bool,
+
+    /// 只打印数据库的关系模式
+    #[arg(long)]
+    print_schema: bool,
+}
+
+/// 枚举值在 JSON 中的名字，用作 layer、kind 等列的值
+fn name_of<T: Serialize>(value: T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// 源文件表：按路径分配ID，并缓存源码用于把字节偏移换算为行号
+struct Files<'a> {
+    project: &'a Path,
+    ids: HashMap<PathBuf, i64>,
+    sources: HashMap<PathBuf, Option<String>>,
+}
+
+impl Files<'_> {
+    fn id(&mut self, tx: &Transaction, path: &Path) -> Result<i64, rusqlite::Error> {
+        if let Some(&id) = self.ids.get(path) {
+            return Ok(id);
+        }
+        tx.execute(
+            "INSERT INTO files (path, language) VALUES (?1, ?2)",
+            params![path.display().to_string(), language_of(path)],
+        )?;
+        let id = tx.last_insert_rowid();
+        self.ids.insert(path.to_path_buf(), id);
+        Ok(id)
+    }
+
+    /// 源码范围所在的起止行号；源码不可读时为 None
+    fn lines(&mut self, path: &Path, start: usize, end: usize) -> Lines {
+        let project = self.project;
+        let source = self
+            .sources
+            .entry(path.to_path_buf())
+            .or_insert_with(|| fs::read_to_string(project.join(path)).ok())
+            .as_deref()?;
+        Some((line_of(source, start), line_of(source, end)))
+    }
+}
+
+/// 各表写入的行数，用于日志
+#[derive(Default)]
+struct Counts {
+    ast_nodes: usize,
+    nodes: usize,
+    edges: usize,
+    provenance: usize,
+    functions: usize,
+    calls: usize,
+    findings: usize,
+}
+
+/// 递归写入一棵AST，返回写入的节点数
+fn insert_ast(
+    stmt: &mut Statement,
+    file: &Path,
+    file_id: i64,
+    parent_id: Option<i64>,
+    position: usize,
+    node: &AstNode,
+) -> Result<usize, rusqlite::Error> {
+    let text = node.children.is_empty().then_some(node.text.as_str());
+    // 与 provenance 表中的 ast_node 相同：优先使用AST中的节点ID
+    let node_id = if node.id.is_empty() {
+        ast_node_id(file, &node.kind, node.start_byte, node.end_byte)
+    } else {
+        node.id.clone()
+    };
+    let id = stmt.insert(params![
+        node_id,
+        file_id,
+        parent_id,
+        position,
+        node.kind,
+        node.start_byte,
+        node.end_byte,
+        text
+    ])?;
+    let mut count = 1;
+    for (i, child) in node.children.iter().enumerate() {
+        count += insert_ast(stmt, file, file_id, Some(id), i, child)?;
+    }
+    Ok(count)
+}
+
+/// 源码范围的起止行号
+type Lines = Option<(usize, usize)>;
+
+/// 节点的文件ID和起止行号
+fn location(
+    tx: &Transaction,
+    files: &mut Files,
+    node: &MergedNode,
+) -> Result<(Option<i64>, Lines), rusqlite::Error> {
+    let Some(span) = &node.span else {
+        return Ok((None, None));
+    };
+    let file_id = files.id(tx, &span.file)?;
+    Ok((
+        Some(file_id),
+        files.lines(&span.file, span.start_byte, span.end_byte),
+    ))
+}
+
+/// 写入图、调用图和问题
+fn populate(
+    tx: &Transaction,
+    project: &Path,
+    callgraph: &ProjectCallGraph,
+    findings: &[Value],
+    args: &DbArgs,
+) -> Result<Counts, Box<dyn Error>> {
+    let mut counts = Counts::default();
+    let mut files = Files {
+        project,
+        ids: HashMap::new(),
+        sources: HashMap::new(),
+    };
+
+    for (key, value) in [
+        ("tool", env!("CARGO_PKG_NAME").to_string()),
+        ("tool_version", env!("CARGO_PKG_VERSION").to_string()),
+        ("schema_version", SCHEMA_VERSION.to_string()),
+        ("generated_at", now_rfc3339()),
+        ("project", project.display().to_string()),
+    ] {
+        tx.execute(
+            "INSERT INTO metadata (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+    }
+
+    // AST
+    if !args.no_ast {
+        let mut stmt = tx.prepare(
+            "INSERT INTO ast_nodes (key, file_id, parent_id, position, kind, start_byte, end_byte, text) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?;
+        for (file, root) in &callgraph.asts {
+            let file_id = files.id(tx, file)?;
+            counts.ast_nodes += insert_ast(&mut stmt, file, file_id, None, 0, root)?;
+        }
+    }
+
+    // 跨语言图
+    let mut node_ids = HashSet::new();
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO nodes (id, layer, graph, function, kind, label, file_id, start_byte, end_byte, \
+             start_line, end_line, provenance, properties) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        )?;
+        for node in &callgraph.linked.nodes {
+            if !node_ids.insert(node.id.as_str()) {
+                continue;
+            }
+            let (file_id, lines) = location(tx, &mut files, node)?;
+            let properties = (!node.properties.is_empty())
+                .then(|| serde_json::to_string(&node.properties))
+                .transpose()?;
+            stmt.execute(params![
+                node.id,
+                name_of(node.layer),
+                graph_key(&node.id),
+                node.function,
+                name_of(node.kind),
+                node.label,
+                file_id,
+                node.span.as_ref().map(|s| s.start_byte),
+                node.span.as_ref().map(|s| s.end_byte),
+                lines.map(|(start, _)| start),
+                lines.map(|(_, end)| end),
+                node.provenance,
+                properties
+            ])?;
+            counts.nodes += 1;
+        }
+        let mut stmt =
+            tx.prepare("INSERT INTO edges (source, target, kind) VALUES (?1, ?2, ?3)")?;
+        for edge in &callgraph.linked.edges {
+            stmt.execute(params![edge.source, edge.target, name_of(edge.kind)])?;
+            counts.edges += 1;
+        }
+    }
+
+    // 跨层溯源
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO provenance (node_id, statement, cfg_node, cfg_statement, ast_node, ast_kind, file_id, \
+             start_byte, end_byte, start_line, end_line) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )?;
+        for entry in provenance(&callgraph.linked, &callgraph.asts) {
+            let file_id = files.id(tx, &entry.file)?;
+            let lines = files.lines(&entry.file, entry.start_byte, entry.end_byte);
+            stmt.execute(params![
+                entry.node,
+                entry.statement,
+                entry.cfg_node,
+                entry.cfg_statement,
+                entry.ast_node,
+                entry.ast_kind,
+                file_id,
+                entry.start_byte,
+                entry.end_byte,
+                lines.map(|(start, _)| start),
+                lines.map(|(_, end)| end)
+            ])?;
+            counts.provenance += 1;
+        }
+    }
+
+    // 函数级调用图
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO functions (entry, name, side, language, program, instruction, file_id, start_line, end_line) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        for node in &callgraph.graph.nodes {
+            let (file_id, lines) = location(tx, &mut files, node)?;
+            let property = |name: &str| node.properties.get(name).and_then(Value::as_str);
+            stmt.execute(params![
+                node.id,
+                node.function,
+                side(node),
+                property("language"),
+                property("program"),
+                property("instruction"),
+                file_id,
+                lines.map(|(start, _)| start),
+                lines.map(|(_, end)| end)
+            ])?;
+            counts.functions += 1;
+        }
+        let mut stmt = tx.prepare("INSERT INTO calls (caller, callee, cpi) VALUES (?1, ?2, ?3)")?;
+        for edge in &callgraph.graph.edges {
+            let cpi = callgraph
+                .cpi
+                .contains(&(edge.source.clone(), edge.target.clone()));
+            stmt.execute(params![edge.source, edge.target, cpi])?;
+            counts.calls += 1;
+        }
+    }
+
+    // agent report 的问题
+    {
+        let mut finding_stmt = tx.prepare(
+            "INSERT INTO findings (rule, stage, level, message, class, file_id, line, subject, fingerprint, status) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+        let mut node_stmt = tx
+            .prepare("INSERT OR IGNORE INTO finding_nodes (finding_id, node_id) VALUES (?1, ?2)")?;
+        let mut cwe_stmt =
+            tx.prepare("INSERT OR IGNORE INTO finding_cwes (finding_id, cwe) VALUES (?1, ?2)")?;
+        for finding in findings {
+            let field = |name: &str| finding.get(name).and_then(Value::as_str);
+            let file_id = field("file")
+                .map(|file| files.id(tx, Path::new(file)))
+                .transpose()?;
+            finding_stmt.execute(params![
+                field("rule").unwrap_or_default(),
+                field("stage"),
+                field("level").unwrap_or("warning"),
+                field("message").unwrap_or_default(),
+                field("class"),
+                file_id,
+                finding.get("line").and_then(Value::as_u64),
+                field("subject"),
+                field("fingerprint").filter(|f| !f.is_empty()),
+                field("status")
+            ])?;
+            let id = tx.last_insert_rowid();
+            let list = |name: &str| {
+                finding
+                    .get(name)
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+            };
+            for node in list("graph_nodes").filter_map(Value::as_str) {
+                node_stmt.execute(params![id, node])?;
+            }
+            for cwe in list("cwe").filter_map(Value::as_u64) {
+                cwe_stmt.execute(params![id, cwe])?;
+            }
+            counts.findings += 1;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// 把产物目录中的图和报告导入 SQLite 数据库
+pub fn run(args: &DbArgs) -> Result<(), Box<dyn Error>> {
+    if args.print_schema {
+        print!("{}", SCHEMA);
+        return Ok(());
+    }
+
+    let callgraph = project_callgraph(&args.artifacts)?;
+    let findings = load_findings(&callgraph.artifacts_dir)?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| callgraph.artifacts_dir.join(DB_FILE_NAME));
+    if output.exists() {
+        fs::remove_file(&output)
+            .map_err(|e| format!("无法覆盖数据库 '{}': {}", output.display(), e))?;
+    }
+
+    let mut conn = Connection::open(&output)
+        .map_err(|e| format!("无法创建数据库 '{}': {}", output.display(), e))?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    let counts = populate(&tx, &args.artifacts.project, &callgraph, &findings, args)?;
+    tx.commit()?;
+
+    info!(
+        output = %output.display(),
+        ast_nodes = counts.ast_nodes,
+        nodes = counts.nodes,
+        edges = counts.edges,
+        provenance = counts.provenance,
+        functions = counts.functions,
+        calls = counts.calls,
+        findings = counts.findings,
+        "已写出 SQLite 数据库"
+    );
+    Ok(())
+}
@@ -0,0 +1,362 @@
+// dead_code.rs
+//
+// agent dead-code：在整个项目的调用图 (见 callgraph.rs) 上从入口出发求可达性，列出到达不了的指令处理函数和程序函数
+// 入口：客户端中的所有函数 (客户端调用指令的地方)，以及程序中隐式调用的函数：测试、trait 实现和 trait 中的函数、
+// main 和 entrypoint!(..) 指定的原生程序入口；项目中没有客户端代码时所有处理函数也是入口，只检查程序内部的函数
+// 处理函数的状态：client (有客户端调用)、internal (只能经 CPI 或测试等程序内部的入口到达)、unreachable (到达不了)
+// 到达不了的函数如果能从到达不了的处理函数走到，在 via 中列出这些处理函数：删掉处理函数时它们也一并成为死代码
+// 调用按名字解析 (见 symbols.rs)，通过函数指针、宏或动态派发的调用看不到，结果需要人工确认
+
+use crate::callgraph::{project_callgraph, side};
+use crate::config::ArtifactsArgs;
+use crate::manifest::now_rfc3339;
+use crate::merge::{graph_key, MergedNode};
+use crate::symbols::{definition_name, line_of, AstNode};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const DEAD_CODE_FILE_NAME: &str = "dead_code.json";
+
+/// 总是作为入口的函数名
+const ENTRY_FUNCTIONS: &[&str] = &["main"];
+
+/// `agent dead-code` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct DeadCodeArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 dead_code.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 处理函数的可达性
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum HandlerStatus {
+    /// 有客户端调用
+    Client,
+    /// 只能经 CPI 或测试等程序内部的入口到达
+    Internal,
+    Unreachable,
+}
+
+/// 一个处理函数
+#[derive(Serialize, Debug)]
+struct Handler {
+    instruction: String,
+    /// 调用图中的节点
+    node: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    status: HandlerStatus,
+    /// 直接调用它的函数 (调用图中的节点)
+    callers: Vec<String>,
+}
+
+/// 一个到达不了的程序函数
+#[derive(Serialize, Debug)]
+struct DeadFunction {
+    function: String,
+    node: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    /// 能走到它的到达不了的处理函数 (指令)；为空表示没有任何处理函数能走到
+    via: Vec<String>,
+}
+
+/// dead_code.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct DeadCodeReport {
+    metadata: DeadCodeMetadata,
+    /// 入口函数的个数
+    entry_points: usize,
+    /// 项目中是否有客户端代码；没有时不检查处理函数
+    has_clients: bool,
+    /// 到达不了的指令
+    unreachable_handlers: Vec<String>,
+    handlers: Vec<Handler>,
+    dead_functions: Vec<DeadFunction>,
+}
+
+#[derive(Serialize, Debug)]
+struct DeadCodeMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// AST层函数图的键，与调用图节点的 graph_key 一致
+fn function_key(file: &Path, name: &str) -> String {
+    format!("ast:{}:{}", file.display(), name)
+}
+
+/// #[test]、#[tokio::test]、#[cfg(test)] 等
+fn is_test_attribute(text: &str) -> bool {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    text == "#[test]" || text.ends_with("::test]") || text.contains("cfg(test)")
+}
+
+/// entrypoint!(process_instruction) 中的函数名
+pub fn entrypoint_function(text: &str) -> Option<&str> {
+    let arguments = text.strip_prefix("entrypoint!")?.trim_start();
+    let name = arguments.strip_prefix('(')?.split(')').next()?.trim();
+    (!name.is_empty()).then_some(name)
+}
+
+/// 程序中隐式调用的函数；`implicit` 表示所在的项 (测试模块、trait 实现等) 中的函数都是入口
+fn collect_implicit_entries(
+    node: &AstNode,
+    file: &Path,
+    implicit: bool,
+    entries: &mut HashSet<String>,
+) {
+    let mut test = false;
+    for item in &node.children {
+        match item.kind.as_str() {
+            "attribute_item" => {
+                test |= is_test_attribute(&item.text);
+                continue;
+            }
+            "line_comment" | "block_comment" => continue,
+            "function_item" => {
+                if let Some(name) = definition_name(item) {
+                    if implicit || test || ENTRY_FUNCTIONS.contains(&name.text.as_str()) {
+                        entries.insert(function_key(file, &name.text));
+                    }
+                }
+            }
+            "macro_invocation" => {
+                if let Some(name) = entrypoint_function(&item.text) {
+                    entries.insert(function_key(file, name));
+                }
+            }
+            _ => {}
+        }
+        let inner = implicit
+            || test
+            || match item.kind.as_str() {
+                // impl Trait for T
+                "impl_item" => item.children.iter().any(|c| c.kind == "for"),
+                "trait_item" => true,
+                _ => false,
+            };
+        test = false;
+        collect_implicit_entries(item, file, inner, entries);
+    }
+}
+
+/// 从 `starts` 出发沿调用边可达的节点
+fn reachable(starts: &[usize], successors: &HashMap<usize, Vec<usize>>) -> HashSet<usize> {
+    let mut seen: HashSet<usize> = starts.iter().copied().collect();
+    let mut queue: VecDeque<usize> = starts.iter().copied().collect();
+    while let Some(current) = queue.pop_front() {
+        for &next in successors.get(&current).into_iter().flatten() {
+            if seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    seen
+}
+
+/// 节点所在的文件和行号
+fn location(
+    node: &MergedNode,
+    project: &Path,
+    sources: &mut HashMap<PathBuf, String>,
+) -> (Option<PathBuf>, Option<usize>) {
+    let Some(span) = &node.span else {
+        return (None, None);
+    };
+    let source = sources
+        .entry(span.file.clone())
+        .or_insert_with(|| fs::read_to_string(project.join(&span.file)).unwrap_or_default());
+    let line = (!source.is_empty()).then(|| line_of(source, span.start_byte));
+    (Some(span.file.clone()), line)
+}
+
+/// 找出到达不了的处理函数和程序函数，写出 dead_code.json
+pub fn run(args: &DeadCodeArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("dead_code")? {
+        return Ok(());
+    }
+    let callgraph = project_callgraph(&args.artifacts)?;
+    let graph = &callgraph.graph;
+
+    let mut implicit = HashSet::new();
+    for (file, root) in &callgraph.asts {
+        if file.extension().is_some_and(|ext| ext == "rs") {
+            collect_implicit_entries(root, file, false, &mut implicit);
+        }
+    }
+
+    let index: HashMap<&str, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.as_str(), i))
+        .collect();
+    let mut successors: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut predecessors: HashMap<usize, Vec<usize>> = HashMap::new();
+    for edge in &graph.edges {
+        if let (Some(&source), Some(&target)) = (
+            index.get(edge.source.as_str()),
+            index.get(edge.target.as_str()),
+        ) {
+            successors.entry(source).or_default().push(target);
+            predecessors.entry(target).or_default().push(source);
+        }
+    }
+
+    let instruction_of = |i: usize| {
+        graph.nodes[i]
+            .properties
+            .get("instruction")
+            .and_then(|ix| ix.as_str())
+            .filter(|_| side(&graph.nodes[i]) == "program")
+    };
+    let handlers: Vec<usize> = (0..graph.nodes.len())
+        .filter(|&i| instruction_of(i).is_some())
+        .collect();
+    let has_clients = graph.nodes.iter().any(|n| side(n) == "client");
+    let entries: Vec<usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(i, n)| {
+            side(n) == "client"
+                || implicit.contains(graph_key(&n.id))
+                || (!has_clients && handlers.contains(i))
+        })
+        .map(|(i, _)| i)
+        .collect();
+    let live = reachable(&entries, &successors);
+
+    let project = &args.artifacts.project;
+    let mut sources = HashMap::new();
+    let mut handler_items = vec![];
+    let mut unreachable_handlers = vec![];
+    for &i in &handlers {
+        let instruction = instruction_of(i).unwrap_or_default().to_string();
+        let callers: Vec<usize> = predecessors.get(&i).cloned().unwrap_or_default();
+        let status = if callers.iter().any(|&c| side(&graph.nodes[c]) == "client") {
+            HandlerStatus::Client
+        } else if live.contains(&i) {
+            HandlerStatus::Internal
+        } else {
+            HandlerStatus::Unreachable
+        };
+        if status == HandlerStatus::Unreachable {
+            unreachable_handlers.push(instruction.clone());
+        }
+        let (file, line) = location(&graph.nodes[i], project, &mut sources);
+        handler_items.push(Handler {
+            instruction,
+            node: graph.nodes[i].id.clone(),
+            file,
+            line,
+            status,
+            callers: callers
+                .iter()
+                .map(|&c| graph.nodes[c].id.clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+        });
+    }
+    handler_items.sort_by(|a, b| a.instruction.cmp(&b.instruction));
+    unreachable_handlers.sort();
+
+    // 到达不了的程序函数，及能走到它们的到达不了的处理函数
+    let mut via: HashMap<usize, BTreeSet<String>> = HashMap::new();
+    for &i in &handlers {
+        if live.contains(&i) {
+            continue;
+        }
+        let instruction = instruction_of(i).unwrap_or_default();
+        for reached in reachable(&[i], &successors) {
+            if reached != i {
+                via.entry(reached)
+                    .or_default()
+                    .insert(instruction.to_string());
+            }
+        }
+    }
+    let mut dead_functions = vec![];
+    for (i, node) in graph.nodes.iter().enumerate() {
+        if side(node) != "program" || live.contains(&i) || handlers.contains(&i) {
+            continue;
+        }
+        let (file, line) = location(node, project, &mut sources);
+        dead_functions.push(DeadFunction {
+            function: node.function.clone(),
+            node: node.id.clone(),
+            file,
+            line,
+            via: via.remove(&i).unwrap_or_default().into_iter().collect(),
+        });
+    }
+    dead_functions.sort_by(|a, b| (&a.file, a.line, &a.node).cmp(&(&b.file, b.line, &b.node)));
+
+    for handler in handler_items
+        .iter()
+        .filter(|h| h.status == HandlerStatus::Unreachable)
+    {
+        warn!(
+            instruction = %handler.instruction,
+            file = handler.file.as_ref().map(|f| f.display().to_string()),
+            line = handler.line,
+            "没有任何客户端调用或 CPI 能到达这条指令"
+        );
+    }
+    for function in &dead_functions {
+        warn!(
+            function = %function.function,
+            file = function.file.as_ref().map(|f| f.display().to_string()),
+            line = function.line,
+            via = ?function.via,
+            "从任何入口都到达不了这个函数"
+        );
+    }
+    if !has_clients {
+        info!("项目中没有客户端代码，处理函数都作为入口，不检查处理函数");
+    }
+
+    let report = DeadCodeReport {
+        metadata: DeadCodeMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        entry_points: entries.len(),
+        has_clients,
+        unreachable_handlers,
+        handlers: handler_items,
+        dead_functions,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| callgraph.artifacts_dir.join(DEAD_CODE_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        entry_points = report.entry_points,
+        handlers = report.handlers.len(),
+        unreachable_handlers = report.unreachable_handlers.len(),
+        dead_functions = report.dead_functions.len(),
+        output = %output.display(),
+        "已写出死代码检查结果"
+    );
+    Ok(())
+}
@@ -0,0 +1,309 @@
+// detect.rs
+//
+// agent detect：在合并图 (agent merge) 上执行规则文件中的 source/sink/guard 规则，写出 detect.json
+// 规则在运行时读入，不需要重新编译就可以共享和更新规则包；节点的写法与 agent query 的选择器相同 (见 query.rs)
+// 规则写在TOML文件中：
+//
+//     [[rules]]
+//     id = "cpi-without-signer-check"
+//     message = "CPI 之前没有检查签名者"
+//     source = "[kind=entry,layer=ast]"
+//     sink = "[label~invoke]"
+//     guard = ["[label~is_signer]"]
+//     edges = ["control_flow", "call"]
+//     class = "signer_authorization"
+//     cwe = [862]
+//
+// 从任意一个 source 节点沿 edges 中的边到达 sink 节点、且途中不经过 guard 节点时报告一次，附上最短的路径
+// source 默认为所有函数入口，edges 默认为控制流边和调用边；此时 guard 节点是否支配 sink 节点决定是否报告
+// class 和 cwe 可选，为问题的标准化分类 (见 taxonomy.rs)，随发现写出，agent report 据此分类
+
+use crate::config::ArtifactsArgs;
+use crate::graph::EdgeKind;
+use crate::manifest::now_rfc3339;
+use crate::merge::{load_merged, MergedGraph};
+use crate::query::{parse_edge_kind, parse_selector, Selector};
+use crate::symbols::line_of;
+use crate::taxonomy::VulnClass;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const DETECT_FILE_NAME: &str = "detect.json";
+
+/// 规则没有给出 source 时使用的选择器
+const DEFAULT_SOURCE: &str = "[kind=entry]";
+
+/// 规则没有给出 edges 时沿哪些边搜索
+const DEFAULT_EDGES: &[EdgeKind] = &[EdgeKind::ControlFlow, EdgeKind::Call];
+
+/// `agent detect` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct DetectArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 规则文件 (TOML，可重复)
+    #[arg(long = "rules", value_name = "FILE", required = true)]
+    rules: Vec<PathBuf>,
+
+    /// 输出文件，默认为产物目录下的 detect.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 规则文件的顶层结构
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    rules: Vec<Rule>,
+}
+
+/// 一条规则
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    id: String,
+    message: String,
+    source: Option<String>,
+    sink: String,
+    #[serde(default)]
+    guard: Vec<String>,
+    edges: Option<Vec<String>>,
+    #[serde(default)]
+    class: Option<VulnClass>,
+    #[serde(default)]
+    cwe: Vec<u32>,
+}
+
+/// 解析后的规则
+struct CompiledRule {
+    id: String,
+    message: String,
+    source: Selector,
+    sink: Selector,
+    guard: Vec<Selector>,
+    edges: Vec<EdgeKind>,
+    class: Option<VulnClass>,
+    cwe: Vec<u32>,
+}
+
+/// 一处发现
+#[derive(Serialize, Debug)]
+struct Detection {
+    rule: String,
+    message: String,
+    /// sink 节点
+    node: String,
+    function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    /// 从 source 节点到 sink 节点的节点ID
+    path: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class: Option<VulnClass>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cwe: Vec<u32>,
+}
+
+/// detect.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct DetectReport {
+    metadata: DetectMetadata,
+    rules: Vec<String>,
+    detections: Vec<Detection>,
+}
+
+#[derive(Serialize, Debug)]
+struct DetectMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 解析一个完整的选择器，不允许末尾有多余的内容
+fn selector(rule: &str, text: &str) -> Result<Selector, String> {
+    let (selector, rest) =
+        parse_selector(text).map_err(|e| format!("规则 '{}' 的选择器有误: {}", rule, e))?;
+    if !rest.trim().is_empty() {
+        return Err(format!(
+            "规则 '{}' 的选择器末尾有多余的内容: {}",
+            rule,
+            rest.trim()
+        ));
+    }
+    Ok(selector)
+}
+
+impl CompiledRule {
+    fn new(rule: Rule) -> Result<Self, String> {
+        let source = selector(&rule.id, rule.source.as_deref().unwrap_or(DEFAULT_SOURCE))?;
+        let sink = selector(&rule.id, &rule.sink)?;
+        let guard = rule
+            .guard
+            .iter()
+            .map(|g| selector(&rule.id, g))
+            .collect::<Result<_, _>>()?;
+        let edges = match &rule.edges {
+            Some(edges) => edges
+                .iter()
+                .map(|e| parse_edge_kind(e))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("规则 '{}': {}", rule.id, e))?,
+            None => DEFAULT_EDGES.to_vec(),
+        };
+        Ok(CompiledRule {
+            id: rule.id,
+            message: rule.message,
+            source,
+            sink,
+            guard,
+            edges,
+            class: rule.class,
+            cwe: rule.cwe,
+        })
+    }
+}
+
+/// 读取规则文件
+fn load_rules(path: &Path) -> Result<Vec<Rule>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("无法读取规则文件 '{}': {}", path.display(), e))?;
+    let file: RuleFile = toml::from_str(&content)
+        .map_err(|e| format!("规则文件 '{}' 格式错误: {}", path.display(), e))?;
+    Ok(file.rules)
+}
+
+/// 从所有 source 节点同时做广度优先搜索，不穿过 guard 节点，返回到达每个 sink 节点的最短路径
+fn evaluate(
+    graph: &MergedGraph,
+    successors: &HashMap<usize, Vec<(EdgeKind, usize)>>,
+    rule: &CompiledRule,
+) -> Vec<Vec<usize>> {
+    let guarded = |i: usize| rule.guard.iter().any(|g| g.matches(&graph.nodes[i]));
+    let mut parent: HashMap<usize, Option<usize>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for start in (0..graph.nodes.len()).filter(|&i| rule.source.matches(&graph.nodes[i])) {
+        parent.insert(start, None);
+        queue.push_back(start);
+    }
+
+    let mut paths = vec![];
+    while let Some(current) = queue.pop_front() {
+        if guarded(current) {
+            continue;
+        }
+        if rule.sink.matches(&graph.nodes[current]) {
+            let mut path = vec![current];
+            while let Some(&Some(p)) = parent.get(path.last().unwrap()) {
+                path.push(p);
+            }
+            path.reverse();
+            paths.push(path);
+        }
+        for (kind, next) in successors.get(&current).into_iter().flatten() {
+            if !rule.edges.contains(kind) {
+                continue;
+            }
+            if let Entry::Vacant(entry) = parent.entry(*next) {
+                entry.insert(Some(current));
+                queue.push_back(*next);
+            }
+        }
+    }
+    paths
+}
+
+/// 读入规则并在合并图上执行，写出 detect.json
+pub fn run(args: &DetectArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("detect")? {
+        return Ok(());
+    }
+    // 先检查规则，避免在读入图之后才报错
+    let mut rules = vec![];
+    for path in &args.rules {
+        for rule in load_rules(path)? {
+            rules.push(CompiledRule::new(rule)?);
+        }
+    }
+    let (artifacts_dir, graph) = load_merged(&args.artifacts)?;
+
+    let index: HashMap<&str, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.as_str(), i))
+        .collect();
+    let mut successors: HashMap<usize, Vec<(EdgeKind, usize)>> = HashMap::new();
+    for edge in &graph.edges {
+        if let (Some(&s), Some(&t)) = (
+            index.get(edge.source.as_str()),
+            index.get(edge.target.as_str()),
+        ) {
+            successors.entry(s).or_default().push((edge.kind, t));
+        }
+    }
+
+    let mut sources: HashMap<PathBuf, String> = HashMap::new();
+    let mut detections = vec![];
+    for rule in &rules {
+        let paths = evaluate(&graph, &successors, rule);
+        debug!(rule = %rule.id, detections = paths.len(), "已执行规则");
+        for path in paths {
+            let sink = &graph.nodes[*path.last().unwrap()];
+            let line = sink.span.as_ref().map(|span| {
+                let source = sources.entry(span.file.clone()).or_insert_with(|| {
+                    fs::read_to_string(args.artifacts.project.join(&span.file)).unwrap_or_default()
+                });
+                line_of(source, span.start_byte)
+            });
+            warn!(
+                rule = %rule.id,
+                node = %sink.id,
+                line = ?line,
+                "{}",
+                rule.message
+            );
+            detections.push(Detection {
+                rule: rule.id.clone(),
+                message: rule.message.clone(),
+                node: sink.id.clone(),
+                function: sink.function.clone(),
+                file: sink.span.as_ref().map(|span| span.file.clone()),
+                line,
+                path: path.iter().map(|&i| graph.nodes[i].id.clone()).collect(),
+                class: rule.class,
+                cwe: rule.cwe.clone(),
+            });
+        }
+    }
+
+    let report = DetectReport {
+        metadata: DetectMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        rules: rules.iter().map(|r| r.id.clone()).collect(),
+        detections,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(DETECT_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        rules = report.rules.len(),
+        detections = report.detections.len(),
+        output = %output.display(),
+        "已写出规则检测结果"
+    );
+    Ok(())
+}
@@ -0,0 +1,424 @@
+// events.rs
+//
+// agent events：提取 #[event] 结构体、emit!/emit_cpi! 的位置以及客户端中的 program.addEventListener("<事件>", ..)，
+// 按调用图把 emit 归属到指令，写出 events.json 和事件流图 events.dot (指令 -> 事件 -> 监听者)
+// 客户端中的事件名按 snake_case 与结构体名比较，因此 "feeChanged" 与 FeeChanged 视为同一个事件
+// 会写账户 (账户结构体中有 mut 或 init 字段) 的指令视为修改状态，这类指令不触发任何事件时给出警告
+
+use crate::config::ArtifactsArgs;
+use crate::idl::{collect_accounts_structs, collect_programs, snake_case};
+use crate::manifest::now_rfc3339;
+use crate::symbols::{child, definition_name, line_of, load_asts, normalize, AstNode, CallGraph};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const EVENTS_FILE_NAME: &str = "events.json";
+
+/// 事件流图的文件名，与 events.json 位于同一目录
+const EVENTS_DOT_FILE_NAME: &str = "events.dot";
+
+/// 触发事件的宏
+const EMIT_MACROS: &[&str] = &["emit", "emit_cpi"];
+
+/// `agent events` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct EventsArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 events.json；events.dot 写在同一目录
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 一个 #[event] 结构体
+#[derive(Serialize, Debug)]
+struct EventDef {
+    name: String,
+    file: PathBuf,
+    line: usize,
+    fields: Vec<EventField>,
+    /// 触发该事件的指令
+    emitted_by: BTreeSet<String>,
+    /// 顶层 listeners 中监听该事件的下标
+    listeners: Vec<usize>,
+}
+
+#[derive(Serialize, Debug)]
+struct EventField {
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+/// 一处 emit!/emit_cpi!
+#[derive(Serialize, Debug)]
+struct EmitSite {
+    event: String,
+    #[serde(rename = "macro")]
+    macro_name: String,
+    file: PathBuf,
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function: Option<String>,
+    instructions: BTreeSet<String>,
+}
+
+/// 客户端中的一处 addEventListener
+#[derive(Serialize, Debug)]
+struct Listener {
+    /// 客户端代码中写的事件名
+    event: String,
+    file: PathBuf,
+    line: usize,
+    code: String,
+}
+
+/// 一条指令触发的事件
+#[derive(Serialize, Debug)]
+struct HandlerEvents {
+    instruction: String,
+    file: PathBuf,
+    line: usize,
+    /// 是否写账户
+    state_changing: bool,
+    events: BTreeSet<String>,
+}
+
+/// 问题的种类
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum FindingKind {
+    /// 修改状态的指令不触发任何事件
+    StateChangeWithoutEvent,
+    /// 客户端监听的事件没有任何指令触发
+    ListenerWithoutEmit,
+    /// 定义了事件但从未触发
+    EventNeverEmitted,
+}
+
+#[derive(Serialize, Debug)]
+struct Finding {
+    kind: FindingKind,
+    /// 指令、事件名或监听者所在位置
+    subject: String,
+    message: String,
+}
+
+/// events.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct EventsReport {
+    metadata: EventsMetadata,
+    events: Vec<EventDef>,
+    emits: Vec<EmitSite>,
+    listeners: Vec<Listener>,
+    handlers: Vec<HandlerEvents>,
+    findings: Vec<Finding>,
+}
+
+#[derive(Serialize, Debug)]
+struct EventsMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 收集 #[event] 结构体和 emit 位置；`function` 为所在的函数
+fn visit_rust(
+    node: &AstNode,
+    file: &Path,
+    source: &str,
+    function: Option<&str>,
+    events: &mut Vec<EventDef>,
+    emits: &mut Vec<EmitSite>,
+) {
+    let mut is_event = false;
+    for item in &node.children {
+        match item.kind.as_str() {
+            "struct_item" if is_event => {
+                let fields = child(item, "field_declaration_list")
+                    .into_iter()
+                    .flat_map(|list| &list.children)
+                    .filter(|f| f.kind == "field_declaration")
+                    .map(|f| EventField {
+                        name: child(f, "field_identifier")
+                            .map_or_else(String::new, |n| n.text.clone()),
+                        ty: f
+                            .children
+                            .last()
+                            .map_or_else(String::new, |t| normalize(&t.text)),
+                    })
+                    .collect();
+                if let Some(name) = definition_name(item) {
+                    events.push(EventDef {
+                        name: name.text.clone(),
+                        file: file.to_path_buf(),
+                        line: line_of(source, item.start_byte),
+                        fields,
+                        emitted_by: BTreeSet::new(),
+                        listeners: vec![],
+                    });
+                }
+            }
+            "macro_invocation" => {
+                let name = child(item, "identifier").map(|n| n.text.as_str());
+                if let Some(name) = name.filter(|n| EMIT_MACROS.contains(n)) {
+                    // emit!(path::Event { .. }) 中第一个 {} 之前的最后一个标识符
+                    let event = child(item, "token_tree").and_then(|tree| {
+                        tree.children
+                            .iter()
+                            .take_while(|c| c.kind != "token_tree")
+                            .filter(|c| c.kind == "identifier")
+                            .last()
+                    });
+                    if let Some(event) = event {
+                        emits.push(EmitSite {
+                            event: event.text.clone(),
+                            macro_name: name.to_string(),
+                            file: file.to_path_buf(),
+                            line: line_of(source, item.start_byte),
+                            function: function.map(str::to_string),
+                            instructions: BTreeSet::new(),
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+        // #[event] 与结构体之间可能还有其他属性
+        is_event =
+            item.kind == "attribute_item" && (is_event || normalize(&item.text) == "#[event]");
+        let function = match item.kind.as_str() {
+            "function_item" => definition_name(item).map(|n| n.text.as_str()),
+            _ => function,
+        };
+        visit_rust(item, file, source, function, events, emits);
+    }
+}
+
+/// 客户端代码中的 <program>.addEventListener("<事件>", ..)
+fn visit_client(node: &AstNode, file: &Path, source: &str, listeners: &mut Vec<Listener>) {
+    for item in &node.children {
+        if item.kind == "call_expression" {
+            let callee = item
+                .children
+                .first()
+                .filter(|c| c.kind == "member_expression");
+            let method = callee.and_then(|c| child(c, "property_identifier"));
+            let event = child(item, "arguments")
+                .and_then(|args| child(args, "string"))
+                .and_then(|s| child(s, "string_fragment"));
+            if let (Some(method), Some(event)) = (method, event) {
+                if method.text == "addEventListener" {
+                    listeners.push(Listener {
+                        event: event.text.clone(),
+                        file: file.to_path_buf(),
+                        line: line_of(source, item.start_byte),
+                        code: normalize(callee.map_or("", |c| c.text.as_str())),
+                    });
+                }
+            }
+        }
+        visit_client(item, file, source, listeners);
+    }
+}
+
+/// 事件流图：指令为方框，事件为椭圆 (没有定义的事件用虚线)，监听者为便签
+fn to_dot(report: &EventsReport) -> String {
+    let mut dot = String::from("digraph events {\n  rankdir=LR;\n");
+    let mut listened: BTreeMap<String, Vec<&Listener>> = BTreeMap::new();
+    for listener in &report.listeners {
+        listened
+            .entry(snake_case(&listener.event))
+            .or_default()
+            .push(listener);
+    }
+    for event in &report.events {
+        let _ = writeln!(dot, "  {:?} [shape=ellipse];", event.name);
+    }
+    for handler in &report.handlers {
+        let _ = writeln!(dot, "  {:?} [shape=box];", handler.instruction);
+        for event in &handler.events {
+            let _ = writeln!(dot, "  {:?} -> {:?};", handler.instruction, event);
+        }
+    }
+    for (key, listeners) in &listened {
+        let event = report
+            .events
+            .iter()
+            .find(|e| snake_case(&e.name) == *key)
+            .map(|e| e.name.clone());
+        let event = event.unwrap_or_else(|| {
+            let name = listeners[0].event.clone();
+            let _ = writeln!(dot, "  {:?} [shape=ellipse, style=dashed];", name);
+            name
+        });
+        for listener in listeners {
+            let id = format!("{}:{}", listener.file.display(), listener.line);
+            let _ = writeln!(dot, "  {:?} [shape=note];", id);
+            let _ = writeln!(dot, "  {:?} -> {:?};", event, id);
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// 提取事件、触发位置和客户端监听者，写出 events.json 和 events.dot
+pub fn run(args: &EventsArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("events")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut events = vec![];
+    let mut emits = vec![];
+    let mut listeners = vec![];
+    let mut calls = CallGraph::default();
+    let mut programs = vec![];
+    let mut accounts_structs = HashMap::new();
+    for (file, root) in &asts {
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        match file.extension().and_then(|ext| ext.to_str()) {
+            Some("rs") => {
+                visit_rust(root, file, &source, None, &mut events, &mut emits);
+                calls.add_file(file, root);
+                collect_programs(root, file, &source, &mut programs);
+                collect_accounts_structs(root, &mut accounts_structs);
+            }
+            Some("ts" | "tsx" | "js" | "jsx" | "mjs" | "cjs") => {
+                visit_client(root, file, &source, &mut listeners)
+            }
+            _ => {}
+        }
+    }
+
+    // (文件, 函数名) -> 在其中的 emit
+    let mut by_function: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+    for (index, emit) in emits.iter().enumerate() {
+        if let Some(function) = &emit.function {
+            by_function
+                .entry((emit.file.clone(), function.clone()))
+                .or_default()
+                .push(index);
+        }
+    }
+    let mut handlers = vec![];
+    let mut findings = vec![];
+    for program in &programs {
+        for (handler, _) in &program.handlers {
+            let instruction = format!("{}::{}", program.name, handler.function);
+            let mut emitted = BTreeSet::new();
+            for key in calls.reachable(&handler.file, &handler.function) {
+                for &index in by_function.get(&key).into_iter().flatten() {
+                    emits[index].instructions.insert(instruction.clone());
+                    emitted.insert(emits[index].event.clone());
+                }
+            }
+            let state_changing = handler
+                .accounts_struct
+                .as_ref()
+                .and_then(|s| accounts_structs.get(s))
+                .is_some_and(|accounts| accounts.iter().any(|a| a.writable));
+            if state_changing && emitted.is_empty() {
+                warn!(instruction = %instruction, "指令修改了状态，但不触发任何事件");
+                findings.push(Finding {
+                    kind: FindingKind::StateChangeWithoutEvent,
+                    subject: instruction.clone(),
+                    message: "指令写账户，但不触发任何事件".to_string(),
+                });
+            }
+            handlers.push(HandlerEvents {
+                instruction,
+                file: handler.file.clone(),
+                line: handler.line,
+                state_changing,
+                events: emitted,
+            });
+        }
+    }
+
+    for event in &mut events {
+        event.emitted_by = emits
+            .iter()
+            .filter(|e| e.event == event.name)
+            .flat_map(|e| e.instructions.iter().cloned())
+            .collect();
+        event.listeners = listeners
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| snake_case(&l.event) == snake_case(&event.name))
+            .map(|(i, _)| i)
+            .collect();
+        if event.emitted_by.is_empty() {
+            warn!(event = %event.name, file = %event.file.display(), line = event.line, "事件没有被任何指令触发");
+            findings.push(Finding {
+                kind: FindingKind::EventNeverEmitted,
+                subject: event.name.clone(),
+                message: "定义了事件，但没有指令会触发它".to_string(),
+            });
+        }
+    }
+    for listener in &listeners {
+        let emitted = events.iter().any(|e| {
+            snake_case(&e.name) == snake_case(&listener.event) && !e.emitted_by.is_empty()
+        });
+        if !emitted {
+            warn!(
+                event = %listener.event,
+                file = %listener.file.display(),
+                line = listener.line,
+                "客户端监听的事件没有任何指令触发"
+            );
+            findings.push(Finding {
+                kind: FindingKind::ListenerWithoutEmit,
+                subject: format!("{}:{}", listener.file.display(), listener.line),
+                message: format!("监听事件 {}，但没有指令触发它", listener.event),
+            });
+        }
+    }
+
+    let report = EventsReport {
+        metadata: EventsMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        events,
+        emits,
+        listeners,
+        handlers,
+        findings,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(EVENTS_FILE_NAME));
+    let dot_output = output.with_file_name(EVENTS_DOT_FILE_NAME);
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    fs::write(&dot_output, to_dot(&report))?;
+    info!(
+        events = report.events.len(),
+        emits = report.emits.len(),
+        listeners = report.listeners.len(),
+        findings = report.findings.len(),
+        output = %output.display(),
+        dot = %dot_output.display(),
+        "已写出事件流"
+    );
+    Ok(())
+}
@@ -0,0 +1,250 @@
+// features.rs
+//
+// 节点特征提取：把合并图中的每个节点映射为一个定长的数值向量
+// AST层和MIR层的节点使用同一套特征，agent dataset 和 agent merge --emit-features 都通过这里提取
+// 节点所在函数的CU估算取自 agent analyze 写出的 cu.json (见 cu.rs)
+
+use crate::config::FeaturesConfig;
+use crate::cu::CuEstimates;
+use crate::graph::{EdgeKind, Layer, NodeKind};
+use crate::merge::{called_names, graph_key, MergedGraph};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::Path;
+use tracing::warn;
+
+/// 默认的节点文本特征哈希维数
+const DEFAULT_HASH_DIM: usize = 64;
+
+/// 节点种类的 one-hot 特征顺序
+const NODE_KINDS: [NodeKind; 5] = [
+    NodeKind::Entry,
+    NodeKind::Exit,
+    NodeKind::BasicBlock,
+    NodeKind::Statement,
+    NodeKind::Terminator,
+];
+
+/// 类型类别及其对应的词，节点文本中出现任意一个词时该类别记为 1
+const TYPE_CLASSES: &[(&str, &[&str])] = &[
+    (
+        "integer",
+        &[
+            "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+        ],
+    ),
+    ("bool", &["bool", "true", "false"]),
+    ("pubkey", &["Pubkey"]),
+    (
+        "account",
+        &[
+            "AccountInfo",
+            "Account",
+            "AccountLoader",
+            "UncheckedAccount",
+            "accounts",
+        ],
+    ),
+    ("signer", &["Signer", "is_signer"]),
+    ("cpi", &["invoke", "invoke_signed", "CpiContext", "Program"]),
+];
+
+/// 可以选择的特征组
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum FeatureGroup {
+    /// 节点种类 one-hot
+    Kind,
+    /// 所在层 (ast/mir) one-hot
+    Layer,
+    /// ln(1 + 节点文本长度)
+    Length,
+    /// 节点文本中各个词的哈希计数
+    Tokens,
+    /// 节点文本涉及的类型类别 (整数、Pubkey、账户、签名者、CPI)
+    TypeClass,
+    /// 是否包含调用、是否为分支
+    Flags,
+    /// ln(1 + 节点所在函数的最坏路径CU估算)，没有估算时为 0
+    ComputeUnits,
+}
+
+impl FeatureGroup {
+    const ALL: [FeatureGroup; 7] = [
+        FeatureGroup::Kind,
+        FeatureGroup::Layer,
+        FeatureGroup::Length,
+        FeatureGroup::Tokens,
+        FeatureGroup::TypeClass,
+        FeatureGroup::Flags,
+        FeatureGroup::ComputeUnits,
+    ];
+}
+
+/// 特征相关的命令行参数，优先于 agent.toml 中的 [features]
+#[derive(clap::Args, Debug, Default)]
+pub struct FeatureArgs {
+    /// 要提取的特征组 (逗号分隔)，默认提取全部
+    #[arg(long = "features", value_enum, value_delimiter = ',')]
+    groups: Vec<FeatureGroup>,
+
+    /// 节点文本的特征哈希维数
+    #[arg(long, value_name = "N")]
+    hash_dim: Option<usize>,
+}
+
+/// 按配置提取节点特征
+pub struct Featurizer {
+    groups: Vec<FeatureGroup>,
+    hash_dim: usize,
+}
+
+/// FNV-1a 哈希，保证同一个词在不同运行之间落在同一个特征维上
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// 节点文本中的词
+fn tokens(label: &str) -> impl Iterator<Item = &str> {
+    label
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|t| !t.is_empty())
+}
+
+impl Featurizer {
+    pub fn new(args: &FeatureArgs, config: &FeaturesConfig) -> Self {
+        let groups = if !args.groups.is_empty() {
+            args.groups.clone()
+        } else if !config.groups.is_empty() {
+            config.groups.clone()
+        } else {
+            FeatureGroup::ALL.to_vec()
+        };
+        Featurizer {
+            groups,
+            hash_dim: args
+                .hash_dim
+                .or(config.hash_dim)
+                .unwrap_or(DEFAULT_HASH_DIM),
+        }
+    }
+
+    /// 特征各维的名字
+    pub fn names(&self) -> Vec<String> {
+        let mut names = vec![];
+        for group in &self.groups {
+            match group {
+                FeatureGroup::Kind => names.extend(NODE_KINDS.iter().map(|k| {
+                    format!(
+                        "kind={}",
+                        serde_json::to_value(k)
+                            .unwrap_or_default()
+                            .as_str()
+                            .unwrap_or("")
+                    )
+                })),
+                FeatureGroup::Layer => names.extend(["layer=ast", "layer=mir"].map(String::from)),
+                FeatureGroup::Length => names.push("ln_label_len".to_string()),
+                FeatureGroup::Tokens => {
+                    names.extend((0..self.hash_dim).map(|i| format!("token_hash[{}]", i)))
+                }
+                FeatureGroup::TypeClass => names.extend(
+                    TYPE_CLASSES
+                        .iter()
+                        .map(|(class, _)| format!("type={}", class)),
+                ),
+                FeatureGroup::Flags => names.extend(["is_call", "is_branch"].map(String::from)),
+                FeatureGroup::ComputeUnits => names.push("ln_function_cu".to_string()),
+            }
+        }
+        names
+    }
+
+    /// 特征向量的维数
+    pub fn dim(&self) -> usize {
+        self.names().len()
+    }
+
+    /// 产物目录中 cu.json 的各函数估算，供 featurize 使用；特征中不含CU或没有 cu.json 时为空
+    pub fn load_compute_units(
+        &self,
+        artifacts_dir: &Path,
+    ) -> Result<BTreeMap<String, u64>, Box<dyn Error>> {
+        if !self.groups.contains(&FeatureGroup::ComputeUnits) {
+            return Ok(BTreeMap::new());
+        }
+        match CuEstimates::load(artifacts_dir)? {
+            Some(estimates) => Ok(estimates.functions),
+            None => {
+                warn!(
+                    dir = %artifacts_dir.display(),
+                    "没有 cu.json，CU特征为 0，请先运行 agent analyze"
+                );
+                Ok(BTreeMap::new())
+            }
+        }
+    }
+
+    /// 提取图中所有节点的特征，按 graph.nodes 的顺序逐行排列
+    /// compute_units 为 cu.json 中各函数的估算，键为函数图的键
+    pub fn featurize(
+        &self,
+        graph: &MergedGraph,
+        compute_units: &BTreeMap<String, u64>,
+    ) -> Vec<f32> {
+        // 分支：控制流出边多于一条的节点
+        let mut successors: HashMap<&str, usize> = HashMap::new();
+        for edge in graph
+            .edges
+            .iter()
+            .filter(|e| e.kind == EdgeKind::ControlFlow)
+        {
+            *successors.entry(edge.source.as_str()).or_default() += 1;
+        }
+
+        let mut x = Vec::with_capacity(graph.nodes.len() * self.dim());
+        for node in &graph.nodes {
+            for group in &self.groups {
+                match group {
+                    FeatureGroup::Kind => {
+                        x.extend(NODE_KINDS.map(|k| (k == node.kind) as u8 as f32))
+                    }
+                    FeatureGroup::Layer => x.extend([
+                        (node.layer == Layer::Ast) as u8 as f32,
+                        (node.layer == Layer::Mir) as u8 as f32,
+                    ]),
+                    FeatureGroup::Length => x.push((node.label.chars().count() as f32).ln_1p()),
+                    FeatureGroup::Tokens => {
+                        let mut counts = vec![0.0; self.hash_dim];
+                        if self.hash_dim > 0 {
+                            for token in tokens(&node.label) {
+                                let bucket =
+                                    (fnv1a(&token.to_lowercase()) % self.hash_dim as u64) as usize;
+                                counts[bucket] += 1.0;
+                            }
+                        }
+                        x.extend(counts);
+                    }
+                    FeatureGroup::TypeClass => x.extend(TYPE_CLASSES.iter().map(|(_, words)| {
+                        tokens(&node.label).any(|t| words.contains(&t)) as u8 as f32
+                    })),
+                    FeatureGroup::Flags => x.extend([
+                        !called_names(&node.label).is_empty() as u8 as f32,
+                        (successors.get(node.id.as_str()).copied().unwrap_or(0) > 1) as u8 as f32,
+                    ]),
+                    FeatureGroup::ComputeUnits => x.push(
+                        compute_units
+                            .get(graph_key(&node.id))
+                            .map_or(0.0, |&cu| (cu as f32).ln_1p()),
+                    ),
+                }
+            }
+        }
+        x
+    }
+}
@@ -0,0 +1,343 @@
+// fetch.rs
+//
+// agent fetch：取得第三方程序公开的源码，并在其上运行完整的分析流程 (analyze、各项检查、report、dashboard)
+// --program-id：向 OtterSec 的验证构建服务 (verify.osec.io) 查询链上程序的验证记录，克隆其中的仓库并检出构建所用的提交；
+//   没有通过验证的程序只在 --allow-unverified 时分析，此时源码不一定与链上的程序一致
+// --crate <name>[@<version>]：从 crates.io 下载发布的源码包并解压；不给版本时取最新的稳定版本
+// 源码放在 <输出目录>/source，产物放在 <输出目录>/agent-out，来源记录在 <输出目录>/fetch.json
+// 下载通过 curl、克隆通过 git 完成，两者都需要在 PATH 中
+
+use crate::history::{analyze_revision, DEFAULT_CHECKS};
+use crate::manifest::now_rfc3339;
+use crate::scope::{check_commit, check_git_arg, git_lines};
+use crate::LogOptions;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, info, warn};
+
+/// 来源记录的文件名，位于输出目录下
+const FETCH_FILE_NAME: &str = "fetch.json";
+
+/// 输出目录下源码和产物的子目录
+const SOURCE_DIR: &str = "source";
+const ARTIFACTS_DIR: &str = "agent-out";
+
+/// 默认的输出目录，其下每个程序或 crate 一个子目录
+const DEFAULT_OUTPUT_DIR: &str = "fetched";
+
+/// 验证构建服务和 crates.io 的默认地址
+const DEFAULT_VERIFY_API: &str = "https://verify.osec.io";
+const CRATES_IO_API: &str = "https://crates.io/api/v1/crates";
+const CRATES_IO_DOWNLOAD: &str = "https://static.crates.io/crates";
+
+/// `agent fetch` 的命令行参数
+#[derive(clap::Args, Debug)]
+#[command(group(clap::ArgGroup::new("source").required(true).args(["program_id", "crate_name"])))]
+pub struct FetchArgs {
+    /// 链上程序的地址，按验证构建记录取得源码
+    #[arg(long, value_name = "PUBKEY")]
+    program_id: Option<String>,
+
+    /// crates.io 上的 crate，可以带版本，例如 spl-token@4.0.0
+    #[arg(long = "crate", value_name = "NAME[@VERSION]")]
+    crate_name: Option<String>,
+
+    /// 输出目录，默认为 fetched/<程序地址> 或 fetched/<crate>-<版本>
+    #[arg(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+
+    /// 验证构建服务的地址
+    #[arg(long, value_name = "URL", default_value = DEFAULT_VERIFY_API)]
+    verify_api: String,
+
+    /// 程序没有通过验证时仍然克隆记录中的仓库并分析
+    #[arg(long)]
+    allow_unverified: bool,
+
+    /// 只取得源码，不运行分析
+    #[arg(long)]
+    no_analyze: bool,
+
+    /// 运行的检查 (agent 的子命令，逗号分隔)，默认为只需要产物的全部检查
+    #[arg(long, value_name = "CMD", value_delimiter = ',')]
+    checks: Vec<String>,
+
+    /// 分析使用的配置文件，默认为源码中的 agent.toml (没有时使用默认配置)
+    #[arg(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+}
+
+/// 源码的来源
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Origin {
+    /// 链上程序的验证构建
+    Program {
+        program_id: String,
+        verified: bool,
+        repo_url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        commit: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        on_chain_hash: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        executable_hash: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        last_verified_at: Option<String>,
+    },
+    /// crates.io 上发布的 crate
+    Crate {
+        name: String,
+        version: String,
+        url: String,
+    },
+}
+
+/// fetch.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct FetchRecord {
+    metadata: FetchMetadata,
+    origin: Origin,
+    /// 源码和产物目录
+    source: PathBuf,
+    artifacts: PathBuf,
+    /// 是否运行了分析，以及失败的步骤
+    analyzed: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failed: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct FetchMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 用 curl 下载；给出 `output` 时写入文件，否则返回响应内容
+fn curl(url: &str, output: Option<&Path>) -> Result<Vec<u8>, Box<dyn Error>> {
+    debug!(url, "下载");
+    let user_agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let mut command = Command::new("curl");
+    command.args([
+        "--silent",
+        "--show-error",
+        "--fail",
+        "--location",
+        "--user-agent",
+        &user_agent,
+    ]);
+    if let Some(output) = output {
+        command.arg("--output").arg(output);
+    }
+    let result = command
+        .arg(url)
+        .output()
+        .map_err(|e| format!("无法运行 curl: {}", e))?;
+    if !result.status.success() {
+        return Err(format!(
+            "下载 {} 失败: {}",
+            url,
+            String::from_utf8_lossy(&result.stderr).trim()
+        )
+        .into());
+    }
+    Ok(result.stdout)
+}
+
+fn get_json(url: &str) -> Result<Value, Box<dyn Error>> {
+    let body = curl(url, None)?;
+    serde_json::from_slice(&body).map_err(|e| format!("无法解析 {} 的响应: {}", url, e).into())
+}
+
+/// 验证记录中的仓库地址可能带有 /tree/<提交>，拆成仓库和提交
+fn split_repo_url(url: &str) -> (String, Option<String>) {
+    match url.split_once("/tree/") {
+        Some((repo, rev)) => (repo.to_string(), Some(rev.trim_matches('/').to_string())),
+        None => (url.trim_end_matches('/').to_string(), None),
+    }
+}
+
+/// 查询验证记录，克隆仓库并检出构建所用的提交
+fn fetch_program(
+    args: &FetchArgs,
+    program_id: &str,
+    source: &Path,
+) -> Result<Origin, Box<dyn Error>> {
+    let url = format!(
+        "{}/status/{}",
+        args.verify_api.trim_end_matches('/'),
+        program_id
+    );
+    let status = get_json(&url)?;
+    let text = |key: &str| {
+        status
+            .get(key)
+            .and_then(Value::as_str)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+    let verified = status
+        .get("is_verified")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let repo_url = text("repo_url").ok_or_else(|| {
+        format!(
+            "程序 {} 没有验证构建记录: {}",
+            program_id,
+            text("message").unwrap_or_default()
+        )
+    })?;
+    if !verified {
+        if !args.allow_unverified {
+            return Err(format!(
+                "程序 {} 没有通过验证 (链上哈希与 {} 的构建结果不一致)，可用 --allow-unverified 仍然分析",
+                program_id, repo_url
+            )
+            .into());
+        }
+        warn!(program_id, repo_url = %repo_url, "程序没有通过验证，源码不一定与链上的程序一致");
+    }
+    let (repo, rev) = split_repo_url(&repo_url);
+    let commit = text("commit").or(rev);
+    check_git_arg("仓库地址", &repo)?;
+    if let Some(commit) = &commit {
+        check_commit(commit)?;
+    }
+    if source.exists() {
+        info!(source = %source.display(), "源码目录已存在，跳过克隆");
+    } else {
+        let parent = source.parent().unwrap_or(Path::new("."));
+        let source_arg = source.display().to_string();
+        git_lines(parent, &["clone", "--quiet", "--", &repo, &source_arg])?;
+        if let Some(commit) = &commit {
+            // checkout 的 -- 之后是路径，提交只能放在它之前；已检查过不是选项
+            git_lines(source, &["checkout", "--quiet", "--detach", commit, "--"])?;
+        }
+        info!(repo = %repo, commit = ?commit, "已克隆验证构建的仓库");
+    }
+    Ok(Origin::Program {
+        program_id: program_id.to_string(),
+        verified,
+        repo_url,
+        commit,
+        on_chain_hash: text("on_chain_hash"),
+        executable_hash: text("executable_hash"),
+        last_verified_at: text("last_verified_at"),
+    })
+}
+
+/// `name@version` 中的版本；没有时取 crates.io 上最新的稳定版本
+fn crate_version(spec: &str) -> Result<(String, String), Box<dyn Error>> {
+    if let Some((name, version)) = spec.split_once('@') {
+        return Ok((name.to_string(), version.to_string()));
+    }
+    let info = get_json(&format!("{}/{}", CRATES_IO_API, spec))?;
+    let version = ["max_stable_version", "max_version"]
+        .iter()
+        .find_map(|key| info.pointer(&format!("/crate/{}", key))?.as_str())
+        .ok_or_else(|| format!("crates.io 上找不到 crate {}", spec))?;
+    Ok((spec.to_string(), version.to_string()))
+}
+
+/// 下载 crate 的源码包并解压到 source
+fn fetch_crate(name: &str, version: &str, source: &Path) -> Result<Origin, Box<dyn Error>> {
+    let url = format!("{}/{}/{}-{}.crate", CRATES_IO_DOWNLOAD, name, name, version);
+    if source.exists() {
+        info!(source = %source.display(), "源码目录已存在，跳过下载");
+    } else {
+        let parent = source.parent().unwrap_or(Path::new("."));
+        let archive = parent.join(format!("{}-{}.crate", name, version));
+        curl(&url, Some(&archive))?;
+        // 源码包中的文件都在 <name>-<version>/ 下
+        let status = Command::new("tar")
+            .arg("-xzf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(parent)
+            .status()
+            .map_err(|e| format!("无法运行 tar: {}", e))?;
+        if !status.success() {
+            return Err(format!("无法解压 {}", archive.display()).into());
+        }
+        fs::rename(parent.join(format!("{}-{}", name, version)), source)?;
+        fs::remove_file(&archive)?;
+        info!(name, version, "已下载 crate 的源码");
+    }
+    Ok(Origin::Crate {
+        name: name.to_string(),
+        version: version.to_string(),
+        url,
+    })
+}
+
+/// 取得源码并运行分析，写出 fetch.json
+pub fn run(args: &FetchArgs, log: &LogOptions) -> Result<(), Box<dyn Error>> {
+    let crate_spec = match &args.crate_name {
+        Some(spec) => Some(crate_version(spec)?),
+        None => None,
+    };
+    let label = match (&args.program_id, &crate_spec) {
+        (Some(program_id), _) => program_id.clone(),
+        (None, Some((name, version))) => format!("{}-{}", name, version),
+        (None, None) => unreachable!("clap 保证给出了其中一个来源"),
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| Path::new(DEFAULT_OUTPUT_DIR).join(&label));
+    fs::create_dir_all(&output)?;
+    let output = output.canonicalize()?;
+    let source = output.join(SOURCE_DIR);
+    let artifacts = output.join(ARTIFACTS_DIR);
+
+    let origin = match (&args.program_id, &crate_spec) {
+        (Some(program_id), _) => fetch_program(args, program_id, &source)?,
+        (None, Some((name, version))) => fetch_crate(name, version, &source)?,
+        (None, None) => unreachable!("clap 保证给出了其中一个来源"),
+    };
+
+    let mut failed = vec![];
+    if !args.no_analyze {
+        let checks: Vec<String> = if args.checks.is_empty() {
+            DEFAULT_CHECKS.iter().map(|c| c.to_string()).collect()
+        } else {
+            args.checks.clone()
+        };
+        let config = args.config.as_ref().map(|c| c.canonicalize()).transpose()?;
+        fs::create_dir_all(&artifacts)?;
+        info!(source = %source.display(), checks = checks.len(), "开始分析");
+        failed = analyze_revision(&source, config.as_deref(), &artifacts, &checks, log);
+    }
+
+    let record = FetchRecord {
+        metadata: FetchMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        origin,
+        source,
+        artifacts,
+        analyzed: !args.no_analyze,
+        failed,
+    };
+    fs::write(
+        output.join(FETCH_FILE_NAME),
+        serde_json::to_string_pretty(&record)?,
+    )?;
+    if record.failed.iter().any(|step| step == "analyze") {
+        return Err(format!("'{}' 的分析失败", record.source.display()).into());
+    }
+    info!(
+        source = %record.source.display(),
+        artifacts = %record.artifacts.display(),
+        failed = record.failed.len(),
+        "已取得源码并完成分析"
+    );
+    Ok(())
+}
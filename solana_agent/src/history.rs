@@ -0,0 +1,574 @@
+// history.rs
+//
+// agent history：在一组历史版本上依次运行分析，写出风险随版本变化的趋势 history.json 和 history.csv
+// 每个版本检出到同一个临时的 git worktree 中，用 agent 自身的子命令 (analyze、各项检查、report、dashboard) 分析，
+// 所有版本共用同一个输出目录 <产物目录>/history/out，因此每个版本只重新生成相对上一个版本有变化的部分
+// 每个版本记录复杂度 (dashboard)、问题数 (report，按严重程度、类别和规则) 和每个处理函数的CU估算 (manifest.json)，
+// 趋势部分比较第一个和最后一个有结果的版本
+
+use crate::config::ArtifactsArgs;
+use crate::manifest::now_rfc3339;
+use crate::report::load_findings;
+use crate::scope::{check_commit, git_lines};
+use crate::LogOptions;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const HISTORY_JSON: &str = "history.json";
+const HISTORY_CSV: &str = "history.csv";
+
+/// 各版本共用的输出目录，位于产物目录下
+const HISTORY_DIR: &str = "history";
+
+/// 每个版本上默认运行的检查 (agent 的子命令)；只需要产物的检查，结果都由 agent report 汇总
+pub const DEFAULT_CHECKS: &[&str] = &[
+    "client-lint",
+    "constraints",
+    "mutability",
+    "signers",
+    "space",
+    "pda",
+    "events",
+    "test-coverage",
+    "dead-code",
+    "lifecycle",
+    "known-vulns",
+];
+
+/// `agent history` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct HistoryArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 要分析的版本：`<rev1>..<rev2>` 范围 (沿第一父提交，包括 rev1 本身) 或逗号分隔的提交列表
+    #[arg(long, value_name = "RANGE")]
+    revs: String,
+
+    /// 最多分析的版本数，范围内的提交更多时均匀抽取 (总是包括第一个和最后一个)
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    max_revs: usize,
+
+    /// 每个版本上运行的检查 (agent 的子命令，逗号分隔)，默认为只需要产物的全部检查
+    #[arg(long, value_name = "CMD", value_delimiter = ',')]
+    checks: Vec<String>,
+
+    /// history.json 和 history.csv 的输出目录，默认为产物目录
+    #[arg(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+}
+
+/// 一个版本的问题数
+#[derive(Serialize, Debug, Default)]
+pub struct FindingCounts {
+    pub total: usize,
+    pub levels: BTreeMap<String, usize>,
+    pub classes: BTreeMap<String, usize>,
+    pub rules: BTreeMap<String, usize>,
+}
+
+impl FindingCounts {
+    /// 按严重程度、类别和规则统计 load_findings 读出的问题
+    pub fn count(findings: &[Value]) -> Self {
+        let mut counts = FindingCounts::default();
+        for finding in findings {
+            let field = |name: &str| finding.get(name).and_then(Value::as_str);
+            counts.total += 1;
+            *counts
+                .levels
+                .entry(field("level").unwrap_or("warning").to_string())
+                .or_default() += 1;
+            if let Some(class) = field("class") {
+                *counts.classes.entry(class.to_string()).or_default() += 1;
+            }
+            *counts
+                .rules
+                .entry(field("rule").unwrap_or_default().to_string())
+                .or_default() += 1;
+        }
+        counts
+    }
+
+    /// 把另一组统计加到这一组上
+    pub fn add(&mut self, other: &FindingCounts) {
+        self.total += other.total;
+        for (mine, theirs) in [
+            (&mut self.levels, &other.levels),
+            (&mut self.classes, &other.classes),
+            (&mut self.rules, &other.rules),
+        ] {
+            for (key, count) in theirs {
+                *mine.entry(key.clone()).or_default() += count;
+            }
+        }
+    }
+}
+
+/// 一个处理函数的指标：指令的复杂度 (处理函数及其传递调用的函数) 和最坏情况的CU估算
+#[derive(Serialize, Debug, Default)]
+struct HandlerMetrics {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    complexity: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compute_units: Option<u64>,
+}
+
+/// 一个版本的分析结果
+#[derive(Serialize, Debug)]
+struct Revision {
+    rev: String,
+    short: String,
+    date: String,
+    subject: String,
+    /// dashboard.json 中所有函数的汇总 (functions、complexity、max_complexity 等)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    totals: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    findings: Option<FindingCounts>,
+    /// 键为 `<program>::<ix>`；没有CU估算的指令以处理函数名为键
+    handlers: BTreeMap<String, HandlerMetrics>,
+    /// 失败的步骤，对应的指标缺失
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failed: Vec<String>,
+}
+
+/// 第一个和最后一个有结果的版本上的值
+#[derive(Serialize, Debug)]
+struct Delta {
+    first: u64,
+    last: u64,
+    change: i64,
+}
+
+impl Delta {
+    fn new(first: u64, last: u64) -> Self {
+        Delta {
+            first,
+            last,
+            change: last as i64 - first as i64,
+        }
+    }
+}
+
+/// 整个版本范围的趋势
+#[derive(Serialize, Debug, Default)]
+struct Trend {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    findings: Option<Delta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    complexity: Option<Delta>,
+    /// 在首末两个版本中都有CU估算的处理函数
+    compute_units: BTreeMap<String, Delta>,
+    /// CU 或复杂度上升的处理函数
+    growing: Vec<String>,
+}
+
+/// history.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct History {
+    metadata: HistoryMetadata,
+    revisions: Vec<Revision>,
+    trend: Trend,
+}
+
+#[derive(Serialize, Debug)]
+struct HistoryMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+    revs: String,
+    checks: Vec<String>,
+}
+
+/// 要分析的提交，从旧到新
+fn revisions(repo: &Path, revs: &str, max_revs: usize) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut commits = vec![];
+    match revs.split_once("..") {
+        Some((from, _)) => {
+            if !from.is_empty() && !revs.contains("...") {
+                commits.extend(git_lines(
+                    repo,
+                    &["rev-parse", "--verify", &format!("{}^{{commit}}", from)],
+                )?);
+            }
+            commits.extend(git_lines(
+                repo,
+                &["rev-list", "--reverse", "--first-parent", revs],
+            )?);
+        }
+        None => {
+            for rev in revs.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+                commits.extend(git_lines(
+                    repo,
+                    &["rev-parse", "--verify", &format!("{}^{{commit}}", rev)],
+                )?);
+            }
+        }
+    }
+    if commits.is_empty() {
+        return Err(format!("'{}' 中没有任何提交", revs).into());
+    }
+    if max_revs >= 2 && commits.len() > max_revs {
+        let n = commits.len();
+        commits = (0..max_revs)
+            .map(|i| commits[i * (n - 1) / (max_revs - 1)].clone())
+            .collect();
+    }
+    commits.dedup();
+    Ok(commits)
+}
+
+/// 以子进程运行 agent 的一个子命令，失败时返回标准错误的最后一行
+fn run_agent(args: &[String], log: &LogOptions) -> Result<(), String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let output = Command::new(exe)
+        .args(args)
+        .args(log.to_tool_args())
+        .output()
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(stderr
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("")
+        .trim()
+        .to_string())
+}
+
+/// 在已检出的版本上运行各步骤，返回失败的步骤
+pub fn analyze_revision(
+    project: &Path,
+    config: Option<&Path>,
+    out: &Path,
+    checks: &[String],
+    log: &LogOptions,
+) -> Vec<String> {
+    let common = |command: &str, output_flag: &str| {
+        let mut args = vec![
+            command.to_string(),
+            project.display().to_string(),
+            output_flag.to_string(),
+            out.display().to_string(),
+        ];
+        if let Some(config) = config {
+            args.push("--config".to_string());
+            args.push(config.display().to_string());
+        }
+        args
+    };
+
+    // 上一个版本的结果文件不能留给这个版本
+    for name in checks
+        .iter()
+        .map(|check| format!("{}.json", check.replace('-', "_")))
+        .chain(["report.json".to_string(), "dashboard.json".to_string()])
+    {
+        let _ = fs::remove_file(out.join(name));
+    }
+
+    let mut failed = vec![];
+    if let Err(e) = run_agent(&common("analyze", "--output"), log) {
+        warn!(error = %e, "analyze 失败，跳过该版本的其余步骤");
+        failed.push("analyze".to_string());
+        return failed;
+    }
+    for check in checks {
+        if let Err(e) = run_agent(&common(check, "--artifacts"), log) {
+            warn!(check = %check, error = %e, "检查失败");
+            failed.push(check.clone());
+        }
+    }
+    let mut report = common("report", "--artifacts");
+    report.extend(["--format".to_string(), "json".to_string()]);
+    if let Err(e) = run_agent(&report, log) {
+        warn!(error = %e, "report 失败");
+        failed.push("report".to_string());
+    }
+    if let Err(e) = run_agent(&common("dashboard", "--artifacts"), log) {
+        warn!(error = %e, "dashboard 失败");
+        failed.push("dashboard".to_string());
+    }
+    failed
+}
+
+/// 读取一个版本的输出目录中的指标
+fn collect_metrics(out: &Path, revision: &mut Revision) -> Result<(), Box<dyn Error>> {
+    let read = |name: &str| -> Option<Value> {
+        serde_json::from_str(&fs::read_to_string(out.join(name)).ok()?).ok()
+    };
+
+    if let Some(dashboard) = read("dashboard.json") {
+        revision.totals = dashboard.get("totals").cloned();
+        for instruction in dashboard
+            .get("instructions")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(name) = instruction.get("instruction").and_then(Value::as_str) {
+                revision
+                    .handlers
+                    .entry(name.to_string())
+                    .or_default()
+                    .complexity = instruction.get("complexity").and_then(Value::as_u64);
+            }
+        }
+    }
+
+    // CU估算以 `<program>::<ix>` 为键，把按处理函数名记下的复杂度并入其中
+    if let Some(manifest) = read("manifest.json") {
+        for (instruction, cost) in manifest
+            .get("compute_units")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let name = instruction.rsplit("::").next().unwrap_or(instruction);
+            let complexity = revision
+                .handlers
+                .remove(name)
+                .and_then(|metrics| metrics.complexity);
+            revision.handlers.insert(
+                instruction.clone(),
+                HandlerMetrics {
+                    complexity,
+                    compute_units: cost.as_u64(),
+                },
+            );
+        }
+    }
+
+    if out.join("report.json").is_file() {
+        revision.findings = Some(FindingCounts::count(&load_findings(out)?));
+    }
+    Ok(())
+}
+
+/// 比较第一个和最后一个有结果的版本
+fn trend(revisions: &[Revision]) -> Trend {
+    let mut trend = Trend::default();
+    let with_findings: Vec<u64> = revisions
+        .iter()
+        .filter_map(|r| r.findings.as_ref().map(|f| f.total as u64))
+        .collect();
+    if let (Some(&first), Some(&last)) = (with_findings.first(), with_findings.last()) {
+        trend.findings = Some(Delta::new(first, last));
+    }
+    let complexity: Vec<u64> = revisions
+        .iter()
+        .filter_map(|r| r.totals.as_ref()?.get("complexity")?.as_u64())
+        .collect();
+    if let (Some(&first), Some(&last)) = (complexity.first(), complexity.last()) {
+        trend.complexity = Some(Delta::new(first, last));
+    }
+
+    let analyzed: Vec<&Revision> = revisions
+        .iter()
+        .filter(|r| !r.handlers.is_empty())
+        .collect();
+    if let (Some(first), Some(last)) = (analyzed.first(), analyzed.last()) {
+        for (handler, now) in &last.handlers {
+            let Some(before) = first.handlers.get(handler) else {
+                continue;
+            };
+            if let (Some(a), Some(b)) = (before.compute_units, now.compute_units) {
+                trend
+                    .compute_units
+                    .insert(handler.clone(), Delta::new(a, b));
+            }
+            let grew =
+                |a: Option<u64>, b: Option<u64>| matches!((a, b), (Some(a), Some(b)) if b > a);
+            if grew(before.compute_units, now.compute_units)
+                || grew(before.complexity, now.complexity)
+            {
+                trend.growing.push(handler.clone());
+            }
+        }
+    }
+    trend
+}
+
+/// 每个版本一行的汇总
+fn write_csv(path: &Path, history: &History) -> Result<(), Box<dyn Error>> {
+    fn field(value: &str) -> String {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+    let mut csv = String::from(
+        "rev,date,subject,functions,complexity,max_complexity,findings,errors,warnings,notes,compute_units\n",
+    );
+    for r in &history.revisions {
+        let total = |name: &str| {
+            r.totals
+                .as_ref()
+                .and_then(|t| t.get(name))
+                .and_then(Value::as_u64)
+                .map_or(String::new(), |v| v.to_string())
+        };
+        let findings = |level: Option<&str>| {
+            r.findings.as_ref().map_or(String::new(), |f| {
+                match level {
+                    Some(level) => f.levels.get(level).copied().unwrap_or(0),
+                    None => f.total,
+                }
+                .to_string()
+            })
+        };
+        let compute_units: u64 = r.handlers.values().filter_map(|h| h.compute_units).sum();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{}\n",
+            r.short,
+            r.date,
+            field(&r.subject),
+            total("functions"),
+            total("complexity"),
+            total("max_complexity"),
+            findings(None),
+            findings(Some("error")),
+            findings(Some("warning")),
+            findings(Some("note")),
+            compute_units
+        ));
+    }
+    fs::write(path, csv)?;
+    Ok(())
+}
+
+/// 依次分析每个版本，写出趋势报告
+pub fn run(args: &HistoryArgs, log: &LogOptions) -> Result<(), Box<dyn Error>> {
+    let project = &args.artifacts.project;
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let repo = PathBuf::from(
+        git_lines(project, &["rev-parse", "--show-toplevel"])?
+            .pop()
+            .ok_or("无法确定 git 仓库的根目录")?,
+    );
+    // 项目在仓库中的相对位置，每个版本的 worktree 中也在同一位置
+    let prefix = git_lines(project, &["rev-parse", "--show-prefix"])?
+        .pop()
+        .unwrap_or_default();
+    let commits = revisions(project, &args.revs, args.max_revs)?;
+    let checks: Vec<String> = if args.checks.is_empty() {
+        DEFAULT_CHECKS.iter().map(|c| c.to_string()).collect()
+    } else {
+        args.checks.clone()
+    };
+    let out = artifacts_dir.join(HISTORY_DIR).join("out");
+    fs::create_dir_all(&out)?;
+    let out = out.canonicalize()?;
+    let config = args
+        .artifacts
+        .config
+        .as_ref()
+        .map(|c| c.canonicalize())
+        .transpose()?;
+    info!(
+        revisions = commits.len(),
+        checks = checks.len(),
+        "开始分析历史版本"
+    );
+
+    let worktree = std::env::temp_dir().join(format!("agent-history-{}", std::process::id()));
+    let worktree_arg = worktree.display().to_string();
+    git_lines(
+        &repo,
+        &[
+            "worktree",
+            "add",
+            "--detach",
+            "--",
+            &worktree_arg,
+            check_commit(&commits[0])?,
+        ],
+    )?;
+    let mut revisions = vec![];
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        for (i, commit) in commits.iter().enumerate() {
+            git_lines(
+                &worktree,
+                &[
+                    "checkout",
+                    "--quiet",
+                    "--detach",
+                    "--force",
+                    check_commit(commit)?,
+                    "--",
+                ],
+            )?;
+            git_lines(&worktree, &["clean", "-fdq"])?;
+            let info = git_lines(&worktree, &["log", "-1", "--format=%h%n%cI%n%s"])?;
+            let field = |n: usize| info.get(n).cloned().unwrap_or_default();
+            info!(rev = %field(0), progress = %format!("{}/{}", i + 1, commits.len()), "分析版本");
+
+            let failed = analyze_revision(
+                &worktree.join(&prefix),
+                config.as_deref(),
+                &out,
+                &checks,
+                log,
+            );
+            let mut revision = Revision {
+                rev: commit.clone(),
+                short: field(0),
+                date: field(1),
+                subject: field(2),
+                totals: None,
+                findings: None,
+                handlers: BTreeMap::new(),
+                failed,
+            };
+            if !revision.failed.iter().any(|step| step == "analyze") {
+                collect_metrics(&out, &mut revision)?;
+            }
+            revisions.push(revision);
+        }
+        Ok(())
+    })();
+    // 无论成败都移除临时的 worktree
+    if let Err(e) = git_lines(&repo, &["worktree", "remove", "--force", &worktree_arg]) {
+        warn!(error = %e, "无法移除临时的 worktree");
+    }
+    result?;
+
+    let history = History {
+        metadata: HistoryMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+            revs: args.revs.clone(),
+            checks,
+        },
+        trend: trend(&revisions),
+        revisions,
+    };
+    let output_dir = args.output.clone().unwrap_or(artifacts_dir);
+    fs::create_dir_all(&output_dir)?;
+    fs::write(
+        output_dir.join(HISTORY_JSON),
+        serde_json::to_string_pretty(&history)?,
+    )?;
+    write_csv(&output_dir.join(HISTORY_CSV), &history)?;
+    info!(
+        revisions = history.revisions.len(),
+        findings_change = history.trend.findings.as_ref().map(|d| d.change),
+        complexity_change = history.trend.complexity.as_ref().map(|d| d.change),
+        growing = history.trend.growing.len(),
+        output = %output_dir.display(),
+        "已写出历史趋势"
+    );
+    Ok(())
+}
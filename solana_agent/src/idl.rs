@@ -0,0 +1,479 @@
+// idl.rs
+//
+// agent idl：读取AST阶段统一格式后的 Anchor IDL (.idl.json，见 solana_ast_generator 的 idl.rs)，
+// 没有IDL时从AST推导出等价的指令列表，再把每条指令、它的参数和账户链接到处理函数及其CFG/CPG
+// 每条指令统一命名为 <program>::<instruction>，instruction 为 snake_case 的处理函数名，供下游报告引用
+// 每条指令还带有判别值和指令数据的 borsh 布局 (见 layout.rs)，参数引用的类型取自IDL的 types 和AST中的结构体/枚举
+
+use crate::config::ArtifactsArgs;
+use crate::graph::Layer;
+use crate::layout::{
+    anchor_discriminator, collect_type_defs, data_layout, idl_type_defs, DataLayout, FieldDef,
+};
+use crate::manifest::{now_rfc3339, PreviousRunManifest};
+use crate::merge::{graph_key, load_merged};
+use crate::symbols::{child, definition_name, is_program_attribute, line_of, load_asts, AstNode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const IDL_FILE_NAME: &str = "idl.json";
+
+/// `agent idl` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct IdlArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 忽略IDL，只从AST推导指令
+    #[arg(long)]
+    from_ast: bool,
+
+    /// 输出文件，默认为产物目录下的 idl.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 指令的一个参数
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstructionArg {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+/// 指令的一个账户；嵌套的账户组展开为 `组名.账户名`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstructionAccount {
+    pub name: String,
+    pub writable: bool,
+    pub signer: bool,
+    pub optional: bool,
+}
+
+/// 指令的处理函数
+#[derive(Serialize, Debug, Clone)]
+pub struct Handler {
+    pub file: PathBuf,
+    pub function: String,
+    /// 函数定义所在的行，从 1 开始
+    pub line: usize,
+    /// `Context<T>` 中的账户结构体 T
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accounts_struct: Option<String>,
+}
+
+/// 一条指令
+#[derive(Serialize, Debug)]
+pub struct Instruction {
+    /// 稳定的指令名 `<program>::<instruction>`
+    pub id: String,
+    /// IDL中的原始名字
+    pub name: String,
+    pub args: Vec<InstructionArg>,
+    pub accounts: Vec<InstructionAccount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handler: Option<Handler>,
+    /// 处理函数的图在合并图中的键 (AST层和MIR层)
+    pub graphs: Vec<String>,
+    /// 指令数据开头的判别值
+    pub discriminator: Vec<u8>,
+    /// 指令数据的 borsh 布局
+    pub layout: DataLayout,
+}
+
+/// 一个程序
+#[derive(Serialize, Debug)]
+pub struct Program {
+    pub name: String,
+    /// 指令列表的来源：idl 或 ast
+    pub source: &'static str,
+    /// 原IDL文件
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idl: Option<PathBuf>,
+    pub instructions: Vec<Instruction>,
+}
+
+/// idl.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct IdlReport {
+    metadata: IdlMetadata,
+    programs: Vec<Program>,
+}
+
+#[derive(Serialize, Debug)]
+struct IdlMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// AST阶段写出的统一格式IDL (.idl.json) 中用到的部分
+#[derive(Deserialize, Debug)]
+pub struct NormalizedIdl {
+    pub program: String,
+    pub instructions: Vec<IdlInstruction>,
+    #[serde(default)]
+    pub types: Vec<IdlType>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IdlInstruction {
+    /// snake_case 的处理函数名
+    pub name: String,
+    /// IDL中的原始名字
+    pub idl_name: String,
+    pub args: Vec<InstructionArg>,
+    pub accounts: Vec<InstructionAccount>,
+    /// 0.30 起IDL直接给出的判别值
+    #[serde(default)]
+    pub discriminator: Option<Vec<u8>>,
+}
+
+/// types 中的结构体或枚举；枚举的每个变体是一个只有名字和字段的条目
+#[derive(Deserialize, Debug)]
+pub struct IdlType {
+    pub name: String,
+    pub kind: String,
+    #[serde(default)]
+    pub fields: Vec<InstructionArg>,
+    #[serde(default)]
+    pub variants: Vec<IdlType>,
+}
+
+/// AST中的一个 `#[program]` 模块
+pub struct AstProgram {
+    pub name: String,
+    pub file: PathBuf,
+    /// 模块中的 pub fn 及其指令参数
+    pub handlers: Vec<(Handler, Vec<InstructionArg>)>,
+}
+
+/// camelCase 转为 snake_case，旧版IDL中的指令名与处理函数名之间的对应关系
+pub fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// 处理函数的参数：第一个 `Context<T>` 参数给出账户结构体，其余为指令参数
+fn handler_params(function: &AstNode) -> (Option<String>, Vec<InstructionArg>) {
+    let mut accounts_struct = None;
+    let mut args = vec![];
+    let Some(parameters) = child(function, "parameters") else {
+        return (None, args);
+    };
+    for parameter in parameters.children.iter().filter(|c| c.kind == "parameter") {
+        let name = parameter.children.first().map_or("", |n| n.text.as_str());
+        let ty = parameter.children.last().map_or("", |t| t.text.as_str());
+        if accounts_struct.is_none() && ty.starts_with("Context<") {
+            accounts_struct = ty
+                .trim_start_matches("Context<")
+                .trim_end_matches('>')
+                .split(['<', ','])
+                .next()
+                .map(|s| s.trim().to_string());
+        } else {
+            args.push(InstructionArg {
+                name: name.to_string(),
+                ty: ty.to_string(),
+            });
+        }
+    }
+    (accounts_struct, args)
+}
+
+/// 在一个文件的AST中收集 `#[program]` 模块
+pub fn collect_programs(root: &AstNode, file: &Path, source: &str, programs: &mut Vec<AstProgram>) {
+    let mut program_attribute = false;
+    for node in &root.children {
+        if node.kind == "mod_item" && program_attribute {
+            let mut handlers = vec![];
+            for function in child(node, "declaration_list")
+                .into_iter()
+                .flat_map(|list| &list.children)
+                .filter(|c| c.kind == "function_item" && child(c, "visibility_modifier").is_some())
+            {
+                let Some(name) = definition_name(function) else {
+                    continue;
+                };
+                let (accounts_struct, args) = handler_params(function);
+                handlers.push((
+                    Handler {
+                        file: file.to_path_buf(),
+                        function: name.text.clone(),
+                        line: line_of(source, function.start_byte),
+                        accounts_struct,
+                    },
+                    args,
+                ));
+            }
+            if let Some(name) = definition_name(node) {
+                programs.push(AstProgram {
+                    name: name.text.clone(),
+                    file: file.to_path_buf(),
+                    handlers,
+                });
+            }
+        }
+        program_attribute =
+            is_program_attribute(node) || (program_attribute && node.kind == "attribute_item");
+        collect_programs(node, file, source, programs);
+    }
+}
+
+/// 在AST中收集 `#[derive(Accounts)]` 结构体的账户
+pub fn collect_accounts_structs(
+    root: &AstNode,
+    structs: &mut HashMap<String, Vec<InstructionAccount>>,
+) {
+    let mut derives_accounts = false;
+    for node in &root.children {
+        if node.kind == "struct_item" && derives_accounts {
+            if let (Some(name), Some(fields)) =
+                (definition_name(node), child(node, "field_declaration_list"))
+            {
+                let mut accounts = vec![];
+                let mut attributes = String::new();
+                for field in &fields.children {
+                    match field.kind.as_str() {
+                        "attribute_item" => attributes.push_str(&field.text),
+                        "field_declaration" => {
+                            let name = child(field, "field_identifier").map_or("", |n| &n.text);
+                            let ty = field.children.last().map_or("", |t| t.text.as_str());
+                            let constraints: Vec<&str> = attributes
+                                .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                                .collect();
+                            accounts.push(InstructionAccount {
+                                name: name.to_string(),
+                                writable: constraints.contains(&"mut")
+                                    || constraints.contains(&"init"),
+                                signer: ty.starts_with("Signer") || constraints.contains(&"signer"),
+                                optional: ty.starts_with("Option<"),
+                            });
+                            attributes.clear();
+                        }
+                        _ => {}
+                    }
+                }
+                structs.insert(name.text.clone(), accounts);
+            }
+        }
+        derives_accounts = (node.kind == "attribute_item"
+            && node.text.replace(' ', "").contains("derive(")
+            && node.text.contains("Accounts"))
+            || (derives_accounts && node.kind == "attribute_item");
+        collect_accounts_structs(node, structs);
+    }
+}
+
+/// 产物目录中AST阶段写出的所有统一格式IDL，以及各自的原IDL文件 (相对于输入目录)
+pub fn load_idls(artifacts_dir: &Path) -> Result<Vec<(PathBuf, NormalizedIdl)>, Box<dyn Error>> {
+    let manifest = PreviousRunManifest::load(artifacts_dir);
+    let mut idls = vec![];
+    for artifact in manifest.artifacts.iter().filter(|a| a.kind == "idl") {
+        let path = artifacts_dir.join(&artifact.path);
+        let idl = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("无法解析 {}: {}", path.display(), e))?;
+        let source = artifact.source.clone().unwrap_or_else(|| path.clone());
+        idls.push((source, idl));
+    }
+    Ok(idls)
+}
+
+/// 合并图中每个函数图的键及其所在层和函数名；读不到图时返回空
+fn graph_keys(artifacts: &ArtifactsArgs) -> BTreeMap<String, (Layer, String)> {
+    let graph = match load_merged(artifacts) {
+        Ok((_, graph)) => graph,
+        Err(e) => {
+            warn!(error = %e, "无法读取CFG/CPG，指令不会链接到图");
+            return BTreeMap::new();
+        }
+    };
+    graph
+        .nodes
+        .iter()
+        .map(|n| (graph_key(&n.id).to_string(), (n.layer, n.function.clone())))
+        .collect()
+}
+
+/// 处理函数在合并图中的图：AST层按文件和函数名匹配，MIR层按 `<模块>::<函数名>` 结尾匹配
+pub fn handler_graphs(
+    keys: &BTreeMap<String, (Layer, String)>,
+    program: &str,
+    handler: &Handler,
+) -> Vec<String> {
+    let ast_key = format!("ast:{}:{}", handler.file.display(), handler.function);
+    let mir_path = format!("{}::{}", program, handler.function);
+    keys.iter()
+        .filter(|(key, (layer, function))| match layer {
+            Layer::Ast => **key == ast_key,
+            Layer::Mir => function == &mir_path || function.ends_with(&format!("::{}", mir_path)),
+        })
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+/// 读取IDL或从AST推导指令，链接到处理函数和图，写出 idl.json
+pub fn run(args: &IdlArgs) -> Result<(), Box<dyn Error>> {
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+
+    let mut ast_programs = vec![];
+    let mut accounts_structs = HashMap::new();
+    let mut type_defs = HashMap::new();
+    for (file, root) in load_asts(&artifacts_dir)? {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(&file)).unwrap_or_default();
+        collect_programs(&root, &file, &source, &mut ast_programs);
+        collect_accounts_structs(&root, &mut accounts_structs);
+        collect_type_defs(&root, &mut type_defs);
+    }
+    debug!(programs = ast_programs.len(), "在AST中找到的程序");
+
+    let idls = if args.from_ast {
+        vec![]
+    } else {
+        load_idls(&artifacts_dir)?
+    };
+
+    let mut programs = vec![];
+    for (path, idl) in idls {
+        // IDL中定义的类型优先于AST中的同名类型
+        idl_type_defs(&idl.types, &mut type_defs);
+        let instructions = idl
+            .instructions
+            .into_iter()
+            .map(|ix| Instruction {
+                id: format!("{}::{}", idl.program, ix.name),
+                // 0.30 之前的IDL没有判别值，按 Anchor 的规则计算
+                discriminator: ix
+                    .discriminator
+                    .unwrap_or_else(|| anchor_discriminator(&ix.name)),
+                name: ix.idl_name,
+                args: ix.args,
+                accounts: ix.accounts,
+                handler: None,
+                graphs: vec![],
+                layout: DataLayout::default(),
+            })
+            .collect();
+        programs.push(Program {
+            name: idl.program,
+            source: "idl",
+            idl: Some(path),
+            instructions,
+        });
+    }
+    // 没有IDL的程序从AST推导：指令即 `#[program]` 模块中的 pub fn
+    for ast_program in &ast_programs {
+        if programs.iter().any(|p| p.name == ast_program.name) {
+            continue;
+        }
+        let instructions = ast_program
+            .handlers
+            .iter()
+            .map(|(handler, args)| Instruction {
+                id: format!("{}::{}", ast_program.name, handler.function),
+                name: handler.function.clone(),
+                args: args.clone(),
+                accounts: handler
+                    .accounts_struct
+                    .as_ref()
+                    .and_then(|s| accounts_structs.get(s))
+                    .cloned()
+                    .unwrap_or_default(),
+                handler: None,
+                graphs: vec![],
+                discriminator: anchor_discriminator(&handler.function),
+                layout: DataLayout::default(),
+            })
+            .collect();
+        programs.push(Program {
+            name: ast_program.name.clone(),
+            source: "ast",
+            idl: None,
+            instructions,
+        });
+    }
+
+    // 链接处理函数：优先在同名的 `#[program]` 模块中查找，否则在所有程序中查找
+    let keys = graph_keys(&args.artifacts);
+    let mut unlinked = 0;
+    for program in &mut programs {
+        let mut candidates: Vec<&AstProgram> = ast_programs.iter().collect();
+        candidates.sort_by_key(|p| p.name != program.name);
+        for instruction in &mut program.instructions {
+            let function = snake_case(&instruction.name);
+            let found = candidates.iter().find_map(|p| {
+                p.handlers
+                    .iter()
+                    .find(|(h, _)| h.function == function)
+                    .map(|(h, _)| (p, h))
+            });
+            match found {
+                Some((ast_program, handler)) => {
+                    instruction.graphs = handler_graphs(&keys, &ast_program.name, handler);
+                    instruction.handler = Some(handler.clone());
+                }
+                None => {
+                    warn!(instruction = %instruction.id, "找不到指令的处理函数");
+                    unlinked += 1;
+                }
+            }
+            let args: Vec<FieldDef> = instruction
+                .args
+                .iter()
+                .map(|arg| FieldDef::new(arg.name.as_str(), arg.ty.as_str()))
+                .collect();
+            instruction.layout = data_layout(instruction.discriminator.len(), &args, &type_defs);
+        }
+    }
+    if programs.is_empty() {
+        warn!("产物中没有IDL (.idl.json)，AST中也没有 #[program] 模块");
+    }
+    for ast_program in &ast_programs {
+        debug!(program = %ast_program.name, file = %ast_program.file.display(), "程序模块");
+    }
+
+    let report = IdlReport {
+        metadata: IdlMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        programs,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(IDL_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        programs = report.programs.len(),
+        instructions = report
+            .programs
+            .iter()
+            .map(|p| p.instructions.len())
+            .sum::<usize>(),
+        unlinked,
+        output = %output.display(),
+        "已写出指令映射"
+    );
+    Ok(())
+}
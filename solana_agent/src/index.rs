@@ -0,0 +1,422 @@
+// index.rs
+
+use crate::config::ArtifactsArgs;
+use crate::symbols::{build_index, Range, SymbolIndex};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// `agent index` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct IndexArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 索引格式，可以同时指定多个，例如 --format lsif,scip
+    #[arg(long = "format", value_enum, value_delimiter = ',', default_values_t = [IndexFormat::Lsif])]
+    formats: Vec<IndexFormat>,
+
+    /// 输出目录，默认为产物目录；文件名为 index.<格式>
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// 支持导出的代码导航索引格式
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum IndexFormat {
+    /// LSIF 0.4.3，每行一个JSON对象
+    Lsif,
+    /// SCIP (protobuf)，可上传到兼容 Sourcegraph 的工具
+    Scip,
+}
+
+impl IndexFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            IndexFormat::Lsif => "lsif",
+            IndexFormat::Scip => "scip",
+        }
+    }
+}
+
+/// 项目根目录的 file:// URI
+fn project_uri(project: &Path) -> Result<String, Box<dyn Error>> {
+    let root = fs::canonicalize(project)?;
+    Ok(format!("file://{}", root.display()))
+}
+
+// --- LSIF ---
+
+/// LSP 格式的范围
+fn range_json(range: &Range) -> Value {
+    json!({
+        "start": { "line": range.start.line, "character": range.start.character },
+        "end": { "line": range.end.line, "character": range.end.character },
+    })
+}
+
+/// 按顺序写出 LSIF 的顶点和边，自动分配ID
+struct LsifWriter<W: Write> {
+    out: W,
+    next_id: u64,
+}
+
+impl<W: Write> LsifWriter<W> {
+    fn emit(&mut self, kind: &str, label: &str, mut element: Value) -> io::Result<u64> {
+        self.next_id += 1;
+        element["id"] = json!(self.next_id);
+        element["type"] = json!(kind);
+        element["label"] = json!(label);
+        writeln!(self.out, "{}", element)?;
+        Ok(self.next_id)
+    }
+
+    fn vertex(&mut self, label: &str, element: Value) -> io::Result<u64> {
+        self.emit("vertex", label, element)
+    }
+
+    fn edge(&mut self, label: &str, out_v: u64, in_v: u64) -> io::Result<u64> {
+        self.emit("edge", label, json!({ "outV": out_v, "inV": in_v }))
+    }
+
+    fn edges(&mut self, label: &str, out_v: u64, in_vs: &[u64], extra: Value) -> io::Result<u64> {
+        let mut element = json!({ "outV": out_v, "inVs": in_vs });
+        if let (Some(element), Value::Object(extra)) = (element.as_object_mut(), extra) {
+            element.extend(extra);
+        }
+        self.emit("edge", label, element)
+    }
+
+    fn range(&mut self, range: &Range, tag: Option<Value>) -> io::Result<u64> {
+        let mut element = range_json(range);
+        if let Some(tag) = tag {
+            element["tag"] = tag;
+        }
+        self.vertex("range", element)
+    }
+}
+
+/// 符号种类对应的 LSP SymbolKind 编号
+fn lsp_symbol_kind(kind: &str) -> u32 {
+    match kind {
+        "module" => 2,
+        "class" => 5,
+        "method" => 6,
+        "field" => 8,
+        "enum" => 10,
+        "trait" | "interface" => 11,
+        "constant" => 14,
+        "struct" => 23,
+        "event" => 24,
+        "type" => 26,
+        _ => 12, // function、macro
+    }
+}
+
+/// 每个定义在 LSIF 中对应的顶点
+struct DefinitionVertices {
+    result_set: u64,
+    definition_result: u64,
+    reference_result: u64,
+}
+
+/// 写出 LSIF 转储
+/// 先写出与文档无关的 resultSet 等顶点，再逐个文档写出范围和 item 边，
+/// 保证每个文档的数据都位于它的 begin/end 事件之间
+fn write_lsif(index: &SymbolIndex, project: &Path, output: &Path) -> Result<(), Box<dyn Error>> {
+    let mut writer = LsifWriter {
+        out: BufWriter::new(File::create(output)?),
+        next_id: 0,
+    };
+    let root = project_uri(project)?;
+    writer.vertex(
+        "metaData",
+        json!({
+            "version": "0.4.3",
+            "projectRoot": root,
+            "positionEncoding": "utf-16",
+            "toolInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+        }),
+    )?;
+
+    let mut vertices = vec![];
+    for definition in &index.definitions {
+        let result_set = writer.vertex("resultSet", json!({}))?;
+        let definition_result = writer.vertex("definitionResult", json!({}))?;
+        writer.edge("textDocument/definition", result_set, definition_result)?;
+        let language = index.documents[definition.document].language;
+        let hover = writer.vertex(
+            "hoverResult",
+            json!({ "result": { "contents": [{ "language": language, "value": definition.hover }] } }),
+        )?;
+        writer.edge("textDocument/hover", result_set, hover)?;
+        let reference_result = writer.vertex("referenceResult", json!({}))?;
+        writer.edge("textDocument/references", result_set, reference_result)?;
+        vertices.push(DefinitionVertices {
+            result_set,
+            definition_result,
+            reference_result,
+        });
+    }
+
+    for (doc_index, document) in index.documents.iter().enumerate() {
+        let document_id = writer.vertex(
+            "document",
+            json!({
+                "uri": format!("{}/{}", root, document.path.display()),
+                "languageId": document.language,
+            }),
+        )?;
+        writer.vertex(
+            "$event",
+            json!({ "kind": "begin", "scope": "document", "data": document_id }),
+        )?;
+
+        let mut ranges = vec![];
+        for (i, definition) in index.definitions.iter().enumerate() {
+            if definition.document != doc_index {
+                continue;
+            }
+            let tag = json!({
+                "type": "definition",
+                "text": definition.name,
+                "kind": lsp_symbol_kind(definition.kind),
+                "fullRange": range_json(&definition.range),
+            });
+            let range = writer.range(&definition.range, Some(tag))?;
+            writer.edge("next", range, vertices[i].result_set)?;
+            let extra = json!({ "document": document_id });
+            writer.edges("item", vertices[i].definition_result, &[range], extra)?;
+            let extra = json!({ "document": document_id, "property": "definitions" });
+            writer.edges("item", vertices[i].reference_result, &[range], extra)?;
+            ranges.push(range);
+        }
+
+        // 同一个定义在本文档中的所有引用合并为一条 item 边
+        let mut references: BTreeMap<usize, Vec<u64>> = BTreeMap::new();
+        for reference in index.references.iter().filter(|r| r.document == doc_index) {
+            let range = writer.range(&reference.range, None)?;
+            writer.edge("next", range, vertices[reference.definition].result_set)?;
+            references
+                .entry(reference.definition)
+                .or_default()
+                .push(range);
+            ranges.push(range);
+        }
+        for (definition, in_vs) in references {
+            let extra = json!({ "document": document_id, "property": "references" });
+            writer.edges("item", vertices[definition].reference_result, &in_vs, extra)?;
+        }
+
+        if !ranges.is_empty() {
+            writer.edges("contains", document_id, &ranges, json!({}))?;
+        }
+        writer.vertex(
+            "$event",
+            json!({ "kind": "end", "scope": "document", "data": document_id }),
+        )?;
+    }
+    writer.out.flush()?;
+    Ok(())
+}
+
+// --- SCIP ---
+
+/// 最小的 protobuf 编码器，只实现 SCIP 用到的字段类型
+#[derive(Default)]
+struct ProtoWriter {
+    buf: Vec<u8>,
+}
+
+impl ProtoWriter {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(u64::from(field << 3 | wire_type));
+    }
+
+    fn int32(&mut self, field: u32, value: i32) {
+        if value != 0 {
+            self.key(field, 0);
+            self.varint(value as i64 as u64);
+        }
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, 2);
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        if !value.is_empty() {
+            self.bytes(field, value.as_bytes());
+        }
+    }
+
+    fn message(&mut self, field: u32, message: ProtoWriter) {
+        self.bytes(field, &message.buf);
+    }
+
+    fn packed_int32(&mut self, field: u32, values: &[i32]) {
+        let mut packed = ProtoWriter::default();
+        for &value in values {
+            packed.varint(value as i64 as u64);
+        }
+        self.bytes(field, &packed.buf);
+    }
+}
+
+/// SCIP 描述符中的名字，含有特殊字符时用反引号括起来
+fn scip_name(name: &str) -> String {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '+' | '-' | '$'))
+    {
+        name.to_string()
+    } else {
+        format!("`{}`", name.replace('`', "``"))
+    }
+}
+
+/// 定义的全局 SCIP 符号：`agent . <项目名> . <文件路径命名空间><名字及后缀>`
+/// 同一文件中的同名定义会得到相同的符号
+fn scip_symbol(package: &str, path: &Path, name: &str, kind: &str) -> String {
+    let namespaces: String = path
+        .components()
+        .map(|c| format!("{}/", scip_name(&c.as_os_str().to_string_lossy())))
+        .collect();
+    let name = scip_name(name);
+    let descriptor = match kind {
+        "function" | "method" => format!("{}().", name),
+        "module" => format!("{}/", name),
+        "macro" => format!("{}!", name),
+        "constant" | "field" => format!("{}.", name),
+        _ => format!("{}#", name), // struct、enum、trait、class、interface、type、event
+    };
+    format!(
+        "agent . {} . {}{}",
+        scip_name(package),
+        namespaces,
+        descriptor
+    )
+}
+
+/// SCIP 的位置：单行时为 [起始行, 起始列, 结束列]，否则为四个值
+fn scip_range(range: &Range) -> Vec<i32> {
+    let (start, end) = (range.start, range.end);
+    if start.line == end.line {
+        vec![
+            start.line as i32,
+            start.character as i32,
+            end.character as i32,
+        ]
+    } else {
+        vec![
+            start.line as i32,
+            start.character as i32,
+            end.line as i32,
+            end.character as i32,
+        ]
+    }
+}
+
+/// SCIP 中 Occurrence.symbol_roles 的 Definition 位
+const SCIP_ROLE_DEFINITION: i32 = 0x1;
+/// SCIP 中 TextEncoding.UTF16 以及 PositionEncoding.UTF16CodeUnitOffsetFromLineStart
+const SCIP_UTF16: i32 = 2;
+
+/// 写出 SCIP 索引
+fn write_scip(index: &SymbolIndex, project: &Path, output: &Path) -> Result<(), Box<dyn Error>> {
+    let root = project_uri(project)?;
+    let package = fs::canonicalize(project)?
+        .file_name()
+        .map_or("project".to_string(), |n| n.to_string_lossy().into_owned());
+    let symbols: Vec<String> = index
+        .definitions
+        .iter()
+        .map(|d| scip_symbol(&package, &index.documents[d.document].path, &d.name, d.kind))
+        .collect();
+
+    let mut tool_info = ProtoWriter::default();
+    tool_info.string(1, env!("CARGO_PKG_NAME"));
+    tool_info.string(2, env!("CARGO_PKG_VERSION"));
+    let mut metadata = ProtoWriter::default();
+    metadata.message(2, tool_info);
+    metadata.string(3, &root);
+    metadata.int32(4, SCIP_UTF16);
+    let mut scip = ProtoWriter::default();
+    scip.message(1, metadata);
+
+    for (doc_index, document) in index.documents.iter().enumerate() {
+        let mut doc = ProtoWriter::default();
+        doc.string(1, &document.path.to_string_lossy());
+        for (i, definition) in index.definitions.iter().enumerate() {
+            if definition.document != doc_index {
+                continue;
+            }
+            let mut occurrence = ProtoWriter::default();
+            occurrence.packed_int32(1, &scip_range(&definition.range));
+            occurrence.string(2, &symbols[i]);
+            occurrence.int32(3, SCIP_ROLE_DEFINITION);
+            doc.message(2, occurrence);
+
+            let mut information = ProtoWriter::default();
+            information.string(1, &symbols[i]);
+            information.string(
+                3,
+                &format!("```{}\n{}\n```", document.language, definition.hover),
+            );
+            information.string(6, &definition.name);
+            doc.message(3, information);
+        }
+        for reference in index.references.iter().filter(|r| r.document == doc_index) {
+            let mut occurrence = ProtoWriter::default();
+            occurrence.packed_int32(1, &scip_range(&reference.range));
+            occurrence.string(2, &symbols[reference.definition]);
+            doc.message(2, occurrence);
+        }
+        doc.string(4, document.language);
+        doc.int32(6, SCIP_UTF16);
+        scip.message(2, doc);
+    }
+
+    fs::write(output, scip.buf)?;
+    Ok(())
+}
+
+/// 从AST产物建立符号索引，并导出为代码导航索引
+pub fn run(args: &IndexArgs) -> Result<(), Box<dyn Error>> {
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let index = build_index(&artifacts_dir, &args.artifacts.project)?;
+    let output_dir = args.output.clone().unwrap_or_else(|| artifacts_dir.clone());
+    fs::create_dir_all(&output_dir)?;
+    info!(
+        documents = index.documents.len(),
+        definitions = index.definitions.len(),
+        references = index.references.len(),
+        "符号索引已建立"
+    );
+
+    for &format in &args.formats {
+        let output = output_dir.join(format!("index.{}", format.extension()));
+        match format {
+            IndexFormat::Lsif => write_lsif(&index, &args.artifacts.project, &output)?,
+            IndexFormat::Scip => write_scip(&index, &args.artifacts.project, &output)?,
+        }
+        info!(output = %output.display(), "索引已导出");
+    }
+    Ok(())
+}
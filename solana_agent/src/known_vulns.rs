@@ -0,0 +1,728 @@
+// known_vulns.rs
+//
+// agent known-vulns：把每条指令的结构与已知漏洞模式库比较，写出 known_vulns.json
+// 内置的模式库 (known_vulns.toml) 取自 sealevel-attacks 和公开的 Solana 攻击事件，不需要先写规则就能使用；
+// 每个模式由若干条件组成：field 条件由同一个账户字段满足，handler 条件针对整条指令 (处理函数、它传递调用的函数和 Accounts 约束)，
+// 条件前加 `!` 表示要求不满足。相似度为满足的条件所占的权重，结构特征 (肯定条件) 的权重是缺少的防护 (否定条件) 的两倍
+// 模式库写在TOML文件中，--corpus 可以追加自己的模式：
+//
+//     [[patterns]]
+//     id = "sealevel-owner-checks"
+//     title = "缺少 owner 检查"
+//     reference = "https://github.com/coral-xyz/sealevel-attacks/tree/master/programs/2-owner-checks"
+//     description = "从 AccountInfo 反序列化账户数据之前没有检查账户的 owner"
+//     field = ["unchecked", "deserialized", "!owner_checked"]
+//     handler = ["!owner_check"]
+//     class = "account_validation"
+//     cwe = [283]
+//
+// class 和 cwe 为问题的标准化分类 (见 taxonomy.rs)，随匹配写出，agent report 据此分类
+
+use crate::anchor_accounts::{self, constraint_key, AccountsField};
+use crate::callgraph::{collect_function_items, cpi_targets};
+use crate::config::ArtifactsArgs;
+use crate::manifest::now_rfc3339;
+use crate::mutability::Writes;
+use crate::symbols::{load_asts, normalize, CallGraph};
+use crate::taxonomy::VulnClass;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use tracing::{debug, info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const KNOWN_VULNS_FILE_NAME: &str = "known_vulns.json";
+
+/// 内置的模式库
+const BUILTIN_CORPUS: &str = include_str!("known_vulns.toml");
+
+/// 由 Anchor 检查 owner 的账户类型，与 constraints.rs 一致
+const OWNER_CHECKED_TYPES: &[&str] = &[
+    "Account",
+    "AccountLoader",
+    "InterfaceAccount",
+    "SystemAccount",
+];
+
+/// 由 Anchor 检查地址的账户类型，与 constraints.rs 一致
+const ADDRESS_CHECKED_TYPES: &[&str] = &["Program", "Interface", "Sysvar"];
+
+/// 带有账户数据类型的账户类型
+const DATA_ACCOUNT_TYPES: &[&str] = &["Account", "AccountLoader", "InterfaceAccount"];
+
+/// 不做任何检查的账户类型
+const UNCHECKED_TYPES: &[&str] = &["AccountInfo", "UncheckedAccount"];
+
+/// 名字中含有这些词的字段视为权限账户
+const AUTHORITY_WORDS: &[&str] = &["authority", "admin", "owner"];
+
+/// 常见的 sysvar 账户字段名
+const SYSVAR_NAMES: &[&str] = &[
+    "rent",
+    "clock",
+    "instructions",
+    "recent_blockhashes",
+    "slot_hashes",
+    "stake_history",
+];
+
+/// 读取账户数据的调用，与字段出现在同一行时视为反序列化了该字段
+const DESERIALIZE_MARKERS: &[&str] = &[
+    "try_borrow_data",
+    "data.borrow",
+    "try_from_slice",
+    "try_deserialize",
+    "::unpack",
+    "deserialize(",
+];
+
+/// 账户字段的条件及其含义
+const FIELD_TRAITS: &[(&str, &str)] = &[
+    ("unchecked", "AccountInfo 或 UncheckedAccount"),
+    ("data_account", "Account、AccountLoader 或 InterfaceAccount"),
+    ("owner_checked", "类型或 owner 约束检查了 owner"),
+    ("signer", "Signer 类型或 signer 约束"),
+    ("mut", "mut、init、zero 或 close 约束"),
+    ("init", "init、init_if_needed 或 zero 约束"),
+    ("close", "close 约束"),
+    ("has_one", "声明了 has_one"),
+    ("constraint", "有 constraint = .. 自定义约束"),
+    ("seeds", "有 seeds 约束"),
+    ("seeds_with_signer", "seeds 中引用了签名者字段"),
+    ("address", "address 约束，或 Program/Sysvar 类型"),
+    ("authority_like", "名字像权限账户"),
+    ("program_like", "名字像程序账户"),
+    ("sysvar_like", "名字像 sysvar 账户"),
+    ("deserialized", "处理函数中读取并反序列化了账户数据"),
+    ("passed_to_cpi", "传给了 CPI"),
+];
+
+/// 整条指令的条件及其含义
+const HANDLER_TRAITS: &[(&str, &str)] = &[
+    ("has_signer", "有签名者账户"),
+    ("cpi", "调用了其他程序"),
+    ("signed_cpi", "以PDA签名调用其他程序"),
+    ("mint_cpi", "通过 CPI 铸造代币"),
+    ("signer_check", "检查了 is_signer"),
+    ("owner_check", "比较了账户的 owner"),
+    ("key_check", "比较了账户地址"),
+    ("key_inequality", "要求两个账户地址不同"),
+    ("program_id_check", "比较了程序ID"),
+    ("sysvar_check", "检查了 sysvar 的地址"),
+    ("borsh_deserialize", "用 borsh 直接反序列化"),
+    ("discriminator_check", "检查了判别值"),
+    ("serialize_write", "把数据序列化写回账户"),
+    ("initialized_check", "检查了 is_initialized"),
+    ("same_type_mut_pair", "有两个同类型的可写账户"),
+    ("create_program_address", "调用了 create_program_address"),
+    ("find_program_address", "调用了 find_program_address"),
+    ("manual_close", "手动把 lamports 清零"),
+    (
+        "close_guard",
+        "用 close 约束、.close(..) 或关闭标记关闭账户",
+    ),
+    ("load_instruction_at", "调用了已弃用的 load_instruction_at"),
+];
+
+/// 结构特征 (肯定条件) 和缺少的防护 (否定条件) 的权重
+const POSITIVE_WEIGHT: f64 = 2.0;
+const NEGATIVE_WEIGHT: f64 = 1.0;
+
+/// `agent known-vulns` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct KnownVulnsArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 追加的模式文件 (TOML，可重复)，与内置模式库格式相同
+    #[arg(long = "corpus", value_name = "FILE")]
+    corpus: Vec<PathBuf>,
+
+    /// 不使用内置的模式库
+    #[arg(long)]
+    no_builtin: bool,
+
+    /// 报告的最低相似度 (0-1)
+    #[arg(long, value_name = "SCORE", default_value_t = 0.8)]
+    min_similarity: f64,
+
+    /// 输出文件，默认为产物目录下的 known_vulns.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 模式文件的顶层结构
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CorpusFile {
+    patterns: Vec<Pattern>,
+}
+
+/// 一个已知漏洞模式
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Pattern {
+    id: String,
+    title: String,
+    /// 参考案例的链接
+    reference: String,
+    description: String,
+    #[serde(default)]
+    field: Vec<String>,
+    #[serde(default)]
+    handler: Vec<String>,
+    #[serde(default)]
+    class: Option<VulnClass>,
+    #[serde(default)]
+    cwe: Vec<u32>,
+}
+
+/// 一个条件：特征名及是否要求不满足
+#[derive(Debug)]
+struct Condition {
+    text: String,
+    name: String,
+    negated: bool,
+}
+
+impl Condition {
+    fn weight(&self) -> f64 {
+        if self.negated {
+            NEGATIVE_WEIGHT
+        } else {
+            POSITIVE_WEIGHT
+        }
+    }
+
+    fn holds(&self, traits: &BTreeSet<&str>) -> bool {
+        traits.contains(self.name.as_str()) != self.negated
+    }
+}
+
+/// 检查过条件的模式
+struct CompiledPattern {
+    pattern: Pattern,
+    field: Vec<Condition>,
+    handler: Vec<Condition>,
+}
+
+/// 一条指令与一个模式的相似之处
+#[derive(Serialize, Debug)]
+struct Match {
+    pattern: String,
+    title: String,
+    reference: String,
+    instruction: String,
+    similarity: f64,
+    /// 满足 field 条件的字段
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    matched: Vec<String>,
+    missing: Vec<String>,
+    message: String,
+    file: PathBuf,
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class: Option<VulnClass>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cwe: Vec<u32>,
+}
+
+/// 模式库中的一个模式
+#[derive(Serialize, Debug)]
+struct CorpusEntry {
+    id: String,
+    title: String,
+    reference: String,
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class: Option<VulnClass>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cwe: Vec<u32>,
+}
+
+/// known_vulns.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct KnownVulnsReport {
+    metadata: KnownVulnsMetadata,
+    min_similarity: f64,
+    instructions: usize,
+    corpus: Vec<CorpusEntry>,
+    /// 按相似度从高到低排序
+    matches: Vec<Match>,
+}
+
+#[derive(Serialize, Debug)]
+struct KnownVulnsMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 文本中是否出现了作为完整标识符的 `name`
+fn mentions(text: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(name).any(|(i, _)| {
+        !text[..i].ends_with(is_ident) && !text[i + name.len()..].starts_with(is_ident)
+    })
+}
+
+/// 解析条件，检查特征名
+fn condition(pattern: &str, text: &str, traits: &[(&str, &str)]) -> Result<Condition, String> {
+    let (negated, name) = match text.trim().strip_prefix('!') {
+        Some(name) => (true, name.trim()),
+        None => (false, text.trim()),
+    };
+    if !traits.iter().any(|(t, _)| *t == name) {
+        return Err(format!(
+            "模式 '{}' 的条件 '{}' 不是已知的特征",
+            pattern, text
+        ));
+    }
+    Ok(Condition {
+        text: text.trim().to_string(),
+        name: name.to_string(),
+        negated,
+    })
+}
+
+impl CompiledPattern {
+    fn new(pattern: Pattern) -> Result<Self, String> {
+        if pattern.field.is_empty() && pattern.handler.is_empty() {
+            return Err(format!("模式 '{}' 没有条件", pattern.id));
+        }
+        let field = pattern
+            .field
+            .iter()
+            .map(|c| condition(&pattern.id, c, FIELD_TRAITS))
+            .collect::<Result<_, _>>()?;
+        let handler = pattern
+            .handler
+            .iter()
+            .map(|c| condition(&pattern.id, c, HANDLER_TRAITS))
+            .collect::<Result<_, _>>()?;
+        Ok(CompiledPattern {
+            pattern,
+            field,
+            handler,
+        })
+    }
+}
+
+/// 解析模式文件的内容
+fn parse_corpus(content: &str, origin: &str) -> Result<Vec<Pattern>, Box<dyn Error>> {
+    let file: CorpusFile =
+        toml::from_str(content).map_err(|e| format!("模式文件 '{}' 格式错误: {}", origin, e))?;
+    Ok(file.patterns)
+}
+
+/// 字段是否有以 `key` 为名的约束；`token::mint` 等与 `mint` 相同
+fn flag(field: &AccountsField, key: &str) -> bool {
+    field
+        .constraints
+        .iter()
+        .any(|c| constraint_key(c).trim_start_matches("token::") == key)
+}
+
+/// 一个字段的特征
+fn field_traits(
+    field: &AccountsField,
+    signers: &[&str],
+    body: &str,
+    passed_to_cpi: &BTreeSet<String>,
+) -> BTreeSet<&'static str> {
+    let flag = |key: &str| flag(field, key);
+    let base = field.base_type();
+    let name = field.name.to_lowercase();
+    let mut traits = BTreeSet::new();
+    let mut add = |condition: bool, name: &'static str| {
+        if condition {
+            traits.insert(name);
+        }
+    };
+    add(UNCHECKED_TYPES.contains(&base), "unchecked");
+    add(DATA_ACCOUNT_TYPES.contains(&base), "data_account");
+    add(
+        OWNER_CHECKED_TYPES.contains(&base) || flag("owner"),
+        "owner_checked",
+    );
+    add(field.is_signer(), "signer");
+    add(
+        ["mut", "init", "init_if_needed", "zero", "close"]
+            .iter()
+            .any(|f| flag(f)),
+        "mut",
+    );
+    add(
+        ["init", "init_if_needed", "zero"].iter().any(|f| flag(f)),
+        "init",
+    );
+    add(flag("close"), "close");
+    add(flag("has_one"), "has_one");
+    add(flag("constraint"), "constraint");
+    add(flag("seeds"), "seeds");
+    add(
+        field
+            .constraints
+            .iter()
+            .filter(|c| c.starts_with("seeds"))
+            .any(|c| signers.iter().any(|s| mentions(c, s))),
+        "seeds_with_signer",
+    );
+    add(
+        ADDRESS_CHECKED_TYPES.contains(&base) || flag("address"),
+        "address",
+    );
+    add(
+        AUTHORITY_WORDS.iter().any(|w| name.contains(w)),
+        "authority_like",
+    );
+    add(
+        name == "program" || name.ends_with("_program"),
+        "program_like",
+    );
+    add(
+        name.contains("sysvar") || SYSVAR_NAMES.contains(&name.as_str()),
+        "sysvar_like",
+    );
+    add(
+        body.lines().any(|line| {
+            mentions(line, &field.name) && DESERIALIZE_MARKERS.iter().any(|m| line.contains(m))
+        }),
+        "deserialized",
+    );
+    add(passed_to_cpi.contains(&field.name), "passed_to_cpi");
+    traits
+}
+
+/// 整条指令的特征；`checks` 为处理函数、它传递调用的函数和 Accounts 约束的文本
+fn handler_traits(fields: &[AccountsField], body: &str, checks: &str) -> BTreeSet<&'static str> {
+    let lines: Vec<&str> = checks.lines().collect();
+    let compares = |line: &str| {
+        line.contains("==")
+            || line.contains("!=")
+            || line.contains("require_keys_eq!")
+            || line.contains("require_keys_neq!")
+            || line.contains(".eq(")
+    };
+    let any_line = |f: &dyn Fn(&str) -> bool| lines.iter().any(|l| f(l));
+    let targets = cpi_targets(body);
+    let mut mutable_types: HashMap<&str, usize> = HashMap::new();
+    for field in fields {
+        // seeds 不同的PDA不会是同一个账户
+        if flag(field, "mut") && !flag(field, "seeds") {
+            *mutable_types.entry(field.ty.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut traits = BTreeSet::new();
+    let mut add = |condition: bool, name: &'static str| {
+        if condition {
+            traits.insert(name);
+        }
+    };
+    add(fields.iter().any(AccountsField::is_signer), "has_signer");
+    add(!targets.is_empty(), "cpi");
+    add(
+        body.contains("invoke_signed") || body.contains("with_signer"),
+        "signed_cpi",
+    );
+    add(
+        targets
+            .iter()
+            .any(|(_, instruction)| instruction.starts_with("mint_to")),
+        "mint_cpi",
+    );
+    add(checks.contains("is_signer"), "signer_check");
+    add(
+        any_line(&|l| l.contains(".owner") && compares(l)),
+        "owner_check",
+    );
+    add(any_line(&|l| l.contains("key") && compares(l)), "key_check");
+    add(
+        any_line(&|l| l.contains("key") && (l.contains("!=") || l.contains("require_keys_neq!"))),
+        "key_inequality",
+    );
+    add(
+        any_line(&|l| {
+            (l.contains("::ID") || l.contains("::id()") || l.contains("program_id")) && compares(l)
+        }),
+        "program_id_check",
+    );
+    add(
+        checks.contains("check_id(") || any_line(&|l| l.contains("sysvar::") && compares(l)),
+        "sysvar_check",
+    );
+    add(
+        body.contains("try_from_slice") || body.contains("BorshDeserialize::deserialize"),
+        "borsh_deserialize",
+    );
+    add(
+        checks.contains("discriminator") || body.contains("try_deserialize"),
+        "discriminator_check",
+    );
+    add(
+        body.contains(".serialize(") || body.contains("serialize(&mut"),
+        "serialize_write",
+    );
+    add(checks.contains("is_initialized"), "initialized_check");
+    add(mutable_types.values().any(|&n| n > 1), "same_type_mut_pair");
+    add(
+        body.contains("create_program_address("),
+        "create_program_address",
+    );
+    add(
+        body.contains("find_program_address("),
+        "find_program_address",
+    );
+    add(
+        body.lines()
+            .any(|l| l.contains("lamports") && normalize(l).contains("= 0")),
+        "manual_close",
+    );
+    add(
+        body.contains(".close(")
+            || body.contains("CLOSED_ACCOUNT_DISCRIMINATOR")
+            || fields.iter().any(|f| flag(f, "close")),
+        "close_guard",
+    );
+    add(
+        body.lines()
+            .any(|l| l.contains("load_instruction_at(") || l.contains("load_instruction_at (")),
+        "load_instruction_at",
+    );
+    traits
+}
+
+/// 按权重计算满足的条件，返回 (满足的权重, 满足的条件, 不满足的条件)
+fn score<'a>(
+    conditions: &'a [Condition],
+    traits: &BTreeSet<&str>,
+) -> (f64, Vec<&'a str>, Vec<&'a str>) {
+    let (mut weight, mut matched, mut missing) = (0.0, vec![], vec![]);
+    for condition in conditions {
+        if condition.holds(traits) {
+            weight += condition.weight();
+            matched.push(condition.text.as_str());
+        } else {
+            missing.push(condition.text.as_str());
+        }
+    }
+    (weight, matched, missing)
+}
+
+/// 把指令与模式库比较，写出 known_vulns.json
+pub fn run(args: &KnownVulnsArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("known_vulns")? {
+        return Ok(());
+    }
+    if !(0.0..=1.0).contains(&args.min_similarity) {
+        return Err(format!(
+            "--min-similarity 必须在 0 到 1 之间: {}",
+            args.min_similarity
+        )
+        .into());
+    }
+    // 先检查模式，避免在读入AST之后才报错
+    let mut patterns = vec![];
+    if !args.no_builtin {
+        patterns.extend(parse_corpus(BUILTIN_CORPUS, "known_vulns.toml")?);
+    }
+    for path in &args.corpus {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("无法读取模式文件 '{}': {}", path.display(), e))?;
+        patterns.extend(parse_corpus(&content, &path.display().to_string())?);
+    }
+    if patterns.is_empty() {
+        return Err("没有模式：不使用内置模式库时请用 --corpus 指定模式文件".into());
+    }
+    let patterns = patterns
+        .into_iter()
+        .map(CompiledPattern::new)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut structs = HashMap::new();
+    let mut calls = CallGraph::default();
+    let mut bodies: HashMap<(PathBuf, String), String> = HashMap::new();
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        anchor_accounts::collect(root, file, &source, &mut structs);
+        calls.add_file(file, root);
+        let mut items = vec![];
+        collect_function_items(root, &mut items);
+        for (name, item) in items {
+            bodies
+                .entry((file.clone(), name.to_string()))
+                .or_insert_with(|| item.text.clone());
+        }
+    }
+    let writes = Writes::collect(&asts, project);
+
+    let mut instructions = 0;
+    let mut matches = vec![];
+    for program in &writes.programs {
+        for (handler, _) in &program.handlers {
+            instructions += 1;
+            let instruction = format!("{}::{}", program.name, handler.function);
+            let accounts = handler
+                .accounts_struct
+                .as_ref()
+                .and_then(|s| structs.get(s));
+            let fields: &[AccountsField] = accounts.map_or(&[], |a| a.fields.as_slice());
+            let body: Vec<&str> = calls
+                .reachable(&handler.file, &handler.function)
+                .iter()
+                .filter_map(|key| bodies.get(key).map(String::as_str))
+                .collect();
+            let body = body.join("\n");
+            let checks = fields
+                .iter()
+                .flat_map(|f| &f.constraints)
+                .fold(body.clone(), |text, c| text + "\n" + c);
+            let (_, passed_to_cpi) = writes.handler(handler);
+            let signers: Vec<&str> = fields
+                .iter()
+                .filter(|f| f.is_signer())
+                .map(|f| f.name.as_str())
+                .collect();
+            let handler_traits = handler_traits(fields, &body, &checks);
+            let field_traits: Vec<BTreeSet<&str>> = fields
+                .iter()
+                .map(|f| field_traits(f, &signers, &body, &passed_to_cpi))
+                .collect();
+            debug!(instruction = %instruction, traits = ?handler_traits, "指令的特征");
+
+            for compiled in &patterns {
+                let total: f64 = compiled
+                    .field
+                    .iter()
+                    .chain(&compiled.handler)
+                    .map(Condition::weight)
+                    .sum();
+                let (mut weight, mut matched, mut missing) =
+                    score(&compiled.handler, &handler_traits);
+                // field 条件取满足权重最大的字段
+                let mut field = None;
+                if !compiled.field.is_empty() {
+                    let best = fields
+                        .iter()
+                        .zip(&field_traits)
+                        .map(|(f, traits)| (f, score(&compiled.field, traits)))
+                        .max_by(|(_, a), (_, b)| a.0.total_cmp(&b.0));
+                    match best {
+                        Some((f, (w, field_matched, field_missing))) => {
+                            weight += w;
+                            matched.splice(0..0, field_matched);
+                            missing.splice(0..0, field_missing);
+                            field = Some(f);
+                        }
+                        None => {
+                            missing.splice(0..0, compiled.field.iter().map(|c| c.text.as_str()));
+                        }
+                    }
+                }
+                let similarity = weight / total;
+                if similarity < args.min_similarity {
+                    continue;
+                }
+                let pattern = &compiled.pattern;
+                let (file, line) = match (field, accounts) {
+                    (Some(f), Some(a)) => (a.file.clone(), f.line),
+                    _ => (handler.file.clone(), handler.line),
+                };
+                let message = match field {
+                    Some(f) => format!(
+                        "指令 {} 的账户 {} 与已知漏洞「{}」相似 ({:.0}%)，参考 {}",
+                        instruction,
+                        f.name,
+                        pattern.title,
+                        similarity * 100.0,
+                        pattern.reference
+                    ),
+                    None => format!(
+                        "指令 {} 与已知漏洞「{}」相似 ({:.0}%)，参考 {}",
+                        instruction,
+                        pattern.title,
+                        similarity * 100.0,
+                        pattern.reference
+                    ),
+                };
+                matches.push(Match {
+                    pattern: pattern.id.clone(),
+                    title: pattern.title.clone(),
+                    reference: pattern.reference.clone(),
+                    instruction: instruction.clone(),
+                    similarity: (similarity * 100.0).round() / 100.0,
+                    field: field.map(|f| f.name.clone()),
+                    matched: matched.into_iter().map(String::from).collect(),
+                    missing: missing.into_iter().map(String::from).collect(),
+                    message,
+                    file,
+                    line,
+                    class: pattern.class,
+                    cwe: pattern.cwe.clone(),
+                });
+            }
+        }
+    }
+    matches.sort_by(|a, b| {
+        b.similarity
+            .total_cmp(&a.similarity)
+            .then_with(|| a.instruction.cmp(&b.instruction))
+            .then_with(|| a.pattern.cmp(&b.pattern))
+    });
+    for found in &matches {
+        warn!(pattern = %found.pattern, file = %found.file.display(), line = found.line, "{}", found.message);
+    }
+
+    let report = KnownVulnsReport {
+        metadata: KnownVulnsMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        min_similarity: args.min_similarity,
+        instructions,
+        corpus: patterns
+            .iter()
+            .map(|p| CorpusEntry {
+                id: p.pattern.id.clone(),
+                title: p.pattern.title.clone(),
+                reference: p.pattern.reference.clone(),
+                description: p.pattern.description.clone(),
+                class: p.pattern.class,
+                cwe: p.pattern.cwe.clone(),
+            })
+            .collect(),
+        matches,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(KNOWN_VULNS_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        patterns = report.corpus.len(),
+        instructions,
+        matches = report.matches.len(),
+        output = %output.display(),
+        "已写出已知漏洞模式的匹配结果"
+    );
+    Ok(())
+}
@@ -0,0 +1,169 @@
+// labels.rs
+//
+// agent dataset --labels：把标签文件映射到合并图的节点和函数图上
+// 标签文件有两种形式：
+// - JSON对象，键为图的键 (例如 ast:programs/vault/src/lib.rs:deposit) 或函数名，值为整数标签
+// - JSON数组，每一项为一段有问题的源码，例如来自历史漏洞或审计报告：
+//   {"commit": "3f2a9c1", "file": "programs/vault/src/lib.rs", "start_line": 40, "end_line": 52, "cwe": "CWE-862"}
+//   源码范围与之重叠的节点带上该类别，包含这些节点的函数图也带上该类别；
+//   类别按首次出现的顺序编号，commit 与项目当前 HEAD 不一致的条目会被跳过
+
+use crate::merge::MergedGraph;
+use crate::scope::git_lines;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 没有标签的节点或图的标签值
+pub const UNLABELED: i64 = -1;
+
+/// 标签文件中的一段源码
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SpanLabel {
+    /// 标签对应的提交，省略时不检查
+    #[serde(default)]
+    commit: Option<String>,
+    /// 相对于项目根目录的源文件
+    file: PathBuf,
+    /// 起止行号，从 1 开始，包含两端；省略 end_line 时只标记 start_line 一行
+    start_line: usize,
+    #[serde(default)]
+    end_line: Option<usize>,
+    /// CWE编号或自定义的漏洞类别，例如 "CWE-862" 或 "missing-signer-check"
+    #[serde(alias = "class")]
+    cwe: String,
+}
+
+/// 标签文件的两种形式
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LabelsFile {
+    Graphs(HashMap<String, i64>),
+    Spans(Vec<SpanLabel>),
+}
+
+/// 读入的标签
+#[derive(Default)]
+pub struct Labels {
+    graphs: HashMap<String, i64>,
+    spans: Vec<SpanLabel>,
+    /// 源码范围标签的类别，下标即标签值
+    pub classes: Vec<String>,
+}
+
+/// 文件中每一行开头的字节偏移
+fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+/// 字节偏移所在的行号，从 1 开始
+fn line_of(starts: &[usize], byte: usize) -> usize {
+    starts.partition_point(|&start| start <= byte).max(1)
+}
+
+impl Labels {
+    /// 读取标签文件；源码范围标签只保留 commit 与项目当前 HEAD 一致的条目
+    pub fn load(path: &Path, project: &Path) -> Result<Labels, Box<dyn Error>> {
+        let file: LabelsFile = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| format!("无法解析标签文件 {}: {}", path.display(), e))?;
+        let spans = match file {
+            LabelsFile::Graphs(graphs) => {
+                return Ok(Labels {
+                    graphs,
+                    ..Labels::default()
+                })
+            }
+            LabelsFile::Spans(spans) => spans,
+        };
+
+        let head = git_lines(project, &["rev-parse", "HEAD"])
+            .ok()
+            .and_then(|lines| lines.into_iter().next());
+        if head.is_none() && spans.iter().any(|s| s.commit.is_some()) {
+            warn!("无法确定项目当前的提交，忽略标签中的 commit");
+        }
+        let total = spans.len();
+        let spans: Vec<SpanLabel> = spans
+            .into_iter()
+            .filter(|span| match (&span.commit, &head) {
+                (Some(commit), Some(head)) => head.starts_with(commit.as_str()),
+                _ => true,
+            })
+            .collect();
+        if spans.len() < total {
+            info!(
+                skipped = total - spans.len(),
+                head = head.as_deref().unwrap_or(""),
+                "跳过了其他提交的标签"
+            );
+        }
+
+        let mut classes: Vec<String> = vec![];
+        for span in &spans {
+            if !classes.contains(&span.cwe) {
+                classes.push(span.cwe.clone());
+            }
+        }
+        Ok(Labels {
+            graphs: HashMap::new(),
+            spans,
+            classes,
+        })
+    }
+
+    /// 每个节点的标签，按 graph.nodes 的顺序排列；没有源码范围的节点不带标签
+    /// 一个节点与多个标签重叠时取标签文件中靠前的那个
+    pub fn node_labels(&self, graph: &MergedGraph, project: &Path) -> Vec<i64> {
+        let mut labels = vec![UNLABELED; graph.nodes.len()];
+        if self.spans.is_empty() {
+            return labels;
+        }
+        let mut starts: HashMap<&Path, Option<Vec<usize>>> = HashMap::new();
+        for (label, node) in labels.iter_mut().zip(&graph.nodes) {
+            let Some(span) = &node.span else {
+                continue;
+            };
+            let Some(starts) = starts
+                .entry(span.file.as_path())
+                .or_insert_with(|| {
+                    fs::read_to_string(project.join(&span.file))
+                        .ok()
+                        .map(|source| line_starts(&source))
+                })
+                .as_deref()
+            else {
+                continue;
+            };
+            let first = line_of(starts, span.start_byte);
+            let last = line_of(starts, span.end_byte.saturating_sub(1).max(span.start_byte));
+            if let Some(span_label) = self.spans.iter().find(|s| {
+                s.file == span.file
+                    && s.start_line <= last
+                    && first <= s.end_line.unwrap_or(s.start_line)
+            }) {
+                *label = self
+                    .classes
+                    .iter()
+                    .position(|c| *c == span_label.cwe)
+                    .unwrap() as i64;
+            }
+        }
+        labels
+    }
+
+    /// 函数图的标签：优先使用按图的键或函数名给出的标签，其次是图中第一个带标签的节点
+    pub fn graph_label(&self, key: &str, function: &str, node_labels: &[i64]) -> i64 {
+        self.graphs
+            .get(key)
+            .or_else(|| self.graphs.get(function))
+            .copied()
+            .or_else(|| node_labels.iter().copied().find(|&l| l != UNLABELED))
+            .unwrap_or(UNLABELED)
+    }
+}
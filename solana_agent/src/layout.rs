@@ -0,0 +1,534 @@
+// layout.rs
+//
+// 指令数据的 borsh 编码布局：由指令参数及其引用的结构体和枚举推导出每个字段的偏移、大小和判别值
+// Anchor 指令数据以 8 字节的判别值 sha256("global:<指令名>")[..8] 开头，之后依次是各个参数的 borsh 编码
+// borsh 的整数为小端序且不做对齐；String/Vec/HashMap 等以 u32 长度开头，Option 以 1 字节的 0/1 开头，
+// 枚举以 1 字节的变体序号开头；变长字段之后的偏移不再固定，记为 null
+// 字段上的 #[max_len(..)] (Anchor InitSpace) 给出 String/Vec 的最大长度，据此计算最大大小
+// agent idl 为每条指令写出布局，供生成 fuzz 输入和检查客户端与程序的一致性使用；
+// agent space 用同样的方法计算账户结构体的大小，zero-copy 账户则按 repr(C) 的对齐规则计算
+
+use crate::idl::IdlType;
+use crate::symbols::{child, definition_name, AstNode};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// 类型展开的最大深度，防止递归类型 (例如 Box<Self>) 无限展开
+const MAX_DEPTH: usize = 16;
+
+/// SBF 上基本类型的最大对齐：u128/i128 与 u64 一样按 8 字节对齐
+const MAX_ALIGN: usize = 8;
+
+/// 结构体或枚举变体的一个字段；元组字段的名字为 0、1、...
+#[derive(Debug, Clone, Default)]
+pub struct FieldDef {
+    pub name: String,
+    /// Rust写法的类型
+    pub ty: String,
+    /// #[max_len(..)] 给出的最大长度，依次对应由外到内的 String/Vec
+    pub max_len: Vec<usize>,
+}
+
+impl FieldDef {
+    pub fn new(name: impl Into<String>, ty: impl Into<String>) -> Self {
+        FieldDef {
+            name: name.into(),
+            ty: ty.into(),
+            max_len: vec![],
+        }
+    }
+}
+
+/// 结构体或枚举的定义
+#[derive(Debug, Clone)]
+pub enum TypeDef {
+    Struct(Vec<FieldDef>),
+    Enum(Vec<(String, Vec<FieldDef>)>),
+}
+
+/// 字段的编码方式
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// 定长的整数、浮点数、bool 和 Pubkey
+    Primitive,
+    /// 定长数组 [T; N]
+    Array,
+    /// 结构体或元组，各字段依次排列
+    Struct,
+    /// 以 u32 长度开头的 String、Vec、HashMap 等
+    LengthPrefixed,
+    /// 以标记开头的 Option (1 字节) 或 COption (4 字节)
+    Option,
+    /// 以 1 字节变体序号开头的枚举
+    Enum,
+    /// 无法确定布局的类型
+    Unknown,
+}
+
+/// 一个字段的布局
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldLayout {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub encoding: Encoding,
+    /// 相对于指令数据开头的偏移；前面有变长字段时为 null
+    pub offset: Option<usize>,
+    /// 编码后的大小；变长时为 null
+    pub size: Option<usize>,
+    /// 编码后的最小大小 (空集合、None、最短的变体)
+    pub min_size: usize,
+    /// 编码后的最大大小；长度没有上限时为 null
+    pub max_size: Option<usize>,
+    /// 结构体的字段，或者数组、集合和 Option 的元素 (偏移为第一个元素的偏移)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldLayout>,
+    /// 枚举的变体
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<VariantLayout>,
+}
+
+/// 枚举的一个变体
+#[derive(Serialize, Debug, Clone)]
+pub struct VariantLayout {
+    pub name: String,
+    /// 变体序号，即编码的第一个字节
+    pub discriminant: u8,
+    /// 包括序号在内的大小；变长时为 null
+    pub size: Option<usize>,
+    pub fields: Vec<FieldLayout>,
+}
+
+/// 指令数据或账户数据的布局，大小包括开头的判别值
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DataLayout {
+    pub size: Option<usize>,
+    pub min_size: usize,
+    pub max_size: Option<usize>,
+    pub fields: Vec<FieldLayout>,
+}
+
+/// 依次排列的字段的布局和总大小
+struct Sequence {
+    fields: Vec<FieldLayout>,
+    size: Option<usize>,
+    min_size: usize,
+    max_size: Option<usize>,
+}
+
+/// Anchor 指令的判别值：sha256("global:<指令名>") 的前 8 字节
+pub fn anchor_discriminator(instruction: &str) -> Vec<u8> {
+    Sha256::digest(format!("global:{}", instruction))[..8].to_vec()
+}
+
+/// 按顶层的逗号拆分泛型参数、元组元素、属性参数或调用参数
+pub fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' | '(' | '[' | '{' => depth += 1,
+            '>' | ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = text[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+/// 定长基本类型的大小
+fn primitive_size(name: &str) -> Option<usize> {
+    match name {
+        "u8" | "i8" | "bool" => Some(1),
+        "u16" | "i16" => Some(2),
+        "u32" | "i32" | "f32" => Some(4),
+        "u64" | "i64" | "f64" => Some(8),
+        "u128" | "i128" => Some(16),
+        "Pubkey" => Some(32),
+        _ => None,
+    }
+}
+
+/// 向上取整到 align 的倍数
+fn align_up(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+/// 按类型定义展开布局
+struct Layouter<'a> {
+    defs: &'a HashMap<String, TypeDef>,
+}
+
+impl Layouter<'_> {
+    /// 依次排列的字段
+    fn sequence(&self, fields: &[FieldDef], offset: Option<usize>, depth: usize) -> Sequence {
+        let mut sequence = Sequence {
+            fields: vec![],
+            size: Some(0),
+            min_size: 0,
+            max_size: Some(0),
+        };
+        let mut next = offset;
+        for field in fields {
+            let layout = self.layout(&field.name, &field.ty, &field.max_len, next, depth);
+            next = next.zip(layout.size).map(|(o, s)| o + s);
+            sequence.size = sequence.size.zip(layout.size).map(|(a, b)| a + b);
+            sequence.min_size += layout.min_size;
+            sequence.max_size = sequence.max_size.zip(layout.max_size).map(|(a, b)| a + b);
+            sequence.fields.push(layout);
+        }
+        sequence
+    }
+
+    fn layout(
+        &self,
+        name: &str,
+        ty: &str,
+        max_len: &[usize],
+        offset: Option<usize>,
+        depth: usize,
+    ) -> FieldLayout {
+        let ty = ty.trim();
+        let mut field = FieldLayout {
+            name: name.to_string(),
+            ty: ty.to_string(),
+            encoding: Encoding::Unknown,
+            offset,
+            size: None,
+            min_size: 0,
+            max_size: None,
+            fields: vec![],
+            variants: vec![],
+        };
+        if depth > MAX_DEPTH {
+            return field;
+        }
+        let after = |n: usize| offset.map(|o| o + n);
+
+        // [T; N]
+        if let Some(inner) = ty.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            if let Some((item, len)) = inner.rsplit_once(';') {
+                if let Ok(len) = len.trim().parse::<usize>() {
+                    let item = self.layout("item", item, max_len, offset, depth + 1);
+                    field.encoding = Encoding::Array;
+                    field.size = item.size.map(|s| s * len);
+                    field.min_size = item.min_size * len;
+                    field.max_size = item.max_size.map(|s| s * len);
+                    field.fields = vec![item];
+                }
+            }
+            return field;
+        }
+        // 元组，包括 ()
+        if let Some(inner) = ty.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+            let items: Vec<FieldDef> = split_top_level(inner)
+                .into_iter()
+                .enumerate()
+                .map(|(i, t)| FieldDef::new(i.to_string(), t))
+                .collect();
+            let sequence = self.sequence(&items, offset, depth + 1);
+            field.encoding = Encoding::Struct;
+            field.set_sizes(sequence);
+            return field;
+        }
+
+        let (base, args) = match ty.split_once('<') {
+            Some((base, rest)) => (
+                base,
+                split_top_level(rest.strip_suffix('>').unwrap_or(rest)),
+            ),
+            None => (ty, vec![]),
+        };
+        let base = base.rsplit("::").next().unwrap_or(base).trim();
+        if let Some(size) = primitive_size(base) {
+            field.encoding = Encoding::Primitive;
+            field.size = Some(size);
+            field.min_size = size;
+            field.max_size = Some(size);
+            return field;
+        }
+        match (base, args.as_slice()) {
+            ("Box" | "Rc" | "Arc", [inner]) => {
+                let mut inner = self.layout(name, inner, max_len, offset, depth + 1);
+                inner.ty = field.ty;
+                return inner;
+            }
+            ("String", []) => {
+                field.encoding = Encoding::LengthPrefixed;
+                field.min_size = 4;
+                field.max_size = max_len.first().map(|len| 4 + len);
+            }
+            ("Vec" | "VecDeque" | "LinkedList" | "HashSet" | "BTreeSet", [item]) => {
+                let inner_max_len = max_len.get(1..).unwrap_or_default();
+                let item = self.layout("item", item, inner_max_len, after(4), depth + 1);
+                field.encoding = Encoding::LengthPrefixed;
+                field.min_size = 4;
+                field.max_size = max_len
+                    .first()
+                    .zip(item.max_size)
+                    .map(|(len, s)| 4 + len * s);
+                field.fields = vec![item];
+            }
+            ("HashMap" | "BTreeMap", [key, value]) => {
+                let entry = [FieldDef::new("key", *key), FieldDef::new("value", *value)];
+                field.encoding = Encoding::LengthPrefixed;
+                field.min_size = 4;
+                field.fields = self.sequence(&entry, after(4), depth + 1).fields;
+            }
+            ("Option" | "COption", [inner]) => {
+                let tag = if base == "COption" { 4 } else { 1 };
+                let value = self.layout("value", inner, max_len, after(tag), depth + 1);
+                field.encoding = Encoding::Option;
+                field.size = (value.size == Some(0)).then_some(tag);
+                field.min_size = tag;
+                field.max_size = value.max_size.map(|s| s + tag);
+                field.fields = vec![value];
+            }
+            _ => match self.defs.get(base) {
+                Some(TypeDef::Struct(fields)) => {
+                    let sequence = self.sequence(fields, offset, depth + 1);
+                    field.encoding = Encoding::Struct;
+                    field.set_sizes(sequence);
+                }
+                Some(TypeDef::Enum(variants)) => {
+                    field.encoding = Encoding::Enum;
+                    field.max_size = Some(1);
+                    for (discriminant, (variant, fields)) in variants.iter().enumerate() {
+                        let sequence = self.sequence(fields, after(1), depth + 1);
+                        field.min_size = if discriminant == 0 {
+                            sequence.min_size + 1
+                        } else {
+                            field.min_size.min(sequence.min_size + 1)
+                        };
+                        field.max_size = field
+                            .max_size
+                            .zip(sequence.max_size)
+                            .map(|(a, b)| a.max(b + 1));
+                        field.variants.push(VariantLayout {
+                            name: variant.clone(),
+                            discriminant: discriminant as u8,
+                            size: sequence.size.map(|s| s + 1),
+                            fields: sequence.fields,
+                        });
+                    }
+                    // 只有所有变体一样大时枚举才是定长的，borsh 不会补齐较短的变体
+                    let sizes: Vec<Option<usize>> = field.variants.iter().map(|v| v.size).collect();
+                    if sizes.windows(2).all(|w| w[0] == w[1]) {
+                        field.size = sizes.first().copied().unwrap_or(Some(1));
+                    }
+                }
+                None => {}
+            },
+        }
+        field
+    }
+}
+
+impl FieldLayout {
+    fn set_sizes(&mut self, sequence: Sequence) {
+        self.size = sequence.size;
+        self.min_size = sequence.min_size;
+        self.max_size = sequence.max_size;
+        self.fields = sequence.fields;
+    }
+}
+
+/// 指令数据或账户数据的布局：判别值之后依次排列各个字段
+pub fn data_layout(
+    discriminator_len: usize,
+    fields: &[FieldDef],
+    defs: &HashMap<String, TypeDef>,
+) -> DataLayout {
+    let sequence = Layouter { defs }.sequence(fields, Some(discriminator_len), 0);
+    DataLayout {
+        size: sequence.size.map(|s| s + discriminator_len),
+        min_size: sequence.min_size + discriminator_len,
+        max_size: sequence.max_size.map(|s| s + discriminator_len),
+        fields: sequence.fields,
+    }
+}
+
+/// repr(C) 下类型在 SBF 上的大小和对齐，用于 zero-copy 账户；packed 时对齐均为 1
+/// 只支持基本类型、定长数组和由它们组成的结构体，其余类型 (zero-copy 不允许) 返回 None
+fn c_layout(
+    ty: &str,
+    defs: &HashMap<String, TypeDef>,
+    packed: bool,
+    depth: usize,
+) -> Option<(usize, usize)> {
+    let ty = ty.trim();
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    if let Some(inner) = ty.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let (item, len) = inner.rsplit_once(';')?;
+        let (size, align) = c_layout(item, defs, packed, depth + 1)?;
+        return Some((size * len.trim().parse::<usize>().ok()?, align));
+    }
+    let base = ty.rsplit("::").next().unwrap_or(ty);
+    if let Some(size) = primitive_size(base) {
+        // Pubkey 是 [u8; 32]，按 1 字节对齐
+        let align = if packed || base == "Pubkey" {
+            1
+        } else {
+            size.min(MAX_ALIGN)
+        };
+        return Some((size, align));
+    }
+    let Some(TypeDef::Struct(fields)) = defs.get(base) else {
+        return None;
+    };
+    let mut offset = 0;
+    let mut max_align = 1;
+    for field in fields {
+        let (size, align) = c_layout(&field.ty, defs, packed, depth + 1)?;
+        offset = align_up(offset, align) + size;
+        max_align = max_align.max(align);
+    }
+    Some((align_up(offset, max_align), max_align))
+}
+
+/// zero-copy 结构体的大小 (即 size_of::<T>())
+pub fn zero_copy_size(ty: &str, defs: &HashMap<String, TypeDef>, packed: bool) -> Option<usize> {
+    c_layout(ty, defs, packed, 0).map(|(size, _)| size)
+}
+
+/// #[max_len(..)] 属性中的长度；长度不是整数字面量时返回空
+fn max_len(attribute: &str) -> Vec<usize> {
+    let compact = attribute.replace(' ', "");
+    let Some(args) = compact
+        .strip_prefix("#[max_len(")
+        .and_then(|a| a.strip_suffix(")]"))
+    else {
+        return vec![];
+    };
+    split_top_level(args)
+        .into_iter()
+        .map(|len| len.replace('_', "").trim_end_matches("usize").parse().ok())
+        .collect::<Option<Vec<usize>>>()
+        .unwrap_or_default()
+}
+
+/// 结构体或枚举变体的字段：具名字段或元组字段
+fn ast_fields(node: &AstNode) -> Vec<FieldDef> {
+    if let Some(fields) = child(node, "field_declaration_list") {
+        let mut defs = vec![];
+        let mut lengths = vec![];
+        for field in &fields.children {
+            match field.kind.as_str() {
+                "attribute_item" if lengths.is_empty() => lengths = max_len(&field.text),
+                "field_declaration" => {
+                    let name = child(field, "field_identifier").map_or("", |n| &n.text);
+                    let ty = field.children.last().map_or("", |t| t.text.as_str());
+                    defs.push(FieldDef {
+                        max_len: std::mem::take(&mut lengths),
+                        ..FieldDef::new(name, ty)
+                    });
+                }
+                _ => {}
+            }
+        }
+        return defs;
+    }
+    child(node, "ordered_field_declaration_list")
+        .into_iter()
+        .flat_map(|fields| &fields.children)
+        .filter(|c| {
+            !matches!(
+                c.kind.as_str(),
+                "(" | ")" | "," | "visibility_modifier" | "attribute_item" | "line_comment"
+            )
+        })
+        .enumerate()
+        .map(|(i, ty)| FieldDef::new(i.to_string(), ty.text.as_str()))
+        .collect()
+}
+
+/// 在AST中收集结构体和枚举的定义
+pub fn collect_type_defs(root: &AstNode, defs: &mut HashMap<String, TypeDef>) {
+    for node in &root.children {
+        let def = match node.kind.as_str() {
+            "struct_item" => Some(TypeDef::Struct(ast_fields(node))),
+            "enum_item" => Some(TypeDef::Enum(
+                child(node, "enum_variant_list")
+                    .into_iter()
+                    .flat_map(|variants| &variants.children)
+                    .filter(|c| c.kind == "enum_variant")
+                    .filter_map(|variant| {
+                        Some((definition_name(variant)?.text.clone(), ast_fields(variant)))
+                    })
+                    .collect(),
+            )),
+            _ => None,
+        };
+        if let (Some(def), Some(name)) = (def, definition_name(node)) {
+            defs.insert(name.text.clone(), def);
+        }
+        collect_type_defs(node, defs);
+    }
+}
+
+/// 统一格式IDL (.idl.json) 的 types 中定义的结构体和枚举
+pub fn idl_type_defs(types: &[IdlType], defs: &mut HashMap<String, TypeDef>) {
+    let fields = |ty: &IdlType| -> Vec<FieldDef> {
+        ty.fields
+            .iter()
+            .map(|field| FieldDef::new(field.name.as_str(), field.ty.as_str()))
+            .collect()
+    };
+    for ty in types {
+        let def = match ty.kind.as_str() {
+            "struct" => TypeDef::Struct(fields(ty)),
+            "enum" => TypeDef::Enum(
+                ty.variants
+                    .iter()
+                    .map(|variant| (variant.name.clone(), fields(variant)))
+                    .collect(),
+            ),
+            _ => continue,
+        };
+        defs.insert(ty.name.clone(), def);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 字段为 u8、u64、u128、u8 的结构体
+    fn mixed() -> HashMap<String, TypeDef> {
+        let fields = [("a", "u8"), ("b", "u64"), ("c", "u128"), ("d", "u8")]
+            .into_iter()
+            .map(|(name, ty)| FieldDef::new(name, ty))
+            .collect();
+        HashMap::from([("Mixed".to_string(), TypeDef::Struct(fields))])
+    }
+
+    #[test]
+    fn borsh_layout_is_unaligned() {
+        let defs = mixed();
+        let layout = data_layout(8, &[FieldDef::new("mixed", "Mixed")], &defs);
+        assert_eq!(layout.size, Some(8 + 1 + 8 + 16 + 1));
+        let offsets: Vec<_> = layout.fields[0].fields.iter().map(|f| f.offset).collect();
+        assert_eq!(offsets, [Some(8), Some(9), Some(17), Some(33)]);
+    }
+
+    #[test]
+    fn c_layout_aligns_u128_to_8() {
+        let defs = mixed();
+        // a@0, b@8, c@16, d@32，整体按 8 字节对齐
+        assert_eq!(c_layout("Mixed", &defs, false, 0), Some((40, 8)));
+        assert_eq!(c_layout("u128", &defs, false, 0), Some((16, 8)));
+        assert_eq!(zero_copy_size("Mixed", &defs, true), Some(26));
+    }
+}
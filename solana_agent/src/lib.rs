@@ -0,0 +1,98 @@
+// lib.rs
+//
+// agent 的各个子命令；命令行入口 (main.rs) 和 Python 绑定 (python.rs) 共用这些模块
+
+pub mod advisories;
+pub mod analyze;
+pub mod anchor_accounts;
+pub mod autofix;
+pub mod batch;
+pub mod bench;
+pub mod callgraph;
+pub mod chunks;
+pub mod client_graph;
+pub mod client_lint;
+pub mod config;
+pub mod constraints;
+pub mod cu;
+pub mod dashboard;
+pub mod dataset;
+pub mod db;
+pub mod dead_code;
+pub mod detect;
+pub mod events;
+pub mod features;
+pub mod fetch;
+pub mod history;
+pub mod idl;
+pub mod index;
+pub mod known_vulns;
+pub mod labels;
+pub mod layout;
+pub mod lifecycle;
+pub mod link;
+pub mod manifest;
+pub mod merge;
+pub mod mutability;
+pub mod npz;
+pub mod patterns;
+pub mod pda;
+pub mod privileges;
+pub mod protocol;
+pub mod provenance;
+#[cfg(feature = "python")]
+mod python;
+pub mod query;
+pub mod rdf;
+pub mod repl;
+pub mod report;
+pub mod sample;
+pub mod scope;
+pub mod signers;
+pub mod snapshot;
+pub mod space;
+pub mod suppressions;
+pub mod symbols;
+pub mod sysvars;
+pub mod taxonomy;
+pub mod test_coverage;
+pub mod tokens;
+pub mod view;
+
+/// 与CFG、CPG生成器共用的图格式
+pub use solana_graph::graph;
+
+use clap::ValueEnum;
+
+/// 日志的输出格式
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum LogFormat {
+    /// 人类可读的文本格式
+    Text,
+    /// 每行一个JSON对象
+    Json,
+}
+
+/// 日志相关的选项，需要原样转发给各个生成器
+pub struct LogOptions {
+    pub verbose: u8,
+    pub quiet: bool,
+    pub format: LogFormat,
+}
+
+impl LogOptions {
+    /// 转换为生成器能理解的命令行参数
+    pub fn to_tool_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if self.quiet {
+            args.push("--quiet".to_string());
+        }
+        for _ in 0..self.verbose {
+            args.push("--verbose".to_string());
+        }
+        if let LogFormat::Json = self.format {
+            args.push("--log-format=json".to_string());
+        }
+        args
+    }
+}
@@ -0,0 +1,492 @@
+// lifecycle.rs
+//
+// agent lifecycle：按账户类型推断各条指令隐含的生命周期 (创建 → 修改 → 关闭)，写出每种账户的状态机 lifecycle.json 和 lifecycle.dot
+// 账户类型取自 Accounts 结构体中的 Account<'info, T>、AccountLoader<'info, T> 和 InterfaceAccount<'info, T>，只看项目中用 #[account] 定义的 T；
+// 指令对字段的操作：init/zero 约束为创建，init_if_needed 为按需创建，close 约束或 .close(..) 调用为关闭，
+// 处理函数及其传递调用的函数中的写入 (见 mutability.rs) 为修改，其余为读取
+// 状态机有三个状态：uninitialized、initialized 和 closed；关闭后再次创建时从 closed 回到 initialized
+// 异常：同一种账户有多条创建路径 (multiple_init)、关闭后可以被 init_if_needed 重新初始化 (reinit_after_close)、
+// 同一函数中关闭之后仍然修改账户 (mutate_after_close)，以及有修改或关闭却没有任何指令创建的账户 (never_initialized)
+
+use crate::anchor_accounts;
+use crate::config::ArtifactsArgs;
+use crate::layout::split_top_level;
+use crate::manifest::now_rfc3339;
+use crate::mutability::{WriteKind, Writes};
+use crate::symbols::{definition_name, load_asts, normalize, AstNode};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const LIFECYCLE_FILE_NAME: &str = "lifecycle.json";
+const LIFECYCLE_DOT_FILE_NAME: &str = "lifecycle.dot";
+
+/// 带有账户数据类型的账户类型
+const DATA_ACCOUNT_TYPES: &[&str] = &["Account", "AccountLoader", "InterfaceAccount"];
+
+/// `agent lifecycle` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct LifecycleArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 lifecycle.json；lifecycle.dot 写在同一目录下
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 账户的状态
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum State {
+    Uninitialized,
+    Initialized,
+    Closed,
+}
+
+impl State {
+    fn name(self) -> &'static str {
+        match self {
+            State::Uninitialized => "uninitialized",
+            State::Initialized => "initialized",
+            State::Closed => "closed",
+        }
+    }
+}
+
+/// 指令对账户的操作
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum Operation {
+    /// init 或 zero
+    Init,
+    InitIfNeeded,
+    Mutate,
+    Close,
+    Read,
+}
+
+impl Operation {
+    fn name(self) -> &'static str {
+        match self {
+            Operation::Init => "init",
+            Operation::InitIfNeeded => "init_if_needed",
+            Operation::Mutate => "mutate",
+            Operation::Close => "close",
+            Operation::Read => "read",
+        }
+    }
+}
+
+/// 一条指令对一个账户字段的使用
+#[derive(Serialize, Debug)]
+struct Use {
+    instruction: String,
+    accounts_struct: String,
+    field: String,
+    operations: BTreeSet<Operation>,
+    file: PathBuf,
+    line: usize,
+}
+
+/// 状态机的一条边
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Transition {
+    from: State,
+    to: State,
+    instruction: String,
+    operation: Operation,
+}
+
+/// 一种账户的生命周期
+#[derive(Serialize, Debug)]
+struct AccountLifecycle {
+    account_type: String,
+    uses: Vec<Use>,
+    transitions: Vec<Transition>,
+}
+
+/// 异常的种类
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum AnomalyKind {
+    MultipleInit,
+    ReinitAfterClose,
+    MutateAfterClose,
+    NeverInitialized,
+}
+
+#[derive(Serialize, Debug)]
+struct Anomaly {
+    kind: AnomalyKind,
+    account_type: String,
+    message: String,
+    instructions: BTreeSet<String>,
+    file: PathBuf,
+    line: usize,
+}
+
+/// lifecycle.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct LifecycleReport {
+    metadata: LifecycleMetadata,
+    anomalies: Vec<Anomaly>,
+    accounts: Vec<AccountLifecycle>,
+}
+
+#[derive(Serialize, Debug)]
+struct LifecycleMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// Account<'info, T> 等类型中的数据类型 T，去掉 Box<..>、Option<..> 和路径
+fn data_type(ty: &str) -> Option<String> {
+    let mut ty = ty.replace(char::is_whitespace, "");
+    for wrapper in ["Box<", "Option<"] {
+        if let Some(inner) = ty.strip_prefix(wrapper).and_then(|t| t.strip_suffix('>')) {
+            ty = inner.to_string();
+        }
+    }
+    let (base, rest) = ty.split_once('<')?;
+    if !DATA_ACCOUNT_TYPES.contains(&base.rsplit("::").next().unwrap_or(base)) {
+        return None;
+    }
+    let arguments = rest.strip_suffix('>')?;
+    let inner = split_top_level(arguments).into_iter().last()?.trim();
+    let inner = inner.split('<').next().unwrap_or(inner);
+    Some(inner.rsplit("::").next().unwrap_or(inner).to_string())
+}
+
+/// 在AST中收集 `#[account]` 定义的账户类型
+fn collect_account_types(node: &AstNode, account_types: &mut HashSet<String>) {
+    let mut is_account = false;
+    for item in &node.children {
+        match item.kind.as_str() {
+            "attribute_item" => {
+                let text = normalize(&item.text);
+                is_account |= text == "#[account]" || text.starts_with("#[account(");
+                continue;
+            }
+            "line_comment" | "block_comment" => continue,
+            "struct_item" if is_account => {
+                if let Some(name) = definition_name(item) {
+                    account_types.insert(name.text.clone());
+                }
+            }
+            _ => {}
+        }
+        is_account = false;
+        collect_account_types(item, account_types);
+    }
+}
+
+/// 操作对应的状态转移
+fn transitions(operation: Operation, closable: bool) -> Vec<(State, State)> {
+    let mut edges = match operation {
+        Operation::Init => vec![(State::Uninitialized, State::Initialized)],
+        Operation::InitIfNeeded => vec![
+            (State::Uninitialized, State::Initialized),
+            (State::Initialized, State::Initialized),
+        ],
+        Operation::Mutate => vec![(State::Initialized, State::Initialized)],
+        Operation::Close => vec![(State::Initialized, State::Closed)],
+        Operation::Read => vec![],
+    };
+    if closable && matches!(operation, Operation::Init | Operation::InitIfNeeded) {
+        edges.push((State::Closed, State::Initialized));
+    }
+    edges
+}
+
+/// 每种账户一个子图，同一对状态之间的边合并为一条，标签为指令和操作
+fn to_dot(accounts: &[AccountLifecycle]) -> String {
+    let mut dot = String::from("digraph lifecycle {\n    rankdir=LR;\n    node [shape=ellipse];\n");
+    for (i, account) in accounts
+        .iter()
+        .filter(|a| !a.transitions.is_empty())
+        .enumerate()
+    {
+        writeln!(dot, "    subgraph cluster_{} {{", i).unwrap();
+        writeln!(dot, "        label=\"{}\";", account.account_type).unwrap();
+        let id = |state: State| format!("\"{}/{}\"", account.account_type, state.name());
+        let states: BTreeSet<State> = account
+            .transitions
+            .iter()
+            .flat_map(|t| [t.from, t.to])
+            .collect();
+        for state in states {
+            let style = match state {
+                State::Uninitialized => " style=dashed",
+                State::Closed => " style=filled fillcolor=\"#eeeeee\"",
+                State::Initialized => "",
+            };
+            writeln!(
+                dot,
+                "        {} [label=\"{}\"{}];",
+                id(state),
+                state.name(),
+                style
+            )
+            .unwrap();
+        }
+        let mut labels: BTreeMap<(State, State), Vec<String>> = BTreeMap::new();
+        for t in &account.transitions {
+            labels.entry((t.from, t.to)).or_default().push(format!(
+                "{} ({})",
+                t.instruction,
+                t.operation.name()
+            ));
+        }
+        for ((from, to), label) in labels {
+            writeln!(
+                dot,
+                "        {} -> {} [label=\"{}\"];",
+                id(from),
+                id(to),
+                label.join("\\n")
+            )
+            .unwrap();
+        }
+        dot.push_str("    }\n");
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// 推断每种账户的生命周期，写出 lifecycle.json 和 lifecycle.dot
+pub fn run(args: &LifecycleArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("lifecycle")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut structs = HashMap::new();
+    let mut account_types = HashSet::new();
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        anchor_accounts::collect(root, file, &source, &mut structs);
+        collect_account_types(root, &mut account_types);
+    }
+    let writes = Writes::collect(&asts, project);
+
+    // 账户类型 -> 各条指令的使用
+    let mut uses: BTreeMap<String, Vec<Use>> = BTreeMap::new();
+    let mut anomalies = vec![];
+    for program in &writes.programs {
+        for (handler, _) in &program.handlers {
+            let Some((struct_name, accounts)) = handler
+                .accounts_struct
+                .as_ref()
+                .and_then(|s| Some((s, structs.get(s)?)))
+            else {
+                continue;
+            };
+            let instruction = format!("{}::{}", program.name, handler.function);
+            let (handler_writes, _) = writes.handler(handler);
+            // 只看项目中定义的账户类型，其他程序的账户 (TokenAccount 等) 由其他程序创建
+            for (field, account_type) in accounts.fields.iter().filter_map(|f| {
+                let account_type = data_type(&f.ty).filter(|t| account_types.contains(t))?;
+                Some((f, account_type))
+            }) {
+                let init = field.flag("init") || field.flag("zero");
+                let init_if_needed = field.flag("init_if_needed");
+                let field_writes: Vec<_> = handler_writes
+                    .iter()
+                    .filter(|w| w.account == field.name)
+                    .collect();
+                // .close(..) 调用；close 约束隐含的写入没有所在的函数
+                let manual_closes: Vec<_> = field_writes
+                    .iter()
+                    .filter(|w| w.kind == WriteKind::MutBorrow && w.code.contains(".close("))
+                    .collect();
+                let mut operations = BTreeSet::new();
+                if init {
+                    operations.insert(Operation::Init);
+                }
+                if init_if_needed {
+                    operations.insert(Operation::InitIfNeeded);
+                }
+                if field.flag("close") || !manual_closes.is_empty() {
+                    operations.insert(Operation::Close);
+                }
+                let mutations: Vec<_> = field_writes
+                    .iter()
+                    .filter(|w| w.kind != WriteKind::Close && !w.code.contains(".close("))
+                    .collect();
+                // 创建账户的指令中的写入属于初始化
+                if !mutations.is_empty() && !init && !init_if_needed {
+                    operations.insert(Operation::Mutate);
+                }
+                if operations.is_empty() {
+                    operations.insert(Operation::Read);
+                }
+
+                for close in &manual_closes {
+                    let after = mutations.iter().find(|w| {
+                        w.file == close.file && w.function == close.function && w.line > close.line
+                    });
+                    if let Some(after) = after {
+                        anomalies.push(Anomaly {
+                            kind: AnomalyKind::MutateAfterClose,
+                            account_type: account_type.clone(),
+                            message: format!(
+                                "账户 {} 在第 {} 行关闭之后仍被修改",
+                                field.name, close.line
+                            ),
+                            instructions: BTreeSet::from([instruction.clone()]),
+                            file: after.file.clone(),
+                            line: after.line,
+                        });
+                    }
+                }
+                uses.entry(account_type).or_default().push(Use {
+                    instruction: instruction.clone(),
+                    accounts_struct: struct_name.clone(),
+                    field: field.name.clone(),
+                    operations,
+                    file: accounts.file.clone(),
+                    line: field.line,
+                });
+            }
+        }
+    }
+
+    let mut accounts = vec![];
+    for (account_type, uses) in uses {
+        let with = |operation: Operation| -> Vec<&Use> {
+            uses.iter()
+                .filter(|u| u.operations.contains(&operation))
+                .collect()
+        };
+        let instructions_of = |uses: &[&Use]| -> BTreeSet<String> {
+            uses.iter().map(|u| u.instruction.clone()).collect()
+        };
+        let inits: Vec<&Use> = with(Operation::Init)
+            .into_iter()
+            .chain(with(Operation::InitIfNeeded))
+            .collect();
+        let closes = with(Operation::Close);
+        let mutations = with(Operation::Mutate);
+
+        let init_instructions = instructions_of(&inits);
+        if init_instructions.len() > 1 {
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::MultipleInit,
+                account_type: account_type.clone(),
+                message: format!(
+                    "{} 有 {} 条互相独立的创建路径",
+                    account_type,
+                    init_instructions.len()
+                ),
+                instructions: init_instructions.clone(),
+                file: inits[0].file.clone(),
+                line: inits[0].line,
+            });
+        }
+        let reinits = with(Operation::InitIfNeeded);
+        if let (false, Some(first)) = (closes.is_empty(), reinits.first()) {
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::ReinitAfterClose,
+                account_type: account_type.clone(),
+                message: format!(
+                    "{} 关闭之后可以被 init_if_needed 重新初始化，关闭前的修改路径再次可达",
+                    account_type
+                ),
+                instructions: instructions_of(&closes)
+                    .into_iter()
+                    .chain(instructions_of(&reinits))
+                    .collect(),
+                file: first.file.clone(),
+                line: first.line,
+            });
+        }
+        let changed: Vec<&Use> = mutations.iter().chain(&closes).copied().collect();
+        if let (true, Some(first)) = (inits.is_empty(), changed.first()) {
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::NeverInitialized,
+                account_type: account_type.clone(),
+                message: format!("{} 被修改或关闭，但没有任何指令创建它", account_type),
+                instructions: instructions_of(&changed),
+                file: first.file.clone(),
+                line: first.line,
+            });
+        }
+
+        let closable = !closes.is_empty();
+        let transitions: BTreeSet<Transition> = uses
+            .iter()
+            .flat_map(|u| {
+                u.operations.iter().flat_map(move |&operation| {
+                    transitions(operation, closable)
+                        .into_iter()
+                        .map(move |(from, to)| Transition {
+                            from,
+                            to,
+                            instruction: u.instruction.clone(),
+                            operation,
+                        })
+                })
+            })
+            .collect();
+        accounts.push(AccountLifecycle {
+            account_type,
+            uses,
+            transitions: transitions.into_iter().collect(),
+        });
+    }
+
+    for anomaly in &anomalies {
+        warn!(
+            account_type = %anomaly.account_type,
+            file = %anomaly.file.display(),
+            line = anomaly.line,
+            instructions = ?anomaly.instructions,
+            "{}",
+            anomaly.message
+        );
+    }
+
+    let report = LifecycleReport {
+        metadata: LifecycleMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        anomalies,
+        accounts,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(LIFECYCLE_FILE_NAME));
+    let dot_output = output.with_file_name(LIFECYCLE_DOT_FILE_NAME);
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    fs::write(&dot_output, to_dot(&report.accounts))?;
+    info!(
+        account_types = report.accounts.len(),
+        anomalies = report.anomalies.len(),
+        output = %output.display(),
+        dot = %dot_output.display(),
+        "已写出账户生命周期"
+    );
+    Ok(())
+}
@@ -0,0 +1,166 @@
+// link.rs
+//
+// agent link：把客户端调用图 (见 client_graph.rs) 接到程序的合并图 (agent merge) 上，写出一张跨语言的图 linked.json
+// 客户端的 Anchor 调用 program.methods.<ix>(..) 按 IDL 的命名规则 (camelCase -> snake_case) 对应到 #[program] 模块中的处理函数，
+// 从调用点到处理函数在AST层和MIR层的入口各加一条 Call 边，并在调用点上记下指令 `<program>::<ix>`
+// 调用点能解析到程序 X 时只在程序 X 中查找，否则只在指令名在所有程序中唯一时链接
+// 这样"哪些前端流程能走到 withdraw"就是从客户端函数入口沿 Call/ControlFlow 边的可达性问题
+
+use crate::client_graph::client_graph;
+use crate::config::ArtifactsArgs;
+use crate::graph::{EdgeKind, Layer, NodeKind};
+use crate::idl::{collect_programs, handler_graphs, AstProgram};
+use crate::merge::{graph_key, load_merged, MergedEdge, MergedGraph};
+use crate::symbols::{load_asts, AstNode};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use tracing::{debug, info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const LINKED_FILE_NAME: &str = "linked.json";
+
+/// `agent link` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct LinkArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 linked.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 链接后的图及其来源
+pub struct Linked {
+    pub artifacts_dir: PathBuf,
+    pub graph: MergedGraph,
+    /// AST中的 `#[program]` 模块
+    pub programs: Vec<AstProgram>,
+    /// 产物目录中的所有AST
+    pub asts: Vec<(PathBuf, AstNode)>,
+}
+
+/// 合并程序图和客户端调用图，加上客户端调用点到处理函数的边
+pub fn linked_graph(artifacts: &ArtifactsArgs) -> Result<Linked, Box<dyn Error>> {
+    let artifacts_dir = artifacts.artifacts_dir()?;
+    let project = &artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let (_, mut linked) = load_merged(artifacts)?;
+    let mut client = client_graph(&asts);
+    let mut programs = vec![];
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        collect_programs(root, file, &source, &mut programs);
+    }
+
+    // 每个函数图的键及其入口节点：AST层为 Entry 节点，MIR层为第一个节点
+    let keys: BTreeMap<String, (Layer, String)> = linked
+        .nodes
+        .iter()
+        .map(|n| (graph_key(&n.id).to_string(), (n.layer, n.function.clone())))
+        .collect();
+    let mut entries: HashMap<&str, &str> = HashMap::new();
+    for node in &linked.nodes {
+        let key = graph_key(&node.id);
+        if node.kind == NodeKind::Entry {
+            entries.insert(key, &node.id);
+        } else {
+            entries.entry(key).or_insert(&node.id);
+        }
+    }
+
+    let mut edges = vec![];
+    let (mut calls, mut unlinked) = (0, 0);
+    for node in &mut client.nodes {
+        if node
+            .properties
+            .get("category")
+            .is_none_or(|c| c != "anchor")
+        {
+            continue;
+        }
+        calls += 1;
+        let instruction = node.properties["instruction"].as_str().unwrap_or_default();
+        let program = node.properties.get("program").and_then(|p| p.as_str());
+        let candidates: Vec<_> = programs
+            .iter()
+            .filter(|p| program.is_none_or(|name| name == p.name))
+            .flat_map(|p| {
+                p.handlers
+                    .iter()
+                    .filter(|(h, _)| h.function == instruction)
+                    .map(move |(h, _)| (p, h))
+            })
+            .collect();
+        let [(program, handler)] = candidates.as_slice() else {
+            if candidates.is_empty() {
+                warn!(
+                    call_site = %node.id,
+                    instruction,
+                    program = program.unwrap_or(""),
+                    "客户端调用的指令在程序中找不到处理函数"
+                );
+            } else {
+                debug!(call_site = %node.id, instruction, "指令名不唯一，未链接");
+            }
+            unlinked += 1;
+            continue;
+        };
+        for key in handler_graphs(&keys, &program.name, handler) {
+            if let Some(entry) = entries.get(key.as_str()) {
+                edges.push(MergedEdge {
+                    source: node.id.clone(),
+                    target: entry.to_string(),
+                    kind: EdgeKind::Call,
+                });
+            }
+        }
+        node.properties.insert(
+            "handler".to_string(),
+            json!(format!("{}::{}", program.name, handler.function)),
+        );
+    }
+
+    info!(
+        client_calls = calls,
+        linked = calls - unlinked,
+        unlinked,
+        cross_language_edges = edges.len(),
+        "已链接客户端调用"
+    );
+    linked.nodes.extend(client.nodes);
+    linked.edges.extend(client.edges);
+    linked.edges.extend(edges);
+    Ok(Linked {
+        artifacts_dir,
+        graph: linked,
+        programs,
+        asts,
+    })
+}
+
+/// 写出 linked.json
+pub fn run(args: &LinkArgs) -> Result<(), Box<dyn Error>> {
+    let linked = linked_graph(&args.artifacts)?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| linked.artifacts_dir.join(LINKED_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&linked.graph)?)?;
+    info!(output = %output.display(), "已写出跨语言链接图");
+    Ok(())
+}
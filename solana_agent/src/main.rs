@@ -0,0 +1,190 @@
+// main.rs
+
+use clap::{ArgAction, Parser as ClapParser, Subcommand};
+use solana_agent::{
+    advisories, analyze, autofix, batch, bench, callgraph, client_graph, client_lint, constraints,
+    cu, dashboard, dataset, db, dead_code, detect, events, fetch, history, idl, index, known_vulns,
+    lifecycle, link, merge, mutability, patterns, pda, privileges, protocol, query, repl, report,
+    signers, snapshot, space, sysvars, test_coverage, tokens, view, LogFormat, LogOptions,
+};
+use std::error::Error;
+use tracing_subscriber::EnvFilter;
+
+/// 定义命令行参数结构
+#[derive(ClapParser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// 提高日志详细程度 (-v 输出 debug, -vv 输出 trace)，同时传递给各个生成器
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// 只输出警告和错误
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// 日志输出格式
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+/// 子命令
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 按照 agent.toml 依次运行 AST、CFG 和 CPG 生成器
+    Analyze(analyze::AnalyzeArgs),
+    /// 把各函数的CFG/CPG合并为整个项目的一张图，补上函数间的调用边和跨层的同源边
+    Merge(merge::MergeArgs),
+    /// 在生成的图上执行可达性、支配关系和节点属性查询
+    Query(query::QueryArgs),
+    /// 启动本地Web服务，在浏览器中交互式地浏览生成的CFG/CPG
+    View(view::ViewArgs),
+    /// 从AST导出符号的定义、引用和悬停信息，供代码导航工具使用
+    Index(index::IndexArgs),
+    /// 完整运行一次流水线，报告各阶段、各语言和各文件大小区间的耗时与峰值内存
+    Bench(bench::BenchArgs),
+    /// 把函数图导出为 PyG/DGL 可以直接读取的图学习数据集 (npz)
+    Dataset(dataset::DatasetArgs),
+    /// 把所有函数的复杂度、unsafe 用法等指标按crate和指令汇总为 dashboard.json 和 CSV
+    Dashboard(dashboard::DashboardArgs),
+    /// 读取 Anchor IDL (或从AST推导)，把每条指令及其参数和账户链接到处理函数的CFG/CPG
+    Idl(idl::IdlArgs),
+    /// 计算账户结构体的大小和租金豁免金额，检查 init/realloc 的 space 是否与之相符
+    Space(space::SpaceArgs),
+    /// 列出程序派生的所有PDA及其种子，哪些指令创建、使用它们，以及种子格式的冲突
+    Pda(pda::PdaArgs),
+    /// 按指令列出 Clock、Rent、Instructions 等 sysvar 的使用，标出已弃用的用法
+    Sysvars(sysvars::SysvarsArgs),
+    /// 画出各指令对代币账户和 mint 执行的 transfer、mint_to、burn 等 SPL Token 操作
+    Tokens(tokens::TokensArgs),
+    /// 找出受管理员权限保护的指令，输出哪些密钥可以调用哪些修改状态的指令
+    Privileges(privileges::PrivilegesArgs),
+    /// 追踪 Signer 和 PDA 签名如何进入 CPI，标出把用户签名转交给特权 CPI 却没有检查的指令
+    Signers(signers::SignersArgs),
+    /// 对照指令实际写入的账户和声明的可写性，找出缺少的 #[account(mut)] 和多余的 mut
+    Mutability(mutability::MutabilityArgs),
+    /// 列出每个账户字段由 Anchor 检查了哪些方面，按使用方式排出没有任何约束的字段
+    Constraints(constraints::ConstraintsArgs),
+    /// 把 TypeScript 测试中的 program.methods.<ix>() 调用对应到指令，列出没有测试调用的指令
+    TestCoverage(test_coverage::TestCoverageArgs),
+    /// 基于 TypeScript/JavaScript 的AST构建客户端调用图 (Anchor 客户端调用、web3.js 交易构造)，格式与 merged.json 相同
+    ClientGraph(client_graph::ClientGraphArgs),
+    /// 把客户端的 Anchor 调用链接到程序的处理函数，写出程序图与客户端调用图合在一起的跨语言图
+    Link(link::LinkArgs),
+    /// 提取 Anchor 事件的定义、emit! 位置和客户端监听者，写出事件流图
+    Events(events::EventsArgs),
+    /// 检查客户端代码中已废弃或危险的 web3.js 用法 (Account、confirmTransaction、私钥、skipPreflight 等)
+    ClientLint(client_lint::ClientLintArgs),
+    /// 用带元变量 ($X) 和 ... 的代码片段在AST上查找代码，规则写在TOML文件中或用 -e 直接给出
+    Patterns(patterns::PatternsArgs),
+    /// 在合并图上执行规则文件中的 source/sink/guard 规则
+    Detect(detect::DetectArgs),
+    /// 把各阶段的问题汇总为一份去重后的报告 (SARIF、JSON 或 HTML)
+    Report(report::ReportArgs),
+    /// 整个项目 (程序和客户端) 的函数级调用图，包括跨语言调用和 CPI，写出 callgraph.json 和 callgraph.dot
+    Callgraph(callgraph::CallgraphArgs),
+    /// 估算每个处理函数的最坏路径CU开销，与基线 manifest.json 比较，报告增长超过阈值的处理函数
+    CompareCu(cu::CompareCuArgs),
+    /// 在调用图上从入口 (客户端、测试、trait 实现等) 求可达性，列出到达不了的处理函数和程序函数，写出 dead_code.json
+    DeadCode(dead_code::DeadCodeArgs),
+    /// 按账户类型推断指令隐含的生命周期 (创建 → 修改 → 关闭) 并检查异常，写出 lifecycle.json 和 lifecycle.dot
+    Lifecycle(lifecycle::LifecycleArgs),
+    /// 解析项目中多个程序之间的 CPI (按程序名和声明的程序ID)，写出程序级调用图 protocol.json 和 protocol.dot
+    Protocol(protocol::ProtocolArgs),
+    /// 把每条指令的结构与内置的已知漏洞模式库 (sealevel-attacks 和公开的攻击事件) 比较，写出 known_vulns.json
+    KnownVulns(known_vulns::KnownVulnsArgs),
+    /// 把选定函数的CFG/CPG记录为黄金文件；--verify 时与黄金文件比较，图的结构变化时以非零状态退出
+    Snapshot(snapshot::SnapshotArgs),
+    /// 把一次运行的AST、CFG、CPG、调用图和报告中的问题导入一个 SQLite 数据库 agent.db，附带常用查询的视图
+    Db(db::DbArgs),
+    /// 在一组历史版本上依次做增量分析，写出复杂度、问题数和各处理函数CU估算随版本变化的趋势 history.json
+    History(history::HistoryArgs),
+    /// 读入合并图后进入交互式命令行：列出函数、查看节点、邻居、切片、执行查询和导出子图，支持历史记录和 Tab 补全
+    Repl(repl::ReplArgs),
+    /// 为缺少 mut、丢弃 invoke 返回值和未检查溢出的算术等机械性问题生成建议的修复，写出 autofix.json 和可以 git apply 的 autofix.patch
+    Autofix(autofix::AutofixArgs),
+    /// 把 Cargo.lock 中的依赖与 RustSec 公告对照，在CPG和调用图上定位受影响函数的调用点并判断能否从程序入口到达，写出 advisories.json
+    Advisories(advisories::AdvisoriesArgs),
+    /// 按链上程序地址 (验证构建记录) 或 crates.io 上的名字取得公开的源码，并在其上运行完整的分析流程
+    Fetch(fetch::FetchArgs),
+    /// 按仓库清单克隆或更新多个仓库，用各自的配置分析，写出跨项目的汇总报告和合并的数据集
+    Batch(batch::BatchArgs),
+}
+
+/// 根据命令行参数初始化 tracing 日志
+/// 日志统一写到 stderr；设置了 RUST_LOG 环境变量时以其为准
+fn init_logging(log: &LogOptions) {
+    let default_level = if log.quiet {
+        "warn"
+    } else {
+        match log.verbose {
+            0 => "info",
+            1 => "debug",
+            _ => "trace",
+        }
+    };
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    match log.format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let log = LogOptions {
+        verbose: args.verbose,
+        quiet: args.quiet,
+        format: args.log_format,
+    };
+    init_logging(&log);
+
+    match args.command {
+        Command::Analyze(analyze_args) => analyze::run(&analyze_args, &log),
+        Command::Merge(merge_args) => merge::run(&merge_args),
+        Command::Query(query_args) => query::run(&query_args),
+        Command::View(view_args) => view::run(&view_args),
+        Command::Index(index_args) => index::run(&index_args),
+        Command::Bench(bench_args) => bench::run(&bench_args, &log),
+        Command::Dataset(dataset_args) => dataset::run(&dataset_args),
+        Command::Dashboard(dashboard_args) => dashboard::run(&dashboard_args),
+        Command::Idl(idl_args) => idl::run(&idl_args),
+        Command::Space(space_args) => space::run(&space_args),
+        Command::Pda(pda_args) => pda::run(&pda_args),
+        Command::Sysvars(sysvars_args) => sysvars::run(&sysvars_args),
+        Command::Tokens(tokens_args) => tokens::run(&tokens_args),
+        Command::Privileges(privileges_args) => privileges::run(&privileges_args),
+        Command::Signers(signers_args) => signers::run(&signers_args),
+        Command::Mutability(mutability_args) => mutability::run(&mutability_args),
+        Command::Constraints(constraints_args) => constraints::run(&constraints_args),
+        Command::TestCoverage(test_coverage_args) => test_coverage::run(&test_coverage_args),
+        Command::ClientGraph(client_graph_args) => client_graph::run(&client_graph_args),
+        Command::Link(link_args) => link::run(&link_args),
+        Command::Events(events_args) => events::run(&events_args),
+        Command::ClientLint(client_lint_args) => client_lint::run(&client_lint_args),
+        Command::Patterns(patterns_args) => patterns::run(&patterns_args),
+        Command::Detect(detect_args) => detect::run(&detect_args),
+        Command::Report(report_args) => report::run(&report_args),
+        Command::Callgraph(callgraph_args) => callgraph::run(&callgraph_args),
+        Command::CompareCu(compare_cu_args) => cu::run(&compare_cu_args),
+        Command::DeadCode(dead_code_args) => dead_code::run(&dead_code_args),
+        Command::Lifecycle(lifecycle_args) => lifecycle::run(&lifecycle_args),
+        Command::Protocol(protocol_args) => protocol::run(&protocol_args),
+        Command::KnownVulns(known_vulns_args) => known_vulns::run(&known_vulns_args),
+        Command::Snapshot(snapshot_args) => snapshot::run(&snapshot_args),
+        Command::Db(db_args) => db::run(&db_args),
+        Command::History(history_args) => history::run(&history_args, &log),
+        Command::Repl(repl_args) => repl::run(&repl_args),
+        Command::Autofix(autofix_args) => autofix::run(&autofix_args),
+        Command::Advisories(advisories_args) => advisories::run(&advisories_args),
+        Command::Fetch(fetch_args) => fetch::run(&fetch_args, &log),
+        Command::Batch(batch_args) => batch::run(&batch_args, &log),
+    }
+}
@@ -0,0 +1,229 @@
+// manifest.rs
+
+use crate::chunks::Chunk;
+use crate::scope::Scope;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+/// 各阶段和整次运行的 manifest 文件名
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// 各个生成器在自己的输出目录下写出的 manifest.json
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StageManifest {
+    pub tool: String,
+    pub tool_version: String,
+    pub generated_at: String,
+    pub artifacts: Vec<Artifact>,
+}
+
+/// manifest 中的一条产物记录
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Artifact {
+    pub path: PathBuf,
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_hash: Option<String>,
+    pub hash: String,
+}
+
+/// 输出根目录下的 manifest.json：汇总本次运行所有阶段的产物
+/// 其中的 path 相对于输出根目录，source 相对于项目根目录或输出根目录
+#[derive(Serialize, Debug)]
+pub struct RunManifest {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+    stages: Vec<StageSummary>,
+    /// 使用 --since/--diff 时的分析范围
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<Scope>,
+    /// 分块模式下的各个块
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    chunks: Vec<Chunk>,
+    artifacts: Vec<Artifact>,
+    /// 每个处理函数的CU估算 (见 cu.rs)，供 agent compare-cu 作为基线
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    compute_units: BTreeMap<String, u64>,
+}
+
+/// 上一次运行的 manifest.json，用于增量分析和合并
+#[derive(Deserialize, Debug, Default)]
+pub struct PreviousRunManifest {
+    pub artifacts: Vec<Artifact>,
+    #[serde(default)]
+    pub chunks: Vec<Chunk>,
+    /// 使用 --since/--diff 时的分析范围
+    #[serde(default)]
+    pub scope: Option<Scope>,
+}
+
+impl PreviousRunManifest {
+    /// 读取输出根目录下已有的 manifest.json；不存在或无法解析时返回空的 manifest
+    pub fn load(output_root: &Path) -> PreviousRunManifest {
+        fs::read_to_string(output_root.join(MANIFEST_FILE_NAME))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// 查找某个路径的产物，且该文件仍然存在于输出目录中
+    pub fn find(&self, output_root: &Path, path: &Path) -> Option<&Artifact> {
+        self.artifacts
+            .iter()
+            .find(|a| a.path == path && output_root.join(&a.path).is_file())
+    }
+}
+
+/// 单个阶段的概要
+#[derive(Serialize, Debug)]
+pub struct StageSummary {
+    stage: String,
+    tool: String,
+    tool_version: String,
+    generated_at: String,
+}
+
+/// 计算内容哈希 (blake3，十六进制)
+pub fn content_hash(bytes: &[u8]) -> String {
+    blake3::hash(bytes).to_hex().to_string()
+}
+
+/// 计算一个crate全部Rust源文件的哈希
+/// 以crate根文件所在目录为范围，按路径排序后依次哈希相对路径和内容
+pub fn crate_sources_hash(crate_root: &Path) -> Result<String, Box<dyn Error>> {
+    let src_dir = crate_root.parent().unwrap_or(Path::new("."));
+    let mut files: Vec<PathBuf> = WalkDir::new(src_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "rs"))
+        .collect();
+    files.sort();
+
+    let mut hasher = blake3::Hasher::new();
+    for path in files {
+        let relative = path.strip_prefix(src_dir)?;
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update(&fs::read(&path)?);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+impl Default for RunManifest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunManifest {
+    pub fn new() -> Self {
+        RunManifest {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+            stages: vec![],
+            scope: None,
+            chunks: vec![],
+            artifacts: vec![],
+            compute_units: BTreeMap::new(),
+        }
+    }
+
+    /// 记录本次运行的分析范围
+    pub fn set_scope(&mut self, scope: Scope) {
+        self.scope = Some(scope);
+    }
+
+    /// 记录分块模式下的各个块
+    pub fn set_chunks(&mut self, chunks: Vec<Chunk>) {
+        self.chunks = chunks;
+    }
+
+    /// 记录处理函数的CU估算
+    pub fn set_compute_units(&mut self, compute_units: BTreeMap<String, u64>) {
+        self.compute_units = compute_units;
+    }
+
+    /// 读入某个阶段的 manifest.json，并把其中的路径改写为相对于输出根目录
+    /// `source_dir` 为该阶段输入目录相对于输出根目录的路径 (AST阶段的输入是项目本身，传 None)
+    pub fn add_stage(
+        &mut self,
+        stage: &str,
+        output_root: &Path,
+        stage_dir: &Path,
+        source_dir: Option<&Path>,
+    ) -> Result<(), Box<dyn Error>> {
+        let manifest_path = stage_dir.join(MANIFEST_FILE_NAME);
+        let content = fs::read_to_string(&manifest_path)
+            .map_err(|e| format!("无法读取 {}: {}", manifest_path.display(), e))?;
+        let manifest: StageManifest = serde_json::from_str(&content)?;
+        let prefix = stage_dir.strip_prefix(output_root)?;
+
+        self.stages.push(StageSummary {
+            stage: stage.to_string(),
+            tool: manifest.tool,
+            tool_version: manifest.tool_version,
+            generated_at: manifest.generated_at,
+        });
+        for mut artifact in manifest.artifacts {
+            artifact.path = prefix.join(&artifact.path);
+            if let (Some(source), Some(source_dir)) = (&artifact.source, source_dir) {
+                artifact.source = Some(source_dir.join(source));
+            }
+            self.artifacts.push(artifact);
+        }
+        self.artifacts.push(Artifact {
+            path: prefix.join(MANIFEST_FILE_NAME),
+            kind: "manifest".to_string(),
+            source: None,
+            source_hash: None,
+            hash: content_hash(content.as_bytes()),
+        });
+        Ok(())
+    }
+
+    /// 记录一个不带 manifest 的阶段 (例如直接输出到 stdout 的CPG生成器)
+    pub fn add_stage_summary(&mut self, stage: &str, tool: &str, tool_version: String) {
+        self.stages.push(StageSummary {
+            stage: stage.to_string(),
+            tool: tool.to_string(),
+            tool_version,
+            generated_at: now_rfc3339(),
+        });
+    }
+
+    /// 记录一个由 agent 自己写出的产物
+    pub fn add_artifact(&mut self, artifact: Artifact) {
+        self.artifacts.push(artifact);
+    }
+
+    /// 写入输出根目录下的 manifest.json
+    pub fn write(&mut self, output_root: &Path) -> Result<(), Box<dyn Error>> {
+        self.artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+        fs::write(
+            output_root.join(MANIFEST_FILE_NAME),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// 当前时间的 RFC 3339 表示 (精确到秒)
+/// 设置了 SOURCE_DATE_EPOCH 时使用该时间，使重复运行的输出逐字节相同；该变量会传给各个生成器
+pub fn now_rfc3339() -> String {
+    let time = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .map_or_else(SystemTime::now, |secs| {
+            UNIX_EPOCH + Duration::from_secs(secs)
+        });
+    humantime::format_rfc3339_seconds(time).to_string()
+}
@@ -0,0 +1,406 @@
+// merge.rs
+
+use crate::config::ArtifactsArgs;
+use crate::features::{FeatureArgs, Featurizer};
+use crate::graph::{EdgeKind, Graph, Layer, NodeKind, Span};
+use crate::manifest::{now_rfc3339, PreviousRunManifest};
+use crate::npz::{npy_f32, write_npz};
+use crate::rdf::to_turtle;
+use crate::sample::{self, SampleArgs};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// 能够读取的CFG/CPG JSON文件格式版本 (见各生成器的 SCHEMA_VERSION)
+const SUPPORTED_SCHEMA_VERSION: u32 = 2;
+
+/// merged.json 格式的版本号，格式发生不兼容的变化时递增
+const MERGED_SCHEMA_VERSION: u32 = 1;
+
+/// 合并结果的默认文件名，位于输出目录下
+const MERGED_FILE_NAME: &str = "merged.json";
+
+/// `agent merge` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct MergeArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 合并结果的路径，默认为产物目录下的 merged.json
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// 同时写出节点特征：<OUTPUT>.features.npz (x，行顺序与 nodes 一致) 和各维的名字 <OUTPUT>.features.json
+    #[arg(long)]
+    emit_features: bool,
+
+    /// 同时把合并图写成 RDF (Turtle)：<OUTPUT>.ttl，可以导入支持 SPARQL 的存储
+    #[arg(long)]
+    emit_rdf: bool,
+
+    #[command(flatten)]
+    features: FeatureArgs,
+
+    #[command(flatten)]
+    sample: SampleArgs,
+}
+
+/// 生成器输出的JSON文件中 agent merge 需要的部分
+#[derive(Deserialize)]
+struct GraphFile {
+    metadata: GraphFileMetadata,
+    graphs: Vec<Graph>,
+}
+
+#[derive(Deserialize)]
+struct GraphFileMetadata {
+    schema_version: u32,
+}
+
+/// merged.json 开头的元数据
+#[derive(Serialize)]
+pub struct MergedMetadata {
+    schema_version: u32,
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+impl MergedMetadata {
+    /// 当前版本的元数据
+    pub fn current() -> Self {
+        MergedMetadata {
+            schema_version: MERGED_SCHEMA_VERSION,
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        }
+    }
+}
+
+/// 整个项目的图中的一个节点
+/// `id` 形如 `ast:programs/vault/src/lib.rs:deposit#3`，在整个项目内唯一
+/// `provenance` 为节点对应的源码范围 `file@start..end`，与AST文件中节点的字节范围一致
+#[derive(Serialize)]
+pub struct MergedNode {
+    pub id: String,
+    pub layer: Layer,
+    pub function: String,
+    pub kind: NodeKind,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, Value>,
+}
+
+/// 整个项目的图中的一条边
+#[derive(Serialize)]
+pub struct MergedEdge {
+    pub source: String,
+    pub target: String,
+    pub kind: EdgeKind,
+}
+
+/// merged.json 的顶层结构
+#[derive(Serialize)]
+pub struct MergedGraph {
+    pub metadata: MergedMetadata,
+    pub nodes: Vec<MergedNode>,
+    pub edges: Vec<MergedEdge>,
+}
+
+/// 从某个文件中读入的一张图，`unit` 为它所属的源文件 (AST层) 或crate根文件 (MIR层)
+struct LoadedGraph {
+    unit: String,
+    graph: Graph,
+}
+
+/// 全局节点ID
+pub fn node_id(layer: Layer, unit: &str, function: &str, local_id: usize) -> String {
+    let layer = match layer {
+        Layer::Ast => "ast",
+        Layer::Mir => "mir",
+    };
+    format!("{}:{}:{}#{}", layer, unit, function, local_id)
+}
+
+/// 节点ID中 `#` 之前的部分，标识节点所属的函数图
+pub fn graph_key(id: &str) -> &str {
+    id.rsplit_once('#').map_or(id, |(key, _)| key)
+}
+
+/// 函数路径的最后一段，例如 `instructions::deposit` -> `deposit`
+fn short_name(function: &str) -> &str {
+    function.rsplit("::").next().unwrap_or(function)
+}
+
+/// 找出文本中所有形如 `name(` 的调用，返回被调用的名字
+pub fn called_names(text: &str) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let mut names = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if is_ident(bytes[i]) && (i == 0 || !is_ident(bytes[i - 1])) {
+            let start = i;
+            while i < bytes.len() && is_ident(bytes[i]) {
+                i += 1;
+            }
+            // 允许泛型参数，例如 `foo::<T>(`
+            let mut j = i;
+            if text[j..].starts_with("::<") {
+                j = text[j..].find('>').map_or(j, |end| j + end + 1);
+            }
+            if bytes.get(j) == Some(&b'(') && !bytes[start].is_ascii_digit() {
+                names.push(&text[start..i]);
+            }
+        } else {
+            i += 1;
+        }
+    }
+    names
+}
+
+/// 去掉源码路径中的项目根目录前缀，使MIR层与AST层的路径可以直接比较
+fn normalize_span(span: &Span, project: &Path) -> Span {
+    Span {
+        file: span
+            .file
+            .strip_prefix(project)
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|_| span.file.clone()),
+        start_byte: span.start_byte,
+        end_byte: span.end_byte,
+    }
+}
+
+/// 按照输出目录中的 manifest.json 读入所有JSON格式的CFG/CPG
+fn load_graphs(output_dir: &Path) -> Result<Vec<LoadedGraph>, Box<dyn Error>> {
+    let manifest = PreviousRunManifest::load(output_dir);
+    let mut loaded = vec![];
+    for artifact in &manifest.artifacts {
+        let is_graph = matches!(artifact.kind.as_str(), "cfg" | "cpg")
+            && artifact.path.extension().is_some_and(|ext| ext == "json");
+        let Some(source) = artifact.source.as_ref().filter(|_| is_graph) else {
+            continue;
+        };
+        let path = output_dir.join(&artifact.path);
+        let file: GraphFile = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("无法解析 {}: {}", path.display(), e))?;
+        if file.metadata.schema_version != SUPPORTED_SCHEMA_VERSION {
+            warn!(
+                path = %path.display(),
+                schema_version = file.metadata.schema_version,
+                "不支持的图格式版本，已跳过；请重新运行 agent analyze --full"
+            );
+            continue;
+        }
+
+        // CFG的 source 是 ast/<源文件>.ast.json，CPG的 source 是crate根文件
+        let source = source.strip_prefix("ast").unwrap_or(source);
+        let unit = source
+            .to_string_lossy()
+            .trim_end_matches(".ast.json")
+            .to_string();
+        for graph in file.graphs {
+            loaded.push(LoadedGraph {
+                unit: unit.clone(),
+                graph,
+            });
+        }
+    }
+    Ok(loaded)
+}
+
+/// 把所有函数的图合并为一张图，并补上调用边和跨层的同源边
+fn merge_graphs(graphs: Vec<LoadedGraph>, project: &Path) -> MergedGraph {
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    // (层, 函数短名) -> [(所属单元, 入口节点ID)]
+    let mut entries: HashMap<(Layer, String), Vec<(String, String)>> = HashMap::new();
+
+    for LoadedGraph { unit, graph } in &graphs {
+        let id = |local_id| node_id(graph.layer, unit, &graph.function, local_id);
+        // AST层有显式的入口节点；MIR层的入口是 bb0 的第一个节点
+        let entry = graph
+            .nodes
+            .iter()
+            .find(|n| n.kind == NodeKind::Entry)
+            .or(graph.nodes.first());
+        if let Some(entry) = entry {
+            entries
+                .entry((graph.layer, short_name(&graph.function).to_string()))
+                .or_default()
+                .push((unit.clone(), id(entry.id)));
+        }
+
+        for node in &graph.nodes {
+            let span = node.span.as_ref().map(|span| normalize_span(span, project));
+            nodes.push(MergedNode {
+                id: id(node.id),
+                layer: graph.layer,
+                function: graph.function.clone(),
+                kind: node.kind,
+                label: node.label.clone(),
+                provenance: span.as_ref().map(|span| {
+                    format!(
+                        "{}@{}..{}",
+                        span.file.display(),
+                        span.start_byte,
+                        span.end_byte
+                    )
+                }),
+                span,
+                properties: node.properties.clone(),
+            });
+        }
+        for edge in &graph.edges {
+            edges.push(MergedEdge {
+                source: id(edge.source),
+                target: id(edge.target),
+                kind: edge.kind,
+            });
+        }
+    }
+
+    // 调用边：优先解析到同一单元内的同名函数，否则只在名字全局唯一时解析
+    let mut call_edges = HashSet::new();
+    for LoadedGraph { unit, graph } in &graphs {
+        for node in &graph.nodes {
+            let is_call_site = match graph.layer {
+                Layer::Ast => node.kind != NodeKind::Exit,
+                Layer::Mir => node.kind == NodeKind::Terminator,
+            };
+            if !is_call_site {
+                continue;
+            }
+            let source = node_id(graph.layer, unit, &graph.function, node.id);
+            for name in called_names(&node.label) {
+                let Some(candidates) = entries.get(&(graph.layer, name.to_string())) else {
+                    continue;
+                };
+                let local: Vec<_> = candidates.iter().filter(|(u, _)| u == unit).collect();
+                let target = match (local.as_slice(), candidates.as_slice()) {
+                    ([(_, target)], _) | ([], [(_, target)]) => target,
+                    _ => {
+                        debug!(call_site = %source, name, "调用目标不唯一，未解析");
+                        continue;
+                    }
+                };
+                if call_edges.insert((source.clone(), target.clone())) {
+                    edges.push(MergedEdge {
+                        source: source.clone(),
+                        target: target.clone(),
+                        kind: EdgeKind::Call,
+                    });
+                }
+            }
+        }
+    }
+
+    // 同源边：把每个MIR节点连到源码范围包含它的最小的AST层节点
+    let mut ast_spans: HashMap<&Path, Vec<(&Span, &str)>> = HashMap::new();
+    for node in nodes.iter().filter(|n| n.layer == Layer::Ast) {
+        if let Some(span) = &node.span {
+            ast_spans
+                .entry(span.file.as_path())
+                .or_default()
+                .push((span, node.id.as_str()));
+        }
+    }
+    let mut same_source = vec![];
+    for node in nodes.iter().filter(|n| n.layer == Layer::Mir) {
+        let Some(span) = &node.span else {
+            continue;
+        };
+        let target = ast_spans
+            .get(span.file.as_path())
+            .into_iter()
+            .flatten()
+            .filter(|(s, _)| s.start_byte <= span.start_byte && span.end_byte <= s.end_byte)
+            .min_by_key(|(s, _)| s.end_byte - s.start_byte);
+        if let Some((_, target)) = target {
+            same_source.push(MergedEdge {
+                source: node.id.clone(),
+                target: target.to_string(),
+                kind: EdgeKind::SameSource,
+            });
+        }
+    }
+    edges.extend(same_source);
+
+    MergedGraph {
+        metadata: MergedMetadata::current(),
+        nodes,
+        edges,
+    }
+}
+
+/// 读取产物目录中的所有图并合并为一张图
+pub fn load_merged(artifacts: &ArtifactsArgs) -> Result<(PathBuf, MergedGraph), Box<dyn Error>> {
+    let output_dir = artifacts.artifacts_dir()?;
+    let graphs = load_graphs(&output_dir)?;
+    if graphs.is_empty() {
+        return Err(format!(
+            "'{}' 中没有JSON格式的CFG/CPG，请先运行 agent analyze",
+            output_dir.display()
+        )
+        .into());
+    }
+    debug!(graphs = graphs.len(), "开始合并");
+
+    let merged = merge_graphs(graphs, &artifacts.project);
+    let count = |kind| merged.edges.iter().filter(|e| e.kind == kind).count();
+    info!(
+        nodes = merged.nodes.len(),
+        call_edges = count(EdgeKind::Call),
+        same_source_edges = count(EdgeKind::SameSource),
+        "合并完成"
+    );
+    Ok((output_dir, merged))
+}
+
+/// 读取 agent analyze 的输出，写出整个项目的合并图
+pub fn run(args: &MergeArgs) -> Result<(), Box<dyn Error>> {
+    let (output_dir, mut merged) = load_merged(&args.artifacts)?;
+    sample::apply(&args.sample, &mut merged);
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| output_dir.join(MERGED_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&merged)?)?;
+    info!(output = %output.display(), "已写出合并图");
+
+    if args.emit_rdf {
+        let rdf_path = output.with_extension("ttl");
+        fs::write(&rdf_path, to_turtle(&merged))?;
+        info!(output = %rdf_path.display(), "已写出 RDF");
+    }
+
+    if args.emit_features {
+        let featurizer = Featurizer::new(&args.features, &args.artifacts.load_config()?.features);
+        let compute_units = featurizer.load_compute_units(&output_dir)?;
+        let x = featurizer.featurize(&merged, &compute_units);
+        let features_path = output.with_extension("features.npz");
+        write_npz(
+            &features_path,
+            &[(
+                "x.npy",
+                npy_f32(&[merged.nodes.len(), featurizer.dim()], &x),
+            )],
+        )?;
+        fs::write(
+            output.with_extension("features.json"),
+            serde_json::to_string_pretty(&featurizer.names())?,
+        )?;
+        info!(output = %features_path.display(), dim = featurizer.dim(), "已写出节点特征");
+    }
+    Ok(())
+}
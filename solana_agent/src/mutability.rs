@@ -0,0 +1,624 @@
+// mutability.rs
+//
+// agent mutability：对照每条指令实际写入的账户和 Accounts 结构体中声明的可写性，
+// 找出写入了没有标记 mut 的账户 (缺少 #[account(mut)]) 和标记了 mut 却从不写入的账户 (多余的写权限)
+// 写入包括对账户字段的赋值、load_mut/try_borrow_mut_lamports 等可变借用、作为 CPI 的转出/转入账户、
+// 以 AccountMeta::new 传给 CPI，以及 init 的 payer 和 close 的接收账户
+// 处理函数及其传递调用的函数都会计入；调用关系按名字解析 (见 symbols::CallGraph)
+
+use crate::anchor_accounts::{self, AccountsField, AccountsStruct};
+use crate::config::ArtifactsArgs;
+use crate::idl::{collect_programs, AstProgram, Handler};
+use crate::layout::split_top_level;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{child, definition_name, line_of, load_asts, normalize, AstNode, CallGraph};
+use crate::tokens::written_fields;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const MUTABILITY_FILE_NAME: &str = "mutability.json";
+
+/// 对账户做可变借用或修改账户本身的方法
+const MUT_METHODS: &[&str] = &[
+    "load_mut",
+    "load_init",
+    "try_borrow_mut_data",
+    "try_borrow_mut_lamports",
+    "borrow_mut",
+    "set_inner",
+    "realloc",
+    "resize",
+    "assign",
+    "exit",
+    "close",
+    "sub_lamports",
+    "add_lamports",
+];
+
+/// 使账户可写的约束
+const MUTABLE_CONSTRAINTS: &[&str] = &["mut", "init", "init_if_needed", "zero"];
+
+/// 把别名绑定到账户时，账户表达式后面允许出现的方法
+const ALIAS_SUFFIXES: &[&str] = &[
+    "",
+    ".to_account_info()",
+    ".as_ref()",
+    ".clone()",
+    ".load_mut()?",
+    ".load_mut().unwrap()",
+    ".load_init()?",
+];
+
+/// `agent mutability` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct MutabilityArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 mutability.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 写入的方式
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteKind {
+    /// 对账户字段赋值
+    Assign,
+    /// load_mut、try_borrow_mut_lamports 等
+    MutBorrow,
+    /// 作为 CPI 账户结构体的转出/转入/mint 等字段
+    Cpi,
+    /// AccountMeta::new(..) 以可写方式传给 CPI
+    AccountMeta,
+    /// init 或 realloc 的 payer
+    Payer,
+    /// close 约束关闭的账户或接收 lamports 的账户
+    Close,
+}
+
+/// 一处写入
+#[derive(Serialize, Debug, Clone)]
+pub struct Write {
+    pub account: String,
+    pub kind: WriteKind,
+    pub file: PathBuf,
+    pub line: usize,
+    /// 写入所在的函数；Accounts 约束隐含的写入为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    pub code: String,
+}
+
+/// 字段是否声明为可写
+fn declared_mut(field: &AccountsField) -> bool {
+    MUTABLE_CONSTRAINTS.iter().any(|f| field.flag(f))
+}
+
+/// 问题的种类
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum FindingKind {
+    /// 写入了没有标记 mut 的账户
+    MissingMut,
+    /// 标记了 mut，但使用该结构体的指令都不写入它
+    UnnecessaryMut,
+}
+
+#[derive(Serialize, Debug)]
+struct Finding {
+    kind: FindingKind,
+    accounts_struct: String,
+    account: String,
+    file: PathBuf,
+    line: usize,
+    instructions: BTreeSet<String>,
+}
+
+/// 一条指令写入的账户
+#[derive(Serialize, Debug)]
+struct HandlerWrites {
+    instruction: String,
+    file: PathBuf,
+    function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accounts_struct: Option<String>,
+    declared_mut: Vec<String>,
+    written: BTreeSet<String>,
+    /// 传给了 CPI 但无法确定是否写入的账户
+    passed_to_cpi: BTreeSet<String>,
+    writes: Vec<Write>,
+}
+
+/// mutability.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct MutabilityReport {
+    metadata: MutabilityMetadata,
+    findings: Vec<Finding>,
+    handlers: Vec<HandlerWrites>,
+}
+
+#[derive(Serialize, Debug)]
+struct MutabilityMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 表达式开头的标识符
+fn leading_identifier(text: &str) -> &str {
+    let end = text
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
+/// 去掉表达式开头的解引用、借用和括号
+fn strip_refs(expr: &str) -> String {
+    let mut expr = expr.replace(char::is_whitespace, "");
+    loop {
+        let before = expr.len();
+        for prefix in ["*", "&mut", "&", "("] {
+            if let Some(rest) = expr.strip_prefix(prefix) {
+                expr = rest.to_string();
+            }
+        }
+        if expr.len() == before {
+            return expr;
+        }
+    }
+}
+
+/// 表达式访问的账户：`ctx.accounts.X`、`self.X` 或绑定到账户的别名
+fn account_of(expr: &str, aliases: &HashMap<String, String>) -> Option<String> {
+    let expr = strip_refs(expr);
+    for prefix in ["ctx.accounts.", "self."] {
+        if let Some(rest) = expr.strip_prefix(prefix) {
+            let name = leading_identifier(rest);
+            return (!name.is_empty()).then(|| name.to_string());
+        }
+    }
+    aliases.get(leading_identifier(&expr)).cloned()
+}
+
+/// 函数中绑定到账户的别名，例如 `let vault = &mut ctx.accounts.vault;`
+fn collect_aliases(node: &AstNode, aliases: &mut HashMap<String, String>) {
+    for item in &node.children {
+        if item.kind == "let_declaration" {
+            let pattern = child(item, "identifier");
+            let value = item.children.iter().skip_while(|c| c.kind != "=").nth(1);
+            if let (Some(pattern), Some(value)) = (pattern, value) {
+                let value = strip_refs(&value.text);
+                let account = ["ctx.accounts.", "self."].iter().find_map(|prefix| {
+                    let rest = value.strip_prefix(prefix)?;
+                    let name = leading_identifier(rest);
+                    ALIAS_SUFFIXES
+                        .contains(&&rest[name.len()..])
+                        .then(|| name.to_string())
+                });
+                if let Some(account) = account {
+                    aliases.insert(pattern.text.clone(), account);
+                }
+            }
+        }
+        if item.kind != "function_item" {
+            collect_aliases(item, aliases);
+        }
+    }
+}
+
+/// 从AST中收集的写入和 Accounts 结构体
+#[derive(Default)]
+struct Collected<'a> {
+    /// (文件, 函数名) -> 函数中的写入
+    functions: HashMap<(PathBuf, String), Vec<Write>>,
+    /// (文件, 函数名) -> 传给 CPI 的账户
+    cpi_accounts: HashMap<(PathBuf, String), BTreeSet<String>>,
+    accounts_structs: BTreeMap<String, AccountsStruct<'a>>,
+    /// Accounts 结构体 -> 约束隐含的写入 (payer、close)
+    constraint_writes: HashMap<String, Vec<Write>>,
+}
+
+/// 当前所在的位置
+struct Scope<'a> {
+    file: &'a Path,
+    source: &'a str,
+    function: Option<&'a str>,
+    aliases: &'a HashMap<String, String>,
+}
+
+impl<'a> Collected<'a> {
+    fn visit(&mut self, node: &'a AstNode, scope: &Scope) {
+        let mut derives_accounts = false;
+        for item in &node.children {
+            match item.kind.as_str() {
+                "attribute_item" => {
+                    let text = normalize(&item.text);
+                    derives_accounts |= text.starts_with("#[derive(") && text.contains("Accounts");
+                    continue;
+                }
+                "line_comment" | "block_comment" => continue,
+                "struct_item" if derives_accounts => self.visit_accounts(item, scope),
+                "assignment_expression" | "compound_assignment_expr" => {
+                    if let Some(left) = item.children.first() {
+                        self.push(scope, item, &left.text, WriteKind::Assign);
+                    }
+                }
+                "call_expression" => self.visit_call(item, scope),
+                "macro_invocation" => {
+                    // vec![AccountMeta::new(..), ..] 中的调用不会被解析为 call_expression
+                    let text = normalize(&item.text);
+                    for (_, rest) in text
+                        .match_indices("AccountMeta::new(")
+                        .map(|(i, m)| text.split_at(i + m.len()))
+                    {
+                        if let Some(first) = split_top_level(rest).first() {
+                            self.push(scope, item, first, WriteKind::AccountMeta);
+                        }
+                    }
+                }
+                "struct_expression" => {
+                    let name = item.children.first().map_or("", |t| t.text.as_str());
+                    let name = name.rsplit("::").next().unwrap_or(name).trim();
+                    let fields = written_fields(name).unwrap_or_default();
+                    for field in child(item, "field_initializer_list")
+                        .into_iter()
+                        .flat_map(|list| &list.children)
+                    {
+                        let key = child(field, "field_identifier").map_or("", |f| f.text.as_str());
+                        if let (true, Some(value)) = (fields.contains(&key), field.children.last())
+                        {
+                            self.push(scope, field, &value.text, WriteKind::Cpi);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            derives_accounts = false;
+            if item.kind == "function_item" {
+                let mut aliases = HashMap::new();
+                collect_aliases(item, &mut aliases);
+                let scope = Scope {
+                    function: definition_name(item).map(|n| n.text.as_str()),
+                    aliases: &aliases,
+                    ..*scope
+                };
+                self.visit(item, &scope);
+            } else {
+                self.visit(item, scope);
+            }
+        }
+    }
+
+    fn push(&mut self, scope: &Scope, node: &AstNode, expr: &str, kind: WriteKind) {
+        let (Some(function), Some(account)) = (scope.function, account_of(expr, scope.aliases))
+        else {
+            return;
+        };
+        self.functions
+            .entry((scope.file.to_path_buf(), function.to_string()))
+            .or_default()
+            .push(Write {
+                account,
+                kind,
+                file: scope.file.to_path_buf(),
+                line: line_of(scope.source, node.start_byte),
+                function: Some(function.to_string()),
+                code: normalize(&node.text),
+            });
+    }
+
+    /// 可变借用的方法调用、AccountMeta::new(..)，以及传给 invoke/CpiContext 的账户
+    fn visit_call(&mut self, call: &AstNode, scope: &Scope) {
+        let Some(callee) = call.children.first() else {
+            return;
+        };
+        let args = child(call, "arguments");
+        if callee.kind == "field_expression" {
+            let method = child(callee, "field_identifier").map_or("", |f| f.text.as_str());
+            if let (true, Some(receiver)) = (MUT_METHODS.contains(&method), callee.children.first())
+            {
+                self.push(scope, call, &receiver.text, WriteKind::MutBorrow);
+            }
+            return;
+        }
+        let callee = normalize(&callee.text);
+        let name = callee.rsplit("::").next().unwrap_or(&callee);
+        if callee.ends_with("AccountMeta::new") {
+            let first = args.and_then(|a| {
+                a.children
+                    .iter()
+                    .find(|c| !matches!(c.kind.as_str(), "(" | ")" | ","))
+            });
+            if let Some(first) = first {
+                self.push(scope, call, &first.text, WriteKind::AccountMeta);
+            }
+        } else if name.starts_with("invoke") || callee.starts_with("CpiContext::") {
+            let Some(function) = scope.function else {
+                return;
+            };
+            let text = normalize(args.map_or("", |a| a.text.as_str()));
+            let accounts: Vec<String> = text
+                .split([',', '[', ']', '(', ')', '{', '}', ':'])
+                .filter_map(|part| account_of(part.trim(), scope.aliases))
+                .collect();
+            self.cpi_accounts
+                .entry((scope.file.to_path_buf(), function.to_string()))
+                .or_default()
+                .extend(accounts);
+        }
+    }
+
+    /// Accounts 结构体中声明的可写性，以及 payer 和 close 约束隐含的写入
+    fn visit_accounts(&mut self, item: &'a AstNode, scope: &Scope) {
+        let Some(name) = definition_name(item) else {
+            return;
+        };
+        let fields = anchor_accounts::fields(item, scope.source);
+        let mut writes = vec![];
+        for field in &fields {
+            let write = |account: &str, kind| Write {
+                account: account.to_string(),
+                kind,
+                file: scope.file.to_path_buf(),
+                line: field.line,
+                function: None,
+                code: normalize(&field.node.text),
+            };
+            for constraint in &field.constraints {
+                let Some((key, value)) = constraint.split_once('=') else {
+                    continue;
+                };
+                let value = value.split(" @ ").next().unwrap_or(value).trim();
+                match key.trim() {
+                    "payer" | "realloc::payer" => writes.push(write(value, WriteKind::Payer)),
+                    "close" => {
+                        writes.push(write(&field.name, WriteKind::Close));
+                        writes.push(write(value, WriteKind::Close));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.constraint_writes.insert(name.text.clone(), writes);
+        self.accounts_structs.insert(
+            name.text.clone(),
+            AccountsStruct {
+                file: scope.file.to_path_buf(),
+                fields,
+            },
+        );
+    }
+}
+
+/// 项目中所有的写入，按处理函数查询
+pub struct Writes<'a> {
+    collected: Collected<'a>,
+    calls: CallGraph,
+    /// AST中的 `#[program]` 模块
+    pub programs: Vec<AstProgram>,
+}
+
+impl<'a> Writes<'a> {
+    /// 从AST中收集写入、Accounts 结构体和处理函数
+    pub fn collect(asts: &'a [(PathBuf, AstNode)], project: &Path) -> Writes<'a> {
+        let mut collected = Collected::default();
+        let mut calls = CallGraph::default();
+        let mut programs = vec![];
+        let no_aliases = HashMap::new();
+        for (file, root) in asts {
+            if file.extension().is_none_or(|ext| ext != "rs") {
+                continue;
+            }
+            let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+            let scope = Scope {
+                file,
+                source: &source,
+                function: None,
+                aliases: &no_aliases,
+            };
+            collected.visit(root, &scope);
+            calls.add_file(file, root);
+            collect_programs(root, file, &source, &mut programs);
+        }
+        Writes {
+            collected,
+            calls,
+            programs,
+        }
+    }
+
+    /// 处理函数及其传递调用的函数中的写入 (包括 Accounts 约束隐含的写入) 和传给 CPI 的账户，
+    /// 只保留处理函数的 Accounts 结构体中的账户
+    pub fn handler(&self, handler: &Handler) -> (Vec<Write>, BTreeSet<String>) {
+        let accounts = handler
+            .accounts_struct
+            .as_ref()
+            .and_then(|s| self.collected.accounts_structs.get(s));
+        let fields: &[AccountsField] = accounts.map_or(&[], |a| a.fields.as_slice());
+        let mut writes: Vec<Write> = handler
+            .accounts_struct
+            .as_ref()
+            .and_then(|s| self.collected.constraint_writes.get(s))
+            .cloned()
+            .unwrap_or_default();
+        let mut passed_to_cpi = BTreeSet::new();
+        for key in self.calls.reachable(&handler.file, &handler.function) {
+            writes.extend(
+                self.collected
+                    .functions
+                    .get(&key)
+                    .into_iter()
+                    .flatten()
+                    .cloned(),
+            );
+            passed_to_cpi.extend(
+                self.collected
+                    .cpi_accounts
+                    .get(&key)
+                    .into_iter()
+                    .flatten()
+                    .cloned(),
+            );
+        }
+        writes.retain(|w| fields.iter().any(|f| f.name == w.account));
+        passed_to_cpi.retain(|a| fields.iter().any(|f| f.name == *a));
+        (writes, passed_to_cpi)
+    }
+}
+
+/// 收集写入，与声明的可写性对照，写出 mutability.json
+pub fn run(args: &MutabilityArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("mutability")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let project_writes = Writes::collect(&asts, project);
+    let collected = &project_writes.collected;
+
+    let mut handlers = vec![];
+    // (Accounts 结构体, 账户) -> 写入它或把它传给 CPI 的指令
+    let mut used: HashMap<(String, String), BTreeSet<String>> = HashMap::new();
+    let mut missing: BTreeMap<(String, String), BTreeSet<String>> = BTreeMap::new();
+    let mut structs_in_use = HashSet::new();
+    for program in &project_writes.programs {
+        for (handler, _) in &program.handlers {
+            let instruction = format!("{}::{}", program.name, handler.function);
+            let accounts = handler
+                .accounts_struct
+                .as_ref()
+                .and_then(|s| Some((s, collected.accounts_structs.get(s)?)));
+            let fields: &[AccountsField] = accounts.map_or(&[], |(_, a)| a.fields.as_slice());
+            let (writes, passed_to_cpi) = project_writes.handler(handler);
+            let written: BTreeSet<String> = writes.iter().map(|w| w.account.clone()).collect();
+
+            if let Some((struct_name, _)) = accounts {
+                structs_in_use.insert(struct_name.clone());
+                for account in written.iter().chain(&passed_to_cpi) {
+                    used.entry((struct_name.clone(), account.clone()))
+                        .or_default()
+                        .insert(instruction.clone());
+                }
+                for field in fields
+                    .iter()
+                    .filter(|f| !declared_mut(f) && written.contains(&f.name))
+                {
+                    missing
+                        .entry((struct_name.clone(), field.name.clone()))
+                        .or_default()
+                        .insert(instruction.clone());
+                }
+            }
+            handlers.push(HandlerWrites {
+                instruction,
+                file: handler.file.clone(),
+                function: handler.function.clone(),
+                accounts_struct: handler.accounts_struct.clone(),
+                declared_mut: fields
+                    .iter()
+                    .filter(|f| declared_mut(f))
+                    .map(|f| f.name.clone())
+                    .collect(),
+                written,
+                passed_to_cpi,
+                writes,
+            });
+        }
+    }
+
+    let mut findings = vec![];
+    for ((struct_name, account), instructions) in missing {
+        let accounts = &collected.accounts_structs[&struct_name];
+        let line = accounts
+            .fields
+            .iter()
+            .find(|f| f.name == account)
+            .map_or(0, |f| f.line);
+        findings.push(Finding {
+            kind: FindingKind::MissingMut,
+            file: accounts.file.clone(),
+            line,
+            accounts_struct: struct_name,
+            account,
+            instructions,
+        });
+    }
+    for (struct_name, accounts) in &collected.accounts_structs {
+        if !structs_in_use.contains(struct_name) {
+            continue;
+        }
+        for field in accounts.fields.iter().filter(|f| f.flag("mut")) {
+            if !used.contains_key(&(struct_name.clone(), field.name.clone())) {
+                findings.push(Finding {
+                    kind: FindingKind::UnnecessaryMut,
+                    accounts_struct: struct_name.clone(),
+                    account: field.name.clone(),
+                    file: accounts.file.clone(),
+                    line: field.line,
+                    instructions: BTreeSet::new(),
+                });
+            }
+        }
+    }
+    for finding in &findings {
+        let message = match finding.kind {
+            FindingKind::MissingMut => "写入了没有标记 mut 的账户",
+            FindingKind::UnnecessaryMut => "账户标记了 mut，但没有指令写入它",
+        };
+        warn!(
+            accounts_struct = %finding.accounts_struct,
+            account = %finding.account,
+            file = %finding.file.display(),
+            line = finding.line,
+            "{}",
+            message
+        );
+    }
+
+    let report = MutabilityReport {
+        metadata: MutabilityMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        findings,
+        handlers,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(MUTABILITY_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        handlers = report.handlers.len(),
+        missing_mut = report
+            .findings
+            .iter()
+            .filter(|f| f.kind == FindingKind::MissingMut)
+            .count(),
+        unnecessary_mut = report
+            .findings
+            .iter()
+            .filter(|f| f.kind == FindingKind::UnnecessaryMut)
+            .count(),
+        output = %output.display(),
+        "已写出可写性检查结果"
+    );
+    Ok(())
+}
@@ -0,0 +1,118 @@
+// npz.rs
+//
+// 最小的 .npy/.npz 写入器，供 agent dataset 和节点特征导出使用
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// 一个 .npy 文件：版本 1.0 的头部加上小端序的原始数据
+fn npy(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({},)", n),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+    // 魔数 (6) + 版本 (2) + 头部长度 (2) + 头部，总长度按64字节对齐，以换行结尾
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut out = b"\x93NUMPY\x01\x00".to_vec();
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// float32 数组
+pub fn npy_f32(shape: &[usize], values: &[f32]) -> Vec<u8> {
+    let data: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    npy("<f4", shape, &data)
+}
+
+/// int64 数组
+pub fn npy_i64(shape: &[usize], values: &[i64]) -> Vec<u8> {
+    let data: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    npy("<i8", shape, &data)
+}
+
+/// CRC-32 (IEEE)，zip 文件头需要
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// 写出 .npz：不压缩 (stored) 的 zip 文件，每个条目是一个 .npy，numpy.load 可以直接读取
+pub fn write_npz(path: &Path, entries: &[(&str, Vec<u8>)]) -> Result<(), Box<dyn Error>> {
+    // 1980-01-01 00:00，zip 能表示的最早日期
+    const DOS_TIME: u16 = 0;
+    const DOS_DATE: u16 = 0x21;
+    let mut out = vec![];
+    let mut central = vec![];
+    for (name, data) in entries {
+        let offset = u32::try_from(out.len())?;
+        let size = u32::try_from(data.len()).map_err(|_| "数组超过 4GiB，无法写入 npz")?;
+        let crc = crc32(data);
+        let name_len = name.len() as u16;
+
+        out.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        for field in [20, 0, 0, DOS_TIME, DOS_DATE] {
+            out.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        for field in [crc, size, size] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        out.extend_from_slice(&name_len.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+
+        central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        for field in [20, 20, 0, 0, DOS_TIME, DOS_DATE] {
+            central.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        for field in [crc, size, size] {
+            central.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [name_len, 0, 0, 0, 0] {
+            central.extend_from_slice(&field.to_le_bytes());
+        }
+        central.extend_from_slice(&0u32.to_le_bytes());
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+
+    let central_offset = u32::try_from(out.len())?;
+    let count = entries.len() as u16;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    for field in [0, 0, count, count] {
+        out.extend_from_slice(&u16::to_le_bytes(field));
+    }
+    out.extend_from_slice(&u32::try_from(central.len())?.to_le_bytes());
+    out.extend_from_slice(&central_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    fs::write(path, out)?;
+    Ok(())
+}
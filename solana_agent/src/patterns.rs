@@ -0,0 +1,497 @@
+// patterns.rs
+//
+// agent patterns：用代码片段写的模式在AST上查找代码，类似 semgrep，写出 patterns.json
+// 模式按与源码相同的方式切成词法单元后逐个比较，空白和换行不影响匹配；另有两种特殊写法：
+// - `$X` (大写字母、数字和下划线) 为元变量，匹配恰好一个AST节点；同一个元变量出现多次时各处的文本必须相同
+// - `...` 匹配任意一段括号配对的代码，例如 `invoke_signed($IX, ...)`
+// 模式必须与某个AST节点的全部代码匹配，同一段代码只报告一次
+// 规则写在TOML文件中：
+//
+//     [[rules]]
+//     id = "unchecked-unwrap"
+//     languages = ["rust"]
+//     message = "$X 可能为 None"
+//     patterns = ["$X.unwrap()"]
+//     pattern_not = ["Clock::get().unwrap()"]
+//     class = "account_validation"
+//     cwe = [252]
+//
+// patterns 中任意一个匹配即可，pattern_not 中任意一个与同一段代码匹配时排除；message 中的元变量替换为匹配到的代码
+// class 和 cwe 可选，为问题的标准化分类 (见 taxonomy.rs)，随匹配写出，agent report 据此分类
+
+use crate::config::ArtifactsArgs;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{language_of, line_of, load_asts, AstNode};
+use crate::taxonomy::VulnClass;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const PATTERNS_FILE_NAME: &str = "patterns.json";
+
+/// 支持的语言，与 symbols.rs 中按扩展名识别的语言一致
+const LANGUAGES: &[&str] = &["rust", "typescript", "javascript", "python", "solidity", "toml", "move"];
+
+/// 命令行中用 -e 给出的模式所属规则的ID
+const INLINE_RULE_ID: &str = "inline";
+
+/// `agent patterns` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct PatternsArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 规则文件 (TOML，可重复)
+    #[arg(long = "rules", value_name = "FILE")]
+    rules: Vec<PathBuf>,
+
+    /// 直接在命令行中给出的模式 (可重复)，与 --lang 一起使用
+    #[arg(short = 'e', long = "pattern", value_name = "PATTERN")]
+    patterns: Vec<String>,
+
+    /// -e 给出的模式的语言
+    #[arg(long, value_name = "LANG", default_value = "rust")]
+    lang: String,
+
+    /// 输出文件，默认为产物目录下的 patterns.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 规则文件的顶层结构
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    rules: Vec<Rule>,
+}
+
+/// 一条规则
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    id: String,
+    languages: Vec<String>,
+    message: String,
+    patterns: Vec<String>,
+    #[serde(default)]
+    pattern_not: Vec<String>,
+    #[serde(default)]
+    class: Option<VulnClass>,
+    #[serde(default)]
+    cwe: Vec<u32>,
+}
+
+/// 模式中的一个单元
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PatternToken {
+    Literal(String),
+    Metavariable(String),
+    Ellipsis,
+}
+
+/// 编译后的规则
+struct CompiledRule {
+    rule: Rule,
+    patterns: Vec<Vec<PatternToken>>,
+    pattern_not: Vec<Vec<PatternToken>>,
+}
+
+/// 源码中的一个词法单元，`start`/`end` 为字节偏移
+struct Token {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+/// 一个文件的词法单元，以及每个AST节点覆盖的单元范围
+struct FileTokens {
+    tokens: Vec<Token>,
+    /// 单元下标 -> 从这里开始的节点的结束下标
+    ends: HashMap<usize, Vec<usize>>,
+    /// 节点覆盖的单元范围，外层节点在前
+    nodes: Vec<(usize, usize)>,
+}
+
+/// 元变量绑定：名字及其匹配的单元范围
+type Binding = (String, usize, usize);
+
+/// 一处匹配
+#[derive(Serialize, Debug)]
+struct Match {
+    rule: String,
+    file: PathBuf,
+    line: usize,
+    end_line: usize,
+    code: String,
+    message: String,
+    bindings: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class: Option<VulnClass>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cwe: Vec<u32>,
+}
+
+/// patterns.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct PatternsReport {
+    metadata: PatternsMetadata,
+    rules: Vec<String>,
+    matches: Vec<Match>,
+}
+
+#[derive(Serialize, Debug)]
+struct PatternsMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 标识符、数字和关键字中的字符；其余非空白字符各自成为一个单元
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// 把一段源码切成词法单元，`base` 为这段源码在文件中的字节偏移
+fn lex(text: &str, base: usize, tokens: &mut Vec<Token>) {
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        if is_word(c) {
+            while let Some(&(i, next)) = chars.peek() {
+                if !is_word(next) {
+                    break;
+                }
+                end = i + next.len_utf8();
+                chars.next();
+            }
+        }
+        tokens.push(Token {
+            text: text[start..end].to_string(),
+            start: base + start,
+            end: base + end,
+        });
+    }
+}
+
+/// 把模式切成单元
+fn compile(pattern: &str) -> Result<Vec<PatternToken>, Box<dyn Error>> {
+    let mut compiled = vec![];
+    let mut rest = pattern;
+    while !rest.is_empty() {
+        // `...` 在切分之前单独识别，否则会被切成三个 `.`
+        let (chunk, ellipsis, tail) = match rest.find("...") {
+            Some(i) => (&rest[..i], true, &rest[i + 3..]),
+            None => (rest, false, ""),
+        };
+        let mut tokens = vec![];
+        lex(chunk, 0, &mut tokens);
+        for token in tokens {
+            let name = token.text.strip_prefix('$').filter(|name| {
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+            });
+            compiled.push(match name {
+                Some(name) => PatternToken::Metavariable(name.to_string()),
+                None => PatternToken::Literal(token.text),
+            });
+        }
+        if ellipsis {
+            compiled.push(PatternToken::Ellipsis);
+        }
+        rest = tail;
+    }
+    if compiled.is_empty() {
+        return Err(format!("模式 '{}' 为空", pattern).into());
+    }
+    Ok(compiled)
+}
+
+impl CompiledRule {
+    fn new(rule: Rule) -> Result<Self, Box<dyn Error>> {
+        if rule.patterns.is_empty() {
+            return Err(format!("规则 '{}' 没有 patterns", rule.id).into());
+        }
+        if let Some(language) = rule
+            .languages
+            .iter()
+            .find(|l| !LANGUAGES.contains(&l.as_str()))
+        {
+            return Err(format!("规则 '{}' 的语言 '{}' 不受支持", rule.id, language).into());
+        }
+        let patterns = rule
+            .patterns
+            .iter()
+            .map(|p| compile(p))
+            .collect::<Result<_, _>>()?;
+        let pattern_not = rule
+            .pattern_not
+            .iter()
+            .map(|p| compile(p))
+            .collect::<Result<_, _>>()?;
+        Ok(CompiledRule {
+            rule,
+            patterns,
+            pattern_not,
+        })
+    }
+}
+
+/// 读取规则文件
+fn load_rules(path: &Path) -> Result<Vec<Rule>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("无法读取规则文件 '{}': {}", path.display(), e))?;
+    let file: RuleFile = toml::from_str(&content)
+        .map_err(|e| format!("规则文件 '{}' 格式错误: {}", path.display(), e))?;
+    Ok(file.rules)
+}
+
+/// 收集节点的词法单元：子节点之间的文本 (例如某些字符串的内容) 归属于父节点
+fn collect_tokens(node: &AstNode, file: &mut FileTokens) {
+    let start = file.tokens.len();
+    let mut cursor = node.start_byte;
+    for item in &node.children {
+        if let Some(gap) = node
+            .text
+            .get(cursor - node.start_byte..item.start_byte.saturating_sub(node.start_byte))
+        {
+            lex(gap, cursor, &mut file.tokens);
+        }
+        collect_tokens(item, file);
+        cursor = cursor.max(item.end_byte);
+    }
+    if let Some(gap) = node.text.get(cursor - node.start_byte..) {
+        lex(gap, cursor, &mut file.tokens);
+    }
+    let end = file.tokens.len();
+    if end > start {
+        file.ends.entry(start).or_default().push(end);
+        file.nodes.push((start, end));
+    }
+}
+
+/// 一段单元中的括号是否配对
+fn balanced(tokens: &[Token]) -> bool {
+    let mut depth = 0i32;
+    for token in tokens {
+        match token.text.as_str() {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+/// 从第 `pi` 个模式单元、第 `ti` 个源码单元开始匹配，必须恰好用完 `..end` 的源码单元
+fn match_tokens(
+    pattern: &[PatternToken],
+    pi: usize,
+    file: &FileTokens,
+    ti: usize,
+    end: usize,
+    bindings: &mut Vec<Binding>,
+) -> bool {
+    let Some(token) = pattern.get(pi) else {
+        return ti == end;
+    };
+    match token {
+        PatternToken::Literal(text) => {
+            ti < end
+                && file.tokens[ti].text == *text
+                && match_tokens(pattern, pi + 1, file, ti + 1, end, bindings)
+        }
+        PatternToken::Ellipsis => (ti..=end).any(|k| {
+            balanced(&file.tokens[ti..k]) && match_tokens(pattern, pi + 1, file, k, end, bindings)
+        }),
+        PatternToken::Metavariable(name) => {
+            if let Some(&(_, start, stop)) = bindings.iter().find(|(n, _, _)| n == name) {
+                let len = stop - start;
+                return ti + len <= end
+                    && (0..len).all(|i| file.tokens[start + i].text == file.tokens[ti + i].text)
+                    && match_tokens(pattern, pi + 1, file, ti + len, end, bindings);
+            }
+            for &stop in file.ends.get(&ti).into_iter().flatten() {
+                if stop > end {
+                    continue;
+                }
+                bindings.push((name.clone(), ti, stop));
+                if match_tokens(pattern, pi + 1, file, stop, end, bindings) {
+                    return true;
+                }
+                bindings.pop();
+            }
+            false
+        }
+    }
+}
+
+/// 在一个文件中查找规则的所有匹配
+fn find_matches(rule: &CompiledRule, file: &FileTokens) -> Vec<(usize, usize, Vec<Binding>)> {
+    let mut seen = HashSet::new();
+    let mut found = vec![];
+    for &(start, end) in &file.nodes {
+        if seen.contains(&(start, end)) {
+            continue;
+        }
+        let matched = rule.patterns.iter().find_map(|pattern| {
+            let mut bindings = vec![];
+            match_tokens(pattern, 0, file, start, end, &mut bindings).then_some(bindings)
+        });
+        let Some(bindings) = matched else {
+            continue;
+        };
+        let excluded = rule
+            .pattern_not
+            .iter()
+            .any(|pattern| match_tokens(pattern, 0, file, start, end, &mut vec![]));
+        seen.insert((start, end));
+        if !excluded {
+            found.push((start, end, bindings));
+        }
+    }
+    found
+}
+
+/// 按模式规则查找代码，写出 patterns.json
+pub fn run(args: &PatternsArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("patterns")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+
+    let mut rules = vec![];
+    for path in &args.rules {
+        rules.extend(load_rules(path)?);
+    }
+    for (index, pattern) in args.patterns.iter().enumerate() {
+        rules.push(Rule {
+            id: format!("{}-{}", INLINE_RULE_ID, index + 1),
+            languages: vec![args.lang.clone()],
+            message: pattern.clone(),
+            patterns: vec![pattern.clone()],
+            pattern_not: vec![],
+            class: None,
+            cwe: vec![],
+        });
+    }
+    if rules.is_empty() {
+        return Err("没有规则：请用 --rules 指定规则文件或用 -e 给出模式".into());
+    }
+    let rules = rules
+        .into_iter()
+        .map(CompiledRule::new)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut matches = vec![];
+    for (file, root) in &asts {
+        let Some(language) = language_of(file) else {
+            continue;
+        };
+        let applicable: Vec<&CompiledRule> = rules
+            .iter()
+            .filter(|r| r.rule.languages.iter().any(|l| l == language))
+            .collect();
+        if applicable.is_empty() {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        let mut tokens = FileTokens {
+            tokens: vec![],
+            ends: HashMap::new(),
+            nodes: vec![],
+        };
+        collect_tokens(root, &mut tokens);
+        // 收集时为后序，反转后外层节点先于内层节点，同一段代码报告最外层的节点
+        tokens.nodes.reverse();
+        debug!(file = %file.display(), tokens = tokens.tokens.len(), "已切分词法单元");
+
+        let text = |start: usize, end: usize| {
+            let (from, to) = (tokens.tokens[start].start, tokens.tokens[end - 1].end);
+            source.get(from..to).map_or_else(
+                || {
+                    tokens.tokens[start..end]
+                        .iter()
+                        .map(|t| t.text.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                },
+                str::to_string,
+            )
+        };
+        for rule in applicable {
+            for (start, end, bindings) in find_matches(rule, &tokens) {
+                let bindings: BTreeMap<String, String> = bindings
+                    .into_iter()
+                    .map(|(name, from, to)| (name, text(from, to)))
+                    .collect();
+                // 先替换较长的名字，避免 $X 替换掉 $X1 的前缀
+                let mut message = rule.rule.message.clone();
+                let mut names: Vec<&String> = bindings.keys().collect();
+                names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+                for name in names {
+                    message = message.replace(&format!("${}", name), &bindings[name]);
+                }
+                matches.push(Match {
+                    rule: rule.rule.id.clone(),
+                    file: file.clone(),
+                    line: line_of(&source, tokens.tokens[start].start),
+                    end_line: line_of(&source, tokens.tokens[end - 1].end),
+                    code: text(start, end),
+                    message,
+                    bindings,
+                    class: rule.rule.class,
+                    cwe: rule.rule.cwe.clone(),
+                });
+            }
+        }
+    }
+    for found in &matches {
+        warn!(rule = %found.rule, file = %found.file.display(), line = found.line, "{}", found.message);
+    }
+
+    let report = PatternsReport {
+        metadata: PatternsMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        rules: rules.iter().map(|r| r.rule.id.clone()).collect(),
+        matches,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(PATTERNS_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        rules = report.rules.len(),
+        matches = report.matches.len(),
+        output = %output.display(),
+        "已写出模式匹配结果"
+    );
+    Ok(())
+}
@@ -0,0 +1,518 @@
+// pda.rs
+//
+// agent pda：列出程序派生的所有PDA
+// 种子来自 Accounts 结构体中的 seeds = [...] 约束，以及函数中 find_program_address/create_program_address 的第一个参数；
+// 每个种子归一化为字面量、账户公钥、整数或其他表达式，常量按 const 的定义展开
+// 相邻的字面量合并后，种子序列的形状 (签名) 相同的PDA属于同一个命名空间：PDA地址由各个种子依次拼接后哈希得到，
+// 因此 [b"ab", x] 与 [b"a", b"b", x] 派生出同一个地址；同一命名空间中出现不同的账户类型时报告为冲突
+// init/init_if_needed 的账户视为由所在指令创建，其余视为使用，函数中计算的地址记为派生
+
+use crate::anchor_accounts;
+use crate::config::ArtifactsArgs;
+use crate::idl::{collect_programs, AstProgram};
+use crate::layout::split_top_level;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{child, definition_name, line_of, load_asts, normalize, AstNode};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const PDA_FILE_NAME: &str = "pda.json";
+
+/// 常量展开的最大深度
+const MAX_DEPTH: usize = 8;
+
+/// 计算PDA的函数
+const DERIVE_FUNCTIONS: &[&str] = &[
+    "find_program_address",
+    "try_find_program_address",
+    "create_program_address",
+];
+
+/// `agent pda` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct PdaArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 pda.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 归一化后的种子
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+enum Seed {
+    /// 字节串或字符串字面量
+    Literal(String),
+    /// 账户的公钥，值为账户名
+    Key(String),
+    /// 整数的字节表示，值为整数表达式
+    Int(String),
+    /// create_program_address 的 bump 种子
+    Bump(String),
+    /// 其他表达式
+    Expr(String),
+}
+
+/// 种子如何参与派生
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Role {
+    /// init/init_if_needed 创建该账户
+    Create,
+    /// 作为已有账户使用并校验地址
+    Consume,
+    /// 在函数中计算地址
+    Derive,
+}
+
+/// bump 的来源
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum BumpKind {
+    /// 由 Anchor 或 find_program_address 查找规范 bump
+    Canonical,
+    /// 使用账户中保存的 bump (bump = ...)
+    Stored,
+    /// create_program_address 中直接给出的 bump，可能不是规范 bump
+    Explicit,
+    /// 没有 bump
+    None,
+}
+
+/// 一处派生PDA的位置
+#[derive(Serialize, Debug)]
+struct Usage {
+    file: PathBuf,
+    line: usize,
+    role: Role,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accounts_struct: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function: Option<String>,
+    /// 账户的类型，例如 Account<'info, Vault> 中的 Vault
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account_type: Option<String>,
+    /// 涉及的指令 `<program>::<instruction>`
+    instructions: Vec<String>,
+    bump: BumpKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bump_expression: Option<String>,
+    /// seeds::program 指定的其他程序
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seeds_program: Option<String>,
+    seeds: Vec<Seed>,
+}
+
+/// 一个PDA命名空间：签名相同的所有派生位置
+#[derive(Serialize, Debug)]
+struct Pda {
+    signature: String,
+    account_types: BTreeSet<String>,
+    created_by: BTreeSet<String>,
+    consumed_by: BTreeSet<String>,
+    derived_in: BTreeSet<String>,
+    usages: Vec<Usage>,
+}
+
+/// 同一命名空间中的不同账户类型
+#[derive(Serialize, Debug)]
+struct Collision {
+    signature: String,
+    account_types: BTreeSet<String>,
+}
+
+/// pda.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct PdaReport {
+    metadata: PdaMetadata,
+    pdas: Vec<Pda>,
+    collisions: Vec<Collision>,
+}
+
+#[derive(Serialize, Debug)]
+struct PdaMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 去掉引用、as_ref() 等不影响种子内容的部分
+fn strip_seed(expr: &str) -> &str {
+    let mut expr = expr.trim();
+    loop {
+        let before = expr;
+        expr = expr.trim_start_matches('&').trim();
+        for suffix in [
+            ".as_ref()",
+            ".as_bytes()",
+            ".as_slice()",
+            ".to_vec()",
+            ".to_bytes()",
+            "[..]",
+        ] {
+            expr = expr.strip_suffix(suffix).unwrap_or(expr);
+        }
+        if expr == before {
+            return expr;
+        }
+    }
+}
+
+/// 归一化一个种子表达式
+fn normalize_seed(expr: &str, consts: &HashMap<String, String>, depth: usize) -> Seed {
+    let expr = strip_seed(expr);
+    if let Some(literal) = expr
+        .strip_prefix("b\"")
+        .or_else(|| expr.strip_prefix('"'))
+        .and_then(|e| e.strip_suffix('"'))
+    {
+        return Seed::Literal(literal.to_string());
+    }
+    if let Some(inner) = expr.strip_prefix('[').and_then(|e| e.strip_suffix(']')) {
+        if split_top_level(inner).len() == 1 && inner.contains("bump") {
+            return Seed::Bump(inner.trim().to_string());
+        }
+    }
+    for suffix in [".key()", ".key", ".pubkey()"] {
+        if let Some(account) = expr.strip_suffix(suffix) {
+            let account = account
+                .trim_start_matches("ctx.accounts.")
+                .trim_end_matches(".to_account_info()");
+            return Seed::Key(account.to_string());
+        }
+    }
+    for suffix in [".to_le_bytes()", ".to_be_bytes()"] {
+        if let Some(int) = expr.strip_suffix(suffix) {
+            return Seed::Int(int.to_string());
+        }
+    }
+    let name = expr.rsplit("::").next().unwrap_or(expr);
+    if depth < MAX_DEPTH {
+        if let Some(value) = consts.get(expr).or_else(|| consts.get(name)) {
+            return match normalize_seed(value, consts, depth + 1) {
+                Seed::Expr(_) => Seed::Expr(expr.to_string()),
+                seed => seed,
+            };
+        }
+    }
+    Seed::Expr(expr.to_string())
+}
+
+/// 种子序列的签名：相邻字面量合并，其余种子按种类替换为占位符，bump 不计入
+fn signature(seeds: &[Seed]) -> String {
+    let mut parts: Vec<String> = vec![];
+    let mut literal: Option<String> = None;
+    for seed in seeds {
+        let placeholder = match seed {
+            Seed::Literal(text) => {
+                literal.get_or_insert_with(String::new).push_str(text);
+                continue;
+            }
+            Seed::Bump(_) => continue,
+            Seed::Key(_) => "<pubkey>",
+            Seed::Int(_) => "<int>",
+            Seed::Expr(_) => "<bytes>",
+        };
+        if let Some(text) = literal.take() {
+            parts.push(format!("{:?}", text));
+        }
+        parts.push(placeholder.to_string());
+    }
+    if let Some(text) = literal {
+        parts.push(format!("{:?}", text));
+    }
+    format!("[{}]", parts.join(", "))
+}
+
+/// 数组表达式 `[a, b]` 或 `&[a, b]` 中的元素；不是数组字面量时返回 None
+fn array_items(expr: &str) -> Option<Vec<&str>> {
+    let expr = expr.trim().trim_start_matches('&').trim();
+    let inner = expr.strip_prefix('[')?.strip_suffix(']')?;
+    Some(split_top_level(inner))
+}
+
+/// 字段类型中的账户类型：Account<'info, T>、Box<Account<'info, T>> 等的最后一个泛型参数
+fn account_type(ty: &str) -> Option<String> {
+    let mut ty = ty.trim();
+    loop {
+        let (base, rest) = ty.split_once('<')?;
+        let args = split_top_level(rest.strip_suffix('>')?);
+        match base.rsplit("::").next()?.trim() {
+            "Box" | "Option" => ty = args.first()?,
+            _ => {
+                return args
+                    .last()
+                    .filter(|t| !t.starts_with('\''))
+                    .map(|t| t.rsplit("::").next().unwrap_or(t).to_string())
+            }
+        }
+    }
+}
+
+/// 从AST中收集的派生位置和常量
+#[derive(Default)]
+struct Collected {
+    /// 派生位置及其种子表达式；常量收集完后再归一化种子
+    usages: Vec<(Usage, Vec<String>)>,
+    consts: HashMap<String, String>,
+}
+
+impl Collected {
+    fn visit(&mut self, node: &AstNode, file: &Path, source: &str, function: Option<&str>) {
+        let mut derives_accounts = false;
+        for item in &node.children {
+            match item.kind.as_str() {
+                "attribute_item" => {
+                    let text = normalize(&item.text);
+                    derives_accounts |= text.starts_with("#[derive(") && text.contains("Accounts");
+                    continue;
+                }
+                "line_comment" | "block_comment" => continue,
+                "struct_item" if derives_accounts => self.visit_accounts(item, file, source),
+                "const_item" => {
+                    let value = item.children.iter().skip_while(|c| c.kind != "=").nth(1);
+                    if let (Some(name), Some(value)) = (definition_name(item), value) {
+                        self.consts.insert(name.text.clone(), value.text.clone());
+                    }
+                }
+                "call_expression" => self.visit_call(item, file, source, function),
+                _ => {}
+            }
+            derives_accounts = false;
+            let function = match item.kind.as_str() {
+                "function_item" => definition_name(item).map(|n| n.text.as_str()),
+                _ => function,
+            };
+            self.visit(item, file, source, function);
+        }
+    }
+
+    /// Accounts 结构体中带 seeds 约束的字段
+    fn visit_accounts(&mut self, item: &AstNode, file: &Path, source: &str) {
+        let Some(name) = definition_name(item) else {
+            return;
+        };
+        for field in anchor_accounts::fields(item, source) {
+            let Some(seeds) = field.value("seeds") else {
+                continue;
+            };
+            let create = field.flag("init") || field.flag("init_if_needed");
+            let (bump, bump_expression) = match field.value("bump") {
+                Some(expr) => (BumpKind::Stored, Some(expr)),
+                None if field.flag("bump") => (BumpKind::Canonical, None),
+                None => (BumpKind::None, None),
+            };
+            let raw = match array_items(&seeds) {
+                Some(items) => items.into_iter().map(String::from).collect(),
+                None => vec![seeds.clone()],
+            };
+            let usage = Usage {
+                file: file.to_path_buf(),
+                line: field.line,
+                role: if create { Role::Create } else { Role::Consume },
+                accounts_struct: Some(name.text.clone()),
+                account_type: account_type(&field.ty),
+                field: Some(field.name.clone()),
+                function: None,
+                instructions: vec![],
+                bump,
+                bump_expression,
+                seeds_program: field.value("seeds::program"),
+                seeds: vec![],
+            };
+            self.usages.push((usage, raw));
+        }
+    }
+
+    /// find_program_address/create_program_address 调用
+    fn visit_call(&mut self, call: &AstNode, file: &Path, source: &str, function: Option<&str>) {
+        let Some(callee) = call.children.first() else {
+            return;
+        };
+        let Some(name) = DERIVE_FUNCTIONS
+            .iter()
+            .find(|f| callee.text.rsplit("::").next() == Some(**f))
+        else {
+            return;
+        };
+        let Some(seeds) = child(call, "arguments").and_then(|args| {
+            args.children
+                .iter()
+                .find(|c| !matches!(c.kind.as_str(), "(" | ")" | ","))
+        }) else {
+            return;
+        };
+        let seeds = normalize(&seeds.text);
+        let raw = match array_items(&seeds) {
+            Some(items) => items.into_iter().map(String::from).collect(),
+            None => vec![seeds],
+        };
+        let explicit = *name == "create_program_address";
+        let usage = Usage {
+            file: file.to_path_buf(),
+            line: line_of(source, call.start_byte),
+            role: Role::Derive,
+            accounts_struct: None,
+            field: None,
+            function: function.map(String::from),
+            account_type: None,
+            instructions: vec![],
+            bump: if explicit {
+                BumpKind::Explicit
+            } else {
+                BumpKind::Canonical
+            },
+            bump_expression: None,
+            seeds_program: None,
+            seeds: vec![],
+        };
+        self.usages.push((usage, raw));
+    }
+}
+
+/// Accounts 结构体和处理函数对应的指令
+fn instruction_index(
+    programs: &[AstProgram],
+) -> (HashMap<&str, Vec<String>>, HashMap<&str, String>) {
+    let mut by_accounts: HashMap<&str, Vec<String>> = HashMap::new();
+    let mut by_function = HashMap::new();
+    for program in programs {
+        for (handler, _) in &program.handlers {
+            let id = format!("{}::{}", program.name, handler.function);
+            if let Some(accounts) = &handler.accounts_struct {
+                by_accounts.entry(accounts).or_default().push(id.clone());
+            }
+            by_function.insert(handler.function.as_str(), id);
+        }
+    }
+    (by_accounts, by_function)
+}
+
+/// 收集所有PDA，按签名分组，写出 pda.json
+pub fn run(args: &PdaArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("pda")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut collected = Collected::default();
+    let mut programs = vec![];
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        collected.visit(root, file, &source, None);
+        collect_programs(root, file, &source, &mut programs);
+    }
+    let (by_accounts, by_function) = instruction_index(&programs);
+
+    let mut pdas: BTreeMap<String, Pda> = BTreeMap::new();
+    for (mut usage, raw) in collected.usages {
+        usage.seeds = raw
+            .iter()
+            .map(|seed| normalize_seed(seed, &collected.consts, 0))
+            .collect();
+        if usage.bump == BumpKind::Explicit {
+            usage.bump_expression = usage.seeds.iter().find_map(|seed| match seed {
+                Seed::Bump(expr) => Some(expr.clone()),
+                _ => None,
+            });
+        }
+        usage.instructions = match (&usage.accounts_struct, &usage.function) {
+            (Some(accounts), _) => by_accounts
+                .get(accounts.as_str())
+                .cloned()
+                .unwrap_or_default(),
+            (None, Some(function)) => by_function
+                .get(function.as_str())
+                .cloned()
+                .into_iter()
+                .collect(),
+            (None, None) => vec![],
+        };
+        // 其他程序的PDA属于那个程序的命名空间
+        let signature = match &usage.seeds_program {
+            Some(program) => format!("{} @ {}", signature(&usage.seeds), program),
+            None => signature(&usage.seeds),
+        };
+        let pda = pdas.entry(signature.clone()).or_insert_with(|| Pda {
+            signature,
+            account_types: BTreeSet::new(),
+            created_by: BTreeSet::new(),
+            consumed_by: BTreeSet::new(),
+            derived_in: BTreeSet::new(),
+            usages: vec![],
+        });
+        pda.account_types.extend(usage.account_type.clone());
+        let targets = match usage.role {
+            Role::Create => &mut pda.created_by,
+            Role::Consume => &mut pda.consumed_by,
+            Role::Derive => &mut pda.derived_in,
+        };
+        match (&usage.function, usage.instructions.is_empty()) {
+            (Some(function), true) => {
+                targets.insert(function.clone());
+            }
+            _ => targets.extend(usage.instructions.iter().cloned()),
+        }
+        pda.usages.push(usage);
+    }
+
+    let collisions: Vec<Collision> = pdas
+        .values()
+        .filter(|pda| pda.account_types.len() > 1)
+        .map(|pda| Collision {
+            signature: pda.signature.clone(),
+            account_types: pda.account_types.clone(),
+        })
+        .collect();
+    for collision in &collisions {
+        warn!(
+            signature = %collision.signature,
+            account_types = ?collision.account_types,
+            "不同类型的账户使用了相同的种子格式"
+        );
+    }
+
+    let report = PdaReport {
+        metadata: PdaMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        pdas: pdas.into_values().collect(),
+        collisions,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(PDA_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        pdas = report.pdas.len(),
+        usages = report.pdas.iter().map(|p| p.usages.len()).sum::<usize>(),
+        collisions = report.collisions.len(),
+        output = %output.display(),
+        "已写出PDA清单"
+    );
+    Ok(())
+}
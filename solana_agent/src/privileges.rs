@@ -0,0 +1,516 @@
+// privileges.rs
+//
+// agent privileges：找出受管理员权限保护的指令，输出权限矩阵 (哪些密钥可以调用哪些修改状态的指令)
+// 权限检查来自 Accounts 结构体的 has_one、address 和 constraint 约束，以及处理函数和其传递调用的函数中的
+// require_keys_eq!、require!、assert! 和 if 条件里的公钥比较；调用关系按名字解析 (见 symbols::CallGraph)
+// 被比较的一方不是 Signer 的检查 (不能证明调用者持有该密钥) 和名字像管理操作却没有管理员检查的指令写入 findings
+
+use crate::anchor_accounts::{self, AccountsField};
+use crate::config::ArtifactsArgs;
+use crate::idl::collect_programs;
+use crate::layout::split_top_level;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{definition_name, line_of, load_asts, normalize, AstNode, CallGraph};
+use crate::tokens::account_name;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const PRIVILEGES_FILE_NAME: &str = "privileges.json";
+
+/// 名字中带有这些词的密钥视为管理员密钥
+const ADMIN_NAMES: &[&str] = &[
+    "admin",
+    "authority",
+    "owner",
+    "governance",
+    "governor",
+    "manager",
+    "operator",
+    "guardian",
+    "multisig",
+    "upgrade",
+];
+
+/// 名字以这些词开头的指令看起来需要管理员权限
+const ADMIN_VERBS: &[&str] = &[
+    "set_",
+    "update_",
+    "pause",
+    "unpause",
+    "upgrade",
+    "migrate",
+    "admin",
+    "withdraw_fees",
+];
+
+/// 使账户可写的约束；指令中有可写的账户时视为会修改状态
+const MUTABLE_CONSTRAINTS: &[&str] = &["mut", "init", "init_if_needed", "close", "realloc"];
+
+/// 矩阵中表示任何签名者都可以调用的键
+const ANY_SIGNER: &str = "*";
+
+/// `agent privileges` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct PrivilegesArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 privileges.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 权限检查的来源
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum GateKind {
+    /// has_one = X 约束
+    HasOne,
+    /// address = X 约束
+    Address,
+    /// constraint = a == b 约束
+    Constraint,
+    /// 函数体中的 require_keys_eq!、require!、assert! 或 if 条件
+    Check,
+    /// 与 ProgramData 的 upgrade_authority_address 比较
+    UpgradeAuthority,
+}
+
+/// 一处权限检查：要求账户 `account` 等于密钥 `key`
+#[derive(Serialize, Debug, Clone)]
+struct Gate {
+    kind: GateKind,
+    /// 被要求的密钥，例如 `config.admin` 或常量 `ADMIN`
+    key: String,
+    /// 与之比较的账户
+    account: String,
+    /// 该账户在指令中是否为 Signer
+    signed: bool,
+    /// 密钥名看起来是管理员密钥
+    admin: bool,
+    file: PathBuf,
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function: Option<String>,
+    code: String,
+}
+
+/// 一条指令的权限
+#[derive(Serialize, Debug)]
+struct HandlerPrivileges {
+    instruction: String,
+    file: PathBuf,
+    function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accounts_struct: Option<String>,
+    signers: Vec<String>,
+    /// 可写、init、close 或 realloc 的账户
+    mutates: Vec<String>,
+    /// 需要 Signer 持有的管理员密钥；为空说明没有管理员检查
+    required_keys: BTreeSet<String>,
+    gates: Vec<Gate>,
+}
+
+/// 问题的种类
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum FindingKind {
+    /// 管理员密钥与非 Signer 账户比较，不能证明调用者持有该密钥
+    UnsignedAdminKey,
+    /// 名字像管理操作 (set_、update_、pause 等) 的修改状态的指令，但没有管理员检查
+    MissingAdminCheck,
+}
+
+#[derive(Serialize, Debug)]
+struct Finding {
+    kind: FindingKind,
+    instruction: String,
+    file: PathBuf,
+    line: usize,
+    message: String,
+}
+
+/// privileges.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct PrivilegesReport {
+    metadata: PrivilegesMetadata,
+    /// 密钥 -> 可以调用的修改状态的指令；`*` 表示任何签名者
+    matrix: BTreeMap<String, BTreeSet<String>>,
+    handlers: Vec<HandlerPrivileges>,
+    findings: Vec<Finding>,
+}
+
+#[derive(Serialize, Debug)]
+struct PrivilegesMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 密钥名是否像管理员密钥；大写的常量 (写死的公钥) 也算，但程序和 sysvar 的 `ID` 不算
+fn is_admin_key(key: &str) -> bool {
+    let last = key.rsplit(['.', ':']).next().unwrap_or(key);
+    let lower = last.to_lowercase();
+    ADMIN_NAMES.iter().any(|n| lower.contains(n))
+        || (last.starts_with(|c: char| c.is_ascii_uppercase())
+            && last.chars().all(|c| !c.is_ascii_lowercase())
+            && last != "ID")
+}
+
+/// 比较的两边中，哪一边是被要求的密钥、哪一边是账户；两边都不像账户时返回 None
+fn key_and_account(left: &str, right: &str) -> Option<(String, String)> {
+    let (left, right) = (account_name(left), account_name(right));
+    let is_account = |s: &str| {
+        !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && s != "None"
+    };
+    let is_const = |s: &str| s.starts_with(|c: char| c.is_ascii_uppercase());
+    match (is_account(&left), is_account(&right)) {
+        (true, false) => Some((right, left)),
+        (false, true) => Some((left, right)),
+        (true, true) if is_const(&left) => Some((left, right)),
+        (true, true) => Some((right, left)),
+        (false, false) => None,
+    }
+}
+
+/// 条件中用 == 或 != 连接的公钥比较；!= 一般出现在检查失败时返回错误的 if 中
+fn comparisons(condition: &str) -> Vec<(String, String)> {
+    let mut found = vec![];
+    for part in condition.split("&&").flat_map(|p| p.split("||")) {
+        // 按 && 和 || 拆开后，去掉多出来的括号
+        let mut part = part.trim();
+        let count = |s: &str, c| s.matches(c).count();
+        while part.starts_with('(') && count(part, '(') > count(part, ')') {
+            part = part[1..].trim_start();
+        }
+        while part.ends_with(')') && count(part, ')') > count(part, '(') {
+            part = part[..part.len() - 1].trim_end();
+        }
+        let Some((left, right)) = part.split_once("==").or_else(|| part.split_once("!=")) else {
+            continue;
+        };
+        let looks_like_key = |s: &str| s.contains("key") || is_admin_key(&account_name(s));
+        if looks_like_key(left) || looks_like_key(right) {
+            if let Some(pair) = key_and_account(left, right) {
+                found.push(pair);
+            }
+        }
+    }
+    found
+}
+
+/// 从AST中收集的权限检查
+#[derive(Default)]
+struct Collected<'a> {
+    /// Accounts 结构体 -> 字段和其中的检查
+    accounts_structs: HashMap<String, (Vec<AccountsField<'a>>, Vec<Gate>)>,
+    /// (文件, 函数名) -> 函数体中的检查
+    functions: HashMap<(PathBuf, String), Vec<Gate>>,
+}
+
+/// 当前所在的位置
+struct Scope<'a> {
+    file: &'a Path,
+    source: &'a str,
+    function: Option<&'a str>,
+}
+
+impl<'a> Collected<'a> {
+    fn visit(&mut self, node: &'a AstNode, scope: &Scope) {
+        let mut derives_accounts = false;
+        for item in &node.children {
+            match item.kind.as_str() {
+                "attribute_item" => {
+                    let text = normalize(&item.text);
+                    derives_accounts |= text.starts_with("#[derive(") && text.contains("Accounts");
+                    continue;
+                }
+                "line_comment" | "block_comment" => continue,
+                "struct_item" if derives_accounts => self.visit_accounts(item, scope),
+                "macro_invocation" => self.visit_macro(item, scope),
+                "if_expression" => {
+                    let condition = item
+                        .children
+                        .iter()
+                        .find(|c| !matches!(c.kind.as_str(), "if" | "block"));
+                    if let Some(condition) = condition {
+                        for (key, account) in comparisons(&normalize(&condition.text)) {
+                            self.push(scope, condition, GateKind::Check, key, account);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            derives_accounts = false;
+            let function = match item.kind.as_str() {
+                "function_item" => definition_name(item).map(|n| n.text.as_str()),
+                _ => scope.function,
+            };
+            self.visit(item, &Scope { function, ..*scope });
+        }
+    }
+
+    fn push(
+        &mut self,
+        scope: &Scope,
+        node: &AstNode,
+        kind: GateKind,
+        key: String,
+        account: String,
+    ) {
+        let Some(function) = scope.function else {
+            return;
+        };
+        self.functions
+            .entry((scope.file.to_path_buf(), function.to_string()))
+            .or_default()
+            .push(gate(scope, node, kind, key, account));
+    }
+
+    /// require_keys_eq!(a, b)、require!(a == b, ..)、assert_eq!(a, b) 等
+    fn visit_macro(&mut self, item: &AstNode, scope: &Scope) {
+        let text = normalize(&item.text);
+        let Some((name, args)) = text.split_once('!') else {
+            return;
+        };
+        let args = args.trim();
+        let Some(args) = args.get(1..args.len().saturating_sub(1)) else {
+            return;
+        };
+        let args = split_top_level(args);
+        let pairs = match name.trim() {
+            "require_keys_eq" | "require_eq" | "assert_eq" if args.len() >= 2 => {
+                key_and_account(args[0], args[1]).into_iter().collect()
+            }
+            "require" | "assert" if !args.is_empty() => comparisons(args[0]),
+            _ => vec![],
+        };
+        for (key, account) in pairs {
+            self.push(scope, item, GateKind::Check, key, account);
+        }
+    }
+
+    /// Accounts 结构体的 Signer、可写账户和 has_one/address/constraint 约束
+    fn visit_accounts(&mut self, item: &'a AstNode, scope: &Scope) {
+        let Some(name) = definition_name(item) else {
+            return;
+        };
+        let fields = anchor_accounts::fields(item, scope.source);
+        let mut gates = vec![];
+        for field in &fields {
+            for constraint in &field.constraints {
+                // 去掉自定义错误 `@ ErrorCode::X`
+                let constraint = constraint.split(" @ ").next().unwrap_or(constraint);
+                let Some((key, value)) = constraint.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim();
+                let found = match key.trim() {
+                    "has_one" => vec![(
+                        GateKind::HasOne,
+                        format!("{}.{}", field.name, value),
+                        value.to_string(),
+                    )],
+                    "address" => vec![(GateKind::Address, account_name(value), field.name.clone())],
+                    "constraint" => comparisons(value)
+                        .into_iter()
+                        .map(|(key, account)| (GateKind::Constraint, key, account))
+                        .collect(),
+                    _ => vec![],
+                };
+                let node_scope = Scope {
+                    function: None,
+                    ..*scope
+                };
+                for (kind, key, account) in found {
+                    gates.push(gate(&node_scope, field.node, kind, key, account));
+                }
+            }
+        }
+        self.accounts_structs
+            .insert(name.text.clone(), (fields, gates));
+    }
+}
+
+fn gate(scope: &Scope, node: &AstNode, kind: GateKind, key: String, account: String) -> Gate {
+    let kind = if key.contains("upgrade_authority") {
+        GateKind::UpgradeAuthority
+    } else {
+        kind
+    };
+    Gate {
+        kind,
+        admin: is_admin_key(&key) || kind == GateKind::UpgradeAuthority,
+        key,
+        account,
+        signed: false,
+        file: scope.file.to_path_buf(),
+        line: line_of(scope.source, node.start_byte),
+        function: scope.function.map(String::from),
+        code: normalize(&node.text),
+    }
+}
+
+/// 收集权限检查，按指令汇总为权限矩阵，写出 privileges.json
+pub fn run(args: &PrivilegesArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("privileges")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut collected = Collected::default();
+    let mut calls = CallGraph::default();
+    let mut programs = vec![];
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        let scope = Scope {
+            file,
+            source: &source,
+            function: None,
+        };
+        collected.visit(root, &scope);
+        calls.add_file(file, root);
+        collect_programs(root, file, &source, &mut programs);
+    }
+
+    let mut handlers = vec![];
+    let mut findings = vec![];
+    let mut matrix: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for program in &programs {
+        for (handler, _) in &program.handlers {
+            let instruction = format!("{}::{}", program.name, handler.function);
+            let (fields, mut gates) = handler
+                .accounts_struct
+                .as_ref()
+                .and_then(|s| collected.accounts_structs.get(s))
+                .map_or((&[][..], vec![]), |(fields, gates)| {
+                    (fields.as_slice(), gates.clone())
+                });
+            for key in calls.reachable(&handler.file, &handler.function) {
+                gates.extend(collected.functions.get(&key).into_iter().flatten().cloned());
+            }
+            let signers: Vec<String> = fields
+                .iter()
+                .filter(|f| f.is_signer())
+                .map(|f| f.name.clone())
+                .collect();
+            for gate in &mut gates {
+                gate.signed = signers.contains(&gate.account);
+            }
+            let required_keys: BTreeSet<String> = gates
+                .iter()
+                .filter(|g| g.admin && g.signed)
+                .map(|g| g.key.clone())
+                .collect();
+            let mutates: Vec<String> = fields
+                .iter()
+                .filter(|f| MUTABLE_CONSTRAINTS.iter().any(|c| f.flag(c)))
+                .map(|f| f.name.clone())
+                .collect();
+
+            for gate in gates.iter().filter(|g| g.admin && !g.signed) {
+                findings.push(Finding {
+                    kind: FindingKind::UnsignedAdminKey,
+                    instruction: instruction.clone(),
+                    file: gate.file.clone(),
+                    line: gate.line,
+                    message: format!(
+                        "管理员密钥 {} 与非 Signer 账户 {} 比较，不能证明调用者持有该密钥",
+                        gate.key, gate.account
+                    ),
+                });
+            }
+            if !mutates.is_empty() {
+                if required_keys.is_empty() {
+                    if ADMIN_VERBS.iter().any(|v| handler.function.starts_with(v)) {
+                        findings.push(Finding {
+                            kind: FindingKind::MissingAdminCheck,
+                            instruction: instruction.clone(),
+                            file: handler.file.clone(),
+                            line: handler.line,
+                            message: format!(
+                                "指令 {} 看起来需要管理员权限，但没有找到管理员检查",
+                                instruction
+                            ),
+                        });
+                    }
+                    matrix
+                        .entry(ANY_SIGNER.to_string())
+                        .or_default()
+                        .insert(instruction.clone());
+                }
+                for key in &required_keys {
+                    matrix
+                        .entry(key.clone())
+                        .or_default()
+                        .insert(instruction.clone());
+                }
+            }
+            handlers.push(HandlerPrivileges {
+                instruction,
+                file: handler.file.clone(),
+                function: handler.function.clone(),
+                accounts_struct: handler.accounts_struct.clone(),
+                signers,
+                mutates,
+                required_keys,
+                gates,
+            });
+        }
+    }
+
+    for finding in &findings {
+        warn!(
+            instruction = %finding.instruction,
+            file = %finding.file.display(),
+            line = finding.line,
+            "{}",
+            finding.message
+        );
+    }
+    let report = PrivilegesReport {
+        metadata: PrivilegesMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        matrix,
+        handlers,
+        findings,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(PRIVILEGES_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        handlers = report.handlers.len(),
+        admin_gated = report
+            .handlers
+            .iter()
+            .filter(|h| !h.required_keys.is_empty())
+            .count(),
+        keys = report.matrix.len(),
+        findings = report.findings.len(),
+        output = %output.display(),
+        "已写出权限矩阵"
+    );
+    Ok(())
+}
@@ -0,0 +1,481 @@
+// protocol.rs
+//
+// agent protocol：同时分析同一协议的多个程序时，解析它们之间的 CPI，写出程序级的调用图 protocol.json 和 protocol.dot
+// 每条边从发起 CPI 的程序和指令 (能走到发起 CPI 的函数的处理函数) 指向被调用的程序和指令
+// 被调用的程序先按 callgraph.rs 中的规则识别 (<程序>::cpi::<指令>、token::transfer 等)，程序名也可以是程序所在 crate 的包名；
+// 无法确定程序的 invoke 按函数中出现的程序ID解析：declare_id!(..) 和 Anchor.toml 的 [programs.*] 中声明的ID、
+// 常见程序的ID，以及 <程序>::ID / <程序>::id()；指令取 <程序>::instruction::<Ix> { .. } 或 "global:<ix>"，找不到时为空
+// 项目外的程序作为外部节点出现在图中
+
+use crate::callgraph::{collect_function_items, cpi_targets};
+use crate::config::ArtifactsArgs;
+use crate::idl::{collect_programs, snake_case};
+use crate::manifest::now_rfc3339;
+use crate::symbols::{line_of, load_asts, AstNode, CallGraph};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// 输出文件名，默认位于产物目录下
+const PROTOCOL_FILE_NAME: &str = "protocol.json";
+const PROTOCOL_DOT_FILE_NAME: &str = "protocol.dot";
+
+/// Anchor 工作区的配置文件
+const ANCHOR_TOML: &str = "Anchor.toml";
+
+/// callgraph.rs 中无法确定目标程序的 CPI
+const UNRESOLVED_PROGRAM: &str = "<unresolved>";
+
+/// 常见程序的ID
+const KNOWN_PROGRAM_IDS: &[(&str, &str)] = &[
+    ("11111111111111111111111111111111", "system_program"),
+    ("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", "spl_token"),
+    (
+        "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
+        "spl_token_2022",
+    ),
+    (
+        "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL",
+        "spl_associated_token_account",
+    ),
+    ("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr", "spl_memo"),
+    (
+        "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s",
+        "mpl_token_metadata",
+    ),
+];
+
+/// `agent protocol` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct ProtocolArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 protocol.json；protocol.dot 写在同一目录下
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// Anchor.toml 中需要的部分：[programs.<cluster>] 下的 <程序名> = "<ID>" 或 { address = "<ID>", .. }
+#[derive(Deserialize, Debug, Default)]
+struct AnchorToml {
+    #[serde(default)]
+    programs: BTreeMap<String, BTreeMap<String, toml::Value>>,
+}
+
+/// Cargo.toml 中需要的部分
+#[derive(Deserialize, Debug)]
+struct CargoToml {
+    package: Option<CargoPackage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CargoPackage {
+    name: String,
+}
+
+/// 目标程序是如何确定的
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum Resolution {
+    /// 按调用路径中的程序名或 crate 名
+    Name,
+    /// 按函数中出现的程序ID
+    ProgramId,
+    Unresolved,
+}
+
+/// 项目中的一个程序
+#[derive(Serialize, Debug)]
+struct ProgramInfo {
+    name: String,
+    /// 程序所在 crate 的包名
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crate_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    program_id: Option<String>,
+    file: PathBuf,
+    instructions: Vec<String>,
+}
+
+/// 一处程序间调用
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Invocation {
+    caller_program: String,
+    /// 能走到发起调用的函数的指令
+    caller_instructions: BTreeSet<String>,
+    callee_program: String,
+    /// 无法确定时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    callee_instruction: Option<String>,
+    /// 被调用的程序是否也在项目中
+    internal: bool,
+    resolved_by: Resolution,
+    function: String,
+    file: PathBuf,
+    line: usize,
+}
+
+/// protocol.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct ProtocolReport {
+    metadata: ProtocolMetadata,
+    programs: Vec<ProgramInfo>,
+    /// 项目外被调用的程序
+    external_programs: BTreeSet<String>,
+    invocations: Vec<Invocation>,
+}
+
+#[derive(Serialize, Debug)]
+struct ProtocolMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 文件所在 crate 的目录 (最近的含有 Cargo.toml 的上级目录，相对于项目根目录)
+fn crate_dir(project: &Path, file: &Path) -> PathBuf {
+    let mut dir = file.parent();
+    while let Some(current) = dir {
+        if project.join(current).join("Cargo.toml").is_file() {
+            return current.to_path_buf();
+        }
+        dir = current.parent();
+    }
+    PathBuf::new()
+}
+
+/// crate 的包名，`-` 换成 `_` 以便与代码中的路径比较
+fn crate_name(project: &Path, dir: &Path) -> Option<String> {
+    let content = fs::read_to_string(project.join(dir).join("Cargo.toml")).ok()?;
+    let manifest: CargoToml = toml::from_str(&content).ok()?;
+    Some(manifest.package?.name.replace('-', "_"))
+}
+
+/// 文件中 declare_id!("..") 声明的程序ID
+fn collect_declared_ids(node: &AstNode, ids: &mut Vec<String>) {
+    for item in &node.children {
+        if item.kind == "macro_invocation" {
+            let text: String = item.text.split_whitespace().collect();
+            if let Some(id) = text
+                .strip_prefix("declare_id!(\"")
+                .and_then(|rest| rest.split('"').next())
+            {
+                ids.push(id.to_string());
+            }
+        }
+        collect_declared_ids(item, ids);
+    }
+}
+
+/// Anchor.toml 中各程序的ID；同一程序在不同集群上的ID都保留
+fn anchor_program_ids(project: &Path) -> Vec<(String, String)> {
+    let Ok(content) = fs::read_to_string(project.join(ANCHOR_TOML)) else {
+        return vec![];
+    };
+    let anchor: AnchorToml = match toml::from_str(&content) {
+        Ok(anchor) => anchor,
+        Err(e) => {
+            debug!(error = %e, "无法解析 Anchor.toml");
+            return vec![];
+        }
+    };
+    let mut ids = vec![];
+    for programs in anchor.programs.values() {
+        for (name, value) in programs {
+            let id = match value {
+                toml::Value::String(id) => Some(id.as_str()),
+                toml::Value::Table(table) => table.get("address").and_then(|a| a.as_str()),
+                _ => None,
+            };
+            if let Some(id) = id {
+                ids.push((name.replace('-', "_"), id.to_string()));
+            }
+        }
+    }
+    ids
+}
+
+/// 函数中构造的指令：<程序>::instruction::<Ix> { .. }.data() 或 sighash 的 "global:<ix>"
+fn instruction_in(text: &str, program: &str) -> Option<String> {
+    let prefix = format!("{}::instruction::", program);
+    if let Some(start) = text.find(&prefix) {
+        let rest = &text[start + prefix.len()..];
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if end > 0 {
+            return Some(snake_case(&rest[..end]));
+        }
+    }
+    let start = text.find("\"global:")? + "\"global:".len();
+    let end = text[start..].find('"')?;
+    Some(text[start..start + end].to_string())
+}
+
+/// 按函数中出现的程序ID确定 invoke 的目标程序；`ids` 中的名字可以是 crate 名，按 `names` 换成程序名；
+/// 出现多个程序时无法确定
+fn program_by_id<'a>(
+    text: &str,
+    ids: &'a [(String, String)],
+    names: &'a HashMap<String, String>,
+) -> Option<&'a str> {
+    let mentioned: BTreeSet<&str> = ids
+        .iter()
+        .filter(|(id, name)| {
+            text.contains(&format!("\"{}\"", id))
+                || text.contains(&format!("{}::ID", name))
+                || text.contains(&format!("{}::id()", name))
+        })
+        .map(|(_, name)| names.get(name).map_or(name.as_str(), String::as_str))
+        .collect();
+    match mentioned.len() {
+        1 => mentioned.into_iter().next(),
+        _ => None,
+    }
+}
+
+/// 程序级调用图：项目中的程序为实线框，外部程序为虚线框，同一对程序之间的调用合并为一条边
+fn to_dot(report: &ProtocolReport) -> String {
+    let mut dot = String::from("digraph protocol {\n    rankdir=LR;\n    node [shape=box];\n");
+    for program in &report.programs {
+        let label = match &program.program_id {
+            Some(id) => format!("{}\\n{}", program.name, id),
+            None => program.name.clone(),
+        };
+        writeln!(dot, "    \"{}\" [label=\"{}\"];", program.name, label).unwrap();
+    }
+    for program in &report.external_programs {
+        writeln!(dot, "    \"{}\" [style=dashed];", program).unwrap();
+    }
+    let mut labels: BTreeMap<(&str, &str), BTreeSet<String>> = BTreeMap::new();
+    for invocation in &report.invocations {
+        let callee = invocation.callee_instruction.as_deref().unwrap_or("?");
+        for caller in &invocation.caller_instructions {
+            let caller = caller.rsplit("::").next().unwrap_or(caller);
+            labels
+                .entry((&invocation.caller_program, &invocation.callee_program))
+                .or_default()
+                .insert(format!("{} → {}", caller, callee));
+        }
+    }
+    for ((caller, callee), label) in labels {
+        let label: Vec<String> = label.into_iter().collect();
+        writeln!(
+            dot,
+            "    \"{}\" -> \"{}\" [label=\"{}\"];",
+            caller,
+            callee,
+            label.join("\\n")
+        )
+        .unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// 解析项目中各程序之间的 CPI，写出 protocol.json 和 protocol.dot
+pub fn run(args: &ProtocolArgs) -> Result<(), Box<dyn Error>> {
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut programs = vec![];
+    let mut calls = CallGraph::default();
+    // crate 目录 -> declare_id! 声明的ID
+    let mut declared: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    let mut sources = HashMap::new();
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        collect_programs(root, file, &source, &mut programs);
+        calls.add_file(file, root);
+        collect_declared_ids(root, declared.entry(crate_dir(project, file)).or_default());
+        sources.insert(file.clone(), source);
+    }
+
+    // 程序、crate 名和ID；crate 名 -> 程序名，ID -> 程序名
+    let mut infos = vec![];
+    let mut names: HashMap<String, String> = HashMap::new();
+    let mut ids: Vec<(String, String)> = vec![];
+    let anchor_ids = anchor_program_ids(project);
+    for program in &programs {
+        let dir = crate_dir(project, &program.file);
+        let crate_name = crate_name(project, &dir);
+        let program_id = declared
+            .get(&dir)
+            .and_then(|ids| ids.first().cloned())
+            .or_else(|| {
+                anchor_ids
+                    .iter()
+                    .find(|(name, _)| *name == program.name || Some(name) == crate_name.as_ref())
+                    .map(|(_, id)| id.clone())
+            });
+        names.insert(program.name.clone(), program.name.clone());
+        if let Some(crate_name) = &crate_name {
+            names.insert(crate_name.clone(), program.name.clone());
+        }
+        if let Some(id) = &program_id {
+            ids.push((id.clone(), program.name.clone()));
+            if let Some(crate_name) = &crate_name {
+                ids.push((id.clone(), crate_name.clone()));
+            }
+        }
+        for (name, id) in &anchor_ids {
+            if *name == program.name || Some(name) == crate_name.as_ref() {
+                ids.push((id.clone(), program.name.clone()));
+            }
+        }
+        infos.push(ProgramInfo {
+            name: program.name.clone(),
+            crate_name,
+            program_id,
+            file: program.file.clone(),
+            instructions: program
+                .handlers
+                .iter()
+                .map(|(h, _)| format!("{}::{}", program.name, h.function))
+                .collect(),
+        });
+    }
+    ids.extend(
+        KNOWN_PROGRAM_IDS
+            .iter()
+            .map(|(id, name)| (id.to_string(), name.to_string())),
+    );
+    ids.sort();
+    ids.dedup();
+
+    // 函数 -> 能走到它的指令；函数所在 crate -> 程序
+    let mut reached_by: HashMap<(PathBuf, String), BTreeSet<String>> = HashMap::new();
+    let mut crate_programs: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    for program in &programs {
+        crate_programs
+            .entry(crate_dir(project, &program.file))
+            .or_default()
+            .push(program.name.clone());
+        for (handler, _) in &program.handlers {
+            let instruction = format!("{}::{}", program.name, handler.function);
+            for key in calls.reachable(&handler.file, &handler.function) {
+                reached_by
+                    .entry(key)
+                    .or_default()
+                    .insert(instruction.clone());
+            }
+        }
+    }
+
+    let mut invocations = BTreeSet::new();
+    let mut external_programs = BTreeSet::new();
+    for (file, root) in &asts {
+        let Some(source) = sources.get(file) else {
+            continue;
+        };
+        let dir = crate_dir(project, file);
+        let mut items = vec![];
+        collect_function_items(root, &mut items);
+        for (function, item) in items {
+            let caller_instructions = reached_by
+                .get(&(file.clone(), function.to_string()))
+                .cloned()
+                .unwrap_or_default();
+            // 调用方：能走到该函数的指令所在的程序，否则为函数所在 crate 中唯一的程序
+            let caller_programs: BTreeSet<String> = if caller_instructions.is_empty() {
+                match crate_programs.get(&dir).map(Vec::as_slice) {
+                    Some([program]) => BTreeSet::from([program.clone()]),
+                    _ => BTreeSet::new(),
+                }
+            } else {
+                caller_instructions
+                    .iter()
+                    .filter_map(|ix| ix.split("::").next().map(str::to_string))
+                    .collect()
+            };
+            if caller_programs.is_empty() {
+                continue;
+            }
+            for (program, instruction) in cpi_targets(&item.text) {
+                let (callee_program, callee_instruction, resolved_by) =
+                    if program == UNRESOLVED_PROGRAM {
+                        match program_by_id(&item.text, &ids, &names) {
+                            Some(name) => {
+                                let crate_name = infos
+                                    .iter()
+                                    .find(|p| p.name == name)
+                                    .and_then(|p| p.crate_name.as_deref());
+                                let instruction = instruction_in(&item.text, name)
+                                    .or_else(|| instruction_in(&item.text, crate_name?));
+                                (name.to_string(), instruction, Resolution::ProgramId)
+                            }
+                            None => (program, None, Resolution::Unresolved),
+                        }
+                    } else {
+                        let name = names.get(&program).cloned().unwrap_or(program);
+                        (name, Some(instruction), Resolution::Name)
+                    };
+                let internal = infos.iter().any(|p| p.name == callee_program);
+                if !internal {
+                    external_programs.insert(callee_program.clone());
+                }
+                for caller_program in &caller_programs {
+                    invocations.insert(Invocation {
+                        caller_program: caller_program.clone(),
+                        caller_instructions: caller_instructions
+                            .iter()
+                            .filter(|ix| ix.starts_with(&format!("{}::", caller_program)))
+                            .cloned()
+                            .collect(),
+                        callee_program: callee_program.clone(),
+                        callee_instruction: callee_instruction.clone(),
+                        internal,
+                        resolved_by,
+                        function: function.to_string(),
+                        file: file.clone(),
+                        line: line_of(source, item.start_byte),
+                    });
+                }
+            }
+        }
+    }
+
+    let report = ProtocolReport {
+        metadata: ProtocolMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        programs: infos,
+        external_programs,
+        invocations: invocations.into_iter().collect(),
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(PROTOCOL_FILE_NAME));
+    let dot_output = output.with_file_name(PROTOCOL_DOT_FILE_NAME);
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    fs::write(&dot_output, to_dot(&report))?;
+    info!(
+        programs = report.programs.len(),
+        external_programs = report.external_programs.len(),
+        invocations = report.invocations.len(),
+        internal_invocations = report.invocations.iter().filter(|i| i.internal).count(),
+        output = %output.display(),
+        dot = %dot_output.display(),
+        "已写出程序间调用图"
+    );
+    Ok(())
+}
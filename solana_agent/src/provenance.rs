@@ -0,0 +1,345 @@
+// provenance.rs
+//
+// 跨层溯源：把合并图中的节点对应到 CFG 语句和AST语法节点，三层的标识由此关联起来
+// AST语法节点使用AST中的节点ID；较早生成的AST没有ID，此时用 `<文件>@<起始字节>..<结束字节>:<种类>` 标识，
+// 在同一份源码上稳定，写法与节点的 provenance 一致
+// CFG 基本块 (AST层) 的每条语句由生成器在 statement_nodes 属性中给出来源的语法节点；
+// 较早的产物没有该属性时，整个基本块对应到包含其源码范围的最小语法节点
+// MIR节点 (CPG) 经 SameSource 边对应到 CFG 基本块，取其中源码范围包含它的最小的一条语句，语法节点为包含其范围的最小语法节点
+
+use crate::graph::{EdgeKind, Layer};
+use crate::merge::{MergedGraph, MergedNode};
+use crate::symbols::AstNode;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// 一个图节点 (或 CFG 基本块中的一条语句) 在各层中的对应
+#[derive(Serialize, Debug, Clone)]
+pub struct Provenance {
+    /// 合并图中的节点ID
+    pub node: String,
+    /// CFG 基本块中语句的序号；整个节点对应同一处源码时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statement: Option<usize>,
+    /// MIR节点的同源 CFG 基本块及其中的语句
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cfg_node: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cfg_statement: Option<usize>,
+    /// 对应的AST语法节点
+    pub ast_node: String,
+    pub ast_kind: String,
+    pub file: PathBuf,
+    pub start_byte: usize,
+    pub end_byte: usize,
+}
+
+/// 没有节点ID的AST中语法节点的标识
+pub fn ast_node_id(file: &Path, kind: &str, start_byte: usize, end_byte: usize) -> String {
+    format!("{}@{}..{}:{}", file.display(), start_byte, end_byte, kind)
+}
+
+/// 源码范围包含 [start, end) 的最小语法节点
+fn enclosing(root: &AstNode, start: usize, end: usize) -> Option<&AstNode> {
+    if root.start_byte > start || end > root.end_byte {
+        return None;
+    }
+    let mut node = root;
+    while let Some(child) = node
+        .children
+        .iter()
+        .find(|c| c.start_byte <= start && end <= c.end_byte)
+    {
+        node = child;
+    }
+    Some(node)
+}
+
+/// 对应的语法节点
+struct SyntaxNode<'a> {
+    /// AST中的节点ID，较早的产物中没有
+    id: Option<&'a str>,
+    kind: &'a str,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> From<&'a AstNode> for SyntaxNode<'a> {
+    fn from(node: &'a AstNode) -> Self {
+        SyntaxNode {
+            id: Some(node.id.as_str()).filter(|id| !id.is_empty()),
+            kind: &node.kind,
+            start: node.start_byte,
+            end: node.end_byte,
+        }
+    }
+}
+
+/// CFG 基本块中各语句来源的语法节点，按语句序号排列，没有来源的语句为 None
+fn statement_nodes(node: &MergedNode) -> Vec<Option<SyntaxNode<'_>>> {
+    node.properties
+        .get("statement_nodes")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|source| {
+            let byte = |name| source.get(name).and_then(Value::as_u64).map(|b| b as usize);
+            Some(SyntaxNode {
+                id: source.get("id").and_then(Value::as_str),
+                kind: source.get("kind")?.as_str()?,
+                start: byte("start_byte")?,
+                end: byte("end_byte")?,
+            })
+        })
+        .collect()
+}
+
+/// 合并图 (或 agent link 的跨语言图) 中所有带源码范围的节点的跨层对应
+pub fn provenance(graph: &MergedGraph, asts: &[(PathBuf, AstNode)]) -> Vec<Provenance> {
+    let roots: HashMap<&Path, &AstNode> = asts
+        .iter()
+        .map(|(file, root)| (file.as_path(), root))
+        .collect();
+    let nodes: HashMap<&str, &MergedNode> =
+        graph.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let same_source: HashMap<&str, &str> = graph
+        .edges
+        .iter()
+        .filter(|e| e.kind == EdgeKind::SameSource)
+        .map(|e| (e.source.as_str(), e.target.as_str()))
+        .collect();
+
+    let mut result = vec![];
+    for node in &graph.nodes {
+        let Some(span) = &node.span else {
+            continue;
+        };
+        let entry = |statement, cfg_node, cfg_statement, ast: SyntaxNode| Provenance {
+            node: node.id.clone(),
+            statement,
+            cfg_node,
+            cfg_statement,
+            ast_node: ast.id.map_or_else(
+                || ast_node_id(&span.file, ast.kind, ast.start, ast.end),
+                str::to_string,
+            ),
+            ast_kind: ast.kind.to_string(),
+            file: span.file.clone(),
+            start_byte: ast.start,
+            end_byte: ast.end,
+        };
+        let smallest = || {
+            roots
+                .get(span.file.as_path())
+                .and_then(|root| enclosing(root, span.start_byte, span.end_byte))
+        };
+        match node.layer {
+            Layer::Ast => {
+                let sources = statement_nodes(node);
+                if sources.iter().any(Option::is_some) {
+                    for (i, source) in sources.into_iter().enumerate() {
+                        if let Some(source) = source {
+                            result.push(entry(Some(i), None, None, source));
+                        }
+                    }
+                } else if let Some(ast) = smallest() {
+                    result.push(entry(None, None, None, ast.into()));
+                }
+            }
+            Layer::Mir => {
+                let cfg_node = same_source.get(node.id.as_str()).copied();
+                let cfg_statement = cfg_node
+                    .and_then(|id| nodes.get(id))
+                    .and_then(|cfg| {
+                        statement_nodes(cfg)
+                            .into_iter()
+                            .enumerate()
+                            .filter_map(|(i, source)| Some((i, source?)))
+                            .filter(|(_, source)| {
+                                source.start <= span.start_byte && span.end_byte <= source.end
+                            })
+                            .min_by_key(|(_, source)| source.end - source.start)
+                    })
+                    .map(|(i, _)| i);
+                if let Some(ast) = smallest() {
+                    result.push(entry(
+                        None,
+                        cfg_node.map(str::to_string),
+                        cfg_statement,
+                        ast.into(),
+                    ));
+                }
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{NodeKind, Span};
+    use crate::merge::{MergedEdge, MergedMetadata};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn syntax(
+        id: &str,
+        kind: &str,
+        start_byte: usize,
+        end_byte: usize,
+        children: Vec<AstNode>,
+    ) -> AstNode {
+        AstNode {
+            id: id.to_string(),
+            kind: kind.to_string(),
+            field: None,
+            text: String::new(),
+            start_byte,
+            end_byte,
+            start_line: 0,
+            start_column: 0,
+            end_line: 0,
+            end_column: 0,
+            children,
+            source: None,
+        }
+    }
+
+    fn node(id: &str, layer: Layer, span: Option<(usize, usize)>) -> MergedNode {
+        MergedNode {
+            id: id.to_string(),
+            layer,
+            function: "deposit".to_string(),
+            kind: NodeKind::BasicBlock,
+            label: String::new(),
+            span: span.map(|(start_byte, end_byte)| Span {
+                file: PathBuf::from("src/lib.rs"),
+                start_byte,
+                end_byte,
+            }),
+            provenance: None,
+            properties: BTreeMap::new(),
+        }
+    }
+
+    /// fn deposit { let a = x + 1; transfer(a); } 的AST，`with_ids` 为 false 时模拟较早的没有节点ID的产物
+    fn ast(with_ids: bool) -> Vec<(PathBuf, AstNode)> {
+        let id = |id: &'static str| if with_ids { id } else { "" };
+        let root = syntax(
+            id("f:0"),
+            "source_file",
+            0,
+            100,
+            vec![syntax(
+                id("f:1"),
+                "function_item",
+                0,
+                100,
+                vec![syntax(
+                    id("f:2"),
+                    "block",
+                    20,
+                    100,
+                    vec![
+                        syntax(
+                            id("f:3"),
+                            "let_declaration",
+                            22,
+                            40,
+                            vec![syntax(id("f:4"), "binary_expression", 30, 35, vec![])],
+                        ),
+                        syntax(id("f:5"), "expression_statement", 41, 53, vec![]),
+                    ],
+                )],
+            )],
+        );
+        vec![(PathBuf::from("src/lib.rs"), root)]
+    }
+
+    #[test]
+    fn cfg_statements_map_to_their_syntax_nodes() {
+        let mut block = node("ast:src/lib.rs:deposit#1", Layer::Ast, Some((22, 53)));
+        block.properties.insert(
+            "statement_nodes".to_string(),
+            json!([
+                null,
+                {"id": "f:3", "kind": "let_declaration", "start_byte": 22, "end_byte": 40},
+                {"kind": "expression_statement", "start_byte": 41, "end_byte": 53},
+            ]),
+        );
+        // 没有 statement_nodes 的基本块对应到包含其范围的最小语法节点
+        let old = node("ast:src/lib.rs:deposit#2", Layer::Ast, Some((30, 35)));
+        let graph = MergedGraph {
+            metadata: MergedMetadata::current(),
+            nodes: vec![
+                block,
+                old,
+                node("ast:src/lib.rs:deposit#0", Layer::Ast, None),
+            ],
+            edges: vec![],
+        };
+        let result = provenance(&graph, &ast(true));
+        let entries: Vec<_> = result
+            .iter()
+            .map(|p| {
+                (
+                    p.node.as_str(),
+                    p.statement,
+                    p.ast_node.as_str(),
+                    p.ast_kind.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "ast:src/lib.rs:deposit#1",
+                    Some(1),
+                    "f:3",
+                    "let_declaration"
+                ),
+                (
+                    "ast:src/lib.rs:deposit#1",
+                    Some(2),
+                    "src/lib.rs@41..53:expression_statement",
+                    "expression_statement"
+                ),
+                ("ast:src/lib.rs:deposit#2", None, "f:4", "binary_expression"),
+            ]
+        );
+    }
+
+    #[test]
+    fn mir_nodes_follow_same_source_edges() {
+        let mut block = node("ast:src/lib.rs:deposit#1", Layer::Ast, Some((22, 53)));
+        block.properties.insert(
+            "statement_nodes".to_string(),
+            json!([
+                {"kind": "block", "start_byte": 20, "end_byte": 100},
+                {"kind": "let_declaration", "start_byte": 22, "end_byte": 40},
+            ]),
+        );
+        let mir = node("mir:src/lib.rs:deposit#7", Layer::Mir, Some((30, 35)));
+        let graph = MergedGraph {
+            metadata: MergedMetadata::current(),
+            nodes: vec![block, mir],
+            edges: vec![MergedEdge {
+                source: "mir:src/lib.rs:deposit#7".to_string(),
+                target: "ast:src/lib.rs:deposit#1".to_string(),
+                kind: EdgeKind::SameSource,
+            }],
+        };
+        // 较早的AST没有节点ID，按位置标识
+        let result = provenance(&graph, &ast(false));
+        let mir = result.iter().find(|p| p.node.starts_with("mir:")).unwrap();
+        assert_eq!(mir.cfg_node.as_deref(), Some("ast:src/lib.rs:deposit#1"));
+        // 两条语句都包含它，取范围较小的 let
+        assert_eq!(mir.cfg_statement, Some(1));
+        assert_eq!(mir.ast_node, "src/lib.rs@30..35:binary_expression");
+        assert_eq!((mir.start_byte, mir.end_byte), (30, 35));
+    }
+}
@@ -0,0 +1,177 @@
+// python.rs
+//
+// Python 绑定 (启用 python feature 时编译)，由 maturin 打包为 solana_agent 模块：
+//   parse(path, source=None)                       源文件的AST (dict)
+//   build_cfg(path, source=None)                   源文件中每个函数的CFG (list)
+//   load_cpg(project=".", artifacts=None, config=None)          agent merge 的合并图
+//   query(expr, project=".", artifacts=None, config=None, edges=None, limit=100)
+// 图都采用 networkx (3.4 及以上) 的 node-link 格式，可以直接传给 networkx.node_link_graph
+
+use crate::config::ArtifactsArgs;
+use crate::graph::EdgeKind;
+use crate::merge::{load_merged, MergedGraph};
+use crate::query::{execute, parse_edge_kind, QueryResult};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+use solana_ast_generator::{node_to_serializable, Language, SerializableNode};
+use solana_cfg_generator::{build_function_cfg, find_functions, function_name, AstNode};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 把Rust侧的错误转换为 Python 的 RuntimeError
+fn error(e: impl Display) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+/// 通过 JSON 把可序列化的值转换为 Python 的 dict/list
+fn to_python(py: Python<'_>, value: &impl Serialize) -> PyResult<PyObject> {
+    let text = serde_json::to_string(value).map_err(error)?;
+    Ok(py.import("json")?.call_method1("loads", (text,))?.unbind())
+}
+
+/// networkx node-link 格式的图，节点和边保留各自的全部字段
+fn node_link(graph: Value, nodes: Value, edges: Value) -> Value {
+    json!({
+        "directed": true,
+        "multigraph": true,
+        "graph": graph,
+        "nodes": nodes,
+        "edges": edges,
+    })
+}
+
+/// 解析源文件；没有给出 source 时从 path 读取
+fn parse_source(path: &str, source: Option<String>) -> PyResult<SerializableNode> {
+    let language = Path::new(path)
+        .extension()
+        .and_then(|s| s.to_str())
+        .and_then(Language::from_extension)
+        .ok_or_else(|| error(format!("不支持的文件类型: {}", path)))?;
+    let source = match source {
+        Some(source) => source,
+        None => fs::read_to_string(path).map_err(error)?,
+    };
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&language.grammar().map_err(error)?)
+        .map_err(error)?;
+    let tree = parser
+        .parse(&source, None)
+        .ok_or_else(|| error("tree-sitter 解析失败"))?;
+    Ok(node_to_serializable(tree.root_node(), &source, path))
+}
+
+/// 读取产物目录中的所有图并合并
+fn load_graph(
+    project: PathBuf,
+    artifacts: Option<PathBuf>,
+    config: Option<PathBuf>,
+) -> PyResult<(PathBuf, MergedGraph)> {
+    let artifacts = ArtifactsArgs {
+        project,
+        config,
+        artifacts,
+    };
+    let (_, graph) = load_merged(&artifacts).map_err(error)?;
+    Ok((artifacts.project, graph))
+}
+
+/// 解析一个源文件，返回与 solana_ast_generator 输出的 .ast.json 相同结构的AST
+#[pyfunction]
+#[pyo3(signature = (path, source=None))]
+fn parse(py: Python<'_>, path: &str, source: Option<String>) -> PyResult<PyObject> {
+    to_python(py, &parse_source(path, source)?)
+}
+
+/// 解析一个源文件并为其中的每个函数构建CFG
+#[pyfunction]
+#[pyo3(signature = (path, source=None))]
+fn build_cfg(py: Python<'_>, path: &str, source: Option<String>) -> PyResult<PyObject> {
+    let ast = serde_json::to_value(parse_source(path, source)?).map_err(error)?;
+    let root: AstNode = serde_json::from_value(ast).map_err(error)?;
+    let mut functions = vec![];
+    find_functions(&root, &mut functions);
+    let mut graphs = vec![];
+    for func_node in functions {
+        let Some(builder) = build_function_cfg(func_node, None) else {
+            continue;
+        };
+        let graph = builder.to_graph(&function_name(func_node), Path::new(path));
+        graphs.push(node_link(
+            json!({ "layer": graph.layer, "function": graph.function }),
+            json!(graph.nodes),
+            json!(graph.edges),
+        ));
+    }
+    to_python(py, &graphs)
+}
+
+/// 读取 agent analyze 的输出，返回整个项目的合并图 (与 agent merge 相同)
+#[pyfunction]
+#[pyo3(signature = (project=PathBuf::from("."), artifacts=None, config=None))]
+fn load_cpg(
+    py: Python<'_>,
+    project: PathBuf,
+    artifacts: Option<PathBuf>,
+    config: Option<PathBuf>,
+) -> PyResult<PyObject> {
+    let (_, graph) = load_graph(project, artifacts, config)?;
+    to_python(
+        py,
+        &node_link(
+            json!(graph.metadata),
+            json!(graph.nodes),
+            json!(graph.edges),
+        ),
+    )
+}
+
+/// 在合并图上执行 agent query 的查询表达式
+/// 节点查询返回节点的列表，可达性查询返回路径 (节点列表) 的列表，
+/// 支配关系查询返回 {"dominator": 节点, "dominated": 节点} 的列表
+#[pyfunction]
+#[pyo3(signature = (expr, project=PathBuf::from("."), artifacts=None, config=None, edges=None, limit=100))]
+fn query(
+    py: Python<'_>,
+    expr: &str,
+    project: PathBuf,
+    artifacts: Option<PathBuf>,
+    config: Option<PathBuf>,
+    edges: Option<Vec<String>>,
+    limit: usize,
+) -> PyResult<PyObject> {
+    let edges: Vec<EdgeKind> = match edges {
+        Some(edges) => edges
+            .iter()
+            .map(|e| parse_edge_kind(e))
+            .collect::<Result<_, _>>()
+            .map_err(error)?,
+        None => vec![EdgeKind::ControlFlow, EdgeKind::DataFlow, EdgeKind::Call],
+    };
+    let (_, graph) = load_graph(project, artifacts, config)?;
+    let node = |i: usize| json!(graph.nodes[i]);
+    let result = match execute(&graph, expr, &edges, limit).map_err(error)? {
+        QueryResult::Nodes(nodes) => nodes.into_iter().map(node).collect::<Vec<_>>(),
+        QueryResult::Paths(paths) => paths
+            .into_iter()
+            .map(|path| path.into_iter().map(node).collect())
+            .collect(),
+        QueryResult::Dominators(pairs) => pairs
+            .into_iter()
+            .map(|(d, t)| json!({ "dominator": node(d), "dominated": node(t) }))
+            .collect(),
+    };
+    to_python(py, &result)
+}
+
+#[pymodule]
+fn solana_agent(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(build_cfg, m)?)?;
+    m.add_function(wrap_pyfunction!(load_cpg, m)?)?;
+    m.add_function(wrap_pyfunction!(query, m)?)?;
+    Ok(())
+}
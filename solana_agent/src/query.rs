@@ -0,0 +1,422 @@
+// query.rs
+//
+// agent query 的查询语言：
+//
+//   <filter>   := <key>=<value> | <key>!=<value> | <key>~<子串>
+//   <selector> := [<filter>, ...]              空的 [] 匹配所有节点
+//   <expr>     := <selector>                   列出匹配的节点
+//               | <selector> -> <selector>     从前者可以到达后者 (沿 --edge 指定的边)
+//               | <selector> dom <selector>    前者支配后者 (同一函数内的控制流)
+//
+// key 可以是 id、layer、kind、function、label、file、provenance 或节点的任意 properties
+// 值中含有 `,` 或 `]` 时用双引号括起来，例如 [label~"a, b"]
+
+use crate::config::ArtifactsArgs;
+use crate::graph::{EdgeKind, NodeKind};
+use crate::merge::{graph_key, load_merged, MergedGraph, MergedNode};
+use petgraph::algo::dominators;
+use petgraph::graph::{DiGraph, NodeIndex};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// `agent query` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct QueryArgs {
+    /// 查询表达式，例如 '[kind=terminator,label~invoke] -> [kind=exit]'
+    expr: String,
+
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 可达性查询沿哪些边搜索
+    #[arg(
+        long = "edge",
+        value_delimiter = ',',
+        value_parser = parse_edge_kind,
+        default_values = ["control_flow", "data_flow", "call"]
+    )]
+    edges: Vec<EdgeKind>,
+
+    /// 最多输出多少条结果
+    #[arg(long, default_value_t = 100)]
+    limit: usize,
+}
+
+/// 把 snake_case 的边种类名解析为 EdgeKind
+pub fn parse_edge_kind(value: &str) -> Result<EdgeKind, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| format!("未知的边种类 '{}'", value))
+}
+
+/// 单个过滤条件
+#[derive(Debug)]
+enum Filter {
+    Eq(String, String),
+    Ne(String, String),
+    Contains(String, String),
+}
+
+/// 一组过滤条件，全部满足时匹配
+#[derive(Debug)]
+pub struct Selector(Vec<Filter>);
+
+/// 解析后的查询
+#[derive(Debug)]
+enum Query {
+    Nodes(Selector),
+    Reach(Selector, Selector),
+    Dominates(Selector, Selector),
+}
+
+/// 解析一个 `[...]` 选择器，返回它和剩余的文本
+pub fn parse_selector(text: &str) -> Result<(Selector, &str), String> {
+    let text = text.trim_start();
+    let body = text
+        .strip_prefix('[')
+        .ok_or_else(|| format!("选择器应以 '[' 开头: {}", text))?;
+
+    // 按不在引号内的 `,` 切分，直到不在引号内的 `]`
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut in_quote = false;
+    let mut end = None;
+    for (i, c) in body.char_indices() {
+        match c {
+            '"' => in_quote = !in_quote,
+            ',' if !in_quote => parts.push(std::mem::take(&mut current)),
+            ']' if !in_quote => {
+                end = Some(i);
+                break;
+            }
+            _ => current.push(c),
+        }
+    }
+    let end = end.ok_or("选择器缺少 ']'")?;
+    parts.push(current);
+
+    let mut filters = vec![];
+    for part in parts.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let filter = if let Some((key, value)) = part.split_once("!=") {
+            Filter::Ne(key.trim().to_string(), value.trim().to_string())
+        } else if let Some((key, value)) = part.split_once('=') {
+            Filter::Eq(key.trim().to_string(), value.trim().to_string())
+        } else if let Some((key, value)) = part.split_once('~') {
+            Filter::Contains(key.trim().to_string(), value.trim().to_string())
+        } else {
+            return Err(format!("无法解析过滤条件 '{}'", part));
+        };
+        filters.push(filter);
+    }
+    Ok((Selector(filters), &body[end + 1..]))
+}
+
+/// 解析完整的查询表达式
+fn parse_query(expr: &str) -> Result<Query, String> {
+    let (left, rest) = parse_selector(expr)?;
+    let rest = rest.trim();
+    if rest.is_empty() {
+        return Ok(Query::Nodes(left));
+    }
+    let (operator, rest) = if let Some(rest) = rest.strip_prefix("->") {
+        ("->", rest)
+    } else if let Some(rest) = rest.strip_prefix("dom") {
+        ("dom", rest)
+    } else {
+        return Err(format!("未知的运算符: {}", rest));
+    };
+    let (right, rest) = parse_selector(rest)?;
+    if !rest.trim().is_empty() {
+        return Err(format!("查询末尾有多余的内容: {}", rest.trim()));
+    }
+    Ok(match operator {
+        "->" => Query::Reach(left, right),
+        _ => Query::Dominates(left, right),
+    })
+}
+
+/// 枚举值序列化后的名字，例如 NodeKind::BasicBlock -> "basic_block"
+pub fn enum_name<T: Serialize>(value: T) -> Option<String> {
+    serde_json::to_value(value)
+        .ok()?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// 取出节点某个字段的文本值
+fn field(node: &MergedNode, key: &str) -> Option<String> {
+    match key {
+        "id" => Some(node.id.clone()),
+        "layer" => enum_name(node.layer),
+        "kind" => enum_name(node.kind),
+        "function" => Some(node.function.clone()),
+        "label" => Some(node.label.clone()),
+        "file" => node.span.as_ref().map(|s| s.file.display().to_string()),
+        "provenance" => node.provenance.clone(),
+        _ => node.properties.get(key).map(|value| match value.as_str() {
+            Some(s) => s.to_string(),
+            None => value.to_string(),
+        }),
+    }
+}
+
+/// 去掉值两端的引号
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+impl Selector {
+    pub fn matches(&self, node: &MergedNode) -> bool {
+        self.0.iter().all(|filter| match filter {
+            Filter::Eq(key, value) => field(node, key).as_deref() == Some(unquote(value)),
+            Filter::Ne(key, value) => field(node, key).as_deref() != Some(unquote(value)),
+            Filter::Contains(key, value) => {
+                field(node, key).is_some_and(|v| v.contains(unquote(value)))
+            }
+        })
+    }
+}
+
+/// 把节点的源码范围转换为 `file:line:column`，读不到源文件时退回字节偏移
+pub struct Locator<'a> {
+    project: &'a Path,
+    sources: HashMap<PathBuf, Option<String>>,
+}
+
+impl<'a> Locator<'a> {
+    pub fn new(project: &'a Path) -> Self {
+        Locator {
+            project,
+            sources: HashMap::new(),
+        }
+    }
+
+    pub fn locate(&mut self, node: &MergedNode) -> String {
+        let Some(span) = &node.span else {
+            return "-".to_string();
+        };
+        let path = self.project.join(&span.file);
+        let source = self
+            .sources
+            .entry(path.clone())
+            .or_insert_with(|| fs::read_to_string(&path).ok());
+        match source.as_deref().and_then(|s| s.get(..span.start_byte)) {
+            Some(before) => {
+                let line = before.matches('\n').count() + 1;
+                let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+                format!("{}:{}:{}", span.file.display(), line, column)
+            }
+            None => format!("{}@{}", span.file.display(), span.start_byte),
+        }
+    }
+}
+
+/// 可达性查询：对每个匹配左侧的节点做广度优先搜索，返回到达右侧节点的最短路径
+fn reach(
+    graph: &MergedGraph,
+    from: &Selector,
+    to: &Selector,
+    edge_kinds: &[EdgeKind],
+    limit: usize,
+) -> Vec<Vec<usize>> {
+    let index: HashMap<&str, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.as_str(), i))
+        .collect();
+    let mut successors: HashMap<usize, Vec<usize>> = HashMap::new();
+    for edge in graph.edges.iter().filter(|e| edge_kinds.contains(&e.kind)) {
+        if let (Some(&s), Some(&t)) = (
+            index.get(edge.source.as_str()),
+            index.get(edge.target.as_str()),
+        ) {
+            successors.entry(s).or_default().push(t);
+        }
+    }
+
+    let mut paths = vec![];
+    for start in (0..graph.nodes.len()).filter(|&i| from.matches(&graph.nodes[i])) {
+        let mut parent: HashMap<usize, usize> = HashMap::new();
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            for &next in successors.get(&current).into_iter().flatten() {
+                if !visited.insert(next) {
+                    continue;
+                }
+                parent.insert(next, current);
+                queue.push_back(next);
+                if to.matches(&graph.nodes[next]) {
+                    let mut path = vec![next];
+                    while let Some(&p) = parent.get(path.last().unwrap()) {
+                        path.push(p);
+                    }
+                    path.reverse();
+                    paths.push(path);
+                    if paths.len() >= limit {
+                        return paths;
+                    }
+                }
+            }
+        }
+    }
+    paths
+}
+
+/// 单个函数的控制流图 (节点权重为 MergedGraph.nodes 中的下标) 及节点ID到图中下标的映射
+type FunctionGraph<'a> = (DiGraph<usize, ()>, HashMap<&'a str, NodeIndex>);
+
+/// 支配关系查询：在每个函数的控制流图内，返回 (支配者, 被支配者) 对
+fn dominates(
+    graph: &MergedGraph,
+    dominator: &Selector,
+    dominated: &Selector,
+    limit: usize,
+) -> Vec<(usize, usize)> {
+    // 按函数图分组建立 petgraph 图
+    let mut graphs: HashMap<&str, FunctionGraph> = HashMap::new();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let (g, index) = graphs.entry(graph_key(&node.id)).or_default();
+        index.insert(node.id.as_str(), g.add_node(i));
+    }
+    for edge in graph
+        .edges
+        .iter()
+        .filter(|e| e.kind == EdgeKind::ControlFlow)
+    {
+        if let Some((g, index)) = graphs.get_mut(graph_key(&edge.source)) {
+            if let (Some(&s), Some(&t)) = (
+                index.get(edge.source.as_str()),
+                index.get(edge.target.as_str()),
+            ) {
+                g.add_edge(s, t, ());
+            }
+        }
+    }
+
+    let mut pairs = vec![];
+    let mut keys: Vec<_> = graphs.keys().copied().collect();
+    keys.sort();
+    for key in keys {
+        let (g, _) = &graphs[key];
+        // 入口是AST层的 Entry 节点或MIR层的第一个节点，二者都是图中的第一个节点
+        let Some(entry) = g
+            .node_indices()
+            .find(|&n| graph.nodes[g[n]].kind == NodeKind::Entry)
+            .or(g.node_indices().next())
+        else {
+            continue;
+        };
+        let doms = dominators::simple_fast(g, entry);
+        for target in g
+            .node_indices()
+            .filter(|&n| dominated.matches(&graph.nodes[g[n]]))
+        {
+            let Some(chain) = doms.dominators(target) else {
+                continue; // 从入口不可达
+            };
+            for d in chain.filter(|&d| d != target && dominator.matches(&graph.nodes[g[d]])) {
+                pairs.push((g[d], g[target]));
+                if pairs.len() >= limit {
+                    return pairs;
+                }
+            }
+        }
+    }
+    pairs
+}
+
+/// 查询的结果，下标为 MergedGraph.nodes 中的下标
+pub enum QueryResult {
+    /// 匹配的节点
+    Nodes(Vec<usize>),
+    /// 可达性查询找到的路径
+    Paths(Vec<Vec<usize>>),
+    /// 支配关系查询找到的 (支配者, 被支配者) 对
+    Dominators(Vec<(usize, usize)>),
+}
+
+impl QueryResult {
+    /// 结果的条数
+    pub fn len(&self) -> usize {
+        match self {
+            QueryResult::Nodes(nodes) => nodes.len(),
+            QueryResult::Paths(paths) => paths.len(),
+            QueryResult::Dominators(pairs) => pairs.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 在合并图上解析并执行查询表达式，最多返回 `limit` 条结果
+pub fn execute(
+    graph: &MergedGraph,
+    expr: &str,
+    edges: &[EdgeKind],
+    limit: usize,
+) -> Result<QueryResult, String> {
+    let query = parse_query(expr).map_err(|e| format!("查询语法错误: {}", e))?;
+    Ok(match &query {
+        Query::Nodes(selector) => QueryResult::Nodes(
+            (0..graph.nodes.len())
+                .filter(|&i| selector.matches(&graph.nodes[i]))
+                .take(limit)
+                .collect(),
+        ),
+        Query::Reach(from, to) => QueryResult::Paths(reach(graph, from, to, edges, limit)),
+        Query::Dominates(dominator, dominated) => {
+            QueryResult::Dominators(dominates(graph, dominator, dominated, limit))
+        }
+    })
+}
+
+/// 把查询结果打印到 stdout，每个节点一行：位置、ID和标签的第一行
+pub fn print_result(graph: &MergedGraph, result: &QueryResult, locator: &mut Locator) {
+    let mut print_node = |prefix: &str, node: &MergedNode| {
+        let label = node.label.lines().next().unwrap_or("");
+        println!("{}{}  {}  {}", prefix, locator.locate(node), node.id, label);
+    };
+
+    match result {
+        QueryResult::Nodes(nodes) => {
+            for &i in nodes {
+                print_node("", &graph.nodes[i]);
+            }
+        }
+        QueryResult::Paths(paths) => {
+            for path in paths {
+                println!("路径 ({} 步):", path.len() - 1);
+                for &i in path {
+                    print_node("    ", &graph.nodes[i]);
+                }
+            }
+        }
+        QueryResult::Dominators(pairs) => {
+            for &(d, t) in pairs {
+                print_node("", &graph.nodes[d]);
+                print_node("  支配 ", &graph.nodes[t]);
+            }
+        }
+    }
+}
+
+/// 解析并执行查询，把结果打印到 stdout
+pub fn run(args: &QueryArgs) -> Result<(), Box<dyn Error>> {
+    // 先检查语法，避免在读入图之后才报错
+    parse_query(&args.expr).map_err(|e| format!("查询语法错误: {}", e))?;
+    let (_, graph) = load_merged(&args.artifacts)?;
+    let result = execute(&graph, &args.expr, &args.edges, args.limit)?;
+    print_result(&graph, &result, &mut Locator::new(&args.artifacts.project));
+    info!(matches = result.len(), limit = args.limit, "查询完成");
+    Ok(())
+}
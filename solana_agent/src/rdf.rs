@@ -0,0 +1,294 @@
+// rdf.rs
+//
+// 把合并图写成 RDF (Turtle)，供 agent merge --emit-rdf 使用
+// 本体很小：每种节点一个类 (都是 sa:Node 的子类)，每种边一个对象属性，节点字段为数据属性，
+// 节点的 properties 放在 prop: 命名空间下；函数和源文件也是资源，便于在 SPARQL 中按函数或文件连接外部数据
+// IRI 使用 urn: 形式，不依赖任何网络地址
+
+use crate::graph::{EdgeKind, Layer, NodeKind};
+use crate::merge::MergedGraph;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// 本体的命名空间
+const ONTOLOGY: &str = "urn:solana-agent:ontology#";
+
+/// 节点 properties 的命名空间
+const PROPERTY: &str = "urn:solana-agent:property#";
+
+/// 节点、函数和源文件的 IRI 前缀
+const NODE_PREFIX: &str = "urn:solana-agent:node:";
+const FUNCTION_PREFIX: &str = "urn:solana-agent:function:";
+const FILE_PREFIX: &str = "urn:solana-agent:file:";
+
+/// 层的名字，与 merged.json 中一致
+fn layer_name(layer: Layer) -> &'static str {
+    match layer {
+        Layer::Ast => "ast",
+        Layer::Mir => "mir",
+    }
+}
+
+/// 节点种类对应的类名
+fn class_name(kind: NodeKind) -> &'static str {
+    match kind {
+        NodeKind::Entry => "Entry",
+        NodeKind::Exit => "Exit",
+        NodeKind::BasicBlock => "BasicBlock",
+        NodeKind::Statement => "Statement",
+        NodeKind::Terminator => "Terminator",
+        NodeKind::Syntax => "SyntaxNode",
+    }
+}
+
+/// 边种类对应的属性名
+fn predicate_name(kind: EdgeKind) -> &'static str {
+    match kind {
+        EdgeKind::ControlFlow => "controlFlow",
+        EdgeKind::DataFlow => "dataFlow",
+        EdgeKind::Call => "call",
+        EdgeKind::SameSource => "sameSource",
+        EdgeKind::Child => "child",
+    }
+}
+
+/// 本体：类和属性的声明
+const CLASSES: &[(&str, &str)] = &[
+    ("Node", "图中的节点"),
+    ("Entry", "函数入口"),
+    ("Exit", "函数出口"),
+    ("BasicBlock", "由若干条语句组成的基本块 (AST层)"),
+    ("Statement", "一条MIR语句"),
+    ("Terminator", "MIR基本块的终结符"),
+    ("SyntaxNode", "语法树节点 (AST生成器的图导出)"),
+    ("Function", "函数"),
+    ("File", "源文件"),
+];
+
+const OBJECT_PROPERTIES: &[(&str, &str)] = &[
+    ("controlFlow", "控制流边"),
+    ("dataFlow", "数据流边"),
+    ("call", "调用点到被调用函数入口"),
+    ("sameSource", "MIR节点到覆盖同一段源码的AST层节点"),
+    ("child", "语法树中父节点到子节点"),
+    ("inFunction", "节点所属的函数"),
+    ("inFile", "节点或函数所在的源文件"),
+];
+
+const DATA_PROPERTIES: &[(&str, &str)] = &[
+    ("id", "节点ID"),
+    ("layer", "ast 或 mir"),
+    ("label", "节点的文本"),
+    ("name", "函数名或文件路径"),
+    ("startByte", "源码范围的起始字节"),
+    ("endByte", "源码范围的结束字节"),
+    ("provenance", "源码范围 file@start..end"),
+];
+
+/// 对 IRI 中不安全的字符做百分号编码
+fn encode_iri(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => write!(encoded, "%{:02X}", byte).unwrap(),
+        }
+    }
+    encoded
+}
+
+/// Turtle 字符串字面量
+fn literal(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// properties 中的值：数字和布尔值使用 xsd 类型，其余写成字符串 (对象和数组为 JSON 文本)
+fn value_literal(value: &Value) -> String {
+    match value {
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) if n.is_i64() || n.is_u64() => n.to_string(),
+        Value::Number(n) => format!("{}^^xsd:double", literal(&n.to_string())),
+        Value::String(s) => literal(s),
+        _ => literal(&value.to_string()),
+    }
+}
+
+/// properties 的键能否直接写成 prop:<key>
+fn is_local_name(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 把合并图写成 Turtle 文本
+pub fn to_turtle(graph: &MergedGraph) -> String {
+    let mut out = String::new();
+    for (prefix, iri) in [
+        ("sa", ONTOLOGY),
+        ("prop", PROPERTY),
+        ("rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#"),
+        ("rdfs", "http://www.w3.org/2000/01/rdf-schema#"),
+        ("owl", "http://www.w3.org/2002/07/owl#"),
+        ("xsd", "http://www.w3.org/2001/XMLSchema#"),
+    ] {
+        writeln!(out, "@prefix {}: <{}> .", prefix, iri).unwrap();
+    }
+
+    out.push_str("\n# 本体\n");
+    for (name, comment) in CLASSES {
+        writeln!(
+            out,
+            "sa:{} a owl:Class ; rdfs:comment {} .",
+            name,
+            literal(comment)
+        )
+        .unwrap();
+    }
+    for kind in [
+        NodeKind::Entry,
+        NodeKind::Exit,
+        NodeKind::BasicBlock,
+        NodeKind::Statement,
+        NodeKind::Terminator,
+    ] {
+        writeln!(out, "sa:{} rdfs:subClassOf sa:Node .", class_name(kind)).unwrap();
+    }
+    for (name, comment) in OBJECT_PROPERTIES {
+        writeln!(
+            out,
+            "sa:{} a owl:ObjectProperty ; rdfs:comment {} .",
+            name,
+            literal(comment)
+        )
+        .unwrap();
+    }
+    for (name, comment) in DATA_PROPERTIES {
+        writeln!(
+            out,
+            "sa:{} a owl:DatatypeProperty ; rdfs:comment {} .",
+            name,
+            literal(comment)
+        )
+        .unwrap();
+    }
+
+    let node_iri = |id: &str| format!("<{}{}>", NODE_PREFIX, encode_iri(id));
+    let file_iri = |file: &str| format!("<{}{}>", FILE_PREFIX, encode_iri(file));
+    // 同名函数在AST层和MIR层的名字不同 (后者带模块路径)，按层区分
+    let function_iri = |layer: Layer, function: &str| {
+        format!(
+            "<{}{}:{}>",
+            FUNCTION_PREFIX,
+            layer_name(layer),
+            encode_iri(function)
+        )
+    };
+
+    out.push_str("\n# 函数和源文件\n");
+    // 函数 IRI -> (函数名, 所在的源文件)
+    let mut functions: BTreeMap<String, (&str, BTreeSet<String>)> = BTreeMap::new();
+    for node in &graph.nodes {
+        let (_, files) = functions
+            .entry(function_iri(node.layer, &node.function))
+            .or_insert((&node.function, BTreeSet::new()));
+        files.extend(
+            node.span
+                .as_ref()
+                .map(|s| s.file.to_string_lossy().replace('\\', "/")),
+        );
+    }
+    let files: BTreeSet<&String> = functions.values().flat_map(|(_, files)| files).collect();
+    for file in files {
+        writeln!(
+            out,
+            "{} a sa:File ; sa:name {} .",
+            file_iri(file),
+            literal(file)
+        )
+        .unwrap();
+    }
+    for (iri, (function, files)) in &functions {
+        writeln!(
+            out,
+            "{} a sa:Function ; sa:name {} .",
+            iri,
+            literal(function)
+        )
+        .unwrap();
+        for file in files {
+            writeln!(out, "{} sa:inFile {} .", iri, file_iri(file)).unwrap();
+        }
+    }
+
+    out.push_str("\n# 节点\n");
+    for node in &graph.nodes {
+        writeln!(
+            out,
+            "{} a sa:{} ;",
+            node_iri(&node.id),
+            class_name(node.kind)
+        )
+        .unwrap();
+        writeln!(out, "    sa:id {} ;", literal(&node.id)).unwrap();
+        writeln!(out, "    sa:layer {} ;", literal(layer_name(node.layer))).unwrap();
+        writeln!(out, "    sa:label {} ;", literal(&node.label)).unwrap();
+        if let Some(span) = &node.span {
+            let file = span.file.to_string_lossy().replace('\\', "/");
+            writeln!(out, "    sa:inFile {} ;", file_iri(&file)).unwrap();
+            writeln!(out, "    sa:startByte {} ;", span.start_byte).unwrap();
+            writeln!(out, "    sa:endByte {} ;", span.end_byte).unwrap();
+        }
+        if let Some(provenance) = &node.provenance {
+            writeln!(out, "    sa:provenance {} ;", literal(provenance)).unwrap();
+        }
+        for (key, value) in &node.properties {
+            let predicate = if is_local_name(key) {
+                format!("prop:{}", key)
+            } else {
+                format!("<{}{}>", PROPERTY, encode_iri(key))
+            };
+            // 数组的每个元素各成一个三元组
+            match value {
+                Value::Array(items) => {
+                    for item in items {
+                        writeln!(out, "    {} {} ;", predicate, value_literal(item)).unwrap();
+                    }
+                }
+                _ => writeln!(out, "    {} {} ;", predicate, value_literal(value)).unwrap(),
+            }
+        }
+        writeln!(
+            out,
+            "    sa:inFunction {} .",
+            function_iri(node.layer, &node.function)
+        )
+        .unwrap();
+    }
+
+    out.push_str("\n# 边\n");
+    for edge in &graph.edges {
+        writeln!(
+            out,
+            "{} sa:{} {} .",
+            node_iri(&edge.source),
+            predicate_name(edge.kind),
+            node_iri(&edge.target)
+        )
+        .unwrap();
+    }
+    out
+}
@@ -0,0 +1,573 @@
+// repl.rs
+//
+// agent repl：读入产物目录的合并图后进入交互式命令行，逐步浏览和查询图，不必每次重新运行 agent query
+// 命令见 COMMANDS；以 `[` 开头的行按 agent query 的查询语言执行。命令名、节点ID、函数和边种类可以用 Tab 补全，
+// 历史记录保存在产物目录下的 repl_history.txt 中
+// 函数用 `<层>:<单元>:<函数>` (节点ID中 `#` 之前的部分) 或在项目中唯一的函数名表示
+
+use crate::config::ArtifactsArgs;
+use crate::graph::EdgeKind;
+use crate::merge::{graph_key, load_merged, MergedGraph, MergedNode};
+use crate::query::{enum_name, execute, parse_edge_kind, print_result, Locator};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// 历史记录的文件名，默认位于产物目录下
+const REPL_HISTORY_FILE_NAME: &str = "repl_history.txt";
+
+/// 命令、用法和说明
+const COMMANDS: &[(&str, &str, &str)] = &[
+    ("functions", "functions [子串]", "列出函数及其节点数"),
+    (
+        "show",
+        "show <节点|函数>",
+        "节点的详细信息，或函数中的所有节点和边",
+    ),
+    (
+        "neighbors",
+        "neighbors <节点> [in|out|both]",
+        "节点的前驱和后继及连接它们的边",
+    ),
+    (
+        "slice",
+        "slice <节点> [backward|forward]",
+        "沿数据流边的后向 (默认) 或前向切片",
+    ),
+    (
+        "query",
+        "query <表达式>",
+        "执行 agent query 的查询，也可以直接输入以 [ 开头的表达式",
+    ),
+    (
+        "render",
+        "render <节点|函数> [跳数] [文件]",
+        "把函数或节点的 k 跳邻域 (默认 1 跳) 写成 DOT，未给出文件时打印",
+    ),
+    (
+        "set",
+        "set [limit <N> | edges <种类,...>]",
+        "设置结果条数上限和可达性查询沿的边，不带参数时显示当前设置",
+    ),
+    ("help", "help", "显示本帮助"),
+    ("quit", "quit", "退出 (也可以用 exit 或 Ctrl-D)"),
+];
+
+/// 可以补全和设置的边种类
+const EDGE_KINDS: &[&str] = &["control_flow", "data_flow", "call", "same_source"];
+
+/// `agent repl` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct ReplArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 历史记录文件，默认为产物目录下的 repl_history.txt
+    #[arg(long, value_name = "FILE")]
+    history: Option<PathBuf>,
+}
+
+/// Tab 补全：第一个词补全命令名，其后按命令补全节点ID、函数、边种类和选项
+struct ReplHelper {
+    /// 节点ID和函数，已排序
+    targets: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let prefix = &before[start..];
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+        let options: Vec<&str> = match words.as_slice() {
+            [] => COMMANDS.iter().map(|(name, _, _)| *name).collect(),
+            ["show" | "neighbors" | "slice" | "render"] => {
+                let first = self.targets.partition_point(|t| t.as_str() < prefix);
+                let candidates = self.targets[first..]
+                    .iter()
+                    .take_while(|t| t.starts_with(prefix))
+                    .cloned()
+                    .collect();
+                return Ok((start, candidates));
+            }
+            ["neighbors", _] => vec!["in", "out", "both"],
+            ["slice", _] => vec!["backward", "forward"],
+            ["set"] => vec!["limit", "edges"],
+            ["set", "edges"] => EDGE_KINDS.to_vec(),
+            _ => vec![],
+        };
+        Ok((
+            start,
+            options
+                .into_iter()
+                .filter(|option| option.starts_with(prefix))
+                .map(str::to_string)
+                .collect(),
+        ))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// 命令执行后是否继续
+enum Flow {
+    Continue,
+    Quit,
+}
+
+/// 命令的参数：一个节点或一个函数 (其所有节点)
+enum Target {
+    Node(usize),
+    Function(String),
+}
+
+/// 一次会话的状态：合并图、按节点建立的邻接表和当前的设置
+struct Session<'a> {
+    graph: &'a MergedGraph,
+    index: HashMap<&'a str, usize>,
+    /// 每个节点的出边和入边：(另一端的下标, 边的种类)
+    outgoing: Vec<Vec<(usize, EdgeKind)>>,
+    incoming: Vec<Vec<(usize, EdgeKind)>>,
+    /// 函数 -> 其节点的下标
+    functions: BTreeMap<&'a str, Vec<usize>>,
+    locator: Locator<'a>,
+    edges: Vec<EdgeKind>,
+    limit: usize,
+}
+
+impl<'a> Session<'a> {
+    fn new(graph: &'a MergedGraph, locator: Locator<'a>) -> Self {
+        let index: HashMap<&str, usize> = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.id.as_str(), i))
+            .collect();
+        let mut outgoing = vec![vec![]; graph.nodes.len()];
+        let mut incoming = vec![vec![]; graph.nodes.len()];
+        for edge in &graph.edges {
+            if let (Some(&s), Some(&t)) = (
+                index.get(edge.source.as_str()),
+                index.get(edge.target.as_str()),
+            ) {
+                outgoing[s].push((t, edge.kind));
+                incoming[t].push((s, edge.kind));
+            }
+        }
+        let mut functions: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (i, node) in graph.nodes.iter().enumerate() {
+            functions.entry(graph_key(&node.id)).or_default().push(i);
+        }
+        Session {
+            graph,
+            index,
+            outgoing,
+            incoming,
+            functions,
+            locator,
+            edges: vec![EdgeKind::ControlFlow, EdgeKind::DataFlow, EdgeKind::Call],
+            limit: 100,
+        }
+    }
+
+    /// 节点的一行摘要：位置、ID和标签的第一行
+    fn summary(&mut self, i: usize) -> String {
+        let node = &self.graph.nodes[i];
+        format!(
+            "{}  {}  {}",
+            self.locator.locate(node),
+            node.id,
+            node.label.lines().next().unwrap_or("")
+        )
+    }
+
+    /// 把参数解析为节点或函数：节点ID、函数键，或唯一的函数名
+    fn target(&self, arg: &str) -> Result<Target, String> {
+        if let Some(&i) = self.index.get(arg) {
+            return Ok(Target::Node(i));
+        }
+        if self.functions.contains_key(arg) {
+            return Ok(Target::Function(arg.to_string()));
+        }
+        let matches: Vec<&str> = self
+            .functions
+            .keys()
+            .copied()
+            .filter(|key| key.rsplit(':').next() == Some(arg))
+            .collect();
+        match matches.as_slice() {
+            [key] => Ok(Target::Function(key.to_string())),
+            [] => Err(format!("没有节点或函数 '{}'", arg)),
+            _ => Err(format!(
+                "函数名 '{}' 不唯一，请使用完整的函数键: {}",
+                arg,
+                matches.join(", ")
+            )),
+        }
+    }
+
+    fn node(&self, arg: &str) -> Result<usize, String> {
+        match self.target(arg)? {
+            Target::Node(i) => Ok(i),
+            Target::Function(key) => Err(format!("'{}' 是函数，这里需要一个节点ID", key)),
+        }
+    }
+
+    /// 执行一行输入
+    fn execute(&mut self, line: &str) -> Result<Flow, String> {
+        if line.starts_with('[') {
+            self.query(line)?;
+            return Ok(Flow::Continue);
+        }
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        match (command, args.as_slice()) {
+            ("help", _) => {
+                for (_, usage, description) in COMMANDS {
+                    println!("  {:<40} {}", usage, description);
+                }
+            }
+            ("quit" | "exit", _) => return Ok(Flow::Quit),
+            ("functions", filter) => self.list_functions(filter.first().copied().unwrap_or("")),
+            ("show", [target]) => self.show(target)?,
+            ("neighbors", [node, rest @ ..]) => {
+                let direction = rest.first().copied().unwrap_or("both");
+                if !matches!(direction, "in" | "out" | "both") {
+                    return Err(format!("方向应为 in、out 或 both，而不是 '{}'", direction));
+                }
+                self.neighbors(node, direction)?;
+            }
+            ("slice", [node, rest @ ..]) => {
+                let forward = match rest.first().copied().unwrap_or("backward") {
+                    "backward" => false,
+                    "forward" => true,
+                    other => {
+                        return Err(format!("方向应为 backward 或 forward，而不是 '{}'", other))
+                    }
+                };
+                self.slice(node, forward)?;
+            }
+            ("query", _) if !rest.is_empty() => self.query(rest)?,
+            ("render", [target, rest @ ..]) => {
+                let (hops, file) = match rest {
+                    [] => (1, None),
+                    [hops] if hops.parse::<usize>().is_ok() => (hops.parse().unwrap(), None),
+                    [file] => (1, Some(*file)),
+                    [hops, file] => (
+                        hops.parse()
+                            .map_err(|_| format!("跳数应为整数，而不是 '{}'", hops))?,
+                        Some(*file),
+                    ),
+                    _ => return Err("用法: render <节点|函数> [跳数] [文件]".to_string()),
+                };
+                self.render(target, hops, file)?;
+            }
+            ("set", []) => {
+                let edges: Vec<String> = self
+                    .edges
+                    .iter()
+                    .map(|&e| enum_name(e).unwrap_or_default())
+                    .collect();
+                println!("limit = {}", self.limit);
+                println!("edges = {}", edges.join(","));
+            }
+            ("set", ["limit", n]) => {
+                self.limit = n
+                    .parse()
+                    .map_err(|_| format!("limit 应为整数，而不是 '{}'", n))?;
+            }
+            ("set", ["edges", kinds]) => {
+                self.edges = kinds
+                    .split(',')
+                    .map(parse_edge_kind)
+                    .collect::<Result<_, _>>()?;
+            }
+            _ => {
+                let usage = COMMANDS
+                    .iter()
+                    .find(|(name, _, _)| *name == command)
+                    .map(|(_, usage, _)| *usage);
+                return Err(match usage {
+                    Some(usage) => format!("用法: {}", usage),
+                    None => format!("未知的命令 '{}'，输入 help 查看可用的命令", command),
+                });
+            }
+        }
+        Ok(Flow::Continue)
+    }
+
+    fn list_functions(&mut self, filter: &str) {
+        let functions: Vec<(&str, usize)> = self
+            .functions
+            .iter()
+            .filter(|(key, _)| key.contains(filter))
+            .map(|(key, nodes)| (*key, nodes[0]))
+            .collect();
+        for &(key, first) in functions.iter().take(self.limit) {
+            let location = self.locator.locate(&self.graph.nodes[first]);
+            println!(
+                "{}  {} 个节点  {}",
+                key,
+                self.functions[key].len(),
+                location
+            );
+        }
+        if functions.len() > self.limit {
+            println!(
+                "... 共 {} 个函数，只显示前 {} 个",
+                functions.len(),
+                self.limit
+            );
+        }
+    }
+
+    fn show(&mut self, target: &str) -> Result<(), String> {
+        match self.target(target)? {
+            Target::Node(i) => {
+                let node: &MergedNode = &self.graph.nodes[i];
+                println!("id:         {}", node.id);
+                println!("layer:      {}", enum_name(node.layer).unwrap_or_default());
+                println!("kind:       {}", enum_name(node.kind).unwrap_or_default());
+                println!("function:   {}", node.function);
+                println!("location:   {}", self.locator.locate(node));
+                if let Some(provenance) = &node.provenance {
+                    println!("provenance: {}", provenance);
+                }
+                if !node.properties.is_empty() {
+                    let properties = serde_json::to_string(&node.properties).unwrap_or_default();
+                    println!("properties: {}", properties);
+                }
+                println!(
+                    "edges:      {} 条入边, {} 条出边",
+                    self.incoming[i].len(),
+                    self.outgoing[i].len()
+                );
+                println!("label:");
+                for line in node.label.lines() {
+                    println!("    {}", line);
+                }
+            }
+            Target::Function(key) => {
+                let nodes = self.functions[key.as_str()].clone();
+                println!("{} ({} 个节点)", key, nodes.len());
+                for &i in &nodes {
+                    println!("  {}", self.summary(i));
+                }
+                let members: HashSet<usize> = nodes.iter().copied().collect();
+                for &i in &nodes {
+                    for &(t, kind) in &self.outgoing[i] {
+                        if members.contains(&t) {
+                            println!(
+                                "  {} -> {}  ({})",
+                                self.graph.nodes[i].id,
+                                self.graph.nodes[t].id,
+                                enum_name(kind).unwrap_or_default()
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn neighbors(&mut self, node: &str, direction: &str) -> Result<(), String> {
+        let i = self.node(node)?;
+        let mut lines = vec![];
+        if direction != "out" {
+            lines.extend(self.incoming[i].iter().map(|&(j, kind)| ("<-", j, kind)));
+        }
+        if direction != "in" {
+            lines.extend(self.outgoing[i].iter().map(|&(j, kind)| ("->", j, kind)));
+        }
+        for (arrow, j, kind) in lines.into_iter().take(self.limit) {
+            let kind = enum_name(kind).unwrap_or_default();
+            println!("{} {:<12} {}", arrow, kind, self.summary(j));
+        }
+        Ok(())
+    }
+
+    /// 沿数据流边的切片，按距离由近及远列出
+    fn slice(&mut self, node: &str, forward: bool) -> Result<(), String> {
+        let start = self.node(node)?;
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([(start, 0)]);
+        let mut slice = vec![];
+        while let Some((current, depth)) = queue.pop_front() {
+            slice.push((current, depth));
+            let next = if forward {
+                &self.outgoing[current]
+            } else {
+                &self.incoming[current]
+            };
+            for &(j, kind) in next {
+                if kind == EdgeKind::DataFlow && visited.insert(j) {
+                    queue.push_back((j, depth + 1));
+                }
+            }
+        }
+        let total = slice.len();
+        for (i, depth) in slice.into_iter().take(self.limit) {
+            println!("{:>3}  {}", depth, self.summary(i));
+        }
+        if total > self.limit {
+            println!("... 切片共 {} 个节点，只显示前 {} 个", total, self.limit);
+        }
+        Ok(())
+    }
+
+    fn query(&mut self, expr: &str) -> Result<(), String> {
+        let result = execute(self.graph, expr, &self.edges, self.limit)?;
+        print_result(self.graph, &result, &mut self.locator);
+        println!("({} 条结果)", result.len());
+        Ok(())
+    }
+
+    /// 函数的所有节点，或节点的 k 跳邻域 (沿所有边、不区分方向)
+    fn render(&mut self, target: &str, hops: usize, file: Option<&str>) -> Result<(), String> {
+        let (name, nodes) = match self.target(target)? {
+            Target::Function(key) => (key.clone(), self.functions[key.as_str()].clone()),
+            Target::Node(start) => {
+                let mut visited = HashSet::from([start]);
+                let mut frontier = vec![start];
+                for _ in 0..hops {
+                    let mut next = vec![];
+                    for &i in &frontier {
+                        for &(j, _) in self.outgoing[i].iter().chain(&self.incoming[i]) {
+                            if visited.insert(j) {
+                                next.push(j);
+                            }
+                        }
+                    }
+                    frontier = next;
+                }
+                let mut nodes: Vec<usize> = visited.into_iter().collect();
+                nodes.sort_unstable();
+                (self.graph.nodes[start].id.clone(), nodes)
+            }
+        };
+        let dot = to_dot(self.graph, &name, &nodes, &self.outgoing);
+        match file {
+            Some(file) => {
+                fs::write(file, dot).map_err(|e| format!("无法写入 '{}': {}", file, e))?;
+                println!("已写出 {} ({} 个节点)", file, nodes.len());
+            }
+            None => print!("{}", dot),
+        }
+        Ok(())
+    }
+}
+
+/// 子图的 DOT：控制流为实线，数据流为蓝色虚线，调用为红色，同源边为灰色点线
+fn to_dot(
+    graph: &MergedGraph,
+    name: &str,
+    nodes: &[usize],
+    outgoing: &[Vec<(usize, EdgeKind)>],
+) -> String {
+    let members: HashSet<usize> = nodes.iter().copied().collect();
+    let mut dot = format!(
+        "digraph {:?} {{\n  node [shape=box, fontname=\"monospace\"];\n",
+        name
+    );
+    for &i in nodes {
+        let node = &graph.nodes[i];
+        let label = format!(
+            "{}\n{}",
+            node.id,
+            node.label.lines().take(5).collect::<Vec<_>>().join("\n")
+        );
+        let _ = writeln!(dot, "  {:?} [label={:?}];", node.id, label);
+    }
+    for &i in nodes {
+        for &(j, kind) in outgoing[i].iter().filter(|(j, _)| members.contains(j)) {
+            let style = match kind {
+                EdgeKind::ControlFlow => "",
+                EdgeKind::DataFlow => " [style=dashed, color=blue]",
+                EdgeKind::Call => " [color=red]",
+                EdgeKind::SameSource => " [style=dotted, color=gray]",
+                EdgeKind::Child => " [color=gray]",
+            };
+            let _ = writeln!(
+                dot,
+                "  {:?} -> {:?}{};",
+                graph.nodes[i].id, graph.nodes[j].id, style
+            );
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// 读入合并图，进入交互式命令行
+pub fn run(args: &ReplArgs) -> Result<(), Box<dyn Error>> {
+    let (artifacts_dir, graph) = load_merged(&args.artifacts)?;
+    let mut session = Session::new(&graph, Locator::new(&args.artifacts.project));
+
+    let mut targets: Vec<String> = graph.nodes.iter().map(|n| n.id.clone()).collect();
+    targets.extend(session.functions.keys().map(|key| key.to_string()));
+    targets.sort();
+    targets.dedup();
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper { targets }));
+    let history = args
+        .history
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(REPL_HISTORY_FILE_NAME));
+    let _ = editor.load_history(&history);
+    info!(
+        nodes = graph.nodes.len(),
+        functions = session.functions.len(),
+        "已读入合并图，输入 help 查看可用的命令"
+    );
+
+    loop {
+        let line = match editor.readline("agent> ") {
+            Ok(line) => line,
+            // Ctrl-C 只放弃当前输入
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        match session.execute(line) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Quit) => break,
+            Err(e) => eprintln!("错误: {}", e),
+        }
+    }
+    if let Err(e) = editor.save_history(&history) {
+        warn!(history = %history.display(), error = %e, "无法保存历史记录");
+    }
+    Ok(())
+}
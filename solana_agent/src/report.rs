@@ -0,0 +1,1784 @@
+// report.rs
+//
+// agent report：把各阶段写出的问题汇总为一份报告 (SARIF、JSON 或 HTML)
+// - 代码模式：agent patterns、agent client-lint
+// - 指令与账户检查：agent constraints、mutability、signers、space、pda、events、test-coverage
+// - 图上的规则：agent detect
+// 只读取产物目录中已有的结果文件，不重新运行任何阶段；没有的文件跳过
+// 同一位置的同类问题只报告一次：不同阶段在同一文件、同一行报告的类别相同的问题合并，各阶段的结果都记在 sources 中
+// 每个问题都带有它在结果文件中的位置 (JSON Pointer)，以及合并图中覆盖该行的最内层的AST/MIR节点
+// 给出基线 (之前一次运行的报告) 时，按指纹把问题分为新增、已有和已修复；指纹不含行号，
+// 由规则、文件、对象、说明和该行去掉首尾空白后的源码计算，因此上方插入或删除代码不会让已有问题变成新问题
+// 没有基线、而产物来自 agent analyze --since/--diff 时，按 manifest.json 中记录的范围区分：
+// 位于范围内改动过的函数 (或不在函数中的改动行、没有行号时改动过的文件) 中的问题为新增，其余为已有
+// 源码中用 agent-ignore 标注 (见 suppressions.rs) 抑制的问题不计入 findings，连同标注记在 suppressed 中，
+// SARIF 中作为带有 suppressions 的结果输出
+// 每个问题带有漏洞类别和 CWE 编号 (见 taxonomy.rs)：规则文件中给出的优先，内置分析的问题取自 taxonomy.rs 中的登记；
+// SARIF 中写为规则的 tags 和到 CWE 分类法的 relationships，--class、--cwe 只保留相应的问题，--group-by-class 按类别分组
+// agent autofix 生成的修复 (autofix.json) 按规则、文件和行号附到问题上，写为 SARIF 的 fixes，HTML 中可以展开 diff
+// 配置文件的 [detectors] 表 (见 config.rs) 中禁用的检测器和规则不报告，给出 severity 时覆盖问题的严重程度
+
+use crate::autofix::{self, Fix};
+use crate::config::{ArtifactsArgs, DetectorsConfig};
+use crate::graph::Layer;
+use crate::manifest::{now_rfc3339, PreviousRunManifest};
+use crate::merge::{load_merged, MergedGraph};
+use crate::scope::{git_lines, Scope};
+use crate::suppressions::{self, Suppression};
+use crate::symbols::{line_of, load_asts, AstNode, DEFINITION_KINDS};
+use crate::taxonomy::{self, VulnClass, CWE_TAXONOMY, CWE_VERSION};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// SARIF 的版本及其 JSON Schema
+const SARIF_VERSION: &str = "2.1.0";
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// SARIF partialFingerprints 中指纹的名字
+const FINGERPRINT_NAME: &str = "solanaAgent/v1";
+
+/// 指纹取 blake3 哈希的前多少个十六进制字符
+const FINGERPRINT_LEN: usize = 32;
+
+/// 各阶段的结果文件及从中取出问题的函数
+const SOURCES: &[(&str, Stage, Extractor)] = &[
+    ("patterns.json", Stage::Pattern, patterns_findings),
+    ("client_lint.json", Stage::Pattern, client_lint_findings),
+    ("constraints.json", Stage::Check, constraints_findings),
+    ("mutability.json", Stage::Check, mutability_findings),
+    ("signers.json", Stage::Check, signers_findings),
+    ("privileges.json", Stage::Check, privileges_findings),
+    ("sysvars.json", Stage::Check, sysvars_findings),
+    ("space.json", Stage::Check, space_findings),
+    ("pda.json", Stage::Check, pda_findings),
+    ("events.json", Stage::Check, events_findings),
+    ("test_coverage.json", Stage::Check, test_coverage_findings),
+    ("dead_code.json", Stage::Check, dead_code_findings),
+    ("lifecycle.json", Stage::Check, lifecycle_findings),
+    ("known_vulns.json", Stage::Check, known_vulns_findings),
+    ("autofix.json", Stage::Check, autofix_findings),
+    ("advisories.json", Stage::Check, advisories_findings),
+    ("detect.json", Stage::Detector, detect_findings),
+];
+
+/// 从结果文件中取出问题
+type Extractor = fn(&Value) -> Vec<RawFinding>;
+
+/// 去重的键：文件、行号和类别 (没有类别时为问题种类)；没有行号时无法确定是否是同一处代码，另加上规则和对象
+type FindingKey = (
+    Option<PathBuf>,
+    Option<usize>,
+    String,
+    Option<(String, Option<String>)>,
+);
+
+/// `agent report` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct ReportArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 报告的格式
+    #[arg(long, value_enum, default_value = "sarif")]
+    format: ReportFormat,
+
+    /// 输出文件，默认为产物目录下的 report.sarif、report.json 或 report.html
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// 基线：之前一次运行写出的 report.json 或 report.sarif，或含有它们的产物目录
+    #[arg(long, value_name = "PATH", conflicts_with = "baseline_rev")]
+    baseline: Option<PathBuf>,
+
+    /// 基线为某个提交中的报告：产物目录下已提交的 report.json 或 report.sarif
+    #[arg(long, value_name = "REV")]
+    baseline_rev: Option<String>,
+
+    /// 有新问题时以错误退出 (没有基线和 --since/--diff 的分析范围时所有问题都是新问题)，用于 CI
+    #[arg(long)]
+    fail_on_new: bool,
+
+    /// 只报告这些类别的问题，可以重复
+    #[arg(long, value_enum, value_name = "CLASS")]
+    class: Vec<VulnClass>,
+
+    /// 只报告带有这些 CWE 编号的问题，可以重复，例如 --cwe 862
+    #[arg(long, value_name = "ID")]
+    cwe: Vec<u32>,
+
+    /// 按类别分组排列问题，HTML 中每个类别一节
+    #[arg(long)]
+    group_by_class: bool,
+}
+
+impl ReportArgs {
+    /// 问题是否符合 --class 和 --cwe；二者都给出时都要符合
+    fn selects(&self, finding: &Finding) -> bool {
+        (self.class.is_empty() || finding.class.is_some_and(|c| self.class.contains(&c)))
+            && (self.cwe.is_empty() || finding.cwe.iter().any(|c| self.cwe.contains(c)))
+    }
+}
+
+/// --format 的取值
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ReportFormat {
+    Sarif,
+    Json,
+    Html,
+}
+
+impl ReportFormat {
+    fn file_name(self) -> &'static str {
+        match self {
+            ReportFormat::Sarif => "report.sarif",
+            ReportFormat::Json => "report.json",
+            ReportFormat::Html => "report.html",
+        }
+    }
+}
+
+/// 问题来自哪一类分析
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Stage {
+    /// AST上的代码模式
+    Pattern,
+    /// 基于 Accounts 结构体和指令处理函数的检查
+    Check,
+    /// 合并图上的 source/sink/guard 规则
+    Detector,
+}
+
+/// 问题的严重程度，取值与 SARIF 的 level 一致
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Note => "note",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// 与基线比较的结果
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    /// 基线中没有
+    New,
+    /// 基线中已有
+    Existing,
+    /// 基线中有、本次没有
+    Fixed,
+}
+
+impl Status {
+    /// SARIF 的 baselineState
+    fn baseline_state(self) -> &'static str {
+        match self {
+            Status::New => "new",
+            Status::Existing => "unchanged",
+            Status::Fixed => "absent",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Status::New => "新增",
+            Status::Existing => "已有",
+            Status::Fixed => "已修复",
+        }
+    }
+}
+
+/// 从某个结果文件中取出的问题
+struct RawFinding {
+    /// 结果文件中的规则或问题种类
+    kind: String,
+    level: Level,
+    message: String,
+    file: Option<PathBuf>,
+    line: Option<usize>,
+    /// 问题针对的对象，例如指令、账户字段或事件
+    subject: Option<String>,
+    /// 问题在结果文件中的位置
+    pointer: String,
+    /// 结果文件中已经给出的图节点
+    nodes: Vec<String>,
+}
+
+/// 问题的一个来源
+#[derive(Serialize, Deserialize, Debug)]
+struct Source {
+    /// 该来源中的规则；合并的问题的各个来源的规则可能不同
+    #[serde(default)]
+    rule: String,
+    artifact: String,
+    pointer: String,
+}
+
+/// 报告中的一个问题
+#[derive(Serialize, Deserialize, Debug)]
+struct Finding {
+    /// `<阶段的结果文件名>/<问题种类>`，例如 `signers/signer_to_unchecked_program`；合并的问题取第一个来源的规则
+    rule: String,
+    stage: Stage,
+    level: Level,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<String>,
+    sources: Vec<Source>,
+    /// 漏洞类别和 CWE 编号
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    class: Option<VulnClass>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cwe: Vec<u32>,
+    /// 合并图中的相关节点
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    graph_nodes: Vec<String>,
+    #[serde(default)]
+    fingerprint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<Status>,
+    /// 抑制该问题的源码标注
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    suppression: Option<Suppression>,
+    /// agent autofix 建议的修复
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fix: Option<Fix>,
+}
+
+/// report.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct Report {
+    metadata: ReportMetadata,
+    artifacts: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    baseline: Option<String>,
+    /// 没有基线时用来区分新旧问题的 --since/--diff 范围
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    /// 各类别的问题数，不含已修复和被抑制的问题
+    classes: BTreeMap<VulnClass, usize>,
+    findings: Vec<Finding>,
+    /// 基线中有、本次没有的问题
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fixed: Vec<Finding>,
+    /// 被源码标注抑制的问题
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    suppressed: Vec<Finding>,
+}
+
+/// 读取基线时只需要其中的问题
+#[derive(Deserialize)]
+struct BaselineReport {
+    findings: Vec<Finding>,
+}
+
+/// 读入的基线
+struct Baseline {
+    /// 基线的文件名或 `<rev>:<path>`
+    name: String,
+    findings: Vec<Finding>,
+}
+
+#[derive(Serialize, Debug)]
+struct ReportMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 字段的文本值
+fn text(value: &Value, key: &str) -> Option<String> {
+    value.get(key)?.as_str().map(str::to_string)
+}
+
+/// 字段的行号
+fn line(value: &Value) -> Option<usize> {
+    value.get("line")?.as_u64().map(|l| l as usize)
+}
+
+/// 数组字段中的各项及其下标
+fn items<'a>(value: &'a Value, key: &str) -> impl Iterator<Item = (usize, &'a Value)> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+}
+
+/// 字符串数组用 `, ` 连接
+fn joined(value: &Value, key: &str) -> Option<String> {
+    let names: Vec<&str> = value
+        .get(key)?
+        .as_array()?
+        .iter()
+        .filter_map(Value::as_str)
+        .collect();
+    (!names.is_empty()).then(|| names.join(", "))
+}
+
+/// 问题的类别和 CWE 编号：结果文件中的该项给出的优先，否则取内置的登记
+fn classification(item: Option<&Value>, rule: &str) -> (Option<VulnClass>, Vec<u32>) {
+    let class = item
+        .and_then(|i| i.get("class"))
+        .and_then(|c| serde_json::from_value(c.clone()).ok());
+    let cwe: Vec<u32> = item
+        .and_then(|i| i.get("cwe"))
+        .and_then(|c| serde_json::from_value(c.clone()).ok())
+        .unwrap_or_default();
+    match taxonomy::classify(rule) {
+        Some((builtin, builtin_cwe)) if class.is_none() && cwe.is_empty() => {
+            (Some(builtin), builtin_cwe.to_vec())
+        }
+        _ => (class, cwe),
+    }
+}
+
+/// 包含某一行的最内层函数的行范围
+fn enclosing_function(root: &AstNode, source: &str, line: usize) -> Option<(usize, usize)> {
+    let lines = |node: &AstNode| {
+        (
+            line_of(source, node.start_byte),
+            line_of(source, node.end_byte),
+        )
+    };
+    let mut function = None;
+    let mut node = root;
+    loop {
+        if DEFINITION_KINDS
+            .iter()
+            .any(|&(kind, symbol)| kind == node.kind && matches!(symbol, "function" | "method"))
+        {
+            function = Some(lines(node));
+        }
+        match node.children.iter().find(|child| {
+            let (start, end) = lines(child);
+            start <= line && line <= end
+        }) {
+            Some(child) => node = child,
+            None => return function,
+        }
+    }
+}
+
+/// 按分析范围标出新旧问题：问题所在的函数 (不在函数中时为该行) 在范围内有改动时为新增
+fn classify_by_scope(
+    findings: &mut [Finding],
+    scope: &Scope,
+    artifacts_dir: &Path,
+    project: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let asts: HashMap<PathBuf, (AstNode, String)> = load_asts(artifacts_dir)?
+        .into_iter()
+        .filter(|(file, _)| scope.changed.contains(file))
+        .map(|(file, root)| {
+            let source = fs::read_to_string(project.join(&file)).unwrap_or_default();
+            (file, (root, source))
+        })
+        .collect();
+    for finding in findings {
+        let changed = finding.file.as_deref().is_some_and(|file| {
+            let lines = finding.line.map(|line| {
+                asts.get(file)
+                    .and_then(|(root, source)| enclosing_function(root, source, line))
+                    .unwrap_or((line, line))
+            });
+            scope.changes(file, lines)
+        });
+        finding.status = Some(if changed {
+            Status::New
+        } else {
+            Status::Existing
+        });
+    }
+    Ok(())
+}
+
+/// 问题的去重键；问题种类统一为小写、以下划线分隔，规则文件中的 `missing-signer` 与内置的 `missing_signer` 相同
+fn finding_key(raw: &RawFinding, rule: &str, class: Option<VulnClass>) -> FindingKey {
+    let category = class.map_or_else(
+        || raw.kind.to_lowercase().replace(['-', ' '], "_"),
+        |class| class.name().to_string(),
+    );
+    let identity = raw
+        .line
+        .is_none()
+        .then(|| (rule.to_string(), raw.subject.clone()));
+    (raw.file.clone(), raw.line, category, identity)
+}
+
+/// 从各阶段的结果 (结果文件名和内容，按 SOURCES 的顺序) 中取出问题并去重
+/// 合并的问题保留第一个来源的规则和说明，严重程度取各来源中最高的
+fn collect_findings(results: &[(&str, Value)], detectors: &DetectorsConfig) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = vec![];
+    let mut seen: HashMap<FindingKey, usize> = HashMap::new();
+    for (file_name, value) in results {
+        let Some(&(_, stage, extract)) = SOURCES.iter().find(|(name, _, _)| name == file_name)
+        else {
+            continue;
+        };
+        let prefix = file_name.trim_end_matches(".json");
+        for raw in extract(value) {
+            let rule = format!("{}/{}", prefix, raw.kind);
+            if !detectors.enabled(&rule) {
+                continue;
+            }
+            let (class, cwe) = classification(value.pointer(&raw.pointer), &rule);
+            let level = detectors.severity(&rule).unwrap_or(raw.level);
+            let key = finding_key(&raw, &rule, class);
+            let source = Source {
+                rule: rule.clone(),
+                artifact: file_name.to_string(),
+                pointer: raw.pointer,
+            };
+            if let Some(&i) = seen.get(&key) {
+                let finding = &mut findings[i];
+                finding.level = finding.level.max(level);
+                finding.sources.push(source);
+                for node in raw.nodes {
+                    if !finding.graph_nodes.contains(&node) {
+                        finding.graph_nodes.push(node);
+                    }
+                }
+                continue;
+            }
+            seen.insert(key, findings.len());
+            findings.push(Finding {
+                rule,
+                stage,
+                level,
+                message: raw.message,
+                file: raw.file,
+                line: raw.line,
+                subject: raw.subject,
+                sources: vec![source],
+                class,
+                cwe,
+                graph_nodes: raw.nodes,
+                fingerprint: String::new(),
+                status: None,
+                suppression: None,
+                fix: None,
+            });
+        }
+    }
+    findings
+}
+
+/// 与基线比较：指纹相同的问题按出现次数一一对应，标出新增和已有的问题；
+/// 返回基线中有、本次没有的已修复的问题，本次被抑制的问题不算修复
+fn compare_with_baseline(
+    findings: &mut [Finding],
+    suppressed: &[Finding],
+    baseline: Vec<Finding>,
+) -> Vec<Finding> {
+    let mut remaining: HashMap<String, usize> = HashMap::new();
+    for finding in &baseline {
+        *remaining.entry(finding.fingerprint.clone()).or_default() += 1;
+    }
+    for finding in findings {
+        let status = match remaining.get_mut(&finding.fingerprint) {
+            Some(n) if *n > 0 => {
+                *n -= 1;
+                Status::Existing
+            }
+            _ => Status::New,
+        };
+        finding.status = Some(status);
+    }
+    for finding in suppressed {
+        remaining.remove(&finding.fingerprint);
+    }
+    // 已修复的问题的来源和图节点属于旧的产物，不再引用
+    let mut fixed = vec![];
+    for mut finding in baseline {
+        if let Some(n) = remaining.get_mut(&finding.fingerprint).filter(|n| **n > 0) {
+            *n -= 1;
+            finding.status = Some(Status::Fixed);
+            finding.sources.clear();
+            finding.graph_nodes.clear();
+            fixed.push(finding);
+        }
+    }
+    fixed
+}
+
+/// 类别在报告中的名字，没有类别时为 `unclassified`
+fn class_name(class: Option<VulnClass>) -> &'static str {
+    class.map_or("unclassified", VulnClass::name)
+}
+
+fn patterns_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "matches")
+        .map(|(i, m)| RawFinding {
+            kind: text(m, "rule").unwrap_or_default(),
+            level: Level::Warning,
+            message: text(m, "message").unwrap_or_default(),
+            file: text(m, "file").map(PathBuf::from),
+            line: line(m),
+            subject: None,
+            pointer: format!("/matches/{}", i),
+            nodes: vec![],
+        })
+        .collect()
+}
+
+fn client_lint_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "findings")
+        .map(|(i, f)| {
+            let kind = text(f, "kind").unwrap_or_default();
+            RawFinding {
+                level: if kind == "private_key_literal" {
+                    Level::Error
+                } else {
+                    Level::Warning
+                },
+                kind,
+                message: text(f, "message").unwrap_or_default(),
+                file: text(f, "file").map(PathBuf::from),
+                line: line(f),
+                subject: None,
+                pointer: format!("/findings/{}", i),
+                nodes: vec![],
+            }
+        })
+        .collect()
+}
+
+fn constraints_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "uncovered")
+        .map(|(i, u)| {
+            let usage = text(u, "usage").unwrap_or_default();
+            let field = format!(
+                "{}.{}",
+                text(u, "accounts_struct").unwrap_or_default(),
+                text(u, "field").unwrap_or_default()
+            );
+            RawFinding {
+                kind: "uncovered".to_string(),
+                // 与 agent constraints 一致：只有被写入或传给 CPI 的字段才需要警告
+                level: if usage == "written" || usage == "cpi" {
+                    Level::Warning
+                } else {
+                    Level::Note
+                },
+                message: format!("账户 {} 没有任何约束 (使用方式: {})", field, usage),
+                file: text(u, "file").map(PathBuf::from),
+                line: line(u),
+                subject: Some(field),
+                pointer: format!("/uncovered/{}", i),
+                nodes: vec![],
+            }
+        })
+        .collect()
+}
+
+fn mutability_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "findings")
+        .map(|(i, f)| {
+            let kind = text(f, "kind").unwrap_or_default();
+            let account = format!(
+                "{}.{}",
+                text(f, "accounts_struct").unwrap_or_default(),
+                text(f, "account").unwrap_or_default()
+            );
+            let (level, message) = if kind == "missing_mut" {
+                (
+                    Level::Error,
+                    format!("账户 {} 被写入，但没有标记 mut", account),
+                )
+            } else {
+                (
+                    Level::Note,
+                    format!("账户 {} 标记了 mut，但没有指令写入它", account),
+                )
+            };
+            RawFinding {
+                kind,
+                level,
+                message,
+                file: text(f, "file").map(PathBuf::from),
+                line: line(f),
+                subject: Some(account),
+                pointer: format!("/findings/{}", i),
+                nodes: vec![],
+            }
+        })
+        .collect()
+}
+
+fn signers_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "findings")
+        .map(|(i, f)| {
+            // 位置取自问题引用的 CPI
+            let cpi = f
+                .get("cpi")
+                .and_then(Value::as_u64)
+                .and_then(|c| report.get("cpis")?.get(c as usize));
+            RawFinding {
+                kind: text(f, "kind").unwrap_or_default(),
+                level: Level::Warning,
+                message: text(f, "message").unwrap_or_default(),
+                file: cpi.and_then(|c| text(c, "file")).map(PathBuf::from),
+                line: cpi.and_then(line),
+                subject: text(f, "instruction"),
+                pointer: format!("/findings/{}", i),
+                nodes: vec![],
+            }
+        })
+        .collect()
+}
+
+fn privileges_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "findings")
+        .map(|(i, f)| RawFinding {
+            kind: text(f, "kind").unwrap_or_default(),
+            level: Level::Warning,
+            message: text(f, "message").unwrap_or_default(),
+            file: text(f, "file").map(PathBuf::from),
+            line: line(f),
+            subject: text(f, "instruction"),
+            pointer: format!("/findings/{}", i),
+            nodes: vec![],
+        })
+        .collect()
+}
+
+fn sysvars_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "findings")
+        .map(|(i, f)| {
+            // 位置取自问题引用的使用
+            let usage = f
+                .get("usage")
+                .and_then(Value::as_u64)
+                .and_then(|u| report.get("uses")?.get(u as usize));
+            let kind = text(f, "kind").unwrap_or_default();
+            RawFinding {
+                level: match kind.as_str() {
+                    "unchecked_loader" | "default_value" => Level::Warning,
+                    _ => Level::Note,
+                },
+                kind,
+                message: text(f, "message").unwrap_or_default(),
+                file: usage.and_then(|u| text(u, "file")).map(PathBuf::from),
+                line: usage.and_then(line),
+                subject: usage.and_then(|u| text(u, "sysvar")),
+                pointer: format!("/findings/{}", i),
+                nodes: vec![],
+            }
+        })
+        .collect()
+}
+
+fn space_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "allocations")
+        .filter_map(|(i, a)| {
+            let status = text(a, "status")?;
+            let message = match status.as_str() {
+                "too_small" => "space 小于账户所需的大小，初始化或写入时会失败",
+                "too_large" => "space 大于账户所需的大小，多付了租金",
+                _ => return None,
+            };
+            Some(RawFinding {
+                level: if status == "too_small" {
+                    Level::Error
+                } else {
+                    Level::Note
+                },
+                kind: status,
+                message: message.to_string(),
+                file: text(a, "file").map(PathBuf::from),
+                line: line(a),
+                subject: Some(format!(
+                    "{}.{}",
+                    text(a, "accounts_struct").unwrap_or_default(),
+                    text(a, "field").unwrap_or_default()
+                )),
+                pointer: format!("/allocations/{}", i),
+                nodes: vec![],
+            })
+        })
+        .collect()
+}
+
+fn pda_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "collisions")
+        .map(|(i, c)| RawFinding {
+            kind: "collision".to_string(),
+            level: Level::Warning,
+            message: format!(
+                "账户 {} 使用了相同的种子格式",
+                joined(c, "account_types").unwrap_or_default()
+            ),
+            file: None,
+            line: None,
+            subject: text(c, "signature"),
+            pointer: format!("/collisions/{}", i),
+            nodes: vec![],
+        })
+        .collect()
+}
+
+fn events_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "findings")
+        .map(|(i, f)| RawFinding {
+            kind: text(f, "kind").unwrap_or_default(),
+            level: Level::Note,
+            message: text(f, "message").unwrap_or_default(),
+            file: None,
+            line: None,
+            subject: text(f, "subject"),
+            pointer: format!("/findings/{}", i),
+            nodes: vec![],
+        })
+        .collect()
+}
+
+fn test_coverage_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "untested")
+        .filter_map(|(i, instruction)| {
+            Some(RawFinding {
+                kind: "untested".to_string(),
+                level: Level::Note,
+                message: "测试中没有调用这条指令".to_string(),
+                file: None,
+                line: None,
+                subject: Some(instruction.as_str()?.to_string()),
+                pointer: format!("/untested/{}", i),
+                nodes: vec![],
+            })
+        })
+        .collect()
+}
+
+fn dead_code_findings(report: &Value) -> Vec<RawFinding> {
+    let handlers = items(report, "handlers")
+        .filter(|(_, h)| text(h, "status").as_deref() == Some("unreachable"))
+        .map(|(i, h)| RawFinding {
+            kind: "unreachable_handler".to_string(),
+            level: Level::Warning,
+            message: "没有任何客户端调用或 CPI 能到达这条指令".to_string(),
+            file: text(h, "file").map(PathBuf::from),
+            line: line(h),
+            subject: text(h, "instruction"),
+            pointer: format!("/handlers/{}", i),
+            nodes: text(h, "node").into_iter().collect(),
+        });
+    let functions = items(report, "dead_functions").map(|(i, f)| {
+        let via: Vec<&str> = f
+            .get("via")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_str())
+            .collect();
+        RawFinding {
+            kind: "dead_function".to_string(),
+            level: Level::Note,
+            message: if via.is_empty() {
+                "从任何入口都到达不了这个函数".to_string()
+            } else {
+                format!("只能从到达不了的指令 {} 走到这个函数", via.join(", "))
+            },
+            file: text(f, "file").map(PathBuf::from),
+            line: line(f),
+            subject: text(f, "function"),
+            pointer: format!("/dead_functions/{}", i),
+            nodes: text(f, "node").into_iter().collect(),
+        }
+    });
+    handlers.chain(functions).collect()
+}
+
+fn lifecycle_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "anomalies")
+        .map(|(i, a)| RawFinding {
+            kind: text(a, "kind").unwrap_or_default(),
+            level: match text(a, "kind").as_deref() {
+                Some("never_initialized") => Level::Note,
+                _ => Level::Warning,
+            },
+            message: text(a, "message").unwrap_or_default(),
+            file: text(a, "file").map(PathBuf::from),
+            line: line(a),
+            subject: text(a, "account_type"),
+            pointer: format!("/anomalies/{}", i),
+            nodes: vec![],
+        })
+        .collect()
+}
+
+fn known_vulns_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "matches")
+        .map(|(i, m)| RawFinding {
+            kind: text(m, "pattern").unwrap_or_default(),
+            level: Level::Warning,
+            message: text(m, "message").unwrap_or_default(),
+            file: text(m, "file").map(PathBuf::from),
+            line: line(m),
+            subject: text(m, "instruction"),
+            pointer: format!("/matches/{}", i),
+            nodes: vec![],
+        })
+        .collect()
+}
+
+fn autofix_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "findings")
+        .map(|(i, f)| {
+            let kind = text(f, "kind").unwrap_or_default();
+            let code = text(f, "code").unwrap_or_default();
+            let message = if kind == "unchecked_invoke" {
+                format!("CPI 的返回值被丢弃，失败不会中止指令：{}", code)
+            } else {
+                format!("账户字段上的算术运算没有检查溢出：{}", code)
+            };
+            RawFinding {
+                kind,
+                level: Level::Warning,
+                message,
+                file: text(f, "file").map(PathBuf::from),
+                line: line(f),
+                subject: text(f, "function"),
+                pointer: format!("/findings/{}", i),
+                nodes: vec![],
+            }
+        })
+        .collect()
+}
+
+fn advisories_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "advisories")
+        .map(|(i, a)| {
+            let kind = text(a, "status").unwrap_or_default();
+            let advisory = format!(
+                "{} ({} {})",
+                text(a, "id").unwrap_or_default(),
+                text(a, "package").unwrap_or_default(),
+                text(a, "version").unwrap_or_default()
+            );
+            let title = text(a, "title").unwrap_or_default();
+            let (level, message) = match kind.as_str() {
+                "reachable" => (
+                    Level::Error,
+                    format!(
+                        "依赖 {} 有公开漏洞，受影响的函数可从程序入口到达：{}",
+                        advisory, title
+                    ),
+                ),
+                "unreachable" => (
+                    Level::Note,
+                    format!(
+                        "依赖 {} 有公开漏洞，受影响的函数有调用点但从程序入口到达不了：{}",
+                        advisory, title
+                    ),
+                ),
+                "not_called" => (
+                    Level::Note,
+                    format!(
+                        "依赖 {} 有公开漏洞，项目中没有调用受影响的函数：{}",
+                        advisory, title
+                    ),
+                ),
+                _ => (
+                    Level::Warning,
+                    format!(
+                        "依赖 {} 有公开漏洞，公告没有列出受影响的函数：{}",
+                        advisory, title
+                    ),
+                ),
+            };
+            RawFinding {
+                kind,
+                level,
+                message,
+                file: text(a, "file").map(PathBuf::from),
+                line: line(a),
+                subject: Some(advisory),
+                pointer: format!("/advisories/{}", i),
+                nodes: vec![],
+            }
+        })
+        .collect()
+}
+
+fn detect_findings(report: &Value) -> Vec<RawFinding> {
+    items(report, "detections")
+        .map(|(i, d)| RawFinding {
+            kind: text(d, "rule").unwrap_or_default(),
+            level: Level::Warning,
+            message: text(d, "message").unwrap_or_default(),
+            file: text(d, "file").map(PathBuf::from),
+            line: line(d),
+            subject: text(d, "function"),
+            pointer: format!("/detections/{}", i),
+            nodes: text(d, "node").into_iter().collect(),
+        })
+        .collect()
+}
+
+/// 节点的层、起始行、结束行、字节长度和ID
+type NodeLines<'a> = (Layer, usize, usize, usize, &'a str);
+
+/// 按源码行查找合并图中的节点：每层取覆盖该行的最短的节点
+struct NodeIndex<'a> {
+    nodes: HashMap<&'a Path, Vec<NodeLines<'a>>>,
+}
+
+impl<'a> NodeIndex<'a> {
+    fn new(graph: &'a MergedGraph, project: &Path) -> Self {
+        let mut sources: HashMap<&Path, String> = HashMap::new();
+        let mut nodes: HashMap<&Path, Vec<_>> = HashMap::new();
+        for node in &graph.nodes {
+            let Some(span) = &node.span else {
+                continue;
+            };
+            let source = sources.entry(&span.file).or_insert_with(|| {
+                fs::read_to_string(project.join(&span.file)).unwrap_or_default()
+            });
+            nodes.entry(span.file.as_path()).or_default().push((
+                node.layer,
+                line_of(source, span.start_byte),
+                line_of(source, span.end_byte),
+                span.end_byte.saturating_sub(span.start_byte),
+                node.id.as_str(),
+            ));
+        }
+        NodeIndex { nodes }
+    }
+
+    fn lookup(&self, file: &Path, line: usize) -> Vec<String> {
+        let mut innermost: BTreeMap<&str, (usize, &str)> = BTreeMap::new();
+        for &(layer, start, end, len, id) in self.nodes.get(file).into_iter().flatten() {
+            if !(start..=end).contains(&line) {
+                continue;
+            }
+            let layer = match layer {
+                Layer::Ast => "ast",
+                Layer::Mir => "mir",
+            };
+            let best = innermost.entry(layer).or_insert((len, id));
+            if len < best.0 {
+                *best = (len, id);
+            }
+        }
+        innermost.values().map(|(_, id)| id.to_string()).collect()
+    }
+}
+
+/// HTML 转义
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// SARIF 中的一条规则：类别和 CWE 编号写为 tags，CWE 编号另写为到 CWE 分类法的 relationships
+fn sarif_rule(id: &str, level: Level, finding: &Finding) -> Value {
+    let mut rule = json!({
+        "id": id,
+        "defaultConfiguration": { "level": level },
+    });
+    let mut tags = vec![];
+    if let Some(class) = finding.class {
+        if class.is_security() {
+            tags.push("security".to_string());
+        }
+        tags.push(format!("solana/{}", class.name()));
+    }
+    tags.extend(
+        finding
+            .cwe
+            .iter()
+            .map(|cwe| format!("external/cwe/cwe-{}", cwe)),
+    );
+    if !tags.is_empty() {
+        rule["properties"] = json!({ "tags": tags });
+    }
+    if !finding.cwe.is_empty() {
+        rule["relationships"] = json!(finding
+            .cwe
+            .iter()
+            .map(|cwe| json!({
+                "target": {
+                    "id": cwe.to_string(),
+                    "toolComponent": { "name": CWE_TAXONOMY },
+                },
+                "kinds": ["superset"],
+            }))
+            .collect::<Vec<_>>());
+    }
+    rule
+}
+
+/// SARIF 格式的报告
+fn sarif(report: &Report) -> Value {
+    let mut rules: BTreeMap<&str, (Level, &Finding)> = BTreeMap::new();
+    for finding in report
+        .findings
+        .iter()
+        .chain(&report.fixed)
+        .chain(&report.suppressed)
+    {
+        let (level, _) = rules
+            .entry(&finding.rule)
+            .or_insert((finding.level, finding));
+        *level = (*level).max(finding.level);
+    }
+    let cwes: BTreeSet<u32> = rules.values().flat_map(|(_, f)| f.cwe.clone()).collect();
+    let results: Vec<Value> = report
+        .findings
+        .iter()
+        .chain(&report.fixed)
+        .chain(&report.suppressed)
+        .map(|f| {
+            let mut result = json!({
+                "ruleId": f.rule,
+                "level": f.level,
+                "message": { "text": f.message },
+                "partialFingerprints": { FINGERPRINT_NAME: f.fingerprint },
+                "properties": {
+                    "stage": f.stage,
+                    "sources": f.sources,
+                    "graphNodes": f.graph_nodes,
+                },
+            });
+            if let Some(class) = f.class {
+                result["properties"]["class"] = json!(class);
+            }
+            if !f.cwe.is_empty() {
+                result["properties"]["cwe"] = json!(f.cwe);
+            }
+            if let Some(status) = f.status {
+                result["baselineState"] = json!(status.baseline_state());
+            }
+            if let Some(suppression) = &f.suppression {
+                let mut entry = json!({ "kind": "inSource", "status": "accepted" });
+                if let Some(reason) = &suppression.reason {
+                    entry["justification"] = json!(reason);
+                }
+                result["suppressions"] = json!([entry]);
+            }
+            if let Some(fix) = &f.fix {
+                let replacements: Vec<Value> = fix
+                    .edits
+                    .iter()
+                    .map(|e| {
+                        json!({
+                            "deletedRegion": {
+                                "byteOffset": e.start_byte,
+                                "byteLength": e.end_byte - e.start_byte,
+                            },
+                            "insertedContent": { "text": e.text },
+                        })
+                    })
+                    .collect();
+                result["fixes"] = json!([{
+                    "description": { "text": fix.message },
+                    "artifactChanges": [{
+                        "artifactLocation": {
+                            "uri": fix.file.to_string_lossy().replace('\\', "/"),
+                            "uriBaseId": "PROJECTROOT",
+                        },
+                        "replacements": replacements,
+                    }],
+                }]);
+            }
+            let mut location = serde_json::Map::new();
+            if let Some(file) = &f.file {
+                let mut physical = json!({
+                    "artifactLocation": {
+                        "uri": file.to_string_lossy().replace('\\', "/"),
+                        "uriBaseId": "PROJECTROOT",
+                    },
+                });
+                if let Some(line) = f.line {
+                    physical["region"] = json!({ "startLine": line });
+                }
+                location.insert("physicalLocation".to_string(), physical);
+            }
+            if let Some(subject) = &f.subject {
+                location.insert(
+                    "logicalLocations".to_string(),
+                    json!([{ "fullyQualifiedName": subject }]),
+                );
+            }
+            if !location.is_empty() {
+                result["locations"] = json!([location]);
+            }
+            result
+        })
+        .collect();
+    json!({
+        "version": SARIF_VERSION,
+        "$schema": SARIF_SCHEMA,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": report.metadata.tool,
+                    "version": report.metadata.tool_version,
+                    "rules": rules.iter().map(|(id, (level, f))| sarif_rule(id, *level, f)).collect::<Vec<_>>(),
+                },
+            },
+            "taxonomies": [{
+                "name": CWE_TAXONOMY,
+                "version": CWE_VERSION,
+                "organization": "MITRE",
+                "informationUri": "https://cwe.mitre.org/",
+                "taxa": cwes.iter().map(|cwe| json!({
+                    "id": cwe.to_string(),
+                    "name": taxonomy::cwe_id(*cwe),
+                    "helpUri": format!("https://cwe.mitre.org/data/definitions/{}.html", cwe),
+                })).collect::<Vec<_>>(),
+            }],
+            "invocations": [{
+                "executionSuccessful": true,
+                "endTimeUtc": report.metadata.generated_at,
+            }],
+            "results": results,
+        }],
+    })
+}
+
+/// HTML 格式的报告：按严重程度排序的一张表，按类别分组时每个类别一节
+fn html(report: &Report, group_by_class: bool) -> String {
+    let mut findings: Vec<&Finding> = report
+        .findings
+        .iter()
+        .chain(&report.fixed)
+        .chain(&report.suppressed)
+        .collect();
+    findings.sort_by_key(|f| {
+        (
+            group_by_class.then_some((f.class.is_none(), f.class)),
+            f.suppression.is_some(),
+            f.status == Some(Status::Fixed),
+            std::cmp::Reverse(f.level),
+        )
+    });
+    let mut rows = String::new();
+    let mut group = None;
+    for f in findings {
+        if group_by_class && group != Some(f.class) {
+            group = Some(f.class);
+            rows.push_str(&format!(
+                "<tr class=\"group\"><th colspan=\"10\">{}</th></tr>\n",
+                class_name(f.class)
+            ));
+        }
+        let cwe: Vec<String> = f.cwe.iter().map(|&c| taxonomy::cwe_id(c)).collect();
+        let location = match (&f.file, f.line) {
+            (Some(file), Some(line)) => format!("{}:{}", file.display(), line),
+            (Some(file), None) => file.display().to_string(),
+            _ => String::new(),
+        };
+        let sources: Vec<String> = f
+            .sources
+            .iter()
+            .map(|s| format!("{}#{}", s.artifact, s.pointer))
+            .collect();
+        let status = match &f.suppression {
+            Some(suppression) => match &suppression.reason {
+                Some(reason) => format!("已抑制：{}", reason),
+                None => "已抑制".to_string(),
+            },
+            None => f.status.map_or("", Status::label).to_string(),
+        };
+        let fix = match &f.fix {
+            Some(fix) => format!(
+                "<details><summary>{}</summary><pre>{}</pre></details>",
+                escape_html(&fix.message),
+                escape_html(&fix.diff)
+            ),
+            None => String::new(),
+        };
+        rows.push_str(&format!(
+            "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            if f.suppression.is_some() { "suppressed" } else { f.level.name() },
+            f.level.name(),
+            escape_html(&status),
+            escape_html(&f.rule),
+            class_name(f.class),
+            cwe.join(" "),
+            escape_html(&f.message) + &fix,
+            escape_html(&location),
+            escape_html(f.subject.as_deref().unwrap_or("")),
+            escape_html(&sources.join(" ")),
+            escape_html(&f.graph_nodes.join(" ")),
+        ));
+    }
+    let baseline = match &report.baseline {
+        Some(name) => {
+            let count = |status| {
+                report
+                    .findings
+                    .iter()
+                    .filter(|f| f.status == Some(status))
+                    .count()
+            };
+            format!(
+                "<p>与基线 {} 相比：新增 {}，已有 {}，已修复 {}</p>\n",
+                escape_html(name),
+                count(Status::New),
+                count(Status::Existing),
+                report.fixed.len()
+            )
+        }
+        None => String::new(),
+    };
+    let classes: Vec<String> = report
+        .classes
+        .iter()
+        .map(|(class, count)| format!("{} {}", class.name(), count))
+        .collect();
+    let classes = if classes.is_empty() {
+        String::new()
+    } else {
+        format!("<p>按类别：{}</p>\n", classes.join("，"))
+    };
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"zh\">\n<head>\n<meta charset=\"utf-8\">\n<title>{tool} 报告</title>\n\
+<style>\nbody {{ font-family: sans-serif; }}\ntable {{ border-collapse: collapse; }}\n\
+td, th {{ border: 1px solid #ccc; padding: 4px 8px; vertical-align: top; }}\n\
+tr.error td:first-child {{ color: #b00; }}\ntr.warning td:first-child {{ color: #b60; }}\ntr.fixed {{ color: #888; }}\ntr.suppressed {{ color: #888; }}\ntr.group th {{ text-align: left; background: #eee; }}\n</style>\n\
+</head>\n<body>\n<h1>{tool} {version} 报告</h1>\n<p>生成于 {generated_at}，共 {count} 个问题 (另有 {suppressed} 个被源码标注抑制)，来自 {artifacts}</p>\n\
+{baseline}{classes}<table>\n<tr><th>级别</th><th>状态</th><th>规则</th><th>类别</th><th>CWE</th><th>说明</th><th>位置</th><th>对象</th><th>来源</th><th>图节点</th></tr>\n\
+{rows}</table>\n</body>\n</html>\n",
+        tool = report.metadata.tool,
+        version = report.metadata.tool_version,
+        generated_at = escape_html(&report.metadata.generated_at),
+        count = report.findings.len(),
+        suppressed = report.suppressed.len(),
+        artifacts = escape_html(&report.artifacts.join(", ")),
+        baseline = baseline,
+        classes = classes,
+        rows = rows,
+    )
+}
+
+/// 问题的指纹，见文件开头的说明
+fn fingerprint(finding: &Finding, line_text: &str) -> String {
+    let file = finding
+        .file
+        .as_ref()
+        .map(|f| f.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_default();
+    let mut hasher = blake3::Hasher::new();
+    for part in [
+        finding.rule.as_str(),
+        &file,
+        finding.subject.as_deref().unwrap_or(""),
+        &finding.message,
+        line_text,
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update(&[0]);
+    }
+    hasher.finalize().to_hex()[..FINGERPRINT_LEN].to_string()
+}
+
+/// 从 SARIF 的结果还原问题，只用于读取基线
+fn finding_from_sarif(result: &Value) -> Option<Finding> {
+    let location = result.pointer("/locations/0");
+    let properties = result.get("properties");
+    let property = |key: &str| properties.and_then(|p| p.get(key)).cloned();
+    Some(Finding {
+        rule: text(result, "ruleId")?,
+        stage: serde_json::from_value(property("stage")?).ok()?,
+        level: serde_json::from_value(result.get("level")?.clone()).ok()?,
+        message: result.pointer("/message/text")?.as_str()?.to_string(),
+        file: location
+            .and_then(|l| l.pointer("/physicalLocation/artifactLocation/uri"))
+            .and_then(Value::as_str)
+            .map(PathBuf::from),
+        line: location
+            .and_then(|l| l.pointer("/physicalLocation/region/startLine"))
+            .and_then(Value::as_u64)
+            .map(|l| l as usize),
+        subject: location
+            .and_then(|l| l.pointer("/logicalLocations/0/fullyQualifiedName"))
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        sources: property("sources")
+            .and_then(|s| serde_json::from_value(s).ok())
+            .unwrap_or_default(),
+        class: property("class").and_then(|c| serde_json::from_value(c).ok()),
+        cwe: property("cwe")
+            .and_then(|c| serde_json::from_value(c).ok())
+            .unwrap_or_default(),
+        graph_nodes: property("graphNodes")
+            .and_then(|n| serde_json::from_value(n).ok())
+            .unwrap_or_default(),
+        fingerprint: result
+            .get("partialFingerprints")?
+            .get(FINGERPRINT_NAME)?
+            .as_str()?
+            .to_string(),
+        status: None,
+        suppression: None,
+        fix: None,
+    })
+}
+
+/// 解析基线报告 (report.json 或 SARIF)，SARIF 中已标记为修复的结果不计入
+fn parse_baseline(content: &str, name: &str) -> Result<Vec<Finding>, Box<dyn Error>> {
+    let value: Value =
+        serde_json::from_str(content).map_err(|e| format!("无法解析基线 '{}': {}", name, e))?;
+    if let Some(runs) = value.get("runs").and_then(Value::as_array) {
+        return Ok(runs
+            .iter()
+            .flat_map(|run| {
+                run.get("results")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+            })
+            .filter(|r| r.get("baselineState").and_then(Value::as_str) != Some("absent"))
+            .filter(|r| r.get("suppressions").is_none())
+            .filter_map(finding_from_sarif)
+            .collect());
+    }
+    let baseline: BaselineReport =
+        serde_json::from_value(value).map_err(|e| format!("基线 '{}' 格式错误: {}", name, e))?;
+    Ok(baseline.findings)
+}
+
+/// 产物目录中 agent report 写出的报告 (report.json，其次 report.sarif) 里的问题，不含已修复和被抑制的；
+/// 每个问题按 JSON 报告中的格式给出，没有报告时返回空
+pub fn load_findings(artifacts_dir: &Path) -> Result<Vec<Value>, Box<dyn Error>> {
+    for format in [ReportFormat::Json, ReportFormat::Sarif] {
+        let path = artifacts_dir.join(format.file_name());
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let findings = parse_baseline(&content, &path.display().to_string())?;
+        return Ok(findings.iter().map(|finding| json!(finding)).collect());
+    }
+    Ok(vec![])
+}
+
+/// 读取 --baseline 或 --baseline-rev 给出的基线，返回基线的名字及其中的问题
+fn load_baseline(
+    args: &ReportArgs,
+    artifacts_dir: &Path,
+) -> Result<Option<Baseline>, Box<dyn Error>> {
+    let names = [
+        ReportFormat::Json.file_name(),
+        ReportFormat::Sarif.file_name(),
+    ];
+    if let Some(path) = &args.baseline {
+        let candidates: Vec<PathBuf> = if path.is_dir() {
+            names.iter().map(|name| path.join(name)).collect()
+        } else {
+            vec![path.clone()]
+        };
+        let Some(file) = candidates.iter().find(|p| p.is_file()) else {
+            return Err(format!(
+                "基线 '{}' 中没有 report.json 或 report.sarif",
+                path.display()
+            )
+            .into());
+        };
+        let content = fs::read_to_string(file)
+            .map_err(|e| format!("无法读取基线 '{}': {}", file.display(), e))?;
+        let name = file.display().to_string();
+        let findings = parse_baseline(&content, &name)?;
+        return Ok(Some(Baseline { name, findings }));
+    }
+
+    let Some(rev) = &args.baseline_rev else {
+        return Ok(None);
+    };
+    let project = &args.artifacts.project;
+    let dir = artifacts_dir.strip_prefix(project).unwrap_or(artifacts_dir);
+    for name in names {
+        // `./` 表示相对于项目目录，而不是仓库根目录
+        let spec = format!(
+            "{}:./{}",
+            rev,
+            dir.join(name).to_string_lossy().replace('\\', "/")
+        );
+        let Ok(lines) = git_lines(project, &["show", &spec]) else {
+            debug!(spec = %spec, "提交中没有该报告");
+            continue;
+        };
+        let findings = parse_baseline(&lines.join("\n"), &spec)?;
+        return Ok(Some(Baseline {
+            name: spec,
+            findings,
+        }));
+    }
+    Err(format!(
+        "提交 {} 的 '{}' 中没有 report.json 或 report.sarif",
+        rev,
+        dir.display()
+    )
+    .into())
+}
+
+/// 汇总产物目录中各阶段的问题，写出报告
+pub fn run(args: &ReportArgs) -> Result<(), Box<dyn Error>> {
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let detectors = args.artifacts.load_config()?.detectors;
+    for name in detectors.0.keys() {
+        let detector = name
+            .split_once('/')
+            .map_or(name.as_str(), |(detector, _)| detector);
+        if !SOURCES
+            .iter()
+            .any(|(file_name, _, _)| file_name.trim_end_matches(".json") == detector)
+        {
+            warn!(detector = %name, "配置的 [detectors] 中有未知的检测器");
+        }
+    }
+
+    let mut artifacts = vec![];
+    let mut results = vec![];
+    for &(file_name, _, _) in SOURCES {
+        let prefix = file_name.trim_end_matches(".json");
+        if !detectors.enabled(prefix) {
+            debug!(detector = prefix, "配置中禁用了该检测器，跳过");
+            continue;
+        }
+        let path = artifacts_dir.join(file_name);
+        let Ok(content) = fs::read_to_string(&path) else {
+            debug!(file = %path.display(), "没有结果文件，跳过");
+            continue;
+        };
+        let value: Value = serde_json::from_str(&content)
+            .map_err(|e| format!("无法解析 '{}': {}", path.display(), e))?;
+        artifacts.push(file_name.to_string());
+        results.push((file_name, value));
+    }
+    if artifacts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有任何阶段的结果，请先运行 agent patterns、agent detect 等命令",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut findings = collect_findings(&results, &detectors);
+    findings.retain(|f| args.selects(f));
+
+    // 图节点的交叉引用：没有 CFG/CPG 时只输出问题本身
+    match load_merged(&args.artifacts) {
+        Ok((_, graph)) => {
+            let index = NodeIndex::new(&graph, &args.artifacts.project);
+            for finding in findings.iter_mut().filter(|f| f.graph_nodes.is_empty()) {
+                if let (Some(file), Some(line)) = (&finding.file, finding.line) {
+                    finding.graph_nodes = index.lookup(file, line);
+                }
+            }
+        }
+        Err(e) => warn!("{}，报告中不含图节点", e),
+    }
+
+    let mut sources: HashMap<PathBuf, String> = HashMap::new();
+    for finding in &mut findings {
+        let line_text = match (&finding.file, finding.line) {
+            (Some(file), Some(line)) => {
+                let source = sources.entry(file.clone()).or_insert_with(|| {
+                    fs::read_to_string(args.artifacts.project.join(file)).unwrap_or_default()
+                });
+                source
+                    .lines()
+                    .nth(line.saturating_sub(1))
+                    .unwrap_or("")
+                    .trim()
+            }
+            _ => "",
+        };
+        finding.fingerprint = fingerprint(finding, line_text);
+    }
+
+    let fixes = autofix::load_fixes(&artifacts_dir)?;
+    for finding in &mut findings {
+        finding.fix = fixes
+            .iter()
+            .find(|fix| {
+                finding.sources.iter().any(|source| source.rule == fix.rule)
+                    && finding.file.as_ref() == Some(&fix.file)
+                    && finding.line == Some(fix.line)
+            })
+            .cloned();
+    }
+
+    // 源码标注抑制的问题单独列出，不参与基线比较
+    let annotations = suppressions::load(&artifacts_dir)?;
+    let mut suppressed = vec![];
+    let mut kept = vec![];
+    for mut finding in findings {
+        let annotation = annotations.iter().find(|s| {
+            finding.sources.iter().any(|source| {
+                s.matches(
+                    &source.rule,
+                    finding.file.as_deref(),
+                    finding.line,
+                    finding.subject.as_deref(),
+                )
+            })
+        });
+        match annotation {
+            Some(annotation) => {
+                debug!(rule = %finding.rule, file = %annotation.file.display(), line = annotation.line, "问题已被标注抑制");
+                finding.suppression = Some(annotation.clone());
+                suppressed.push(finding);
+            }
+            None => kept.push(finding),
+        }
+    }
+    let mut findings = kept;
+
+    let baseline = load_baseline(args, &artifacts_dir)?;
+    let mut fixed = vec![];
+    let baseline_name = baseline.as_ref().map(|b| b.name.clone());
+    let mut scope_range = None;
+    if baseline.is_none() {
+        if let Some(scope) = PreviousRunManifest::load(&artifacts_dir).scope {
+            classify_by_scope(
+                &mut findings,
+                &scope,
+                &artifacts_dir,
+                &args.artifacts.project,
+            )?;
+            scope_range = Some(scope.range);
+        }
+    }
+    if let Some(mut baseline) = baseline {
+        // 旧的基线中可能没有分类；过滤掉的类别不算修复
+        for finding in &mut baseline.findings {
+            if finding.class.is_none() && finding.cwe.is_empty() {
+                (finding.class, finding.cwe) = classification(None, &finding.rule);
+            }
+        }
+        baseline.findings.retain(|f| args.selects(f));
+        fixed = compare_with_baseline(&mut findings, &suppressed, baseline.findings);
+    }
+
+    if args.group_by_class {
+        for list in [&mut findings, &mut fixed, &mut suppressed] {
+            list.sort_by_key(|f| (f.class.is_none(), f.class));
+        }
+    }
+    let mut classes = BTreeMap::new();
+    for finding in &findings {
+        if let Some(class) = finding.class {
+            *classes.entry(class).or_default() += 1;
+        }
+    }
+
+    let report = Report {
+        metadata: ReportMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        artifacts,
+        baseline: baseline_name,
+        scope: scope_range,
+        classes,
+        findings,
+        fixed,
+        suppressed,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(args.format.file_name()));
+    let content = match args.format {
+        ReportFormat::Sarif => serde_json::to_string_pretty(&sarif(&report))?,
+        ReportFormat::Json => serde_json::to_string_pretty(&report)?,
+        ReportFormat::Html => html(&report, args.group_by_class),
+    };
+    fs::write(&output, content)?;
+    let count = |level| report.findings.iter().filter(|f| f.level == level).count();
+    // 没有基线和分析范围时所有问题都是新问题
+    let new = report
+        .findings
+        .iter()
+        .filter(|f| f.status.is_none_or(|s| s == Status::New))
+        .count();
+    info!(
+        artifacts = report.artifacts.len(),
+        findings = report.findings.len(),
+        errors = count(Level::Error),
+        warnings = count(Level::Warning),
+        notes = count(Level::Note),
+        new,
+        fixed = report.fixed.len(),
+        suppressed = report.suppressed.len(),
+        output = %output.display(),
+        "已写出汇总报告"
+    );
+    if args.fail_on_new && new > 0 {
+        return Err(format!("有 {} 个新问题", new).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(rule: &str, fingerprint: &str) -> Finding {
+        Finding {
+            rule: rule.to_string(),
+            stage: Stage::Check,
+            level: Level::Warning,
+            message: String::new(),
+            file: Some(PathBuf::from("src/lib.rs")),
+            line: Some(1),
+            subject: None,
+            sources: vec![Source {
+                rule: rule.to_string(),
+                artifact: format!("{}.json", rule.split('/').next().unwrap()),
+                pointer: "/findings/0".to_string(),
+            }],
+            class: None,
+            cwe: vec![],
+            graph_nodes: vec!["ast:1".to_string()],
+            fingerprint: fingerprint.to_string(),
+            status: None,
+            suppression: None,
+            fix: None,
+        }
+    }
+
+    #[test]
+    fn same_line_and_class_merge_across_stages() {
+        let results = [
+            (
+                "patterns.json",
+                json!({ "matches": [{
+                    "rule": "admin-not-signer",
+                    "message": "admin 没有签名",
+                    "file": "src/lib.rs",
+                    "line": 12,
+                    "class": "signer_authorization",
+                }] }),
+            ),
+            (
+                "privileges.json",
+                json!({ "findings": [
+                    {
+                        "kind": "unsigned_admin_key",
+                        "instruction": "set_fee",
+                        "file": "src/lib.rs",
+                        "line": 12,
+                        "message": "admin 不是 Signer",
+                    },
+                    {
+                        "kind": "unsigned_admin_key",
+                        "instruction": "update_admin",
+                        "file": "src/lib.rs",
+                        "line": 30,
+                        "message": "admin 不是 Signer",
+                    },
+                ] }),
+            ),
+        ];
+        let findings = collect_findings(&results, &DetectorsConfig::default());
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].rule, "patterns/admin-not-signer");
+        assert_eq!(findings[0].class, Some(VulnClass::SignerAuthorization));
+        let sources: Vec<(&str, &str, &str)> = findings[0]
+            .sources
+            .iter()
+            .map(|s| (s.rule.as_str(), s.artifact.as_str(), s.pointer.as_str()))
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                ("patterns/admin-not-signer", "patterns.json", "/matches/0"),
+                (
+                    "privileges/unsigned_admin_key",
+                    "privileges.json",
+                    "/findings/0"
+                ),
+            ]
+        );
+        assert_eq!(findings[1].line, Some(30));
+        assert_eq!(findings[1].sources.len(), 1);
+    }
+
+    #[test]
+    fn baseline_marks_new_existing_and_fixed() {
+        let mut findings = vec![finding("signers/a", "aaa"), finding("signers/b", "bbb")];
+        let suppressed = [finding("signers/d", "ddd")];
+        let baseline = vec![
+            finding("signers/b", "bbb"),
+            finding("signers/c", "ccc"),
+            finding("signers/d", "ddd"),
+        ];
+        let fixed = compare_with_baseline(&mut findings, &suppressed, baseline);
+        let statuses: Vec<Option<Status>> = findings.iter().map(|f| f.status).collect();
+        assert_eq!(statuses, [Some(Status::New), Some(Status::Existing)]);
+        assert_eq!(fixed.len(), 1);
+        assert_eq!(fixed[0].fingerprint, "ccc");
+        assert_eq!(fixed[0].status, Some(Status::Fixed));
+        assert!(fixed[0].sources.is_empty() && fixed[0].graph_nodes.is_empty());
+    }
+
+    #[test]
+    fn sarif_declares_rules_of_suppressed_findings() {
+        let mut suppressed = finding("sysvars/deprecated_sysvar", "eee");
+        suppressed.suppression = Some(Suppression {
+            rules: vec!["deprecated_sysvar".to_string()],
+            reason: Some("只在测试网使用".to_string()),
+            file: PathBuf::from("src/lib.rs"),
+            line: 1,
+            start_line: 1,
+            end_line: 1,
+            item: None,
+        });
+        let report = Report {
+            metadata: ReportMetadata {
+                tool: "solana_agent",
+                tool_version: "0.1.0",
+                generated_at: String::new(),
+            },
+            artifacts: vec!["sysvars.json".to_string()],
+            baseline: None,
+            scope: None,
+            classes: BTreeMap::new(),
+            findings: vec![finding("signers/a", "aaa")],
+            fixed: vec![],
+            suppressed: vec![suppressed],
+        };
+        let sarif = sarif(&report);
+        let run = &sarif["runs"][0];
+        let rules: Vec<&str> = run["tool"]["driver"]["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|rule| rule["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(rules, ["signers/a", "sysvars/deprecated_sysvar"]);
+        let result = &run["results"][1];
+        assert_eq!(result["ruleId"], "sysvars/deprecated_sysvar");
+        assert_eq!(result["suppressions"][0]["justification"], "只在测试网使用");
+    }
+}
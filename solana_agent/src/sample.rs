@@ -0,0 +1,171 @@
+// sample.rs
+//
+// 导出前对过大的合并图进行采样，只保留一部分节点及它们之间的边
+// - entry-hops：调用图入口 (没有调用边指向的函数入口) 的 k 跳邻域
+// - sink-hops：敏感调用 (CPI、转账、关闭账户等) 所在节点的 k 跳邻域
+// - degree：度中心性 (出边与入边的总数) 最高的 k 个节点
+// k 跳邻域沿所有种类的边、不区分方向展开；agent merge、agent dataset 和 agent view 共用这里的参数
+
+use crate::graph::{EdgeKind, NodeKind};
+use crate::merge::{called_names, MergedGraph};
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+use tracing::{info, warn};
+
+/// 默认的敏感调用
+const DEFAULT_SINKS: &[&str] = &[
+    "invoke",
+    "invoke_signed",
+    "transfer",
+    "transfer_checked",
+    "close_account",
+    "set_authority",
+    "realloc",
+    "set_return_data",
+];
+
+/// degree 默认保留的节点数
+const DEFAULT_TOP_K: usize = 1000;
+
+/// 采样策略
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SampleStrategy {
+    /// 调用图入口的 k 跳邻域
+    EntryHops,
+    /// 敏感调用的 k 跳邻域
+    SinkHops,
+    /// 度中心性最高的 k 个节点
+    Degree,
+}
+
+/// 采样相关的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct SampleArgs {
+    /// 导出前对合并图采样，默认不采样
+    #[arg(long, value_enum)]
+    sample: Option<SampleStrategy>,
+
+    /// entry-hops/sink-hops 展开的跳数
+    #[arg(long, value_name = "K", default_value_t = 2)]
+    hops: usize,
+
+    /// degree 保留的节点数 (默认 1000)；对 entry-hops/sink-hops 为保留节点数的上限
+    #[arg(long, value_name = "N")]
+    top_k: Option<usize>,
+
+    /// sink-hops 使用的敏感调用名 (逗号分隔)，默认为CPI、转账、关闭账户等
+    #[arg(long, value_delimiter = ',', value_name = "NAME")]
+    sinks: Vec<String>,
+}
+
+/// 种子节点的 k 跳邻域，按到种子的距离由近到远排列
+fn neighborhood(seeds: Vec<usize>, adjacency: &[Vec<usize>], hops: usize) -> Vec<usize> {
+    let mut seen: HashSet<usize> = seeds.iter().copied().collect();
+    let mut order = seeds;
+    let mut frontier_start = 0;
+    for _ in 0..hops {
+        let frontier_end = order.len();
+        for i in frontier_start..frontier_end {
+            for &next in &adjacency[order[i]] {
+                if seen.insert(next) {
+                    order.push(next);
+                }
+            }
+        }
+        frontier_start = frontier_end;
+    }
+    order
+}
+
+/// 按参数对合并图采样；没有指定 --sample 时原样返回
+pub fn apply(args: &SampleArgs, graph: &mut MergedGraph) {
+    let Some(strategy) = args.sample else {
+        return;
+    };
+    let index: HashMap<&str, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id.as_str(), i))
+        .collect();
+    let mut adjacency = vec![vec![]; graph.nodes.len()];
+    for edge in &graph.edges {
+        if let (Some(&source), Some(&target)) = (
+            index.get(edge.source.as_str()),
+            index.get(edge.target.as_str()),
+        ) {
+            adjacency[source].push(target);
+            adjacency[target].push(source);
+        }
+    }
+
+    let mut kept = match strategy {
+        SampleStrategy::EntryHops => {
+            let called: HashSet<&str> = graph
+                .edges
+                .iter()
+                .filter(|e| e.kind == EdgeKind::Call)
+                .map(|e| e.target.as_str())
+                .collect();
+            let seeds = graph
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, n)| n.kind == NodeKind::Entry && !called.contains(n.id.as_str()))
+                .map(|(i, _)| i)
+                .collect();
+            neighborhood(seeds, &adjacency, args.hops)
+        }
+        SampleStrategy::SinkHops => {
+            let sinks: Vec<&str> = if args.sinks.is_empty() {
+                DEFAULT_SINKS.to_vec()
+            } else {
+                args.sinks.iter().map(String::as_str).collect()
+            };
+            let seeds = graph
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, n)| called_names(&n.label).iter().any(|c| sinks.contains(c)))
+                .map(|(i, _)| i)
+                .collect();
+            neighborhood(seeds, &adjacency, args.hops)
+        }
+        SampleStrategy::Degree => {
+            let mut order: Vec<usize> = (0..graph.nodes.len()).collect();
+            order.sort_by_key(|&i| std::cmp::Reverse(adjacency[i].len()));
+            order
+        }
+    };
+    let top_k = match strategy {
+        SampleStrategy::Degree => Some(args.top_k.unwrap_or(DEFAULT_TOP_K)),
+        _ => args.top_k,
+    };
+    if let Some(top_k) = top_k {
+        kept.truncate(top_k);
+    }
+
+    if kept.is_empty() {
+        warn!(?strategy, "没有找到采样的起点，采样后的图为空");
+    }
+
+    let before = (graph.nodes.len(), graph.edges.len());
+    let mut keep = vec![false; graph.nodes.len()];
+    for i in kept {
+        keep[i] = true;
+    }
+    let mut flags = keep.iter();
+    graph.nodes.retain(|_| *flags.next().unwrap());
+    let ids: HashSet<&str> = graph.nodes.iter().map(|n| n.id.as_str()).collect();
+    graph
+        .edges
+        .retain(|e| ids.contains(e.source.as_str()) && ids.contains(e.target.as_str()));
+    info!(
+        ?strategy,
+        nodes_before = before.0,
+        edges_before = before.1,
+        nodes = graph.nodes.len(),
+        edges = graph.edges.len(),
+        "已对合并图采样"
+    );
+}
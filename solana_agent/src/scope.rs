@@ -0,0 +1,466 @@
+// scope.rs
+//
+// agent analyze --since/--diff：用 git 找出某个提交范围内变化的文件，
+// 再沿导入关系找出依赖它们的文件，只分析这两部分
+// 导入关系按源码文本近似解析：Rust 的 `mod`/`use crate::`，TypeScript/JavaScript 的相对路径 import/require
+// 范围连同变化文件中改动的行一起记录在 manifest.json 中，agent report 据此把问题分为新增和已有
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+use walkdir::WalkDir;
+
+/// 参与导入关系分析的源文件扩展名
+const SOURCE_EXTENSIONS: &[&str] = &["rs", "ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+/// TypeScript/JavaScript 相对导入省略扩展名时依次尝试的后缀
+const SCRIPT_SUFFIXES: &[&str] = &[
+    "",
+    ".ts",
+    ".tsx",
+    ".js",
+    ".jsx",
+    ".mjs",
+    ".cjs",
+    "/index.ts",
+    "/index.tsx",
+    "/index.js",
+];
+
+/// 每个文件中改动的行范围
+type ChangedLines = BTreeMap<PathBuf, Vec<(usize, usize)>>;
+
+/// 本次分析的范围，记录在 manifest.json 中，供下游区分新旧结果
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Scope {
+    /// 用户给出的提交范围，例如 "main" 或 "v1.0..HEAD"
+    pub range: String,
+    /// 范围内有变化的文件 (相对于项目根目录，包括已删除的文件)
+    pub changed: BTreeSet<PathBuf>,
+    /// 变化的文件中改动的行 (当前版本的行号，从1开始的闭区间)；没有记录的变化文件 (例如未跟踪的新文件) 整个算作改动
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub changed_lines: ChangedLines,
+    /// 通过导入关系依赖于变化文件的其他文件
+    pub dependents: BTreeSet<PathBuf>,
+}
+
+impl Scope {
+    /// 需要分析的文件：变化的和依赖它们的文件中，目前仍然存在的那些
+    pub fn files(&self, project: &Path) -> Vec<&PathBuf> {
+        self.changed
+            .iter()
+            .chain(&self.dependents)
+            .filter(|path| project.join(path).is_file())
+            .collect()
+    }
+
+    /// 文件中的一段行 (闭区间) 在范围内是否有改动；不给出行时看整个文件
+    pub fn changes(&self, file: &Path, lines: Option<(usize, usize)>) -> bool {
+        if !self.changed.contains(file) {
+            return false;
+        }
+        let (Some(hunks), Some((start, end))) = (self.changed_lines.get(file), lines) else {
+            return true;
+        };
+        hunks
+            .iter()
+            .any(|&(first, last)| first <= end && start <= last)
+    }
+
+    /// 某个目录 (相对于项目根目录) 下是否有需要分析的文件
+    pub fn touches(&self, project: &Path, dir: &Path) -> bool {
+        self.files(project).iter().any(|path| path.starts_with(dir))
+    }
+}
+
+/// 在项目目录中运行 git，返回标准输出的每一行
+pub fn git_lines(project: &Path, args: &[&str]) -> Result<Vec<String>, Box<dyn Error>> {
+    debug!(?args, "运行 git");
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(project)
+        .args(args)
+        .output()
+        .map_err(|e| format!("无法运行 git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} 执行失败: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// 检查作为 git 位置参数的值 (仓库地址、路径、版本)：这些值可能来自远程API或清单文件，
+/// 以 - 开头时会被 git 当作选项 (例如 --upload-pack=<命令>)
+pub fn check_git_arg<'a>(what: &str, value: &'a str) -> Result<&'a str, Box<dyn Error>> {
+    if value.starts_with('-') {
+        return Err(format!("{} '{}' 不能以 - 开头", what, value).into());
+    }
+    Ok(value)
+}
+
+/// 检查提交的哈希：7到40位十六进制数
+pub fn check_commit(commit: &str) -> Result<&str, Box<dyn Error>> {
+    if !(7..=40).contains(&commit.len()) || !commit.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("'{}' 不是有效的提交哈希", commit).into());
+    }
+    Ok(commit)
+}
+
+/// 范围内变化的文件，路径相对于项目根目录
+/// `--since <rev>` 比较 rev 与工作区 (包括未跟踪的文件)，`--diff <rev1>..<rev2>` 比较两个提交
+fn changed_files(
+    project: &Path,
+    since: Option<&str>,
+    diff: Option<&str>,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = match (since, diff) {
+        (Some(rev), _) => {
+            let mut files = git_lines(project, &["diff", "--name-only", "--relative", rev])?;
+            files.extend(git_lines(
+                project,
+                &["ls-files", "--others", "--exclude-standard"],
+            )?);
+            files
+        }
+        (None, Some(range)) => {
+            if !range.contains("..") {
+                return Err(
+                    format!("--diff 需要 <rev1>..<rev2> 形式的范围，而不是 '{}'", range).into(),
+                );
+            }
+            git_lines(project, &["diff", "--name-only", "--relative", range])?
+        }
+        (None, None) => vec![],
+    };
+    files.sort();
+    files.dedup();
+    Ok(files
+        .into_iter()
+        .map(PathBuf::from)
+        .filter(|path| is_source_file(path))
+        .collect())
+}
+
+/// 变化的文件中改动的行：`git diff -U0` 各个块在当前版本中的行范围，只删除了行的块记为删除处的一行
+/// 范围的格式已由 changed_files 检查过
+fn changed_lines(
+    project: &Path,
+    since: Option<&str>,
+    diff: Option<&str>,
+) -> Result<ChangedLines, Box<dyn Error>> {
+    let Some(range) = since.or(diff) else {
+        return Ok(BTreeMap::new());
+    };
+    let mut lines = ChangedLines::new();
+    let mut file = None;
+    for line in git_lines(
+        project,
+        &[
+            "diff",
+            "-U0",
+            "--no-color",
+            "--no-ext-diff",
+            "--src-prefix=a/",
+            "--dst-prefix=b/",
+            "--relative",
+            range,
+        ],
+    )? {
+        if let Some(path) = line.strip_prefix("+++ ") {
+            file = path.strip_prefix("b/").map(PathBuf::from);
+        } else if let (Some(file), Some(hunk)) = (&file, line.strip_prefix("@@ ")) {
+            // @@ -<旧起始>[,<旧行数>] +<新起始>[,<新行数>] @@
+            let Some(new) = hunk.split(' ').find_map(|part| part.strip_prefix('+')) else {
+                continue;
+            };
+            let (start, count) = new.split_once(',').unwrap_or((new, "1"));
+            let (Ok(start), Ok(count)) = (start.parse::<usize>(), count.parse::<usize>()) else {
+                continue;
+            };
+            let start = start.max(1);
+            lines
+                .entry(file.clone())
+                .or_default()
+                .push((start, start + count.max(1) - 1));
+        }
+    }
+    Ok(lines)
+}
+
+/// 是否为参与分析的源文件 (按扩展名判断)
+fn is_source_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| SOURCE_EXTENSIONS.contains(&ext))
+}
+
+/// 去掉路径中的 `.` 和 `..`，不访问文件系统
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Rust 源文件所属crate的 src 目录：向上找到的第一个名为 src 的目录
+fn rust_src_dir(file: &Path) -> Option<&Path> {
+    file.ancestors().skip(1).find(|dir| dir.ends_with("src"))
+}
+
+/// Rust 模块文件的两种可能位置：`<dir>/<name>.rs` 和 `<dir>/<name>/mod.rs`
+fn module_files(dir: &Path, name: &str) -> [PathBuf; 2] {
+    [
+        dir.join(format!("{}.rs", name)),
+        dir.join(name).join("mod.rs"),
+    ]
+}
+
+/// 一个 Rust 文件导入的文件 (可能不存在，例如已被删除的模块)
+fn rust_imports(file: &Path, source: &str) -> Vec<PathBuf> {
+    // 子模块所在的目录：lib.rs/main.rs/mod.rs 的子模块与其同级，其他文件的子模块在同名目录下
+    let parent = file.parent().unwrap_or(Path::new(""));
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let module_dir = if matches!(stem, "lib" | "main" | "mod") {
+        parent.to_path_buf()
+    } else {
+        parent.join(stem)
+    };
+
+    let mut imports = vec![];
+    for line in source.lines().map(str::trim) {
+        let line = line.strip_prefix("pub ").unwrap_or(line);
+        if let Some(name) = line
+            .strip_prefix("mod ")
+            .and_then(|rest| rest.strip_suffix(';'))
+        {
+            imports.extend(module_files(&module_dir, name.trim()));
+        } else if let Some(path) = line.strip_prefix("use crate::") {
+            // use crate::a::b::c 依次尝试 a、a/b、a/b/c 对应的模块文件
+            let Some(src_dir) = rust_src_dir(file) else {
+                continue;
+            };
+            let mut dir = src_dir.to_path_buf();
+            for segment in path
+                .split("::")
+                .map(|s| s.trim_end_matches(';').trim())
+                .take_while(|s| s.chars().all(|c| c.is_alphanumeric() || c == '_') && !s.is_empty())
+            {
+                imports.extend(module_files(&dir, segment));
+                dir.push(segment);
+            }
+        }
+    }
+    imports
+}
+
+/// 一个 TypeScript/JavaScript 文件通过相对路径导入的文件
+fn script_imports(file: &Path, source: &str) -> Vec<PathBuf> {
+    let parent = file.parent().unwrap_or(Path::new(""));
+    let mut imports = vec![];
+    for line in source.lines().map(str::trim) {
+        let is_import = line.starts_with("import ")
+            || line.starts_with("export ")
+            || line.contains("require(")
+            || line.contains("import(");
+        if !is_import {
+            continue;
+        }
+        // 取出行内第一个以 . 开头的字符串字面量
+        let specifier = line
+            .split(['"', '\'', '`'])
+            .skip(1)
+            .step_by(2)
+            .find(|s| s.starts_with('.'));
+        if let Some(specifier) = specifier {
+            let target = normalize(&parent.join(specifier));
+            for suffix in SCRIPT_SUFFIXES {
+                imports.push(PathBuf::from(format!("{}{}", target.display(), suffix)));
+            }
+        }
+    }
+    imports
+}
+
+/// 反向导入关系：被导入的文件 -> 导入它的文件
+fn reverse_imports(project: &Path, roots: &[PathBuf]) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let roots = if roots.is_empty() {
+        vec![project.to_path_buf()]
+    } else {
+        roots.iter().map(|root| project.join(root)).collect()
+    };
+    let mut importers: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for entry in roots
+        .iter()
+        .flat_map(|root| WalkDir::new(root).into_iter().filter_map(|e| e.ok()))
+        .filter(|e| e.path().is_file())
+    {
+        let path = entry.path();
+        if !is_source_file(path) {
+            continue;
+        }
+        let Ok(source) = fs::read_to_string(path) else {
+            continue;
+        };
+        let relative = path.strip_prefix(project).unwrap_or(path).to_path_buf();
+        let imports = if path.extension().is_some_and(|ext| ext == "rs") {
+            rust_imports(&relative, &source)
+        } else {
+            script_imports(&relative, &source)
+        };
+        for imported in imports {
+            importers
+                .entry(imported)
+                .or_default()
+                .push(relative.clone());
+        }
+    }
+    importers
+}
+
+/// 计算分析范围：变化的文件，以及沿反向导入关系传递可达的文件
+pub fn resolve(
+    project: &Path,
+    roots: &[PathBuf],
+    since: Option<&str>,
+    diff: Option<&str>,
+) -> Result<Scope, Box<dyn Error>> {
+    let changed: BTreeSet<PathBuf> = changed_files(project, since, diff)?.into_iter().collect();
+    let mut changed_lines = changed_lines(project, since, diff)?;
+    changed_lines.retain(|file, _| changed.contains(file));
+    let importers = reverse_imports(project, roots);
+
+    let mut seen: HashSet<&PathBuf> = changed.iter().collect();
+    let mut queue: Vec<&PathBuf> = changed.iter().collect();
+    let mut dependents = BTreeSet::new();
+    while let Some(file) = queue.pop() {
+        for importer in importers.get(file).into_iter().flatten() {
+            if seen.insert(importer) {
+                dependents.insert(importer.clone());
+                queue.push(importer);
+            }
+        }
+    }
+
+    Ok(Scope {
+        range: since.or(diff).unwrap_or_default().to_string(),
+        changed,
+        changed_lines,
+        dependents,
+    })
+}
+
+/// 把文件路径转义为只匹配它自己的 glob
+pub fn literal_glob(path: &Path) -> String {
+    let mut glob = String::new();
+    for c in path.to_string_lossy().chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '{' | '}') {
+            glob.push('[');
+            glob.push(c);
+            glob.push(']');
+        } else {
+            glob.push(c);
+        }
+    }
+    glob
+}
+
+/// 把 agent.toml 中的 glob 列表编译为 GlobSet
+pub fn globset(patterns: &[String]) -> Result<GlobSet, Box<dyn Error>> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(project: &Path, file: &str, content: &str) {
+        let path = project.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn since_finds_changed_lines_and_importers() {
+        let project =
+            std::env::temp_dir().join(format!("solana_agent-scope-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&project);
+        write(&project, "src/lib.rs", "mod state;\nmod util;\n");
+        write(&project, "src/state.rs", "use crate::util::helper;\n");
+        write(&project, "src/util.rs", "pub fn helper() {\n    1;\n}\n");
+        write(&project, "app/index.ts", "import { a } from './client';\n");
+        write(&project, "app/client.ts", "export const a = 1;\n");
+        write(&project, "app/other.ts", "export const b = 2;\n");
+        let git = |args: &[&str]| git_lines(&project, args).unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "-A"]);
+        git(&[
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-qm",
+            "init",
+        ]);
+
+        write(&project, "src/util.rs", "pub fn helper() {\n    2;\n}\n");
+        write(&project, "app/client.ts", "export const a = 3;\n");
+        write(&project, "src/new.rs", "pub fn added() {}\n");
+        let scope = resolve(&project, &[], Some("HEAD"), None).unwrap();
+        fs::remove_dir_all(&project).unwrap();
+
+        let paths = |files: &[&str]| files.iter().map(PathBuf::from).collect::<BTreeSet<_>>();
+        assert_eq!(
+            scope.changed,
+            paths(&["app/client.ts", "src/new.rs", "src/util.rs"])
+        );
+        assert_eq!(
+            scope.dependents,
+            paths(&["app/index.ts", "src/lib.rs", "src/state.rs"])
+        );
+        assert_eq!(scope.changed_lines[Path::new("src/util.rs")], [(2, 2)]);
+        // 改动的行之外不算新增；没有行记录的新文件整个算作改动
+        assert!(scope.changes(Path::new("src/util.rs"), Some((2, 3))));
+        assert!(!scope.changes(Path::new("src/util.rs"), Some((1, 1))));
+        assert!(scope.changes(Path::new("src/new.rs"), Some((1, 1))));
+        assert!(!scope.changes(Path::new("src/lib.rs"), None));
+    }
+
+    #[test]
+    fn diff_requires_a_range() {
+        let error = changed_files(Path::new("."), None, Some("main")).unwrap_err();
+        assert!(error.to_string().contains("<rev1>..<rev2>"));
+    }
+
+    #[test]
+    fn literal_glob_matches_only_itself() {
+        let path = Path::new("programs/[id]/src/*.rs");
+        let globs = globset(&[literal_glob(path)]).unwrap();
+        assert!(globs.is_match(path));
+        assert!(!globs.is_match("programs/i/src/lib.rs"));
+    }
+}
@@ -0,0 +1,664 @@
+// signers.rs
+//
+// agent signers：追踪签名权限如何进入 CPI —— 哪些 Signer 账户被传给了 CPI，哪些 CPI 通过 invoke_signed
+// 或 CpiContext::new_with_signer 附加了 PDA 签名，并标出把用户的签名转交给特权 CPI 却没有检查的指令
+// CPI 的账户来自调用处的表达式及其经由 let 绑定传入的值 (AST层的数据流)；合并图中有MIR层的数据流边时，
+// 再沿数据流边找出到达 CPI 调用的定义，作为补充证据
+
+use crate::anchor_accounts::{self, AccountsField};
+use crate::config::ArtifactsArgs;
+use crate::graph::{EdgeKind, Layer};
+use crate::idl::collect_programs;
+use crate::layout::split_top_level;
+use crate::manifest::now_rfc3339;
+use crate::merge::load_merged;
+use crate::symbols::{child, definition_name, line_of, load_asts, normalize, AstNode, CallGraph};
+use crate::tokens::account_name;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const SIGNERS_FILE_NAME: &str = "signers.json";
+
+/// 转移或销毁资产、修改权限的操作
+const PRIVILEGED_OPERATIONS: &[&str] = &[
+    "transfer",
+    "transfer_checked",
+    "burn",
+    "burn_checked",
+    "mint_to",
+    "mint_to_checked",
+    "approve",
+    "approve_checked",
+    "set_authority",
+    "close_account",
+    "assign",
+    "withdraw",
+];
+
+/// 指令构造函数所在的模块，这些模块的指令的程序ID是固定的
+const FIXED_PROGRAM_MODULES: &[&str] = &[
+    "system_instruction",
+    "spl_token::instruction",
+    "spl_token_2022::instruction",
+    "spl_associated_token_account::instruction",
+    "token_instruction",
+];
+
+/// 递归展开 let 绑定的最大深度
+const MAX_BINDING_DEPTH: usize = 8;
+
+/// `agent signers` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct SignersArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 signers.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// CPI 的调用方式
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum CpiKind {
+    Invoke,
+    InvokeSigned,
+    /// Anchor 的 CpiContext::new
+    Anchor,
+    /// CpiContext::new_with_signer 或 .with_signer(..)
+    AnchorSigned,
+}
+
+/// 一处 CPI
+#[derive(Serialize, Debug)]
+struct CpiSite {
+    kind: CpiKind,
+    file: PathBuf,
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function: Option<String>,
+    /// 被调用的程序：账户名，或者固定的程序 (`system_instruction` 等模块名)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    program: Option<String>,
+    /// 指令构造函数或 Anchor CPI 函数的名字
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operation: Option<String>,
+    /// PDA 签名的种子
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seeds: Option<String>,
+    code: String,
+    /// 调用处的表达式及其经由 let 绑定传入的值
+    #[serde(skip)]
+    reach: String,
+    /// 沿MIR数据流边到达调用的定义的源码
+    #[serde(skip)]
+    mir_reach: String,
+    #[serde(skip)]
+    start_byte: usize,
+    #[serde(skip)]
+    end_byte: usize,
+}
+
+/// 问题的种类
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum FindingKind {
+    /// 用户的签名被转交给没有固定程序ID的 CPI，调用方可以换成任意程序
+    SignerToUncheckedProgram,
+    /// 用户的签名被转交给特权操作，但无法确认被调用的程序
+    SignerToUnknownProgram,
+    /// 带 PDA 签名的特权操作，但指令没有任何 Signer
+    PdaSignatureWithoutSigner,
+}
+
+/// 一条指令中的一处 CPI
+#[derive(Serialize, Debug)]
+struct HandlerCpi {
+    /// 顶层 cpis 中的下标
+    cpi: usize,
+    /// 传给 CPI 的 Signer 账户
+    forwarded_signers: BTreeSet<String>,
+    /// 找到转交关系的依据：ast (let 绑定) 或 mir (数据流边)
+    evidence: BTreeSet<&'static str>,
+    pda_signed: bool,
+    privileged: bool,
+    /// 被调用的程序是否固定；无法判断时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    program_checked: Option<bool>,
+}
+
+/// 一条指令的签名传递情况
+#[derive(Serialize, Debug)]
+struct HandlerSigners {
+    instruction: String,
+    file: PathBuf,
+    function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accounts_struct: Option<String>,
+    signers: Vec<String>,
+    cpis: Vec<HandlerCpi>,
+}
+
+#[derive(Serialize, Debug)]
+struct Finding {
+    kind: FindingKind,
+    instruction: String,
+    /// 顶层 cpis 中的下标
+    cpi: usize,
+    message: String,
+}
+
+/// signers.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct SignersReport {
+    metadata: SignersMetadata,
+    handlers: Vec<HandlerSigners>,
+    cpis: Vec<CpiSite>,
+    findings: Vec<Finding>,
+}
+
+#[derive(Serialize, Debug)]
+struct SignersMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 文本中的所有标识符
+fn identifiers(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|s| !s.is_empty() && !s.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// Program<'info, T>、Interface<'info, T> 或带 address 约束的账户，调用的程序是固定的
+fn fixed_program(field: &AccountsField) -> bool {
+    matches!(field.base_type(), "Program" | "Interface") || field.value("address").is_some()
+}
+
+/// 从AST中收集的 CPI 和 Accounts 结构体
+#[derive(Default)]
+struct Collected<'a> {
+    cpis: Vec<CpiSite>,
+    accounts_structs: HashMap<String, Vec<AccountsField<'a>>>,
+}
+
+/// 当前所在的位置
+struct Scope<'a> {
+    file: &'a Path,
+    source: &'a str,
+    function: Option<&'a str>,
+    /// 所在函数中的 let 绑定：变量名 -> 值的表达式
+    bindings: &'a HashMap<String, String>,
+}
+
+/// 函数中的 let 绑定，包括嵌套块中的
+fn collect_bindings(node: &AstNode, bindings: &mut HashMap<String, String>) {
+    for item in &node.children {
+        if item.kind == "let_declaration" {
+            let pattern = item
+                .children
+                .iter()
+                .find(|c| matches!(c.kind.as_str(), "identifier" | "tuple_pattern"));
+            let value = item.children.iter().skip_while(|c| c.kind != "=").nth(1);
+            if let (Some(pattern), Some(value)) = (pattern, value) {
+                for name in identifiers(&pattern.text).filter(|n| *n != "mut") {
+                    bindings.insert(name.to_string(), normalize(&value.text));
+                }
+            }
+        }
+        if item.kind != "function_item" {
+            collect_bindings(item, bindings);
+        }
+    }
+}
+
+/// 表达式及其经由 let 绑定传入的值
+fn expand(text: &str, bindings: &HashMap<String, String>) -> String {
+    let mut reach = normalize(text);
+    let mut seen = HashSet::new();
+    let mut frontier = vec![reach.clone()];
+    for _ in 0..MAX_BINDING_DEPTH {
+        let mut next = vec![];
+        for text in &frontier {
+            for name in identifiers(text) {
+                if let Some(value) = bindings.get(name) {
+                    if seen.insert(name.to_string()) {
+                        next.push(value.clone());
+                    }
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        for value in &next {
+            reach.push_str(" ; ");
+            reach.push_str(value);
+        }
+        frontier = next;
+    }
+    reach
+}
+
+/// 展开后的文本中某个调用的参数，例如 `CpiContext::new(` 的参数
+fn call_args<'a>(reach: &'a str, callee: &str) -> Option<Vec<&'a str>> {
+    let start = reach.find(callee)? + callee.len();
+    let mut depth = 1;
+    for (i, c) in reach[start..].char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(split_top_level(&reach[start..start + i]));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 指令的构造：固定程序的模块名，或 Instruction 的 program_id 所取的账户
+fn instruction_program(reach: &str) -> Option<String> {
+    if let Some(module) = FIXED_PROGRAM_MODULES
+        .iter()
+        .find(|m| reach.contains(&format!("{}::", m)))
+    {
+        return Some(module.to_string());
+    }
+    let program_id = reach
+        .split_once("program_id:")
+        .map(|(_, rest)| rest)
+        .or_else(|| {
+            reach
+                .split_once("Instruction::new_with_")
+                .map(|(_, rest)| rest)
+        })?;
+    let program_id = program_id.split_once(['(', ','])?;
+    let program_id = match program_id.0.trim() {
+        "" => program_id.1.split([',', ')']).next()?,
+        value => value,
+    };
+    Some(account_name(program_id))
+}
+
+/// 固定程序的指令构造函数名
+fn instruction_operation(reach: &str) -> Option<String> {
+    FIXED_PROGRAM_MODULES.iter().find_map(|m| {
+        let (_, rest) = reach.split_once(&format!("{}::", m))?;
+        let name = rest.split('(').next()?.trim();
+        Some(name.rsplit("::").next().unwrap_or(name).to_string())
+    })
+}
+
+impl<'a> Collected<'a> {
+    fn visit(&mut self, node: &'a AstNode, scope: &Scope) {
+        let mut derives_accounts = false;
+        for item in &node.children {
+            match item.kind.as_str() {
+                "attribute_item" => {
+                    let text = normalize(&item.text);
+                    derives_accounts |= text.starts_with("#[derive(") && text.contains("Accounts");
+                    continue;
+                }
+                "line_comment" | "block_comment" => continue,
+                "struct_item" if derives_accounts => self.visit_accounts(item, scope),
+                "call_expression" => self.visit_call(item, scope),
+                _ => {}
+            }
+            derives_accounts = false;
+            if item.kind == "function_item" {
+                let mut bindings = HashMap::new();
+                collect_bindings(item, &mut bindings);
+                let scope = Scope {
+                    function: definition_name(item).map(|n| n.text.as_str()),
+                    bindings: &bindings,
+                    ..*scope
+                };
+                self.visit(item, &scope);
+            } else {
+                self.visit(item, scope);
+            }
+        }
+    }
+
+    /// invoke/invoke_signed，以及参数中 (经由 let 绑定) 含有 CpiContext 的调用
+    fn visit_call(&mut self, call: &AstNode, scope: &Scope) {
+        let Some(callee) = call.children.first().map(|c| normalize(&c.text)) else {
+            return;
+        };
+        let name = callee.rsplit("::").next().unwrap_or(&callee);
+        let args: Vec<&AstNode> = child(call, "arguments")
+            .into_iter()
+            .flat_map(|a| &a.children)
+            .filter(|a| {
+                !matches!(
+                    a.kind.as_str(),
+                    "(" | ")" | "," | "line_comment" | "block_comment"
+                )
+            })
+            .collect();
+        let reach = expand(&call.text, scope.bindings);
+        let args_reach =
+            child(call, "arguments").map_or(String::new(), |a| expand(&a.text, scope.bindings));
+        let (kind, program, operation, seeds) = match name {
+            "invoke" | "invoke_signed" | "invoke_unchecked" | "invoke_signed_unchecked" => {
+                let ix = args
+                    .first()
+                    .map_or(String::new(), |ix| expand(&ix.text, scope.bindings));
+                let signed = name.starts_with("invoke_signed");
+                (
+                    if signed {
+                        CpiKind::InvokeSigned
+                    } else {
+                        CpiKind::Invoke
+                    },
+                    instruction_program(&ix),
+                    instruction_operation(&ix),
+                    signed
+                        .then(|| args.get(2).map(|s| normalize(&s.text)))
+                        .flatten(),
+                )
+            }
+            // CpiContext 作为参数传入的调用，例如 token::transfer(cpi_ctx, amount)
+            _ if !callee.contains("CpiContext::") && args_reach.contains("CpiContext::") => {
+                let signed = call_args(&reach, "CpiContext::new_with_signer(");
+                let context = signed
+                    .clone()
+                    .or_else(|| call_args(&reach, "CpiContext::new("));
+                let seeds = signed
+                    .and_then(|args| args.get(2).map(|s| s.to_string()))
+                    .or_else(|| {
+                        call_args(&reach, ".with_signer(")?
+                            .first()
+                            .map(|s| s.to_string())
+                    });
+                (
+                    if seeds.is_some() {
+                        CpiKind::AnchorSigned
+                    } else {
+                        CpiKind::Anchor
+                    },
+                    context.and_then(|args| args.first().map(|p| account_name(p))),
+                    Some(name.to_string()),
+                    seeds,
+                )
+            }
+            _ => return,
+        };
+        self.cpis.push(CpiSite {
+            kind,
+            file: scope.file.to_path_buf(),
+            line: line_of(scope.source, call.start_byte),
+            function: scope.function.map(String::from),
+            program,
+            operation,
+            seeds,
+            code: normalize(&call.text),
+            reach,
+            mir_reach: String::new(),
+            start_byte: call.start_byte,
+            end_byte: call.end_byte,
+        });
+    }
+
+    /// Accounts 结构体的 Signer 和程序账户
+    fn visit_accounts(&mut self, item: &'a AstNode, scope: &Scope) {
+        let Some(name) = definition_name(item) else {
+            return;
+        };
+        let fields = anchor_accounts::fields(item, scope.source);
+        self.accounts_structs.insert(name.text.clone(), fields);
+    }
+}
+
+/// 沿合并图中MIR层的数据流边，找出到达每处 CPI 调用的定义的源码
+fn attach_mir_reach(artifacts: &ArtifactsArgs, cpis: &mut [CpiSite]) {
+    let graph = match load_merged(artifacts) {
+        Ok((_, graph)) => graph,
+        Err(e) => {
+            warn!(error = %e, "无法读取CFG/CPG，只使用AST中的数据流");
+            return;
+        }
+    };
+    let mut predecessors: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in graph.edges.iter().filter(|e| e.kind == EdgeKind::DataFlow) {
+        predecessors
+            .entry(edge.target.as_str())
+            .or_default()
+            .push(edge.source.as_str());
+    }
+    let spans: HashMap<&str, _> = graph
+        .nodes
+        .iter()
+        .filter_map(|n| Some((n.id.as_str(), n.span.as_ref()?)))
+        .collect();
+    let mut sources: HashMap<PathBuf, String> = HashMap::new();
+    for node in graph.nodes.iter().filter(|n| n.layer == Layer::Mir) {
+        let Some(span) = &node.span else {
+            continue;
+        };
+        let Some(cpi) = cpis.iter_mut().find(|c| {
+            c.file == span.file && c.start_byte <= span.start_byte && span.end_byte <= c.end_byte
+        }) else {
+            continue;
+        };
+        let mut seen = HashSet::from([node.id.as_str()]);
+        let mut queue = vec![node.id.as_str()];
+        while let Some(id) = queue.pop() {
+            for &source in predecessors.get(id).into_iter().flatten() {
+                if !seen.insert(source) {
+                    continue;
+                }
+                queue.push(source);
+                let Some(span) = spans.get(source) else {
+                    continue;
+                };
+                let text = sources.entry(span.file.clone()).or_insert_with(|| {
+                    fs::read_to_string(artifacts.project.join(&span.file)).unwrap_or_default()
+                });
+                if let Some(text) = text.get(span.start_byte..span.end_byte) {
+                    cpi.mir_reach.push_str(" ; ");
+                    cpi.mir_reach.push_str(&normalize(text));
+                }
+            }
+        }
+    }
+}
+
+/// 收集 CPI，按指令追踪 Signer 的转交，写出 signers.json
+pub fn run(args: &SignersArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("signers")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut collected = Collected::default();
+    let mut calls = CallGraph::default();
+    let mut programs = vec![];
+    let no_bindings = HashMap::new();
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        let scope = Scope {
+            file,
+            source: &source,
+            function: None,
+            bindings: &no_bindings,
+        };
+        collected.visit(root, &scope);
+        calls.add_file(file, root);
+        collect_programs(root, file, &source, &mut programs);
+    }
+    attach_mir_reach(&args.artifacts, &mut collected.cpis);
+
+    // (文件, 函数名) -> 其中的 CPI
+    let mut by_function: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+    for (index, cpi) in collected.cpis.iter().enumerate() {
+        if let Some(function) = &cpi.function {
+            by_function
+                .entry((cpi.file.clone(), function.clone()))
+                .or_default()
+                .push(index);
+        }
+    }
+
+    let mut handlers = vec![];
+    let mut findings = vec![];
+    for program in &programs {
+        for (handler, _) in &program.handlers {
+            let instruction = format!("{}::{}", program.name, handler.function);
+            let fields: &[AccountsField] = handler
+                .accounts_struct
+                .as_ref()
+                .and_then(|s| collected.accounts_structs.get(s))
+                .map_or(&[], Vec::as_slice);
+            let signers: Vec<String> = fields
+                .iter()
+                .filter(|f| f.is_signer())
+                .map(|f| f.name.clone())
+                .collect();
+            let mut cpis = vec![];
+            for key in calls.reachable(&handler.file, &handler.function) {
+                for &index in by_function.get(&key).into_iter().flatten() {
+                    let cpi = &collected.cpis[index];
+                    let mut forwarded_signers = BTreeSet::new();
+                    let mut evidence = BTreeSet::new();
+                    for (reach, source) in [(&cpi.reach, "ast"), (&cpi.mir_reach, "mir")] {
+                        let names: HashSet<&str> = identifiers(reach).collect();
+                        for signer in signers.iter().filter(|s| names.contains(s.as_str())) {
+                            forwarded_signers.insert(signer.clone());
+                            evidence.insert(source);
+                        }
+                    }
+                    let program_checked = cpi.program.as_deref().and_then(|program| {
+                        if FIXED_PROGRAM_MODULES.contains(&program)
+                            || program.contains("::")
+                            || program.starts_with(|c: char| c.is_ascii_uppercase())
+                        {
+                            return Some(true);
+                        }
+                        fields.iter().find(|f| f.name == program).map(fixed_program)
+                    });
+                    let pda_signed =
+                        matches!(cpi.kind, CpiKind::InvokeSigned | CpiKind::AnchorSigned);
+                    let privileged = cpi
+                        .operation
+                        .as_deref()
+                        .is_some_and(|op| PRIVILEGED_OPERATIONS.contains(&op))
+                        || program_checked == Some(false);
+
+                    if !forwarded_signers.is_empty() {
+                        let signers = forwarded_signers
+                            .iter()
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        match program_checked {
+                            Some(false) => findings.push(Finding {
+                                kind: FindingKind::SignerToUncheckedProgram,
+                                instruction: instruction.clone(),
+                                cpi: index,
+                                message: format!(
+                                    "Signer {} 被传给程序账户 {} 的 CPI，但该账户不是 Program<'info, T>，也没有 address 约束",
+                                    signers,
+                                    cpi.program.as_deref().unwrap_or("")
+                                ),
+                            }),
+                            None if privileged => findings.push(Finding {
+                                kind: FindingKind::SignerToUnknownProgram,
+                                instruction: instruction.clone(),
+                                cpi: index,
+                                message: format!(
+                                    "Signer {} 被传给特权操作 {}，但无法确认被调用的程序",
+                                    signers,
+                                    cpi.operation.as_deref().unwrap_or("")
+                                ),
+                            }),
+                            _ => {}
+                        }
+                    }
+                    if pda_signed && privileged && signers.is_empty() {
+                        findings.push(Finding {
+                            kind: FindingKind::PdaSignatureWithoutSigner,
+                            instruction: instruction.clone(),
+                            cpi: index,
+                            message: format!(
+                                "带 PDA 签名的 {} 可以由任何人触发，指令没有 Signer",
+                                cpi.operation.as_deref().unwrap_or("CPI")
+                            ),
+                        });
+                    }
+                    cpis.push(HandlerCpi {
+                        cpi: index,
+                        forwarded_signers,
+                        evidence,
+                        pda_signed,
+                        privileged,
+                        program_checked,
+                    });
+                }
+            }
+            handlers.push(HandlerSigners {
+                instruction,
+                file: handler.file.clone(),
+                function: handler.function.clone(),
+                accounts_struct: handler.accounts_struct.clone(),
+                signers,
+                cpis,
+            });
+        }
+    }
+
+    for finding in &findings {
+        let cpi = &collected.cpis[finding.cpi];
+        warn!(
+            instruction = %finding.instruction,
+            file = %cpi.file.display(),
+            line = cpi.line,
+            "{}",
+            finding.message
+        );
+    }
+    let report = SignersReport {
+        metadata: SignersMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        handlers,
+        cpis: collected.cpis,
+        findings,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(SIGNERS_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        cpis = report.cpis.len(),
+        handlers = report.handlers.len(),
+        findings = report.findings.len(),
+        output = %output.display(),
+        "已写出签名传递分析"
+    );
+    Ok(())
+}
@@ -0,0 +1,474 @@
+// snapshot.rs
+//
+// agent snapshot：把选定函数当前的CFG/CPG记录为黄金文件，提交到仓库中固定安全关键的处理函数的控制流
+// agent snapshot --verify 把当前的图与黄金文件比较，结构 (节点数、节点种类和函数内的边) 变化时以非零状态退出
+// 节点标签 (语句文本) 默认不参与比较，只改了变量名或表达式的重构不会触发；--strict 时标签也参与比较
+// 黄金文件中没有源码范围和时间戳，重新记录未变化的函数时文件内容不变
+
+use crate::config::ArtifactsArgs;
+use crate::graph::{EdgeKind, Layer, NodeKind};
+use crate::manifest::now_rfc3339;
+use crate::merge::{graph_key, load_merged};
+use crate::scope::globset;
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+/// 黄金文件格式的版本号，格式发生不兼容的变化时递增
+const SNAPSHOT_SCHEMA_VERSION: u32 = 1;
+
+/// 默认的快照目录，相对于项目根目录
+const SNAPSHOT_DIR: &str = "agent-snapshots";
+
+/// 校验结果的文件名，默认位于产物目录下
+const SNAPSHOT_VERIFY_FILE_NAME: &str = "snapshot_verify.json";
+
+/// `agent snapshot` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct SnapshotArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 选择函数：函数名、函数路径 (a::b) 或 `<文件>:<函数>`，可以使用 glob，可以重复；
+    /// 不指定时选择快照目录中已有的全部函数
+    #[arg(short, long = "function", value_name = "PATTERN")]
+    functions: Vec<String>,
+
+    /// 快照目录，相对路径相对于项目根目录
+    #[arg(long, value_name = "DIR", default_value = SNAPSHOT_DIR)]
+    dir: PathBuf,
+
+    /// 不写快照，而是把当前的图与快照比较，有变化时以非零状态退出
+    #[arg(long)]
+    verify: bool,
+
+    /// 校验时节点标签 (语句文本) 的变化也算作变化
+    #[arg(long, requires = "verify")]
+    strict: bool,
+
+    /// 校验结果文件，默认为产物目录下的 snapshot_verify.json
+    #[arg(short, long, value_name = "FILE", requires = "verify")]
+    output: Option<PathBuf>,
+}
+
+/// 黄金文件：一个函数的图，节点用图内的编号
+#[derive(Serialize, Deserialize, Debug)]
+struct Snapshot {
+    schema_version: u32,
+    layer: Layer,
+    /// 函数所属的源文件 (AST层) 或crate根文件 (MIR层)
+    unit: String,
+    function: String,
+    nodes: Vec<SnapshotNode>,
+    edges: Vec<SnapshotEdge>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SnapshotNode {
+    id: usize,
+    kind: NodeKind,
+    label: String,
+}
+
+/// 函数内的边；调用边和同源边取决于其他函数，不记录
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SnapshotEdge {
+    source: usize,
+    target: usize,
+    kind: EdgeKind,
+}
+
+impl Snapshot {
+    /// 在合并图中的键 `<层>:<单元>:<函数>`
+    fn key(&self) -> String {
+        format!("{}:{}:{}", layer_name(self.layer), self.unit, self.function)
+    }
+
+    /// 黄金文件相对于快照目录的路径：`<层>/<单元>.<函数>.json`
+    fn relative_path(&self) -> PathBuf {
+        let function: String = self
+            .function
+            .replace("::", ".")
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '.' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        Path::new(layer_name(self.layer)).join(format!("{}.{}.json", self.unit, function))
+    }
+}
+
+fn layer_name(layer: Layer) -> &'static str {
+    match layer {
+        Layer::Ast => "ast",
+        Layer::Mir => "mir",
+    }
+}
+
+/// 枚举值在JSON中的名字
+fn variant_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// 一个函数的校验结果
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Unchanged,
+    Changed,
+    /// 当前的产物中没有该函数
+    Missing,
+}
+
+#[derive(Serialize, Debug)]
+struct Verification {
+    layer: Layer,
+    unit: String,
+    function: String,
+    snapshot: PathBuf,
+    status: Status,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    changes: Vec<String>,
+}
+
+/// snapshot_verify.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct VerifyReport {
+    metadata: VerifyMetadata,
+    snapshot_dir: PathBuf,
+    strict: bool,
+    changed: usize,
+    functions: Vec<Verification>,
+}
+
+#[derive(Serialize, Debug)]
+struct VerifyMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 从合并图中取出每个函数的图，键为 `<层>:<单元>:<函数>`
+fn current_graphs(artifacts: &ArtifactsArgs) -> Result<BTreeMap<String, Snapshot>, Box<dyn Error>> {
+    let (_, merged) = load_merged(artifacts)?;
+    let mut graphs: BTreeMap<String, Snapshot> = BTreeMap::new();
+    let mut local_ids: HashMap<&str, (&str, usize)> = HashMap::new();
+    for node in &merged.nodes {
+        let key = graph_key(&node.id);
+        let Some(id) = node.id[key.len()..]
+            .strip_prefix('#')
+            .and_then(|id| id.parse().ok())
+        else {
+            continue;
+        };
+        local_ids.insert(&node.id, (key, id));
+        let unit = key
+            .strip_prefix(layer_name(node.layer))
+            .and_then(|rest| rest.strip_prefix(':'))
+            .and_then(|rest| rest.strip_suffix(node.function.as_str()))
+            .and_then(|rest| rest.strip_suffix(':'))
+            .unwrap_or_default();
+        graphs
+            .entry(key.to_string())
+            .or_insert_with(|| Snapshot {
+                schema_version: SNAPSHOT_SCHEMA_VERSION,
+                layer: node.layer,
+                unit: unit.to_string(),
+                function: node.function.clone(),
+                nodes: vec![],
+                edges: vec![],
+            })
+            .nodes
+            .push(SnapshotNode {
+                id,
+                kind: node.kind,
+                label: node.label.clone(),
+            });
+    }
+    for edge in &merged.edges {
+        if !matches!(edge.kind, EdgeKind::ControlFlow | EdgeKind::DataFlow) {
+            continue;
+        }
+        let (Some(&(key, source)), Some(&(target_key, target))) = (
+            local_ids.get(edge.source.as_str()),
+            local_ids.get(edge.target.as_str()),
+        ) else {
+            continue;
+        };
+        if key != target_key {
+            continue;
+        }
+        if let Some(graph) = graphs.get_mut(key) {
+            graph.edges.push(SnapshotEdge {
+                source,
+                target,
+                kind: edge.kind,
+            });
+        }
+    }
+    for graph in graphs.values_mut() {
+        graph.nodes.sort_by_key(|n| n.id);
+        graph
+            .edges
+            .sort_by_key(|e| (e.source, e.target, variant_name(&e.kind)));
+    }
+    Ok(graphs)
+}
+
+/// 读取快照目录中的全部黄金文件
+fn load_snapshots(dir: &Path) -> Result<Vec<(PathBuf, Snapshot)>, Box<dyn Error>> {
+    let mut snapshots = vec![];
+    if !dir.is_dir() {
+        return Ok(snapshots);
+    }
+    for entry in WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file() && e.path().extension().is_some_and(|ext| ext == "json"))
+    {
+        let path = entry.into_path();
+        let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("无法解析快照 '{}': {}", path.display(), e))?;
+        if snapshot.schema_version != SNAPSHOT_SCHEMA_VERSION {
+            return Err(format!(
+                "快照 '{}' 的格式版本为 {}，当前版本只支持 {}，请重新记录",
+                path.display(),
+                snapshot.schema_version,
+                SNAPSHOT_SCHEMA_VERSION
+            )
+            .into());
+        }
+        snapshots.push((path, snapshot));
+    }
+    Ok(snapshots)
+}
+
+/// 比较黄金文件与当前的图，返回变化的描述
+fn compare(expected: &Snapshot, actual: &Snapshot, strict: bool) -> Vec<String> {
+    let mut changes = vec![];
+    if expected.nodes.len() != actual.nodes.len() {
+        changes.push(format!(
+            "节点数 {} -> {}",
+            expected.nodes.len(),
+            actual.nodes.len()
+        ));
+    }
+    let actual_nodes: HashMap<usize, &SnapshotNode> =
+        actual.nodes.iter().map(|n| (n.id, n)).collect();
+    for node in &expected.nodes {
+        let Some(current) = actual_nodes.get(&node.id) else {
+            changes.push(format!(
+                "删除节点 {} ({})",
+                node.id,
+                variant_name(&node.kind)
+            ));
+            continue;
+        };
+        if current.kind != node.kind {
+            changes.push(format!(
+                "节点 {} 的种类 {} -> {}",
+                node.id,
+                variant_name(&node.kind),
+                variant_name(&current.kind)
+            ));
+        }
+        if strict && current.label != node.label {
+            changes.push(format!(
+                "节点 {} 的标签 {:?} -> {:?}",
+                node.id, node.label, current.label
+            ));
+        }
+    }
+    for node in &actual.nodes {
+        if !expected.nodes.iter().any(|n| n.id == node.id) {
+            changes.push(format!(
+                "新增节点 {} ({})",
+                node.id,
+                variant_name(&node.kind)
+            ));
+        }
+    }
+    let describe =
+        |e: &SnapshotEdge| format!("{} -> {} ({})", e.source, e.target, variant_name(&e.kind));
+    for edge in &expected.edges {
+        if !actual.edges.contains(edge) {
+            changes.push(format!("删除边 {}", describe(edge)));
+        }
+    }
+    for edge in &actual.edges {
+        if !expected.edges.contains(edge) {
+            changes.push(format!("新增边 {}", describe(edge)));
+        }
+    }
+    changes
+}
+
+/// 函数是否被某个模式选中：匹配函数名、函数路径的最后一段或 `<单元>:<函数>`
+fn selected(patterns: &GlobSet, snapshot: &Snapshot) -> bool {
+    let short = snapshot
+        .function
+        .rsplit("::")
+        .next()
+        .unwrap_or(&snapshot.function);
+    patterns.is_match(&snapshot.function)
+        || patterns.is_match(short)
+        || patterns.is_match(format!("{}:{}", snapshot.unit, snapshot.function))
+}
+
+/// 记录或校验选定函数的快照
+pub fn run(args: &SnapshotArgs) -> Result<(), Box<dyn Error>> {
+    let dir = args.artifacts.project.join(&args.dir);
+    let patterns = globset(&args.functions)?;
+    let existing: Vec<(PathBuf, Snapshot)> = load_snapshots(&dir)?
+        .into_iter()
+        .filter(|(_, snapshot)| args.functions.is_empty() || selected(&patterns, snapshot))
+        .collect();
+    let current = current_graphs(&args.artifacts)?;
+
+    if args.verify {
+        return verify(args, &dir, existing, &current);
+    }
+
+    // 记录：选中的当前函数；没有指定函数时重新记录已有的快照
+    let mut recorded = 0;
+    if args.functions.is_empty() {
+        if existing.is_empty() {
+            return Err(format!(
+                "快照目录 '{}' 中没有快照，请用 --function 选择要记录的函数",
+                dir.display()
+            )
+            .into());
+        }
+        for (path, snapshot) in &existing {
+            match current.get(&snapshot.key()) {
+                Some(graph) => {
+                    fs::write(path, serde_json::to_string_pretty(graph)?)?;
+                    recorded += 1;
+                }
+                None => warn!(
+                    function = %snapshot.function,
+                    unit = %snapshot.unit,
+                    snapshot = %path.display(),
+                    "当前的产物中没有该函数，保留原快照"
+                ),
+            }
+        }
+    } else {
+        for pattern in &args.functions {
+            let single = globset(std::slice::from_ref(pattern))?;
+            if !current.values().any(|graph| selected(&single, graph)) {
+                warn!(pattern = %pattern, "没有匹配的函数");
+            }
+        }
+        for graph in current.values().filter(|graph| selected(&patterns, graph)) {
+            let path = dir.join(graph.relative_path());
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, serde_json::to_string_pretty(graph)?)?;
+            debug!(function = %graph.function, snapshot = %path.display(), "已记录快照");
+            recorded += 1;
+        }
+        if recorded == 0 {
+            return Err("没有匹配 --function 的函数".into());
+        }
+    }
+    info!(functions = recorded, dir = %dir.display(), "已记录快照");
+    Ok(())
+}
+
+/// 校验快照，写出 snapshot_verify.json；有变化或函数已不存在时返回错误
+fn verify(
+    args: &SnapshotArgs,
+    dir: &Path,
+    snapshots: Vec<(PathBuf, Snapshot)>,
+    current: &BTreeMap<String, Snapshot>,
+) -> Result<(), Box<dyn Error>> {
+    if snapshots.is_empty() {
+        return Err(format!("快照目录 '{}' 中没有可校验的快照", dir.display()).into());
+    }
+    let mut functions = vec![];
+    for (path, snapshot) in snapshots {
+        let (status, changes) = match current.get(&snapshot.key()) {
+            Some(graph) => {
+                let changes = compare(&snapshot, graph, args.strict);
+                let status = if changes.is_empty() {
+                    Status::Unchanged
+                } else {
+                    Status::Changed
+                };
+                (status, changes)
+            }
+            None => (Status::Missing, vec![]),
+        };
+        match status {
+            Status::Changed => warn!(
+                function = %snapshot.function,
+                unit = %snapshot.unit,
+                changes = %changes.join("; "),
+                "函数的图与快照不一致"
+            ),
+            Status::Missing => warn!(
+                function = %snapshot.function,
+                unit = %snapshot.unit,
+                "当前的产物中没有快照中的函数"
+            ),
+            Status::Unchanged => {}
+        }
+        functions.push(Verification {
+            layer: snapshot.layer,
+            unit: snapshot.unit,
+            function: snapshot.function,
+            snapshot: path.strip_prefix(dir).unwrap_or(&path).to_path_buf(),
+            status,
+            changes,
+        });
+    }
+
+    let changed = functions
+        .iter()
+        .filter(|f| f.status != Status::Unchanged)
+        .count();
+    let report = VerifyReport {
+        metadata: VerifyMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        snapshot_dir: args.dir.clone(),
+        strict: args.strict,
+        changed,
+        functions,
+    };
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => args
+            .artifacts
+            .artifacts_dir()?
+            .join(SNAPSHOT_VERIFY_FILE_NAME),
+    };
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        snapshots = report.functions.len(),
+        changed,
+        output = %output.display(),
+        "已校验快照"
+    );
+    if changed > 0 {
+        return Err(format!("{} 个函数的图与快照不一致", changed).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,675 @@
+// space.rs
+//
+// agent space：计算每个 #[account] 结构体序列化后的大小和租金豁免所需的 lamports，
+// 再与 #[derive(Accounts)] 中 init/init_if_needed 的 space 和 realloc 约束比较，标出大小不符的分配
+// borsh 账户的大小为 8 字节判别值加上各字段的 borsh 编码 (见 layout.rs)，String/Vec 的上限取自 #[max_len(..)]；
+// zero-copy 账户为 8 加上 repr(C) 下的 size_of，#[account(zero_copy(unsafe))] 和 Anchor 0.27 之前的 zero_copy 为 packed
+// space 表达式支持整数、+ - * /、括号、常量 (const 和 impl 中的关联常量)、T::INIT_SPACE 和 size_of::<T>()
+
+use crate::config::ArtifactsArgs;
+use crate::layout::{
+    collect_type_defs, data_layout, split_top_level, zero_copy_size, DataLayout, FieldDef, TypeDef,
+};
+use crate::manifest::now_rfc3339;
+use crate::symbols::{child, definition_name, line_of, load_asts, normalize, AstNode};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const SPACE_FILE_NAME: &str = "space.json";
+
+/// Anchor 账户判别值的长度
+const DISCRIMINATOR_LEN: usize = 8;
+
+/// 租金：每字节每年的 lamports、豁免所需的年数和每个账户额外计入的字节数
+const LAMPORTS_PER_BYTE_YEAR: u64 = 3480;
+const EXEMPTION_THRESHOLD_YEARS: u64 = 2;
+const ACCOUNT_STORAGE_OVERHEAD: u64 = 128;
+
+/// 常量和 space 表达式的最大嵌套深度
+const MAX_DEPTH: usize = 16;
+
+/// `agent space` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct SpaceArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 space.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 一个 #[account] 结构体的大小，均包括判别值
+#[derive(Serialize, Debug)]
+struct AccountSize {
+    name: String,
+    file: PathBuf,
+    line: usize,
+    zero_copy: bool,
+    /// 定长时的大小
+    size: Option<usize>,
+    min_size: usize,
+    /// 最大大小；有没有 #[max_len] 的 String/Vec 时为 null
+    max_size: Option<usize>,
+    /// 按最大大小计算的租金豁免金额
+    rent_exempt_lamports: Option<u64>,
+    /// borsh 账户的字段布局
+    #[serde(skip_serializing_if = "Option::is_none")]
+    layout: Option<DataLayout>,
+}
+
+/// space 约束与账户大小的比较结果
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum SpaceStatus {
+    /// 与所需大小一致
+    Ok,
+    /// 小于所需大小，初始化或写入时会失败
+    TooSmall,
+    /// 大于所需大小，多付了租金
+    TooLarge,
+    /// 账户含有没有上限的 String/Vec，只能确认不小于最小大小
+    Unbounded,
+    /// 无法计算 space 表达式或找不到账户结构体
+    Unknown,
+}
+
+/// Accounts 结构体中的一处分配 (init/init_if_needed 的 space 或 realloc)
+#[derive(Serialize, Debug)]
+struct Allocation {
+    accounts_struct: String,
+    field: String,
+    file: PathBuf,
+    line: usize,
+    /// init 或 realloc
+    constraint: &'static str,
+    /// 分配的账户结构体
+    account: Option<String>,
+    /// 约束中的表达式
+    expression: String,
+    /// 表达式的值
+    space: Option<usize>,
+    /// 账户所需的大小
+    required: Option<usize>,
+    status: SpaceStatus,
+    /// 按 space 计算的租金豁免金额
+    rent_exempt_lamports: Option<u64>,
+}
+
+/// space.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct SpaceReport {
+    metadata: SpaceMetadata,
+    accounts: Vec<AccountSize>,
+    allocations: Vec<Allocation>,
+}
+
+#[derive(Serialize, Debug)]
+struct SpaceMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// AST中的一个 #[account] 结构体
+struct AccountStruct {
+    name: String,
+    file: PathBuf,
+    line: usize,
+    zero_copy: bool,
+    packed: bool,
+}
+
+/// AST中一个 Accounts 结构体字段上的约束
+struct Constraint {
+    accounts_struct: String,
+    field: String,
+    file: PathBuf,
+    line: usize,
+    kind: &'static str,
+    ty: String,
+    expression: String,
+}
+
+/// 账户数据大小对应的租金豁免金额
+fn rent_exempt_lamports(size: usize) -> u64 {
+    (ACCOUNT_STORAGE_OVERHEAD + size as u64) * LAMPORTS_PER_BYTE_YEAR * EXEMPTION_THRESHOLD_YEARS
+}
+
+/// 去掉空白后的属性文本
+fn compact(attribute: &AstNode) -> String {
+    attribute.text.split_whitespace().collect()
+}
+
+/// 从AST中收集的定义
+#[derive(Default)]
+struct Collected {
+    accounts: Vec<AccountStruct>,
+    constraints: Vec<Constraint>,
+    /// 常量的值表达式，键为常量名和 `类型::常量名`
+    consts: HashMap<String, String>,
+}
+
+impl Collected {
+    /// 收集 #[account] 结构体、Accounts 结构体中的约束和常量；impl_type 为所在 impl 的类型
+    fn visit(&mut self, node: &AstNode, file: &Path, source: &str, impl_type: Option<&str>) {
+        let mut attributes: Vec<String> = vec![];
+        for item in &node.children {
+            match item.kind.as_str() {
+                "attribute_item" => {
+                    attributes.push(compact(item));
+                    continue;
+                }
+                "line_comment" | "block_comment" => continue,
+                "struct_item" => {
+                    if let Some(name) = definition_name(item) {
+                        self.visit_struct(item, &name.text, &attributes, file, source);
+                    }
+                }
+                "const_item" => {
+                    let name = definition_name(item);
+                    let value = item.children.iter().skip_while(|c| c.kind != "=").nth(1);
+                    if let (Some(name), Some(value)) = (name, value) {
+                        if let Some(impl_type) = impl_type {
+                            self.consts.insert(
+                                format!("{}::{}", impl_type, name.text),
+                                value.text.clone(),
+                            );
+                        } else {
+                            self.consts.insert(name.text.clone(), value.text.clone());
+                        }
+                    }
+                }
+                _ => {}
+            }
+            attributes.clear();
+            let impl_type = match item.kind.as_str() {
+                // impl T / impl Trait for T：类型为最后一个类型名
+                "impl_item" => item
+                    .children
+                    .iter()
+                    .rfind(|c| matches!(c.kind.as_str(), "type_identifier" | "generic_type"))
+                    .map(|t| t.text.split('<').next().unwrap_or(&t.text)),
+                "declaration_list" => impl_type,
+                _ => None,
+            };
+            self.visit(item, file, source, impl_type);
+        }
+    }
+
+    fn visit_struct(
+        &mut self,
+        item: &AstNode,
+        name: &str,
+        attributes: &[String],
+        file: &Path,
+        source: &str,
+    ) {
+        if let Some(account) = attributes.iter().find(|a| a.starts_with("#[account")) {
+            self.accounts.push(AccountStruct {
+                name: name.to_string(),
+                file: file.to_path_buf(),
+                line: line_of(source, item.start_byte),
+                zero_copy: account.contains("zero_copy"),
+                packed: account.contains("zero_copy(unsafe)")
+                    || attributes
+                        .iter()
+                        .any(|a| a.starts_with("#[repr(") && a.contains("packed")),
+            });
+            return;
+        }
+        if !attributes
+            .iter()
+            .any(|a| a.starts_with("#[derive(") && a.contains("Accounts"))
+        {
+            return;
+        }
+        let mut constraints: Vec<(&'static str, String)> = vec![];
+        for field in child(item, "field_declaration_list")
+            .into_iter()
+            .flat_map(|fields| &fields.children)
+        {
+            match field.kind.as_str() {
+                "attribute_item" => {
+                    let text = normalize(&field.text);
+                    let Some(args) = text
+                        .strip_prefix("#[account(")
+                        .and_then(|t| t.strip_suffix(")]"))
+                    else {
+                        continue;
+                    };
+                    let args = split_top_level(args);
+                    let init = args.iter().any(|a| *a == "init" || *a == "init_if_needed");
+                    for arg in args {
+                        match arg.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                            Some(("space", value)) if init => {
+                                constraints.push(("init", value.to_string()))
+                            }
+                            Some(("realloc", value)) => {
+                                constraints.push(("realloc", value.to_string()))
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                "field_declaration" => {
+                    let field_name = child(field, "field_identifier").map_or("", |n| &n.text);
+                    let ty = field.children.last().map_or("", |t| t.text.as_str());
+                    for (kind, expression) in constraints.drain(..) {
+                        self.constraints.push(Constraint {
+                            accounts_struct: name.to_string(),
+                            field: field_name.to_string(),
+                            file: file.to_path_buf(),
+                            line: line_of(source, field.start_byte),
+                            kind,
+                            ty: ty.split_whitespace().collect(),
+                            expression,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// 字段类型中的账户结构体：Account<'info, T>、Box<Account<'info, T>>、AccountLoader<'info, T> 等的最后一个泛型参数
+fn account_type(ty: &str) -> Option<&str> {
+    let mut ty = ty;
+    loop {
+        let (base, rest) = ty.split_once('<')?;
+        let args = split_top_level(rest.strip_suffix('>')?);
+        match base.rsplit("::").next()? {
+            "Box" | "Option" => ty = args.first()?,
+            _ => return args.last().map(|t| t.rsplit("::").next().unwrap_or(t)),
+        }
+    }
+}
+
+/// space 表达式的求值
+struct Evaluator<'a> {
+    consts: &'a HashMap<String, String>,
+    defs: &'a HashMap<String, TypeDef>,
+    accounts: &'a [AccountStruct],
+}
+
+impl Evaluator<'_> {
+    fn eval(&self, expression: &str, depth: usize) -> Option<usize> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let text = normalize(expression);
+        let mut parser = Parser {
+            text: &text,
+            pos: 0,
+            evaluator: self,
+            depth,
+        };
+        let value = parser.expr()?;
+        parser.skip_spaces();
+        (parser.pos == text.len()).then_some(())?;
+        usize::try_from(value).ok()
+    }
+
+    /// T::INIT_SPACE：各字段 borsh 编码的最大大小，不含判别值
+    fn init_space(&self, ty: &str) -> Option<usize> {
+        match self.defs.get(ty)? {
+            TypeDef::Struct(fields) => data_layout(0, fields, self.defs).max_size,
+            TypeDef::Enum(_) => data_layout(0, &[FieldDef::new("", ty)], self.defs).max_size,
+        }
+    }
+
+    /// size_of::<T>()
+    fn size_of(&self, ty: &str) -> Option<usize> {
+        let packed = self.accounts.iter().any(|a| a.name == ty && a.packed);
+        zero_copy_size(ty, self.defs, packed)
+    }
+
+    /// 路径的值：常量，或者 T::INIT_SPACE
+    fn path(&self, path: &str, depth: usize) -> Option<usize> {
+        let segments: Vec<&str> = path.split("::").collect();
+        let last = *segments.last()?;
+        if last == "INIT_SPACE" && segments.len() >= 2 {
+            if let Some(space) = self.init_space(segments[segments.len() - 2]) {
+                return Some(space);
+            }
+        }
+        let qualified = segments[segments.len().saturating_sub(2)..].join("::");
+        let value = self
+            .consts
+            .get(path)
+            .or_else(|| self.consts.get(&qualified))
+            .or_else(|| self.consts.get(last))?;
+        self.eval(value, depth + 1)
+    }
+}
+
+/// 递归下降解析 space 表达式
+struct Parser<'a, 'e> {
+    text: &'a str,
+    pos: usize,
+    evaluator: &'e Evaluator<'e>,
+    depth: usize,
+}
+
+impl Parser<'_, '_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_spaces(&mut self) {
+        self.pos += self.rest().len() - self.rest().trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 由满足条件的字符组成的一段文本
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        let len = self
+            .rest()
+            .find(|c: char| !f(c))
+            .unwrap_or(self.rest().len());
+        self.pos += len;
+        &self.text[start..self.pos]
+    }
+
+    fn expr(&mut self) -> Option<i128> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat("+") {
+                value += self.term()?;
+            } else if self.eat("-") {
+                value -= self.term()?;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Option<i128> {
+        let mut value = self.factor()?;
+        loop {
+            if self.eat("*") {
+                value *= self.factor()?;
+            } else if self.eat("/") {
+                value = value.checked_div(self.factor()?)?;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Option<i128> {
+        self.skip_spaces();
+        let value = if self.eat("(") {
+            let value = self.expr()?;
+            self.eat(")").then_some(value)?
+        } else if self.rest().starts_with(|c: char| c.is_ascii_digit()) {
+            let digits = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            // 去掉下划线和类型后缀，例如 1_000usize
+            let digits: String = digits
+                .chars()
+                .take_while(|c| c.is_ascii_digit() || *c == '_')
+                .filter(|c| *c != '_')
+                .collect();
+            digits.parse().ok()?
+        } else {
+            let path = self
+                .take_while(|c| c.is_alphanumeric() || c == '_' || c == ':')
+                .to_string();
+            if path.is_empty() {
+                return None;
+            }
+            if path.ends_with("::") && self.eat("<") {
+                // size_of::<T>()
+                let ty = self.take_while(|c| c != '>').trim().to_string();
+                (self.eat(">") && self.eat("(") && self.eat(")")).then_some(())?;
+                if path.trim_end_matches("::").rsplit("::").next() != Some("size_of") {
+                    return None;
+                }
+                self.evaluator.size_of(&ty)? as i128
+            } else if self.eat(".len()") {
+                // T::DISCRIMINATOR.len()
+                path.ends_with("DISCRIMINATOR")
+                    .then_some(DISCRIMINATOR_LEN as i128)?
+            } else {
+                self.evaluator.path(&path, self.depth)? as i128
+            }
+        };
+        // 忽略类型转换，例如 `N as usize`
+        if self.eat("as ") {
+            self.skip_spaces();
+            self.take_while(|c| c.is_alphanumeric() || c == '_');
+        }
+        Some(value)
+    }
+}
+
+/// 账户结构体的大小
+fn account_size(account: &AccountStruct, defs: &HashMap<String, TypeDef>) -> AccountSize {
+    let (size, min_size, max_size, layout) = if account.zero_copy {
+        let size =
+            zero_copy_size(&account.name, defs, account.packed).map(|s| s + DISCRIMINATOR_LEN);
+        (size, size.unwrap_or(DISCRIMINATOR_LEN), size, None)
+    } else {
+        let fields = match defs.get(&account.name) {
+            Some(TypeDef::Struct(fields)) => fields.as_slice(),
+            _ => &[],
+        };
+        let layout = data_layout(DISCRIMINATOR_LEN, fields, defs);
+        (layout.size, layout.min_size, layout.max_size, Some(layout))
+    };
+    AccountSize {
+        name: account.name.clone(),
+        file: account.file.clone(),
+        line: account.line,
+        zero_copy: account.zero_copy,
+        size,
+        min_size,
+        max_size,
+        rent_exempt_lamports: max_size.map(rent_exempt_lamports),
+        layout,
+    }
+}
+
+/// 比较 space 与账户大小：定长或有上限时要求相等，没有上限时只检查最小大小
+fn compare(space: Option<usize>, account: Option<&AccountSize>) -> (Option<usize>, SpaceStatus) {
+    let (Some(space), Some(account)) = (space, account) else {
+        return (None, SpaceStatus::Unknown);
+    };
+    match account.max_size {
+        Some(required) if space < required => (Some(required), SpaceStatus::TooSmall),
+        Some(required) if space > required => (Some(required), SpaceStatus::TooLarge),
+        Some(required) => (Some(required), SpaceStatus::Ok),
+        None if space < account.min_size => (Some(account.min_size), SpaceStatus::TooSmall),
+        None => (Some(account.min_size), SpaceStatus::Unbounded),
+    }
+}
+
+/// 计算账户大小，检查 space/realloc 约束，写出 space.json
+pub fn run(args: &SpaceArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("space")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut collected = Collected::default();
+    let mut defs = HashMap::new();
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        collected.visit(root, file, &source, None);
+        collect_type_defs(root, &mut defs);
+    }
+
+    let accounts: Vec<AccountSize> = collected
+        .accounts
+        .iter()
+        .map(|account| account_size(account, &defs))
+        .collect();
+    let evaluator = Evaluator {
+        consts: &collected.consts,
+        defs: &defs,
+        accounts: &collected.accounts,
+    };
+    let mut allocations = vec![];
+    for constraint in &collected.constraints {
+        let account_name = account_type(&constraint.ty);
+        let account = account_name.and_then(|name| accounts.iter().find(|a| a.name == name));
+        let space = evaluator.eval(&constraint.expression, 0);
+        let (required, status) = compare(space, account);
+        match status {
+            SpaceStatus::TooSmall | SpaceStatus::TooLarge => warn!(
+                accounts_struct = %constraint.accounts_struct,
+                field = %constraint.field,
+                expression = %constraint.expression,
+                space,
+                required,
+                ?status,
+                "space 与账户大小不符"
+            ),
+            _ => {}
+        }
+        allocations.push(Allocation {
+            accounts_struct: constraint.accounts_struct.clone(),
+            field: constraint.field.clone(),
+            file: constraint.file.clone(),
+            line: constraint.line,
+            constraint: constraint.kind,
+            account: account_name.map(String::from),
+            expression: constraint.expression.clone(),
+            space,
+            required,
+            status,
+            rent_exempt_lamports: space.map(rent_exempt_lamports),
+        });
+    }
+
+    let mismatches = allocations
+        .iter()
+        .filter(|a| matches!(a.status, SpaceStatus::TooSmall | SpaceStatus::TooLarge))
+        .count();
+    let report = SpaceReport {
+        metadata: SpaceMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        accounts,
+        allocations,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(SPACE_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        accounts = report.accounts.len(),
+        allocations = report.allocations.len(),
+        mismatches,
+        output = %output.display(),
+        "已写出账户大小报告"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(name: &str) -> AccountStruct {
+        AccountStruct {
+            name: name.to_string(),
+            file: PathBuf::from("src/lib.rs"),
+            line: 1,
+            zero_copy: false,
+            packed: false,
+        }
+    }
+
+    /// Vault { owner: Pubkey, amount: u64 }，以及
+    /// State { authority: Pubkey, #[max_len(2)] amounts: Vec<u64>, #[max_len(8)] memo: Option<String> }
+    fn defs() -> HashMap<String, TypeDef> {
+        let vault = vec![
+            FieldDef::new("owner", "Pubkey"),
+            FieldDef::new("amount", "u64"),
+        ];
+        let state = vec![
+            FieldDef::new("authority", "Pubkey"),
+            FieldDef {
+                max_len: vec![2],
+                ..FieldDef::new("amounts", "Vec<u64>")
+            },
+            FieldDef {
+                max_len: vec![8],
+                ..FieldDef::new("memo", "Option<String>")
+            },
+        ];
+        HashMap::from([
+            ("Vault".to_string(), TypeDef::Struct(vault)),
+            ("State".to_string(), TypeDef::Struct(state)),
+        ])
+    }
+
+    #[test]
+    fn fixed_size_account() {
+        let size = account_size(&account("Vault"), &defs());
+        assert_eq!(size.size, Some(48));
+        assert_eq!((size.min_size, size.max_size), (48, Some(48)));
+        assert_eq!(size.rent_exempt_lamports, Some(1_224_960));
+    }
+
+    #[test]
+    fn variable_size_account() {
+        let size = account_size(&account("State"), &defs());
+        assert_eq!(size.size, None);
+        // 8 + 32 + (4 + 2 * 8) + (1 + 4 + 8)；最小时 Vec 为空、Option 为 None
+        assert_eq!(size.max_size, Some(73));
+        assert_eq!(size.min_size, 45);
+    }
+
+    #[test]
+    fn too_small_space_is_flagged() {
+        let defs = defs();
+        let accounts = [account("Vault")];
+        let evaluator = Evaluator {
+            consts: &HashMap::new(),
+            defs: &defs,
+            accounts: &accounts,
+        };
+        let vault = account_size(&accounts[0], &defs);
+        let space = evaluator.eval("8 + 32", 0);
+        assert_eq!(space, Some(40));
+        assert_eq!(
+            compare(space, Some(&vault)),
+            (Some(48), SpaceStatus::TooSmall)
+        );
+        let space = evaluator.eval("8 + Vault::INIT_SPACE", 0);
+        assert_eq!(compare(space, Some(&vault)), (Some(48), SpaceStatus::Ok));
+    }
+}
@@ -0,0 +1,260 @@
+// suppressions.rs
+//
+// 源码中的抑制标注：让团队逐处关闭某条规则，并写明理由
+// - 注释：`// agent-ignore: missing_signer_check reason="由外层程序检查"`，规则可以有多个 (用逗号或空格分开)；
+//   注释单独占一行时作用于其后的第一个语法节点 (函数、字段、语句等，跳过中间的属性和注释)，写在行尾时只作用于该行
+// - 属性：`#[agent_ignore(missing_signer_check, reason = "..")]`，作用于它修饰的整个条目；
+//   为了让程序照常编译，可以写成 `#[cfg_attr(any(), agent_ignore(..))]`；含有 `/*` 的规则要加引号，例如 `agent_ignore("known_vulns/*")`
+// 作用于处理函数时，以该指令为对象、报告在别处 (例如 Accounts 结构体的字段上) 的问题也被抑制
+// 标注跟随它所在的代码，因此在代码移动或上方插入代码之后仍然有效
+// 规则可以写完整的 `<结果文件>/<种类>` (例如 signers/missing_signer_check)、只写种类、写 `<结果文件>/*`，或用 `*` 表示所有规则
+// agent analyze 在AST阶段之后提取标注写出 suppressions.json，agent report 把匹配的问题移到报告的 suppressed 中
+
+use crate::layout::split_top_level;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{definition_name, line_of, load_asts, AstNode};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// 输出文件名，位于产物目录下
+pub const SUPPRESSIONS_FILE_NAME: &str = "suppressions.json";
+
+/// 注释标注的前缀
+const COMMENT_MARKER: &str = "agent-ignore:";
+
+/// 属性标注的名字
+const ATTRIBUTE_NAMES: &[&str] = &["agent_ignore(", "agent::ignore("];
+
+/// 注释节点的种类 (Rust 和 TypeScript/JavaScript)
+const COMMENT_KINDS: &[&str] = &["line_comment", "block_comment", "comment"];
+
+/// 一处抑制标注
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Suppression {
+    pub rules: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub file: PathBuf,
+    /// 标注所在的行
+    pub line: usize,
+    /// 作用范围的起止行 (含)
+    pub start_line: usize,
+    pub end_line: usize,
+    /// 作用的条目名 (函数、结构体、字段等)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+}
+
+impl Suppression {
+    /// 是否抑制 `rule` 在 `file` 第 `line` 行、以 `subject` 为对象的问题
+    pub fn matches(
+        &self,
+        rule: &str,
+        file: Option<&Path>,
+        line: Option<usize>,
+        subject: Option<&str>,
+    ) -> bool {
+        let at = file.is_some_and(|f| f == self.file)
+            && line.is_some_and(|l| (self.start_line..=self.end_line).contains(&l));
+        // 指令对象为 `<程序>::<指令>`
+        let on_item = self.item.as_deref().is_some_and(|item| {
+            subject.is_some_and(|s| s.contains("::") && s.rsplit("::").next() == Some(item))
+        });
+        (at || on_item) && self.rules.iter().any(|spec| rule_matches(spec, rule))
+    }
+}
+
+/// 标注中的规则是否匹配报告中的规则 `<结果文件>/<种类>`
+pub fn rule_matches(spec: &str, rule: &str) -> bool {
+    let (artifact, kind) = rule.split_once('/').unwrap_or(("", rule));
+    spec == "*"
+        || spec == rule
+        || spec == kind
+        || spec
+            .strip_suffix("/*")
+            .is_some_and(|prefix| prefix == artifact)
+}
+
+/// suppressions.json 的顶层结构
+#[derive(Serialize, Deserialize, Debug)]
+struct SuppressionsFile {
+    metadata: SuppressionsMetadata,
+    suppressions: Vec<Suppression>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SuppressionsMetadata {
+    tool: String,
+    tool_version: String,
+    generated_at: String,
+}
+
+/// 解析 `规则, 规则 reason="理由"`；没有规则时返回空
+fn parse_spec(text: &str) -> Option<(Vec<String>, Option<String>)> {
+    let (rules, reason) = match text.find("reason") {
+        Some(i) => {
+            let value = text[i + "reason".len()..].trim_start();
+            let value = value.strip_prefix('=')?.trim();
+            let reason = value
+                .strip_prefix('"')
+                .and_then(|v| v.split_once('"'))
+                .map_or(value, |(reason, _)| reason);
+            (&text[..i], Some(reason.to_string()))
+        }
+        None => (text, None),
+    };
+    let rules: Vec<String> = rules
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|r| !r.is_empty())
+        .map(String::from)
+        .collect();
+    (!rules.is_empty()).then_some((rules, reason))
+}
+
+/// 去掉注释符号之后的内容
+fn comment_body(text: &str) -> &str {
+    text.trim()
+        .trim_start_matches('/')
+        .trim_start_matches('*')
+        .trim_end_matches("*/")
+        .trim()
+}
+
+/// 注释中的标注
+fn comment_spec(text: &str) -> Option<(Vec<String>, Option<String>)> {
+    parse_spec(comment_body(text).strip_prefix(COMMENT_MARKER)?)
+}
+
+/// 属性中的标注，属性可以包在 cfg_attr 中
+fn attribute_spec(text: &str) -> Option<(Vec<String>, Option<String>)> {
+    let start = ATTRIBUTE_NAMES
+        .iter()
+        .find_map(|name| Some(text.find(name)? + name.len()))?;
+    let mut depth = 1;
+    let end = text[start..].char_indices().find_map(|(i, c)| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        (depth == 0).then_some(start + i)
+    })?;
+    let (mut rules, mut reason) = (vec![], None);
+    for part in split_top_level(&text[start..end]) {
+        match part.split_once('=') {
+            Some((key, value)) if key.trim() == "reason" => {
+                reason = Some(value.trim().trim_matches('"').to_string());
+            }
+            _ => rules.push(part.trim_matches('"').to_string()),
+        }
+    }
+    (!rules.is_empty()).then_some((rules, reason))
+}
+
+/// 节点是否为注释或属性，二者不作为标注的目标
+fn is_trivia(node: &AstNode) -> bool {
+    COMMENT_KINDS.contains(&node.kind.as_str()) || node.kind == "attribute_item"
+}
+
+/// 在AST中收集标注
+fn collect(node: &AstNode, file: &Path, source: &str, suppressions: &mut Vec<Suppression>) {
+    for (i, item) in node.children.iter().enumerate() {
+        let spec = if COMMENT_KINDS.contains(&item.kind.as_str()) {
+            comment_spec(&item.text).map(|spec| (spec, false))
+        } else if item.kind == "attribute_item" {
+            attribute_spec(&item.text).map(|spec| (spec, true))
+        } else {
+            None
+        };
+        if let Some(((rules, reason), is_attribute)) = spec {
+            let line = line_of(source, item.start_byte);
+            let line_start = source[..item.start_byte.min(source.len())]
+                .rfind('\n')
+                .map_or(0, |i| i + 1);
+            let trailing = !is_attribute
+                && source
+                    .get(line_start..item.start_byte)
+                    .is_some_and(|before| !before.trim().is_empty());
+            let target = node.children[i + 1..].iter().find(|n| !is_trivia(n));
+            let (start_line, end_line, item_name) = match target {
+                Some(target) if !trailing => (
+                    line_of(source, target.start_byte),
+                    line_of(source, target.end_byte.saturating_sub(1)),
+                    definition_name(target).map(|n| n.text.clone()),
+                ),
+                _ => (line, line, None),
+            };
+            if target.is_none() && !trailing {
+                warn!(file = %file.display(), line, "抑制标注之后没有代码，只作用于标注所在的行");
+            }
+            suppressions.push(Suppression {
+                rules,
+                reason,
+                file: file.to_path_buf(),
+                line,
+                start_line,
+                end_line,
+                item: item_name,
+            });
+        } else if COMMENT_KINDS.contains(&item.kind.as_str())
+            && comment_body(&item.text).starts_with(COMMENT_MARKER.trim_end_matches(':'))
+        {
+            warn!(file = %file.display(), line = line_of(source, item.start_byte), "抑制标注中没有规则，已忽略");
+        }
+        collect(item, file, source, suppressions);
+    }
+}
+
+/// 从产物目录中的AST提取所有标注
+pub fn extract(artifacts_dir: &Path, project: &Path) -> Result<Vec<Suppression>, Box<dyn Error>> {
+    let mut suppressions = vec![];
+    for (file, root) in load_asts(artifacts_dir)? {
+        let source = fs::read_to_string(project.join(&file)).unwrap_or_default();
+        collect(&root, &file, &source, &mut suppressions);
+    }
+    Ok(suppressions)
+}
+
+/// 提取标注并写出 suppressions.json
+pub fn write(artifacts_dir: &Path, project: &Path) -> Result<(), Box<dyn Error>> {
+    let suppressions = extract(artifacts_dir, project)?;
+    for suppression in &suppressions {
+        debug!(
+            file = %suppression.file.display(),
+            line = suppression.line,
+            rules = ?suppression.rules,
+            "抑制标注"
+        );
+    }
+    let file = SuppressionsFile {
+        metadata: SuppressionsMetadata {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            generated_at: now_rfc3339(),
+        },
+        suppressions,
+    };
+    let output = artifacts_dir.join(SUPPRESSIONS_FILE_NAME);
+    fs::write(&output, serde_json::to_string_pretty(&file)?)?;
+    info!(
+        suppressions = file.suppressions.len(),
+        output = %output.display(),
+        "已写出抑制标注"
+    );
+    Ok(())
+}
+
+/// 读取产物目录中的 suppressions.json；没有该文件时返回空
+pub fn load(artifacts_dir: &Path) -> Result<Vec<Suppression>, Box<dyn Error>> {
+    let path = artifacts_dir.join(SUPPRESSIONS_FILE_NAME);
+    let Ok(content) = fs::read_to_string(&path) else {
+        debug!(file = %path.display(), "没有抑制标注文件");
+        return Ok(vec![]);
+    };
+    let file: SuppressionsFile = serde_json::from_str(&content)
+        .map_err(|e| format!("无法解析 '{}': {}", path.display(), e))?;
+    Ok(file.suppressions)
+}
@@ -0,0 +1,460 @@
+// symbols.rs
+//
+// 从AST阶段的输出中提取符号的定义和引用，供 agent index 导出代码导航索引
+// 引用按名字解析：优先解析到同一文件中的定义，否则只在名字全局唯一时解析
+
+use crate::manifest::PreviousRunManifest;
+use crate::merge::called_names;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// 从第一步复用的AST节点结构，用于反序列化
+#[derive(Deserialize, Debug)]
+pub struct AstNode {
+    /// 节点ID (`文件ID:先序序号`)；较早生成的 .ast.json 没有该字段
+    #[serde(default)]
+    pub id: String,
+    pub kind: String,
+    /// 在父节点中的字段名 (例如 "name", "body")；较早生成的 .ast.json 没有该字段
+    #[serde(default)]
+    pub field: Option<String>,
+    /// --no-text 生成的AST没有该字段，load_asts 读取时从 source 还原
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub start_byte: usize,
+    #[serde(default)]
+    pub end_byte: usize,
+    /// 起止位置的行列号，从1开始；较早生成的 .ast.json 没有这些字段，此时为0
+    #[serde(default)]
+    pub start_line: usize,
+    #[serde(default)]
+    pub start_column: usize,
+    #[serde(default)]
+    pub end_line: usize,
+    #[serde(default)]
+    pub end_column: usize,
+    pub children: Vec<AstNode>,
+    /// --no-text 时根节点上保存的整个源文件
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+impl AstNode {
+    /// 为 --no-text 生成的AST补上各节点的文本：这类AST的节点不带 text，根节点上保存一份源文件
+    /// 已经带有文本的AST不受影响
+    pub fn restore_text(&mut self) {
+        fn fill(node: &mut AstNode, source: &str) {
+            if node.text.is_empty() {
+                node.text = source.get(node.start_byte..node.end_byte).unwrap_or("").to_string();
+            }
+            for child in &mut node.children {
+                fill(child, source);
+            }
+        }
+        if let Some(source) = self.source.take() {
+            fill(self, &source);
+        }
+    }
+}
+
+/// 会引入一个具名符号的节点种类，以及对应的符号种类
+pub const DEFINITION_KINDS: &[(&str, &str)] = &[
+    // Rust
+    ("function_item", "function"),
+    ("function_signature_item", "function"),
+    ("struct_item", "struct"),
+    ("enum_item", "enum"),
+    ("union_item", "struct"),
+    ("trait_item", "trait"),
+    ("type_item", "type"),
+    ("const_item", "constant"),
+    ("static_item", "constant"),
+    ("mod_item", "module"),
+    ("macro_definition", "macro"),
+    // TypeScript / JavaScript
+    ("function_declaration", "function"),
+    ("generator_function_declaration", "function"),
+    ("method_definition", "method"),
+    ("class_declaration", "class"),
+    ("interface_declaration", "interface"),
+    ("type_alias_declaration", "type"),
+    ("enum_declaration", "enum"),
+    // Python
+    ("function_definition", "function"),
+    ("class_definition", "class"),
+    // Solidity (interface_declaration、enum_declaration、function_definition 与上面同名)
+    ("contract_declaration", "class"),
+    ("library_declaration", "module"),
+    ("modifier_definition", "function"),
+    ("event_definition", "event"),
+    ("error_declaration", "type"),
+    ("struct_declaration", "struct"),
+    ("state_variable_declaration", "field"),
+    ("constant_variable_declaration", "constant"),
+];
+
+/// 作为名字出现的节点种类
+const NAME_KINDS: &[&str] = &[
+    "identifier",
+    "type_identifier",
+    "field_identifier",
+    "property_identifier",
+];
+
+/// 源码中的位置，行和列都从0开始，列按UTF-16代码单元计
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// 源码中的一段范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// 一个被索引的源文件
+#[derive(Debug)]
+pub struct Document {
+    pub path: PathBuf, // 相对于项目根目录
+    pub language: &'static str,
+}
+
+/// 一个符号的定义
+#[derive(Debug)]
+pub struct Definition {
+    pub name: String,
+    pub kind: &'static str,
+    pub document: usize, // 在 SymbolIndex.documents 中的下标
+    pub range: Range,    // 名字所在的范围
+    pub hover: String,   // 定义的第一行
+}
+
+/// 对某个定义的一次引用
+#[derive(Debug)]
+pub struct Reference {
+    pub document: usize,
+    pub range: Range,
+    pub definition: usize, // 在 SymbolIndex.definitions 中的下标
+}
+
+/// 整个项目的符号索引
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    pub documents: Vec<Document>,
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+/// 把字节偏移转换为行列位置
+struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex {
+            source,
+            line_starts,
+        }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.source.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let character = self
+            .source
+            .get(self.line_starts[line]..offset)
+            .map_or(0, |text| text.encode_utf16().count());
+        Position {
+            line: line as u32,
+            character: character as u32,
+        }
+    }
+
+    fn range(&self, node: &AstNode) -> Range {
+        Range {
+            start: self.position(node.start_byte),
+            end: self.position(node.end_byte),
+        }
+    }
+}
+
+/// 根据扩展名判断语言，返回 LSIF/SCIP 使用的语言标识
+pub fn language_of(path: &Path) -> Option<&'static str> {
+    match path.extension()?.to_str()? {
+        "rs" => Some("rust"),
+        "ts" | "tsx" => Some("typescript"),
+        "js" | "jsx" | "mjs" | "cjs" => Some("javascript"),
+        "py" => Some("python"),
+        "sol" => Some("solidity"),
+        "toml" => Some("toml"),
+        "move" => Some("move"),
+        _ => None,
+    }
+}
+
+/// 定义节点的名字：第一个作为名字出现的直接子节点
+pub fn definition_name(node: &AstNode) -> Option<&AstNode> {
+    node.children
+        .iter()
+        .find(|c| NAME_KINDS.contains(&c.kind.as_str()))
+}
+
+/// 直接子节点中第一个指定种类的节点
+pub fn child<'a>(node: &'a AstNode, kind: &str) -> Option<&'a AstNode> {
+    node.children.iter().find(|c| c.kind == kind)
+}
+
+/// 字节偏移所在的行号，从 1 开始
+pub fn line_of(source: &str, byte: usize) -> usize {
+    source.get(..byte).map_or(0, |s| s.matches('\n').count()) + 1
+}
+
+/// 空白合并为一个空格后的文本
+pub fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 第一遍：收集定义，同时记下每个定义名字节点的起始位置
+fn collect_definitions(
+    node: &AstNode,
+    document: usize,
+    lines: &LineIndex,
+    index: &mut SymbolIndex,
+    name_offsets: &mut HashSet<(usize, usize)>,
+) {
+    let kind = DEFINITION_KINDS
+        .iter()
+        .find(|(k, _)| *k == node.kind)
+        .map(|(_, kind)| *kind);
+    if let Some((kind, name)) = kind.zip(definition_name(node)) {
+        name_offsets.insert((document, name.start_byte));
+        index.definitions.push(Definition {
+            name: name.text.clone(),
+            kind,
+            document,
+            range: lines.range(name),
+            hover: node.text.lines().next().unwrap_or("").trim().to_string(),
+        });
+    }
+    for child in &node.children {
+        collect_definitions(child, document, lines, index, name_offsets);
+    }
+}
+
+/// 第二遍：把名字节点解析为对定义的引用
+fn collect_references(
+    node: &AstNode,
+    document: usize,
+    lines: &LineIndex,
+    by_name: &HashMap<&str, Vec<usize>>,
+    index: &SymbolIndex,
+    definition_offsets: &HashSet<(usize, usize)>,
+    references: &mut Vec<Reference>,
+) {
+    if NAME_KINDS.contains(&node.kind.as_str())
+        && !definition_offsets.contains(&(document, node.start_byte))
+    {
+        if let Some(candidates) = by_name.get(node.text.as_str()) {
+            let local: Vec<_> = candidates
+                .iter()
+                .copied()
+                .filter(|&d| index.definitions[d].document == document)
+                .collect();
+            let definition = match (local.as_slice(), candidates.as_slice()) {
+                ([d], _) | ([], [d]) => Some(*d),
+                _ => None,
+            };
+            if let Some(definition) = definition {
+                references.push(Reference {
+                    document,
+                    range: lines.range(node),
+                    definition,
+                });
+            }
+        }
+    }
+    for child in &node.children {
+        collect_references(
+            child,
+            document,
+            lines,
+            by_name,
+            index,
+            definition_offsets,
+            references,
+        );
+    }
+}
+
+/// 是否为Anchor的 `#[program]` 属性
+pub fn is_program_attribute(node: &AstNode) -> bool {
+    node.kind == "attribute_item" && node.text.replace(' ', "") == "#[program]"
+}
+
+/// 函数：(文件, 函数名)
+pub type FunctionRef = (PathBuf, String);
+
+/// 按名字解析的函数调用关系：调用优先解析到同一文件中的同名函数，否则只在名字全局唯一时解析
+#[derive(Default)]
+pub struct CallGraph {
+    /// (文件, 函数名) -> 函数体中调用的名字
+    callees: HashMap<(PathBuf, String), HashSet<String>>,
+    /// 函数名 -> 定义它的文件
+    by_name: HashMap<String, Vec<PathBuf>>,
+}
+
+impl CallGraph {
+    /// 收集一个文件的AST中的所有函数
+    pub fn add_file(&mut self, file: &Path, root: &AstNode) {
+        for item in &root.children {
+            if item.kind == "function_item" {
+                if let Some(name) = definition_name(item) {
+                    let callees = called_names(&item.text)
+                        .into_iter()
+                        .filter(|c| *c != name.text)
+                        .map(String::from);
+                    let key = (file.to_path_buf(), name.text.clone());
+                    if !self.callees.contains_key(&key) {
+                        self.by_name
+                            .entry(name.text.clone())
+                            .or_default()
+                            .push(file.to_path_buf());
+                    }
+                    self.callees.entry(key).or_default().extend(callees);
+                }
+            }
+            self.add_file(file, item);
+        }
+    }
+
+    /// 解析 `file` 中对 `name` 的调用
+    fn resolve(&self, file: &Path, name: &str) -> Option<(PathBuf, String)> {
+        let files = self.by_name.get(name)?;
+        let target = match files.as_slice() {
+            files if files.iter().any(|f| f == file) => file,
+            [target] => target,
+            _ => return None,
+        };
+        Some((target.to_path_buf(), name.to_string()))
+    }
+
+    /// 所有能解析的调用 (调用者, 被调用者)，按调用者排序
+    pub fn calls(&self) -> Vec<(FunctionRef, FunctionRef)> {
+        let mut calls: Vec<_> = self
+            .callees
+            .iter()
+            .flat_map(|(caller, callees)| {
+                callees
+                    .iter()
+                    .filter_map(|callee| Some((caller.clone(), self.resolve(&caller.0, callee)?)))
+            })
+            .collect();
+        calls.sort();
+        calls
+    }
+
+    /// 从 (文件, 函数名) 出发传递可达的函数，包括它自己
+    pub fn reachable(&self, file: &Path, function: &str) -> Vec<(PathBuf, String)> {
+        let start = (file.to_path_buf(), function.to_string());
+        let mut seen = HashSet::from([start.clone()]);
+        let mut queue = vec![start];
+        let mut reached = vec![];
+        while let Some(key) = queue.pop() {
+            for callee in self.callees.get(&key).into_iter().flatten() {
+                if let Some(target) = self.resolve(&key.0, callee) {
+                    if seen.insert(target.clone()) {
+                        queue.push(target);
+                    }
+                }
+            }
+            reached.push(key);
+        }
+        reached
+    }
+}
+
+/// 产物目录中的所有AST，以及各自的源文件路径 (相对于项目根目录)
+pub fn load_asts(artifacts_dir: &Path) -> Result<Vec<(PathBuf, AstNode)>, Box<dyn Error>> {
+    let manifest = PreviousRunManifest::load(artifacts_dir);
+    let mut asts = vec![];
+    for artifact in manifest.artifacts.iter().filter(|a| a.kind == "ast") {
+        let Some(source_path) = &artifact.source else {
+            continue;
+        };
+        let ast_path = artifacts_dir.join(&artifact.path);
+        let mut root: AstNode = serde_json::from_str(&fs::read_to_string(&ast_path)?)
+            .map_err(|e| format!("无法解析 {}: {}", ast_path.display(), e))?;
+        root.restore_text();
+        asts.push((source_path.clone(), root));
+    }
+    Ok(asts)
+}
+
+/// 读取产物目录中的所有AST，建立整个项目的符号索引
+pub fn build_index(artifacts_dir: &Path, project: &Path) -> Result<SymbolIndex, Box<dyn Error>> {
+    let mut index = SymbolIndex::default();
+    let mut files = vec![];
+    for (source_path, root) in load_asts(artifacts_dir)? {
+        let Some(language) = language_of(&source_path) else {
+            continue;
+        };
+        let source = match fs::read_to_string(project.join(&source_path)) {
+            Ok(source) => source,
+            Err(e) => {
+                warn!(path = %source_path.display(), error = %e, "无法读取源文件，已跳过");
+                continue;
+            }
+        };
+        index.documents.push(Document {
+            path: source_path,
+            language,
+        });
+        files.push((root, source));
+    }
+    if index.documents.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut name_offsets = HashSet::new();
+    for (document, (root, source)) in files.iter().enumerate() {
+        let lines = LineIndex::new(source);
+        collect_definitions(root, document, &lines, &mut index, &mut name_offsets);
+    }
+
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, definition) in index.definitions.iter().enumerate() {
+        by_name.entry(definition.name.as_str()).or_default().push(i);
+    }
+    let mut references = vec![];
+    for (document, (root, source)) in files.iter().enumerate() {
+        let lines = LineIndex::new(source);
+        collect_references(
+            root,
+            document,
+            &lines,
+            &by_name,
+            &index,
+            &name_offsets,
+            &mut references,
+        );
+    }
+    index.references = references;
+    Ok(index)
+}
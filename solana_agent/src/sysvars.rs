@@ -0,0 +1,480 @@
+// sysvars.rs
+//
+// agent sysvars：列出每处对 sysvar (Clock、Rent、Instructions、SlotHashes、RecentBlockhashes 等) 的使用，
+// 包括 X::get() 系统调用、Sysvar<'info, X> 账户和 from_account_info、指令 sysvar 的读取函数、sysvar ID 以及 Clock 字段的读取
+// 已弃用的 sysvar 和访问方式 (传入可以直接 get() 的 sysvar 账户、不检查地址的 load_instruction_at 等) 作为问题写入 findings
+// 每条指令汇总其处理函数、Accounts 结构体和传递调用的函数中的使用；调用关系按名字解析 (见 symbols::CallGraph)
+
+use crate::anchor_accounts;
+use crate::config::ArtifactsArgs;
+use crate::idl::collect_programs;
+use crate::layout::split_top_level;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{child, definition_name, line_of, load_asts, normalize, AstNode, CallGraph};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const SYSVARS_FILE_NAME: &str = "sysvars.json";
+
+/// sysvar 的名字 (即 solana_program::sysvar 下的模块名) 和类型名
+const SYSVARS: &[(&str, &str)] = &[
+    ("clock", "Clock"),
+    ("epoch_rewards", "EpochRewards"),
+    ("epoch_schedule", "EpochSchedule"),
+    ("fees", "Fees"),
+    ("instructions", "Instructions"),
+    ("last_restart_slot", "LastRestartSlot"),
+    ("recent_blockhashes", "RecentBlockhashes"),
+    ("rent", "Rent"),
+    ("slot_hashes", "SlotHashes"),
+    ("slot_history", "SlotHistory"),
+    ("stake_history", "StakeHistory"),
+];
+
+/// 已弃用的 sysvar
+const DEPRECATED_SYSVARS: &[&str] = &["fees", "recent_blockhashes"];
+
+/// 可以通过 X::get() 直接读取的 sysvar
+const GETTABLE_SYSVARS: &[&str] = &[
+    "clock",
+    "epoch_rewards",
+    "epoch_schedule",
+    "fees",
+    "last_restart_slot",
+    "rent",
+];
+
+/// 读取指令 sysvar 的函数，以及其中不检查账户地址的旧版本
+const INSTRUCTION_LOADERS: &[&str] = &[
+    "load_instruction_at_checked",
+    "load_current_index_checked",
+    "get_instruction_relative",
+    "load_instruction_at",
+    "load_current_index",
+];
+const UNCHECKED_LOADERS: &[&str] = &["load_instruction_at", "load_current_index"];
+
+/// Clock 的字段；slot 和 epoch 只有在所读的值看起来是 Clock 时才计入
+const CLOCK_FIELDS: &[&str] = &[
+    "unix_timestamp",
+    "epoch_start_timestamp",
+    "leader_schedule_epoch",
+    "slot",
+    "epoch",
+];
+
+/// `agent sysvars` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct SysvarsArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 sysvars.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 访问 sysvar 的方式
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Access {
+    /// X::get()
+    Get,
+    /// Sysvar<'info, X> 账户、address = sysvar::x::ID 约束或 X::from_account_info
+    Account,
+    /// 读取指令 sysvar 的函数
+    Load,
+    /// 引用 sysvar 的ID
+    Id,
+    /// 读取 Clock 的字段
+    Field,
+    /// X::default() 等不读取链上数据的构造
+    Default,
+}
+
+/// 一处 sysvar 的使用
+#[derive(Serialize, Debug)]
+struct SysvarUse {
+    sysvar: &'static str,
+    access: Access,
+    file: PathBuf,
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accounts_struct: Option<String>,
+    /// Accounts 结构体的字段，或者读取的 Clock 字段
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    code: String,
+    /// 已弃用或不推荐的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deprecated: Option<String>,
+    /// 已弃用或不推荐的用法对应的问题种类
+    #[serde(skip)]
+    finding: Option<FindingKind>,
+}
+
+/// 一条指令用到的 sysvar
+#[derive(Serialize, Debug)]
+struct HandlerSysvars {
+    instruction: String,
+    file: PathBuf,
+    function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accounts_struct: Option<String>,
+    sysvars: BTreeSet<&'static str>,
+    clock_fields: BTreeSet<String>,
+    deprecated: usize,
+    /// 顶层 uses 中的下标
+    uses: Vec<usize>,
+}
+
+/// 问题的种类
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum FindingKind {
+    /// 已弃用的 sysvar (Fees、RecentBlockhashes)
+    DeprecatedSysvar,
+    /// 传入可以直接 get() 读取的 sysvar 账户
+    SysvarAccount,
+    /// 不检查账户地址的指令 sysvar 读取函数，可以传入伪造的账户
+    UncheckedLoader,
+    /// X::default() 不是链上的实际值
+    DefaultValue,
+}
+
+#[derive(Serialize, Debug)]
+struct Finding {
+    kind: FindingKind,
+    /// 顶层 uses 中的下标
+    usage: usize,
+    message: String,
+}
+
+/// sysvars.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct SysvarsReport {
+    metadata: SysvarsMetadata,
+    handlers: Vec<HandlerSysvars>,
+    uses: Vec<SysvarUse>,
+    findings: Vec<Finding>,
+}
+
+#[derive(Serialize, Debug)]
+struct SysvarsMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 类型名对应的 sysvar
+fn sysvar_of_type(ty: &str) -> Option<&'static str> {
+    let ty = ty.rsplit("::").next().unwrap_or(ty);
+    SYSVARS
+        .iter()
+        .find(|(_, name)| *name == ty)
+        .map(|(sysvar, _)| *sysvar)
+}
+
+/// 路径中 `sysvar::<模块>` 对应的 sysvar
+fn sysvar_of_path(path: &str) -> Option<&'static str> {
+    let segments: Vec<&str> = path.split("::").map(str::trim).collect();
+    let i = segments.iter().position(|s| *s == "sysvar")?;
+    let module = segments.get(i + 1)?;
+    SYSVARS
+        .iter()
+        .find(|(sysvar, _)| sysvar == module)
+        .map(|(sysvar, _)| *sysvar)
+}
+
+/// 弃用的原因
+fn deprecation(sysvar: &str, access: Access, name: &str) -> Option<(FindingKind, String)> {
+    if DEPRECATED_SYSVARS.contains(&sysvar) {
+        return Some((
+            FindingKind::DeprecatedSysvar,
+            format!("sysvar {} 已弃用", sysvar),
+        ));
+    }
+    match access {
+        Access::Account if GETTABLE_SYSVARS.contains(&sysvar) => Some((
+            FindingKind::SysvarAccount,
+            "可以直接调用 get() 读取，不需要传入 sysvar 账户".to_string(),
+        )),
+        Access::Load if UNCHECKED_LOADERS.contains(&name) => Some((
+            FindingKind::UncheckedLoader,
+            format!("{} 不检查 sysvar 账户的地址，应使用 {}_checked", name, name),
+        )),
+        Access::Default => Some((
+            FindingKind::DefaultValue,
+            "default() 不是链上的实际参数，应使用 get()".to_string(),
+        )),
+        _ => None,
+    }
+}
+
+/// 从AST中收集的使用
+#[derive(Default)]
+struct Collected {
+    uses: Vec<SysvarUse>,
+    /// (文件, 函数名) -> 函数中的使用
+    functions: HashMap<(PathBuf, String), Vec<usize>>,
+    /// Accounts 结构体 -> 其中的使用
+    accounts_structs: HashMap<String, Vec<usize>>,
+}
+
+/// 当前所在的位置
+struct Scope<'a> {
+    file: &'a Path,
+    source: &'a str,
+    function: Option<&'a str>,
+}
+
+impl Collected {
+    fn push(
+        &mut self,
+        scope: &Scope,
+        node: &AstNode,
+        sysvar: &'static str,
+        access: Access,
+        name: &str,
+    ) {
+        let index = self.uses.len();
+        let (finding, deprecated) = deprecation(sysvar, access, name).unzip();
+        self.uses.push(SysvarUse {
+            sysvar,
+            access,
+            file: scope.file.to_path_buf(),
+            line: line_of(scope.source, node.start_byte),
+            function: scope.function.map(String::from),
+            accounts_struct: None,
+            field: (access == Access::Field).then(|| name.to_string()),
+            code: normalize(&node.text),
+            deprecated,
+            finding,
+        });
+        if let Some(function) = scope.function {
+            self.functions
+                .entry((scope.file.to_path_buf(), function.to_string()))
+                .or_default()
+                .push(index);
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode, scope: &Scope) {
+        let mut derives_accounts = false;
+        for item in &node.children {
+            match item.kind.as_str() {
+                "attribute_item" => {
+                    let text = normalize(&item.text);
+                    derives_accounts |= text.starts_with("#[derive(") && text.contains("Accounts");
+                    continue;
+                }
+                "line_comment" | "block_comment" => continue,
+                "struct_item" if derives_accounts => self.visit_accounts(item, scope),
+                "call_expression" => self.visit_call(item, scope),
+                "scoped_identifier" => {
+                    // sysvar::clock::ID、sysvar::instructions::id()
+                    let last = item.text.rsplit("::").next().unwrap_or("");
+                    if matches!(last, "ID" | "id" | "check_id") {
+                        if let Some(sysvar) = sysvar_of_path(&item.text) {
+                            self.push(scope, item, sysvar, Access::Id, last);
+                        }
+                    }
+                }
+                "field_expression" => {
+                    let value = item.children.first().map_or("", |v| v.text.as_str());
+                    let field = child(item, "field_identifier").map_or("", |f| f.text.as_str());
+                    let clock_value = value.to_lowercase().contains("clock");
+                    if CLOCK_FIELDS.contains(&field)
+                        && (clock_value || !matches!(field, "slot" | "epoch"))
+                    {
+                        self.push(scope, item, "clock", Access::Field, field);
+                    }
+                }
+                _ => {}
+            }
+            derives_accounts = false;
+            let function = match item.kind.as_str() {
+                "function_item" => definition_name(item).map(|n| n.text.as_str()),
+                _ => scope.function,
+            };
+            self.visit(item, &Scope { function, ..*scope });
+        }
+    }
+
+    /// X::get()、X::from_account_info(..)、X::default() 和指令 sysvar 的读取函数
+    fn visit_call(&mut self, call: &AstNode, scope: &Scope) {
+        let Some(callee) = call.children.first() else {
+            return;
+        };
+        let callee = normalize(&callee.text);
+        let (path, name) = callee.rsplit_once("::").unwrap_or(("", &callee));
+        let access = match name {
+            "get" => Access::Get,
+            "from_account_info" => Access::Account,
+            "default" => Access::Default,
+            _ if INSTRUCTION_LOADERS.contains(&name) => {
+                self.push(scope, call, "instructions", Access::Load, name);
+                return;
+            }
+            _ => return,
+        };
+        if let Some(sysvar) = sysvar_of_type(path) {
+            self.push(scope, call, sysvar, access, name);
+        }
+    }
+
+    /// Accounts 结构体中的 Sysvar<'info, X> 字段和 address = sysvar::x::ID 约束
+    fn visit_accounts(&mut self, item: &AstNode, scope: &Scope) {
+        let Some(name) = definition_name(item) else {
+            return;
+        };
+        for field in anchor_accounts::fields(item, scope.source) {
+            let sysvar = field
+                .ty
+                .split_once("Sysvar<")
+                .and_then(|(_, rest)| split_top_level(rest.strip_suffix('>')?).pop())
+                .and_then(sysvar_of_type)
+                .or_else(|| sysvar_of_path(&field.value("address")?));
+            let Some(sysvar) = sysvar else {
+                continue;
+            };
+            let index = self.uses.len();
+            let (finding, deprecated) = deprecation(sysvar, Access::Account, "").unzip();
+            self.uses.push(SysvarUse {
+                sysvar,
+                access: Access::Account,
+                file: scope.file.to_path_buf(),
+                line: field.line,
+                function: None,
+                accounts_struct: Some(name.text.clone()),
+                field: Some(field.name),
+                code: normalize(&field.node.text),
+                deprecated,
+                finding,
+            });
+            self.accounts_structs
+                .entry(name.text.clone())
+                .or_default()
+                .push(index);
+        }
+    }
+}
+
+/// 收集 sysvar 的使用，按指令汇总，写出 sysvars.json
+pub fn run(args: &SysvarsArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("sysvars")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut collected = Collected::default();
+    let mut calls = CallGraph::default();
+    let mut programs = vec![];
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        let scope = Scope {
+            file,
+            source: &source,
+            function: None,
+        };
+        collected.visit(root, &scope);
+        calls.add_file(file, root);
+        collect_programs(root, file, &source, &mut programs);
+    }
+
+    let mut handlers = vec![];
+    for program in &programs {
+        for (handler, _) in &program.handlers {
+            let mut uses: Vec<usize> = handler
+                .accounts_struct
+                .as_ref()
+                .and_then(|s| collected.accounts_structs.get(s))
+                .cloned()
+                .unwrap_or_default();
+            for key in calls.reachable(&handler.file, &handler.function) {
+                uses.extend(collected.functions.get(&key).into_iter().flatten());
+            }
+            uses.sort_unstable();
+            uses.dedup();
+            let used: Vec<&SysvarUse> = uses.iter().map(|&i| &collected.uses[i]).collect();
+            handlers.push(HandlerSysvars {
+                instruction: format!("{}::{}", program.name, handler.function),
+                file: handler.file.clone(),
+                function: handler.function.clone(),
+                accounts_struct: handler.accounts_struct.clone(),
+                sysvars: used.iter().map(|u| u.sysvar).collect(),
+                clock_fields: used
+                    .iter()
+                    .filter_map(|u| u.field.clone().filter(|_| u.access == Access::Field))
+                    .collect(),
+                deprecated: used.iter().filter(|u| u.deprecated.is_some()).count(),
+                uses,
+            });
+        }
+    }
+
+    let findings: Vec<Finding> = collected
+        .uses
+        .iter()
+        .enumerate()
+        .filter_map(|(usage, u)| {
+            Some(Finding {
+                kind: u.finding?,
+                usage,
+                message: format!("{}：{}", u.code, u.deprecated.as_deref()?),
+            })
+        })
+        .collect();
+    for finding in &findings {
+        let usage = &collected.uses[finding.usage];
+        warn!(
+            sysvar = usage.sysvar,
+            file = %usage.file.display(),
+            line = usage.line,
+            "{}",
+            finding.message
+        );
+    }
+    let report = SysvarsReport {
+        metadata: SysvarsMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        handlers,
+        uses: collected.uses,
+        findings,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(SYSVARS_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        uses = report.uses.len(),
+        handlers = report.handlers.len(),
+        findings = report.findings.len(),
+        output = %output.display(),
+        "已写出 sysvar 清单"
+    );
+    Ok(())
+}
@@ -0,0 +1,247 @@
+// taxonomy.rs
+//
+// 问题的标准化分类：Solana 程序专用的漏洞类别，以及对应的 CWE 编号
+// 内置分析的每种问题在 BUILTIN 中登记；用户规则 (agent patterns、agent detect) 和已知漏洞模式 (agent known-vulns)
+// 在规则文件中用 class 和 cwe 给出自己的分类，写入它们的结果文件，优先于这里的登记
+// agent report 把分类写入 SARIF (规则的 tags 和 CWE 分类法的 relationships)、JSON 和 HTML，并可以按类别过滤和分组
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// CWE 分类法的名字和版本，写入 SARIF 的 taxonomies
+pub const CWE_TAXONOMY: &str = "CWE";
+pub const CWE_VERSION: &str = "4.14";
+
+/// Solana 程序的漏洞类别
+#[derive(
+    ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum VulnClass {
+    /// 缺少签名检查，或用户的签名被转交给不可信的程序
+    SignerAuthorization,
+    /// 账户的 owner、地址或与其他账户的关系没有验证
+    AccountValidation,
+    /// 账户类型伪装：布局相同的另一种账户可以冒充
+    TypeConfusion,
+    /// 账户的创建、重复初始化、关闭与复活
+    AccountLifecycle,
+    /// CPI 的目标程序没有验证
+    ArbitraryCpi,
+    /// PDA 的种子、bump 或在用户之间共享
+    PdaMisuse,
+    /// 同一个账户作为多个可写账户传入
+    DuplicateAccounts,
+    /// 伪造的 sysvar 账户
+    SysvarSpoofing,
+    /// 声明的可写性与实际写入不符
+    AccountMutability,
+    /// 账户空间与租金
+    AccountSpace,
+    /// 权限与访问控制
+    AccessControl,
+    /// 整数溢出、精度与舍入
+    Arithmetic,
+    /// 客户端对 web3.js 的过时或不安全用法
+    ClientMisuse,
+    /// 依赖中有公开漏洞 (RustSec 公告) 的 crate
+    VulnerableDependency,
+    /// 源码中的私钥等机密
+    SecretExposure,
+    /// 状态变化没有事件，链下无法观测
+    Observability,
+    /// 死代码、没有测试的指令
+    CodeQuality,
+}
+
+impl VulnClass {
+    /// 在 JSON 和命令行中的名字
+    pub fn name(self) -> &'static str {
+        match self {
+            VulnClass::SignerAuthorization => "signer_authorization",
+            VulnClass::AccountValidation => "account_validation",
+            VulnClass::TypeConfusion => "type_confusion",
+            VulnClass::AccountLifecycle => "account_lifecycle",
+            VulnClass::ArbitraryCpi => "arbitrary_cpi",
+            VulnClass::PdaMisuse => "pda_misuse",
+            VulnClass::DuplicateAccounts => "duplicate_accounts",
+            VulnClass::SysvarSpoofing => "sysvar_spoofing",
+            VulnClass::AccountMutability => "account_mutability",
+            VulnClass::AccountSpace => "account_space",
+            VulnClass::AccessControl => "access_control",
+            VulnClass::Arithmetic => "arithmetic",
+            VulnClass::ClientMisuse => "client_misuse",
+            VulnClass::VulnerableDependency => "vulnerable_dependency",
+            VulnClass::SecretExposure => "secret_exposure",
+            VulnClass::Observability => "observability",
+            VulnClass::CodeQuality => "code_quality",
+        }
+    }
+
+    /// 是否为安全问题；可观测性和代码质量类的问题在 SARIF 中不带 security 标签
+    pub fn is_security(self) -> bool {
+        !matches!(self, VulnClass::Observability | VulnClass::CodeQuality)
+    }
+}
+
+/// 内置分析的问题分类：规则 (`<结果文件>/<种类>`)、类别和 CWE 编号
+const BUILTIN: &[(&str, VulnClass, &[u32])] = &[
+    (
+        "client_lint/legacy_account",
+        VulnClass::ClientMisuse,
+        &[477],
+    ),
+    (
+        "client_lint/confirm_without_commitment",
+        VulnClass::ClientMisuse,
+        &[754],
+    ),
+    (
+        "client_lint/deprecated_api",
+        VulnClass::ClientMisuse,
+        &[477],
+    ),
+    (
+        "client_lint/private_key_literal",
+        VulnClass::SecretExposure,
+        &[798],
+    ),
+    (
+        "client_lint/skip_preflight",
+        VulnClass::ClientMisuse,
+        &[754],
+    ),
+    (
+        "constraints/uncovered",
+        VulnClass::AccountValidation,
+        &[20, 345],
+    ),
+    (
+        "mutability/missing_mut",
+        VulnClass::AccountMutability,
+        &[732],
+    ),
+    (
+        "mutability/unnecessary_mut",
+        VulnClass::AccountMutability,
+        &[250],
+    ),
+    (
+        "signers/signer_to_unchecked_program",
+        VulnClass::ArbitraryCpi,
+        &[829],
+    ),
+    (
+        "signers/signer_to_unknown_program",
+        VulnClass::SignerAuthorization,
+        &[441],
+    ),
+    (
+        "signers/pda_signature_without_signer",
+        VulnClass::SignerAuthorization,
+        &[862],
+    ),
+    (
+        "privileges/unsigned_admin_key",
+        VulnClass::SignerAuthorization,
+        &[287],
+    ),
+    (
+        "privileges/missing_admin_check",
+        VulnClass::AccessControl,
+        &[862],
+    ),
+    ("sysvars/deprecated_sysvar", VulnClass::CodeQuality, &[477]),
+    ("sysvars/sysvar_account", VulnClass::CodeQuality, &[477]),
+    (
+        "sysvars/unchecked_loader",
+        VulnClass::SysvarSpoofing,
+        &[345],
+    ),
+    ("sysvars/default_value", VulnClass::CodeQuality, &[1188]),
+    ("space/too_small", VulnClass::AccountSpace, &[131]),
+    ("space/too_large", VulnClass::AccountSpace, &[131]),
+    ("pda/collision", VulnClass::PdaMisuse, &[694]),
+    (
+        "events/state_change_without_event",
+        VulnClass::Observability,
+        &[778],
+    ),
+    (
+        "events/listener_without_emit",
+        VulnClass::Observability,
+        &[778],
+    ),
+    (
+        "events/event_never_emitted",
+        VulnClass::Observability,
+        &[778],
+    ),
+    ("test_coverage/untested", VulnClass::CodeQuality, &[1164]),
+    (
+        "dead_code/unreachable_handler",
+        VulnClass::CodeQuality,
+        &[561],
+    ),
+    ("dead_code/dead_function", VulnClass::CodeQuality, &[561]),
+    (
+        "lifecycle/multiple_init",
+        VulnClass::AccountLifecycle,
+        &[665],
+    ),
+    (
+        "lifecycle/reinit_after_close",
+        VulnClass::AccountLifecycle,
+        &[672],
+    ),
+    (
+        "lifecycle/mutate_after_close",
+        VulnClass::AccountLifecycle,
+        &[672],
+    ),
+    (
+        "lifecycle/never_initialized",
+        VulnClass::AccountLifecycle,
+        &[908],
+    ),
+    ("autofix/unchecked_invoke", VulnClass::CodeQuality, &[252]),
+    (
+        "autofix/unchecked_arithmetic",
+        VulnClass::Arithmetic,
+        &[190],
+    ),
+    (
+        "advisories/reachable",
+        VulnClass::VulnerableDependency,
+        &[1395],
+    ),
+    (
+        "advisories/unreachable",
+        VulnClass::VulnerableDependency,
+        &[1395],
+    ),
+    (
+        "advisories/not_called",
+        VulnClass::VulnerableDependency,
+        &[1395],
+    ),
+    (
+        "advisories/unknown",
+        VulnClass::VulnerableDependency,
+        &[1395],
+    ),
+];
+
+/// 内置分析中某条规则的类别和 CWE 编号；用户规则和未登记的规则返回 None
+pub fn classify(rule: &str) -> Option<(VulnClass, &'static [u32])> {
+    BUILTIN
+        .iter()
+        .find(|(r, _, _)| *r == rule)
+        .map(|&(_, class, cwe)| (class, cwe))
+}
+
+/// CWE 编号的标准写法，例如 `CWE-862`
+pub fn cwe_id(cwe: u32) -> String {
+    format!("CWE-{}", cwe)
+}
@@ -0,0 +1,269 @@
+// test_coverage.rs
+//
+// agent test-coverage：在 TypeScript/JavaScript 的AST中找出 program.methods.<ix>(..)、program.rpc.<ix>(..)
+// 等调用，把它们对应到链上指令，列出测试中从未调用过的指令
+// 测试文件指 tests/、test/ 或 __tests__ 目录下的文件，以及 *.test.ts、*.spec.ts 等；其他文件中的调用也会列出，但不计入覆盖
+// 调用的对象能解析到 anchor.workspace.X 或 Program<X> 时只匹配程序 X 的指令，否则按指令名匹配所有程序
+
+use crate::config::ArtifactsArgs;
+use crate::idl::{collect_programs, snake_case};
+use crate::manifest::now_rfc3339;
+use crate::symbols::{child, line_of, load_asts, normalize, AstNode};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 输出文件名，默认位于产物目录下
+const TEST_COVERAGE_FILE_NAME: &str = "test_coverage.json";
+
+/// Anchor 客户端中按指令名调用的命名空间：program.<命名空间>.<指令>
+pub const NAMESPACES: &[&str] = &["methods", "rpc", "instruction", "transaction", "simulate"];
+
+/// 测试目录名
+const TEST_DIRS: &[&str] = &["tests", "test", "__tests__"];
+
+/// 测试文件名的后缀 (去掉扩展名后)
+const TEST_SUFFIXES: &[&str] = &[".test", ".spec"];
+
+/// `agent test-coverage` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct TestCoverageArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 test_coverage.json
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 一处客户端调用
+#[derive(Serialize, Debug)]
+struct CallSite {
+    file: PathBuf,
+    line: usize,
+    /// 调用时写的名字，例如 setFee
+    method: String,
+    namespace: String,
+    /// 解析出的程序名 (snake_case)；无法解析时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    program: Option<String>,
+    test: bool,
+    code: String,
+}
+
+/// 一条指令的测试覆盖
+#[derive(Serialize, Debug)]
+struct InstructionCoverage {
+    instruction: String,
+    file: PathBuf,
+    line: usize,
+    /// 测试文件中的调用次数
+    test_calls: usize,
+    /// 顶层 calls 中的下标
+    calls: Vec<usize>,
+}
+
+/// test_coverage.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct TestCoverageReport {
+    metadata: TestCoverageMetadata,
+    /// 测试中从未调用的指令
+    untested: Vec<String>,
+    instructions: Vec<InstructionCoverage>,
+    /// 对不上任何指令的调用在 calls 中的下标
+    unmatched: Vec<usize>,
+    calls: Vec<CallSite>,
+}
+
+#[derive(Serialize, Debug)]
+struct TestCoverageMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 是否为测试文件
+pub fn is_test_file(path: &Path) -> bool {
+    let in_test_dir = path.components().any(|c| {
+        c.as_os_str()
+            .to_str()
+            .is_some_and(|c| TEST_DIRS.contains(&c))
+    });
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    in_test_dir || TEST_SUFFIXES.iter().any(|s| stem.ends_with(s))
+}
+
+/// 变量的声明中引用的程序，例如 `anchor.workspace.Demo` 或 `Program<Demo>` -> demo
+pub fn program_of_declaration(text: &str) -> Option<String> {
+    let name = text
+        .split_once("workspace.")
+        .or_else(|| text.split_once("Program<"))
+        .map(|(_, rest)| rest)?;
+    let end = name
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(name.len());
+    (end > 0).then(|| snake_case(&name[..end]))
+}
+
+/// 文件中变量和参数的声明：名字 -> 声明的文本
+pub fn collect_declarations(node: &AstNode, declarations: &mut HashMap<String, String>) {
+    for item in &node.children {
+        if matches!(
+            item.kind.as_str(),
+            "variable_declarator"
+                | "required_parameter"
+                | "optional_parameter"
+                | "public_field_definition"
+        ) {
+            let name = item
+                .children
+                .iter()
+                .find(|c| matches!(c.kind.as_str(), "identifier" | "property_identifier"));
+            if let Some(name) = name {
+                declarations
+                    .entry(name.text.clone())
+                    .or_default()
+                    .push_str(&item.text);
+            }
+        }
+        collect_declarations(item, declarations);
+    }
+}
+
+/// program.<命名空间>.<指令> 形式的成员表达式
+fn collect_calls(
+    node: &AstNode,
+    file: &Path,
+    source: &str,
+    declarations: &HashMap<String, String>,
+    calls: &mut Vec<CallSite>,
+) {
+    for item in &node.children {
+        if item.kind == "member_expression" {
+            let object = item.children.first().map(|o| normalize(&o.text));
+            let method = child(item, "property_identifier");
+            if let (Some(object), Some(method)) = (object, method) {
+                let (receiver, namespace) = object.rsplit_once('.').unwrap_or(("", &object));
+                if NAMESPACES.contains(&namespace) && !receiver.is_empty() {
+                    let variable = receiver.rsplit('.').next().unwrap_or(receiver);
+                    let program = program_of_declaration(receiver).or_else(|| {
+                        declarations
+                            .get(variable)
+                            .and_then(|d| program_of_declaration(d))
+                    });
+                    calls.push(CallSite {
+                        file: file.to_path_buf(),
+                        line: line_of(source, item.start_byte),
+                        method: method.text.clone(),
+                        namespace: namespace.to_string(),
+                        program,
+                        test: is_test_file(file),
+                        code: normalize(&item.text),
+                    });
+                }
+            }
+        }
+        collect_calls(item, file, source, declarations, calls);
+    }
+}
+
+/// 把客户端调用对应到指令，写出 test_coverage.json
+pub fn run(args: &TestCoverageArgs) -> Result<(), Box<dyn Error>> {
+    if !args.artifacts.detector_enabled("test_coverage")? {
+        return Ok(());
+    }
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut programs = vec![];
+    let mut calls = vec![];
+    for (file, root) in &asts {
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        match file.extension().and_then(|ext| ext.to_str()) {
+            Some("rs") => collect_programs(root, file, &source, &mut programs),
+            Some("ts" | "tsx" | "js" | "jsx" | "mjs" | "cjs") => {
+                let mut declarations = HashMap::new();
+                collect_declarations(root, &mut declarations);
+                collect_calls(root, file, &source, &declarations, &mut calls);
+            }
+            _ => {}
+        }
+    }
+
+    let mut instructions = vec![];
+    let mut matched = vec![false; calls.len()];
+    for program in &programs {
+        for (handler, _) in &program.handlers {
+            let indices: Vec<usize> = calls
+                .iter()
+                .enumerate()
+                .filter(|(_, call)| {
+                    snake_case(&call.method) == handler.function
+                        && call.program.as_ref().is_none_or(|p| *p == program.name)
+                })
+                .map(|(i, _)| i)
+                .collect();
+            for &i in &indices {
+                matched[i] = true;
+            }
+            instructions.push(InstructionCoverage {
+                instruction: format!("{}::{}", program.name, handler.function),
+                file: handler.file.clone(),
+                line: handler.line,
+                test_calls: indices.iter().filter(|&&i| calls[i].test).count(),
+                calls: indices,
+            });
+        }
+    }
+    let untested: Vec<String> = instructions
+        .iter()
+        .filter(|i| i.test_calls == 0)
+        .map(|i| i.instruction.clone())
+        .collect();
+    let unmatched: Vec<usize> = (0..calls.len()).filter(|&i| !matched[i]).collect();
+    for instruction in instructions.iter().filter(|i| i.test_calls == 0) {
+        warn!(
+            instruction = %instruction.instruction,
+            file = %instruction.file.display(),
+            line = instruction.line,
+            "测试中没有调用这条指令"
+        );
+    }
+
+    let report = TestCoverageReport {
+        metadata: TestCoverageMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        untested,
+        instructions,
+        unmatched,
+        calls,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(TEST_COVERAGE_FILE_NAME));
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    info!(
+        instructions = report.instructions.len(),
+        untested = report.untested.len(),
+        calls = report.calls.len(),
+        test_calls = report.calls.iter().filter(|c| c.test).count(),
+        output = %output.display(),
+        "已写出测试覆盖"
+    );
+    Ok(())
+}
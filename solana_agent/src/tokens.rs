@@ -0,0 +1,597 @@
+// tokens.rs
+//
+// agent tokens：从处理函数中提取对 SPL Token 程序的 CPI (transfer、mint_to、burn、set_authority、close_account 及其 _checked 版本)
+// 以及 system_program 的 SOL 转账，得到每条指令在哪些代币账户和 mint 之间移动资产
+// 支持 Anchor 的 CPI 账户结构体 (token::Transfer { from, to, authority } 等) 和 spl_token::instruction::X(..) 的原始调用
+// 输出 tokens.json (操作清单和图) 以及 tokens.dot，用于画出整个协议的资产流向
+
+use crate::config::ArtifactsArgs;
+use crate::idl::collect_programs;
+use crate::manifest::now_rfc3339;
+use crate::symbols::{child, definition_name, line_of, load_asts, normalize, AstNode, CallGraph};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// 输出文件名，默认位于产物目录下
+const TOKENS_FILE_NAME: &str = "tokens.json";
+const TOKENS_DOT_FILE_NAME: &str = "tokens.dot";
+
+/// CPI 账户结构体的字段 -> 角色
+type FieldRoles = &'static [(&'static str, Role)];
+
+/// Anchor CPI 账户结构体 -> (操作, 字段 -> 角色)
+const ANCHOR_ACCOUNTS: &[(&str, &str, FieldRoles)] = &[
+    (
+        "Transfer",
+        "transfer",
+        &[
+            ("from", Role::Source),
+            ("to", Role::Destination),
+            ("authority", Role::Authority),
+        ],
+    ),
+    (
+        "TransferChecked",
+        "transfer_checked",
+        &[
+            ("from", Role::Source),
+            ("mint", Role::Mint),
+            ("to", Role::Destination),
+            ("authority", Role::Authority),
+        ],
+    ),
+    (
+        "MintTo",
+        "mint_to",
+        &[
+            ("mint", Role::Mint),
+            ("to", Role::Destination),
+            ("authority", Role::Authority),
+        ],
+    ),
+    (
+        "Burn",
+        "burn",
+        &[
+            ("mint", Role::Mint),
+            ("from", Role::Source),
+            ("authority", Role::Authority),
+        ],
+    ),
+    (
+        "SetAuthority",
+        "set_authority",
+        &[
+            ("current_authority", Role::Authority),
+            ("account_or_mint", Role::Account),
+        ],
+    ),
+    (
+        "CloseAccount",
+        "close_account",
+        &[
+            ("account", Role::Account),
+            ("destination", Role::Destination),
+            ("authority", Role::Authority),
+        ],
+    ),
+];
+
+/// spl_token::instruction 中的函数 -> 各位置参数的角色 (第一个参数是程序ID)
+const RAW_INSTRUCTIONS: &[(&str, &[Role])] = &[
+    (
+        "transfer",
+        &[Role::Source, Role::Destination, Role::Authority],
+    ),
+    (
+        "transfer_checked",
+        &[Role::Source, Role::Mint, Role::Destination, Role::Authority],
+    ),
+    ("mint_to", &[Role::Mint, Role::Destination, Role::Authority]),
+    (
+        "mint_to_checked",
+        &[Role::Mint, Role::Destination, Role::Authority],
+    ),
+    ("burn", &[Role::Source, Role::Mint, Role::Authority]),
+    ("burn_checked", &[Role::Source, Role::Mint, Role::Authority]),
+    (
+        "set_authority",
+        &[
+            Role::Account,
+            Role::NewAuthority,
+            Role::Other,
+            Role::Authority,
+        ],
+    ),
+    (
+        "close_account",
+        &[Role::Account, Role::Destination, Role::Authority],
+    ),
+];
+
+/// `agent tokens` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct TokensArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 输出文件，默认为产物目录下的 tokens.json；DOT 文件写在同一目录下
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// 账户在一次操作中的角色
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum Role {
+    Source,
+    Destination,
+    Mint,
+    Authority,
+    /// set_authority 修改的账户或 mint，close_account 关闭的账户
+    Account,
+    NewAuthority,
+    /// 不是账户的参数
+    Other,
+}
+
+/// 被调用的程序
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Program {
+    Token,
+    /// token_2022 或 token_interface
+    Token2022,
+    System,
+}
+
+/// 一次代币操作
+#[derive(Serialize, Debug)]
+struct TokenOperation {
+    program: Program,
+    operation: &'static str,
+    file: PathBuf,
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function: Option<String>,
+    /// 角色 -> 账户，去掉了 ctx.accounts. 和 .to_account_info() 等
+    accounts: BTreeMap<Role, String>,
+    /// 是否带 PDA 签名 (CpiContext::new_with_signer / invoke_signed)
+    signed: bool,
+    /// 经由处理函数或其调用的函数执行这次操作的指令
+    instructions: Vec<String>,
+    code: String,
+}
+
+/// 图中的节点：代币账户、mint、只参与 SOL 转账的账户或 authority
+#[derive(Serialize, Debug)]
+struct GraphNode {
+    id: String,
+    kind: &'static str,
+}
+
+/// 图中的边：资产从 source 流向 target，或者 authority 修改了 target 的权限
+#[derive(Serialize, Debug)]
+struct GraphEdge {
+    source: String,
+    target: String,
+    program: Program,
+    operation: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instruction: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    authority: Option<String>,
+    /// 在 operations 中的下标
+    operation_index: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct TokenGraph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+/// tokens.json 的顶层结构
+#[derive(Serialize, Debug)]
+struct TokensReport {
+    metadata: TokensMetadata,
+    operations: Vec<TokenOperation>,
+    graph: TokenGraph,
+}
+
+#[derive(Serialize, Debug)]
+struct TokensMetadata {
+    tool: &'static str,
+    tool_version: &'static str,
+    generated_at: String,
+}
+
+/// 把账户表达式化简为账户名，例如 `ctx.accounts.vault.to_account_info()` -> `vault`
+pub fn account_name(expr: &str) -> String {
+    let mut name = expr.replace(char::is_whitespace, "");
+    loop {
+        let before = name.len();
+        for prefix in ["&mut", "&", "*", "ctx.accounts.", "self.", "accounts."] {
+            if let Some(rest) = name.strip_prefix(prefix) {
+                name = rest.to_string();
+            }
+        }
+        for suffix in [
+            ".to_account_info()",
+            ".clone()",
+            ".as_ref()",
+            ".key()",
+            ".key",
+            ".to_owned()",
+        ] {
+            if let Some(rest) = name.strip_suffix(suffix) {
+                name = rest.to_string();
+            }
+        }
+        if let Some(inner) = name.strip_prefix("Some(").and_then(|n| n.strip_suffix(')')) {
+            name = inner.to_string();
+        }
+        if name.len() == before {
+            return name;
+        }
+    }
+}
+
+/// Anchor CPI 账户结构体中会被 CPI 写入的字段 (转出、转入、mint 和被关闭或修改权限的账户)
+pub fn written_fields(struct_name: &str) -> Option<Vec<&'static str>> {
+    let (_, _, fields) = ANCHOR_ACCOUNTS.iter().find(|(n, ..)| *n == struct_name)?;
+    Some(
+        fields
+            .iter()
+            .filter(|(_, role)| *role != Role::Authority)
+            .map(|(field, _)| *field)
+            .collect(),
+    )
+}
+
+/// 路径对应的程序
+fn program_of(path: &str) -> Program {
+    if path.contains("token_2022") || path.contains("token_interface") {
+        Program::Token2022
+    } else if path.contains("system_program") || path.contains("system_instruction") {
+        Program::System
+    } else {
+        Program::Token
+    }
+}
+
+/// 当前所在的位置
+struct Scope<'a> {
+    file: &'a Path,
+    source: &'a str,
+    function: Option<&'a str>,
+    /// 所在函数是否带 PDA 签名
+    signed: bool,
+}
+
+/// 收集 AST 中的代币操作
+fn visit(node: &AstNode, scope: &Scope, operations: &mut Vec<TokenOperation>) {
+    for item in &node.children {
+        match item.kind.as_str() {
+            "struct_expression" => visit_struct(item, scope, operations),
+            "call_expression" => visit_call(item, scope, operations),
+            _ => {}
+        }
+        match item.kind.as_str() {
+            "function_item" => {
+                let text = normalize(&item.text);
+                let scope = Scope {
+                    function: definition_name(item).map(|n| n.text.as_str()),
+                    signed: text.contains("with_signer") || text.contains("invoke_signed"),
+                    ..*scope
+                };
+                visit(item, &scope, operations);
+            }
+            _ => visit(item, scope, operations),
+        }
+    }
+}
+
+/// Anchor 的 CPI 账户结构体，例如 `token::Transfer { from, to, authority }`
+fn visit_struct(item: &AstNode, scope: &Scope, operations: &mut Vec<TokenOperation>) {
+    let Some(path) = item.children.first().map(|t| normalize(&t.text)) else {
+        return;
+    };
+    let name = path.rsplit("::").next().unwrap_or(&path);
+    let Some((_, operation, fields)) = ANCHOR_ACCOUNTS.iter().find(|(n, ..)| *n == name) else {
+        return;
+    };
+    let mut accounts = BTreeMap::new();
+    for field in child(item, "field_initializer_list")
+        .into_iter()
+        .flat_map(|list| &list.children)
+    {
+        let (key, value) = match field.kind.as_str() {
+            "field_initializer" => (
+                child(field, "field_identifier").map_or("", |f| f.text.as_str()),
+                field.children.last().map_or("", |v| v.text.as_str()),
+            ),
+            "shorthand_field_initializer" => (field.text.as_str(), field.text.as_str()),
+            _ => continue,
+        };
+        if let Some((_, role)) = fields.iter().find(|(f, _)| *f == key) {
+            accounts.insert(*role, account_name(value));
+        }
+    }
+    // system_program::Transfer 只有 from 和 to，没有 authority
+    let program = match program_of(&path) {
+        Program::Token if name == "Transfer" && !accounts.contains_key(&Role::Authority) => {
+            Program::System
+        }
+        program => program,
+    };
+    push(item, scope, program, operation, accounts, operations);
+}
+
+/// spl_token::instruction::X(..) 以及 system_instruction::transfer(..)
+fn visit_call(call: &AstNode, scope: &Scope, operations: &mut Vec<TokenOperation>) {
+    let Some(callee) = call.children.first().map(|c| normalize(&c.text)) else {
+        return;
+    };
+    let Some((path, name)) = callee.rsplit_once("::") else {
+        return;
+    };
+    let args: Vec<&AstNode> = child(call, "arguments")
+        .into_iter()
+        .flat_map(|a| &a.children)
+        .filter(|a| {
+            !matches!(
+                a.kind.as_str(),
+                "(" | ")" | "," | "line_comment" | "block_comment"
+            )
+        })
+        .collect();
+    let program = program_of(path);
+    if program == Program::System {
+        // system_instruction::transfer(from, to, lamports)
+        if name == "transfer" && path.ends_with("system_instruction") && args.len() >= 2 {
+            let accounts = BTreeMap::from([
+                (Role::Source, account_name(&args[0].text)),
+                (Role::Destination, account_name(&args[1].text)),
+            ]);
+            push(call, scope, program, "transfer", accounts, operations);
+        }
+        return;
+    }
+    if !path.ends_with("instruction") {
+        return;
+    }
+    let Some((operation, roles)) = RAW_INSTRUCTIONS.iter().find(|(n, _)| *n == name) else {
+        return;
+    };
+    let accounts = roles
+        .iter()
+        .zip(args.iter().skip(1))
+        .filter(|(role, _)| **role != Role::Other)
+        .map(|(role, arg)| (*role, account_name(&arg.text)))
+        .collect();
+    push(call, scope, program, operation, accounts, operations);
+}
+
+fn push(
+    node: &AstNode,
+    scope: &Scope,
+    program: Program,
+    operation: &'static str,
+    accounts: BTreeMap<Role, String>,
+    operations: &mut Vec<TokenOperation>,
+) {
+    operations.push(TokenOperation {
+        program,
+        operation,
+        file: scope.file.to_path_buf(),
+        line: line_of(scope.source, node.start_byte),
+        function: scope.function.map(String::from),
+        accounts,
+        signed: scope.signed,
+        instructions: vec![],
+        code: normalize(&node.text),
+    });
+}
+
+/// 操作中资产流动的方向 (或权限修改的方向)
+fn flow(operation: &TokenOperation) -> Option<(&str, &str)> {
+    let role = |r| operation.accounts.get(&r).map(String::as_str);
+    match operation.operation {
+        "transfer" | "transfer_checked" => role(Role::Source).zip(role(Role::Destination)),
+        "mint_to" | "mint_to_checked" => role(Role::Mint).zip(role(Role::Destination)),
+        "burn" | "burn_checked" => role(Role::Source).zip(role(Role::Mint)),
+        "close_account" => role(Role::Account).zip(role(Role::Destination)),
+        "set_authority" => role(Role::Authority).zip(role(Role::Account)),
+        _ => None,
+    }
+}
+
+/// 账户节点的种类
+fn account_kind(operations: &[TokenOperation], account: &str) -> &'static str {
+    let has_role = |role, system| {
+        operations.iter().any(|o| {
+            (o.program == Program::System) == system
+                && o.accounts.get(&role).is_some_and(|a| a == account)
+        })
+    };
+    if has_role(Role::Mint, false) {
+        "mint"
+    } else if has_role(Role::Source, false)
+        || has_role(Role::Destination, false)
+        || has_role(Role::Account, false)
+    {
+        "token_account"
+    } else if has_role(Role::Source, true) || has_role(Role::Destination, true) {
+        // 只参与 SOL 转账的账户
+        "system_account"
+    } else {
+        "authority"
+    }
+}
+
+/// 由操作清单构造图：每个账户一个节点，每条 (指令, 操作) 一条边
+fn build_graph(operations: &[TokenOperation]) -> TokenGraph {
+    let mut nodes: BTreeMap<String, &'static str> = BTreeMap::new();
+    let mut edges = vec![];
+    for (index, operation) in operations.iter().enumerate() {
+        let Some((source, target)) = flow(operation) else {
+            continue;
+        };
+        for account in [source, target] {
+            nodes
+                .entry(account.to_string())
+                .or_insert_with(|| account_kind(operations, account));
+        }
+        let instructions: Vec<Option<String>> = match operation.instructions.as_slice() {
+            [] => vec![None],
+            instructions => instructions.iter().cloned().map(Some).collect(),
+        };
+        for instruction in instructions {
+            edges.push(GraphEdge {
+                source: source.to_string(),
+                target: target.to_string(),
+                program: operation.program,
+                operation: operation.operation,
+                instruction,
+                authority: (operation.operation != "set_authority")
+                    .then(|| operation.accounts.get(&Role::Authority).cloned())
+                    .flatten(),
+                operation_index: index,
+            });
+        }
+    }
+    TokenGraph {
+        nodes: nodes
+            .into_iter()
+            .map(|(id, kind)| GraphNode { id, kind })
+            .collect(),
+        edges,
+    }
+}
+
+/// 把图写成 DOT：mint 为菱形，代币账户为椭圆，其余账户为方框；set_authority 用虚线
+fn to_dot(graph: &TokenGraph) -> String {
+    let mut dot = String::from("digraph tokens {\n  rankdir=LR;\n");
+    for node in &graph.nodes {
+        let shape = match node.kind {
+            "mint" => "diamond",
+            "token_account" => "ellipse",
+            _ => "box",
+        };
+        let _ = writeln!(dot, "  {:?} [shape={}];", node.id, shape);
+    }
+    for edge in &graph.edges {
+        let operation = match edge.program {
+            Program::System => format!("{} (SOL)", edge.operation),
+            _ => edge.operation.to_string(),
+        };
+        let mut label = match &edge.instruction {
+            Some(instruction) => format!("{}: {}", instruction, operation),
+            None => operation,
+        };
+        if let Some(authority) = &edge.authority {
+            let _ = write!(label, "\n(authority = {})", authority);
+        }
+        let style = if edge.operation == "set_authority" {
+            ", style=dashed"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            dot,
+            "  {:?} -> {:?} [label={:?}{}];",
+            edge.source, edge.target, label, style
+        );
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// 提取代币操作，按指令归属，写出 tokens.json 和 tokens.dot
+pub fn run(args: &TokensArgs) -> Result<(), Box<dyn Error>> {
+    let artifacts_dir = args.artifacts.artifacts_dir()?;
+    let project = &args.artifacts.project;
+    let asts = load_asts(&artifacts_dir)?;
+    if asts.is_empty() {
+        return Err(format!(
+            "'{}' 中没有AST，请先运行 agent analyze",
+            artifacts_dir.display()
+        )
+        .into());
+    }
+
+    let mut operations = vec![];
+    let mut calls = CallGraph::default();
+    let mut programs = vec![];
+    for (file, root) in &asts {
+        if file.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = fs::read_to_string(project.join(file)).unwrap_or_default();
+        let scope = Scope {
+            file,
+            source: &source,
+            function: None,
+            signed: false,
+        };
+        visit(root, &scope, &mut operations);
+        calls.add_file(file, root);
+        collect_programs(root, file, &source, &mut programs);
+    }
+
+    // (文件, 函数名) -> 在其中执行的操作
+    let mut by_function: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+    for (index, operation) in operations.iter().enumerate() {
+        if let Some(function) = &operation.function {
+            by_function
+                .entry((operation.file.clone(), function.clone()))
+                .or_default()
+                .push(index);
+        }
+    }
+    for program in &programs {
+        for (handler, _) in &program.handlers {
+            let instruction = format!("{}::{}", program.name, handler.function);
+            for key in calls.reachable(&handler.file, &handler.function) {
+                for &index in by_function.get(&key).into_iter().flatten() {
+                    if !operations[index].instructions.contains(&instruction) {
+                        operations[index].instructions.push(instruction.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    let graph = build_graph(&operations);
+    let report = TokensReport {
+        metadata: TokensMetadata {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            generated_at: now_rfc3339(),
+        },
+        operations,
+        graph,
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| artifacts_dir.join(TOKENS_FILE_NAME));
+    let dot_output = output.with_file_name(TOKENS_DOT_FILE_NAME);
+    fs::write(&output, serde_json::to_string_pretty(&report)?)?;
+    fs::write(&dot_output, to_dot(&report.graph))?;
+    info!(
+        operations = report.operations.len(),
+        accounts = report.graph.nodes.len(),
+        edges = report.graph.edges.len(),
+        output = %output.display(),
+        dot = %dot_output.display(),
+        "已写出代币操作图"
+    );
+    Ok(())
+}
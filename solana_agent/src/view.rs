@@ -0,0 +1,222 @@
+// view.rs
+
+use crate::config::ArtifactsArgs;
+use crate::merge::{graph_key, load_merged, MergedGraph};
+use crate::sample::{self, SampleArgs};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, PathBuf};
+use tracing::{debug, info, warn};
+
+/// 浏览器界面，编译时嵌入可执行文件
+const INDEX_HTML: &str = include_str!("view.html");
+
+/// `agent view` 的命令行参数
+#[derive(clap::Args, Debug)]
+pub struct ViewArgs {
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    /// 监听的地址
+    #[arg(long, default_value = "127.0.0.1")]
+    bind: String,
+
+    /// 监听的端口
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
+
+    #[command(flatten)]
+    sample: SampleArgs,
+}
+
+/// 服务器的全部状态：启动时读入的合并图
+struct Viewer {
+    project: PathBuf,
+    graph: MergedGraph,
+    /// 允许通过 /api/source 读取的源文件，只包含图中出现过的文件
+    sources: HashSet<PathBuf>,
+}
+
+impl Viewer {
+    /// GET /api/functions：所有函数图的列表
+    fn functions(&self) -> Value {
+        let mut functions: BTreeMap<&str, (usize, &str, Value)> = BTreeMap::new();
+        for node in &self.graph.nodes {
+            let entry = functions.entry(graph_key(&node.id)).or_insert((
+                0,
+                node.function.as_str(),
+                json!(node.layer),
+            ));
+            entry.0 += 1;
+        }
+        functions
+            .into_iter()
+            .map(|(key, (nodes, function, layer))| {
+                json!({ "key": key, "function": function, "layer": layer, "nodes": nodes })
+            })
+            .collect()
+    }
+
+    /// GET /api/graph?key=...：一个函数图的节点、函数内的边，以及它调用的其他函数
+    fn function_graph(&self, key: &str) -> Value {
+        let nodes: Vec<_> = self
+            .graph
+            .nodes
+            .iter()
+            .filter(|n| graph_key(&n.id) == key)
+            .collect();
+        let ids: HashSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+        let edges: Vec<_> = self
+            .graph
+            .edges
+            .iter()
+            .filter(|e| ids.contains(e.source.as_str()) && ids.contains(e.target.as_str()))
+            .collect();
+        let links: Vec<_> = self
+            .graph
+            .edges
+            .iter()
+            .filter(|e| ids.contains(e.source.as_str()) && !ids.contains(e.target.as_str()))
+            .map(|e| json!({ "source": e.source, "kind": e.kind, "target": graph_key(&e.target) }))
+            .collect();
+        json!({ "key": key, "nodes": nodes, "edges": edges, "links": links })
+    }
+
+    /// GET /api/source?file=...：源文件内容，用于跳转到源码
+    fn source(&self, file: &str) -> Option<String> {
+        let path = PathBuf::from(file);
+        let safe = path.components().all(|c| matches!(c, Component::Normal(_)));
+        if !safe || !self.sources.contains(&path) {
+            return None;
+        }
+        fs::read_to_string(self.project.join(path)).ok()
+    }
+
+    /// 处理一个HTTP请求，返回 (状态行, Content-Type, 响应体)
+    fn route(&self, target: &str) -> (&'static str, &'static str, String) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let param = |name: &str| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| percent_decode(value))
+        };
+        const JSON: &str = "application/json; charset=utf-8";
+        match path {
+            "/" | "/index.html" => ("200 OK", "text/html; charset=utf-8", INDEX_HTML.to_string()),
+            "/api/functions" => ("200 OK", JSON, self.functions().to_string()),
+            "/api/graph" => match param("key") {
+                Some(key) => ("200 OK", JSON, self.function_graph(&key).to_string()),
+                None => (
+                    "400 Bad Request",
+                    JSON,
+                    r#"{"error":"缺少 key 参数"}"#.to_string(),
+                ),
+            },
+            "/api/source" => match param("file").and_then(|file| self.source(&file)) {
+                Some(content) => ("200 OK", "text/plain; charset=utf-8", content),
+                None => (
+                    "404 Not Found",
+                    JSON,
+                    r#"{"error":"无法读取该源文件"}"#.to_string(),
+                ),
+            },
+            _ => (
+                "404 Not Found",
+                JSON,
+                r#"{"error":"not found"}"#.to_string(),
+            ),
+        }
+    }
+
+    /// 读取请求行并写回响应；只支持 GET
+    fn handle(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // 丢弃剩余的请求头
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (status, content_type, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some(target)) => self.route(target),
+            _ => ("405 Method Not Allowed", "text/plain", String::new()),
+        };
+        debug!(request = request_line.trim(), status, "HTTP请求");
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            content_type,
+            body.len()
+        )?;
+        stream.write_all(body.as_bytes())
+    }
+}
+
+/// 解码URL查询参数中的 `%XX` 和 `+`
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// 启动本地HTTP服务器，在浏览器中交互式地查看生成的图
+pub fn run(args: &ViewArgs) -> Result<(), Box<dyn Error>> {
+    let (_, mut graph) = load_merged(&args.artifacts)?;
+    sample::apply(&args.sample, &mut graph);
+    let sources = graph
+        .nodes
+        .iter()
+        .filter_map(|n| n.span.as_ref())
+        .map(|span| span.file.clone())
+        .collect();
+    let viewer = Viewer {
+        project: args.artifacts.project.clone(),
+        graph,
+        sources,
+    };
+
+    let listener = TcpListener::bind((args.bind.as_str(), args.port))
+        .map_err(|e| format!("无法监听 {}:{}: {}", args.bind, args.port, e))?;
+    info!(url = %format!("http://{}:{}/", args.bind, args.port), "可视化服务已启动，按 Ctrl+C 退出");
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = viewer.handle(stream) {
+                    warn!(error = %e, "处理请求失败");
+                }
+            }
+            Err(e) => warn!(error = %e, "接受连接失败"),
+        }
+    }
+    Ok(())
+}
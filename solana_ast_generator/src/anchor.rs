@@ -0,0 +1,530 @@
+// anchor.rs
+//
+// Anchor 结构提取：从 Rust 源文件中识别 declare_id!、#[program] 模块中的指令处理函数、#[derive(Accounts)] 账户结构体
+// 及其字段上 #[account(...)] 的约束表达式、#[account] 数据账户类型，按crate写出 anchor.json (位于crate目录对应的输出目录下)
+// CFG的函数命名、安全规则等后续阶段都需要这份指令 -> 账户结构体 -> 约束的对应关系，不必再各自解析属性
+
+use serde::{Deserialize, Serialize};
+use solana_ast_generator::{file_id, node_text_lossy};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tree_sitter::{Node, Tree};
+
+/// 输出文件名，位于crate目录对应的输出目录下
+pub const ANCHOR_FILE_NAME: &str = "anchor.json";
+
+/// 一组文件中的 Anchor 结构；每一项都带有所在的源文件 (相对于输入目录)，行号从1开始，
+/// id 为节点在 .ast.json 中的ID
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Anchor {
+    pub declare_ids: Vec<DeclaredId>,
+    pub programs: Vec<Program>,
+    /// #[derive(Accounts)] 结构体
+    pub accounts: Vec<AccountsStruct>,
+    /// #[account] 数据账户类型
+    pub account_types: Vec<AccountType>,
+}
+
+/// declare_id!("...")
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeclaredId {
+    pub program_id: String,
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+/// #[program] 模块
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Program {
+    pub name: String,
+    pub file: PathBuf,
+    pub id: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub instructions: Vec<Instruction>,
+}
+
+/// 指令处理函数：#[program] 模块中的 pub fn
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Instruction {
+    pub name: String,
+    /// Context<T> 中的账户结构体 T
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accounts: Option<String>,
+    /// Context 之后的参数
+    pub args: Vec<Arg>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub returns: Option<String>,
+    pub id: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Arg {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+/// #[derive(Accounts)] 结构体
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountsStruct {
+    pub name: String,
+    pub file: PathBuf,
+    pub id: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// #[instruction(...)] 中声明的指令参数，约束表达式中可以引用
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instruction_args: Vec<String>,
+    pub fields: Vec<AccountField>,
+}
+
+/// 账户结构体的一个字段
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// #[account(...)] 中逗号分隔的约束，例如 "mut"、"has_one = owner"、"seeds = [b\"vault\", user.key().as_ref()]"；
+    /// 空白压缩为一个空格
+    pub constraints: Vec<String>,
+    pub line: usize,
+}
+
+/// #[account] 或 #[account(zero_copy)] 等标注的数据账户类型
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountType {
+    pub name: String,
+    pub file: PathBuf,
+    pub id: String,
+    pub line: usize,
+    /// #[account(...)] 的参数，例如 "zero_copy"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+/// anchor.json 的内容
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CrateAnchor {
+    /// crate目录 (Cargo.toml 所在的目录)，相对于输入目录；不在任何crate中的文件为 ""
+    #[serde(rename = "crate")]
+    pub crate_dir: PathBuf,
+    /// 第一个 declare_id! 给出的程序ID；按 feature 声明了多个时见 declare_ids
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program_id: Option<String>,
+    #[serde(flatten)]
+    pub anchor: Anchor,
+}
+
+impl Anchor {
+    pub fn is_empty(&self) -> bool {
+        self.declare_ids.is_empty()
+            && self.programs.is_empty()
+            && self.accounts.is_empty()
+            && self.account_types.is_empty()
+    }
+
+    pub fn extend(&mut self, other: Anchor) {
+        self.declare_ids.extend(other.declare_ids);
+        self.programs.extend(other.programs);
+        self.accounts.extend(other.accounts);
+        self.account_types.extend(other.account_types);
+    }
+
+    /// 按源文件拆分，增量模式下复用的文件沿用上一次的记录
+    fn by_file(self) -> HashMap<PathBuf, Anchor> {
+        let mut files: HashMap<PathBuf, Anchor> = HashMap::new();
+        for item in self.declare_ids {
+            files
+                .entry(item.file.clone())
+                .or_default()
+                .declare_ids
+                .push(item);
+        }
+        for item in self.programs {
+            files
+                .entry(item.file.clone())
+                .or_default()
+                .programs
+                .push(item);
+        }
+        for item in self.accounts {
+            files
+                .entry(item.file.clone())
+                .or_default()
+                .accounts
+                .push(item);
+        }
+        for item in self.account_types {
+            files
+                .entry(item.file.clone())
+                .or_default()
+                .account_types
+                .push(item);
+        }
+        files
+    }
+
+    /// 按源文件所在的crate分组，每组按文件和行号排序
+    pub fn by_crate(self, input_dir: &Path) -> BTreeMap<PathBuf, CrateAnchor> {
+        let mut crates: BTreeMap<PathBuf, CrateAnchor> = BTreeMap::new();
+        let mut dirs: HashMap<PathBuf, PathBuf> = HashMap::new();
+        for (file, anchor) in self.by_file() {
+            let dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+            let crate_dir = dirs
+                .entry(dir)
+                .or_insert_with_key(|dir| crate_dir(input_dir, dir))
+                .clone();
+            crates
+                .entry(crate_dir.clone())
+                .or_insert_with(|| CrateAnchor {
+                    crate_dir,
+                    program_id: None,
+                    anchor: Anchor::default(),
+                })
+                .anchor
+                .extend(anchor);
+        }
+        for krate in crates.values_mut() {
+            let anchor = &mut krate.anchor;
+            anchor
+                .declare_ids
+                .sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+            anchor
+                .programs
+                .sort_by(|a, b| (&a.file, a.start_line).cmp(&(&b.file, b.start_line)));
+            anchor
+                .accounts
+                .sort_by(|a, b| (&a.file, a.start_line).cmp(&(&b.file, b.start_line)));
+            anchor
+                .account_types
+                .sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+            krate.program_id = anchor.declare_ids.first().map(|d| d.program_id.clone());
+        }
+        crates
+    }
+}
+
+/// 包含该目录 (相对于输入目录) 的最近的crate目录，没有时为输入目录本身
+fn crate_dir(input_dir: &Path, dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|ancestor| input_dir.join(ancestor).join("Cargo.toml").is_file())
+        .unwrap_or(Path::new(""))
+        .to_path_buf()
+}
+
+/// 读取上一次运行写出的 anchor.json (路径相对于输出目录)，按源文件索引
+pub fn load_previous<'a>(
+    output_dir: &Path,
+    paths: impl Iterator<Item = &'a Path>,
+) -> HashMap<PathBuf, Anchor> {
+    let mut previous = Anchor::default();
+    for path in paths {
+        if let Some(krate) = fs::read_to_string(output_dir.join(path))
+            .ok()
+            .and_then(|content| serde_json::from_str::<CrateAnchor>(&content).ok())
+        {
+            previous.extend(krate.anchor);
+        }
+    }
+    previous.by_file()
+}
+
+/// 收集一个 Rust 源文件中的 Anchor 结构
+pub fn collect(tree: &Tree, source: &[u8], file: &Path) -> Anchor {
+    let mut collector = Collector {
+        source,
+        file,
+        file_id: file_id(&file.to_string_lossy()),
+        next_index: 0,
+        program: None,
+        anchor: Anchor::default(),
+    };
+    collector.visit(tree.root_node());
+    collector.anchor
+}
+
+/// 按先序遍历整棵树；节点的序号与 .ast.json 中节点ID的序号相同
+struct Collector<'a> {
+    source: &'a [u8],
+    file: &'a Path,
+    file_id: String,
+    next_index: usize,
+    /// 正在遍历的 #[program] 模块的 declaration_list
+    program: Option<usize>,
+    anchor: Anchor,
+}
+
+impl Collector<'_> {
+    fn visit(&mut self, node: Node) {
+        let id = format!("{}:{}", self.file_id, self.next_index);
+        self.next_index += 1;
+
+        // 进入 #[program] 模块时保存外层的状态，离开时恢复
+        let mut entered = None;
+        match node.kind() {
+            "macro_invocation" => self.declare_id(node),
+            "mod_item" if has_attribute(node, self.source, "program") => {
+                if let Some(body) = node.child_by_field_name("body") {
+                    entered = Some(self.program.replace(body.id()));
+                    self.anchor.programs.push(Program {
+                        name: field_text(node, "name", self.source),
+                        file: self.file.to_path_buf(),
+                        id,
+                        start_line: node.start_position().row + 1,
+                        end_line: node.end_position().row + 1,
+                        instructions: vec![],
+                    });
+                }
+            }
+            "function_item" if self.is_instruction(node) => {
+                let instruction = instruction(node, id, self.source);
+                if let Some(program) = self.anchor.programs.last_mut() {
+                    program.instructions.push(instruction);
+                }
+            }
+            "struct_item" => self.structure(node, id),
+            _ => {}
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.visit(child);
+        }
+        if let Some(outer) = entered {
+            self.program = outer;
+        }
+    }
+
+    /// 直接位于 #[program] 模块中的 pub fn
+    fn is_instruction(&self, node: Node) -> bool {
+        self.program.is_some()
+            && node.parent().map(|p| p.id()) == self.program
+            && node
+                .child(0)
+                .is_some_and(|c| c.kind() == "visibility_modifier")
+    }
+
+    fn declare_id(&mut self, node: Node) {
+        let Some(name) = node.child_by_field_name("macro") else {
+            return;
+        };
+        let name = text(name, self.source);
+        if name != "declare_id" && !name.ends_with("::declare_id") {
+            return;
+        }
+        let mut cursor = node.walk();
+        let program_id = node
+            .children(&mut cursor)
+            .filter(|c| c.kind() == "token_tree")
+            .find_map(|tokens| {
+                let mut cursor = tokens.walk();
+                let found = tokens
+                    .children(&mut cursor)
+                    .find(|c| c.kind() == "string_literal");
+                found
+            });
+        if let Some(program_id) = program_id {
+            self.anchor.declare_ids.push(DeclaredId {
+                program_id: text(program_id, self.source).trim_matches('"').to_string(),
+                file: self.file.to_path_buf(),
+                line: node.start_position().row + 1,
+            });
+        }
+    }
+
+    /// #[derive(Accounts)] 结构体或 #[account] 数据账户类型
+    fn structure(&mut self, node: Node, id: String) {
+        let attributes = attributes(node);
+        let name = field_text(node, "name", self.source);
+        let derives_accounts = attributes
+            .iter()
+            .filter(|a| attribute_name(**a, self.source) == "derive")
+            .flat_map(|a| arguments(*a, self.source))
+            .any(|derived| derived == "Accounts" || derived.ends_with("::Accounts"));
+        if derives_accounts {
+            let instruction_args = attributes
+                .iter()
+                .filter(|a| attribute_name(**a, self.source) == "instruction")
+                .flat_map(|a| arguments(*a, self.source))
+                .collect();
+            self.anchor.accounts.push(AccountsStruct {
+                name,
+                file: self.file.to_path_buf(),
+                id,
+                start_line: node.start_position().row + 1,
+                end_line: node.end_position().row + 1,
+                instruction_args,
+                fields: fields(node, self.source),
+            });
+        } else if let Some(account) = attributes
+            .iter()
+            .find(|a| attribute_name(**a, self.source) == "account")
+        {
+            self.anchor.account_types.push(AccountType {
+                name,
+                file: self.file.to_path_buf(),
+                id,
+                line: node.start_position().row + 1,
+                options: arguments(*account, self.source),
+            });
+        }
+    }
+}
+
+fn text(node: Node, source: &[u8]) -> String {
+    node_text_lossy(node, source).into_owned()
+}
+
+fn field_text(node: Node, field: &str, source: &[u8]) -> String {
+    node.child_by_field_name(field)
+        .map(|n| text(n, source))
+        .unwrap_or_default()
+}
+
+/// 节点之前的属性 (attribute 节点，中间可以隔着注释)，按源代码顺序
+fn attributes(node: Node) -> Vec<Node> {
+    let mut attributes = vec![];
+    let mut previous = node.prev_sibling();
+    while let Some(sibling) = previous {
+        match sibling.kind() {
+            "attribute_item" => attributes.extend(sibling.named_child(0)),
+            "line_comment" | "block_comment" => {}
+            _ => break,
+        }
+        previous = sibling.prev_sibling();
+    }
+    attributes.reverse();
+    attributes
+}
+
+fn has_attribute(node: Node, source: &[u8], name: &str) -> bool {
+    attributes(node)
+        .into_iter()
+        .any(|a| attribute_name(a, source) == name)
+}
+
+/// 属性的路径，例如 #[account(mut)] -> "account"
+fn attribute_name(attribute: Node, source: &[u8]) -> String {
+    attribute
+        .named_child(0)
+        .map(|path| text(path, source))
+        .unwrap_or_default()
+}
+
+/// 属性括号中按顶层逗号分隔的参数，空白压缩为一个空格
+fn arguments(attribute: Node, source: &[u8]) -> Vec<String> {
+    let Some(tokens) = attribute.child_by_field_name("arguments") else {
+        return vec![];
+    };
+    let mut arguments = vec![];
+    let mut start: Option<Node> = None;
+    let mut end: Option<Node> = None;
+    let mut push = |start: Option<Node>, end: Option<Node>| {
+        if let (Some(start), Some(end)) = (start, end) {
+            let bytes = source
+                .get(start.start_byte()..end.end_byte())
+                .unwrap_or_default();
+            let argument = String::from_utf8_lossy(bytes)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            arguments.push(argument);
+        }
+    };
+    let count = tokens.child_count();
+    // 第一个和最后一个子节点是括号
+    for i in 1..count.saturating_sub(1) {
+        let Some(child) = tokens.child(i) else {
+            continue;
+        };
+        if child.kind() == "," {
+            push(start.take(), end.take());
+        } else {
+            start.get_or_insert(child);
+            end = Some(child);
+        }
+    }
+    push(start, end);
+    arguments
+}
+
+/// 指令处理函数：名字、Context<T> 中的账户结构体、其余参数和返回类型
+fn instruction(node: Node, id: String, source: &[u8]) -> Instruction {
+    let mut accounts = None;
+    let mut args = vec![];
+    if let Some(parameters) = node.child_by_field_name("parameters") {
+        let mut cursor = parameters.walk();
+        for parameter in parameters
+            .named_children(&mut cursor)
+            .filter(|p| p.kind() == "parameter")
+        {
+            let ty = parameter.child_by_field_name("type");
+            match ty.and_then(|ty| context_accounts(ty, source)) {
+                Some(context) if accounts.is_none() && args.is_empty() => accounts = Some(context),
+                _ => args.push(Arg {
+                    name: field_text(parameter, "pattern", source),
+                    ty: ty.map(|ty| text(ty, source)).unwrap_or_default(),
+                }),
+            }
+        }
+    }
+    Instruction {
+        name: field_text(node, "name", source),
+        accounts,
+        args,
+        returns: node
+            .child_by_field_name("return_type")
+            .map(|ty| text(ty, source)),
+        id,
+        start_line: node.start_position().row + 1,
+        end_line: node.end_position().row + 1,
+    }
+}
+
+/// Context<T> 或 Context<'_, '_, '_, 'info, T<'info>> 中的 T
+fn context_accounts(ty: Node, source: &[u8]) -> Option<String> {
+    if ty.kind() != "generic_type" {
+        return None;
+    }
+    let name = text(ty.child_by_field_name("type")?, source);
+    if name != "Context" && !name.ends_with("::Context") {
+        return None;
+    }
+    let arguments = ty.child_by_field_name("type_arguments")?;
+    let mut cursor = arguments.walk();
+    let accounts = arguments
+        .named_children(&mut cursor)
+        .filter(|a| a.kind() != "lifetime")
+        .last()?;
+    Some(match accounts.kind() {
+        "generic_type" => field_text(accounts, "type", source),
+        _ => text(accounts, source),
+    })
+}
+
+/// 账户结构体的字段及其 #[account(...)] 约束
+fn fields(node: Node, source: &[u8]) -> Vec<AccountField> {
+    let Some(body) = node.child_by_field_name("body") else {
+        return vec![];
+    };
+    let mut cursor = body.walk();
+    let fields = body
+        .named_children(&mut cursor)
+        .filter(|f| f.kind() == "field_declaration")
+        .map(|field| AccountField {
+            name: field_text(field, "name", source),
+            ty: field_text(field, "type", source),
+            constraints: attributes(field)
+                .into_iter()
+                .filter(|a| attribute_name(*a, source) == "account")
+                .flat_map(|a| arguments(a, source))
+                .collect(),
+            line: field.start_position().row + 1,
+        })
+        .collect();
+    fields
+}
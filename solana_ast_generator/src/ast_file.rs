@@ -0,0 +1,213 @@
+// ast_file.rs
+//
+// AST文件的编码与读写
+// --format msgpack/cbor：格式化的JSON写入和解析都慢，大量文件时改用二进制格式 (lib.rs.ast.msgpack、lib.rs.ast.cbor)
+// --format sexp：tree-sitter 的S表达式 (lib.rs.ast.sexp)，供语法调试和语料工具使用，只写不读
+// --compress：带文本的完整AST体积很大，压缩后写出 (lib.rs.ast.json.zst、lib.rs.ast.msgpack.gz)
+// 本工具中读取AST的地方 (--diff-base、增量模式下更新来源) 按后缀透明地解压和解码，CFG生成器也同样处理
+// --stream-above：很大的文件不在内存中建出整棵AST，边遍历语法树边写出JSON
+
+use clap::ValueEnum;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_ast_generator::{
+    write_json_streaming, AstOptions, Provenance, SerializableNode, StreamLimits, StreamStats,
+};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// AST文件的编码，与 .ast.<后缀> 对应
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    Msgpack,
+    Cbor,
+    Sexp,
+}
+
+impl Encoding {
+    /// 加在源文件路径之后的后缀
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Json => "ast.json",
+            Encoding::Msgpack => "ast.msgpack",
+            Encoding::Cbor => "ast.cbor",
+            Encoding::Sexp => "ast.sexp",
+        }
+    }
+
+    /// 编码一个AST；JSON为格式化的，MessagePack 以字段名为键 (省略的字段不影响读取)
+    pub fn encode(self, ast: &SerializableNode) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match self {
+            Encoding::Json => serde_json::to_vec_pretty(ast)?,
+            Encoding::Msgpack => rmp_serde::to_vec_named(ast)?,
+            Encoding::Cbor => {
+                let mut bytes = vec![];
+                ciborium::into_writer(ast, &mut bytes)?;
+                bytes
+            }
+            Encoding::Sexp => ast.to_sexp().into_bytes(),
+        })
+    }
+
+    fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, Box<dyn Error>> {
+        Ok(match self {
+            Encoding::Json => serde_json::from_slice(bytes)?,
+            Encoding::Msgpack => rmp_serde::from_slice(bytes)?,
+            Encoding::Cbor => ciborium::from_reader(bytes)?,
+            Encoding::Sexp => return Err("S表达式格式的AST不能读回".into()),
+        })
+    }
+}
+
+/// AST文件的压缩格式
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// 压缩率和速度都更好，推荐
+    Zstd,
+    /// 不支持 zstd 的环境中使用
+    Gzip,
+}
+
+impl Compression {
+    /// 加在AST文件的后缀 (.ast.json 等) 之后的后缀
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Zstd => "zst",
+            Compression::Gzip => "gz",
+        }
+    }
+
+    pub fn compress(self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match self {
+            Compression::Zstd => zstd::encode_all(bytes, 0)?,
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()?
+            }
+        })
+    }
+}
+
+/// 写出一个AST文件；压缩时在路径后加上压缩格式的后缀。返回实际写出的路径和内容
+pub fn write_ast(
+    path: &Path,
+    content: &[u8],
+    compression: Option<Compression>,
+) -> Result<(PathBuf, Vec<u8>), Box<dyn Error>> {
+    let (path, content) = match compression {
+        Some(compression) => {
+            let mut name = path.as_os_str().to_owned();
+            name.push(".");
+            name.push(compression.extension());
+            (PathBuf::from(name), compression.compress(content)?)
+        }
+        None => (path.to_path_buf(), content.to_vec()),
+    };
+    fs::write(&path, &content)?;
+    Ok((path, content))
+}
+
+/// 读取一个AST文件，按后缀解压 (.zst、.gz) 并解码 (.ast.json、.ast.msgpack、.ast.cbor)；S表达式无法读回
+pub fn read_ast(path: &Path) -> Result<Value, Box<dyn Error>> {
+    let (content, encoding, _) = read_encoded(path)?;
+    encoding
+        .decode(&content)
+        .map_err(|e| format!("无法解析 {}: {}", path.display(), e).into())
+}
+
+/// 把来源写入已有的AST文件的根节点，编码和压缩格式不变；返回写出的内容
+/// 增量模式下源文件未变化、只有 HEAD 不同时使用，不必重新解析
+pub fn rewrite_provenance(
+    path: &Path,
+    provenance: Option<&Provenance>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (content, encoding, compression) = read_encoded(path)?;
+    let mut root: SerializableNode = encoding
+        .decode(&content)
+        .map_err(|e| format!("无法解析 {}: {}", path.display(), e))?;
+    root.set_provenance(provenance.cloned());
+    let mut content = encoding.encode(&root)?;
+    if let Some(compression) = compression {
+        content = compression.compress(&content)?;
+    }
+    fs::write(path, &content)?;
+    Ok(content)
+}
+
+/// 解压后的AST文件内容，以及由后缀确定的编码和压缩格式
+type EncodedAst = (Vec<u8>, Encoding, Option<Compression>);
+
+/// 读取并解压一个AST文件
+fn read_encoded(path: &Path) -> Result<EncodedAst, Box<dyn Error>> {
+    let mut content = fs::read(path)?;
+    let mut name = path.to_string_lossy().into_owned();
+    let mut compression = None;
+    if let Some(stem) = name.strip_suffix(".zst") {
+        content = zstd::decode_all(content.as_slice())?;
+        name = stem.to_string();
+        compression = Some(Compression::Zstd);
+    } else if let Some(stem) = name.strip_suffix(".gz") {
+        let mut decompressed = vec![];
+        flate2::read::GzDecoder::new(content.as_slice()).read_to_end(&mut decompressed)?;
+        content = decompressed;
+        name = stem.to_string();
+        compression = Some(Compression::Gzip);
+    }
+    let encoding = [Encoding::Msgpack, Encoding::Cbor, Encoding::Sexp]
+        .into_iter()
+        .find(|e| name.ends_with(e.extension()))
+        .unwrap_or(Encoding::Json);
+    Ok((content, encoding, compression))
+}
+
+/// 流式写出一个JSON格式的AST，不压缩；同时计算写出内容的哈希，不必把文件读回
+pub fn write_ast_streaming(
+    path: &Path,
+    root: tree_sitter::Node,
+    source: &[u8],
+    relative_path: &str,
+    options: &AstOptions,
+    provenance: Option<&Provenance>,
+    limits: StreamLimits,
+) -> Result<(StreamStats, String), Box<dyn Error>> {
+    let mut writer = HashingWriter {
+        inner: BufWriter::new(File::create(path)?),
+        hasher: blake3::Hasher::new(),
+    };
+    let stats = write_json_streaming(
+        root,
+        source,
+        relative_path,
+        options,
+        provenance,
+        limits,
+        &mut writer,
+    )?;
+    writer.inner.flush()?;
+    Ok((stats, writer.hasher.finalize().to_hex().to_string()))
+}
+
+/// 写入的同时计算哈希
+struct HashingWriter<W> {
+    inner: W,
+    hasher: blake3::Hasher,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,113 @@
+// dedup.rs
+//
+// --content-addressed：许多工作区复制了相同的文件 (程序模板、vendored 的依赖等)，按路径存放时每份都要解析和写出一次。
+// 这种布局下AST按源文件内容的哈希存放在 objects/ 下，index.json 和 manifest.json 中由每个源文件指向它的AST；
+// 内容相同的文件只处理排在最前的一个，其余文件的记录 (符号、语法错误等) 在汇总时从它复制
+
+use rayon::prelude::*;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 按内容存放的AST所在的目录，位于输出目录下
+pub const OBJECTS_DIR: &str = "objects";
+
+/// 按内容存放的AST的路径 (相对于输出目录)，例如 objects/3f/3f9a….rs.ast.json
+/// 保留源文件的扩展名，CFG生成器等按扩展名识别语言的工具仍能使用
+pub fn object_path(source_hash: &str, source_path: &Path, extension: &str) -> PathBuf {
+    let mut name = OsString::from(source_hash);
+    if let Some(source_extension) = source_path.extension() {
+        name.push(".");
+        name.push(source_extension);
+    }
+    name.push(".");
+    name.push(extension);
+    Path::new(OBJECTS_DIR)
+        .join(&source_hash[..2.min(source_hash.len())])
+        .join(name)
+}
+
+/// 内容相同的文件：排在最前的文件 (处理的那个) -> 其余的文件，都相对于输入目录
+#[derive(Default, Debug)]
+pub struct Duplicates {
+    groups: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl Duplicates {
+    /// 对所有源文件计算内容哈希，返回需要处理的文件和重复的文件；内容和扩展名都相同才算重复
+    /// (同样内容的 .js 和 .ts 按不同的语法解析)。无法读取的文件照常处理，由处理时报告错误
+    pub fn find(files: &[PathBuf], input_dir: &Path) -> (Vec<PathBuf>, Duplicates) {
+        let hashes: Vec<Option<String>> =
+            files.par_iter().map(|path| hash_file(path).ok()).collect();
+        let mut first = HashMap::new();
+        let mut representatives = vec![];
+        let mut duplicates = Duplicates::default();
+        for (path, hash) in files.iter().zip(hashes) {
+            let Some(hash) = hash else {
+                representatives.push(path.clone());
+                continue;
+            };
+            match first.entry((hash, path.extension())) {
+                Entry::Vacant(entry) => {
+                    entry.insert(path);
+                    representatives.push(path.clone());
+                }
+                Entry::Occupied(entry) => duplicates
+                    .groups
+                    .entry(relative(entry.get(), input_dir))
+                    .or_default()
+                    .push(relative(path, input_dir)),
+            }
+        }
+        (representatives, duplicates)
+    }
+
+    /// 重复的文件数
+    pub fn count(&self) -> usize {
+        self.groups.values().map(Vec::len).sum()
+    }
+
+    /// 与该文件内容相同、未被处理的文件
+    pub fn of(&self, file: &Path) -> &[PathBuf] {
+        self.groups.get(file).map_or(&[], Vec::as_slice)
+    }
+
+    /// 为每个重复的文件复制处理过的文件的记录，`file` 取出记录中的源文件路径，不适用的记录返回 None
+    pub fn fan_out<T: Clone>(
+        &self,
+        items: &mut Vec<T>,
+        file: impl Fn(&mut T) -> Option<&mut PathBuf>,
+    ) {
+        if self.groups.is_empty() {
+            return;
+        }
+        let mut copies = vec![];
+        for item in items.iter_mut() {
+            let Some(duplicates) = file(item).and_then(|path| self.groups.get(path)) else {
+                continue;
+            };
+            for duplicate in duplicates {
+                let mut copy = item.clone();
+                if let Some(path) = file(&mut copy) {
+                    *path = duplicate.clone();
+                }
+                copies.push(copy);
+            }
+        }
+        items.extend(copies);
+    }
+}
+
+fn relative(path: &Path, input_dir: &Path) -> PathBuf {
+    path.strip_prefix(input_dir).unwrap_or(path).to_path_buf()
+}
+
+/// 与 content_hash 相同的哈希，流式读取，不把整个文件读入内存
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
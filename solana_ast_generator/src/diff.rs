@@ -0,0 +1,380 @@
+// diff.rs
+//
+// --diff-base：把本次生成的AST与另一次运行 (通常是升级前的版本) 的输出目录中的AST逐文件比较，写出 diff.json
+// 比较只看语法结构和记号的文本，忽略位置、空白和注释的变化，审计升级时只需查看语义上有变化的代码
+
+use crate::ast_file::read_ast;
+use serde::{Deserialize, Serialize};
+use solana_ast_generator::restore_text;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// 输出文件名，位于输出目录下
+pub const DIFF_FILE_NAME: &str = "diff.json";
+
+/// 函数定义的节点种类 (Rust、TypeScript/JavaScript、Python/Solidity/Move)
+const FUNCTION_KINDS: &[&str] = &[
+    "function_item",
+    "function_declaration",
+    "method_definition",
+    "function_definition",
+];
+
+/// 函数名的前缀取自这些外层节点的名字，例如 impl Vault 中的 deposit 为 Vault::deposit
+const CONTAINER_KINDS: &[&str] = &[
+    "impl_item",
+    "trait_item",
+    "mod_item",
+    "class_declaration",
+    "class_definition",
+    "contract_declaration",
+    "module_definition",
+];
+
+/// 不参与比较的节点种类
+const COMMENT_KINDS: &[&str] = &["line_comment", "block_comment", "comment"];
+
+/// 从 .ast.json 读取的节点，只保留比较需要的字段
+#[derive(Deserialize, Debug)]
+struct Node {
+    kind: String,
+    #[serde(default)]
+    field: Option<String>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    start_line: usize,
+    #[serde(default)]
+    end_line: usize,
+    children: Vec<Node>,
+    /// 种类、字段名、叶子节点的文本和子节点的哈希，读取后由 compute_hash 计算
+    #[serde(skip)]
+    hash: u64,
+}
+
+impl Node {
+    fn load(path: &Path) -> Result<Node, Box<dyn Error>> {
+        let mut value = read_ast(path)?;
+        // --no-text 生成的AST先还原文本
+        restore_text(&mut value);
+        let mut root: Node = serde_json::from_value(value)?;
+        root.compute_hash();
+        Ok(root)
+    }
+
+    fn is_comment(&self) -> bool {
+        COMMENT_KINDS.contains(&self.kind.as_str())
+    }
+
+    /// 参与比较的子节点
+    fn compared_children(&self) -> Vec<&Node> {
+        self.children.iter().filter(|c| !c.is_comment()).collect()
+    }
+
+    fn compute_hash(&mut self) {
+        let mut hasher = DefaultHasher::new();
+        self.kind.hash(&mut hasher);
+        self.field.hash(&mut hasher);
+        for child in &mut self.children {
+            child.compute_hash();
+            if !child.is_comment() {
+                child.hash.hash(&mut hasher);
+            }
+        }
+        if self.children.is_empty() {
+            self.text.hash(&mut hasher);
+        }
+        self.hash = hasher.finish();
+    }
+
+    /// 名字子节点 (字段名为 name，没有字段名的旧AST取第一个标识符) 的文本
+    fn name(&self) -> Option<&str> {
+        self.children
+            .iter()
+            .find(|c| c.field.as_deref() == Some("name"))
+            .or_else(|| {
+                self.children
+                    .iter()
+                    .find(|c| c.kind.ends_with("identifier"))
+            })
+            .map(|c| c.text.as_str())
+    }
+
+    /// impl 块以实现的类型命名
+    fn container_name(&self) -> Option<&str> {
+        self.children
+            .iter()
+            .find(|c| c.field.as_deref() == Some("type"))
+            .map(|c| c.text.as_str())
+            .or_else(|| self.name())
+    }
+}
+
+/// 文件中的所有函数，键为带外层名字的函数名；同名的函数依次加上 #2、#3
+fn functions(root: &Node) -> BTreeMap<String, &Node> {
+    fn visit<'a>(node: &'a Node, prefix: &str, out: &mut BTreeMap<String, &'a Node>) {
+        for child in &node.children {
+            if FUNCTION_KINDS.contains(&child.kind.as_str()) {
+                let name = format!("{}{}", prefix, child.name().unwrap_or("<anonymous>"));
+                let mut key = name.clone();
+                let mut n = 1;
+                while out.contains_key(&key) {
+                    n += 1;
+                    key = format!("{}#{}", name, n);
+                }
+                out.insert(key, child);
+                visit(child, prefix, out);
+            } else if CONTAINER_KINDS.contains(&child.kind.as_str()) {
+                let prefix = match child.container_name() {
+                    Some(name) => format!("{}{}::", prefix, name),
+                    None => prefix.to_string(),
+                };
+                visit(child, &prefix, out);
+            } else {
+                visit(child, prefix, out);
+            }
+        }
+    }
+    let mut out = BTreeMap::new();
+    visit(root, "", &mut out);
+    out
+}
+
+/// 文件或节点的变化类型
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// 变化的节点覆盖的行，从1开始
+#[derive(Serialize, Debug)]
+pub struct Lines {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl From<&Node> for Lines {
+    fn from(node: &Node) -> Self {
+        Lines {
+            start: node.start_line,
+            end: node.end_line,
+        }
+    }
+}
+
+/// 一处变化的子树：新增的只有 new，删除的只有 old
+#[derive(Serialize, Debug)]
+pub struct Change {
+    pub status: Status,
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Lines>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Lines>,
+}
+
+/// 按函数名列出的变化
+#[derive(Serialize, Debug, Default)]
+pub struct FunctionChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+/// 一个文件的结构差异
+#[derive(Serialize, Debug)]
+pub struct FileDiff {
+    /// 源文件，相对于输入目录
+    pub file: PathBuf,
+    pub status: Status,
+    pub functions: FunctionChanges,
+    /// 有变化的最小子树；新增和删除的文件不列出
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+}
+
+/// diff.json 的内容
+#[derive(Serialize, Debug)]
+pub struct AstDiff {
+    /// 作为比较基准的输出目录
+    pub base: PathBuf,
+    pub files_added: usize,
+    pub files_removed: usize,
+    pub files_modified: usize,
+    pub functions_added: usize,
+    pub functions_removed: usize,
+    pub functions_modified: usize,
+    /// 有变化的文件；没有变化的文件不列出
+    pub files: Vec<FileDiff>,
+}
+
+/// 按 `same` 求最长公共子序列，把两组节点对齐为编辑脚本：两边都有的为对齐的节点，只有一边的为删除或新增
+fn align<'a>(
+    old: &[&'a Node],
+    new: &[&'a Node],
+    same: fn(&Node, &Node) -> bool,
+) -> Vec<(Option<&'a Node>, Option<&'a Node>)> {
+    let (n, m) = (old.len(), new.len());
+    // lcs[i][j]：old[i..] 与 new[j..] 的最长公共子序列长度
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if same(old[i], new[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut script = vec![];
+    while i < n || j < m {
+        if i < n && j < m && same(old[i], new[j]) {
+            script.push((Some(old[i]), Some(new[j])));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            script.push((None, Some(new[j])));
+            j += 1;
+        } else {
+            script.push((Some(old[i]), None));
+            i += 1;
+        }
+    }
+    script
+}
+
+/// 比较两个节点，把有变化的最小子树加入 `changes`
+/// 子节点先按哈希对齐，未变化的子节点之间剩下的再按种类对齐：种类相同的逐个深入比较，其余记为删除或新增
+fn compare(old: &Node, new: &Node, changes: &mut Vec<Change>) {
+    if old.hash == new.hash {
+        return;
+    }
+    let (old_children, new_children) = (old.compared_children(), new.compared_children());
+    if old.kind != new.kind || old_children.is_empty() || new_children.is_empty() {
+        changes.push(Change {
+            status: Status::Modified,
+            kind: new.kind.clone(),
+            old: Some(old.into()),
+            new: Some(new.into()),
+        });
+        return;
+    }
+    let (mut removed, mut added) = (vec![], vec![]);
+    for step in align(&old_children, &new_children, |a, b| a.hash == b.hash) {
+        match step {
+            (Some(_), Some(_)) => {
+                changed(&removed, &added, changes);
+                removed.clear();
+                added.clear();
+            }
+            (Some(old), None) => removed.push(old),
+            (None, Some(new)) => added.push(new),
+            (None, None) => {}
+        }
+    }
+    changed(&removed, &added, changes);
+}
+
+/// 记录两个未变化的子节点之间有变化的子节点
+fn changed(removed: &[&Node], added: &[&Node], changes: &mut Vec<Change>) {
+    for step in align(removed, added, |a, b| a.kind == b.kind) {
+        match step {
+            (Some(old), Some(new)) => compare(old, new, changes),
+            (Some(old), None) => changes.push(Change {
+                status: Status::Removed,
+                kind: old.kind.clone(),
+                old: Some(old.into()),
+                new: None,
+            }),
+            (None, Some(new)) => changes.push(Change {
+                status: Status::Added,
+                kind: new.kind.clone(),
+                old: None,
+                new: Some(new.into()),
+            }),
+            (None, None) => {}
+        }
+    }
+}
+
+/// 一个文件中函数的增删改
+fn function_changes(old: Option<&Node>, new: Option<&Node>) -> FunctionChanges {
+    let (old, new) = (
+        old.map(functions).unwrap_or_default(),
+        new.map(functions).unwrap_or_default(),
+    );
+    let mut changes = FunctionChanges::default();
+    for (name, node) in &new {
+        match old.get(name) {
+            None => changes.added.push(name.clone()),
+            Some(previous) if previous.hash != node.hash => changes.modified.push(name.clone()),
+            Some(_) => {}
+        }
+    }
+    changes.removed = old
+        .keys()
+        .filter(|name| !new.contains_key(*name))
+        .cloned()
+        .collect();
+    changes
+}
+
+/// 比较两组AST；`base` 和 `current` 为 (源文件路径, .ast.json 路径)
+pub fn diff(
+    base_dir: &Path,
+    base: &[(PathBuf, PathBuf)],
+    current: &[(PathBuf, PathBuf)],
+) -> Result<AstDiff, Box<dyn Error>> {
+    let base: HashMap<&PathBuf, &PathBuf> = base.iter().map(|(s, a)| (s, a)).collect();
+    let current: HashMap<&PathBuf, &PathBuf> = current.iter().map(|(s, a)| (s, a)).collect();
+    let mut sources: Vec<&PathBuf> = base.keys().chain(current.keys()).copied().collect();
+    sources.sort();
+    sources.dedup();
+
+    let mut files = vec![];
+    for source in sources {
+        let old = base.get(source).map(|path| Node::load(path)).transpose()?;
+        let new = current
+            .get(source)
+            .map(|path| Node::load(path))
+            .transpose()?;
+        let status = match (&old, &new) {
+            (Some(old), Some(new)) if old.hash == new.hash => continue,
+            (Some(_), Some(_)) => Status::Modified,
+            (None, _) => Status::Added,
+            (_, None) => Status::Removed,
+        };
+        let mut changes = vec![];
+        if let (Some(old), Some(new)) = (&old, &new) {
+            compare(old, new, &mut changes);
+        }
+        files.push(FileDiff {
+            file: source.clone(),
+            status,
+            functions: function_changes(old.as_ref(), new.as_ref()),
+            changes,
+        });
+    }
+
+    let count = |status| files.iter().filter(|f| f.status == status).count();
+    let functions = |list: fn(&FunctionChanges) -> &Vec<String>| {
+        files.iter().map(|f| list(&f.functions).len()).sum()
+    };
+    Ok(AstDiff {
+        base: base_dir.to_path_buf(),
+        files_added: count(Status::Added),
+        files_removed: count(Status::Removed),
+        files_modified: count(Status::Modified),
+        functions_added: functions(|f| &f.added),
+        functions_removed: functions(|f| &f.removed),
+        functions_modified: functions(|f| &f.modified),
+        files,
+    })
+}
@@ -0,0 +1,46 @@
+// encoding.rs
+//
+// 源文件的编码检查：仓库中混入的二进制文件 (编译产物、密钥文件等) 按扩展名无法区分，通过开头的字节识别后跳过；
+// 不是有效UTF-8的源文件 (Latin-1 注释、截断的多字节字符等) 仍按原始字节解析，节点文本有损解码，
+// 记录写入输出目录下的 encoding.json，说明哪些文件的文本与字节位置不完全对应
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// 输出文件名，位于输出目录下
+pub const ENCODING_FILE_NAME: &str = "encoding.json";
+
+/// 判断是否是二进制文件时检查的开头字节数
+const SNIFF_BYTES: usize = 8192;
+
+/// 一个不是有效UTF-8的源文件
+#[derive(Serialize, Debug, Clone)]
+pub struct LossyFile {
+    /// 源文件，相对于输入目录
+    pub file: PathBuf,
+    /// 采用的解码方式，与AST根节点上的 encoding 相同
+    pub encoding: &'static str,
+    /// 无效的字节序列数，每个在节点文本中替换为一个 U+FFFD
+    pub invalid_sequences: usize,
+    /// 第一个无效字节的位置
+    pub first_invalid_byte: usize,
+}
+
+/// 开头的字节中有 NUL 时视为二进制文件；UTF-8 的文本中不会出现，UTF-16 的文本也按二进制处理
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(SNIFF_BYTES)].contains(&0)
+}
+
+/// 检查源文件是否是有效的UTF-8，不是时返回记录
+pub fn check(bytes: &[u8], file: &Path) -> Option<LossyFile> {
+    let error = std::str::from_utf8(bytes).err()?;
+    Some(LossyFile {
+        file: file.to_path_buf(),
+        encoding: solana_ast_generator::ENCODING_UTF8_LOSSY,
+        invalid_sequences: bytes
+            .utf8_chunks()
+            .filter(|chunk| !chunk.invalid().is_empty())
+            .count(),
+        first_invalid_byte: error.valid_up_to(),
+    })
+}
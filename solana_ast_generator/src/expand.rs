@@ -0,0 +1,92 @@
+// expand.rs
+//
+// --expand：Anchor 的 #[program]、#[derive(Accounts)] 等宏展开后才是实际执行的代码 (账户反序列化、约束检查、指令分发)
+// 对每个crate运行 `cargo rustc --profile=check -- -Zunpretty=expanded` (cargo expand 的做法) 得到展开后的整个crate源码，
+// 与表层的AST一起写出。展开后所有模块都内联在同一个文件中，因此以crate的根文件 (src/lib.rs 或 src/main.rs) 为单位
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// 展开后的源码文件的后缀，写在crate根文件的输出路径之后，例如 src/lib.rs -> src/lib.rs.expanded.rs
+pub const EXPANDED_SUFFIX: &str = "expanded.rs";
+
+/// 错误信息中保留的 cargo 输出的最后几行
+const STDERR_TAIL_LINES: usize = 20;
+
+/// 一个要展开的crate
+#[derive(Debug)]
+pub struct Crate {
+    /// crate的根文件 (src/lib.rs 或 src/main.rs)
+    pub root: PathBuf,
+    pub manifest: PathBuf,
+    /// 是否是库；只有二进制目标的crate不指定目标，由 cargo 选择唯一的二进制
+    pub lib: bool,
+}
+
+/// 从要处理的源文件中找出crate的根文件：<dir>/src/lib.rs 或 <dir>/src/main.rs，且 <dir> 下有 Cargo.toml
+/// 同一个crate既有库又有二进制时只展开库
+pub fn crates(source_files: &[PathBuf]) -> Vec<Crate> {
+    let mut crates: Vec<Crate> = vec![];
+    for path in source_files {
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !["lib.rs", "main.rs"].contains(&file_name) {
+            continue;
+        }
+        let Some(dir) = path
+            .parent()
+            .filter(|src| src.file_name().is_some_and(|n| n == "src"))
+            .and_then(Path::parent)
+        else {
+            continue;
+        };
+        let manifest = dir.join("Cargo.toml");
+        if !manifest.is_file() {
+            continue;
+        }
+        let lib = file_name == "lib.rs";
+        match crates.iter_mut().find(|c| c.manifest == manifest) {
+            Some(existing) if lib => {
+                existing.root = path.clone();
+                existing.lib = true;
+            }
+            Some(_) => {}
+            None => crates.push(Crate {
+                root: path.clone(),
+                manifest,
+                lib,
+            }),
+        }
+    }
+    crates
+}
+
+/// 展开一个crate，返回展开后的源码
+/// -Zunpretty 是不稳定的选项，通过 RUSTC_BOOTSTRAP 在稳定版工具链上启用
+pub fn expand(krate: &Crate) -> Result<String, Box<dyn Error>> {
+    let mut command = Command::new("cargo");
+    command
+        .arg("rustc")
+        .arg("--quiet")
+        .arg("--manifest-path")
+        .arg(&krate.manifest)
+        .arg("--profile=check");
+    if krate.lib {
+        command.arg("--lib");
+    }
+    let output = command
+        .arg("--")
+        .arg("-Zunpretty=expanded")
+        .env("RUSTC_BOOTSTRAP", "1")
+        .output()
+        .map_err(|e| format!("无法运行 cargo: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<&str> = stderr.lines().collect();
+        let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+        return Err(format!("宏展开失败 ({}):\n{}", output.status, tail).into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
@@ -0,0 +1,96 @@
+// graph_export.rs
+//
+// --format dot/graphml/html：把每个文件的语法树作为一张图 (solana_graph 的交换格式，AST层，节点为语法树节点，
+// 边为父节点到子节点) 交给与CFG、CPG生成器共用的导出层，一次写出所有请求的格式
+
+use serde::Serialize;
+use serde_json::Value;
+use solana_ast_generator::{SerializableNode, SCHEMA_VERSION};
+use solana_graph::graph::{EdgeKind, Graph, GraphEdge, GraphNode, Layer, NodeKind, Span};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// 导出文件的元数据，写在DOT的注释、JSON的 metadata 和 GraphML 的 <desc> 中
+#[derive(Serialize, Debug)]
+pub struct ExportMetadata<'a> {
+    pub schema_version: u32,
+    pub tool: &'static str,
+    pub tool_version: &'static str,
+    /// 源文件，相对于输入目录
+    pub source: &'a Path,
+    pub source_hash: &'a str,
+    pub generated_at: &'a str,
+}
+
+impl<'a> ExportMetadata<'a> {
+    pub fn new(source: &'a Path, source_hash: &'a str, generated_at: &'a str) -> Self {
+        ExportMetadata {
+            schema_version: SCHEMA_VERSION,
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            source,
+            source_hash,
+            generated_at,
+        }
+    }
+}
+
+/// 把一个文件的语法树转换为图；节点按先序编号，properties 中的 ast_id 为节点在 .ast.json 中的ID
+pub fn syntax_graph(root: &SerializableNode, file: &Path) -> Graph {
+    let mut graph = Graph {
+        layer: Layer::Ast,
+        function: file.to_string_lossy().into_owned(),
+        nodes: vec![],
+        edges: vec![],
+    };
+    let mut stack = vec![(root, None)];
+    while let Some((node, parent)) = stack.pop() {
+        let id = graph.nodes.len();
+        let leaf = node.children().is_empty();
+        let mut properties = BTreeMap::new();
+        properties.insert("ast_id".to_string(), Value::from(node.id()));
+        if let Some(field) = node.field() {
+            properties.insert("field".to_string(), Value::from(field));
+        }
+        let range = node.byte_range();
+        graph.nodes.push(GraphNode {
+            id,
+            kind: NodeKind::Syntax,
+            // 叶子节点带上文本 (引号中转义，保持单行)
+            label: match node.text() {
+                Some(text) if leaf => format!("{} {:?}", node.kind(), text),
+                _ => node.kind().to_string(),
+            },
+            span: Some(Span {
+                file: file.to_path_buf(),
+                start_byte: range.start,
+                end_byte: range.end,
+            }),
+            properties,
+        });
+        if let Some(parent) = parent {
+            graph.edges.push(GraphEdge {
+                source: parent,
+                target: id,
+                kind: EdgeKind::Child,
+                properties: BTreeMap::new(),
+            });
+        }
+        stack.extend(node.children().iter().rev().map(|child| (child, Some(id))));
+    }
+    graph
+}
+
+/// 语法树的DOT (不含元数据注释)
+pub fn syntax_dot(graph: &Graph) -> String {
+    let mut dot = String::from("digraph {\n    node [shape=box];\n");
+    for node in &graph.nodes {
+        let _ = writeln!(dot, "    {} [label={:?}];", node.id, node.label);
+    }
+    for edge in &graph.edges {
+        let _ = writeln!(dot, "    {} -> {};", edge.source, edge.target);
+    }
+    dot.push_str("}\n");
+    dot
+}
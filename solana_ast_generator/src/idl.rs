@@ -0,0 +1,317 @@
+// idl.rs
+//
+// 读取 Anchor IDL 并写出统一格式的 .idl.json，与AST放在同一个输出目录中
+// 同时支持 Anchor 0.30 之前 (camelCase 指令名、isMut/isSigner) 和之后 (snake_case、writable/signer) 的IDL格式，
+// 统一后指令名为 snake_case 的处理函数名，类型写成Rust的写法，后续阶段据此把指令对应到处理函数
+
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Anchor 写出IDL的目录，相对于输入目录
+pub const ANCHOR_IDL_DIR: &str = "target/idl";
+
+/// 统一格式的IDL
+#[derive(Serialize, Debug)]
+pub struct NormalizedIdl {
+    pub program: String,
+    /// 程序地址：0.30 起为顶层的 address，之前为 metadata.address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// IDL 格式：legacy (0.30 之前) 或 0.30
+    pub spec: &'static str,
+    pub instructions: Vec<IdlInstruction>,
+    /// 程序拥有的账户类型
+    pub accounts: Vec<IdlNamed>,
+    pub events: Vec<IdlNamed>,
+    pub types: Vec<IdlType>,
+    pub errors: Vec<IdlError>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct IdlInstruction {
+    /// snake_case 的处理函数名
+    pub name: String,
+    /// IDL中的原始名字
+    pub idl_name: String,
+    pub args: Vec<IdlField>,
+    pub accounts: Vec<IdlAccount>,
+    /// 0.30 起IDL直接给出的判别值
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discriminator: Option<Vec<u8>>,
+}
+
+/// 指令的一个账户；嵌套的账户组展开为 `组名.账户名`
+#[derive(Serialize, Debug)]
+pub struct IdlAccount {
+    pub name: String,
+    pub writable: bool,
+    pub signer: bool,
+    pub optional: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct IdlField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+/// 账户或事件：名字及 (0.30 起的) 判别值
+#[derive(Serialize, Debug)]
+pub struct IdlNamed {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discriminator: Option<Vec<u8>>,
+}
+
+/// types 中的结构体或枚举；枚举的每个变体是一个只有名字和字段的条目
+#[derive(Serialize, Debug)]
+pub struct IdlType {
+    pub name: String,
+    pub kind: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<IdlField>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<IdlType>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct IdlError {
+    pub code: u64,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg: Option<String>,
+}
+
+/// 要读取的IDL文件：用户给出的文件和目录 (目录下的所有 .json)，没有给出时为 <input>/target/idl/*.json
+/// target 默认不被遍历，因此这里单独查找
+pub fn idl_files(input: &Path, paths: &[PathBuf]) -> Vec<PathBuf> {
+    let json_files = |dir: &Path| {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+        files
+    };
+    if paths.is_empty() {
+        return json_files(&input.join(ANCHOR_IDL_DIR));
+    }
+    paths
+        .iter()
+        .flat_map(|path| {
+            if path.is_dir() {
+                json_files(path)
+            } else {
+                vec![path.clone()]
+            }
+        })
+        .collect()
+}
+
+/// camelCase 转为 snake_case，旧版IDL中的指令名与处理函数名之间的对应关系
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// IDL中的类型写成Rust的写法，例如 {"vec": "u8"} -> Vec<u8>
+fn type_name(ty: &Value) -> String {
+    match ty {
+        Value::String(name) => match name.as_str() {
+            "publicKey" | "pubkey" => "Pubkey".to_string(),
+            "string" => "String".to_string(),
+            "bytes" => "Vec<u8>".to_string(),
+            other => other.to_string(),
+        },
+        Value::Object(map) => {
+            if let Some(inner) = map.get("vec") {
+                format!("Vec<{}>", type_name(inner))
+            } else if let Some(inner) = map.get("option") {
+                format!("Option<{}>", type_name(inner))
+            } else if let Some(inner) = map.get("coption") {
+                format!("COption<{}>", type_name(inner))
+            } else if let Some(Value::Array(array)) = map.get("array") {
+                match array.as_slice() {
+                    [inner, len] => format!("[{}; {}]", type_name(inner), len),
+                    _ => ty.to_string(),
+                }
+            } else if let Some(defined) = map.get("defined") {
+                // 0.30 之前为 {"defined": "Name"}，之后为 {"defined": {"name": "Name"}}
+                defined
+                    .get("name")
+                    .unwrap_or(defined)
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string()
+            } else {
+                ty.to_string()
+            }
+        }
+        _ => ty.to_string(),
+    }
+}
+
+fn str_field(value: &Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    value.get(key).and_then(Value::as_array).into_iter().flatten()
+}
+
+fn discriminator(value: &Value) -> Option<Vec<u8>> {
+    value.get("discriminator").and_then(Value::as_array).map(|bytes| {
+        bytes
+            .iter()
+            .filter_map(|b| b.as_u64().map(|b| b as u8))
+            .collect()
+    })
+}
+
+/// 结构体或枚举变体的字段；元组形式的字段只有类型，按位置命名
+fn fields(fields: Option<&Value>) -> Vec<IdlField> {
+    fields
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, field)| match (field.get("name"), field.get("type")) {
+            (Some(Value::String(name)), Some(ty)) => IdlField {
+                name: name.clone(),
+                ty: type_name(ty),
+            },
+            _ => IdlField {
+                name: i.to_string(),
+                ty: type_name(field),
+            },
+        })
+        .collect()
+}
+
+/// 展开指令的账户，嵌套的账户组以 `组名.` 为前缀
+fn accounts(accounts: &Value, prefix: &str, out: &mut Vec<IdlAccount>) {
+    let flag = |account: &Value, keys: [&str; 2]| {
+        keys.iter()
+            .any(|key| account.get(key).and_then(Value::as_bool) == Some(true))
+    };
+    for account in accounts.as_array().into_iter().flatten() {
+        let name = format!("{}{}", prefix, str_field(account, "name"));
+        if let Some(nested) = account.get("accounts") {
+            self::accounts(nested, &format!("{}.", name), out);
+            continue;
+        }
+        out.push(IdlAccount {
+            name,
+            writable: flag(account, ["writable", "isMut"]),
+            signer: flag(account, ["signer", "isSigner"]),
+            optional: flag(account, ["optional", "isOptional"]),
+        });
+    }
+}
+
+/// 把一个IDL统一为 NormalizedIdl
+pub fn normalize(idl: &Value) -> Result<NormalizedIdl, Box<dyn Error>> {
+    let program = idl
+        .pointer("/metadata/name")
+        .or_else(|| idl.get("name"))
+        .and_then(Value::as_str)
+        .ok_or("IDL 中没有程序名")?
+        .to_string();
+    let spec = if idl.pointer("/metadata/spec").is_some() {
+        "0.30"
+    } else {
+        "legacy"
+    };
+    let address = idl
+        .get("address")
+        .or_else(|| idl.pointer("/metadata/address"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let instructions = array(idl, "instructions")
+        .map(|ix| {
+            let idl_name = str_field(ix, "name");
+            let mut ix_accounts = vec![];
+            if let Some(list) = ix.get("accounts") {
+                accounts(list, "", &mut ix_accounts);
+            }
+            IdlInstruction {
+                name: snake_case(&idl_name),
+                idl_name,
+                args: fields(ix.get("args")),
+                accounts: ix_accounts,
+                discriminator: discriminator(ix),
+            }
+        })
+        .collect();
+    let named = |key| {
+        array(idl, key)
+            .map(|item| IdlNamed {
+                name: str_field(item, "name"),
+                discriminator: discriminator(item),
+            })
+            .collect()
+    };
+    // 旧格式中账户的定义写在 accounts 中，0.30 起写在 types 中，accounts 只有名字和判别值
+    let types = array(idl, "types")
+        .chain(array(idl, "accounts").filter(|a| a.get("type").is_some()))
+        .map(|ty| IdlType {
+            name: str_field(ty, "name"),
+            kind: ty
+                .pointer("/type/kind")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            fields: fields(ty.pointer("/type/fields")),
+            variants: ty
+                .pointer("/type/variants")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .map(|variant| IdlType {
+                    name: str_field(variant, "name"),
+                    kind: "variant".to_string(),
+                    fields: fields(variant.get("fields")),
+                    variants: vec![],
+                })
+                .collect(),
+        })
+        .collect();
+    let errors = array(idl, "errors")
+        .map(|error| IdlError {
+            code: error.get("code").and_then(Value::as_u64).unwrap_or_default(),
+            name: str_field(error, "name"),
+            msg: error.get("msg").and_then(Value::as_str).map(str::to_string),
+        })
+        .collect();
+    Ok(NormalizedIdl {
+        program,
+        address,
+        spec,
+        instructions,
+        accounts: named("accounts"),
+        events: named("events"),
+        types,
+        errors,
+    })
+}
@@ -0,0 +1,552 @@
+// lib.rs
+//
+// AST生成的核心逻辑：选择语法并把 tree-sitter 的语法树转换为可序列化的结构，不涉及文件读写
+// 命令行工具 (main.rs) 和 wasm 构建 (wasm.rs) 共用这里的实现
+
+#[cfg(feature = "wasm")]
+mod wasm;
+mod stream;
+mod tokens;
+
+pub use stream::{write_json_streaming, StreamLimits, StreamStats};
+pub use tokens::{leaf_tokens, split_identifier, Token};
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tree_sitter::{Language as Grammar, Node};
+
+/// AST输出文件格式的版本号，格式发生不兼容的变化时递增
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// 运行时加载的 Move 语法，见 load_move_grammar
+static MOVE_GRAMMAR: OnceLock<Grammar> = OnceLock::new();
+
+/// 支持的源代码语言
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Language {
+    Rust,
+    Typescript,
+    /// 带 JSX 的 TypeScript (.tsx)；.jsx 由 JavaScript 的语法直接解析
+    Tsx,
+    Javascript,
+    /// 部署、测试等链下脚本
+    Python,
+    /// 同一仓库中的 EVM 合约
+    Solidity,
+    /// Cargo.toml、Anchor.toml 等清单，其中有 crate 名、feature 和 Anchor 程序ID
+    Toml,
+    /// Aptos/Sui 的 Move 模块，语法需要先用 load_move_grammar 加载
+    Move,
+}
+
+impl Language {
+    pub const ALL: [Language; 8] = [
+        Language::Rust,
+        Language::Typescript,
+        Language::Tsx,
+        Language::Javascript,
+        Language::Python,
+        Language::Solidity,
+        Language::Toml,
+        Language::Move,
+    ];
+
+    /// 根据文件扩展名判断语言
+    pub fn from_extension(ext: &str) -> Option<Language> {
+        match ext {
+            "rs" => Some(Language::Rust),
+            "ts" => Some(Language::Typescript),
+            "tsx" => Some(Language::Tsx),
+            "js" | "jsx" => Some(Language::Javascript),
+            "py" => Some(Language::Python),
+            "sol" => Some(Language::Solidity),
+            "toml" => Some(Language::Toml),
+            "move" => Some(Language::Move),
+            _ => None,
+        }
+    }
+
+    /// 对应的 tree-sitter 语法
+    /// 使用每个crate提供的安全的、公共的language()函数，而不是使用 extern "C" 块。
+    /// 注意 tree-sitter-typescript 的函数名是 language_typescript()。
+    /// Move 的语法没有加载时返回错误
+    pub fn grammar(self) -> Result<Grammar, String> {
+        Ok(match self {
+            Language::Rust => tree_sitter_rust::language(),
+            Language::Typescript => tree_sitter_typescript::language_typescript(),
+            Language::Tsx => tree_sitter_typescript::language_tsx(),
+            Language::Javascript => tree_sitter_javascript::language(),
+            Language::Python => tree_sitter_python::language(),
+            Language::Solidity => tree_sitter_solidity::language(),
+            Language::Toml => tree_sitter_toml_ng::language(),
+            Language::Move => MOVE_GRAMMAR
+                .get()
+                .cloned()
+                .ok_or("没有加载 Move 语法 (用 --move-grammar 指定 tree-sitter-move 的动态库)")?,
+        })
+    }
+}
+
+/// 从编译好的 tree-sitter-move 动态库 (导出 tree_sitter_move 函数) 加载 Move 语法，整个进程只加载一次
+/// 动态库在进程结束前不会卸载，因为语法中的解析表就在库的内存中
+#[cfg(feature = "cli")]
+pub fn load_move_grammar(path: &std::path::Path) -> Result<(), String> {
+    if MOVE_GRAMMAR.get().is_some() {
+        return Ok(());
+    }
+    let error = |e: libloading::Error| format!("无法加载 Move 语法 '{}': {}", path.display(), e);
+    // SAFETY: 动态库由用户指定，须是 tree-sitter 生成的语法，tree_sitter_move 的签名与各语法crate中的
+    // extern "C" 声明相同；Language 是 #[repr(transparent)] 的指针
+    let grammar = unsafe {
+        let library = libloading::Library::new(path).map_err(error)?;
+        let language: libloading::Symbol<unsafe extern "C" fn() -> Grammar> =
+            library.get(b"tree_sitter_move").map_err(error)?;
+        let grammar = language();
+        std::mem::forget(library);
+        grammar
+    };
+    if !(tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION..=tree_sitter::LANGUAGE_VERSION)
+        .contains(&grammar.version())
+    {
+        return Err(format!(
+            "Move 语法 '{}' 的 ABI 版本 {} 与 tree-sitter 不兼容",
+            path.display(),
+            grammar.version()
+        ));
+    }
+    let _ = MOVE_GRAMMAR.set(grammar);
+    Ok(())
+}
+
+/// 一个源文件在 git 仓库中的来源，写在AST的根节点和 manifest.json、index.json 的AST记录中
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Provenance {
+    /// 相对于仓库根目录的路径
+    pub repo_path: PathBuf,
+    /// 生成时仓库的 HEAD 提交
+    pub commit: String,
+    /// 最后修改该文件的提交；文件尚未提交时没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_commit: Option<CommitInfo>,
+    /// 工作区中的文件与 HEAD 不同 (有未提交的修改或未被跟踪)，AST反映的不是 commit 中的内容
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub uncommitted: bool,
+}
+
+impl Provenance {
+    /// 除生成时的 HEAD 外是否相同；只有 HEAD 不同时，源文件和它的历史都没有变化
+    pub fn same_origin(&self, other: &Provenance) -> bool {
+        self.repo_path == other.repo_path
+            && self.last_commit == other.last_commit
+            && self.uncommitted == other.uncommitted
+    }
+}
+
+/// 一个提交的摘要
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommitInfo {
+    pub id: String,
+    pub author: String,
+    /// 作者时间 (RFC 3339)
+    pub time: String,
+    /// 提交说明的第一行
+    pub summary: String,
+}
+
+/// 自定义的、可序列化为JSON的AST节点结构
+/// 我们将tree-sitter的节点递归地转换为这个结构，以便使用serde进行序列化
+/// 也可以从写出的AST读回 (S表达式中才用到的 named、missing 读回后为 false)
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerializableNode {
+    id: String,         // 节点ID，`文件ID:先序序号`，同一文件的同一节点在每次运行中相同
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<String>, // 父节点 (裁剪后最近的保留的祖先) 的ID；根节点省略
+    kind: String,       // 节点的类型，例如 "function_item", "identifier"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>, // 该节点在父节点中的字段名，例如 "name", "body", "condition"；没有字段名的子节点省略
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    comments: Vec<String>, // 紧挨在该节点之前的注释和文档注释 (例如 Anchor 的 `/// CHECK:`)，注释节点本身仍保留在树中
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>, // 该节点覆盖的源代码文本片段；--no-text 时省略，由 source 和字节位置还原
+    start_byte: usize,  // 在源文件中的起始字节位置
+    end_byte: usize,    // 在源文件中的结束字节位置
+    start_line: usize,  // 起始行号，从1开始
+    start_column: usize, // 起始列号 (行内的字节偏移)，从1开始
+    end_line: usize,    // 结束行号，从1开始
+    end_column: usize,  // 结束列号 (行内的字节偏移)，从1开始，指向最后一个字节之后
+    children: Vec<SerializableNode>, // 该节点的子节点列表
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>, // 省略节点文本时，根节点上保存的整个源文件，其他情况下省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>, // 源文件不是有效的UTF-8时，根节点上记录的解码方式 (ENCODING_UTF8_LOSSY)，其他情况下省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<Provenance>, // 输入目录在 git 仓库中时，根节点上记录的源文件来源，其他情况下省略
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool, // 子节点因超出最大深度而被去掉时为 true，其他情况下省略
+    #[serde(skip)]
+    named: bool, // 是否是命名节点 (匿名节点为标点、关键字等记号)，只用于输出S表达式
+    #[serde(skip)]
+    missing: bool, // 是否是解析器为了继续解析而插入的 MISSING 节点，只用于输出S表达式
+}
+
+impl SerializableNode {
+    /// 节点ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn children(&self) -> &[SerializableNode] {
+        &self.children
+    }
+
+    /// 节点的类型，例如 "function_item"
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// 该节点在父节点中的字段名
+    pub fn field(&self) -> Option<&str> {
+        self.field.as_deref()
+    }
+
+    /// 节点覆盖的源代码文本；--no-text 时没有
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    /// 节点在源文件中的字节范围
+    pub fn byte_range(&self) -> std::ops::Range<usize> {
+        self.start_byte..self.end_byte
+    }
+
+    /// 在根节点上记录源文件的来源
+    pub fn set_provenance(&mut self, provenance: Option<Provenance>) {
+        self.provenance = provenance;
+    }
+
+    /// 以该节点为根的子树中的节点数
+    pub fn node_count(&self) -> usize {
+        1 + self.children.iter().map(Self::node_count).sum::<usize>()
+    }
+
+    /// 子树中因超出最大深度而被截断的节点数
+    pub fn truncated_count(&self) -> usize {
+        usize::from(self.truncated) + self.children.iter().map(Self::truncated_count).sum::<usize>()
+    }
+
+    /// 与 `tree-sitter parse` 的输出相同的S表达式：只有命名节点和 MISSING 节点，带字段名，
+    /// 起止位置按 tree-sitter 的习惯写作从0开始的 [行, 列]，每层缩进两个空格
+    pub fn to_sexp(&self) -> String {
+        let mut sexp = String::new();
+        self.write_sexp(&mut sexp, 0);
+        sexp.push('\n');
+        sexp
+    }
+
+    fn write_sexp(&self, sexp: &mut String, depth: usize) {
+        if !self.named && !self.missing {
+            return;
+        }
+        if depth > 0 {
+            sexp.push('\n');
+            sexp.push_str(&"  ".repeat(depth));
+        }
+        if let Some(field) = &self.field {
+            sexp.push_str(field);
+            sexp.push_str(": ");
+        }
+        sexp.push('(');
+        match (self.missing, self.named) {
+            (true, true) => sexp.push_str(&format!("MISSING {}", self.kind)),
+            (true, false) => sexp.push_str(&format!("MISSING {:?}", self.kind)),
+            _ => sexp.push_str(&self.kind),
+        }
+        sexp.push_str(&format!(
+            " [{}, {}] - [{}, {}]",
+            self.start_line - 1,
+            self.start_column - 1,
+            self.end_line - 1,
+            self.end_column - 1
+        ));
+        for child in &self.children {
+            child.write_sexp(sexp, depth + 1);
+        }
+        sexp.push(')');
+    }
+}
+
+/// 输出AST的选项：按节点种类裁剪、省略节点文本，默认保留所有节点及其文本
+#[derive(Debug, Clone, Default)]
+pub struct AstOptions {
+    /// 非空时只保留这些种类的节点；其他节点被去掉，但其中保留的后代节点提升到最近的保留的祖先下
+    pub include: HashSet<String>,
+    /// 去掉这些种类的节点及其整个子树
+    pub exclude: HashSet<String>,
+    /// 去掉匿名节点 (标点、关键字等记号)
+    pub named_only: bool,
+    /// 不在每个节点上重复保存文本，只在根节点上保存一份源文件，文本用 node_text/restore_text 按字节位置还原
+    pub omit_text: bool,
+    /// 最大深度 (根节点为0，按保留的节点计算)，这一深度的节点不再保留子节点，带有 truncated；
+    /// 用于压缩后的JS、生成代码等嵌套极深的输入。被截断的子树仍占用节点序号，其余节点的ID不变
+    pub max_depth: Option<usize>,
+}
+
+/// 源文件不是有效的UTF-8时采用的解码方式：字节位置和行列号仍按原始字节计算，
+/// 节点文本中的无效字节序列替换为 U+FFFD，因此文本的长度可能与 end_byte - start_byte 不同
+pub const ENCODING_UTF8_LOSSY: &str = "utf-8-lossy";
+
+/// 注释节点的种类：Rust 和 Move 区分行注释与块注释，其他语言的语法只有 comment
+const COMMENT_KINDS: &[&str] = &["line_comment", "block_comment", "comment"];
+
+/// 注释与所属的节点之间可以隔着的节点种类，例如文档注释与函数、字段之间的属性
+const ANNOTATION_KINDS: &[&str] = &["attribute_item", "decorator"];
+
+/// 节点最后一行的行号 (从0开始)；Rust 的行注释包含结尾的换行符，结束位置在下一行的行首
+fn last_row(node: Node) -> usize {
+    let end = node.end_position();
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+
+/// 过滤器对一个节点的处理
+enum Keep {
+    Node,
+    ChildrenOnly,
+    Nothing,
+}
+
+impl AstOptions {
+    fn keep(&self, node: Node) -> Keep {
+        if self.exclude.contains(node.kind()) || (self.named_only && !node.is_named()) {
+            Keep::Nothing
+        } else if !self.include.is_empty() && !self.include.contains(node.kind()) {
+            Keep::ChildrenOnly
+        } else {
+            Keep::Node
+        }
+    }
+}
+
+/// 递归函数，将tree-sitter的Node转换为我们的SerializableNode
+/// 这是一个深度优先的遍历过程；`path` 为源文件相对于项目根目录的路径，用于生成节点ID
+pub fn node_to_serializable(node: Node, source_code: &str, path: &str) -> SerializableNode {
+    node_to_serializable_with(node, source_code.as_bytes(), path, &AstOptions::default())
+}
+
+/// 同 node_to_serializable，但按 `options` 裁剪子孙节点、省略文本；根节点总是保留
+/// `source` 为解析时使用的原始字节，可以不是有效的UTF-8，此时根节点上记录 ENCODING_UTF8_LOSSY；
+/// 无法在根节点上保存一份与字节位置对应的源文件，因此即使要求省略文本也保留每个节点的文本
+pub fn node_to_serializable_with(
+    node: Node,
+    source: &[u8],
+    path: &str,
+    options: &AstOptions,
+) -> SerializableNode {
+    let utf8 = std::str::from_utf8(source).ok();
+    let mut serializer = Serializer {
+        source,
+        omit_text: options.omit_text && utf8.is_some(),
+        options,
+        file_id: file_id(path),
+        next_index: 0,
+    };
+    let id = serializer.next_id();
+    let (children, truncated) = serializer.limited_children(node, &id, 0);
+    let mut root = SerializableNode {
+        truncated,
+        ..serializer.node(node, id, None, None, children)
+    };
+    match utf8 {
+        Some(source_code) if options.omit_text => root.source = Some(source_code.to_string()),
+        Some(_) => {}
+        None => root.encoding = Some(ENCODING_UTF8_LOSSY.to_string()),
+    }
+    root
+}
+
+/// 节点在原始字节中的文本，无效的UTF-8字节序列替换为 U+FFFD
+pub fn node_text_lossy<'a>(node: Node, source: &'a [u8]) -> Cow<'a, str> {
+    String::from_utf8_lossy(source.get(node.byte_range()).unwrap_or_default())
+}
+
+/// 节点覆盖的源代码文本；字节位置越界或不在字符边界上时返回空字符串
+pub fn node_text(source_code: &str, start_byte: usize, end_byte: usize) -> &str {
+    source_code.get(start_byte..end_byte).unwrap_or("")
+}
+
+/// 为 --no-text 生成的AST (JSON) 补上每个节点的 text，并去掉根节点上的 source；
+/// 已经带有文本的AST保持不变
+pub fn restore_text(ast: &mut serde_json::Value) {
+    let Some(serde_json::Value::String(source)) = ast.as_object_mut().and_then(|root| root.remove("source"))
+    else {
+        return;
+    };
+    fn fill(node: &mut serde_json::Value, source: &str) {
+        let byte = |key| node.get(key).and_then(serde_json::Value::as_u64).unwrap_or(0) as usize;
+        let text = node_text(source, byte("start_byte"), byte("end_byte")).to_string();
+        if let Some(object) = node.as_object_mut() {
+            object.entry("text").or_insert(text.into());
+        }
+        if let Some(children) = node.get_mut("children").and_then(serde_json::Value::as_array_mut) {
+            for child in children {
+                fill(child, source);
+            }
+        }
+    }
+    fill(ast, &source);
+}
+
+/// 节点ID的文件部分：源文件路径的 FNV-1a 哈希 (十六进制)
+/// 按路径而不是内容计算，文件修改后其中未受影响的节点 (修改位置之前的) 仍保持原来的ID
+pub fn file_id(path: &str) -> String {
+    let hash = path.replace('\\', "/").bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// 遍历兄弟节点时收集连续的注释，挂到其后的第一个非注释兄弟节点上 (中间可以隔着属性)；
+/// 注释之间或注释与节点之间有空行时，前面的注释不再属于该节点
+#[derive(Default)]
+struct Comments {
+    pending: Vec<String>,
+    /// 前一个兄弟节点的最后一行，以及它是否是注释或属性以外的节点
+    previous: Option<(usize, bool)>,
+}
+
+impl Comments {
+    /// 依次传入每个兄弟节点，返回挂在该节点上的注释
+    fn next(&mut self, child: Node, source: &[u8]) -> Vec<String> {
+        let start_row = child.start_position().row;
+        if self.previous.is_some_and(|(row, _)| start_row > row + 1) {
+            self.pending.clear();
+        }
+        let is_comment = COMMENT_KINDS.contains(&child.kind());
+        let is_item = !is_comment && !ANNOTATION_KINDS.contains(&child.kind());
+        let mut attached = vec![];
+        if is_item {
+            attached = std::mem::take(&mut self.pending);
+        } else if is_comment && self.previous != Some((start_row, true)) {
+            // 与前一个节点在同一行的注释是它的行尾注释，不属于后面的节点
+            self.pending.push(node_text_lossy(child, source).trim_end().to_string());
+        }
+        self.previous = Some((last_row(child), is_item));
+        attached
+    }
+}
+
+/// 一次转换的状态
+/// 节点ID为 `文件ID:序号`，序号是节点在原始语法树先序遍历中的位置；被裁剪掉的节点也占用序号，
+/// 因此同一节点的ID不受 --include-kinds 等选项影响
+struct Serializer<'a> {
+    source: &'a [u8],
+    /// 是否省略节点文本；源文件不是有效的UTF-8时总是保留
+    omit_text: bool,
+    options: &'a AstOptions,
+    file_id: String,
+    next_index: usize,
+}
+
+impl Serializer<'_> {
+    fn next_id(&mut self) -> String {
+        let id = format!("{}:{}", self.file_id, self.next_index);
+        self.next_index += 1;
+        id
+    }
+
+    /// 转换深度为 `depth` 的保留的节点的子节点；达到最大深度时跳过整个子树，返回空并说明被截断
+    fn limited_children(
+        &mut self,
+        node: Node,
+        id: &str,
+        depth: usize,
+    ) -> (Vec<SerializableNode>, bool) {
+        if self.options.max_depth.is_some_and(|max| depth >= max) && node.child_count() > 0 {
+            self.next_index += node.descendant_count() - 1;
+            return (vec![], true);
+        }
+        (self.children(node, id, depth), false)
+    }
+
+    /// 按过滤器转换一个节点的子节点，`parent` 为最近的保留的祖先节点的ID，`depth` 为它的深度
+    /// Node::children 不带字段名，因此用游标遍历，从游标上读取每个子节点的字段名
+    fn children(&mut self, node: Node, parent: &str, depth: usize) -> Vec<SerializableNode> {
+        let mut children = vec![];
+        let mut comments = Comments::default();
+        let mut cursor = node.walk();
+        if cursor.goto_first_child() {
+            loop {
+                let child = cursor.node();
+                let attached = comments.next(child, self.source);
+                match self.options.keep(child) {
+                    Keep::Node => {
+                        let id = self.next_id();
+                        let (grandchildren, truncated) = self.limited_children(child, &id, depth + 1);
+                        children.push(SerializableNode {
+                            comments: attached,
+                            truncated,
+                            ..self.node(child, id, Some(parent), cursor.field_name(), grandchildren)
+                        });
+                    }
+                    // 提升上来的节点不再是原来父节点的直接子节点，字段名也就不再适用
+                    Keep::ChildrenOnly => {
+                        self.next_index += 1;
+                        children.extend(self.children(child, parent, depth).into_iter().map(
+                            |mut grandchild| {
+                                grandchild.field = None;
+                                grandchild
+                            },
+                        ));
+                    }
+                    Keep::Nothing => self.next_index += child.descendant_count(),
+                }
+                if !cursor.goto_next_sibling() {
+                    break;
+                }
+            }
+        }
+        children
+    }
+
+    /// 转换一个节点，`field` 为它在父节点中的字段名
+    fn node(
+        &self,
+        node: Node,
+        id: String,
+        parent: Option<&str>,
+        field: Option<&str>,
+        children: Vec<SerializableNode>,
+    ) -> SerializableNode {
+        // tree-sitter 的行列号从0开始，这里转换为编辑器和报告中习惯的从1开始
+        let (start, end) = (node.start_position(), node.end_position());
+        SerializableNode {
+            id,
+            parent: parent.map(str::to_string),
+            kind: node.kind().to_string(),
+            field: field.map(str::to_string),
+            comments: vec![],
+            text: (!self.omit_text).then(|| node_text_lossy(node, self.source).into_owned()),
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+            start_line: start.row + 1,
+            start_column: start.column + 1,
+            end_line: end.row + 1,
+            end_column: end.column + 1,
+            children,
+            source: None,
+            encoding: None,
+            provenance: None,
+            truncated: false,
+            named: node.is_named(),
+            missing: node.is_missing(),
+        }
+    }
+}
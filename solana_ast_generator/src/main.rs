@@ -1,12 +1,54 @@
 // main.rs
 
-use clap::Parser as ClapParser;
-use serde::Serialize;
+mod anchor;
+mod ast_file;
+mod dedup;
+mod diff;
+mod encoding;
+mod expand;
+mod graph_export;
+mod idl;
+mod provenance;
+mod query;
+mod stats;
+mod symbols;
+mod syntax;
+
+use clap::builder::PossibleValue;
+use clap::{ArgAction, Parser as ClapParser, ValueEnum};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use notify::{RecursiveMode, Watcher};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use anchor::{Anchor, ANCHOR_FILE_NAME};
+use ast_file::{Compression, Encoding};
+use dedup::Duplicates;
+use diff::DIFF_FILE_NAME;
+use encoding::{LossyFile, ENCODING_FILE_NAME};
+use expand::EXPANDED_SUFFIX;
+use graph_export::{syntax_dot, syntax_graph, ExportMetadata};
+use provenance::Repo;
+use query::{QueryMatch, QuerySet, QUERY_FILE_NAME};
+use stats::{FileStats, Stats, STATS_FILE_NAME};
+use symbols::{Symbol, SYMBOLS_FILE_NAME};
+use syntax::{ErrorReport, FileErrors, TruncatedFile, ERRORS_FILE_NAME};
+use solana_ast_generator::{
+    leaf_tokens, load_move_grammar, node_to_serializable_with, AstOptions, Language, Provenance,
+    SerializableNode, StreamLimits, SCHEMA_VERSION,
+};
+use solana_graph::export::{Export, ExportFormat};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
-use tree_sitter::{Node, Parser as TreeSitterParser, Tree};
-use walkdir::WalkDir;
+use std::sync::{mpsc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
+use tree_sitter::{Parser as TreeSitterParser, Tree};
 
 /// 定义命令行参数结构
 /// 使用 clap 库来轻松创建专业的命令行界面
@@ -14,150 +56,2256 @@ use walkdir::WalkDir;
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// 要分析的Solana项目的输入目录路径
-    #[arg(short, long)]
+    #[arg(short, long, required_unless_present = "file", default_value = ".")]
     input: PathBuf,
 
-    /// 用于存储生成的AST文件的输出目录路径
-    #[arg(short, long)]
+    /// 用于存储生成的AST文件的输出目录路径；--format jsonl 时为JSONL文件，报告写在它所在的目录中
+    #[arg(short, long, required_unless_present = "file", default_value = ".")]
     output: PathBuf,
+
+    /// 单文件模式：只解析这个源文件，把AST写到标准输出，不读写目录，用于编辑器插件和 shell 管道；
+    /// 为 "-" 时从标准输入读取，此时必须用 --lang 指定语言
+    #[arg(
+        value_name = "FILE",
+        conflicts_with_all = ["input", "output", "watch", "incremental", "split_functions", "expand", "diff_base", "queries", "timings", "compress", "stream_above", "max_file_size", "content_addressed"]
+    )]
+    file: Option<PathBuf>,
+
+    /// 单文件模式的语言，默认按文件扩展名判断
+    #[arg(long, value_enum, requires = "file")]
+    lang: Option<Language>,
+
+    /// 输出格式 (逗号分隔)：至多一种AST格式，加上任意几种语法树的图导出 (dot、graphml、html)，
+    /// 例如 --format json,dot,html；图导出只适用于逐文件写出的AST
+    #[arg(long = "format", value_enum, value_delimiter = ',', default_value = "json")]
+    formats: Vec<FormatArg>,
+
+    /// --format 中的AST格式，由 Args::split_formats 填入
+    #[arg(skip)]
+    format: OutputFormat,
+
+    /// --format 中的图导出格式，由 Args::split_formats 填入
+    #[arg(skip)]
+    exports: Vec<ExportFormat>,
+
+    /// --format tokens 时把标识符按 camelCase、snake_case 拆成子词，写在记号的 subtokens 中
+    #[arg(long)]
+    subtokens: bool,
+
+    /// 提高日志详细程度 (-v 输出 debug, -vv 输出 trace)
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// 只输出警告和错误
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// 日志输出格式，json 格式便于流水线解析进度和错误事件
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// 并行处理文件的线程数，默认使用全部CPU核心
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// 内存预算 (MiB)。按源文件大小估算峰值内存，超出预算的文件会排队等待或被跳过
+    #[arg(long, value_name = "MIB")]
+    memory_limit: Option<u64>,
+
+    /// 单个文件的解析超时时间 (秒)，超时的文件会被跳过并记录在 skipped.json 中
+    #[arg(long, value_name = "SECS")]
+    timeout_per_file: Option<u64>,
+
+    /// 只遍历输入目录下的这些子目录 (可重复)，输出路径仍然相对于输入目录
+    #[arg(long = "root", value_name = "DIR")]
+    roots: Vec<PathBuf>,
+
+    /// 只处理匹配这些 glob 的文件 (相对于输入目录，可重复)
+    /// 指向默认排除的目录之内的 glob (例如 "target/generated/**") 会让遍历进入该目录
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// 跳过匹配这些 glob 的文件或目录 (相对于输入目录，可重复)，匹配的目录不再进入
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// 不跳过默认排除的目录 (target、node_modules、.git、dist)
+    #[arg(long)]
+    no_default_excludes: bool,
+
+    /// 编译好的 tree-sitter-move 动态库 (.so/.dylib/.dll)，用于解析 .move 文件；
+    /// 不指定时 .move 文件被跳过并记录在 skipped.json 中
+    #[arg(long, value_name = "FILE")]
+    move_grammar: Option<PathBuf>,
+
+    /// 不遵循 .gitignore、.git/info/exclude 和全局 gitignore，处理被 git 忽略的文件
+    #[arg(long)]
+    no_gitignore: bool,
+
+    /// 只分析这些语言 (逗号分隔)，默认分析所有支持的语言
+    #[arg(long = "language", value_enum, value_delimiter = ',')]
+    languages: Vec<Language>,
+
+    /// 增量模式：根据输出目录中上一次的 manifest.json，跳过内容未变化的源文件，
+    /// 并删除源文件已不存在的旧AST
+    #[arg(long)]
+    incremental: bool,
+
+    /// Anchor IDL 文件或目录 (可重复)，统一格式后写成 .idl.json；默认读取 <INPUT>/target/idl 下的所有JSON
+    #[arg(long = "idl", value_name = "PATH", conflicts_with = "no_idl")]
+    idls: Vec<PathBuf>,
+
+    /// 不读取 Anchor IDL
+    #[arg(long)]
+    no_idl: bool,
+
+    /// 不记录AST的来源 (仓库中的路径、HEAD 提交和最后修改的提交)；历史很长的仓库中查找最后修改的提交较慢
+    #[arg(long)]
+    no_provenance: bool,
+
+    /// 输出的AST中只保留这些种类的节点 (逗号分隔)，其他节点被去掉，其中保留的后代节点提升到上层
+    #[arg(long, value_name = "KIND", value_delimiter = ',')]
+    include_kinds: Vec<String>,
+
+    /// 从输出的AST中去掉这些种类的节点及其子树 (逗号分隔)
+    #[arg(long, value_name = "KIND", value_delimiter = ',')]
+    exclude_kinds: Vec<String>,
+
+    /// 从输出的AST中去掉匿名节点 (标点、关键字等记号)
+    #[arg(long)]
+    named_only: bool,
+
+    /// 压缩写出的AST (.ast.json.zst、.ast.json.gz)，报告不压缩；CFG生成器可以直接读取压缩的AST
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    compress: Option<Compression>,
+
+    /// 节点不带 text，只保留字节位置，根节点上保存一份源文件 (source)；
+    /// 读取时用 solana_ast_generator::restore_text 还原文本
+    #[arg(long)]
+    no_text: bool,
+
+    /// tree-sitter 查询文件 (.scm，可重复)，对每个文件运行并把捕获的节点写入输出目录下的 query.json；
+    /// 每个查询只用于能编译它的语言。使用查询时不复用增量模式下未变化文件的AST
+    #[arg(long = "query", value_name = "FILE")]
+    queries: Vec<PathBuf>,
+
+    /// 有文件存在语法错误 (见输出目录下的 errors.json) 时以失败退出，用于CI
+    #[arg(long)]
+    fail_on_parse_error: bool,
+
+    /// 另外把每个函数 (包括方法和赋给变量的箭头函数) 的子树写成单独的AST，
+    /// 放在 <文件>.functions/ 下，以限定名命名 (例如 lib.rs.functions/vault.Vault.deposit.ast.json)
+    #[arg(long)]
+    split_functions: bool,
+
+    /// 对每个crate (<dir>/src/lib.rs 或 main.rs，且 <dir> 下有 Cargo.toml) 运行 cargo 展开宏，
+    /// 把展开后的源码和AST写在crate根文件的AST旁边 (lib.rs.expanded.rs、lib.rs.expanded.rs.ast.json)；
+    /// 需要能编译该crate的 cargo 和依赖，展开失败的crate记录在 skipped.json 中
+    #[arg(long)]
+    expand: bool,
+
+    /// 另一次运行 (例如升级前的版本) 的输出目录：与其中的AST逐文件比较，
+    /// 把新增、删除和修改的函数以及有变化的子树写入输出目录下的 diff.json
+    #[arg(long, value_name = "DIR")]
+    diff_base: Option<PathBuf>,
+
+    /// 把每个文件的处理耗时写入该JSON文件，供 agent bench 统计
+    #[arg(long, value_name = "FILE")]
+    timings: Option<PathBuf>,
+
+    /// 生成后继续运行，监听输入目录：源文件修改、新增或删除时按增量模式重新生成，
+    /// 只重新解析变化的文件，并删除已删除的源文件的AST；按 Ctrl-C 退出
+    #[arg(long, conflicts_with = "fail_on_parse_error")]
+    watch: bool,
+
+    /// 跳过大于该大小 (MiB) 的源文件 (压缩后的JS、生成的代码等)，记录在 skipped.json 中；
+    /// --expand 展开后的源码超过时同样不写出
+    #[arg(long, value_name = "MIB")]
+    max_file_size: Option<u64>,
+
+    /// AST的最大深度 (根节点为0)，更深的子树被截断，截断处的节点带有 "truncated": true，
+    /// 被截断的文件记录在 errors.json 的 truncated 中
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// 按源文件内容的哈希存放AST (objects/3f/3f9a….rs.ast.json)，由 index.json 和 manifest.json 从源文件指向它；
+    /// 内容相同的文件只解析和写出一次，共享的AST中的节点ID按其中路径排在最前的文件计算
+    #[arg(long, conflicts_with = "split_functions")]
+    content_addressed: bool,
+
+    /// 不小于该大小 (MiB) 的源文件边遍历语法树边写出AST，不在内存中建出整棵树，用于打包后的JS等巨大文件；
+    /// 输出与不流式时相同。只能用于 --format json 且不压缩，这些文件不拆分函数AST
+    #[arg(long, value_name = "MIB", conflicts_with = "compress")]
+    stream_above: Option<u64>,
+
+    /// 流式写出时的最大深度 (根节点为0)，更深的子树被截断，截断处的节点带有 "truncated": true
+    #[arg(long, value_name = "N", requires = "stream_above")]
+    stream_max_depth: Option<usize>,
+
+    /// 流式写出的单个AST的最大大小 (MiB)，超出后剩余的子树被截断
+    #[arg(long, value_name = "MIB", requires = "stream_above")]
+    stream_max_size: Option<u64>,
+}
+
+/// AST的输出格式
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    /// 每个源文件一个格式化的 .ast.json
+    #[default]
+    Json,
+    /// 所有AST写入同一个文件，每个源文件一行紧凑的记录 (path、hash、ast)，避免在大仓库中产生大量小文件
+    Jsonl,
+    /// 每个源文件一个 MessagePack 编码的 .ast.msgpack，写入和读取都比JSON快、体积更小
+    Msgpack,
+    /// 每个源文件一个 CBOR 编码的 .ast.cbor
+    Cbor,
+    /// 每个源文件一个 tree-sitter 风格的S表达式 .ast.sexp (带起止位置)，比JSON紧凑得多，
+    /// 但只有命名节点、没有文本和节点ID，不能用作 --diff-base 或CFG生成器的输入
+    Sexp,
+    /// 每个源文件一个 .tokens.json：按源代码顺序排列的叶子记号 (种类、文本、位置)，供机器学习的分词使用；
+    /// 不是AST，不受 --include-kinds 等裁剪选项影响，也不能用作 --diff-base 或CFG生成器的输入
+    Tokens,
 }
 
-/// 自定义的、可序列化为JSON的AST节点结构
-/// 我们将tree-sitter的节点递归地转换为这个结构，以便使用serde进行序列化
+/// --format 的一个值：AST格式，或语法树的图导出格式 (共用CFG、CPG生成器的导出层，图的JSON不适用于AST，不提供)
+#[derive(Clone, Copy, Debug)]
+enum FormatArg {
+    Ast(OutputFormat),
+    Graph(ExportFormat),
+}
+
+impl ValueEnum for FormatArg {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            FormatArg::Ast(OutputFormat::Json),
+            FormatArg::Ast(OutputFormat::Jsonl),
+            FormatArg::Ast(OutputFormat::Msgpack),
+            FormatArg::Ast(OutputFormat::Cbor),
+            FormatArg::Ast(OutputFormat::Sexp),
+            FormatArg::Ast(OutputFormat::Tokens),
+            FormatArg::Graph(ExportFormat::Dot),
+            FormatArg::Graph(ExportFormat::Graphml),
+            FormatArg::Graph(ExportFormat::Html),
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            FormatArg::Ast(format) => format.to_possible_value(),
+            FormatArg::Graph(format) => format.to_possible_value(),
+        }
+    }
+}
+
+/// --format tokens 写出的文件加在源文件路径之后的后缀
+const TOKENS_EXTENSION: &str = "tokens.json";
+
+impl OutputFormat {
+    /// 逐文件写出的AST的编码；jsonl 的记录总是JSON
+    fn encoding(self) -> Encoding {
+        match self {
+            // 记号流不是AST，不经过这里的编码
+            OutputFormat::Json | OutputFormat::Jsonl | OutputFormat::Tokens => Encoding::Json,
+            OutputFormat::Msgpack => Encoding::Msgpack,
+            OutputFormat::Cbor => Encoding::Cbor,
+            OutputFormat::Sexp => Encoding::Sexp,
+        }
+    }
+}
+
+impl Args {
+    /// 把 --format 拆成AST格式 (默认 json) 和图导出格式
+    fn split_formats(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut formats = vec![];
+        for format in &self.formats {
+            match *format {
+                FormatArg::Ast(format) if !formats.contains(&format) => formats.push(format),
+                FormatArg::Graph(format) if !self.exports.contains(&format) => self.exports.push(format),
+                _ => {}
+            }
+        }
+        if formats.len() > 1 {
+            return Err("--format 中只能有一种AST格式 (json、jsonl、msgpack、cbor、sexp、tokens)".into());
+        }
+        self.format = formats.first().copied().unwrap_or_default();
+        Ok(())
+    }
+
+    /// 是否复用上一次运行的AST：--incremental，或 --watch 重新生成时 (JSONL文件只能整体重写)
+    fn reuse_previous(&self) -> bool {
+        self.incremental || (self.watch && self.format != OutputFormat::Jsonl)
+    }
+
+    /// 写报告、IDL和 manifest.json 的目录：--format jsonl 时为JSONL文件所在的目录
+    fn output_dir(&self) -> &Path {
+        match self.format {
+            OutputFormat::Json
+            | OutputFormat::Msgpack
+            | OutputFormat::Cbor
+            | OutputFormat::Sexp
+            | OutputFormat::Tokens => &self.output,
+            OutputFormat::Jsonl => match self.output.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            },
+        }
+    }
+}
+
+/// 日志的输出格式
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    /// 人类可读的文本格式
+    Text,
+    /// 每行一个JSON对象
+    Json,
+}
+
+/// 根据命令行参数初始化 tracing 日志
+/// 日志统一写到 stderr；设置了 RUST_LOG 环境变量时以其为准
+fn init_logging(args: &Args) {
+    let default_level = if args.quiet {
+        "warn"
+    } else {
+        match args.verbose {
+            0 => "info",
+            1 => "debug",
+            _ => "trace",
+        }
+    };
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    match args.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
+/// 被跳过的文件的原因
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum SkipReason {
+    /// 解析超过了 --timeout-per-file
+    Timeout,
+    /// 估算的内存占用超过了 --memory-limit
+    MemoryLimit,
+    /// tree-sitter 无法解析该文件
+    ParseFailed,
+    /// 没有对应语言的语法
+    Unsupported,
+    /// 按内容判断为二进制文件
+    Binary,
+    /// 超过了 --max-file-size
+    TooLarge,
+    /// 读取、序列化或写入时发生错误
+    Error,
+}
+
+/// skipped.json 中的一条记录
 #[derive(Serialize, Debug)]
-struct SerializableNode {
-    kind: String,       // 节点的类型，例如 "function_item", "identifier"
-    text: String,       // 该节点覆盖的源代码文本片段
-    start_byte: usize,  // 在源文件中的起始字节位置
-    end_byte: usize,    // 在源文件中的结束字节位置
-    children: Vec<SerializableNode>, // 该节点的子节点列表
-}
-
-/// 递归函数，将tree-sitter的Node转换为我们的SerializableNode
-/// 这是一个深度优先的遍历过程
-fn node_to_serializable(node: Node, source_code: &str) -> SerializableNode {
-    // 递归地为所有子节点调用此函数
-    let children: Vec<SerializableNode> = node
-        .children(&mut node.walk())
-        .map(|child| node_to_serializable(child, source_code))
-        .collect();
+struct SkippedItem {
+    path: PathBuf,
+    reason: SkipReason,
+    detail: String,
+}
+
+/// --timings 输出中的一条记录
+#[derive(Serialize, Debug)]
+struct FileTiming {
+    path: PathBuf, // 相对于输入目录
+    bytes: u64,
+    millis: f64,
+}
+
+/// 单个文件的处理结果
+enum FileOutcome {
+    /// AST 已写入输出目录
+    Written(Artifact),
+    /// 增量模式下源文件未变化，复用上一次的AST
+    Reused(Artifact),
+    /// 文件被跳过
+    Skipped(SkipReason, String),
+}
 
-    SerializableNode {
-        kind: node.kind().to_string(),
-        text: node
-            .utf8_text(source_code.as_bytes())
-            .unwrap_or("") // 如果文本不是有效的UTF-8，则返回空字符串
-            .to_string(),
-        start_byte: node.start_byte(),
-        end_byte: node.end_byte(),
-        children,
+/// manifest.json 的结构：记录本次运行产生的所有文件，供下游判断产物是否过期
+#[derive(Serialize, Debug)]
+struct Manifest {
+    tool: &'static str,
+    tool_version: &'static str,
+    schema_version: u32,
+    generated_at: String,
+    options: OutputOptions,
+    artifacts: Vec<Artifact>,
+}
+
+/// 输出目录下的AST索引文件名
+const INDEX_FILE_NAME: &str = "index.json";
+
+/// index.json 的结构：列出本次运行产生的所有AST，下游不必遍历输出目录、按命名规则推断对应的源文件
+#[derive(Serialize, Debug)]
+struct Index<'a> {
+    schema_version: u32,
+    tool_version: &'static str,
+    generated_at: &'a str,
+    asts: Vec<IndexEntry<'a>>,
+}
+
+/// index.json 中的一个AST
+#[derive(Serialize, Debug)]
+struct IndexEntry<'a> {
+    path: &'a Path, // 相对于输出目录；--format jsonl 时为JSONL文件，由 source 区分其中的记录
+    kind: ArtifactKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<&'a Path>, // 相对于输入目录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<Language>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_hash: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<&'a Provenance>,
+}
+
+/// 影响AST内容的选项；增量模式下只有选项与上一次相同时才复用已有的AST
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+struct OutputOptions {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    include_kinds: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exclude_kinds: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    named_only: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    no_text: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    split_functions: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compress: Option<Compression>,
+    /// 逐文件的二进制格式，JSON时省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<OutputFormat>,
+    /// 语法树的图导出格式
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exports: Vec<ExportFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    content_addressed: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    subtokens: bool,
+    /// 流式写出的上限会截断AST；--stream-above 本身不改变输出，不记录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_max_size: Option<u64>,
+}
+
+impl OutputOptions {
+    fn new(args: &Args) -> Self {
+        let sorted = |kinds: &[String]| {
+            let mut kinds = kinds.to_vec();
+            kinds.sort();
+            kinds.dedup();
+            kinds
+        };
+        OutputOptions {
+            include_kinds: sorted(&args.include_kinds),
+            exclude_kinds: sorted(&args.exclude_kinds),
+            named_only: args.named_only,
+            no_text: args.no_text,
+            split_functions: args.split_functions,
+            compress: args.compress,
+            format: (args.format != OutputFormat::Json).then_some(args.format),
+            exports: args.exports.clone(),
+            max_depth: args.max_depth,
+            content_addressed: args.content_addressed,
+            subtokens: args.subtokens,
+            stream_max_depth: args.stream_max_depth,
+            stream_max_size: args.stream_max_size,
+        }
+    }
+}
+
+/// 增量模式下读取的上一次运行的 manifest.json
+#[derive(Deserialize, Debug)]
+struct PreviousManifest {
+    tool_version: String,
+    #[serde(default)]
+    schema_version: u32,
+    #[serde(default)]
+    options: OutputOptions,
+    artifacts: Vec<Artifact>,
+}
+
+impl PreviousManifest {
+    /// 读取输出目录中已有的 manifest.json；不存在、无法解析、由其他版本生成或格式版本不同时返回 None
+    fn load(output_dir: &Path) -> Option<PreviousManifest> {
+        let content = fs::read_to_string(output_dir.join("manifest.json")).ok()?;
+        let manifest: PreviousManifest = serde_json::from_str(&content).ok()?;
+        (manifest.tool_version == env!("CARGO_PKG_VERSION")
+            && manifest.schema_version == SCHEMA_VERSION)
+            .then_some(manifest)
+    }
+
+    /// 以源文件路径为键、输出文件仍然存在的AST产物；选项与上一次不同时没有可复用的AST
+    fn reusable_asts(&self, output_dir: &Path, options: &OutputOptions) -> HashMap<PathBuf, Artifact> {
+        if &self.options != options {
+            return HashMap::new();
+        }
+        self.artifacts
+            .iter()
+            .filter(|a| matches!(a.kind, ArtifactKind::Ast) && output_dir.join(&a.path).is_file())
+            .filter_map(|a| Some((a.source.clone()?, a.clone())))
+            .collect()
+    }
+
+    /// 以源文件路径为键、文件仍然存在的函数AST和语法树的图导出，随复用的AST一起保留
+    fn derived_artifacts(&self, output_dir: &Path) -> HashMap<PathBuf, Vec<Artifact>> {
+        let mut functions: HashMap<PathBuf, Vec<Artifact>> = HashMap::new();
+        for artifact in self.artifacts.iter().filter(|a| {
+            matches!(a.kind, ArtifactKind::FunctionAst | ArtifactKind::GraphExport)
+                && output_dir.join(&a.path).is_file()
+        }) {
+            if let Some(source) = &artifact.source {
+                functions.entry(source.clone()).or_default().push(artifact.clone());
+            }
+        }
+        functions
+    }
+}
+
+/// 产物的类别
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ArtifactKind {
+    Ast,
+    Idl,
+    Report,
+    /// --expand 展开宏后的crate源码
+    ExpandedSource,
+    /// 展开后的源码的AST
+    ExpandedAst,
+    /// --split-functions 拆出的单个函数的AST
+    FunctionAst,
+    /// --format jsonl 时JSONL文件中的一条记录，path 为该文件
+    AstRecord,
+    /// 一个crate的 anchor.json
+    Anchor,
+    /// --format dot/graphml/html 写出的语法树的图
+    GraphExport,
+}
+
+/// manifest.json 中的一条产物记录
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Artifact {
+    path: PathBuf, // 相对于输出目录
+    kind: ArtifactKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<PathBuf>, // 相对于输入目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_hash: Option<String>,
+    hash: String,
+    /// AST类产物的源代码语言和节点数，写入 index.json；复用的AST从 manifest.json 中沿用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<Language>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    node_count: Option<usize>,
+    /// 输入目录在 git 仓库中时源文件的来源，每次运行重新确定，复用的AST不沿用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    provenance: Option<Provenance>,
+}
+
+/// 计算内容哈希 (blake3，十六进制)
+fn content_hash(bytes: &[u8]) -> String {
+    blake3::hash(bytes).to_hex().to_string()
+}
+
+/// 写入输出的时间戳 (RFC 3339，精确到秒)
+/// 设置了 SOURCE_DATE_EPOCH 时使用该时间而不是当前时间，使重复运行的输出逐字节相同
+fn timestamp() -> String {
+    let time = source_date_epoch()
+        .map_or_else(SystemTime::now, |secs| UNIX_EPOCH + Duration::from_secs(secs));
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// SOURCE_DATE_EPOCH 给出的时间 (秒)；设置了它时要求输出可重现，耗时等每次运行都不同的内容不写入输出
+fn source_date_epoch() -> Option<u64> {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+}
+
+/// 写入一个报告文件，并返回它在 manifest 中的记录
+fn write_report(output_dir: &Path, name: &str, content: String) -> Result<Artifact, Box<dyn Error>> {
+    fs::write(output_dir.join(name), &content)?;
+    Ok(Artifact {
+        path: PathBuf::from(name),
+        kind: ArtifactKind::Report,
+        source: None,
+        source_hash: None,
+        hash: content_hash(content.as_bytes()),
+        language: None,
+        node_count: None,
+        provenance: None,
+    })
+}
+
+thread_local! {
+    /// 每个工作线程的tree-sitter解析器，在该线程处理的所有文件间重用
+    /// rayon 的 map_init 会为每次任务拆分各创建一个解析器，放在线程局部变量中才能保证每个线程只有一个
+    static PARSER: RefCell<TreeSitterParser> = RefCell::new(TreeSitterParser::new());
+}
+
+/// 解析 + 序列化一个文件的峰值内存大约是源文件大小的这么多倍
+/// (每个节点都保存了自己的文本片段，JSON 还会再膨胀一次)
+const MEMORY_ESTIMATE_FACTOR: u64 = 128;
+
+/// 流式写出时只有 tree-sitter 的语法树常驻内存
+const STREAM_MEMORY_ESTIMATE_FACTOR: u64 = 16;
+
+/// 所有工作线程共享的内存预算
+/// 每个任务开始前按估算值申请预算，预算不足时等待其他任务释放
+struct MemoryBudget {
+    limit: u64,
+    in_use: Mutex<u64>,
+    released: Condvar,
+}
+
+/// 已申请的预算，离开作用域时自动归还
+struct BudgetGuard<'a> {
+    budget: &'a MemoryBudget,
+    cost: u64,
+}
+
+impl MemoryBudget {
+    fn new(limit: u64) -> Self {
+        MemoryBudget {
+            limit,
+            in_use: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// 申请 `cost` 字节的预算；单个任务的估算值超过总预算时返回 None
+    fn acquire(&self, cost: u64) -> Option<BudgetGuard<'_>> {
+        if cost > self.limit {
+            return None;
+        }
+        let mut in_use = self.in_use.lock().unwrap();
+        while *in_use + cost > self.limit {
+            in_use = self.released.wait(in_use).unwrap();
+        }
+        *in_use += cost;
+        Some(BudgetGuard { budget: self, cost })
+    }
+}
+
+impl Drop for BudgetGuard<'_> {
+    fn drop(&mut self) {
+        *self.budget.in_use.lock().unwrap() -= self.cost;
+        self.budget.released.notify_all();
+    }
+}
+
+/// 所有文件共用的处理设置
+struct FileSettings {
+    /// 单个文件的解析超时
+    timeout: Option<Duration>,
+    /// 输出的AST中保留哪些节点、是否带文本
+    ast: AstOptions,
+    /// --query 给出的查询
+    queries: Option<QuerySet>,
+    /// 是否把每个函数写成单独的AST
+    split_functions: bool,
+    /// --format jsonl 时所有AST写入的文件
+    jsonl: Option<JsonlSink>,
+    /// AST文件的编码和压缩格式
+    encoding: Encoding,
+    compression: Option<Compression>,
+    /// 不小于该大小 (字节) 的源文件流式写出
+    stream_above: Option<u64>,
+    stream_limits: StreamLimits,
+    /// 大于该大小 (字节) 的源文件被跳过
+    max_file_size: Option<u64>,
+    /// AST是否按源文件内容的哈希存放
+    content_addressed: bool,
+    /// --format tokens：写出叶子记号而不是AST，以及是否拆分标识符
+    tokens: bool,
+    subtokens: bool,
+    /// 语法树的图导出格式
+    exports: Vec<ExportFormat>,
+    /// 源文件 (相对于输入目录) 的来源
+    provenance: HashMap<PathBuf, Provenance>,
+}
+
+impl FileSettings {
+    /// 写在该源文件的AST根节点上的来源；--content-addressed 时同一个AST可能对应多个源文件，只记录在 manifest.json 中
+    fn root_provenance(&self, relative_path: &Path) -> Option<&Provenance> {
+        self.provenance
+            .get(relative_path)
+            .filter(|_| !self.content_addressed)
+    }
+
+    /// 该大小的源文件是否流式写出
+    fn streams(&self, size: u64) -> bool {
+        self.stream_above.is_some_and(|above| size >= above)
     }
+
+    /// AST的输出路径 (压缩时另加后缀)：与源文件保持相同的目录结构，例如 "lib.rs" -> "lib.rs.ast.json"，
+    /// --content-addressed 时在 objects/ 下按内容的哈希命名
+    fn ast_path(
+        &self,
+        output_dir: &Path,
+        relative_path: &Path,
+        source_hash: &str,
+        extension: &str,
+    ) -> PathBuf {
+        if self.content_addressed {
+            output_dir.join(dedup::object_path(source_hash, relative_path, extension))
+        } else {
+            with_suffix(&output_dir.join(relative_path), extension)
+        }
+    }
+
+    /// 有节点被截断时，errors.json 中的记录；原因为适用于该文件的上限
+    fn truncation(
+        &self,
+        file: &Path,
+        truncated_nodes: usize,
+        streamed: bool,
+    ) -> Option<TruncatedFile> {
+        if truncated_nodes == 0 {
+            return None;
+        }
+        let (max_depth, max_bytes) = if streamed {
+            (
+                self.ast.max_depth.into_iter().chain(self.stream_limits.max_depth).min(),
+                self.stream_limits.max_bytes,
+            )
+        } else {
+            (self.ast.max_depth, None)
+        };
+        let limits: Vec<String> = max_depth
+            .map(|depth| format!("最大深度 {}", depth))
+            .into_iter()
+            .chain(max_bytes.map(|bytes| format!("最大大小 {} 字节", bytes)))
+            .collect();
+        Some(TruncatedFile {
+            file: file.to_path_buf(),
+            truncated_nodes,
+            detail: format!("超出{}", limits.join("或")),
+        })
+    }
+}
+
+/// --format jsonl 的输出文件，各工作线程依次追加记录
+struct JsonlSink {
+    /// 相对于输出目录
+    path: PathBuf,
+    writer: Mutex<BufWriter<File>>,
+}
+
+/// JSONL文件中的一行
+#[derive(Serialize)]
+struct AstRecord<'a> {
+    /// 源文件，相对于输入目录
+    path: &'a Path,
+    /// 源文件的内容哈希
+    hash: &'a str,
+    ast: &'a SerializableNode,
+}
+
+/// 处理单个文件时顺带产生的、写入报告文件的内容
+#[derive(Default)]
+struct FileReports {
+    /// --query 的匹配
+    matches: Vec<QueryMatch>,
+    /// 语法错误，写入 errors.json
+    errors: Option<FileErrors>,
+    /// 定义的符号，写入 symbols.json
+    symbols: Vec<Symbol>,
+    /// Anchor 的程序、指令和账户结构体，写入所在crate的 anchor.json
+    anchor: Anchor,
+    /// 不是有效UTF-8时的解码记录，写入 encoding.json
+    encoding: Option<LossyFile>,
+    /// AST被截断时的记录，写入 errors.json
+    truncated: Option<TruncatedFile>,
+    /// 行数和语法树中每种节点的个数，写入 stats.json
+    lines: Option<usize>,
+    kinds: HashMap<&'static str, usize>,
+    /// --split-functions 写出的函数AST
+    functions: Vec<Artifact>,
+    /// --format dot/graphml/html 写出的语法树的图
+    exports: Vec<Artifact>,
 }
 
 /// 核心处理函数：解析单个文件并保存其AST
+/// `previous` 是增量模式下上一次为该文件生成的AST，源文件内容未变化时直接复用
+/// 查询的匹配、语法错误和编码记录在 `reports` 中
 fn process_file(
     source_path: &Path,
     input_dir: &Path,
     output_dir: &Path,
     parser: &mut TreeSitterParser,
-) -> Result<(), Box<dyn Error>> {
-    println!("正在处理: {}", source_path.display());
+    settings: &FileSettings,
+    previous: Option<&Artifact>,
+    reports: &mut FileReports,
+) -> Result<FileOutcome, Box<dyn Error>> {
+    let timeout = settings.timeout;
+    debug!(path = %source_path.display(), "正在处理");
 
-    // 步骤 1: 读取源代码文件内容
-    let source_code = fs::read_to_string(source_path)?;
+    // 步骤 1: 读取源代码文件内容；按原始字节解析，不是有效UTF-8的文件也不中止，字节位置始终对应原文件
+    let source_code = fs::read(source_path)?;
+    if encoding::is_binary(&source_code) {
+        return Ok(FileOutcome::Skipped(SkipReason::Binary, "文件中有 NUL 字节，视为二进制文件".into()));
+    }
+    let relative_path = source_path.strip_prefix(input_dir)?;
+    reports.lines = Some(stats::count_lines(&source_code));
+    reports.encoding = encoding::check(&source_code, relative_path);
+    if let Some(lossy) = &reports.encoding {
+        warn!(
+            path = %source_path.display(),
+            invalid = lossy.invalid_sequences,
+            "文件不是有效的UTF-8，节点文本中的无效字节替换为 U+FFFD"
+        );
+    }
+    let source_hash = content_hash(&source_code);
+    // 来源中只有 HEAD 不同时复用，把新的来源写入已有AST的根节点，不重新解析；源文件的路径、最后修改的提交或
+    // 未提交的修改变化时重新生成。流式写出的AST不读回内存，HEAD 不同时也重新生成
+    let provenance = settings.root_provenance(relative_path);
+    let rewrite = |a: &Artifact| {
+        !settings.content_addressed
+            && !settings.tokens
+            && settings.encoding != Encoding::Sexp
+            && a.provenance.as_ref() != provenance
+    };
+    if let Some(previous) = previous.filter(|a| {
+        a.source_hash.as_ref() == Some(&source_hash)
+            && (settings.content_addressed
+                || match (&a.provenance, provenance) {
+                    (Some(old), Some(new)) => old.same_origin(new),
+                    (old, new) => old.is_none() && new.is_none(),
+                })
+            && !(rewrite(a) && settings.streams(source_code.len() as u64))
+    }) {
+        let mut reused = previous.clone();
+        if rewrite(previous) {
+            let written = ast_file::rewrite_provenance(&output_dir.join(&previous.path), provenance)?;
+            reused.hash = content_hash(&written);
+            debug!(path = %source_path.display(), "源文件未变化，复用已有AST并更新其中的来源");
+        } else {
+            debug!(path = %source_path.display(), "源文件未变化，复用已有AST");
+        }
+        return Ok(FileOutcome::Reused(reused));
+    }
 
     // 步骤 2: 根据文件扩展名选择正确的语言语法
-    // **FIXED**: 使用每个crate提供的安全的、公共的language()函数，
-    // 而不是使用 extern "C" 块。
-    // 注意 tree-sitter-typescript 的函数名是 language_typescript()。
-    let language = match source_path.extension().and_then(|s| s.to_str()) {
-        Some("rs") => tree_sitter_rust::language(),
-        Some("ts") => tree_sitter_typescript::language_typescript(),
-        Some("js") => tree_sitter_javascript::language(),
-        _ => return Ok(()), // 安全地忽略不支持的文件类型
+    let language = match source_path
+        .extension()
+        .and_then(|s| s.to_str())
+        .and_then(Language::from_extension)
+    {
+        Some(language) => language,
+        // 安全地忽略不支持的文件类型
+        None => return Ok(FileOutcome::Skipped(SkipReason::Unsupported, "不支持的文件类型".into())),
     };
 
-    parser.set_language(&language)?;
+    let grammar = match language.grammar() {
+        Ok(grammar) => grammar,
+        Err(e) => return Ok(FileOutcome::Skipped(SkipReason::Unsupported, e)),
+    };
+    parser.set_language(&grammar)?;
+    // 0 表示不限制解析时间
+    parser.set_timeout_micros(timeout.map_or(0, |t| t.as_micros() as u64));
 
     // 步骤 3: 解析源代码生成AST (Tree)
     let tree: Tree = match parser.parse(&source_code, None) {
         Some(tree) => tree,
         None => {
-            // 如果tree-sitter无法解析文件，则打印警告并跳过
-            eprintln!("警告: 解析文件失败 {}", source_path.display());
-            return Ok(());
+            // 超时后解析器会保留中间状态，必须重置，否则下一个文件会接着上次的进度解析
+            parser.reset();
+            return Ok(match timeout {
+                Some(t) => FileOutcome::Skipped(
+                    SkipReason::Timeout,
+                    format!("解析超过 {} 秒", t.as_secs()),
+                ),
+                None => FileOutcome::Skipped(SkipReason::ParseFailed, "tree-sitter 解析失败".into()),
+            });
         }
     };
     
+    if let Some(queries) = &settings.queries {
+        reports
+            .matches
+            .extend(queries.run(language, &tree, &source_code, relative_path));
+    }
+    reports.errors = syntax::collect(&tree, &source_code, relative_path);
+    reports.kinds = stats::count_kinds(&tree);
+    reports.symbols = symbols::collect(&tree, &source_code, language, relative_path);
+    if language == Language::Rust {
+        reports.anchor = anchor::collect(&tree, &source_code, relative_path);
+    }
+    if let Some(errors) = &reports.errors {
+        warn!(path = %source_path.display(), count = errors.errors.len(), "文件有语法错误，AST不完整");
+    }
+
+    // 很大的文件边遍历边写出，不建出可序列化的结构
+    if settings.streams(source_code.len() as u64) {
+        let output_path =
+            settings.ast_path(output_dir, relative_path, &source_hash, Encoding::Json.extension());
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let (stats, hash) = ast_file::write_ast_streaming(
+            &output_path,
+            tree.root_node(),
+            &source_code,
+            &relative_path.to_string_lossy(),
+            &settings.ast,
+            settings.root_provenance(relative_path),
+            settings.stream_limits,
+        )?;
+        reports.truncated = settings.truncation(relative_path, stats.truncated, true);
+        if let Some(truncated) = &reports.truncated {
+            warn!(path = %source_path.display(), count = stats.truncated, detail = %truncated.detail, "AST被截断");
+        }
+        if settings.split_functions {
+            warn!(path = %source_path.display(), "流式写出的文件不拆分函数AST");
+        }
+        info!(
+            path = %source_path.display(),
+            output = %output_path.display(),
+            bytes = stats.bytes,
+            "AST已流式保存"
+        );
+        return Ok(FileOutcome::Written(Artifact {
+            path: output_path.strip_prefix(output_dir)?.to_path_buf(),
+            kind: ArtifactKind::Ast,
+            source: Some(relative_path.to_path_buf()),
+            source_hash: Some(source_hash),
+            hash,
+            language: Some(language),
+            node_count: Some(stats.nodes),
+            provenance: None,
+        }));
+    }
+
+    // --format tokens 只写出叶子记号，不建出AST
+    if settings.tokens {
+        let tokens = leaf_tokens(tree.root_node(), &source_code, settings.subtokens);
+        let output_path =
+            settings.ast_path(output_dir, relative_path, &source_hash, TOKENS_EXTENSION);
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let output = serde_json::to_vec_pretty(&tokens)?;
+        let (output_path, written) = ast_file::write_ast(&output_path, &output, settings.compression)?;
+        info!(
+            path = %source_path.display(),
+            output = %output_path.display(),
+            tokens = tokens.len(),
+            "记号已保存"
+        );
+        return Ok(FileOutcome::Written(Artifact {
+            path: output_path.strip_prefix(output_dir)?.to_path_buf(),
+            kind: ArtifactKind::Ast,
+            source: Some(relative_path.to_path_buf()),
+            source_hash: Some(source_hash),
+            hash: content_hash(&written),
+            language: Some(language),
+            node_count: None,
+            provenance: None,
+        }));
+    }
+
     // 步骤 4: 将整个AST转换为我们定义的可序列化结构
-    let serializable_root = node_to_serializable(tree.root_node(), &source_code);
-    // 使用serde_json将其转换为格式优美的JSON字符串
-    let json_output = serde_json::to_string_pretty(&serializable_root)?;
+    let mut serializable_root = node_to_serializable_with(
+        tree.root_node(),
+        &source_code,
+        &relative_path.to_string_lossy(),
+        &settings.ast,
+    );
+    serializable_root.set_provenance(settings.root_provenance(relative_path).cloned());
+    reports.truncated = settings.truncation(relative_path, serializable_root.truncated_count(), false);
+    if let Some(truncated) = &reports.truncated {
+        warn!(
+            path = %source_path.display(),
+            count = truncated.truncated_nodes,
+            detail = %truncated.detail,
+            "AST被截断"
+        );
+    }
+    if let Some(sink) = &settings.jsonl {
+        let record = serde_json::to_string(&AstRecord {
+            path: relative_path,
+            hash: &source_hash,
+            ast: &serializable_root,
+        })?;
+        writeln!(sink.writer.lock().unwrap(), "{}", record)?;
+        debug!(path = %source_path.display(), "AST已写入JSONL");
+        return Ok(FileOutcome::Written(Artifact {
+            path: sink.path.clone(),
+            kind: ArtifactKind::AstRecord,
+            source: Some(relative_path.to_path_buf()),
+            source_hash: Some(source_hash),
+            hash: content_hash(record.as_bytes()),
+            language: Some(language),
+            node_count: Some(serializable_root.node_count()),
+            provenance: None,
+        }));
+    }
+
+    // 使用serde_json将其转换为格式优美的JSON字符串 (--format msgpack/cbor/sexp 时为相应的编码)
+    let output = settings.encoding.encode(&serializable_root)?;
 
     // 步骤 5: 计算并创建输出路径，以保持原始的目录结构
-    let relative_path = source_path.strip_prefix(input_dir)?;
-    let mut output_path = output_dir.join(relative_path);
-    
     // 为输出文件添加新的后缀，例如 "lib.rs" -> "lib.rs.ast.json"
-    let new_extension = match output_path.extension() {
-        Some(ext) => format!("{}.ast.json", ext.to_str().unwrap_or("")),
-        None => "ast.json".to_string(),
-    };
-    output_path.set_extension(new_extension);
+    let output_path = settings.ast_path(
+        output_dir,
+        relative_path,
+        &source_hash,
+        settings.encoding.extension(),
+    );
 
     // 确保输出路径的父目录存在，如果不存在则创建
     if let Some(parent) = output_path.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    // 步骤 6: 将JSON字符串写入文件
-    fs::write(&output_path, json_output)?;
-    println!("  -> AST已保存至 {}", output_path.display());
+    // 步骤 6: 将编码后的AST写入文件，--compress 时压缩并加上后缀
+    let (output_path, written) = ast_file::write_ast(&output_path, &output, settings.compression)?;
+    info!(
+        path = %source_path.display(),
+        output = %output_path.display(),
+        "AST已保存"
+    );
 
-    Ok(())
+    if !settings.exports.is_empty() {
+        let generated_at = timestamp();
+        let metadata = ExportMetadata::new(relative_path, &source_hash, &generated_at);
+        let graphs = [syntax_graph(&serializable_root, relative_path)];
+        let export = Export {
+            metadata: &metadata,
+            graphs: &graphs,
+            dot: syntax_dot(&graphs[0]),
+        };
+        // 与AST同名，换成图的扩展名，例如 "lib.rs.ast.dot"
+        let base = settings.ast_path(output_dir, relative_path, &source_hash, "ast");
+        for (path, content) in export.write(&base, &settings.exports)? {
+            reports.exports.push(Artifact {
+                path: path.strip_prefix(output_dir)?.to_path_buf(),
+                kind: ArtifactKind::GraphExport,
+                source: Some(relative_path.to_path_buf()),
+                source_hash: Some(source_hash.clone()),
+                hash: content_hash(content.as_bytes()),
+                language: Some(language),
+                node_count: None,
+                provenance: None,
+            });
+        }
+    }
+
+    if settings.split_functions {
+        reports.functions = split_functions(
+            &serializable_root,
+            &reports.symbols,
+            &with_suffix(&output_dir.join(relative_path), "functions"),
+            output_dir,
+            relative_path,
+            language,
+            settings,
+        )?;
+    }
+
+    Ok(FileOutcome::Written(Artifact {
+        path: output_path.strip_prefix(output_dir)?.to_path_buf(),
+        kind: ArtifactKind::Ast,
+        source: Some(relative_path.to_path_buf()),
+        source_hash: Some(source_hash),
+        hash: content_hash(&written),
+        language: Some(language),
+        node_count: Some(serializable_root.node_count()),
+        provenance: None,
+    }))
+}
+
+/// 把文件中每个函数的子树写成单独的AST，文件名为函数的限定名 (:: 换成 .，其他不适合作文件名的字符换成 _)，
+/// 同名的函数依次加上 -2、-3；目录中上一次写出的函数AST先全部删除
+fn split_functions(
+    root: &SerializableNode,
+    symbols: &[Symbol],
+    dir: &Path,
+    output_dir: &Path,
+    relative_path: &Path,
+    language: Language,
+    settings: &FileSettings,
+) -> Result<Vec<Artifact>, Box<dyn Error>> {
+    let names: HashMap<&str, &str> = symbols
+        .iter()
+        .filter(|s| matches!(s.kind.as_str(), "function" | "method"))
+        .map(|s| (s.id.as_str(), s.path.as_str()))
+        .collect();
+    let mut functions = vec![];
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if let Some(name) = names.get(node.id()) {
+            functions.push((*name, node));
+        }
+        stack.extend(node.children().iter().rev());
+    }
+
+    let _ = fs::remove_dir_all(dir);
+    if !functions.is_empty() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut used = HashSet::new();
+    let mut artifacts = vec![];
+    for (name, node) in functions {
+        let base: String = name
+            .replace("::", ".")
+            .chars()
+            .map(|c| if c.is_alphanumeric() || "._-".contains(c) { c } else { '_' })
+            .collect();
+        let mut file_name = base.clone();
+        let mut n = 1;
+        while !used.insert(file_name.clone()) {
+            n += 1;
+            file_name = format!("{}-{}", base, n);
+        }
+        let path = dir.join(format!("{}.{}", file_name, settings.encoding.extension()));
+        let output = settings.encoding.encode(node)?;
+        let (path, written) = ast_file::write_ast(&path, &output, settings.compression)?;
+        artifacts.push(Artifact {
+            path: path.strip_prefix(output_dir)?.to_path_buf(),
+            kind: ArtifactKind::FunctionAst,
+            source: Some(relative_path.to_path_buf()),
+            source_hash: None,
+            hash: content_hash(&written),
+            language: Some(language),
+            node_count: Some(node.node_count()),
+            provenance: None,
+        });
+    }
+    debug!(path = %relative_path.display(), count = artifacts.len(), "函数AST已保存");
+    Ok(artifacts)
+}
+
+/// 读取一个 Anchor IDL，写出统一格式的 .idl.json
+/// 输入目录中的IDL保持原来的相对路径 (target/idl/x.json -> target/idl/x.idl.json)，其他位置的写到 idl/ 下
+fn process_idl(path: &Path, input_dir: &Path, output_dir: &Path) -> Result<Artifact, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    let value = serde_json::from_str(&content).map_err(|e| format!("无法解析IDL: {}", e))?;
+    let normalized = idl::normalize(&value)?;
+    let json_output = serde_json::to_string_pretty(&normalized)?;
+
+    let relative = path.strip_prefix(input_dir).ok();
+    let mut output_path = match relative {
+        Some(relative) => output_dir.join(relative),
+        None => output_dir.join("idl").join(path.file_name().unwrap_or_default()),
+    };
+    output_path.set_extension("idl.json");
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&output_path, &json_output)?;
+    info!(
+        path = %path.display(),
+        output = %output_path.display(),
+        program = %normalized.program,
+        instructions = normalized.instructions.len(),
+        "IDL已保存"
+    );
+
+    Ok(Artifact {
+        path: output_path.strip_prefix(output_dir)?.to_path_buf(),
+        kind: ArtifactKind::Idl,
+        source: relative.map(Path::to_path_buf),
+        source_hash: Some(content_hash(content.as_bytes())),
+        hash: content_hash(json_output.as_bytes()),
+        language: None,
+        node_count: None,
+        provenance: None,
+    })
+}
+
+/// 在路径后加上后缀，例如 ("src/lib.rs", "ast.json") -> "src/lib.rs.ast.json"
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// 展开一个crate的宏，写出展开后的源码及其AST
+/// 两个产物的 source 都是crate的根文件，与表层的AST由此对应
+fn process_expanded(
+    krate: &expand::Crate,
+    args: &Args,
+    parser: &mut TreeSitterParser,
+    settings: &FileSettings,
+) -> Result<Vec<Artifact>, Box<dyn Error>> {
+    let expanded = expand::expand(krate)?;
+    if let Some(max) = settings.max_file_size.filter(|&max| expanded.len() as u64 > max) {
+        return Err(format!(
+            "展开后的源码有 {} 字节，超过 --max-file-size ({} 字节)",
+            expanded.len(),
+            max
+        )
+        .into());
+    }
+    let relative_root = krate.root.strip_prefix(&args.input)?;
+    let relative_source = with_suffix(relative_root, EXPANDED_SUFFIX);
+
+    parser.set_language(&Language::Rust.grammar()?)?;
+    parser.set_timeout_micros(settings.timeout.map_or(0, |t| t.as_micros() as u64));
+    let tree = parser.parse(&expanded, None).ok_or_else(|| {
+        parser.reset();
+        "tree-sitter 无法在时限内解析展开后的源码"
+    })?;
+
+    let source_path = args.output_dir().join(&relative_source);
+    if let Some(parent) = source_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&source_path, &expanded)?;
+    // 展开后的源码常常比原文件大一个数量级，同样按大小决定是否流式写出
+    let (ast_path, hash, node_count) = if settings.streams(expanded.len() as u64) {
+        let ast_path = with_suffix(&source_path, Encoding::Json.extension());
+        let (stats, hash) = ast_file::write_ast_streaming(
+            &ast_path,
+            tree.root_node(),
+            expanded.as_bytes(),
+            &relative_source.to_string_lossy(),
+            &settings.ast,
+            None,
+            settings.stream_limits,
+        )?;
+        if stats.truncated > 0 {
+            warn!(manifest = %krate.manifest.display(), count = stats.truncated, "展开后的AST被截断");
+        }
+        (ast_path, hash, Some(stats.nodes))
+    } else if settings.tokens {
+        let tokens = leaf_tokens(tree.root_node(), expanded.as_bytes(), settings.subtokens);
+        let output = serde_json::to_vec_pretty(&tokens)?;
+        let tokens_path = with_suffix(&source_path, TOKENS_EXTENSION);
+        let (tokens_path, written) =
+            ast_file::write_ast(&tokens_path, &output, settings.compression)?;
+        (tokens_path, content_hash(&written), None)
+    } else {
+        let root = node_to_serializable_with(
+            tree.root_node(),
+            expanded.as_bytes(),
+            &relative_source.to_string_lossy(),
+            &settings.ast,
+        );
+        if root.truncated_count() > 0 {
+            warn!(manifest = %krate.manifest.display(), count = root.truncated_count(), "展开后的AST被截断");
+        }
+        let output = settings.encoding.encode(&root)?;
+        let ast_path = with_suffix(&source_path, settings.encoding.extension());
+        let (ast_path, written) = ast_file::write_ast(&ast_path, &output, settings.compression)?;
+        (ast_path, content_hash(&written), Some(root.node_count()))
+    };
+    info!(
+        manifest = %krate.manifest.display(),
+        output = %ast_path.display(),
+        "展开后的AST已保存"
+    );
+
+    let artifact = |path: &Path, kind, hash: String| -> Result<Artifact, Box<dyn Error>> {
+        Ok(Artifact {
+            path: path.strip_prefix(args.output_dir())?.to_path_buf(),
+            kind,
+            source: Some(relative_root.to_path_buf()),
+            source_hash: None,
+            hash,
+            language: Some(Language::Rust),
+            node_count: None,
+            provenance: None,
+        })
+    };
+    Ok(vec![
+        artifact(&source_path, ArtifactKind::ExpandedSource, content_hash(expanded.as_bytes()))?,
+        Artifact {
+            node_count,
+            ..artifact(&ast_path, ArtifactKind::ExpandedAst, hash)?
+        },
+    ])
+}
+
+/// 在资源限制下处理单个文件
+/// 成功时返回产物记录及其是否复用了上一次的AST，被跳过时返回 skipped.json 中对应的记录
+fn process_file_with_limits(
+    source_path: &Path,
+    args: &Args,
+    parser: &mut TreeSitterParser,
+    budget: Option<&MemoryBudget>,
+    previous: &HashMap<PathBuf, Artifact>,
+    settings: &FileSettings,
+    reports: &mut FileReports,
+) -> Result<(Artifact, bool), SkippedItem> {
+    let skipped = |reason, detail: String| {
+        warn!(path = %source_path.display(), ?reason, %detail, "跳过文件");
+        Err(SkippedItem {
+            path: source_path.to_path_buf(),
+            reason,
+            detail,
+        })
+    };
+
+    // 过大的文件不读取，以免一个文件占用全部内存
+    let size = fs::metadata(source_path).map_or(0, |m| m.len());
+    if let Some(max) = settings.max_file_size.filter(|&max| size > max) {
+        return skipped(
+            SkipReason::TooLarge,
+            format!("文件大小 {} 字节，超过 --max-file-size ({} 字节)", size, max),
+        );
+    }
+
+    // 先申请内存预算；guard 在本函数返回时归还
+    let _guard = match budget {
+        Some(budget) => {
+            let factor = if settings.streams(size) {
+                STREAM_MEMORY_ESTIMATE_FACTOR
+            } else {
+                MEMORY_ESTIMATE_FACTOR
+            };
+            match budget.acquire(size.saturating_mul(factor)) {
+                Some(guard) => Some(guard),
+                None => {
+                    return skipped(
+                        SkipReason::MemoryLimit,
+                        format!("文件大小 {} 字节，估算内存超出预算", size),
+                    )
+                }
+            }
+        }
+        None => None,
+    };
+
+    let relative_path = source_path.strip_prefix(&args.input).unwrap_or(source_path);
+    match process_file(
+        source_path,
+        &args.input,
+        args.output_dir(),
+        parser,
+        settings,
+        previous.get(relative_path),
+        reports,
+    ) {
+        Ok(FileOutcome::Written(artifact)) => Ok((artifact, false)),
+        Ok(FileOutcome::Reused(artifact)) => Ok((artifact, true)),
+        Ok(FileOutcome::Skipped(reason, detail)) => skipped(reason, detail),
+        Err(e) => {
+            error!(path = %source_path.display(), error = %e, "处理文件时发生错误");
+            skipped(SkipReason::Error, e.to_string())
+        }
+    }
+}
+
+/// 默认不进入的目录：构建产物、依赖和版本库元数据
+const DEFAULT_EXCLUDED_DIRS: &[&str] = &["target", "node_modules", ".git", "dist"];
+
+/// glob 中第一个通配符之前的目录部分，例如 "target/gen/**/*.rs" -> "target/gen"
+fn literal_prefix(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|c| {
+            !c.as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '[', '{'])
+        })
+        .collect()
+}
+
+/// 把一组 glob 编译成 GlobSet
+fn build_globset(patterns: &[String]) -> Result<GlobSet, Box<dyn Error>> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// 要分析的语言：--language 给出的，默认为所有支持的语言
+fn selected_languages(args: &Args) -> &[Language] {
+    if args.languages.is_empty() {
+        &Language::ALL
+    } else {
+        &args.languages
+    }
+}
+
+/// 在各个根目录下查找需要处理的源文件，并按语言和 include/exclude 规则过滤
+fn discover_source_files(args: &Args) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let include = build_globset(&args.include)?;
+    let exclude = build_globset(&args.exclude)?;
+    let languages = selected_languages(args);
+    let roots = if args.roots.is_empty() {
+        vec![args.input.clone()]
+    } else {
+        args.roots.iter().map(|root| args.input.join(root)).collect()
+    };
+
+    let include_prefixes: Vec<PathBuf> = args.include.iter().map(|p| literal_prefix(p)).collect();
+    let (input, dir_exclude, no_default_excludes) =
+        (args.input.clone(), exclude.clone(), args.no_default_excludes);
+    // 是否不进入该目录：匹配 --exclude，或是默认排除的目录且没有 --include 指向其中
+    // WalkBuilder 的过滤函数必须是 'static，因此捕获的都是副本
+    let pruned = move |dir: &Path| {
+        let relative = dir.strip_prefix(&input).unwrap_or(dir);
+        if dir_exclude.is_match(relative) {
+            return true;
+        }
+        let default_excluded = !no_default_excludes
+            && dir
+                .file_name()
+                .is_some_and(|name| DEFAULT_EXCLUDED_DIRS.contains(&name.to_string_lossy().as_ref()));
+        default_excluded && !include_prefixes.iter().any(|p| p.starts_with(relative))
+    };
+
+    let mut files = vec![];
+    for root in roots {
+        // 按文件名排序遍历，使文件的处理顺序以及 skipped.json 等输出不依赖文件系统的目录顺序
+        // 隐藏文件和 .ignore 文件不做特殊处理，只按 git 的规则忽略文件 (仅在 git 仓库中生效)
+        let gitignore = !args.no_gitignore;
+        let pruned = pruned.clone();
+        let walker = WalkBuilder::new(&root)
+            .standard_filters(false)
+            .parents(gitignore)
+            .git_ignore(gitignore)
+            .git_exclude(gitignore)
+            .git_global(gitignore)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |e| {
+                e.depth() == 0 || !e.file_type().is_some_and(|t| t.is_dir()) || !pruned(e.path())
+            })
+            .build();
+        for entry in walker
+            .filter_map(|e| e.ok()) // 过滤掉无效的目录条目
+            .filter(|e| e.path().is_file()) // 只关心文件
+        {
+            let path = entry.into_path();
+            // 根据文件扩展名过滤出支持且被选中的语言
+            let language = path
+                .extension()
+                .and_then(|s| s.to_str())
+                .and_then(Language::from_extension);
+            if !language.is_some_and(|l| languages.contains(&l)) {
+                continue;
+            }
+            // glob 规则作用于相对于输入目录的路径
+            let relative = path.strip_prefix(&args.input).unwrap_or(&path);
+            if !args.include.is_empty() && !include.is_match(relative) {
+                continue;
+            }
+            if exclude.is_match(relative) {
+                continue;
+            }
+            files.push(path);
+        }
+    }
+    Ok(files)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     // 解析命令行传入的参数
-    let args = Args::parse();
+    let mut args = Args::parse();
+    args.split_formats()?;
+    init_logging(&args);
+
+    if let Some(path) = &args.move_grammar {
+        load_move_grammar(path)?;
+        debug!(path = %path.display(), "已加载 Move 语法");
+    }
+    if args.subtokens && args.format != OutputFormat::Tokens {
+        return Err("--subtokens 只能用于 --format tokens".into());
+    }
+    if let Some(file) = &args.file {
+        if !args.exports.is_empty() {
+            return Err("单文件模式只输出AST，不能导出图 (--format dot/graphml/html)".into());
+        }
+        return process_single(&args, file);
+    }
 
     // 验证输入路径是否存在且为一个目录
     if !args.input.is_dir() {
         return Err(format!("输入路径 '{}' 不是一个有效的目录。", args.input.display()).into());
     }
 
-    println!("开始分析...");
-    println!("输入项目路径: {}", args.input.display());
-    println!("输出目录路径: {}", args.output.display());
+    info!(
+        input = %args.input.display(),
+        output = %args.output.display(),
+        "开始分析"
+    );
+
+    // JSONL文件中的记录只能追加，无法按文件替换，而拆分函数和AST比较需要逐文件的 .ast.json
+    if args.format == OutputFormat::Jsonl {
+        for (used, flag) in [
+            (args.incremental, "--incremental"),
+            (args.split_functions, "--split-functions"),
+            (args.diff_base.is_some(), "--diff-base"),
+            (args.compress.is_some(), "--compress"),
+            (args.content_addressed, "--content-addressed"),
+        ] {
+            if used {
+                return Err(format!("--format jsonl 不能与 {} 一起使用", flag).into());
+            }
+        }
+    }
+
+    // 记号流不是AST，无法拆分函数或与AST比较
+    if args.format == OutputFormat::Tokens {
+        for (used, flag) in [
+            (args.split_functions, "--split-functions"),
+            (args.diff_base.is_some(), "--diff-base"),
+        ] {
+            if used {
+                return Err(format!("--format tokens 不能与 {} 一起使用", flag).into());
+            }
+        }
+    }
+
+    // 流式写出的只有格式化的JSON
+    if args.stream_above.is_some() && args.format != OutputFormat::Json {
+        return Err("--stream-above 只能用于 --format json".into());
+    }
+
+    // 语法树的图由逐文件建出的AST转换而来
+    if !args.exports.is_empty() {
+        for (used, flag) in [
+            (args.format == OutputFormat::Jsonl, "--format jsonl"),
+            (args.format == OutputFormat::Tokens, "--format tokens"),
+            (args.stream_above.is_some(), "--stream-above"),
+        ] {
+            if used {
+                return Err(format!("--format dot/graphml/html 不能与 {} 一起使用", flag).into());
+            }
+        }
+    }
 
     // 如果输出目录不存在，则递归创建它
-    fs::create_dir_all(&args.output)?;
-    
-    // 初始化tree-sitter解析器。它将在所有文件的处理过程中被重用，以提高效率。
+    fs::create_dir_all(args.output_dir())?;
+
+    // 每个工作线程持有自己的tree-sitter解析器 (见 PARSER)
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()?;
+    if !args.watch {
+        return run(&args, &pool);
+    }
+    if let Err(e) = run(&args, &pool) {
+        error!(error = %e, "生成失败，继续监听");
+    }
+    watch(&args, &pool)
+}
+
+/// 由命令行参数得到输出AST的选项
+fn ast_options(args: &Args) -> AstOptions {
+    AstOptions {
+        include: args.include_kinds.iter().cloned().collect(),
+        exclude: args.exclude_kinds.iter().cloned().collect(),
+        named_only: args.named_only,
+        omit_text: args.no_text,
+        max_depth: args.max_depth,
+    }
+}
+
+/// 单文件模式：解析一个源文件 ("-" 为标准输入)，把AST写到标准输出
+/// --format json 时输出格式化的AST，与 .ast.json 相同；jsonl 时输出一行 JSONL 记录；tokens 时输出记号流
+/// 节点ID按给出的路径计算，与目录模式一致需要传入相对于项目根目录的路径
+fn process_single(args: &Args, file: &Path) -> Result<(), Box<dyn Error>> {
+    let stdin = file == Path::new("-");
+    let mut source_code = vec![];
+    if stdin {
+        std::io::stdin().read_to_end(&mut source_code)?;
+    } else {
+        source_code = fs::read(file)
+            .map_err(|e| format!("无法读取 '{}': {}", file.display(), e))?;
+    }
+    let language = match args.lang {
+        Some(language) => language,
+        None if stdin => return Err("从标准输入读取时必须用 --lang 指定语言".into()),
+        None => file
+            .extension()
+            .and_then(|s| s.to_str())
+            .and_then(Language::from_extension)
+            .ok_or_else(|| format!("无法由扩展名判断 '{}' 的语言，请用 --lang 指定", file.display()))?,
+    };
+    if encoding::is_binary(&source_code) {
+        return Err(format!("'{}' 中有 NUL 字节，视为二进制文件", file.display()).into());
+    }
+    if let Some(lossy) = encoding::check(&source_code, file) {
+        warn!(
+            path = %file.display(),
+            invalid = lossy.invalid_sequences,
+            "文件不是有效的UTF-8，节点文本中的无效字节替换为 U+FFFD"
+        );
+    }
+
     let mut parser = TreeSitterParser::new();
+    parser.set_language(&language.grammar()?)?;
+    parser.set_timeout_micros(
+        args.timeout_per_file
+            .map_or(0, |secs| secs.saturating_mul(1_000_000)),
+    );
+    let tree = parser
+        .parse(&source_code, None)
+        .ok_or("tree-sitter 解析失败或超时")?;
+    let errors = syntax::collect(&tree, &source_code, file);
+    if let Some(errors) = &errors {
+        warn!(path = %file.display(), count = errors.errors.len(), "文件有语法错误，AST不完整");
+    }
 
-    // (阶段1) 使用 walkdir 查找所有相关的源文件
-    for entry in WalkDir::new(&args.input)
-        .into_iter()
-        .filter_map(|e| e.ok()) // 过滤掉无效的目录条目
-        .filter(|e| e.path().is_file()) // 只关心文件
-    {
-        let path = entry.path();
-        // 根据文件扩展名进行最终过滤
-        if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-            if ["rs", "ts", "js"].contains(&ext) {
-                // (阶段2 & 3) 对找到的每个文件进行处理
-                if let Err(e) = process_file(path, &args.input, &args.output, &mut parser) {
-                    eprintln!(
-                        "处理文件 {} 时发生错误: {}",
-                        path.display(),
-                        e
-                    );
+    let root = node_to_serializable_with(
+        tree.root_node(),
+        &source_code,
+        &file.to_string_lossy(),
+        &ast_options(args),
+    );
+    let mut stdout = std::io::stdout().lock();
+    match args.format {
+        OutputFormat::Json => serde_json::to_writer_pretty(&mut stdout, &root)?,
+        OutputFormat::Tokens => serde_json::to_writer_pretty(
+            &mut stdout,
+            &leaf_tokens(tree.root_node(), &source_code, args.subtokens),
+        )?,
+        // 二进制格式原样写出，不加换行；S表达式自带结尾的换行
+        OutputFormat::Msgpack | OutputFormat::Cbor | OutputFormat::Sexp => {
+            stdout.write_all(&args.format.encoding().encode(&root)?)?;
+            return Ok(());
+        }
+        OutputFormat::Jsonl => serde_json::to_writer(
+            &mut stdout,
+            &AstRecord {
+                path: file,
+                hash: &content_hash(&source_code),
+                ast: &root,
+            },
+        )?,
+    }
+    writeln!(stdout)?;
+
+    if let (true, Some(errors)) = (args.fail_on_parse_error, errors) {
+        return Err(format!("'{}' 有 {} 处语法错误", file.display(), errors.errors.len()).into());
+    }
+    Ok(())
+}
+
+/// --watch 收到事件后，等待这么久没有新的事件再重新生成：一次保存常常产生多个事件 (写入临时文件、重命名等)
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// --watch：监听输入目录，有源文件变化时重新生成，直到进程被终止
+/// 重新生成时复用内容未变化的文件的AST (见 Args::reuse_previous)，报告和 manifest.json 随之更新
+fn watch(args: &Args, pool: &rayon::ThreadPool) -> Result<(), Box<dyn Error>> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&fs::canonicalize(&args.input)?, RecursiveMode::Recursive)?;
+    // 输出目录在输入目录之内时忽略其中的变化，否则每次生成都会触发下一次
+    let output_dir = fs::canonicalize(args.output_dir())?;
+    let relevant = |event: notify::Result<notify::Event>| -> Vec<PathBuf> {
+        match event {
+            Ok(event) if !event.kind.is_access() => event
+                .paths
+                .into_iter()
+                .filter(|path| {
+                    !path.starts_with(&output_dir)
+                        && path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+                            e == "json" || Language::from_extension(e).is_some()
+                        })
+                })
+                .collect(),
+            Ok(_) => vec![],
+            Err(e) => {
+                warn!(error = %e, "监听出错");
+                vec![]
+            }
+        }
+    };
+    info!(input = %args.input.display(), "正在监听源文件的变化");
+    loop {
+        let mut changed = relevant(receiver.recv()?);
+        while let Ok(event) = receiver.recv_timeout(WATCH_DEBOUNCE) {
+            changed.extend(relevant(event));
+        }
+        if changed.is_empty() {
+            continue;
+        }
+        changed.sort();
+        changed.dedup();
+        for path in &changed {
+            debug!(path = %path.display(), "源文件有变化");
+        }
+        info!(files = changed.len(), "源文件有变化，重新生成");
+        if let Err(e) = run(args, pool) {
+            error!(error = %e, "生成失败，继续监听");
+        }
+    }
+}
+
+/// 一次完整的生成：遍历输入目录、并行处理所有文件，写出报告和 manifest.json
+fn run(args: &Args, pool: &rayon::ThreadPool) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+
+    // (阶段1) 遍历输入目录，查找所有相关的源文件
+    let source_files = discover_source_files(args)?;
+    debug!(count = source_files.len(), "找到源文件");
+
+    // (阶段2 & 3) 在线程池中并行处理每个文件
+    let budget = args
+        .memory_limit
+        .map(|mib| MemoryBudget::new(mib.saturating_mul(1024 * 1024)));
+    let previous_manifest = if args.reuse_previous() {
+        PreviousManifest::load(args.output_dir())
+    } else {
+        None
+    };
+    // 查询需要每个文件的语法树，因此使用查询时不复用未变化文件的AST
+    let previous = previous_manifest
+        .as_ref()
+        .filter(|_| args.queries.is_empty())
+        .map(|m| m.reusable_asts(args.output_dir(), &OutputOptions::new(args)))
+        .unwrap_or_default();
+    // 每次运行重新确定来源：源文件没有变化，HEAD 也可能已经不同
+    let mut provenance = HashMap::new();
+    if let Some(repo) = Repo::discover(&args.input).filter(|_| !args.no_provenance) {
+        let sources: Vec<PathBuf> = source_files
+            .iter()
+            .filter_map(|path| Some(path.strip_prefix(&args.input).ok()?.to_path_buf()))
+            .collect();
+        match repo.provenance(&sources) {
+            Ok(found) => provenance = found,
+            Err(e) => warn!(error = %e, "无法读取 git 历史，AST中不记录来源"),
+        }
+    }
+    let settings = FileSettings {
+        timeout: args.timeout_per_file.map(Duration::from_secs),
+        ast: ast_options(args),
+        split_functions: args.split_functions,
+        encoding: args.format.encoding(),
+        compression: args.compress,
+        stream_above: args.stream_above.map(|mib| mib * 1024 * 1024),
+        stream_limits: StreamLimits {
+            max_depth: args.stream_max_depth,
+            max_bytes: args.stream_max_size.map(|mib| mib * 1024 * 1024),
+        },
+        max_file_size: args.max_file_size.map(|mib| mib * 1024 * 1024),
+        content_addressed: args.content_addressed,
+        tokens: args.format == OutputFormat::Tokens,
+        subtokens: args.subtokens,
+        exports: args.exports.clone(),
+        provenance,
+        jsonl: match args.format {
+            OutputFormat::Json
+            | OutputFormat::Msgpack
+            | OutputFormat::Cbor
+            | OutputFormat::Sexp
+            | OutputFormat::Tokens => None,
+            OutputFormat::Jsonl => Some(JsonlSink {
+                path: args
+                    .output
+                    .strip_prefix(args.output_dir())
+                    .unwrap_or(&args.output)
+                    .to_path_buf(),
+                writer: Mutex::new(BufWriter::new(File::create(&args.output)?)),
+            }),
+        },
+        queries: if args.queries.is_empty() {
+            None
+        } else {
+            Some(QuerySet::load(&args.queries, selected_languages(args))?)
+        },
+    };
+    // 内容相同的文件只处理一个，其余的在汇总时复制记录
+    let (representatives, duplicates) = if args.content_addressed {
+        pool.install(|| Duplicates::find(&source_files, &args.input))
+    } else {
+        (source_files.clone(), Duplicates::default())
+    };
+    if duplicates.count() > 0 {
+        debug!(count = duplicates.count(), "内容重复的文件不再单独处理");
+    }
+    let results: Vec<_> = pool.install(|| {
+        representatives
+            .par_iter()
+            .map(|path| {
+                let started = Instant::now();
+                let mut reports = FileReports::default();
+                let result = PARSER.with_borrow_mut(|parser| {
+                    process_file_with_limits(
+                        path,
+                        args,
+                        parser,
+                        budget.as_ref(),
+                        &previous,
+                        &settings,
+                        &mut reports,
+                    )
+                });
+                let timing = FileTiming {
+                    path: path.strip_prefix(&args.input).unwrap_or(path).to_path_buf(),
+                    bytes: fs::metadata(path).map_or(0, |m| m.len()),
+                    millis: started.elapsed().as_secs_f64() * 1000.0,
+                };
+                (result, timing, reports)
+            })
+            .collect()
+    });
+    if let Some(sink) = &settings.jsonl {
+        sink.writer.lock().unwrap().flush()?;
+        info!(output = %args.output.display(), "AST已写入JSONL文件");
+    }
+    let (mut artifacts, mut skipped, mut timings) = (vec![], vec![], vec![]);
+    let mut reused = 0;
+    let (mut query_matches, mut syntax_errors, mut symbols) = (vec![], vec![], vec![]);
+    let mut truncated = vec![];
+    let mut lossy_files = vec![];
+    let mut file_stats = vec![];
+    let mut anchor = Anchor::default();
+    // 复用的AST没有重新解析，其语法错误、符号和 Anchor 结构沿用上一次的 errors.json、symbols.json 和 anchor.json
+    let ((mut previous_errors, mut previous_truncated), mut previous_symbols) =
+        if previous.is_empty() {
+            Default::default()
+        } else {
+            (
+                ErrorReport::load_previous(args.output_dir()),
+                symbols::load_previous(args.output_dir()),
+            )
+        };
+    let mut previous_anchor = match &previous_manifest {
+        Some(manifest) if !previous.is_empty() => anchor::load_previous(
+            args.output_dir(),
+            manifest
+                .artifacts
+                .iter()
+                .filter(|a| matches!(a.kind, ArtifactKind::Anchor))
+                .map(|a| a.path.as_path()),
+        ),
+        _ => HashMap::new(),
+    };
+    let mut previous_derived = match &previous_manifest {
+        Some(manifest) if !previous.is_empty() => manifest.derived_artifacts(args.output_dir()),
+        _ => HashMap::new(),
+    };
+    for (result, timing, reports) in results {
+        let mut stats = FileStats {
+            file: timing.path.clone(),
+            lines: reports.lines,
+            bytes: timing.bytes,
+            millis: timing.millis,
+            kinds: reports.kinds,
+            ..FileStats::default()
+        };
+        query_matches.extend(reports.matches);
+        syntax_errors.extend(reports.errors);
+        truncated.extend(reports.truncated);
+        symbols.extend(reports.symbols);
+        anchor.extend(reports.anchor);
+        lossy_files.extend(reports.encoding);
+        artifacts.extend(reports.functions);
+        artifacts.extend(reports.exports);
+        match result {
+            Ok((artifact, was_reused)) => {
+                reused += usize::from(was_reused);
+                stats.reused = was_reused;
+                stats.nodes = artifact.node_count;
+                if was_reused {
+                    syntax_errors.extend(previous_errors.remove(&timing.path));
+                    truncated.extend(previous_truncated.remove(&timing.path));
+                    symbols.extend(previous_symbols.remove(&timing.path).unwrap_or_default());
+                    anchor.extend(previous_anchor.remove(&timing.path).unwrap_or_default());
+                    artifacts.extend(previous_derived.remove(&timing.path).unwrap_or_default());
+                }
+                artifacts.push(artifact);
+            }
+            Err(item) => {
+                stats.skipped = true;
+                skipped.push(item);
+            }
+        }
+        file_stats.push(stats);
+        timings.push(timing);
+    }
+    let regenerated = representatives.len() - reused - skipped.len();
+    if duplicates.count() > 0 {
+        duplicates.fan_out(&mut artifacts, |artifact| match artifact.kind {
+            ArtifactKind::Ast | ArtifactKind::GraphExport => artifact.source.as_mut(),
+            _ => None,
+        });
+        duplicates.fan_out(&mut symbols, |symbol| Some(&mut symbol.file));
+        duplicates.fan_out(&mut anchor.declare_ids, |declared| Some(&mut declared.file));
+        duplicates.fan_out(&mut anchor.programs, |program| Some(&mut program.file));
+        duplicates.fan_out(&mut anchor.accounts, |accounts| Some(&mut accounts.file));
+        duplicates.fan_out(&mut anchor.account_types, |account| Some(&mut account.file));
+        duplicates.fan_out(&mut syntax_errors, |errors| Some(&mut errors.file));
+        duplicates.fan_out(&mut truncated, |truncated| Some(&mut truncated.file));
+        duplicates.fan_out(&mut lossy_files, |lossy| Some(&mut lossy.file));
+        duplicates.fan_out(&mut query_matches, |matched| Some(&mut matched.file));
+        let skipped_duplicates: Vec<SkippedItem> = skipped
+            .iter()
+            .flat_map(|item| {
+                let file = item.path.strip_prefix(&args.input).unwrap_or(&item.path);
+                duplicates.of(file).iter().map(|duplicate| SkippedItem {
+                    path: args.input.join(duplicate),
+                    reason: item.reason,
+                    detail: item.detail.clone(),
+                })
+            })
+            .collect();
+        skipped.extend(skipped_duplicates);
+        // 重复的文件没有单独处理，不计耗时
+        let stats_duplicates: Vec<FileStats> = file_stats
+            .iter()
+            .flat_map(|stats| {
+                duplicates.of(&stats.file).iter().map(|duplicate| FileStats {
+                    file: duplicate.clone(),
+                    millis: 0.0,
+                    ..stats.clone()
+                })
+            })
+            .collect();
+        file_stats.extend(stats_duplicates);
+    }
+    for artifact in &mut artifacts {
+        artifact.provenance = match artifact.kind {
+            ArtifactKind::Ast | ArtifactKind::AstRecord | ArtifactKind::FunctionAst => artifact
+                .source
+                .as_ref()
+                .and_then(|source| settings.provenance.get(source).cloned()),
+            _ => None,
+        };
+    }
+    if settings.queries.is_some() {
+        artifacts.push(write_report(
+            args.output_dir(),
+            QUERY_FILE_NAME,
+            serde_json::to_string_pretty(&query_matches)?,
+        )?);
+        info!(
+            matches = query_matches.len(),
+            output = %args.output_dir().join(QUERY_FILE_NAME).display(),
+            "查询结果已保存"
+        );
+    }
+    symbols.sort_by(|a, b| (&a.file, a.start_byte).cmp(&(&b.file, b.start_byte)));
+    artifacts.push(write_report(
+        args.output_dir(),
+        SYMBOLS_FILE_NAME,
+        serde_json::to_string_pretty(&symbols)?,
+    )?);
+    debug!(count = symbols.len(), "符号表已保存");
+    if !anchor.is_empty() {
+        let crates = anchor.by_crate(&args.input);
+        for krate in crates.values() {
+            let path = krate.crate_dir.join(ANCHOR_FILE_NAME);
+            fs::create_dir_all(args.output_dir().join(&krate.crate_dir))?;
+            artifacts.push(Artifact {
+                kind: ArtifactKind::Anchor,
+                ..write_report(
+                    args.output_dir(),
+                    &path.to_string_lossy(),
+                    serde_json::to_string_pretty(krate)?,
+                )?
+            });
+        }
+        info!(crates = crates.len(), "Anchor 结构已保存");
+    }
+    let error_report = ErrorReport::new(syntax_errors, truncated);
+    artifacts.push(write_report(
+        args.output_dir(),
+        ERRORS_FILE_NAME,
+        serde_json::to_string_pretty(&error_report)?,
+    )?);
+    if error_report.files_with_errors > 0 {
+        warn!(
+            files = error_report.files_with_errors,
+            errors = error_report.error_count,
+            report = %args.output_dir().join(ERRORS_FILE_NAME).display(),
+            "部分文件有语法错误"
+        );
+    }
+    let stats = Stats::new(
+        selected_languages(args),
+        &file_stats,
+        &error_report.files,
+        source_date_epoch().is_none().then(|| started.elapsed()),
+    );
+    artifacts.push(write_report(
+        args.output_dir(),
+        STATS_FILE_NAME,
+        serde_json::to_string_pretty(&stats)?,
+    )?);
+    debug!(output = %args.output_dir().join(STATS_FILE_NAME).display(), "统计已保存");
+    if !error_report.truncated.is_empty() {
+        warn!(
+            files = error_report.truncated.len(),
+            report = %args.output_dir().join(ERRORS_FILE_NAME).display(),
+            "部分文件的AST被截断"
+        );
+    }
+    lossy_files.sort_by(|a: &LossyFile, b| a.file.cmp(&b.file));
+    artifacts.push(write_report(
+        args.output_dir(),
+        ENCODING_FILE_NAME,
+        serde_json::to_string_pretty(&lossy_files)?,
+    )?);
+    if !lossy_files.is_empty() {
+        warn!(
+            files = lossy_files.len(),
+            report = %args.output_dir().join(ENCODING_FILE_NAME).display(),
+            "部分文件不是有效的UTF-8，已有损解码"
+        );
+    }
+    if !args.no_idl {
+        for path in idl::idl_files(&args.input, &args.idls) {
+            match process_idl(&path, &args.input, args.output_dir()) {
+                Ok(artifact) => artifacts.push(artifact),
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "跳过IDL");
+                    skipped.push(SkippedItem {
+                        path,
+                        reason: SkipReason::Error,
+                        detail: e.to_string(),
+                    });
+                }
+            }
+        }
+    }
+    if args.expand {
+        for krate in expand::crates(&source_files) {
+            match PARSER.with_borrow_mut(|parser| process_expanded(&krate, args, parser, &settings)) {
+                Ok(expanded) => artifacts.extend(expanded),
+                Err(e) => {
+                    warn!(manifest = %krate.manifest.display(), error = %e, "跳过宏展开");
+                    skipped.push(SkippedItem {
+                        path: krate.manifest,
+                        reason: SkipReason::Error,
+                        detail: e.to_string(),
+                    });
                 }
             }
         }
     }
+    if let Some(base_dir) = &args.diff_base {
+        let base = PreviousManifest::load(base_dir).ok_or_else(|| {
+            format!(
+                "'{}' 中没有本版本生成的 manifest.json，--diff-base 须指向另一次运行的输出目录",
+                base_dir.display()
+            )
+        })?;
+        let asts = |artifacts: &[Artifact], dir: &Path| -> Vec<(PathBuf, PathBuf)> {
+            artifacts
+                .iter()
+                .filter(|a| matches!(a.kind, ArtifactKind::Ast))
+                .filter_map(|a| Some((a.source.clone()?, dir.join(&a.path))))
+                .collect()
+        };
+        let diff = diff::diff(
+            base_dir,
+            &asts(&base.artifacts, base_dir),
+            &asts(&artifacts, args.output_dir()),
+        )?;
+        artifacts.push(write_report(
+            args.output_dir(),
+            DIFF_FILE_NAME,
+            serde_json::to_string_pretty(&diff)?,
+        )?);
+        info!(
+            base = %base_dir.display(),
+            files_added = diff.files_added,
+            files_removed = diff.files_removed,
+            files_modified = diff.files_modified,
+            functions_modified = diff.functions_modified,
+            output = %args.output_dir().join(DIFF_FILE_NAME).display(),
+            "AST差异已保存"
+        );
+    }
+    if let Some(path) = &args.timings {
+        fs::write(path, serde_json::to_string_pretty(&timings)?)?;
+    }
+
+    // 记录所有被跳过的文件，便于在CI中检查覆盖率
+    let skipped_path = args.output_dir().join("skipped.json");
+    artifacts.push(write_report(
+        args.output_dir(),
+        "skipped.json",
+        serde_json::to_string_pretty(&skipped)?,
+    )?);
+    if !skipped.is_empty() {
+        warn!(
+            count = skipped.len(),
+            report = %skipped_path.display(),
+            "部分文件被跳过"
+        );
+    }
+
+    // 增量模式下删除上一次生成、但这次不再产生的AST和IDL (对应的源文件已被删除或排除)
+    if let Some(previous_manifest) = &previous_manifest {
+        let current: HashSet<&PathBuf> = artifacts.iter().map(|a| &a.path).collect();
+        for stale in previous_manifest
+            .artifacts
+            .iter()
+            .filter(|a| {
+                matches!(
+                    a.kind,
+                    ArtifactKind::Ast
+                        | ArtifactKind::Idl
+                        | ArtifactKind::ExpandedSource
+                        | ArtifactKind::ExpandedAst
+                        | ArtifactKind::FunctionAst
+                        | ArtifactKind::Anchor
+                        | ArtifactKind::GraphExport
+                ) && !current.contains(&a.path)
+            })
+        {
+            debug!(output = %stale.path.display(), "删除过期的AST");
+            let _ = fs::remove_file(args.output_dir().join(&stale.path));
+        }
+    }
+
+    // 写入 index.json，列出所有AST
+    artifacts.sort_by(|a, b| (&a.path, &a.source).cmp(&(&b.path, &b.source)));
+    let generated_at = timestamp();
+    let index = Index {
+        schema_version: SCHEMA_VERSION,
+        tool_version: env!("CARGO_PKG_VERSION"),
+        generated_at: &generated_at,
+        asts: artifacts
+            .iter()
+            .filter(|a| {
+                matches!(
+                    a.kind,
+                    ArtifactKind::Ast
+                        | ArtifactKind::AstRecord
+                        | ArtifactKind::ExpandedAst
+                        | ArtifactKind::FunctionAst
+                )
+            })
+            .map(|a| IndexEntry {
+                path: &a.path,
+                kind: a.kind,
+                source: a.source.as_deref(),
+                language: a.language,
+                source_hash: a.source_hash.as_deref(),
+                node_count: a.node_count,
+                provenance: a.provenance.as_ref(),
+            })
+            .collect(),
+    };
+    debug!(count = index.asts.len(), "AST索引已保存");
+    let index_artifact = write_report(
+        args.output_dir(),
+        INDEX_FILE_NAME,
+        serde_json::to_string_pretty(&index)?,
+    )?;
+    artifacts.push(index_artifact);
+
+    // 最后写入 manifest.json，列出本次运行产生的全部文件
+    artifacts.sort_by(|a, b| (&a.path, &a.source).cmp(&(&b.path, &b.source)));
+    let manifest = Manifest {
+        tool: env!("CARGO_PKG_NAME"),
+        tool_version: env!("CARGO_PKG_VERSION"),
+        schema_version: SCHEMA_VERSION,
+        generated_at,
+        options: OutputOptions::new(args),
+        artifacts,
+    };
+    fs::write(
+        args.output_dir().join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
 
-    println!("\n分析完成。所有AST文件已生成在 '{}' 目录中。", args.output.display());
+    info!(
+        output = %args.output.display(),
+        files = source_files.len(),
+        regenerated,
+        reused,
+        deduplicated = duplicates.count(),
+        skipped = skipped.len(),
+        "分析完成，所有AST文件已生成"
+    );
+    if args.fail_on_parse_error && error_report.files_with_errors > 0 {
+        return Err(format!(
+            "{} 个文件有 {} 处语法错误，见 {}",
+            error_report.files_with_errors,
+            error_report.error_count,
+            args.output_dir().join(ERRORS_FILE_NAME).display()
+        )
+        .into());
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{IndexAddOption, Repository, Signature};
+
+    /// 提交工作区中的所有文件
+    fn commit_all(repo: &Repository, message: &str) {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("dev", "dev@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .unwrap();
+    }
+
+    #[test]
+    fn unrelated_commit_reuses_ast() {
+        let dir = std::env::temp_dir().join(format!("solana_ast_generator-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (input, output) = (dir.join("program"), dir.join("ast"));
+        fs::create_dir_all(input.join("src")).unwrap();
+        fs::write(input.join("src/lib.rs"), "pub fn add(a: u64, b: u64) -> u64 {\n    a + b\n}\n").unwrap();
+        let repo = Repository::init(&input).unwrap();
+        commit_all(&repo, "add");
+
+        let mut args = Args::parse_from([
+            "solana_ast_generator".as_ref(),
+            "--input".as_ref(),
+            input.as_os_str(),
+            "--output".as_ref(),
+            output.as_os_str(),
+            "--incremental".as_ref(),
+        ]);
+        args.split_formats().unwrap();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        run(&args, &pool).unwrap();
+
+        // 与 lib.rs 无关的提交只改变 HEAD
+        fs::write(input.join("README.md"), "# program\n").unwrap();
+        commit_all(&repo, "readme");
+        run(&args, &pool).unwrap();
+
+        let read = |name: &str| -> serde_json::Value {
+            serde_json::from_slice(&fs::read(output.join(name)).unwrap()).unwrap()
+        };
+        assert_eq!(read(STATS_FILE_NAME)["languages"]["rust"]["reused"], 1);
+        let ast = read("src/lib.rs.ast.json");
+        let head = repo.head().unwrap().target().unwrap().to_string();
+        assert_eq!(ast["provenance"]["commit"], head.as_str());
+        assert_eq!(ast["provenance"]["last_commit"]["summary"], "add");
+        // manifest.json 中的哈希是更新来源后的内容
+        let manifest = read("manifest.json");
+        let artifact = manifest["artifacts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|a| a["path"] == "src/lib.rs.ast.json")
+            .unwrap();
+        let written = fs::read(output.join("src/lib.rs.ast.json")).unwrap();
+        assert_eq!(artifact["hash"], content_hash(&written).as_str());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
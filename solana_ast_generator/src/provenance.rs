@@ -0,0 +1,199 @@
+// provenance.rs
+//
+// AST的来源：输入目录在 git 仓库中时，为每个源文件记录它在仓库中的路径、生成时的提交 (HEAD) 和最后修改它的提交，
+// 写入AST的根节点以及 manifest.json 和 index.json 中对应的AST记录。基于AST和图生成的安全报告据此回溯到仓库中的代码和责任人，便于分诊
+
+use git2::{Diff, Oid, Repository, Sort, Status, StatusOptions};
+use solana_ast_generator::{CommitInfo, Provenance};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// 输入目录所在的 git 仓库
+pub struct Repo {
+    repository: Repository,
+    /// 输入目录相对于仓库根目录的路径
+    prefix: PathBuf,
+}
+
+impl Repo {
+    /// 向上查找包含输入目录的仓库；不在仓库中、仓库没有工作区或还没有提交时返回 None
+    pub fn discover(input_dir: &Path) -> Option<Repo> {
+        let repository = Repository::discover(input_dir).ok()?;
+        let workdir = repository.workdir()?.canonicalize().ok()?;
+        let prefix = input_dir
+            .canonicalize()
+            .ok()?
+            .strip_prefix(&workdir)
+            .ok()?
+            .to_path_buf();
+        repository.head().ok()?.peel_to_commit().ok()?;
+        Some(Repo { repository, prefix })
+    }
+
+    /// 为每个源文件 (相对于输入目录) 确定来源
+    /// 最后修改的提交沿 HEAD 的第一父提交链从新到旧查找，每个提交与其父提交比较一次树，所有文件都找到后停止
+    pub fn provenance(
+        &self,
+        files: &[PathBuf],
+    ) -> Result<HashMap<PathBuf, Provenance>, Box<dyn Error>> {
+        let head = self.repository.head()?.peel_to_commit()?;
+        let head_tree = head.tree()?;
+        let repo_paths: HashMap<PathBuf, &PathBuf> = files
+            .iter()
+            .map(|file| (self.prefix.join(file), file))
+            .collect();
+
+        // 只查找 HEAD 中有的文件，其余的从未提交过
+        let mut pending: HashSet<&Path> = repo_paths
+            .keys()
+            .filter(|path| head_tree.get_path(path).is_ok())
+            .map(PathBuf::as_path)
+            .collect();
+        let mut last_commits: HashMap<PathBuf, CommitInfo> = HashMap::new();
+        let mut walk = self.repository.revwalk()?;
+        walk.push(head.id())?;
+        walk.simplify_first_parent()?;
+        walk.set_sorting(Sort::TOPOLOGICAL)?;
+        for id in walk {
+            if pending.is_empty() {
+                break;
+            }
+            let commit = self.repository.find_commit(id?)?;
+            let parent_tree = match commit.parent(0) {
+                Ok(parent) => Some(parent.tree()?),
+                Err(_) => None,
+            };
+            let diff = self.repository.diff_tree_to_tree(
+                parent_tree.as_ref(),
+                Some(&commit.tree()?),
+                None,
+            )?;
+            for path in changed_paths(&diff) {
+                if pending.remove(path.as_path()) {
+                    last_commits.insert(path, commit_info(&commit));
+                }
+            }
+        }
+
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).recurse_untracked_dirs(true);
+        if !self.prefix.as_os_str().is_empty() {
+            options.pathspec(&self.prefix);
+        }
+        let uncommitted: HashSet<PathBuf> = self
+            .repository
+            .statuses(Some(&mut options))?
+            .iter()
+            .filter(|entry| !entry.status().intersects(Status::CURRENT | Status::IGNORED))
+            .filter_map(|entry| entry.path().map(PathBuf::from))
+            .collect();
+
+        let commit = head.id().to_string();
+        Ok(repo_paths
+            .into_iter()
+            .map(|(repo_path, file)| {
+                let provenance = Provenance {
+                    last_commit: last_commits.remove(&repo_path),
+                    uncommitted: uncommitted.contains(&repo_path),
+                    commit: commit.clone(),
+                    repo_path,
+                };
+                (file.clone(), provenance)
+            })
+            .collect())
+    }
+}
+
+/// 差异中新增或修改的文件 (删除的文件不在 HEAD 中，不需要)
+fn changed_paths(diff: &Diff) -> Vec<PathBuf> {
+    diff.deltas()
+        .filter(|delta| delta.new_file().id() != Oid::zero())
+        .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf))
+        .collect()
+}
+
+fn commit_info(commit: &git2::Commit) -> CommitInfo {
+    let author = commit.author();
+    let seconds = u64::try_from(author.when().seconds()).unwrap_or(0);
+    CommitInfo {
+        id: commit.id().to_string(),
+        author: author.name().unwrap_or_default().to_string(),
+        time: humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(seconds))
+            .to_string(),
+        summary: commit.summary().unwrap_or_default().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{IndexAddOption, Signature};
+    use std::fs;
+
+    /// 提交工作区中的所有文件，返回提交的ID
+    fn commit_all(repo: &Repository, message: &str) -> String {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("dev", "dev@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    #[test]
+    fn last_commit_and_uncommitted_changes_per_file() {
+        let dir = std::env::temp_dir().join(format!(
+            "solana_ast_generator-provenance-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let input = dir.join("program");
+        fs::create_dir_all(input.join("src")).unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        // 还没有提交的仓库不记录来源
+        assert!(Repo::discover(&input).is_none());
+
+        fs::write(input.join("src/a.rs"), "fn a() {}\n").unwrap();
+        fs::write(input.join("src/b.rs"), "fn b() {}\n").unwrap();
+        let first = commit_all(&repo, "add a and b");
+        fs::write(input.join("src/b.rs"), "fn b() { 1; }\n").unwrap();
+        let second = commit_all(&repo, "change b");
+        fs::write(dir.join("README.md"), "# readme\n").unwrap();
+        let head = commit_all(&repo, "readme");
+        // a.rs 有未提交的修改，c.rs 未被跟踪
+        fs::write(input.join("src/a.rs"), "fn a() { 2; }\n").unwrap();
+        fs::write(input.join("src/c.rs"), "fn c() {}\n").unwrap();
+
+        let files = ["src/a.rs", "src/b.rs", "src/c.rs"].map(PathBuf::from);
+        let provenance = Repo::discover(&input).unwrap().provenance(&files).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let get = |file: &str| &provenance[Path::new(file)];
+        let last = |file: &str| get(file).last_commit.as_ref().map(|c| c.id.as_str());
+        assert!(files.iter().all(|f| provenance[f].commit == head));
+        assert_eq!(get("src/a.rs").repo_path, Path::new("program/src/a.rs"));
+        assert_eq!(last("src/a.rs"), Some(first.as_str()));
+        assert_eq!(last("src/b.rs"), Some(second.as_str()));
+        assert_eq!(last("src/c.rs"), None);
+        assert_eq!(
+            get("src/b.rs").last_commit.as_ref().unwrap().summary,
+            "change b"
+        );
+        assert_eq!(get("src/b.rs").last_commit.as_ref().unwrap().author, "dev");
+        let uncommitted: Vec<_> = files.iter().map(|f| provenance[f].uncommitted).collect();
+        assert_eq!(uncommitted, [true, false, true]);
+    }
+}
@@ -0,0 +1,126 @@
+// query.rs
+//
+// --query：对每个文件运行用户给出的 tree-sitter 查询 (.scm)，把捕获的节点写入 query.json
+// 查询是针对某种语言的语法写的，每个查询文件只用于能编译它的语言，例如 (call_expression) 只对 Rust 有效
+
+use serde::Serialize;
+use solana_ast_generator::{node_text_lossy, Language};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use tree_sitter::{Node, Query, QueryCursor, Tree};
+
+/// 输出文件名，位于输出目录下
+pub const QUERY_FILE_NAME: &str = "query.json";
+
+/// 一个查询文件针对各语言编译的结果
+pub struct QuerySet {
+    /// (查询文件, 语言, 编译后的查询)
+    queries: Vec<(PathBuf, Language, Query)>,
+}
+
+/// 查询的一个匹配
+#[derive(Serialize, Debug, Clone)]
+pub struct QueryMatch {
+    /// 源文件，相对于输入目录
+    pub file: PathBuf,
+    /// 查询文件
+    pub query: PathBuf,
+    /// 查询文件中匹配的模式的序号，从0开始
+    pub pattern: usize,
+    pub captures: Vec<Capture>,
+}
+
+/// 一个被捕获的节点；行列号从1开始，与 .ast.json 相同
+#[derive(Serialize, Debug, Clone)]
+pub struct Capture {
+    pub name: String,
+    pub kind: String,
+    pub text: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Capture {
+    fn new(name: &str, node: Node, source: &[u8]) -> Self {
+        let (start, end) = (node.start_position(), node.end_position());
+        Capture {
+            name: name.to_string(),
+            kind: node.kind().to_string(),
+            text: node_text_lossy(node, source).into_owned(),
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+            start_line: start.row + 1,
+            start_column: start.column + 1,
+            end_line: end.row + 1,
+            end_column: end.column + 1,
+        }
+    }
+}
+
+impl QuerySet {
+    /// 读取查询文件并针对每种语言编译；一个查询文件不能用于任何语言时返回错误，并给出各语言的编译错误
+    pub fn load(paths: &[PathBuf], languages: &[Language]) -> Result<Self, Box<dyn Error>> {
+        let mut queries = vec![];
+        for path in paths {
+            let source = fs::read_to_string(path)
+                .map_err(|e| format!("无法读取查询 '{}': {}", path.display(), e))?;
+            let mut errors = vec![];
+            for &language in languages {
+                // 没有加载语法的语言 (Move) 直接跳过
+                let Ok(grammar) = language.grammar() else {
+                    continue;
+                };
+                match Query::new(&grammar, &source) {
+                    Ok(query) => queries.push((path.clone(), language, query)),
+                    Err(e) => {
+                        debug!(query = %path.display(), ?language, error = %e, "查询不适用于该语言");
+                        errors.push(format!("{:?}: {}", language, e));
+                    }
+                }
+            }
+            if !queries.iter().any(|(p, _, _)| p == path) {
+                return Err(format!(
+                    "查询 '{}' 不能用于任何语言:\n  {}",
+                    path.display(),
+                    errors.join("\n  ")
+                )
+                .into());
+            }
+        }
+        Ok(QuerySet { queries })
+    }
+
+    /// 在一个文件的语法树上运行适用于该语言的所有查询
+    pub fn run(
+        &self,
+        language: Language,
+        tree: &Tree,
+        source: &[u8],
+        file: &Path,
+    ) -> Vec<QueryMatch> {
+        let mut cursor = QueryCursor::new();
+        let mut matches = vec![];
+        for (path, _, query) in self.queries.iter().filter(|(_, l, _)| *l == language) {
+            let names = query.capture_names();
+            for m in cursor.matches(query, tree.root_node(), source) {
+                matches.push(QueryMatch {
+                    file: file.to_path_buf(),
+                    query: path.clone(),
+                    pattern: m.pattern_index,
+                    captures: m
+                        .captures
+                        .iter()
+                        .map(|c| Capture::new(names[c.index as usize], c.node, source))
+                        .collect(),
+                });
+            }
+        }
+        matches
+    }
+}
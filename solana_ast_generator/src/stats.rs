@@ -0,0 +1,148 @@
+// stats.rs
+//
+// 统计报告：每次运行后写出 stats.json，按语言统计文件数、行数、节点数 (以及每种节点的个数)、语法错误数和耗时，
+// 构建语料时不必另写脚本就能检查覆盖情况 (例如为什么一个 TypeScript 文件都没有)
+
+use crate::syntax::FileErrors;
+use serde::Serialize;
+use solana_ast_generator::Language;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tree_sitter::Tree;
+
+/// 输出文件名，位于输出目录下
+pub const STATS_FILE_NAME: &str = "stats.json";
+
+/// 处理一个源文件时收集的统计
+#[derive(Debug, Clone, Default)]
+pub struct FileStats {
+    /// 源文件，相对于输入目录
+    pub file: PathBuf,
+    /// 行数；读取源文件之前就被跳过时没有
+    pub lines: Option<usize>,
+    pub bytes: u64,
+    pub millis: f64,
+    pub reused: bool,
+    pub skipped: bool,
+    /// 写出的AST中的节点数
+    pub nodes: Option<usize>,
+    /// 语法树中每种节点的个数；只有本次解析了的文件才有
+    pub kinds: HashMap<&'static str, usize>,
+}
+
+/// stats.json 的内容
+#[derive(Serialize, Debug)]
+pub struct Stats {
+    /// 源文件总数
+    pub files: usize,
+    /// 处理所有源文件的耗时 (毫秒)；设置了 SOURCE_DATE_EPOCH 时省略，使输出可重现
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<f64>,
+    /// 每种选中的语言，没有文件的语言也列出
+    pub languages: BTreeMap<Language, LanguageStats>,
+}
+
+/// 一种语言的统计
+#[derive(Serialize, Debug, Default)]
+pub struct LanguageStats {
+    /// 源文件数，包括被跳过的
+    pub files: usize,
+    /// 增量模式下复用上一次的AST的文件数
+    pub reused: usize,
+    /// 被跳过的文件数 (见 skipped.json)
+    pub skipped: usize,
+    pub lines: usize,
+    pub bytes: u64,
+    /// 写出的AST中的节点数之和
+    pub nodes: usize,
+    /// 有语法错误的文件数和语法错误总数 (见 errors.json)
+    pub files_with_errors: usize,
+    pub parse_errors: usize,
+    /// 处理这些文件的耗时之和 (毫秒)；多个线程并行处理，各语言之和可能超过总耗时。与总耗时同样可能省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<f64>,
+    /// 语法树中每种节点的个数，不含复用了AST、没有重新解析的文件
+    pub kinds: BTreeMap<&'static str, usize>,
+}
+
+impl Stats {
+    /// 按语言汇总每个文件的统计；文件的语言由扩展名判断
+    /// `elapsed` 为 None 时不记录任何耗时
+    pub fn new(
+        languages: &[Language],
+        files: &[FileStats],
+        errors: &[FileErrors],
+        elapsed: Option<Duration>,
+    ) -> Self {
+        let mut stats = Stats {
+            files: files.len(),
+            elapsed_ms: elapsed.map(|elapsed| elapsed.as_secs_f64() * 1000.0),
+            languages: languages
+                .iter()
+                .map(|&language| {
+                    let stats = LanguageStats {
+                        elapsed_ms: elapsed.map(|_| 0.0),
+                        ..LanguageStats::default()
+                    };
+                    (language, stats)
+                })
+                .collect(),
+        };
+        for file in files {
+            let Some(language) = stats.language(&file.file) else {
+                continue;
+            };
+            language.files += 1;
+            language.reused += usize::from(file.reused);
+            language.skipped += usize::from(file.skipped);
+            language.lines += file.lines.unwrap_or(0);
+            language.bytes += file.bytes;
+            language.nodes += file.nodes.unwrap_or(0);
+            if elapsed.is_some() {
+                *language.elapsed_ms.get_or_insert(0.0) += file.millis;
+            }
+            for (kind, count) in &file.kinds {
+                *language.kinds.entry(kind).or_default() += count;
+            }
+        }
+        for errors in errors {
+            if let Some(language) = stats.language(&errors.file) {
+                language.files_with_errors += 1;
+                language.parse_errors += errors.errors.len();
+            }
+        }
+        stats
+    }
+
+    fn language(&mut self, file: &Path) -> Option<&mut LanguageStats> {
+        let language = file
+            .extension()
+            .and_then(|s| s.to_str())
+            .and_then(Language::from_extension)?;
+        Some(self.languages.entry(language).or_default())
+    }
+}
+
+/// 源代码的行数，最后一行没有换行符时也计入
+pub fn count_lines(source: &[u8]) -> usize {
+    source.iter().filter(|&&b| b == b'\n').count()
+        + usize::from(!source.is_empty() && !source.ends_with(b"\n"))
+}
+
+/// 语法树中每种节点的个数
+pub fn count_kinds(tree: &Tree) -> HashMap<&'static str, usize> {
+    let mut kinds = HashMap::new();
+    let mut cursor = tree.walk();
+    loop {
+        *kinds.entry(cursor.node().kind()).or_default() += 1;
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return kinds;
+            }
+        }
+    }
+}
@@ -0,0 +1,381 @@
+// stream.rs
+//
+// 流式写出AST：打包后的JS、展开后的宏等生成的文件可达数十MB，node_to_serializable 先在内存中建出整棵
+// SerializableNode 树再序列化，占用的内存是输出的数倍。这里在序列化的同时沿 tree-sitter 的游标逐个生成节点并写出，
+// 内存只与树的深度有关。输出与 node_to_serializable_with 的格式化JSON逐字节相同；另外可以限制深度和大小，超出时截断子树
+
+use super::{file_id, node_text_lossy, AstOptions, Comments, Keep, Provenance, ENCODING_UTF8_LOSSY};
+use serde::ser::{SerializeSeq, SerializeStruct};
+use serde::{Serialize, Serializer};
+use std::cell::Cell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use tree_sitter::Node;
+
+/// 流式写出的上限；超出时不再写出子节点，被截断的节点带有 "truncated": true
+/// 被截断的子树仍占用节点序号，其余节点的ID与不截断时相同；AstOptions::max_depth 同样适用，取两者中较小的
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamLimits {
+    /// 最大深度 (根节点为0)，这一深度的节点不再写出子节点
+    pub max_depth: Option<usize>,
+    /// 输出的最大字节数，超出后剩余的子节点都不再写出；已经开始的节点仍完整地结束，因此实际大小会略微超出
+    pub max_bytes: Option<u64>,
+}
+
+/// 一次流式写出的统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamStats {
+    /// 写出的节点数
+    pub nodes: usize,
+    /// 被截断的节点数
+    pub truncated: usize,
+    /// 写出的字节数
+    pub bytes: u64,
+}
+
+/// 以格式化的JSON流式写出AST，`path`、`options` 与 node_to_serializable_with 相同，`provenance` 写在根节点上
+/// 写入是逐个记号进行的，`writer` 应该带有缓冲
+pub fn write_json_streaming<W: Write>(
+    node: Node,
+    source: &[u8],
+    path: &str,
+    options: &AstOptions,
+    provenance: Option<&Provenance>,
+    limits: StreamLimits,
+    writer: W,
+) -> Result<StreamStats, serde_json::Error> {
+    let utf8 = std::str::from_utf8(source).ok();
+    let written = Rc::new(Cell::new(0));
+    let stream = Stream {
+        source,
+        utf8,
+        omit_text: options.omit_text && utf8.is_some(),
+        options,
+        provenance,
+        max_depth: options.max_depth.into_iter().chain(limits.max_depth).min(),
+        max_bytes: limits.max_bytes,
+        file_id: file_id(path),
+        next_index: Cell::new(0),
+        written: Rc::clone(&written),
+        nodes: Cell::new(0),
+        truncated: Cell::new(0),
+    };
+    let root = StreamNode {
+        stream: &stream,
+        node,
+        id: stream.next_id(),
+        parent: None,
+        field: None,
+        comments: vec![],
+        depth: 0,
+    };
+    let writer = Counting {
+        inner: writer,
+        written: Rc::clone(&written),
+    };
+    root.serialize(&mut serde_json::Serializer::pretty(writer))?;
+    Ok(StreamStats {
+        nodes: stream.nodes.get(),
+        truncated: stream.truncated.get(),
+        bytes: written.get(),
+    })
+}
+
+/// 统计写出的字节数，供 max_bytes 判断
+struct Counting<W> {
+    inner: W,
+    written: Rc<Cell<u64>>,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written.set(self.written.get() + n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 一次流式写出的状态；序列化只能拿到不可变引用，因此计数器用 Cell
+struct Stream<'a> {
+    source: &'a [u8],
+    /// 源文件是有效的UTF-8时为其文本
+    utf8: Option<&'a str>,
+    omit_text: bool,
+    options: &'a AstOptions,
+    provenance: Option<&'a Provenance>,
+    max_depth: Option<usize>,
+    max_bytes: Option<u64>,
+    file_id: String,
+    next_index: Cell<usize>,
+    written: Rc<Cell<u64>>,
+    nodes: Cell<usize>,
+    truncated: Cell<usize>,
+}
+
+impl Stream<'_> {
+    fn next_id(&self) -> String {
+        let index = self.next_index.get();
+        self.next_index.set(index + 1);
+        format!("{}:{}", self.file_id, index)
+    }
+
+    /// 跳过不写出的节点，保持后面的节点序号不变
+    fn skip(&self, count: usize) {
+        self.next_index.set(self.next_index.get() + count);
+    }
+
+    fn over_size(&self) -> bool {
+        self.max_bytes.is_some_and(|max| self.written.get() >= max)
+    }
+
+    /// 与 Serializer::children 相同地过滤子节点并逐个写出；`promoted` 为这些节点是否是被去掉的节点的子节点，
+    /// 此时字段名不再适用
+    fn children<S: SerializeSeq>(
+        &self,
+        seq: &mut S,
+        node: Node,
+        parent: &str,
+        depth: usize,
+        promoted: bool,
+        truncated: &Cell<bool>,
+    ) -> Result<(), S::Error> {
+        let mut comments = Comments::default();
+        let mut cursor = node.walk();
+        if cursor.goto_first_child() {
+            loop {
+                let child = cursor.node();
+                let attached = comments.next(child, self.source);
+                match self.options.keep(child) {
+                    Keep::Node if self.over_size() => {
+                        self.skip(child.descendant_count());
+                        truncated.set(true);
+                    }
+                    Keep::Node => seq.serialize_element(&StreamNode {
+                        stream: self,
+                        node: child,
+                        id: self.next_id(),
+                        parent: Some(parent.to_string()),
+                        field: if promoted { None } else { cursor.field_name() },
+                        comments: attached,
+                        depth: depth + 1,
+                    })?,
+                    Keep::ChildrenOnly => {
+                        self.skip(1);
+                        self.children(seq, child, parent, depth, true, truncated)?;
+                    }
+                    Keep::Nothing => self.skip(child.descendant_count()),
+                }
+                if !cursor.goto_next_sibling() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 写出时才生成的节点，字段与 SerializableNode 相同
+struct StreamNode<'s, 'a> {
+    stream: &'s Stream<'a>,
+    node: Node<'s>,
+    id: String,
+    parent: Option<String>,
+    field: Option<&'static str>,
+    comments: Vec<String>,
+    depth: usize,
+}
+
+impl Serialize for StreamNode<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (stream, node) = (self.stream, self.node);
+        stream.nodes.set(stream.nodes.get() + 1);
+        let (start, end) = (node.start_position(), node.end_position());
+        // 是否截断要等写完子节点才知道，字段数只能给出上限；JSON不使用它，但为0时会写成空对象
+        let mut state = serializer.serialize_struct("SerializableNode", 14)?;
+        state.serialize_field("id", &self.id)?;
+        if let Some(parent) = &self.parent {
+            state.serialize_field("parent", parent)?;
+        }
+        state.serialize_field("kind", node.kind())?;
+        if let Some(field) = self.field {
+            state.serialize_field("field", field)?;
+        }
+        if !self.comments.is_empty() {
+            state.serialize_field("comments", &self.comments)?;
+        }
+        if !stream.omit_text {
+            state.serialize_field("text", &node_text_lossy(node, stream.source))?;
+        }
+        state.serialize_field("start_byte", &node.start_byte())?;
+        state.serialize_field("end_byte", &node.end_byte())?;
+        state.serialize_field("start_line", &(start.row + 1))?;
+        state.serialize_field("start_column", &(start.column + 1))?;
+        state.serialize_field("end_line", &(end.row + 1))?;
+        state.serialize_field("end_column", &(end.column + 1))?;
+        let truncated = Cell::new(false);
+        state.serialize_field(
+            "children",
+            &StreamChildren {
+                parent: self,
+                truncated: &truncated,
+            },
+        )?;
+        if self.depth == 0 {
+            match stream.utf8 {
+                Some(source) if stream.omit_text => state.serialize_field("source", source)?,
+                Some(_) => {}
+                None => state.serialize_field("encoding", ENCODING_UTF8_LOSSY)?,
+            }
+            if let Some(provenance) = stream.provenance {
+                state.serialize_field("provenance", provenance)?;
+            }
+        }
+        if truncated.get() {
+            stream.truncated.set(stream.truncated.get() + 1);
+            state.serialize_field("truncated", &true)?;
+        }
+        state.end()
+    }
+}
+
+/// 节点的 children 字段
+struct StreamChildren<'n, 's, 'a> {
+    parent: &'n StreamNode<'s, 'a>,
+    truncated: &'n Cell<bool>,
+}
+
+impl Serialize for StreamChildren<'_, '_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let StreamNode {
+            stream, node, id, depth, ..
+        } = self.parent;
+        let mut seq = serializer.serialize_seq(None)?;
+        if stream.max_depth.is_some_and(|max| *depth >= max) && node.child_count() > 0 {
+            stream.skip(node.descendant_count() - 1);
+            self.truncated.set(true);
+        } else {
+            stream.children(&mut seq, *node, id, *depth, false, self.truncated)?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{node_to_serializable_with, Language};
+    use serde_json::Value;
+
+    const SOURCE: &str = r#"// 注释
+fn main() {
+    let x = vec![1, 2, 3];
+    if x.len() > 2 {
+        println!("{}", x[0]);
+    }
+}
+"#;
+
+    fn parse(source: &str) -> tree_sitter::Tree {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&Language::Rust.grammar().unwrap()).unwrap();
+        parser.parse(source, None).unwrap()
+    }
+
+    fn stream(options: &AstOptions, limits: StreamLimits) -> (String, StreamStats) {
+        let tree = parse(SOURCE);
+        let mut output = vec![];
+        let stats = write_json_streaming(
+            tree.root_node(),
+            SOURCE.as_bytes(),
+            "src/main.rs",
+            options,
+            None,
+            limits,
+            &mut output,
+        )
+        .unwrap();
+        (String::from_utf8(output).unwrap(), stats)
+    }
+
+    /// 所有节点的 (id, 深度)，按先序
+    fn ids(node: &Value, depth: usize, result: &mut Vec<(String, usize)>) {
+        result.push((node["id"].as_str().unwrap().to_string(), depth));
+        for child in node["children"].as_array().unwrap() {
+            ids(child, depth + 1, result);
+        }
+    }
+
+    #[test]
+    fn matches_in_memory_serializer() {
+        let tree = parse(SOURCE);
+        let options = [
+            AstOptions::default(),
+            AstOptions {
+                named_only: true,
+                omit_text: true,
+                max_depth: Some(3),
+                ..AstOptions::default()
+            },
+            AstOptions {
+                include: ["function_item", "let_declaration", "if_expression"]
+                    .map(String::from)
+                    .into(),
+                ..AstOptions::default()
+            },
+        ];
+        for options in &options {
+            let expected = serde_json::to_string_pretty(&node_to_serializable_with(
+                tree.root_node(),
+                SOURCE.as_bytes(),
+                "src/main.rs",
+                options,
+            ))
+            .unwrap();
+            let (output, stats) = stream(options, StreamLimits::default());
+            assert_eq!(output, expected);
+            assert_eq!(stats.bytes, output.len() as u64);
+        }
+    }
+
+    #[test]
+    fn max_depth_truncates_and_keeps_ids() {
+        let (full, _) = stream(&AstOptions::default(), StreamLimits::default());
+        let limits = StreamLimits {
+            max_depth: Some(2),
+            ..StreamLimits::default()
+        };
+        let (output, stats) = stream(&AstOptions::default(), limits);
+        assert!(stats.truncated > 0);
+
+        let mut all = vec![];
+        ids(&serde_json::from_str(&full).unwrap(), 0, &mut all);
+        let mut kept = vec![];
+        let root: Value = serde_json::from_str(&output).unwrap();
+        ids(&root, 0, &mut kept);
+        let expected: Vec<_> = all.into_iter().filter(|(_, depth)| *depth <= 2).collect();
+        assert_eq!(kept, expected);
+        assert_eq!(stats.nodes, kept.len());
+    }
+
+    #[test]
+    fn max_bytes_stops_writing_children() {
+        let (full, _) = stream(&AstOptions::default(), StreamLimits::default());
+        let limits = StreamLimits {
+            max_bytes: Some(1024),
+            ..StreamLimits::default()
+        };
+        let (output, stats) = stream(&AstOptions::default(), limits);
+        assert!(stats.truncated > 0);
+        assert!(output.len() < full.len());
+        // 截断后仍是完整的JSON，带 truncated 的节点数与统计一致
+        fn truncated(node: &Value) -> usize {
+            usize::from(node["truncated"] == true)
+                + node["children"].as_array().unwrap().iter().map(truncated).sum::<usize>()
+        }
+        assert_eq!(truncated(&serde_json::from_str(&output).unwrap()), stats.truncated);
+    }
+}
@@ -0,0 +1,255 @@
+// symbols.rs
+//
+// 符号表：列出每个文件中的函数、结构体、枚举、impl、trait、模块和 Anchor 的 #[program] 模块，写入 symbols.json
+// 后续阶段和审计人员由名字直接找到定义，不必遍历每个AST
+// 路径是近似的限定名：Rust 为 crate 名 + 由文件位置推出的模块路径 + 外层的 mod/impl/trait，其他语言只有外层的类和合约
+
+use serde::{Deserialize, Serialize};
+use solana_ast_generator::{file_id, node_text_lossy, Language};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tree_sitter::{Node, Tree};
+
+/// 输出文件名，位于输出目录下
+pub const SYMBOLS_FILE_NAME: &str = "symbols.json";
+
+/// 作为符号的节点种类，以及对应的符号种类
+const SYMBOL_KINDS: &[(&str, &str)] = &[
+    // Rust
+    ("function_item", "function"),
+    ("function_signature_item", "function"),
+    ("struct_item", "struct"),
+    ("enum_item", "enum"),
+    ("impl_item", "impl"),
+    ("trait_item", "trait"),
+    ("mod_item", "mod"),
+    // TypeScript/JavaScript
+    ("function_declaration", "function"),
+    ("class_declaration", "class"),
+    ("interface_declaration", "interface"),
+    ("method_definition", "function"),
+    // 赋给变量的箭头函数以变量名命名，其他的 (回调等) 没有名字，不作为符号
+    ("arrow_function", "function"),
+    // Python、Solidity、Move
+    ("function_definition", "function"),
+    ("class_definition", "class"),
+    ("contract_declaration", "contract"),
+    ("struct_declaration", "struct"),
+    ("enum_declaration", "enum"),
+];
+
+/// 其中的定义以它的名字为路径前缀的符号种类
+const CONTAINER_KINDS: &[&str] = &[
+    "impl",
+    "trait",
+    "mod",
+    "program",
+    "class",
+    "interface",
+    "contract",
+];
+
+/// 一个符号；行列号从1开始，与 .ast.json 相同
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    /// function、method (impl、trait 和类中的函数)、struct、enum、impl、trait、mod、program (Anchor 的 #[program] 模块)、
+    /// class、interface、contract
+    pub kind: String,
+    /// 近似的限定名，例如 vault::instructions::deposit::handler、vault::Vault::deposit
+    pub path: String,
+    /// trait 实现 (impl Trait for Type) 中的 trait
+    #[serde(rename = "trait", default, skip_serializing_if = "Option::is_none")]
+    pub trait_name: Option<String>,
+    /// 源文件，相对于输入目录
+    pub file: PathBuf,
+    /// 定义节点在 .ast.json 中的ID
+    pub id: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+/// 读取上一次运行写出的 symbols.json，按源文件分组；增量模式下复用的AST没有重新解析，沿用其中的记录
+pub fn load_previous(output_dir: &Path) -> HashMap<PathBuf, Vec<Symbol>> {
+    let symbols: Vec<Symbol> = fs::read_to_string(output_dir.join(SYMBOLS_FILE_NAME))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    let mut by_file: HashMap<PathBuf, Vec<Symbol>> = HashMap::new();
+    for symbol in symbols {
+        by_file.entry(symbol.file.clone()).or_default().push(symbol);
+    }
+    by_file
+}
+
+/// Rust 源文件的模块路径：src 的上一级目录名作为 crate 名，src 之下的目录和文件名作为模块，
+/// lib.rs、main.rs 和 mod.rs 不增加一级，例如 programs/vault/src/instructions/deposit.rs -> vault::instructions::deposit
+fn module_path(file: &Path) -> Vec<String> {
+    let components: Vec<String> = file
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    let Some(src) = components.iter().rposition(|c| c == "src") else {
+        return vec![];
+    };
+    let mut path = vec![];
+    if let Some(krate) = src.checked_sub(1).map(|i| &components[i]) {
+        path.push(krate.replace('-', "_"));
+    }
+    for (i, component) in components.iter().enumerate().skip(src + 1) {
+        if i + 1 == components.len() {
+            let stem = component.trim_end_matches(".rs");
+            if !["lib", "main", "mod"].contains(&stem) {
+                path.push(stem.to_string());
+            }
+        } else {
+            path.push(component.clone());
+        }
+    }
+    path
+}
+
+fn text<'a>(node: Node, source: &'a [u8]) -> Cow<'a, str> {
+    node_text_lossy(node, source)
+}
+
+/// mod 之前 (中间可以隔着注释和其他属性) 是否有 #[program] 属性
+fn is_program(node: Node, source: &[u8]) -> bool {
+    let mut previous = node.prev_sibling();
+    while let Some(sibling) = previous {
+        match sibling.kind() {
+            "attribute_item" => {
+                let attribute: String = text(sibling, source).split_whitespace().collect();
+                if attribute == "#[program]" {
+                    return true;
+                }
+            }
+            "line_comment" | "block_comment" => {}
+            _ => return false,
+        }
+        previous = sibling.prev_sibling();
+    }
+    false
+}
+
+/// 符号的名字；impl 为实现的类型，另外返回 trait 实现中的 trait
+fn name(node: Node, source: &[u8]) -> Option<(String, Option<String>)> {
+    if node.kind() == "impl_item" {
+        let ty = node.child_by_field_name("type")?;
+        let trait_name = node
+            .child_by_field_name("trait")
+            .map(|t| text(t, source).into_owned());
+        return Some((text(ty, source).into_owned(), trait_name));
+    }
+    if node.kind() == "arrow_function" {
+        let declarator = node
+            .parent()
+            .filter(|p| p.kind() == "variable_declarator")?;
+        let name = declarator.child_by_field_name("name")?;
+        return Some((text(name, source).into_owned(), None));
+    }
+    let name = node.child_by_field_name("name").or_else(|| {
+        // 部分语法的名字没有字段名
+        let mut cursor = node.walk();
+        let found = node
+            .named_children(&mut cursor)
+            .find(|c| c.kind().ends_with("identifier"));
+        found
+    })?;
+    Some((text(name, source).into_owned(), None))
+}
+
+/// 收集一个文件中的所有符号
+pub fn collect(tree: &Tree, source: &[u8], language: Language, file: &Path) -> Vec<Symbol> {
+    let mut prefix = match language {
+        Language::Rust => module_path(file),
+        _ => vec![],
+    };
+    let mut collector = Collector {
+        source,
+        file,
+        file_id: file_id(&file.to_string_lossy()),
+        next_index: 0,
+        symbols: vec![],
+    };
+    collector.visit(tree.root_node(), &mut prefix, false);
+    collector.symbols
+}
+
+/// 按先序遍历整棵树；节点的序号与 .ast.json 中节点ID的序号相同
+struct Collector<'a> {
+    source: &'a [u8],
+    file: &'a Path,
+    file_id: String,
+    next_index: usize,
+    symbols: Vec<Symbol>,
+}
+
+impl Collector<'_> {
+    /// `in_type` 为外层是否是 impl、trait、类等，其中的函数记为 method
+    /// `prefix` 为外层的路径，遍历子节点时临时加入该节点的名字
+    fn visit(&mut self, node: Node, prefix: &mut Vec<String>, in_type: bool) {
+        let id = format!("{}:{}", self.file_id, self.next_index);
+        self.next_index += 1;
+
+        let mut in_type = in_type;
+        let mut pushed = false;
+        let symbol = SYMBOL_KINDS
+            .iter()
+            .find(|(node_kind, _)| *node_kind == node.kind())
+            .and_then(|(_, kind)| Some((*kind, name(node, self.source)?)));
+        if let Some((kind, (name, trait_name))) = symbol {
+            let kind = match kind {
+                "function" if in_type => "method",
+                "mod" if is_program(node, self.source) => "program",
+                kind => kind,
+            };
+            let path = prefix
+                .iter()
+                .chain(std::iter::once(&name))
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("::");
+            let (start, end) = (node.start_position(), node.end_position());
+            self.symbols.push(Symbol {
+                name: name.clone(),
+                kind: kind.to_string(),
+                path,
+                trait_name,
+                file: self.file.to_path_buf(),
+                id,
+                start_byte: node.start_byte(),
+                end_byte: node.end_byte(),
+                start_line: start.row + 1,
+                start_column: start.column + 1,
+                end_line: end.row + 1,
+                end_column: end.column + 1,
+            });
+            if CONTAINER_KINDS.contains(&kind) {
+                prefix.push(name);
+                pushed = true;
+                in_type = !matches!(kind, "mod" | "program");
+            } else {
+                // 函数体中的嵌套函数不是方法
+                in_type = false;
+            }
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.visit(child, prefix, in_type);
+        }
+        if pushed {
+            prefix.pop();
+        }
+    }
+}
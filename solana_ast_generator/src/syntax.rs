@@ -0,0 +1,162 @@
+// syntax.rs
+//
+// 语法错误报告：tree-sitter 遇到无法解析的代码时不会失败，而是在树中插入 ERROR 节点和 MISSING 节点，
+// 写出的AST因此是残缺的。这里收集每个文件中的这些节点，写入输出目录下的 errors.json，
+// --fail-on-parse-error 时有语法错误的运行以失败退出，供CI使用
+// 因 --max-depth 等上限而被截断的AST同样是残缺的，也记录在 errors.json 中 (不算作语法错误)
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tree_sitter::{Node, Tree};
+
+/// 输出文件名，位于输出目录下
+pub const ERRORS_FILE_NAME: &str = "errors.json";
+
+/// 片段的最大长度 (字符)
+const SNIPPET_MAX_CHARS: usize = 120;
+
+/// 语法错误的类型
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// 无法解析的代码 (ERROR 节点)
+    Error,
+    /// 解析器为了继续解析而假定存在、但源代码中没有的记号 (MISSING 节点)，例如缺少的分号或括号
+    Missing,
+}
+
+/// 一处语法错误；行列号从1开始，与 .ast.json 相同
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyntaxError {
+    pub kind: ErrorKind,
+    /// ERROR 节点为 "ERROR"，MISSING 节点为缺少的记号种类
+    pub node_kind: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    /// 错误开始的那一行源代码
+    pub snippet: String,
+}
+
+/// 一个文件中的所有语法错误
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileErrors {
+    /// 源文件，相对于输入目录
+    pub file: PathBuf,
+    pub errors: Vec<SyntaxError>,
+}
+
+/// 一个被截断的AST
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TruncatedFile {
+    /// 源文件，相对于输入目录
+    pub file: PathBuf,
+    /// 带有 "truncated": true、子节点被去掉的节点数
+    pub truncated_nodes: usize,
+    /// 截断的原因，即超出的上限
+    pub detail: String,
+}
+
+/// errors.json 的内容
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ErrorReport {
+    /// 有语法错误的文件数
+    pub files_with_errors: usize,
+    /// 语法错误总数
+    pub error_count: usize,
+    pub files: Vec<FileErrors>,
+    /// 被截断的AST
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub truncated: Vec<TruncatedFile>,
+}
+
+impl ErrorReport {
+    pub fn new(mut files: Vec<FileErrors>, mut truncated: Vec<TruncatedFile>) -> Self {
+        files.sort_by(|a, b| a.file.cmp(&b.file));
+        truncated.sort_by(|a, b| a.file.cmp(&b.file));
+        ErrorReport {
+            files_with_errors: files.len(),
+            error_count: files.iter().map(|f| f.errors.len()).sum(),
+            files,
+            truncated,
+        }
+    }
+
+    /// 读取上一次运行写出的 errors.json，语法错误和截断记录分别按源文件索引；
+    /// 增量模式下复用的AST没有重新解析，沿用其中的记录
+    pub fn load_previous(
+        output_dir: &Path,
+    ) -> (HashMap<PathBuf, FileErrors>, HashMap<PathBuf, TruncatedFile>) {
+        let Some(report) = fs::read_to_string(output_dir.join(ERRORS_FILE_NAME))
+            .ok()
+            .and_then(|content| serde_json::from_str::<ErrorReport>(&content).ok())
+        else {
+            return Default::default();
+        };
+        (
+            report.files.into_iter().map(|f| (f.file.clone(), f)).collect(),
+            report.truncated.into_iter().map(|t| (t.file.clone(), t)).collect(),
+        )
+    }
+}
+
+/// 收集一个文件的语法树中的语法错误，没有错误时返回 None
+/// ERROR 节点内部的错误不再单独记录
+pub fn collect(tree: &Tree, source: &[u8], file: &Path) -> Option<FileErrors> {
+    let root = tree.root_node();
+    if !root.has_error() {
+        return None;
+    }
+    let mut errors = vec![];
+    visit(root, source, &mut errors);
+    Some(FileErrors {
+        file: file.to_path_buf(),
+        errors,
+    })
+}
+
+fn visit(node: Node, source: &[u8], errors: &mut Vec<SyntaxError>) {
+    if node.is_error() || node.is_missing() {
+        errors.push(syntax_error(node, source));
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor).filter(|c| c.has_error()) {
+        visit(child, source, errors);
+    }
+}
+
+fn syntax_error(node: Node, source: &[u8]) -> SyntaxError {
+    let (start, end) = (node.start_position(), node.end_position());
+    let start_byte = node.start_byte().min(source.len());
+    let line_start = source[..start_byte]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let line_end = source[line_start..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(source.len(), |i| line_start + i);
+    let line = String::from_utf8_lossy(&source[line_start..line_end]);
+    let line = line.trim();
+    SyntaxError {
+        kind: if node.is_missing() {
+            ErrorKind::Missing
+        } else {
+            ErrorKind::Error
+        },
+        node_kind: node.kind().to_string(),
+        start_byte: node.start_byte(),
+        end_byte: node.end_byte(),
+        start_line: start.row + 1,
+        start_column: start.column + 1,
+        end_line: end.row + 1,
+        end_column: end.column + 1,
+        snippet: line.chars().take(SNIPPET_MAX_CHARS).collect(),
+    }
+}
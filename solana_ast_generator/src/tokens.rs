@@ -0,0 +1,93 @@
+// tokens.rs
+//
+// 记号流：按源代码顺序排列的叶子记号 (种类、文本、位置)，供 GNN/transformer 等训练流程分词使用，
+// 不必再从AST的JSON中拼出记号序列。注释作为一个整体的记号，标识符可以另外拆成子词 (camelCase、snake_case)
+
+use super::{node_text_lossy, COMMENT_KINDS};
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+/// 一个叶子记号；行列号从1开始，与 .ast.json 相同
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Token {
+    /// 节点种类；匿名记号 (标点、关键字) 的种类即其文本
+    pub kind: String,
+    pub text: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    /// 标识符拆分出的子词，只在要求拆分时对标识符给出
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subtokens: Vec<String>,
+}
+
+/// 按源代码顺序收集叶子记号；MISSING 节点和其他零宽度的节点不在源代码中，不作为记号
+/// `subtokens` 为是否拆分标识符 (种类以 identifier 结尾的节点，例如 type_identifier、property_identifier)
+pub fn leaf_tokens(node: Node, source: &[u8], subtokens: bool) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut cursor = node.walk();
+    loop {
+        let node = cursor.node();
+        let leaf = COMMENT_KINDS.contains(&node.kind()) || !cursor.goto_first_child();
+        if leaf {
+            if !node.is_missing() && node.start_byte() < node.end_byte() {
+                tokens.push(token(node, source, subtokens));
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return tokens;
+                }
+            }
+        }
+    }
+}
+
+fn token(node: Node, source: &[u8], subtokens: bool) -> Token {
+    let (start, end) = (node.start_position(), node.end_position());
+    let text = node_text_lossy(node, source).into_owned();
+    Token {
+        kind: node.kind().to_string(),
+        subtokens: if subtokens && node.kind().ends_with("identifier") {
+            split_identifier(&text)
+        } else {
+            vec![]
+        },
+        text,
+        start_byte: node.start_byte(),
+        end_byte: node.end_byte(),
+        start_line: start.row + 1,
+        start_column: start.column + 1,
+        end_line: end.row + 1,
+        end_column: end.column + 1,
+    }
+}
+
+/// 把标识符按 snake_case、camelCase/PascalCase 和字母与数字的边界拆成子词，保持原来的大小写；
+/// 连续的大写字母作为一个缩写，例如 "parseHTTPResponse_v2" -> ["parse", "HTTP", "Response", "v", "2"]
+pub fn split_identifier(identifier: &str) -> Vec<String> {
+    let mut parts = vec![];
+    for word in identifier
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let chars: Vec<char> = word.chars().collect();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (previous, current) = (chars[i - 1], chars[i]);
+            let boundary = (previous.is_lowercase() && current.is_uppercase())
+                || previous.is_numeric() != current.is_numeric()
+                || (previous.is_uppercase()
+                    && current.is_uppercase()
+                    && chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+            if boundary {
+                parts.push(chars[start..i].iter().collect());
+                start = i;
+            }
+        }
+        parts.push(chars[start..].iter().collect());
+    }
+    parts
+}
@@ -0,0 +1,31 @@
+// wasm.rs
+//
+// wasm32 构建的入口 (启用 wasm feature 时编译)：输入和输出都在内存中，不读写文件，
+// 供浏览器中的代码审查工具和 VS Code for the Web 直接调用
+// tree-sitter 及各语言的语法是C代码，编译到 wasm32 需要支持该目标的 clang 和C标准库头文件 (例如 wasi-sdk)
+
+use crate::{node_to_serializable, Language};
+use std::path::Path;
+use tree_sitter::Parser;
+use wasm_bindgen::prelude::*;
+
+/// 解析一个源文件，返回与命令行工具输出的 .ast.json 相同的JSON
+/// 语言由 `path` 的扩展名决定，`source` 为文件内容
+#[wasm_bindgen(js_name = generateAst)]
+pub fn generate_ast(path: &str, source: &str) -> Result<String, JsError> {
+    let language = Path::new(path)
+        .extension()
+        .and_then(|s| s.to_str())
+        .and_then(Language::from_extension)
+        .ok_or_else(|| JsError::new(&format!("不支持的文件类型: {}", path)))?;
+    let mut parser = Parser::new();
+    parser.set_language(&language.grammar().map_err(|e| JsError::new(&e))?)?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| JsError::new("tree-sitter 解析失败"))?;
+    Ok(serde_json::to_string(&node_to_serializable(
+        tree.root_node(),
+        source,
+        path,
+    ))?)
+}
@@ -0,0 +1,402 @@
+// lib.rs
+//
+// CFG构建的核心逻辑：从AST JSON构建每个函数的CFG，不涉及文件读写
+// 命令行工具 (main.rs) 和 wasm 构建 (wasm.rs) 共用这里的实现
+
+pub mod render;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+/// 与CPG生成器和 agent 共用的图格式和HTML导出
+pub use solana_graph::{graph, html};
+
+use graph::{EdgeKind, Graph, GraphEdge, GraphNode, Layer, NodeKind, Span};
+use petgraph::dot::{Config, Dot};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::Instant;
+
+/// CFG输出文件格式的版本号，格式发生不兼容的变化时递增
+/// 2: JSON改为 graph.rs 中与CPG共用的节点/边格式
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// 从第一步复用的AST节点结构，用于反序列化
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AstNode {
+    /// 节点ID (`文件ID:先序序号`)；较早生成的 .ast.json 没有该字段
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub kind: String,
+    /// 在父节点中的字段名 (例如 "condition", "body")；较早生成的 .ast.json 没有该字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// --no-text 生成的AST没有该字段，读取后用 restore_text 从 source 还原
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub start_byte: usize,
+    #[serde(default)]
+    pub end_byte: usize,
+    /// 起止位置的行列号，从1开始；较早生成的 .ast.json 没有这些字段，此时为0
+    #[serde(default)]
+    pub start_line: usize,
+    #[serde(default)]
+    pub start_column: usize,
+    #[serde(default)]
+    pub end_line: usize,
+    #[serde(default)]
+    pub end_column: usize,
+    pub children: Vec<AstNode>,
+    /// --no-text 时根节点上保存的整个源文件
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl AstNode {
+    /// 字段名为 `field` 的子节点
+    /// 没有字段名的旧 .ast.json 退回到按种类查找第一个属于 `kinds` 的子节点
+    pub fn child(&self, field: &str, kinds: &[&str]) -> Option<&AstNode> {
+        if self.children.iter().any(|c| c.field.is_some()) {
+            self.children.iter().find(|c| c.field.as_deref() == Some(field))
+        } else {
+            self.children.iter().find(|c| kinds.contains(&c.kind.as_str()))
+        }
+    }
+
+    /// 为 --no-text 生成的AST补上各节点的文本：这类AST的节点不带 text，根节点上保存一份源文件
+    /// 已经带有文本的AST不受影响
+    pub fn restore_text(&mut self) {
+        fn fill(node: &mut AstNode, source: &str) {
+            if node.text.is_empty() {
+                node.text = source.get(node.start_byte..node.end_byte).unwrap_or("").to_string();
+            }
+            for child in &mut node.children {
+                fill(child, source);
+            }
+        }
+        if let Some(source) = self.source.take() {
+            fill(self, &source);
+        }
+    }
+}
+
+/// 代表CFG中的一个基本块 (Basic Block)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BasicBlock {
+    pub statements: Vec<String>,
+}
+
+/// 一条语句来源的AST节点
+#[derive(Debug, Clone)]
+struct StatementSource {
+    id: String,
+    kind: String,
+    start_byte: usize,
+    end_byte: usize,
+}
+
+/// 用于构建CFG的状态机
+pub struct CfgBuilder {
+    graph: DiGraph<BasicBlock, ()>,
+    entry_node: NodeIndex,
+    exit_node: NodeIndex,
+    current_block: NodeIndex,
+    loop_contexts: Vec<(NodeIndex, NodeIndex)>, // (loop_start, loop_end)
+    spans: HashMap<NodeIndex, (usize, usize)>, // 基本块覆盖的源码字节范围
+    sources: HashMap<NodeIndex, Vec<(usize, StatementSource)>>, // 语句的序号及其来源的AST节点
+    deadline: Option<Instant>, // 超过该时间点后停止构建
+    timed_out: bool,
+}
+
+impl CfgBuilder {
+    fn new(deadline: Option<Instant>) -> Self {
+        let mut graph = DiGraph::new();
+        let entry_node = graph.add_node(BasicBlock {
+            statements: vec!["Entry".to_string()],
+        });
+        let exit_node = graph.add_node(BasicBlock {
+            statements: vec!["Exit".to_string()],
+        });
+        CfgBuilder {
+            graph,
+            entry_node,
+            exit_node,
+            current_block: entry_node,
+            loop_contexts: vec![],
+            spans: HashMap::new(),
+            sources: HashMap::new(),
+            deadline,
+            timed_out: false,
+        }
+    }
+
+    /// 检查是否已超过构建期限，超时后整个构建过程会尽快退出
+    fn check_deadline(&mut self) -> bool {
+        if !self.timed_out {
+            self.timed_out = self.deadline.is_some_and(|d| Instant::now() >= d);
+        }
+        self.timed_out
+    }
+
+    /// 创建一个新的基本块
+    fn new_block(&mut self) -> NodeIndex {
+        self.graph.add_node(BasicBlock::default())
+    }
+
+    /// 在图中添加一条边
+    fn add_edge(&mut self, from: NodeIndex, to: NodeIndex) {
+        self.graph.add_edge(from, to, ());
+    }
+
+    /// 将一条语句添加到当前基本块，记下语句来源的AST节点 `source`，并把它的范围并入该块的源码范围
+    fn add_statement_to_current_block(&mut self, statement: String, source: &AstNode) {
+        if let Some(block) = self.graph.node_weight_mut(self.current_block) {
+            self.sources.entry(self.current_block).or_default().push((
+                block.statements.len(),
+                StatementSource {
+                    id: source.id.clone(),
+                    kind: source.kind.clone(),
+                    start_byte: source.start_byte,
+                    end_byte: source.end_byte,
+                },
+            ));
+            block.statements.push(statement);
+            let span = self
+                .spans
+                .entry(self.current_block)
+                .or_insert((source.start_byte, source.end_byte));
+            span.0 = span.0.min(source.start_byte);
+            span.1 = span.1.max(source.end_byte);
+        }
+    }
+
+    /// Graphviz DOT 格式的CFG
+    pub fn dot(&self) -> String {
+        format!(
+            "{:?}",
+            Dot::with_config(&self.graph, &[Config::EdgeNoLabel])
+        )
+    }
+
+    /// 转换为与CPG共用的图格式，`file` 为函数所在的源文件
+    pub fn to_graph(&self, function: &str, file: &Path) -> Graph {
+        let nodes = self
+            .graph
+            .node_indices()
+            .map(|index| {
+                let block = &self.graph[index];
+                let kind = if index == self.entry_node {
+                    NodeKind::Entry
+                } else if index == self.exit_node {
+                    NodeKind::Exit
+                } else {
+                    NodeKind::BasicBlock
+                };
+                let mut properties = BTreeMap::new();
+                properties.insert("statements".to_string(), block.statements.clone().into());
+                // 与 statements 一一对应的来源AST节点，Entry/Exit 等没有来源的语句为 null
+                if let Some(sources) = self.sources.get(&index) {
+                    let mut statement_nodes = vec![Value::Null; block.statements.len()];
+                    for (i, source) in sources {
+                        let mut statement_node = json!({
+                            "kind": source.kind,
+                            "start_byte": source.start_byte,
+                            "end_byte": source.end_byte,
+                        });
+                        // AST中的节点ID，用于把CFG的语句对应回AST节点
+                        if !source.id.is_empty() {
+                            statement_node["id"] = source.id.clone().into();
+                        }
+                        statement_nodes[*i] = statement_node;
+                    }
+                    properties.insert("statement_nodes".to_string(), statement_nodes.into());
+                }
+                GraphNode {
+                    id: index.index(),
+                    kind,
+                    label: block.statements.join("\n"),
+                    span: self.spans.get(&index).map(|&(start_byte, end_byte)| Span {
+                        file: file.to_path_buf(),
+                        start_byte,
+                        end_byte,
+                    }),
+                    properties,
+                }
+            })
+            .collect();
+        let edges = self
+            .graph
+            .edge_references()
+            .map(|edge| GraphEdge {
+                source: edge.source().index(),
+                target: edge.target().index(),
+                kind: EdgeKind::ControlFlow,
+                properties: BTreeMap::new(),
+            })
+            .collect();
+        Graph {
+            layer: Layer::Ast,
+            function: function.to_string(),
+            nodes,
+            edges,
+        }
+    }
+}
+
+// --- 阶段 2: CFG 构建核心逻辑 ---
+
+/// 递归地从AST节点构建CFG
+fn build_cfg_from_ast(ast_node: &AstNode, builder: &mut CfgBuilder) {
+    if builder.check_deadline() {
+        return;
+    }
+    match ast_node.kind.as_str() {
+        // 遇到函数体或代码块，遍历其子语句
+        "statement_block" | "block" => {
+            for child in &ast_node.children {
+                build_cfg_from_ast(child, builder);
+            }
+        }
+
+        // 处理 `if` 表达式 (if-else 和 if)
+        "if_expression" => {
+            let condition = ast_node
+                .child("condition", &["condition"])
+                .map_or("".to_string(), |c| c.text.clone());
+            builder.add_statement_to_current_block(format!("IF ({})", condition), ast_node);
+
+            let consequence = ast_node.child("consequence", &["consequence"]);
+            let alternative = ast_node.child("alternative", &["alternative"]);
+
+            let if_block_end = builder.current_block;
+            let merge_block = builder.new_block();
+
+            // 处理 `then` 分支
+            if let Some(consequence_node) = consequence {
+                let then_block_start = builder.new_block();
+                builder.add_edge(if_block_end, then_block_start);
+                builder.current_block = then_block_start;
+                build_cfg_from_ast(consequence_node, builder);
+                builder.add_edge(builder.current_block, merge_block);
+            }
+
+            // 处理 `else` 分支
+            if let Some(alternative_node) = alternative {
+                let else_block_start = builder.new_block();
+                builder.add_edge(if_block_end, else_block_start);
+                builder.current_block = else_block_start;
+                build_cfg_from_ast(alternative_node, builder);
+                builder.add_edge(builder.current_block, merge_block);
+            } else {
+                // 如果没有 `else`，`if` 块可以直接跳到合并块
+                builder.add_edge(if_block_end, merge_block);
+            }
+
+            builder.current_block = merge_block;
+        }
+
+        // 处理 `return` 语句
+        "return_expression" => {
+            builder.add_statement_to_current_block(ast_node.text.clone(), ast_node);
+            builder.add_edge(builder.current_block, builder.exit_node);
+            // return后创建一个新块，但不再连接它，因为它代表不可达代码
+            builder.current_block = builder.new_block();
+        }
+
+        // 简化的循环处理 (loop, while, for)
+        "loop_expression" | "while_expression" | "for_expression" => {
+            let loop_header = builder.new_block();
+            builder.add_edge(builder.current_block, loop_header);
+            
+            let loop_body_start = builder.new_block();
+            let after_loop_block = builder.new_block();
+
+            // 循环上下文，用于 `break` 和 `continue`
+            builder.loop_contexts.push((loop_header, after_loop_block));
+
+            // 循环头连接到循环体和循环后
+            builder.add_edge(loop_header, loop_body_start);
+            builder.add_edge(loop_header, after_loop_block); // 循环退出的边
+            
+            // 构建循环体
+            builder.current_block = loop_body_start;
+            let body_node = ast_node.child("body", &["statement_block"]);
+            if let Some(body) = body_node {
+                build_cfg_from_ast(body, builder);
+            }
+            
+            // 循环体末尾跳回循环头
+            builder.add_edge(builder.current_block, loop_header);
+
+            builder.loop_contexts.pop();
+            builder.current_block = after_loop_block;
+        }
+
+        // `break` 语句
+        "break_expression" => {
+            builder.add_statement_to_current_block("break".to_string(), ast_node);
+            if let Some(&(_, loop_end)) = builder.loop_contexts.last() {
+                builder.add_edge(builder.current_block, loop_end);
+            }
+            builder.current_block = builder.new_block(); // 不可达代码块
+        }
+
+        // 对于其他普通语句，直接添加到当前块
+        _ => {
+            if !ast_node.kind.ends_with("_statement")
+                && !ast_node.kind.ends_with("_declaration")
+                && !ast_node.kind.ends_with("_item")
+            {
+                // 递归处理子节点以深入查找语句
+                for child in &ast_node.children {
+                    build_cfg_from_ast(child, builder);
+                }
+            } else {
+                // 将语句/声明的文本简化为一行，以保持CFG节点的可读性
+                let simplified_text = ast_node.text.lines().next().unwrap_or("").trim().to_string();
+                if !simplified_text.is_empty() {
+                    builder.add_statement_to_current_block(simplified_text, ast_node);
+                }
+            }
+        }
+    }
+}
+
+/// 递归辅助函数，用于在AST中查找所有 `function_item`
+pub fn find_functions<'a>(node: &'a AstNode, functions: &mut Vec<&'a AstNode>) {
+    if node.kind == "function_item" {
+        functions.push(node);
+    }
+    for child in &node.children {
+        find_functions(child, functions);
+    }
+}
+
+/// 函数名：函数节点的 name 字段 (旧 .ast.json 中为第一个 identifier 子节点) 的文本
+pub fn function_name(func_node: &AstNode) -> String {
+    func_node
+        .child("name", &["identifier"])
+        .map_or("unknown_function".to_string(), |c| c.text.clone())
+}
+
+/// 为一个函数构建CFG；超过 `deadline` 时返回 None
+/// 没有期限时不会读取时钟，因此可以在没有系统时钟的 wasm32 上运行
+pub fn build_function_cfg(func_node: &AstNode, deadline: Option<Instant>) -> Option<CfgBuilder> {
+    let mut builder = CfgBuilder::new(deadline);
+
+    // 找到函数体并开始构建CFG
+    if let Some(body) = func_node.child("body", &["statement_block"]) {
+        build_cfg_from_ast(body, &mut builder);
+    }
+    if builder.timed_out {
+        return None;
+    }
+
+    // 将最后一个活动块连接到出口
+    builder.add_edge(builder.current_block, builder.exit_node);
+    Some(builder)
+}
@@ -16,259 +16,506 @@ petgraph = { version = "0.6.5", features = ["serde-1"] }
 
 */
 
-use clap::Parser as ClapParser;
-use petgraph::dot::{Config, Dot};
-use petgraph::graph::{DiGraph, NodeIndex};
+use clap::{ArgAction, Parser as ClapParser, ValueEnum};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use serde_json; // FIX: Added missing import for serde_json
+use solana_cfg_generator::{
+    build_function_cfg, find_functions, function_name, AstNode, SCHEMA_VERSION,
+};
+use solana_cfg_generator::graph::Graph;
+use solana_cfg_generator::render;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 use walkdir::WalkDir;
 
+use solana_graph::export::{Export, ExportFormat};
+
 // --- 阶段 1: 数据结构定义 ---
 
 /// 定义命令行参数
 #[derive(ClapParser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// 包含AST JSON文件的输入目录
+    /// 包含AST JSON文件的输入目录；AST生成器 --format msgpack/cbor 和 --compress 写出的AST按后缀自动解码、解压
     #[arg(short, long)]
     input: PathBuf,
 
     /// 用于存储生成的CFG文件的输出目录
     #[arg(short, long)]
     output: PathBuf,
-}
 
-/// 从第一步复用的AST节点结构，用于反序列化
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct AstNode {
-    kind: String,
-    text: String,
-    children: Vec<AstNode>,
-}
+    /// 提高日志详细程度 (-v 输出 debug, -vv 输出 trace)
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// 只输出警告和错误
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// 日志输出格式
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
 
-/// 代表CFG中的一个基本块 (Basic Block)
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
-struct BasicBlock {
-    statements: Vec<String>,
+    /// 并行处理AST文件的线程数，默认使用全部CPU核心
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// 内存预算 (MiB)。按AST文件大小估算峰值内存，超出预算的文件会排队等待或被跳过
+    #[arg(long, value_name = "MIB")]
+    memory_limit: Option<u64>,
+
+    /// 单个函数的CFG构建超时时间 (秒)，超时的函数会被跳过并记录在 skipped.json 中
+    #[arg(long, value_name = "SECS")]
+    timeout_per_function: Option<u64>,
+
+    /// 要输出的CFG格式 (逗号分隔)，所有格式在一次运行中写出
+    #[arg(long = "format", value_enum, value_delimiter = ',', default_values_t = [ExportFormat::Dot, ExportFormat::Json])]
+    formats: Vec<ExportFormat>,
+
+    /// 把每个函数的CFG渲染为图片。优先调用 graphviz 的 dot 命令 (GRAPHVIZ_DOT 环境变量可指定其路径)，
+    /// 找不到 dot 或 dot 执行失败时改用内置的布局，输出SVG
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    render: Option<RenderFormat>,
+
+    /// 增量模式：根据输出目录中上一次的 manifest.json，跳过内容未变化的AST文件，
+    /// 并删除不再产生的旧CFG
+    #[arg(long)]
+    incremental: bool,
+
+    /// 把每个AST文件的处理耗时写入该JSON文件，供 agent bench 统计
+    #[arg(long, value_name = "FILE")]
+    timings: Option<PathBuf>,
 }
 
-/// 用于构建CFG的状态机
-struct CfgBuilder {
-    graph: DiGraph<BasicBlock, ()>,
-    entry_node: NodeIndex,
-    exit_node: NodeIndex,
-    current_block: NodeIndex,
-    loop_contexts: Vec<(NodeIndex, NodeIndex)>, // (loop_start, loop_end)
+/// --render 的图片格式
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum RenderFormat {
+    Svg,
+    Png,
 }
 
-impl CfgBuilder {
-    fn new() -> Self {
-        let mut graph = DiGraph::new();
-        let entry_node = graph.add_node(BasicBlock {
-            statements: vec!["Entry".to_string()],
-        });
-        let exit_node = graph.add_node(BasicBlock {
-            statements: vec!["Exit".to_string()],
-        });
-        CfgBuilder {
-            graph,
-            entry_node,
-            exit_node,
-            current_block: entry_node,
-            loop_contexts: vec![],
+impl RenderFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            RenderFormat::Svg => "svg",
+            RenderFormat::Png => "png",
         }
     }
+}
 
-    /// 创建一个新的基本块
-    fn new_block(&mut self) -> NodeIndex {
-        self.graph.add_node(BasicBlock::default())
-    }
+/// 日志的输出格式
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    /// 人类可读的文本格式
+    Text,
+    /// 每行一个JSON对象
+    Json,
+}
+
+/// 被跳过的文件或函数的原因
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum SkipReason {
+    /// CFG构建超过了 --timeout-per-function
+    Timeout,
+    /// 估算的内存占用超过了 --memory-limit
+    MemoryLimit,
+    /// 读取、反序列化或写入时发生错误
+    Error,
+}
 
-    /// 在图中添加一条边
-    fn add_edge(&mut self, from: NodeIndex, to: NodeIndex) {
-        self.graph.add_edge(from, to, ());
+/// skipped.json 中的一条记录
+#[derive(Serialize, Debug)]
+struct SkippedItem {
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function: Option<String>,
+    reason: SkipReason,
+    detail: String,
+}
+
+/// --timings 输出中的一条记录
+#[derive(Serialize, Debug)]
+struct FileTiming {
+    path: PathBuf, // 相对于输入目录
+    bytes: u64,
+    millis: f64,
+}
+
+/// 写在每个CFG文件开头的元数据，用于识别由旧版本生成的、格式不兼容的文件
+#[derive(Serialize, Debug)]
+struct Metadata {
+    schema_version: u32,
+    tool: &'static str,
+    tool_version: &'static str,
+    source_hash: String, // 生成该文件的AST文件的哈希
+    generated_at: String,
+}
+
+/// manifest.json 的结构：记录本次运行产生的所有文件，供下游判断产物是否过期
+#[derive(Serialize, Debug)]
+struct Manifest {
+    tool: &'static str,
+    tool_version: &'static str,
+    schema_version: u32,
+    generated_at: String,
+    formats: Vec<ExportFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    render: Option<RenderFormat>,
+    artifacts: Vec<Artifact>,
+}
+
+/// 增量模式下读取的上一次运行的 manifest.json
+#[derive(Deserialize, Debug)]
+struct PreviousManifest {
+    tool_version: String,
+    #[serde(default)]
+    schema_version: u32,
+    #[serde(default)]
+    formats: Vec<ExportFormat>,
+    #[serde(default)]
+    render: Option<RenderFormat>,
+    artifacts: Vec<Artifact>,
+}
+
+impl PreviousManifest {
+    /// 读取输出目录中已有的 manifest.json
+    /// 不存在、无法解析、由其他版本生成、格式版本或输出格式不同时返回 None
+    fn load(
+        output_dir: &Path,
+        formats: &[ExportFormat],
+        render: Option<RenderFormat>,
+    ) -> Option<PreviousManifest> {
+        let content = fs::read_to_string(output_dir.join("manifest.json")).ok()?;
+        let manifest: PreviousManifest = serde_json::from_str(&content).ok()?;
+        (manifest.tool_version == env!("CARGO_PKG_VERSION")
+            && manifest.schema_version == SCHEMA_VERSION
+            && manifest.formats == formats
+            && manifest.render == render)
+            .then_some(manifest)
     }
 
-    /// 将一条语句添加到当前基本块
-    fn add_statement_to_current_block(&mut self, statement: String) {
-        if let Some(block) = self.graph.node_weight_mut(self.current_block) {
-            block.statements.push(statement);
+    /// 按AST文件分组的CFG产物；只要有一个输出文件丢失，整组都不再复用
+    fn reusable_cfgs(&self, output_dir: &Path) -> HashMap<PathBuf, Vec<Artifact>> {
+        let mut groups: HashMap<PathBuf, Vec<Artifact>> = HashMap::new();
+        for artifact in self.artifacts.iter().filter(|a| matches!(a.kind, ArtifactKind::Cfg)) {
+            if let Some(source) = &artifact.source {
+                groups.entry(source.clone()).or_default().push(artifact.clone());
+            }
         }
+        groups.retain(|_, group| group.iter().all(|a| output_dir.join(&a.path).is_file()));
+        groups
     }
 }
 
-// --- 阶段 2: CFG 构建核心逻辑 ---
+/// 产物的类别
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ArtifactKind {
+    Cfg,
+    Report,
+}
+
+/// manifest.json 中的一条产物记录
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Artifact {
+    path: PathBuf, // 相对于输出目录
+    kind: ArtifactKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<PathBuf>, // 生成该产物的AST文件，相对于输入目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_hash: Option<String>,
+    hash: String,
+}
+
+/// 计算内容哈希 (blake3，十六进制)
+fn content_hash(bytes: &[u8]) -> String {
+    blake3::hash(bytes).to_hex().to_string()
+}
+
+/// 写入输出的时间戳 (RFC 3339，精确到秒)
+/// 设置了 SOURCE_DATE_EPOCH 时使用该时间而不是当前时间，使重复运行的输出逐字节相同
+fn timestamp() -> String {
+    let time = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .map_or_else(SystemTime::now, |secs| UNIX_EPOCH + Duration::from_secs(secs));
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// 写入一个报告文件，并返回它在 manifest 中的记录
+fn write_report(output_dir: &Path, name: &str, content: String) -> Result<Artifact, Box<dyn Error>> {
+    fs::write(output_dir.join(name), &content)?;
+    Ok(Artifact {
+        path: PathBuf::from(name),
+        kind: ArtifactKind::Report,
+        source: None,
+        source_hash: None,
+        hash: content_hash(content.as_bytes()),
+    })
+}
+
+/// 把CFG渲染为图片：优先使用 graphviz，失败时退回内置布局
+struct Renderer {
+    format: RenderFormat,
+    graphviz: Option<PathBuf>, // dot 命令的路径
+}
 
-/// 递归地从AST节点构建CFG
-fn build_cfg_from_ast(ast_node: &AstNode, builder: &mut CfgBuilder) {
-    match ast_node.kind.as_str() {
-        // 遇到函数体或代码块，遍历其子语句
-        "statement_block" | "block" => {
-            for child in &ast_node.children {
-                build_cfg_from_ast(child, builder);
+impl Renderer {
+    fn new(format: RenderFormat) -> Self {
+        let graphviz = find_graphviz();
+        match &graphviz {
+            Some(dot) => debug!(dot = %dot.display(), "Using graphviz for --render"),
+            None if format == RenderFormat::Png => {
+                warn!("graphviz not found, --render png falls back to SVG from the built-in layout")
             }
+            None => debug!("graphviz not found, using the built-in layout for --render"),
         }
+        Renderer { format, graphviz }
+    }
 
-        // 处理 `if` 表达式 (if-else 和 if)
-        "if_expression" => {
-            let condition = ast_node
-                .children
-                .iter()
-                .find(|c| c.kind == "condition")
-                .map_or("".to_string(), |c| c.text.clone());
-            builder.add_statement_to_current_block(format!("IF ({})", condition));
-
-            let consequence = ast_node
-                .children
-                .iter()
-                .find(|c| c.kind == "consequence");
-            let alternative = ast_node
-                .children
-                .iter()
-                .find(|c| c.kind == "alternative");
-
-            let if_block_end = builder.current_block;
-            let merge_block = builder.new_block();
-
-            // 处理 `then` 分支
-            if let Some(consequence_node) = consequence {
-                let then_block_start = builder.new_block();
-                builder.add_edge(if_block_end, then_block_start);
-                builder.current_block = then_block_start;
-                build_cfg_from_ast(consequence_node, builder);
-                builder.add_edge(builder.current_block, merge_block);
+    /// 渲染一个函数的CFG，返回图片内容和文件扩展名
+    fn render(&self, dot_content: &str, graph: &Graph) -> (Vec<u8>, &'static str) {
+        if let Some(dot) = &self.graphviz {
+            match run_graphviz(dot, self.format, dot_content) {
+                Ok(image) => return (image, self.format.extension()),
+                Err(e) => warn!(
+                    function = %graph.function,
+                    error = %e,
+                    "graphviz failed, falling back to the built-in layout"
+                ),
             }
+        }
+        (
+            render::svg(graph).into_bytes(),
+            RenderFormat::Svg.extension(),
+        )
+    }
+}
 
-            // 处理 `else` 分支
-            if let Some(alternative_node) = alternative {
-                let else_block_start = builder.new_block();
-                builder.add_edge(if_block_end, else_block_start);
-                builder.current_block = else_block_start;
-                build_cfg_from_ast(alternative_node, builder);
-                builder.add_edge(builder.current_block, merge_block);
-            } else {
-                // 如果没有 `else`，`if` 块可以直接跳到合并块
-                builder.add_edge(if_block_end, merge_block);
-            }
+/// 查找 graphviz 的 dot 命令：先看 GRAPHVIZ_DOT 环境变量，再在 PATH 中查找
+fn find_graphviz() -> Option<PathBuf> {
+    if let Some(dot) = std::env::var_os("GRAPHVIZ_DOT") {
+        return Some(PathBuf::from(dot)).filter(|p| p.is_file());
+    }
+    let name = if cfg!(windows) { "dot.exe" } else { "dot" };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
 
-            builder.current_block = merge_block;
-        }
+/// 调用 dot 把DOT文本渲染为图片
+fn run_graphviz(
+    dot: &Path,
+    format: RenderFormat,
+    dot_content: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut child = Command::new(dot)
+        .arg(format!("-T{}", format.extension()))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // 在另一个线程中写入 stdin，避免输出较大时双方互相等待
+    let mut stdin = child.stdin.take().ok_or("failed to open stdin of dot")?;
+    let input = dot_content.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    // dot 出错时可能在读完输入之前就退出，此时写入失败，以 dot 的错误输出为准
+    let written = writer.join().map_err(|_| "failed to write to dot")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("dot exited with {}: {}", output.status, stderr.trim()).into());
+    }
+    written?;
+    Ok(output.stdout)
+}
 
-        // 处理 `return` 语句
-        "return_expression" => {
-            builder.add_statement_to_current_block(ast_node.text.clone());
-            builder.add_edge(builder.current_block, builder.exit_node);
-            // return后创建一个新块，但不再连接它，因为它代表不可达代码
-            builder.current_block = builder.new_block();
-        }
+/// 反序列化后的AST大约占用AST文件大小的这么多倍内存
+const MEMORY_ESTIMATE_FACTOR: u64 = 4;
 
-        // 简化的循环处理 (loop, while, for)
-        "loop_expression" | "while_expression" | "for_expression" => {
-            let loop_header = builder.new_block();
-            builder.add_edge(builder.current_block, loop_header);
-            
-            let loop_body_start = builder.new_block();
-            let after_loop_block = builder.new_block();
-
-            // 循环上下文，用于 `break` 和 `continue`
-            builder.loop_contexts.push((loop_header, after_loop_block));
-
-            // 循环头连接到循环体和循环后
-            builder.add_edge(loop_header, loop_body_start);
-            builder.add_edge(loop_header, after_loop_block); // 循环退出的边
-            
-            // 构建循环体
-            builder.current_block = loop_body_start;
-            let body_node = ast_node.children.iter().find(|c| c.kind == "statement_block");
-            if let Some(body) = body_node {
-                build_cfg_from_ast(body, builder);
-            }
-            
-            // 循环体末尾跳回循环头
-            builder.add_edge(builder.current_block, loop_header);
+/// 压缩的AST解压后大约是压缩文件的这么多倍大小，估算内存时一并计入
+const COMPRESSION_RATIO_ESTIMATE: u64 = 10;
+
+/// AST生成器 --compress 写出的压缩AST的后缀 (lib.rs.ast.json.zst、lib.rs.ast.json.gz)
+const COMPRESSED_EXTENSIONS: &[&str] = &["zst", "gz"];
+
+/// AST文件的后缀：JSON，以及AST生成器 --format msgpack/cbor 写出的二进制格式
+const AST_EXTENSIONS: &[&str] = &[".ast.json", ".ast.msgpack", ".ast.cbor"];
+
+/// 去掉AST文件名的 .ast.json 等后缀 (以及其后的压缩后缀)，得到源文件名；不是AST文件时返回 None
+fn ast_stem(name: &str) -> Option<&str> {
+    let name = COMPRESSED_EXTENSIONS
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext)?.strip_suffix('.'))
+        .unwrap_or(name);
+    AST_EXTENSIONS.iter().find_map(|ext| name.strip_suffix(ext))
+}
+
+/// AST文件是否是压缩的
+fn is_compressed(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| COMPRESSED_EXTENSIONS.contains(&e))
+}
 
-            builder.loop_contexts.pop();
-            builder.current_block = after_loop_block;
+/// 按后缀解压并解码AST文件的内容
+fn decode_ast(path: &Path, content: Vec<u8>) -> Result<AstNode, Box<dyn Error>> {
+    let mut bytes = vec![];
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("zst") => {
+            zstd::stream::read::Decoder::new(content.as_slice())?.read_to_end(&mut bytes)?;
         }
+        Some("gz") => {
+            flate2::read::GzDecoder::new(content.as_slice()).read_to_end(&mut bytes)?;
+        }
+        _ => bytes = content,
+    }
+    let name = path.to_string_lossy();
+    let name = name
+        .strip_suffix(".zst")
+        .or_else(|| name.strip_suffix(".gz"))
+        .unwrap_or(&name);
+    Ok(if name.ends_with(".ast.msgpack") {
+        rmp_serde::from_slice(&bytes)?
+    } else if name.ends_with(".ast.cbor") {
+        ciborium::from_reader(bytes.as_slice())?
+    } else {
+        serde_json::from_slice(&bytes)?
+    })
+}
 
-        // `break` 语句
-        "break_expression" => {
-            builder.add_statement_to_current_block("break".to_string());
-            if let Some(&(_, loop_end)) = builder.loop_contexts.last() {
-                builder.add_edge(builder.current_block, loop_end);
-            }
-            builder.current_block = builder.new_block(); // 不可达代码块
+/// 所有工作线程共享的内存预算
+/// 每个任务开始前按估算值申请预算，预算不足时等待其他任务释放
+struct MemoryBudget {
+    limit: u64,
+    in_use: Mutex<u64>,
+    released: Condvar,
+}
+
+/// 已申请的预算，离开作用域时自动归还
+struct BudgetGuard<'a> {
+    budget: &'a MemoryBudget,
+    cost: u64,
+}
+
+impl MemoryBudget {
+    fn new(limit: u64) -> Self {
+        MemoryBudget {
+            limit,
+            in_use: Mutex::new(0),
+            released: Condvar::new(),
         }
+    }
 
-        // 对于其他普通语句，直接添加到当前块
-        _ => {
-            if !ast_node.kind.ends_with("_statement")
-                && !ast_node.kind.ends_with("_declaration")
-                && !ast_node.kind.ends_with("_item")
-            {
-                // 递归处理子节点以深入查找语句
-                for child in &ast_node.children {
-                    build_cfg_from_ast(child, builder);
-                }
-            } else {
-                // 将语句/声明的文本简化为一行，以保持CFG节点的可读性
-                let simplified_text = ast_node.text.lines().next().unwrap_or("").trim().to_string();
-                if !simplified_text.is_empty() {
-                    builder.add_statement_to_current_block(simplified_text);
-                }
-            }
+    /// 申请 `cost` 字节的预算；单个任务的估算值超过总预算时返回 None
+    fn acquire(&self, cost: u64) -> Option<BudgetGuard<'_>> {
+        if cost > self.limit {
+            return None;
+        }
+        let mut in_use = self.in_use.lock().unwrap();
+        while *in_use + cost > self.limit {
+            in_use = self.released.wait(in_use).unwrap();
         }
+        *in_use += cost;
+        Some(BudgetGuard { budget: self, cost })
+    }
+}
+
+impl Drop for BudgetGuard<'_> {
+    fn drop(&mut self) {
+        *self.budget.in_use.lock().unwrap() -= self.cost;
+        self.budget.released.notify_all();
     }
 }
 
-// --- 阶段 3: 文件处理与主逻辑 ---
 
 /// 处理单个AST文件，为其中的所有函数生成CFG
+/// 返回写出的产物以及因超时而被跳过的函数
+/// `previous` 是增量模式下上一次从该AST文件生成的CFG，AST内容未变化时直接复用
 fn process_ast_file(
     ast_path: &Path,
     input_dir: &Path,
     output_dir: &Path,
-) -> Result<(), Box<dyn Error>> {
-    let content = fs::read_to_string(ast_path)?;
-    let root_node: AstNode = serde_json::from_str(&content)?;
+    timeout: Option<Duration>,
+    formats: &[ExportFormat],
+    renderer: Option<&Renderer>,
+    previous: Option<&Vec<Artifact>>,
+) -> Result<(Vec<Artifact>, Vec<SkippedItem>), Box<dyn Error>> {
+    let content = fs::read(ast_path)?;
+    let ast_hash = content_hash(&content);
+    if let Some(previous) = previous
+        .filter(|group| group.iter().all(|a| a.source_hash.as_ref() == Some(&ast_hash)))
+    {
+        debug!(path = %ast_path.display(), "AST unchanged, reusing existing CFGs");
+        return Ok((previous.clone(), vec![]));
+    }
+
+    let mut root_node = decode_ast(ast_path, content)?;
+    root_node.restore_text();
+    let relative_ast_path = ast_path.strip_prefix(input_dir)?;
+    // AST文件与源文件的相对路径相同，只多了 .ast.json 等后缀 (压缩时还有 .zst、.gz)
+    let relative_ast_name = relative_ast_path.to_string_lossy();
+    let source_file = PathBuf::from(ast_stem(&relative_ast_name).unwrap_or(&relative_ast_name));
+    let metadata = Metadata {
+        schema_version: SCHEMA_VERSION,
+        tool: env!("CARGO_PKG_NAME"),
+        tool_version: env!("CARGO_PKG_VERSION"),
+        source_hash: ast_hash.clone(),
+        generated_at: timestamp(),
+    };
+    let mut artifacts = vec![];
+    let artifact = |path: &Path, written: &[u8]| -> Result<Artifact, Box<dyn Error>> {
+        Ok(Artifact {
+            path: path.strip_prefix(output_dir)?.to_path_buf(),
+            kind: ArtifactKind::Cfg,
+            source: Some(relative_ast_path.to_path_buf()),
+            source_hash: Some(ast_hash.clone()),
+            hash: content_hash(written),
+        })
+    };
 
     // 查找所有函数
     let mut functions = vec![];
     find_functions(&root_node, &mut functions);
+    let mut skipped = vec![];
 
     for func_node in functions {
-        let func_name = func_node
-            .children
-            .iter()
-            .find(|c| c.kind == "identifier")
-            .map_or("unknown_function".to_string(), |c| c.text.clone());
+        let func_name = function_name(func_node);
 
-        println!(
-            "  -> Found function: `{}` in {}",
-            func_name,
-            ast_path.file_name().unwrap().to_str().unwrap()
+        debug!(
+            function = %func_name,
+            file = %ast_path.display(),
+            "Found function"
         );
 
-        let mut builder = CfgBuilder::new();
-        
-        // 找到函数体并开始构建CFG
-        if let Some(body) = func_node.children.iter().find(|c| c.kind == "statement_block") {
-            build_cfg_from_ast(body, &mut builder);
-        }
-        
-        // 将最后一个活动块连接到出口
-        builder.add_edge(builder.current_block, builder.exit_node);
+        let Some(builder) = build_function_cfg(func_node, timeout.map(|t| Instant::now() + t)) else {
+            let detail = format!("CFG construction exceeded {}s", timeout.unwrap_or_default().as_secs());
+            warn!(function = %func_name, file = %ast_path.display(), %detail, "Skipping function");
+            skipped.push(SkippedItem {
+                path: ast_path.to_path_buf(),
+                function: Some(func_name),
+                reason: SkipReason::Timeout,
+                detail,
+            });
+            continue;
+        };
 
         // --- 序列化与保存 ---
-        let relative_path = ast_path.strip_prefix(input_dir)?;
-        let mut output_path_base = output_dir.join(relative_path);
+        let mut output_path_base = output_dir.join(relative_ast_path);
         
         // **FIXED**: 改进文件命名逻辑，使其更清晰
         let original_filename = output_path_base.file_name().unwrap().to_str().unwrap();
-        let new_filename_base = original_filename.replace(".ast.json", "");
+        let new_filename_base = ast_stem(original_filename).unwrap_or(original_filename);
         output_path_base.set_file_name(format!("{}.{}.cfg", new_filename_base, func_name));
         
         // 确保父目录存在
@@ -276,63 +523,239 @@ fn process_ast_file(
             fs::create_dir_all(parent)?;
         }
 
-        // 保存为 .dot 文件 (用于可视化)
-        let mut dot_path = output_path_base.clone();
-        dot_path.set_extension("dot");
-        let dot_content = format!(
-            "{:?}",
-            Dot::with_config(&builder.graph, &[Config::EdgeNoLabel])
-        );
-        fs::write(&dot_path, dot_content)?;
-
-        // 保存为 .json 文件 (用于程序化分析)
-        let mut json_path = output_path_base;
-        json_path.set_extension("json");
-        let serializable_graph = builder.graph.map(
-            |_, node_weight| node_weight.clone(),
-            |_, _| (),
+        // 按请求的格式一次写出 .dot/.json/.graphml/.html
+        let graphs = [builder.to_graph(&func_name, &source_file)];
+        let export = Export {
+            metadata: &metadata,
+            graphs: &graphs,
+            dot: builder.dot(),
+        };
+        // 输出文件名为 `<源文件>.<函数>.<扩展名>`，去掉 output_path_base 的 .cfg
+        for (path, content) in export.write(&output_path_base.with_extension(""), formats)? {
+            artifacts.push(artifact(&path, content.as_bytes())?);
+        }
+
+        // 渲染为 .svg/.png 图片
+        if let Some(renderer) = renderer {
+            let (image, extension) = renderer.render(&export.dot()?, &graphs[0]);
+            let mut image_path = output_path_base.clone();
+            image_path.set_extension(extension);
+            fs::write(&image_path, &image)?;
+            artifacts.push(artifact(&image_path, &image)?);
+        }
+        info!(
+            function = %func_name,
+            output = %output_path_base.display(),
+            "CFG saved"
         );
-        let json_content = serde_json::to_string_pretty(&serializable_graph)?;
-        fs::write(&json_path, json_content)?;
     }
 
-    Ok(())
+    Ok((artifacts, skipped))
 }
 
-/// 递归辅助函数，用于在AST中查找所有 `function_item`
-fn find_functions<'a>(node: &'a AstNode, functions: &mut Vec<&'a AstNode>) {
-    if node.kind == "function_item" {
-        functions.push(node);
+/// 在资源限制下处理单个AST文件，返回写出的产物和被跳过的文件或函数
+fn process_ast_file_with_limits(
+    ast_path: &Path,
+    args: &Args,
+    budget: Option<&MemoryBudget>,
+    renderer: Option<&Renderer>,
+    previous: &HashMap<PathBuf, Vec<Artifact>>,
+) -> (Vec<Artifact>, Vec<SkippedItem>) {
+    let skipped_file = |reason, detail: String| {
+        warn!(path = %ast_path.display(), ?reason, %detail, "Skipping file");
+        let item = SkippedItem {
+            path: ast_path.to_path_buf(),
+            function: None,
+            reason,
+            detail,
+        };
+        (vec![], vec![item])
+    };
+
+    // 先申请内存预算；guard 在本函数返回时归还
+    let _guard = match budget {
+        Some(budget) => {
+            let size = fs::metadata(ast_path).map_or(0, |m| m.len());
+            let factor = if is_compressed(ast_path) {
+                MEMORY_ESTIMATE_FACTOR * COMPRESSION_RATIO_ESTIMATE
+            } else {
+                MEMORY_ESTIMATE_FACTOR
+            };
+            match budget.acquire(size.saturating_mul(factor)) {
+                Some(guard) => Some(guard),
+                None => {
+                    return skipped_file(
+                        SkipReason::MemoryLimit,
+                        format!("AST file is {} bytes, estimated memory exceeds the budget", size),
+                    )
+                }
+            }
+        }
+        None => None,
+    };
+
+    debug!(path = %ast_path.display(), "Processing file");
+    let timeout = args.timeout_per_function.map(Duration::from_secs);
+    let relative_path = ast_path.strip_prefix(&args.input).unwrap_or(ast_path);
+    match process_ast_file(
+        ast_path,
+        &args.input,
+        &args.output,
+        timeout,
+        &args.formats,
+        renderer,
+        previous.get(relative_path),
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            error!(path = %ast_path.display(), error = %e, "Error processing file");
+            skipped_file(SkipReason::Error, e.to_string())
+        }
     }
-    for child in &node.children {
-        find_functions(child, functions);
+}
+
+/// 根据命令行参数初始化 tracing 日志
+/// 日志统一写到 stderr；设置了 RUST_LOG 环境变量时以其为准
+fn init_logging(args: &Args) {
+    let default_level = if args.quiet {
+        "warn"
+    } else {
+        match args.verbose {
+            0 => "info",
+            1 => "debug",
+            _ => "trace",
+        }
+    };
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    match args.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
     }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
+    init_logging(&args);
     if !args.input.is_dir() {
         return Err(format!("Input path '{}' is not a valid directory.", args.input.display()).into());
     }
     fs::create_dir_all(&args.output)?;
 
-    println!("Starting CFG generation...");
-    println!("Input AST directory: {}", args.input.display());
-    println!("Output CFG directory: {}", args.output.display());
+    info!(
+        input = %args.input.display(),
+        output = %args.output.display(),
+        "Starting CFG generation"
+    );
 
-    // 遍历输入目录，查找所有Rust的AST文件
-    for entry in WalkDir::new(&args.input)
+    // 遍历输入目录，查找所有Rust的AST文件 (包括压缩的)；按文件名排序，使输出不依赖文件系统的目录顺序
+    let ast_files: Vec<PathBuf> = WalkDir::new(&args.input)
+        .sort_by_file_name()
         .into_iter()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().is_file() && e.path().to_str().unwrap().ends_with(".rs.ast.json"))
-    {
-        let path = entry.path();
-        println!("\nProcessing file: {}", path.display());
-        if let Err(e) = process_ast_file(path, &args.input, &args.output) {
-            eprintln!("Error processing file {}: {}", path.display(), e);
+        .filter(|e| {
+            e.path().is_file()
+                && ast_stem(&e.path().to_string_lossy()).is_some_and(|stem| stem.ends_with(".rs"))
+        })
+        .map(|e| e.into_path())
+        .collect();
+
+    // 在线程池中并行处理每个AST文件
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()?;
+    let budget = args
+        .memory_limit
+        .map(|mib| MemoryBudget::new(mib.saturating_mul(1024 * 1024)));
+    let renderer = args.render.map(Renderer::new);
+    let previous_manifest = if args.incremental {
+        PreviousManifest::load(&args.output, &args.formats, args.render)
+    } else {
+        None
+    };
+    let previous = previous_manifest
+        .as_ref()
+        .map(|m| m.reusable_cfgs(&args.output))
+        .unwrap_or_default();
+    let results: Vec<(Vec<Artifact>, Vec<SkippedItem>, FileTiming)> = pool.install(|| {
+        ast_files
+            .par_iter()
+            .map(|path| {
+                let started = Instant::now();
+                let (artifacts, skipped) = process_ast_file_with_limits(
+                    path,
+                    &args,
+                    budget.as_ref(),
+                    renderer.as_ref(),
+                    &previous,
+                );
+                let timing = FileTiming {
+                    path: path.strip_prefix(&args.input).unwrap_or(path).to_path_buf(),
+                    bytes: fs::metadata(path).map_or(0, |m| m.len()),
+                    millis: started.elapsed().as_secs_f64() * 1000.0,
+                };
+                (artifacts, skipped, timing)
+            })
+            .collect()
+    });
+    let (mut artifacts, mut skipped, mut timings) = (vec![], vec![], vec![]);
+    for (file_artifacts, file_skipped, timing) in results {
+        artifacts.extend(file_artifacts);
+        skipped.extend(file_skipped);
+        timings.push(timing);
+    }
+    if let Some(path) = &args.timings {
+        fs::write(path, serde_json::to_string_pretty(&timings)?)?;
+    }
+
+    // 记录所有被跳过的文件和函数
+    let skipped_path = args.output.join("skipped.json");
+    artifacts.push(write_report(
+        &args.output,
+        "skipped.json",
+        serde_json::to_string_pretty(&skipped)?,
+    )?);
+    if !skipped.is_empty() {
+        warn!(
+            count = skipped.len(),
+            report = %skipped_path.display(),
+            "Some items were skipped"
+        );
+    }
+
+    // 增量模式下删除上一次生成、但这次不再产生的CFG (函数或AST文件已被删除)
+    if let Some(previous_manifest) = &previous_manifest {
+        let current: HashSet<&PathBuf> = artifacts.iter().map(|a| &a.path).collect();
+        for stale in previous_manifest
+            .artifacts
+            .iter()
+            .filter(|a| matches!(a.kind, ArtifactKind::Cfg) && !current.contains(&a.path))
+        {
+            debug!(output = %stale.path.display(), "Removing stale CFG");
+            let _ = fs::remove_file(args.output.join(&stale.path));
         }
     }
 
-    println!("\nCFG generation complete.");
+    // 最后写入 manifest.json，列出本次运行产生的全部文件
+    artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+    let manifest = Manifest {
+        tool: env!("CARGO_PKG_NAME"),
+        tool_version: env!("CARGO_PKG_VERSION"),
+        schema_version: SCHEMA_VERSION,
+        generated_at: timestamp(),
+        formats: args.formats.clone(),
+        render: args.render,
+        artifacts,
+    };
+    fs::write(
+        args.output.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    info!("CFG generation complete");
     Ok(())
 }
@@ -0,0 +1,454 @@
+// render.rs
+//
+// 不依赖 graphviz 的简单分层布局，把单个函数的图渲染为SVG
+// 命令行工具的 --render 找不到 dot 命令或 dot 执行失败时使用这里的实现；只用到 graph.rs 中的图格式，wasm 构建同样可用
+// 布局：按控制流的广度优先深度分层，层内按前驱的平均位置排序；向下的边画在节点之间，回边绕到节点右侧
+
+use crate::graph::{EdgeKind, Graph, NodeKind};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+/// 字符宽度 (像素)，宽字符 (中文等) 按两倍计算
+const CHAR_WIDTH: f64 = 7.0;
+/// 行高
+const LINE_HEIGHT: f64 = 14.0;
+/// 节点的内边距
+const PAD: f64 = 8.0;
+/// 每行最多显示的字符数，超出部分以省略号结尾
+const MAX_CHARS: usize = 60;
+/// 每个节点最多显示的行数
+const MAX_LINES: usize = 20;
+/// 同一层节点之间的水平间距
+const H_GAP: f64 = 40.0;
+/// 层与层之间的垂直间距
+const V_GAP: f64 = 50.0;
+/// 画布边距
+const MARGIN: f64 = 20.0;
+/// 层内排序的迭代次数
+const ORDER_SWEEPS: usize = 4;
+
+/// SVG/XML 文本转义
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 字符的显示宽度
+fn char_width(c: char) -> f64 {
+    if c as u32 >= 0x2E80 {
+        CHAR_WIDTH * 2.0
+    } else {
+        CHAR_WIDTH
+    }
+}
+
+/// 截断过长的行
+fn truncate(line: &str) -> String {
+    if line.chars().count() <= MAX_CHARS {
+        return line.to_string();
+    }
+    let mut truncated: String = line.chars().take(MAX_CHARS - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// 节点要显示的文本行：基本块取 statements 属性，其余取 label
+fn node_lines(statements: Option<&serde_json::Value>, label: &str) -> Vec<String> {
+    let lines: Vec<String> = match statements.and_then(|s| s.as_array()) {
+        Some(items) => items
+            .iter()
+            .map(|s| s.as_str().map_or_else(|| s.to_string(), str::to_string))
+            .collect(),
+        None => label.lines().map(str::to_string).collect(),
+    };
+    let mut shown: Vec<String> = lines.iter().take(MAX_LINES).map(|l| truncate(l)).collect();
+    if lines.len() > MAX_LINES {
+        shown.push(format!("… 还有 {} 行", lines.len() - MAX_LINES));
+    }
+    if shown.is_empty() {
+        shown.push(String::new());
+    }
+    shown
+}
+
+/// 布局后的节点
+struct Placed {
+    lines: Vec<String>,
+    layer: usize,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+/// 边的CSS类名，与 solana_graph 的 graph.html 中一致
+fn edge_class(kind: EdgeKind) -> &'static str {
+    match kind {
+        EdgeKind::ControlFlow => "control_flow",
+        EdgeKind::DataFlow => "data_flow",
+        EdgeKind::Call => "call",
+        EdgeKind::SameSource => "same_source",
+        EdgeKind::Child => "child",
+    }
+}
+
+/// 把一个函数的图渲染为SVG
+pub fn svg(graph: &Graph) -> String {
+    let index: HashMap<usize, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id, i))
+        .collect();
+    let edges: Vec<(usize, usize, EdgeKind)> = graph
+        .edges
+        .iter()
+        .filter_map(|e| Some((*index.get(&e.source)?, *index.get(&e.target)?, e.kind)))
+        .collect();
+
+    // 分层：从入口沿控制流边做广度优先搜索，到达不了的节点依次放在最后
+    let count = graph.nodes.len();
+    let entry = graph
+        .nodes
+        .iter()
+        .position(|n| n.kind == NodeKind::Entry)
+        .unwrap_or(0);
+    let mut layer: Vec<Option<usize>> = vec![None; count];
+    let mut queue = VecDeque::new();
+    if count > 0 {
+        layer[entry] = Some(0);
+        queue.push_back(entry);
+    }
+    while let Some(current) = queue.pop_front() {
+        for &(source, target, kind) in &edges {
+            if source == current && kind == EdgeKind::ControlFlow && layer[target].is_none() {
+                layer[target] = Some(layer[current].unwrap() + 1);
+                queue.push_back(target);
+            }
+        }
+    }
+    let mut next_layer = layer.iter().flatten().max().map_or(0, |d| d + 1);
+    let layer: Vec<usize> = layer
+        .into_iter()
+        .map(|d| {
+            d.unwrap_or_else(|| {
+                next_layer += 1;
+                next_layer - 1
+            })
+        })
+        .collect();
+    let layer_count = layer.iter().max().map_or(0, |d| d + 1);
+
+    // 层内排序：按前驱在上一层中的平均位置排序，重复几轮
+    let mut rows: Vec<Vec<usize>> = vec![vec![]; layer_count];
+    for (i, &d) in layer.iter().enumerate() {
+        rows[d].push(i);
+    }
+    for _ in 0..ORDER_SWEEPS {
+        let mut position = vec![0.0; count];
+        for row in &rows {
+            for (p, &i) in row.iter().enumerate() {
+                position[i] = p as f64;
+            }
+        }
+        for row in rows.iter_mut().skip(1) {
+            let barycenter = |i: usize| {
+                let preds: Vec<f64> = edges
+                    .iter()
+                    .filter(|&&(s, t, _)| t == i && layer[s] < layer[i])
+                    .map(|&(s, _, _)| position[s])
+                    .collect();
+                if preds.is_empty() {
+                    position[i]
+                } else {
+                    preds.iter().sum::<f64>() / preds.len() as f64
+                }
+            };
+            let mut keyed: Vec<(f64, usize)> = row.iter().map(|&i| (barycenter(i), i)).collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            *row = keyed.into_iter().map(|(_, i)| i).collect();
+        }
+    }
+
+    // 坐标：每层一行，行宽不同时居中对齐
+    let mut placed: Vec<Placed> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| {
+            let lines = node_lines(n.properties.get("statements"), &n.label);
+            let width = lines
+                .iter()
+                .map(|l| l.chars().map(char_width).sum::<f64>())
+                .fold(0.0, f64::max)
+                + PAD * 2.0;
+            let height = lines.len() as f64 * LINE_HEIGHT + PAD * 2.0;
+            Placed {
+                lines,
+                layer: layer[i],
+                x: 0.0,
+                y: 0.0,
+                width,
+                height,
+            }
+        })
+        .collect();
+    let row_widths: Vec<f64> = rows
+        .iter()
+        .map(|row| {
+            row.iter().map(|&i| placed[i].width).sum::<f64>()
+                + H_GAP * row.len().saturating_sub(1) as f64
+        })
+        .collect();
+    let canvas_width = row_widths.iter().copied().fold(0.0, f64::max);
+    let mut y = MARGIN;
+    for (row, row_width) in rows.iter().zip(&row_widths) {
+        let mut x = MARGIN + (canvas_width - row_width) / 2.0;
+        let row_height = row.iter().map(|&i| placed[i].height).fold(0.0, f64::max);
+        for &i in row {
+            placed[i].x = x;
+            placed[i].y = y;
+            x += placed[i].width + H_GAP;
+        }
+        y += row_height + V_GAP;
+    }
+    // 回边绕到右侧，需要为其留出空间
+    let width = canvas_width + MARGIN * 2.0 + H_GAP * 2.0;
+    let height = y - V_GAP + MARGIN;
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}">"#,
+        width, height, width, height
+    )
+    .unwrap();
+    writeln!(out, "<title>{}</title>", escape_xml(&graph.function)).unwrap();
+    out.push_str(concat!(
+        "<style>\n",
+        "  rect { fill: #fff; stroke: #555; }\n",
+        "  .entry rect, .exit rect { fill: #eef; }\n",
+        "  text { font: 11px monospace; fill: #000; }\n",
+        "  path { fill: none; stroke-width: 1.2; }\n",
+        "  .control_flow { stroke: #333; }\n",
+        "  .data_flow { stroke: #2a8; stroke-dasharray: 4 2; }\n",
+        "  .call, .same_source { stroke: #06c; stroke-dasharray: 2 2; }\n",
+        "</style>\n",
+        r#"<defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M0,0L10,5L0,10z" style="fill:#333;stroke:none"/></marker></defs>"#,
+        "\n",
+    ));
+    out.push_str(r#"<rect width="100%" height="100%" style="fill:#fff;stroke:none"/>"#);
+    out.push('\n');
+
+    for &(source, target, kind) in &edges {
+        let (a, b) = (&placed[source], &placed[target]);
+        let d = if b.layer > a.layer {
+            let (x1, y1) = (a.x + a.width / 2.0, a.y + a.height);
+            let (x2, y2) = (b.x + b.width / 2.0, b.y);
+            format!(
+                "M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}",
+                x1,
+                y1,
+                x1,
+                y1 + V_GAP / 2.0,
+                x2,
+                y2 - V_GAP / 2.0,
+                x2,
+                y2
+            )
+        } else {
+            // 回边和同层的边：从源节点右侧经画布右侧的空白绕到目标节点右侧
+            let (x1, y1) = (a.x + a.width, a.y + a.height / 2.0);
+            let (x2, y2) = (b.x + b.width, b.y + b.height / 2.0);
+            let bulge = MARGIN + canvas_width + H_GAP;
+            format!(
+                "M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}",
+                x1, y1, bulge, y1, bulge, y2, x2, y2
+            )
+        };
+        writeln!(
+            out,
+            r#"<path class="{}" d="{}" marker-end="url(#arrow)"/>"#,
+            edge_class(kind),
+            d
+        )
+        .unwrap();
+    }
+
+    for (node, p) in graph.nodes.iter().zip(&placed) {
+        let class = match node.kind {
+            NodeKind::Entry => "entry",
+            NodeKind::Exit => "exit",
+            _ => "node",
+        };
+        writeln!(
+            out,
+            r#"<g class="{}" transform="translate({:.1},{:.1})"><title>#{}</title>"#,
+            class, p.x, p.y, node.id
+        )
+        .unwrap();
+        writeln!(
+            out,
+            r#"  <rect width="{:.1}" height="{:.1}" rx="4"/>"#,
+            p.width, p.height
+        )
+        .unwrap();
+        for (i, line) in p.lines.iter().enumerate() {
+            writeln!(
+                out,
+                r#"  <text x="{:.1}" y="{:.1}" xml:space="preserve">{}</text>"#,
+                PAD,
+                PAD + (i + 1) as f64 * LINE_HEIGHT - 3.0,
+                escape_xml(line)
+            )
+            .unwrap();
+        }
+        out.push_str("</g>\n");
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{GraphEdge, GraphNode, Layer};
+    use serde_json::json;
+
+    fn graph(nodes: &[(usize, NodeKind, &str)], edges: &[(usize, usize, EdgeKind)]) -> Graph {
+        Graph {
+            layer: Layer::Ast,
+            function: "vault::withdraw<T>".to_string(),
+            nodes: nodes
+                .iter()
+                .map(|&(id, kind, label)| GraphNode {
+                    id,
+                    kind,
+                    label: label.to_string(),
+                    span: None,
+                    properties: Default::default(),
+                })
+                .collect(),
+            edges: edges
+                .iter()
+                .map(|&(source, target, kind)| GraphEdge {
+                    source,
+                    target,
+                    kind,
+                    properties: Default::default(),
+                })
+                .collect(),
+        }
+    }
+
+    /// 每个节点左上角的坐标和宽度，按节点 id
+    fn positions(svg: &str) -> HashMap<usize, (f64, f64, f64)> {
+        let lines: Vec<&str> = svg.lines().collect();
+        lines
+            .windows(2)
+            .filter_map(|pair| {
+                let rest = pair[0].split_once("translate(")?.1;
+                let (xy, rest) = rest.split_once(')')?;
+                let (x, y) = xy.split_once(',')?;
+                let id = rest.split_once("<title>#")?.1.split_once('<')?.0;
+                let width = pair[1].split_once("width=\"")?.1.split_once('"')?.0;
+                Some((
+                    id.parse().ok()?,
+                    (x.parse().ok()?, y.parse().ok()?, width.parse().ok()?),
+                ))
+            })
+            .collect()
+    }
+
+    /// 边的路径 `M x1,y1 C cx1,cy1 cx2,cy2 x2,y2` 中的8个数
+    fn path_points(line: &str) -> Vec<f64> {
+        let d = line
+            .split_once("d=\"")
+            .unwrap()
+            .1
+            .split_once('"')
+            .unwrap()
+            .0;
+        d.split([' ', ','])
+            .map(|n| n.trim_start_matches(['M', 'C']).parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn loop_is_layered_by_control_flow_depth() {
+        // 0 入口 -> 1 循环头 -> 2 循环体 -> 1，1 -> 3 出口；4 不可达
+        let graph = graph(
+            &[
+                (0, NodeKind::Entry, "entry"),
+                (1, NodeKind::BasicBlock, "while i < n"),
+                (2, NodeKind::BasicBlock, "i += 1"),
+                (3, NodeKind::Exit, "exit"),
+                (4, NodeKind::BasicBlock, "unreachable"),
+            ],
+            &[
+                (0, 1, EdgeKind::ControlFlow),
+                (1, 2, EdgeKind::ControlFlow),
+                (2, 1, EdgeKind::ControlFlow),
+                (1, 3, EdgeKind::ControlFlow),
+                (2, 3, EdgeKind::DataFlow),
+            ],
+        );
+        let svg = svg(&graph);
+        let at = positions(&svg);
+        assert_eq!(at.len(), 5);
+        assert!(at[&0].1 < at[&1].1);
+        // 循环体和出口都在循环头的下一层
+        assert!(at[&1].1 < at[&2].1);
+        assert_eq!(at[&2].1, at[&3].1);
+        // 不可达的节点放在最后一层之后
+        assert!(at[&4].1 > at[&3].1);
+        assert_eq!(svg.matches("<path class=\"control_flow\"").count(), 4);
+        assert_eq!(svg.matches("<path class=\"data_flow\"").count(), 1);
+
+        // 控制流中只有回边 2 -> 1 水平引出，从右侧绕过所有节点
+        let back: Vec<Vec<f64>> = svg
+            .lines()
+            .filter(|l| l.starts_with("<path class=\"control_flow\""))
+            .map(path_points)
+            .filter(|p| p[1] == p[3])
+            .collect();
+        assert_eq!(back.len(), 1);
+        // 起止于源节点和目标节点的右边缘；坐标保留一位小数
+        let right = |id: usize| at[&id].0 + at[&id].2;
+        assert!((back[0][0] - right(2)).abs() < 0.2 && (back[0][6] - right(1)).abs() < 0.2);
+        assert!(at.values().all(|&(x, _, width)| x + width < back[0][2]));
+    }
+
+    #[test]
+    fn text_is_escaped_and_truncated() {
+        let mut graph = graph(&[(0, NodeKind::BasicBlock, "Vec<u8> && \"x\"")], &[]);
+        let long = "x".repeat(MAX_CHARS + 10);
+        let mut statements: Vec<String> = vec![long];
+        statements.extend((0..MAX_LINES + 4).map(|i| format!("s{}", i)));
+        let svg_label = svg(&graph);
+        assert!(svg_label.contains("<title>vault::withdraw&lt;T&gt;</title>"));
+        assert!(svg_label.contains(">Vec&lt;u8&gt; &amp;&amp; &quot;x&quot;</text>"));
+
+        graph.nodes[0]
+            .properties
+            .insert("statements".to_string(), json!(statements));
+        let svg = svg(&graph);
+        let texts: Vec<&str> = svg
+            .lines()
+            .filter_map(|l| l.split_once("xml:space=\"preserve\">"))
+            .map(|(_, text)| text.trim_end_matches("</text>"))
+            .collect();
+        assert_eq!(texts.len(), MAX_LINES + 1);
+        assert_eq!(texts[0].chars().count(), MAX_CHARS);
+        assert!(texts[0].ends_with('…'));
+        assert_eq!(texts[MAX_LINES], "… 还有 5 行");
+    }
+
+    #[test]
+    fn empty_graph_renders() {
+        let svg = svg(&graph(&[], &[]));
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+    }
+}
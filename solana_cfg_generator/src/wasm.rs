@@ -0,0 +1,50 @@
+// wasm.rs
+//
+// wasm32 构建的入口 (启用 wasm feature 时编译)：输入和输出都在内存中，不读写文件，
+// 供浏览器中的代码审查工具和 VS Code for the Web 直接调用
+
+use crate::graph::Graph;
+use crate::{build_function_cfg, find_functions, function_name, AstNode, SCHEMA_VERSION};
+use serde::Serialize;
+use std::path::Path;
+use wasm_bindgen::prelude::*;
+
+/// 内存中生成的CFG的元数据；wasm32 上没有系统时钟，因此不包含生成时间
+#[derive(Serialize)]
+struct Metadata {
+    schema_version: u32,
+    tool: &'static str,
+    tool_version: &'static str,
+}
+
+/// 与命令行工具输出的JSON文件相同的顶层结构，一次包含AST中的所有函数
+#[derive(Serialize)]
+struct GraphFile {
+    metadata: Metadata,
+    graphs: Vec<Graph>,
+}
+
+/// 从 solana_ast_generator 生成的AST JSON构建所有函数的CFG，返回JSON
+/// `source_file` 为AST对应的源文件路径，写入各节点的源码范围
+#[wasm_bindgen(js_name = generateCfgs)]
+pub fn generate_cfgs(source_file: &str, ast_json: &str) -> Result<String, JsError> {
+    let mut root: AstNode = serde_json::from_str(ast_json)?;
+    root.restore_text();
+    let mut functions = vec![];
+    find_functions(&root, &mut functions);
+    let graphs = functions
+        .into_iter()
+        .filter_map(|func_node| {
+            build_function_cfg(func_node, None)
+                .map(|builder| builder.to_graph(&function_name(func_node), Path::new(source_file)))
+        })
+        .collect();
+    Ok(serde_json::to_string(&GraphFile {
+        metadata: Metadata {
+            schema_version: SCHEMA_VERSION,
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+        },
+        graphs,
+    })?)
+}
@@ -3,16 +3,29 @@
 extern crate rustc_driver;
 
 // 导入必要的模块
-use clap::Parser as ClapParser;
+use clap::{ArgAction, Parser as ClapParser, ValueEnum};
 use petgraph::dot::{Config, Dot};
 use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
 use rustc_driver::{Callbacks, Compilation};
 use rustc_interface::{interface, Queries};
 use rustc_middle::mir::{self, Rvalue, StatementKind, TerminatorKind};
 use rustc_middle::ty::TyCtxt;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{self, Display, Formatter};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+use tracing_subscriber::EnvFilter;
+use walkdir::WalkDir;
+
+use solana_graph::export::{self, Export, ExportFormat};
+use solana_graph::html;
+use solana_graph::graph::{self, EdgeKind, Graph, GraphEdge, GraphNode, Layer, NodeKind, Span};
 
 /// 定义我们工具的命令行参数
 #[derive(ClapParser, Debug)]
@@ -21,6 +34,60 @@ struct Args {
     /// 要分析的Solana项目crate的路径 (例如 ./single-pool/program)
     #[arg(value_name = "CRATE_PATH")]
     crate_path: String,
+
+    /// 提高日志详细程度 (-v 输出 debug, -vv 输出 trace)
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// 只输出警告和错误
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// 日志输出格式
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// rustc 前端的并行线程数 (对应 -Zthreads)，默认由 rustc 决定
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// 单个函数CPG的内存上限 (MiB)，按MIR语句数估算，超出的函数会被跳过
+    #[arg(long, value_name = "MIB")]
+    memory_limit: Option<u64>,
+
+    /// 单个函数的CPG构建超时时间 (秒)，超时的函数会被跳过
+    #[arg(long, value_name = "SECS")]
+    timeout_per_function: Option<u64>,
+
+    /// 将被跳过的函数列表写入该JSON文件
+    #[arg(long, value_name = "FILE")]
+    skipped_report: Option<PathBuf>,
+
+    /// 将所有函数的CPG以与CFG相同的图格式写入该JSON文件
+    #[arg(long, value_name = "FILE")]
+    json_output: Option<PathBuf>,
+
+    /// 为每个函数的CPG写出一个自包含的交互式HTML文件到该目录
+    #[arg(long, value_name = "DIR")]
+    html_dir: Option<PathBuf>,
+
+    /// 输出文件的路径前缀：按 --format 一次写出 `<前缀>.dot`、`<前缀>.json`、`<前缀>.graphml`，
+    /// HTML 每个函数一个文件 `<前缀>.<函数>.html`；不指定时DOT输出到 stdout
+    #[arg(short, long, value_name = "BASE")]
+    output: Option<PathBuf>,
+
+    /// 与 --output 一起使用的输出格式 (逗号分隔)
+    #[arg(long = "format", value_enum, value_delimiter = ',', default_values_t = [ExportFormat::Dot, ExportFormat::Json])]
+    formats: Vec<ExportFormat>,
+}
+
+/// 日志的输出格式
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    /// 人类可读的文本格式
+    Text,
+    /// 每行一个JSON对象
+    Json,
 }
 
 // --- CPG 数据结构定义 ---
@@ -51,9 +118,94 @@ impl Display for EdgeType {
     }
 }
 
+// --- 资源限制 ---
+
+/// 每个CPG节点（含标签字符串和边）大约占用的内存字节数，用于估算
+const BYTES_PER_NODE_ESTIMATE: u64 = 512;
+
+/// 分析过程中需要遵守的资源限制
+struct Limits {
+    memory_limit: Option<u64>, // 字节
+    timeout: Option<Duration>,
+}
+
+/// 被跳过的函数的原因
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum SkipReason {
+    /// CPG构建超过了 --timeout-per-function
+    Timeout,
+    /// 估算的内存占用超过了 --memory-limit
+    MemoryLimit,
+}
+
+/// 跳过项报告中的一条记录
+#[derive(Serialize, Debug)]
+struct SkippedItem {
+    function: String,
+    reason: SkipReason,
+    detail: String,
+}
+
+// --- 输出元数据 ---
+
+/// CPG输出格式的版本号，格式发生不兼容的变化时递增
+/// 2: 新增 --json-output，使用 graph.rs 中与CFG共用的节点/边格式
+const SCHEMA_VERSION: u32 = 2;
+
+/// 写在每个CPG输出开头的元数据，用于识别由旧版本生成的、格式不兼容的文件
+#[derive(Serialize, Debug)]
+struct Metadata {
+    schema_version: u32,
+    tool: &'static str,
+    tool_version: &'static str,
+    source_hash: String, // 被分析crate全部源文件的哈希
+    generated_at: String,
+}
+
+/// 写入输出的时间戳 (RFC 3339，精确到秒)
+/// 设置了 SOURCE_DATE_EPOCH 时使用该时间而不是当前时间，使重复运行的输出逐字节相同
+fn timestamp() -> String {
+    let time = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .map_or_else(SystemTime::now, |secs| UNIX_EPOCH + Duration::from_secs(secs));
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// 计算一个crate全部Rust源文件的哈希
+/// 以crate根文件所在目录为范围，按路径排序后依次哈希相对路径和内容
+fn crate_sources_hash(crate_root: &Path) -> Result<String, Box<dyn Error>> {
+    let src_dir = crate_root.parent().unwrap_or(Path::new("."));
+    let mut files: Vec<PathBuf> = WalkDir::new(src_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "rs"))
+        .collect();
+    files.sort();
+
+    let mut hasher = blake3::Hasher::new();
+    for path in files {
+        let relative = path.strip_prefix(src_dir)?;
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update(&fs::read(&path)?);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
 // --- 编译器回调与分析逻辑 ---
 
-struct CpgCallback;
+struct CpgCallback {
+    limits: Limits,
+    metadata: Metadata,
+    /// 为 false 时 (指定了 --output) 不再把DOT打印到 stdout
+    print_dot: bool,
+    graphs: Vec<Graph>,
+    /// 所有函数的DOT，不含元数据注释
+    dot: String,
+    skipped: Vec<SkippedItem>,
+}
 
 impl Callbacks for CpgCallback {
     fn after_analysis<'tcx>(
@@ -62,38 +214,148 @@ impl Callbacks for CpgCallback {
         queries: &'tcx Queries<'tcx>,
     ) -> Compilation {
         queries.global_ctxt().unwrap().enter(|tcx| {
-            println!("\n✅ 成功进入编译器上下文，开始分析...");
-            analyze_crate(tcx);
+            info!("成功进入编译器上下文，开始分析");
+            (self.graphs, self.dot, self.skipped) =
+                analyze_crate(tcx, &self.limits, &self.metadata, self.print_dot);
         });
         Compilation::Continue
     }
 }
 
 /// 主分析函数，遍历Crate中的所有函数
-fn analyze_crate(tcx: TyCtxt<'_>) {
+/// 返回统一图格式的CPG、所有函数的DOT以及因资源限制被跳过的函数
+fn analyze_crate(
+    tcx: TyCtxt<'_>,
+    limits: &Limits,
+    metadata: &Metadata,
+    print_dot: bool,
+) -> (Vec<Graph>, String, Vec<SkippedItem>) {
+    let mut graphs = vec![];
+    let mut dots = String::new();
+    let mut skipped = vec![];
+    let mut skip = |function: String, reason, detail: String| {
+        warn!(function = %function, ?reason, %detail, "跳过函数");
+        skipped.push(SkippedItem { function, reason, detail });
+    };
+
     for item_def_id in tcx.hir().body_owners() {
         let function_path = tcx.def_path_str(item_def_id);
-        println!("\n--- 正在分析函数: {} ---", function_path);
+        info!(function = %function_path, "正在分析函数");
 
         let mir_body = tcx.optimized_mir(item_def_id);
-        let cpg = build_cpg_for_function(mir_body);
 
-        // 为生成的图生成DOT文件用于可视化
-        let dot_content = format!(
-            "{:?}",
-            Dot::with_config(&cpg, &[Config::EdgeNoLabel])
-        );
-        
-        // 此处可以添加保存 .dot 和 .json 文件的逻辑
-        // 为了简化，我们直接打印DOT内容
-        println!("--- DOT Representation for {} ---", function_path);
-        println!("{}", dot_content);
-        println!("--- End of DOT ---");
+        // 每条语句和每个终结符都会成为一个CPG节点
+        let node_count: usize = mir_body
+            .basic_blocks
+            .iter()
+            .map(|block| block.statements.len() + 1)
+            .sum();
+        let estimate = node_count as u64 * BYTES_PER_NODE_ESTIMATE;
+        if limits.memory_limit.is_some_and(|limit| estimate > limit) {
+            skip(
+                function_path,
+                SkipReason::MemoryLimit,
+                format!("{} 个节点，估算内存 {} 字节超出预算", node_count, estimate),
+            );
+            continue;
+        }
+
+        let deadline = limits.timeout.map(|t| Instant::now() + t);
+        let Some(cpg) = build_cpg_for_function(mir_body, deadline) else {
+            skip(
+                function_path,
+                SkipReason::Timeout,
+                format!("CPG构建超过 {} 秒", limits.timeout.unwrap_or_default().as_secs()),
+            );
+            continue;
+        };
+
+        // 为生成的图生成DOT用于可视化
+        let dot_content = format!("{:?}", Dot::with_config(&cpg, &[Config::EdgeNoLabel]));
+
+        // 没有 --output 时直接把DOT内容打印到 stdout（日志走 stderr，两者互不干扰）
+        if print_dot {
+            println!("// {}", function_path);
+            print!("{}", export::dot_header(metadata).expect("元数据总能序列化"));
+            println!("{}", dot_content);
+        }
+        dots.push_str(&format!("// {}\n{}\n", function_path, dot_content));
+        graphs.push(to_graph(tcx, mir_body, &function_path, &cpg));
+    }
+
+    (graphs, dots, skipped)
+}
+
+/// 把CPG转换为与CFG共用的图格式，节点的源码范围取自MIR的 source_info
+fn to_graph(
+    tcx: TyCtxt<'_>,
+    mir: &mir::Body<'_>,
+    function: &str,
+    cpg: &DiGraph<CpgNode, EdgeType>,
+) -> Graph {
+    let source_map = tcx.sess.source_map();
+    let nodes = cpg
+        .node_indices()
+        .map(|index| {
+            let node = &cpg[index];
+            let block = &mir.basic_blocks[node.location.block];
+            let kind = if node.location.statement_index == block.statements.len() {
+                NodeKind::Terminator
+            } else {
+                NodeKind::Statement
+            };
+            let span = mir.source_info(node.location).span;
+            let span = (!span.is_dummy()).then(|| {
+                let lo = source_map.lookup_byte_offset(span.lo());
+                let hi = source_map.lookup_byte_offset(span.hi());
+                Span {
+                    file: PathBuf::from(lo.sf.name.prefer_local().to_string()),
+                    start_byte: lo.pos.0 as usize,
+                    end_byte: hi.pos.0 as usize,
+                }
+            });
+            let mut properties = BTreeMap::new();
+            properties.insert("block".to_string(), format!("{:?}", node.location.block).into());
+            properties.insert(
+                "statement_index".to_string(),
+                node.location.statement_index.into(),
+            );
+            GraphNode {
+                id: index.index(),
+                kind,
+                label: node.label.clone(),
+                span,
+                properties,
+            }
+        })
+        .collect();
+    let edges = cpg
+        .edge_references()
+        .map(|edge| GraphEdge {
+            source: edge.source().index(),
+            target: edge.target().index(),
+            kind: match edge.weight() {
+                EdgeType::ControlFlow => EdgeKind::ControlFlow,
+                EdgeType::DataFlow => EdgeKind::DataFlow,
+            },
+            properties: BTreeMap::new(),
+        })
+        .collect();
+    Graph {
+        layer: Layer::Mir,
+        function: function.to_string(),
+        nodes,
+        edges,
     }
 }
 
 /// 为单个函数构建CPG（包含CFG和DFG）
-fn build_cpg_for_function(mir: &mir::Body<'_>) -> DiGraph<CpgNode, EdgeType> {
+/// 超过 `deadline` 时放弃构建并返回 None
+fn build_cpg_for_function(
+    mir: &mir::Body<'_>,
+    deadline: Option<Instant>,
+) -> Option<DiGraph<CpgNode, EdgeType>> {
+    let timed_out = || deadline.is_some_and(|d| Instant::now() >= d);
     let mut cpg = DiGraph::<CpgNode, EdgeType>::new();
     // 映射: MIR位置 -> CPG节点索引
     let mut node_map: HashMap<mir::Location, NodeIndex> = HashMap::new();
@@ -101,6 +363,9 @@ fn build_cpg_for_function(mir: &mir::Body<'_>) -> DiGraph<CpgNode, EdgeType> {
     // --- 阶段 A: 创建节点 ---
     // 遍历所有基本块和其中的语句，为每个MIR指令创建一个CPG节点
     for (block_id, block_data) in mir.basic_blocks.iter_enumerated() {
+        if timed_out() {
+            return None;
+        }
         for (statement_index, statement) in block_data.statements.iter().enumerate() {
             let location = mir::Location {
                 block: block_id,
@@ -131,6 +396,9 @@ fn build_cpg_for_function(mir: &mir::Body<'_>) -> DiGraph<CpgNode, EdgeType> {
     let mut last_def: HashMap<mir::Local, NodeIndex> = HashMap::new();
 
     for (block_id, block_data) in mir.basic_blocks.iter_enumerated() {
+        if timed_out() {
+            return None;
+        }
         // --- 构建DFG ---
         for (statement_index, statement) in block_data.statements.iter().enumerate() {
             let location = mir::Location { block: block_id, statement_index };
@@ -165,7 +433,7 @@ fn build_cpg_for_function(mir: &mir::Body<'_>) -> DiGraph<CpgNode, EdgeType> {
         }
     }
 
-    cpg
+    Some(cpg)
 }
 
 /// 辅助函数：遍历Rvalue，为所有“使用”的变量添加DFG边
@@ -218,9 +486,36 @@ fn visit_operand(operand: &mir::Operand, last_def: &HashMap<mir::Local, NodeInde
 }
 
 
+/// 根据命令行参数初始化 tracing 日志
+/// 日志统一写到 stderr；设置了 RUST_LOG 环境变量时以其为准
+fn init_logging(args: &Args) {
+    let default_level = if args.quiet {
+        "warn"
+    } else {
+        match args.verbose {
+            0 => "info",
+            1 => "debug",
+            _ => "trace",
+        }
+    };
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    match args.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
 fn main() {
     let args = Args::parse();
-    println!("🎯 目标Crate路径: {}", args.crate_path);
+    init_logging(&args);
+    info!(crate_path = %args.crate_path, "目标Crate路径");
+    let source_hash =
+        crate_sources_hash(Path::new(&args.crate_path)).expect("无法读取crate源文件");
 
     let output = Command::new("rustc")
         .arg("--print")
@@ -228,7 +523,7 @@ fn main() {
         .output()
         .expect("无法执行 `rustc --print sysroot`");
     let sysroot = String::from_utf8(output.stdout).unwrap().trim().to_string();
-    println!("📚 使用Sysroot: {}", sysroot);
+    debug!(sysroot = %sysroot, "使用Sysroot");
 
     let mut compiler_args = vec![
         "solana_cpg_generator".to_string(),
@@ -244,11 +539,62 @@ fn main() {
     // 确保我们为Solana BPF目标进行编译
     compiler_args.push("--target=bpfel-unknown-unknown".to_string());
 
-    println!("⚙️ 编译器参数: {:?}", compiler_args);
+    if let Some(jobs) = args.jobs {
+        compiler_args.push(format!("-Zthreads={}", jobs));
+    }
 
-    let mut callbacks = CpgCallback;
+    debug!(?compiler_args, "编译器参数");
+
+    let mut callbacks = CpgCallback {
+        limits: Limits {
+            memory_limit: args.memory_limit.map(|mib| mib.saturating_mul(1024 * 1024)),
+            timeout: args.timeout_per_function.map(Duration::from_secs),
+        },
+        metadata: Metadata {
+            schema_version: SCHEMA_VERSION,
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            source_hash,
+            generated_at: timestamp(),
+        },
+        print_dot: args.output.is_none(),
+        graphs: vec![],
+        dot: String::new(),
+        skipped: vec![],
+    };
     let compiler = rustc_driver::RunCompiler::new(&compiler_args, &mut callbacks);
     compiler.run().expect("编译和分析失败！");
 
-    println!("\n🎉 分析流程成功完成！");
+    if !callbacks.skipped.is_empty() {
+        warn!(count = callbacks.skipped.len(), "部分函数被跳过");
+    }
+    if let Some(report_path) = &args.skipped_report {
+        let report = serde_json::to_string_pretty(&callbacks.skipped).expect("序列化跳过项报告失败");
+        fs::write(report_path, report).expect("无法写入跳过项报告");
+    }
+    let export = Export {
+        metadata: &callbacks.metadata,
+        graphs: &callbacks.graphs,
+        dot: callbacks.dot,
+    };
+    if let Some(base) = &args.output {
+        for (path, _) in export.write(base, &args.formats).expect("无法写入CPG输出文件") {
+            debug!(file = %path.display(), "已写出CPG");
+        }
+    }
+    if let Some(json_path) = &args.json_output {
+        let (_, json) = export.render(ExportFormat::Json).expect("序列化CPG失败").remove(0);
+        fs::write(json_path, json).expect("无法写入CPG JSON文件");
+    }
+    if let Some(html_dir) = &args.html_dir {
+        fs::create_dir_all(html_dir).expect("无法创建HTML输出目录");
+        for graph in &callbacks.graphs {
+            // 函数路径 `a::b::c` 写成文件名 `a.b.c.html`
+            let html = html::render(graph).expect("序列化CPG失败");
+            fs::write(html_dir.join(format!("{}.html", export::file_stem(&graph.function))), html)
+                .expect("无法写入CPG HTML文件");
+        }
+    }
+
+    info!("分析流程成功完成");
 }
@@ -0,0 +1,419 @@
+// export.rs
+//
+// 图的导出层：同一组图按请求的全部格式一次写出，新的导出格式只需要在这里实现一次
+// AST、CFG 和 CPG 生成器的 --format 都使用这里的实现
+
+use crate::graph::Graph;
+use crate::html;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 图的导出格式
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// Graphviz DOT，用于可视化
+    Dot,
+    /// graph.rs 中的统一图格式，用于程序化分析
+    Json,
+    /// GraphML，可以导入 Gephi、yEd、NetworkX 等图工具
+    Graphml,
+    /// 自包含的交互式HTML，可直接在浏览器中打开，每个函数一个文件
+    Html,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Dot => "dot",
+            ExportFormat::Json => "json",
+            ExportFormat::Graphml => "graphml",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+/// JSON 输出的顶层结构，CFG 与 CPG 相同
+#[derive(Serialize)]
+struct GraphFile<'a, M> {
+    metadata: &'a M,
+    graphs: &'a [Graph],
+}
+
+/// DOT 文件开头的注释行，内容为单行JSON的元数据
+pub fn dot_header<M: Serialize>(metadata: &M) -> Result<String, serde_json::Error> {
+    Ok(format!(
+        "// agent-metadata: {}\n",
+        serde_json::to_string(metadata)?
+    ))
+}
+
+/// 函数路径 `a::b::c` 写成文件名的一部分 `a.b.c`
+pub fn file_stem(function: &str) -> String {
+    function
+        .replace("::", ".")
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '.' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// 一次导出的内容：同一份元数据下的一组图
+pub struct Export<'a, M> {
+    pub metadata: &'a M,
+    pub graphs: &'a [Graph],
+    /// 工具自己生成的DOT (不含元数据注释)，保留各工具原有的节点标签和样式
+    pub dot: String,
+}
+
+impl<M: Serialize> Export<'_, M> {
+    /// 带元数据注释的DOT
+    pub fn dot(&self) -> Result<String, Box<dyn Error>> {
+        Ok(format!("{}{}", dot_header(self.metadata)?, self.dot))
+    }
+
+    /// 一种格式的全部输出，每项为 (接在路径前缀之后的文件名后缀, 内容)
+    /// HTML 每个图一个文件：只有一个图时为 `.html`，否则为 `.<函数>.html`
+    pub fn render(&self, format: ExportFormat) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let single = |content: String| vec![(format!(".{}", format.extension()), content)];
+        Ok(match format {
+            ExportFormat::Dot => single(self.dot()?),
+            ExportFormat::Json => single(serde_json::to_string_pretty(&GraphFile {
+                metadata: self.metadata,
+                graphs: self.graphs,
+            })?),
+            ExportFormat::Graphml => {
+                single(graphml(&serde_json::to_string(self.metadata)?, self.graphs))
+            }
+            ExportFormat::Html => {
+                let mut pages = vec![];
+                for graph in self.graphs {
+                    let suffix = if self.graphs.len() == 1 {
+                        ".html".to_string()
+                    } else {
+                        format!(".{}.html", file_stem(&graph.function))
+                    };
+                    pages.push((suffix, html::render(graph)?));
+                }
+                pages
+            }
+        })
+    }
+
+    /// 把请求的每种格式写到 `<base>` 加对应后缀的文件中，返回写出的文件及其内容
+    pub fn write(
+        &self,
+        base: &Path,
+        formats: &[ExportFormat],
+    ) -> Result<Vec<(PathBuf, String)>, Box<dyn Error>> {
+        if let Some(parent) = base.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut written = vec![];
+        for &format in formats {
+            for (suffix, content) in self.render(format)? {
+                let mut path = base.as_os_str().to_owned();
+                path.push(&suffix);
+                let path = PathBuf::from(path);
+                fs::write(&path, &content)?;
+                written.push((path, content));
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// XML 转义
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 枚举值在JSON中的名字，例如 NodeKind::BasicBlock 为 `basic_block`
+fn variant_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// GraphML 中声明的属性：(id, 作用对象, 属性名, 类型)
+const GRAPHML_KEYS: &[(&str, &str, &str, &str)] = &[
+    ("layer", "graph", "layer", "string"),
+    ("function", "graph", "function", "string"),
+    ("kind", "node", "kind", "string"),
+    ("label", "node", "label", "string"),
+    ("file", "node", "file", "string"),
+    ("start_byte", "node", "start_byte", "long"),
+    ("end_byte", "node", "end_byte", "long"),
+    ("properties", "node", "properties", "string"),
+    ("edge_kind", "edge", "kind", "string"),
+    ("edge_properties", "edge", "properties", "string"),
+];
+
+/// 导出为 GraphML，每个函数一个 <graph>；节点 id 加上图的序号以在整个文件内唯一
+/// properties 以JSON字符串保存，元数据写在 <desc> 中
+fn graphml(metadata: &str, graphs: &[Graph]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    let _ = writeln!(out, "  <desc>{}</desc>", escape_xml(metadata));
+    for (id, domain, name, ty) in GRAPHML_KEYS {
+        let _ = writeln!(
+            out,
+            "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>",
+            id, domain, name, ty
+        );
+    }
+    for (g, graph) in graphs.iter().enumerate() {
+        let _ = writeln!(out, "  <graph id=\"g{}\" edgedefault=\"directed\">", g);
+        let _ = writeln!(
+            out,
+            "    <data key=\"layer\">{}</data>",
+            variant_name(&graph.layer)
+        );
+        let _ = writeln!(
+            out,
+            "    <data key=\"function\">{}</data>",
+            escape_xml(&graph.function)
+        );
+        for node in &graph.nodes {
+            let _ = writeln!(out, "    <node id=\"g{}n{}\">", g, node.id);
+            let _ = writeln!(
+                out,
+                "      <data key=\"kind\">{}</data>",
+                variant_name(&node.kind)
+            );
+            let _ = writeln!(
+                out,
+                "      <data key=\"label\">{}</data>",
+                escape_xml(&node.label)
+            );
+            if let Some(span) = &node.span {
+                let _ = writeln!(
+                    out,
+                    "      <data key=\"file\">{}</data>",
+                    escape_xml(&span.file.to_string_lossy())
+                );
+                let _ = writeln!(
+                    out,
+                    "      <data key=\"start_byte\">{}</data>",
+                    span.start_byte
+                );
+                let _ = writeln!(out, "      <data key=\"end_byte\">{}</data>", span.end_byte);
+            }
+            if !node.properties.is_empty() {
+                let properties = serde_json::to_string(&node.properties).unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "      <data key=\"properties\">{}</data>",
+                    escape_xml(&properties)
+                );
+            }
+            out.push_str("    </node>\n");
+        }
+        for edge in &graph.edges {
+            let _ = writeln!(
+                out,
+                "    <edge source=\"g{}n{}\" target=\"g{}n{}\">",
+                g, edge.source, g, edge.target
+            );
+            let _ = writeln!(
+                out,
+                "      <data key=\"edge_kind\">{}</data>",
+                variant_name(&edge.kind)
+            );
+            if !edge.properties.is_empty() {
+                let properties = serde_json::to_string(&edge.properties).unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "      <data key=\"edge_properties\">{}</data>",
+                    escape_xml(&properties)
+                );
+            }
+            out.push_str("    </edge>\n");
+        }
+        out.push_str("  </graph>\n");
+    }
+    out.push_str("</graphml>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{EdgeKind, GraphEdge, GraphNode, Layer, NodeKind, Span};
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+
+    fn graph(function: &str, label: &str) -> Graph {
+        let node = |id, kind, label: &str| GraphNode {
+            id,
+            kind,
+            label: label.to_string(),
+            span: None,
+            properties: BTreeMap::new(),
+        };
+        Graph {
+            layer: Layer::Ast,
+            function: function.to_string(),
+            nodes: vec![
+                node(0, NodeKind::Entry, "entry"),
+                GraphNode {
+                    span: Some(Span {
+                        file: PathBuf::from("src/lib.rs"),
+                        start_byte: 10,
+                        end_byte: 20,
+                    }),
+                    properties: BTreeMap::from([("statements".to_string(), json!([label]))]),
+                    ..node(1, NodeKind::BasicBlock, label)
+                },
+                node(2, NodeKind::Exit, "exit"),
+            ],
+            edges: vec![
+                GraphEdge {
+                    source: 0,
+                    target: 1,
+                    kind: EdgeKind::ControlFlow,
+                    properties: BTreeMap::new(),
+                },
+                GraphEdge {
+                    source: 1,
+                    target: 2,
+                    kind: EdgeKind::ControlFlow,
+                    properties: BTreeMap::from([("condition".to_string(), json!("a < b"))]),
+                },
+            ],
+        }
+    }
+
+    fn metadata() -> Value {
+        json!({"schema_version": 1, "tool": "test"})
+    }
+
+    fn export<'a>(metadata: &'a Value, graphs: &'a [Graph]) -> Export<'a, Value> {
+        Export {
+            metadata,
+            graphs,
+            dot: "digraph G {}\n".to_string(),
+        }
+    }
+
+    #[test]
+    fn json_and_dot_carry_metadata() {
+        let graphs = [graph("vault::deposit", "x += 1")];
+        let metadata = metadata();
+        let export = export(&metadata, &graphs);
+
+        let json = export.render(ExportFormat::Json).unwrap();
+        assert_eq!(json[0].0, ".json");
+        let file: Value = serde_json::from_str(&json[0].1).unwrap();
+        assert_eq!(file["metadata"]["tool"], "test");
+        let parsed: Vec<Graph> = serde_json::from_value(file["graphs"].clone()).unwrap();
+        assert_eq!(parsed[0].function, "vault::deposit");
+        assert_eq!(parsed[0].nodes[1].span, graphs[0].nodes[1].span);
+
+        let dot = export.render(ExportFormat::Dot).unwrap();
+        assert_eq!(
+            dot,
+            [(
+                ".dot".to_string(),
+                "// agent-metadata: {\"schema_version\":1,\"tool\":\"test\"}\ndigraph G {}\n"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn graphml_prefixes_node_ids_and_escapes_text() {
+        let graphs = [graph("a", "if a < b && c"), graph("b", "\"quoted\"")];
+        let graphml = export(&metadata(), &graphs)
+            .render(ExportFormat::Graphml)
+            .unwrap();
+        assert_eq!(graphml.len(), 1);
+        let (suffix, xml) = &graphml[0];
+        assert_eq!(suffix, ".graphml");
+        // 两个图的节点 id 在整个文件内唯一，边引用加了前缀的 id
+        assert_eq!(xml.matches("<node id=\"g0n").count(), 3);
+        assert_eq!(xml.matches("<node id=\"g1n").count(), 3);
+        assert!(xml.contains("<edge source=\"g1n1\" target=\"g1n2\">"));
+        assert!(xml.contains("<data key=\"label\">if a &lt; b &amp;&amp; c</data>"));
+        assert!(xml.contains("<data key=\"label\">&quot;quoted&quot;</data>"));
+        assert!(xml.contains("<data key=\"file\">src/lib.rs</data>"));
+        assert!(xml.contains(
+            "<data key=\"edge_properties\">{&quot;condition&quot;:&quot;a &lt; b&quot;}</data>"
+        ));
+        assert!(xml.contains("<data key=\"kind\">basic_block</data>"));
+        assert!(xml.contains(
+            "<desc>{&quot;schema_version&quot;:1,&quot;tool&quot;:&quot;test&quot;}</desc>"
+        ));
+        assert_eq!(
+            xml.matches("<graph ").count(),
+            xml.matches("</graph>").count()
+        );
+    }
+
+    #[test]
+    fn html_pages_are_named_by_function() {
+        let one = [graph("vault::deposit", "x")];
+        let pages = export(&metadata(), &one)
+            .render(ExportFormat::Html)
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].0, ".html");
+
+        let two = [
+            graph("vault::deposit", "x"),
+            graph("<impl Vault>::withdraw", "y"),
+        ];
+        let suffixes: Vec<_> = export(&metadata(), &two)
+            .render(ExportFormat::Html)
+            .unwrap()
+            .into_iter()
+            .map(|(suffix, _)| suffix)
+            .collect();
+        assert_eq!(
+            suffixes,
+            [".vault.deposit.html", "._impl_Vault_.withdraw.html"]
+        );
+    }
+
+    #[test]
+    fn write_creates_one_file_per_output() {
+        let dir = std::env::temp_dir().join(format!("solana_graph-export-{}", std::process::id()));
+        let graphs = [graph("a", "x"), graph("b", "y")];
+        let written = export(&metadata(), &graphs)
+            .write(
+                &dir.join("nested/lib.rs"),
+                &[ExportFormat::Dot, ExportFormat::Json, ExportFormat::Html],
+            )
+            .unwrap();
+        let names: Vec<_> = written
+            .iter()
+            .map(|(path, content)| {
+                assert_eq!(&fs::read_to_string(path).unwrap(), content);
+                path.file_name().unwrap().to_string_lossy().into_owned()
+            })
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            names,
+            [
+                "lib.rs.dot",
+                "lib.rs.json",
+                "lib.rs.a.html",
+                "lib.rs.b.html"
+            ]
+        );
+    }
+}
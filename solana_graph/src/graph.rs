@@ -0,0 +1,90 @@
+// graph.rs
+//
+// CFG 与 CPG 共用的图交换格式
+// 修改时需要递增 solana_cfg_generator 和 solana_cpg_generator 各自的 SCHEMA_VERSION
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// 图来自哪一层分析
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Layer {
+    /// 由 solana_cfg_generator 基于 tree-sitter AST 构建；solana_ast_generator 导出的语法树本身也属于这一层
+    Ast,
+    /// 由 solana_cpg_generator 基于 rustc MIR 构建
+    Mir,
+}
+
+/// 节点的种类
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    /// 函数入口
+    Entry,
+    /// 函数出口
+    Exit,
+    /// 由若干条语句组成的基本块 (AST层)
+    BasicBlock,
+    /// 一条MIR语句
+    Statement,
+    /// MIR基本块的终结符
+    Terminator,
+    /// 语法树节点 (只出现在 solana_ast_generator 的图导出中)
+    Syntax,
+}
+
+/// 边的种类
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    ControlFlow,
+    DataFlow,
+    /// 调用点到被调用函数入口 (只出现在 agent merge 的输出中)
+    Call,
+    /// MIR节点到覆盖同一段源码的AST层节点 (只出现在 agent merge 的输出中)
+    SameSource,
+    /// 语法树中父节点到子节点 (只出现在 solana_ast_generator 的图导出中)
+    Child,
+}
+
+/// 节点对应的源码范围，字节偏移相对于源文件开头
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub file: PathBuf,
+    pub start_byte: usize,
+    pub end_byte: usize,
+}
+
+/// 图中的一个节点，`id` 在同一张图内唯一
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphNode {
+    pub id: usize,
+    pub kind: NodeKind,
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, Value>,
+}
+
+/// 图中的一条有向边，`source` 和 `target` 为节点的 `id`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphEdge {
+    pub source: usize,
+    pub target: usize,
+    pub kind: EdgeKind,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, Value>,
+}
+
+/// 一个函数的图
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Graph {
+    pub layer: Layer,
+    pub function: String,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
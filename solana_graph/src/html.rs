@@ -0,0 +1,73 @@
+// html.rs
+//
+// 单个函数的图导出为自包含的HTML文件：图数据和页面脚本都嵌在文件中，不依赖网络或 agent view，
+// 可以直接作为附件随报告发送。CFG 与 CPG 生成器的 --format html 都使用这里的实现
+
+use crate::graph::Graph;
+
+/// 页面模板，编译时嵌入
+const TEMPLATE: &str = include_str!("graph.html");
+
+/// HTML 转义
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 把一个函数的图渲染为自包含的HTML页面
+pub fn render(graph: &Graph) -> Result<String, serde_json::Error> {
+    // 图数据放在 <script> 中，`</` 必须转义，否则字符串中的 `</script>` 会提前结束脚本
+    let data = serde_json::to_string(graph)?.replace("</", "<\\/");
+    Ok(TEMPLATE
+        .replace("__TITLE__", &escape_html(&graph.function))
+        .replace("__GRAPH__", &data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{GraphNode, Layer, NodeKind};
+
+    #[test]
+    fn graph_data_cannot_close_the_script() {
+        let graph = Graph {
+            layer: Layer::Ast,
+            function: "<impl Vault>::withdraw".to_string(),
+            nodes: vec![GraphNode {
+                id: 0,
+                kind: NodeKind::BasicBlock,
+                label: "msg!(\"</script><script>alert(1)</script>\")".to_string(),
+                span: None,
+                properties: Default::default(),
+            }],
+            edges: vec![],
+        };
+        let page = render(&graph).unwrap();
+        assert!(page.contains("<title>&lt;impl Vault&gt;::withdraw</title>"));
+        assert!(!page.contains("__GRAPH__") && !page.contains("__TITLE__"));
+
+        // 图数据只能由嵌入它的 <script> 自己的结束标签结束，并且仍是原来的图
+        let start = page
+            .find("<script id=\"graph\" type=\"application/json\">")
+            .unwrap();
+        let data = &page[page[start..].find('>').unwrap() + start + 1..];
+        let data = &data[..data.find("</").unwrap()];
+        let parsed: Graph = serde_json::from_str(data).unwrap();
+        assert_eq!(parsed.nodes[0].label, graph.nodes[0].label);
+    }
+
+    #[test]
+    fn page_is_self_contained() {
+        let graph = Graph {
+            layer: Layer::Mir,
+            function: "f".to_string(),
+            nodes: vec![],
+            edges: vec![],
+        };
+        let page = render(&graph).unwrap();
+        assert!(!page.contains("<script src") && !page.contains("<link"));
+        assert!(!page.contains("https://"));
+    }
+}
@@ -0,0 +1,7 @@
+// lib.rs
+//
+// solana_cfg_generator、solana_cpg_generator 和 solana_agent 共用的图格式和导出层 (DOT、JSON、GraphML、HTML)，三者都以路径依赖引用本crate
+
+pub mod export;
+pub mod graph;
+pub mod html;